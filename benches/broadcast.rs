@@ -0,0 +1,109 @@
+//! Registry fan-out benchmarks
+//!
+//! Broadcasts one GOP worth of frames (a keyframe, inter frames and
+//! interleaved audio) from a publisher to 1, 100 and 1000 subscribers. Each
+//! subscriber is a task draining its receiver, as a subscriber session does.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::runtime::Runtime;
+
+use rtmp_rs::registry::{BroadcastFrame, StreamKey, StreamRegistry};
+
+/// Video frames per GOP (one keyframe followed by inter frames)
+const GOP_LENGTH: usize = 30;
+
+/// Build one GOP of frames with an audio frame after every video frame
+fn gop() -> Vec<BroadcastFrame> {
+    let keyframe = Bytes::from(vec![0x17; 16 * 1024]);
+    let inter = Bytes::from(vec![0x27; 2 * 1024]);
+    let audio = Bytes::from(vec![0xAF; 256]);
+
+    let mut frames = Vec::with_capacity(GOP_LENGTH * 2);
+    for i in 0..GOP_LENGTH {
+        let timestamp = i as u32 * 33;
+        let frame = if i == 0 {
+            BroadcastFrame::video(timestamp, keyframe.clone(), true, false)
+        } else {
+            BroadcastFrame::video(timestamp, inter.clone(), false, false)
+        };
+        frames.push(frame);
+        frames.push(BroadcastFrame::audio(timestamp, audio.clone(), false));
+    }
+    frames
+}
+
+/// Set up a published stream with `subscribers` draining tasks
+fn setup(rt: &Runtime, subscribers: usize) -> (Arc<StreamRegistry>, StreamKey) {
+    let registry = Arc::new(StreamRegistry::new());
+    let key = StreamKey::new("live", "bench");
+
+    rt.block_on(async {
+        registry.register_publisher(&key, 1).await.unwrap();
+
+        // Sequence headers take the cache-update path once, as in a real stream
+        let video_header = Bytes::from_static(&[0x17, 0x00, 0x00, 0x00, 0x00]);
+        let audio_header = Bytes::from_static(&[0xAF, 0x00, 0x12, 0x10]);
+        registry
+            .broadcast(&key, BroadcastFrame::video(0, video_header, true, true))
+            .await;
+        registry
+            .broadcast(&key, BroadcastFrame::audio(0, audio_header, true))
+            .await;
+
+        for _ in 0..subscribers {
+            let (mut rx, _catchup) = registry.subscribe(&key).await.unwrap();
+            tokio::spawn(async move {
+                loop {
+                    match rx.recv().await {
+                        Ok(frame) => {
+                            black_box(frame);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+        }
+    });
+
+    (registry, key)
+}
+
+fn bench_broadcast(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let frames = gop();
+
+    let mut group = c.benchmark_group("broadcast_gop");
+    group.throughput(Throughput::Elements(frames.len() as u64));
+
+    for subscribers in [1, 100, 1000] {
+        let (registry, key) = setup(&rt, subscribers);
+
+        group.bench_with_input(
+            BenchmarkId::from_parameter(subscribers),
+            &subscribers,
+            |b, _| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        for frame in &frames {
+                            registry.broadcast(&key, frame.clone()).await;
+                        }
+                    })
+                })
+            },
+        );
+
+        rt.block_on(registry.unregister_publisher(&key, 1));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_broadcast);
+criterion_main!(benches);
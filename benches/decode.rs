@@ -0,0 +1,111 @@
+//! Decode-path benchmarks
+//!
+//! Parses 10k messages off the wire (chunk decode + message parse) and
+//! reports heap allocations alongside criterion timings. Audio payloads are
+//! kept alive after parsing, as they are when fanned out to subscribers.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bytes::{Bytes, BytesMut};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use rtmp_rs::amf::{amf0, AmfValue};
+use rtmp_rs::protocol::chunk::{ChunkDecoder, ChunkEncoder, RtmpChunk};
+use rtmp_rs::protocol::constants::{CSID_AUDIO, CSID_COMMAND, MSG_AUDIO, MSG_COMMAND_AMF0};
+use rtmp_rs::protocol::message::RtmpMessage;
+
+/// Allocator that counts allocations
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const MESSAGES: usize = 10_000;
+
+/// Chunk size as negotiated by the server
+const CHUNK_SIZE: u32 = 4096;
+
+/// Encode `MESSAGES` copies of a message as they would arrive on the wire
+fn wire(csid: u32, message_type: u8, payload: Bytes) -> Bytes {
+    let mut encoder = ChunkEncoder::new();
+    encoder.set_chunk_size(CHUNK_SIZE);
+    let mut wire = BytesMut::new();
+    for i in 0..MESSAGES {
+        let chunk = RtmpChunk {
+            csid,
+            timestamp: i as u32,
+            message_type,
+            stream_id: 1,
+            payload: payload.clone(),
+        };
+        encoder.encode(&chunk, &mut wire);
+    }
+    wire.freeze()
+}
+
+/// Decode every message in `wire`, returning the parsed messages
+fn parse_all(wire: &Bytes) -> Vec<RtmpMessage> {
+    let mut decoder = ChunkDecoder::new();
+    decoder.set_chunk_size(CHUNK_SIZE);
+    let mut buf = BytesMut::from(&wire[..]);
+    let mut parsed = Vec::with_capacity(MESSAGES);
+    while !buf.is_empty() {
+        if let Some(chunk) = decoder.decode(&mut buf).unwrap() {
+            parsed.push(RtmpMessage::from_chunk(&chunk).unwrap());
+        }
+    }
+    parsed
+}
+
+/// Benchmark parsing `wire` and print the allocations one pass makes
+fn bench_parse(c: &mut Criterion, name: &str, wire: Bytes) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let parsed = parse_all(&wire);
+    let allocs = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    assert_eq!(parsed.len(), MESSAGES);
+    drop(parsed);
+
+    println!(
+        "{}: {} allocations ({:.1} per message)",
+        name,
+        allocs,
+        allocs as f64 / MESSAGES as f64
+    );
+
+    c.bench_function(name, |b| b.iter(|| parse_all(black_box(&wire))));
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let publish = amf0::encode_all(&[
+        AmfValue::String("publish".into()),
+        AmfValue::Number(5.0),
+        AmfValue::Null,
+        AmfValue::String("stream_key_0123456789".into()),
+        AmfValue::String("live".into()),
+    ]);
+    bench_parse(
+        c,
+        "decode_10k_commands",
+        wire(CSID_COMMAND, MSG_COMMAND_AMF0, publish),
+    );
+
+    let audio = Bytes::from(vec![0xAF; 200]);
+    bench_parse(c, "decode_10k_audio", wire(CSID_AUDIO, MSG_AUDIO, audio));
+}
+
+criterion_group!(benches, bench_decode);
+criterion_main!(benches);
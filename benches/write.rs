@@ -0,0 +1,120 @@
+//! Subscriber write path benchmarks
+//!
+//! Delivers bursts of small audio frames to one subscriber over loopback
+//! TCP, flushing after every message (the default) and coalescing writes
+//! with [`ServerConfig::write_flush_deadline`].
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+
+use rtmp_rs::protocol::message::RtmpMessage;
+use rtmp_rs::server::connection::Connection;
+use rtmp_rs::{
+    BroadcastFrame, ClientConfig, RegistryConfig, RtmpConnector, RtmpHandler, ServerConfig,
+    StreamKey, StreamRegistry,
+};
+
+/// Audio frames per burst, enough that the flush deadline is a small part
+/// of each run
+const BURST: u32 = 10_000;
+
+/// Accepts everything
+struct AcceptAll;
+
+impl RtmpHandler for AcceptAll {}
+
+/// Start a session with `config` and a client playing from it
+fn setup(rt: &Runtime, config: ServerConfig) -> (Arc<StreamRegistry>, StreamKey, RtmpConnector) {
+    // Room for a whole burst, so the subscriber never lags
+    let registry = Arc::new(StreamRegistry::with_config(
+        RegistryConfig::default().broadcast_capacity(BURST as usize),
+    ));
+    let key = StreamKey::new("live", "bench");
+
+    let client = rt.block_on(async {
+        registry.register_publisher(&key, 1).await.unwrap();
+        let audio_header = Bytes::from_static(&[0xAF, 0x00, 0x12, 0x10]);
+        registry
+            .broadcast(&key, BroadcastFrame::audio(0, audio_header, true))
+            .await;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let session_registry = registry.clone();
+        tokio::spawn(async move {
+            let (socket, peer_addr) = listener.accept().await.unwrap();
+            socket.set_nodelay(true).unwrap();
+            let mut connection = Connection::new(
+                2,
+                socket,
+                peer_addr,
+                config,
+                Arc::new(AcceptAll),
+                session_registry,
+            );
+            let _ = connection.run().await;
+        });
+
+        let url = format!("rtmp://{}/live", addr);
+        let mut client = RtmpConnector::connect(ClientConfig::new(url))
+            .await
+            .unwrap();
+        client.play("bench").await.unwrap();
+        client
+    });
+
+    (registry, key, client)
+}
+
+fn bench_write(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let audio = Bytes::from_static(&[0xAF, 0x01, 0x21, 0x10, 0x04, 0x60, 0x8C, 0x1C]);
+
+    let mut group = c.benchmark_group("subscriber_write");
+    group.throughput(Throughput::Elements(BURST as u64));
+
+    let modes = [
+        ("per_message", Duration::ZERO),
+        ("coalesced_5ms", Duration::from_millis(5)),
+    ];
+    for (name, deadline) in modes {
+        let config = ServerConfig::default().write_flush_deadline(deadline);
+        let (registry, key, mut client) = setup(&rt, config);
+        let mut timestamp = 0u32;
+
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| {
+                rt.block_on(async {
+                    for _ in 0..BURST {
+                        timestamp = timestamp.wrapping_add(23);
+                        let frame = BroadcastFrame::audio(timestamp, audio.clone(), false);
+                        registry.broadcast(&key, frame).await;
+                    }
+
+                    let mut received = 0;
+                    while received < BURST {
+                        let message = client.read_message().await.unwrap();
+                        if let RtmpMessage::Audio { .. } = black_box(message) {
+                            received += 1;
+                        }
+                    }
+                })
+            })
+        });
+
+        rt.block_on(registry.unregister_publisher(&key, 1));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_write);
+criterion_main!(benches);
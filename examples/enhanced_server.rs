@@ -16,15 +16,14 @@
 //! Currently, OBS 30+ and some other modern clients support E-RTMP.
 //! Legacy clients (older ffmpeg, VLC) will use standard RTMP.
 
-use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicU64, Ordering};
 
-use rtmp_rs::amf::AmfValue;
+use rtmp_rs::amf::AmfObject;
 use rtmp_rs::media::{AacData, EnhancedAudioData, EnhancedVideoData, FlvTag, H264Data};
 use rtmp_rs::protocol::enhanced::CapsEx;
 use rtmp_rs::protocol::message::{ConnectParams, PublishParams};
-use rtmp_rs::server::handler::{AuthResult, MediaDeliveryMode, RtmpHandler};
+use rtmp_rs::server::handler::{AuthResult, DisconnectReason, MediaDeliveryMode, RtmpHandler};
 use rtmp_rs::session::{SessionContext, StreamContext};
 use rtmp_rs::{RtmpServer, ServerConfig};
 
@@ -123,7 +122,7 @@ impl RtmpHandler for EnhancedHandler {
         AuthResult::Accept
     }
 
-    async fn on_metadata(&self, ctx: &StreamContext, metadata: &HashMap<String, AmfValue>) {
+    async fn on_metadata(&self, ctx: &StreamContext, metadata: &AmfObject) {
         println!("[{}] Metadata received:", ctx.session.session_id);
 
         if let Some(width) = metadata.get("width").and_then(|v| v.as_number()) {
@@ -177,7 +176,7 @@ impl RtmpHandler for EnhancedHandler {
                 "[{}] Audio (legacy): {:?}, {} Hz, {} channels",
                 ctx.session.session_id,
                 config.profile(),
-                config.sampling_frequency,
+                config.output_sample_rate(),
                 config.channels()
             );
         }
@@ -214,15 +213,13 @@ impl RtmpHandler for EnhancedHandler {
             }
             EnhancedVideoData::Frame {
                 codec, frame_type, ..
-            } if frame_type.is_keyframe() => {
+            } if frame_type.is_keyframe() && self.video_frames.load(Ordering::Relaxed) == 1 => {
                 // Only log first keyframe to avoid spam
-                if self.video_frames.load(Ordering::Relaxed) == 1 {
-                    println!(
-                        "[{}] Video (E-RTMP): {} keyframe",
-                        ctx.session.session_id,
-                        codec.as_fourcc_str()
-                    );
-                }
+                println!(
+                    "[{}] Video (E-RTMP): {} keyframe",
+                    ctx.session.session_id,
+                    codec.as_fourcc_str()
+                );
             }
             _ => {}
         }
@@ -236,15 +233,12 @@ impl RtmpHandler for EnhancedHandler {
     ) {
         self.audio_frames.fetch_add(1, Ordering::Relaxed);
 
-        match frame {
-            EnhancedAudioData::SequenceHeader { codec, .. } => {
-                println!(
-                    "[{}] Audio (E-RTMP): {} sequence header",
-                    ctx.session.session_id,
-                    codec.as_fourcc_str()
-                );
-            }
-            _ => {}
+        if let EnhancedAudioData::SequenceHeader { codec, .. } = frame {
+            println!(
+                "[{}] Audio (E-RTMP): {} sequence header",
+                ctx.session.session_id,
+                codec.as_fourcc_str()
+            );
         }
     }
 
@@ -263,8 +257,8 @@ impl RtmpHandler for EnhancedHandler {
         );
     }
 
-    async fn on_disconnect(&self, ctx: &SessionContext) {
-        println!("[{}] Disconnected", ctx.session_id);
+    async fn on_disconnect(&self, ctx: &SessionContext, reason: &DisconnectReason) {
+        println!("[{}] Disconnected: {:?}", ctx.session_id, reason);
     }
 
     fn media_delivery_mode(&self) -> MediaDeliveryMode {
@@ -42,12 +42,12 @@
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use rtmp_rs::media::{FlvTag, FlvTagType};
 use rtmp_rs::protocol::message::PublishParams;
-use rtmp_rs::server::handler::{AuthResult, MediaDeliveryMode, RtmpHandler};
+use rtmp_rs::server::handler::{AuthResult, DisconnectReason, MediaDeliveryMode, RtmpHandler};
 use rtmp_rs::session::{SessionContext, StreamContext};
 use rtmp_rs::{RtmpServer, ServerConfig};
 
@@ -140,7 +140,7 @@ struct StreamRecorder {
 
 impl StreamRecorder {
     /// Create a new recorder for the given stream key
-    fn new(stream_key: &str, output_dir: &PathBuf) -> std::io::Result<Self> {
+    fn new(stream_key: &str, output_dir: &Path) -> std::io::Result<Self> {
         // Sanitize stream key for use as filename (replace unsafe chars)
         let safe_name: String = stream_key
             .chars()
@@ -338,8 +338,8 @@ impl RtmpHandler for RecorderHandler {
     }
 
     /// Log disconnections
-    async fn on_disconnect(&self, ctx: &SessionContext) {
-        println!("[{}] Disconnected", ctx.session_id);
+    async fn on_disconnect(&self, ctx: &SessionContext, reason: &DisconnectReason) {
+        println!("[{}] Disconnected: {:?}", ctx.session_id, reason);
     }
 }
 
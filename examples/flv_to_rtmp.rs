@@ -0,0 +1,47 @@
+//! FLV to RTMP - Republishes an FLV file as a live stream
+//!
+//! Run with: cargo run --example flv_to_rtmp -- input.flv rtmp://localhost/live/test_key [--loop]
+//!
+//! The file's tags are sent as they are, paced by their timestamps, like
+//! `ffmpeg -re -i input.flv -c copy -f flv rtmp://...`. With `--loop` the
+//! file repeats until interrupted, with timestamps continuing across loops.
+
+use rtmp_rs::client::{ClientConfig, FlvSource, RtmpPublisher};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Initialize logging
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::from_default_env()
+                .add_directive("rtmp_rs=info".parse()?)
+                .add_directive("flv_to_rtmp=info".parse()?),
+        )
+        .init();
+
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 {
+        eprintln!("Usage: flv_to_rtmp <input.flv> <rtmp_url> [--loop]");
+        eprintln!("Example: flv_to_rtmp input.flv rtmp://localhost/live/test_key --loop");
+        std::process::exit(1);
+    }
+    let path = &args[1];
+    let url = &args[2];
+    let looping = args[3..].iter().any(|a| a == "--loop");
+
+    let mut source = FlvSource::open(path)?.looping(looping);
+
+    println!("Connecting to {}", url);
+    let (mut publisher, _events) = RtmpPublisher::new(ClientConfig::new(url));
+    publisher.connect().await?;
+    println!("Publishing {}", path);
+
+    tokio::select! {
+        result = source.publish(&mut publisher) => result?,
+        _ = tokio::signal::ctrl_c() => println!("\nInterrupted"),
+    }
+
+    publisher.disconnect().await;
+    println!("Done");
+    Ok(())
+}
@@ -7,6 +7,7 @@
 use rtmp_rs::client::{ClientConfig, ClientEvent, RtmpPuller};
 
 #[tokio::main]
+#[allow(clippy::manual_is_multiple_of)]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize logging
     tracing_subscriber::fmt()
@@ -31,15 +31,14 @@
 //! - Publisher reconnect: If publisher disconnects, stream stays alive for 10s grace period
 //! - Backpressure: Slow subscribers skip to next keyframe instead of buffering indefinitely
 
-use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
-use rtmp_rs::amf::AmfValue;
+use rtmp_rs::amf::AmfObject;
 use rtmp_rs::media::{AacData, FlvTag, H264Data};
 use rtmp_rs::protocol::message::{ConnectParams, PublishParams};
-use rtmp_rs::server::handler::{AuthResult, MediaDeliveryMode, RtmpHandler};
+use rtmp_rs::server::handler::{AuthResult, DisconnectReason, MediaDeliveryMode, RtmpHandler};
 use rtmp_rs::session::{SessionContext, StreamContext};
 use rtmp_rs::{RtmpServer, ServerConfig};
 
@@ -107,7 +106,7 @@ impl RtmpHandler for MyHandler {
         AuthResult::Accept
     }
 
-    async fn on_metadata(&self, ctx: &StreamContext, metadata: &HashMap<String, AmfValue>) {
+    async fn on_metadata(&self, ctx: &StreamContext, metadata: &AmfObject) {
         println!("[{}] Metadata received:", ctx.session.session_id);
 
         if let Some(width) = metadata.get("width").and_then(|v| v.as_number()) {
@@ -184,6 +183,7 @@ impl RtmpHandler for MyHandler {
         }
     }
 
+    #[allow(clippy::manual_is_multiple_of)]
     async fn on_keyframe(&self, ctx: &StreamContext, _timestamp: u32) {
         self.keyframes.fetch_add(1, Ordering::Relaxed);
 
@@ -209,8 +209,8 @@ impl RtmpHandler for MyHandler {
         self.print_stats();
     }
 
-    async fn on_disconnect(&self, ctx: &SessionContext) {
-        println!("[{}] Disconnected", ctx.session_id);
+    async fn on_disconnect(&self, ctx: &SessionContext, reason: &DisconnectReason) {
+        println!("[{}] Disconnected: {:?}", ctx.session_id, reason);
     }
 
     /// Controls which media callbacks are invoked for incoming A/V data.
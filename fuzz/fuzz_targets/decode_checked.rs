@@ -0,0 +1,46 @@
+//! Fuzz the `decode_checked` entry points
+//!
+//! The first input byte picks the parser, the rest is its input. Every
+//! parser must return (`Ok` or `Err`) without panicking, and within the
+//! limits below.
+//!
+//! Run with `cargo fuzz run decode_checked` from the repository root.
+
+#![no_main]
+
+use bytes::Bytes;
+use libfuzzer_sys::fuzz_target;
+
+use rtmp_rs::amf::{amf0, amf3};
+use rtmp_rs::media::{AacData, AvcConfig, H264Data};
+use rtmp_rs::DecodeLimits;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, input)) = data.split_first() else {
+        return;
+    };
+
+    let limits = DecodeLimits::default()
+        .max_input_size(64 * 1024)
+        .max_depth(32)
+        .max_collection_len(4096)
+        .max_output_size(1024 * 1024);
+
+    match selector % 5 {
+        0 => {
+            let _ = amf0::decode_all_checked(input, limits);
+        }
+        1 => {
+            let _ = amf3::decode_checked(input, limits);
+        }
+        2 => {
+            let _ = H264Data::decode_checked(Bytes::copy_from_slice(input), limits);
+        }
+        3 => {
+            let _ = AvcConfig::decode_checked(Bytes::copy_from_slice(input), limits);
+        }
+        _ => {
+            let _ = AacData::decode_checked(Bytes::copy_from_slice(input), limits);
+        }
+    }
+});
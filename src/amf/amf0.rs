@@ -24,12 +24,21 @@
 //! 0x10 - Typed Object (class name + properties)
 //! 0x11 - AVM+ (switch to AMF3)
 //! ```
+//!
+//! The AVM+ marker can be handled in two ways:
+//! - Per-value (default): only the value immediately following `0x11` is
+//!   decoded as AMF3, then decoding returns to AMF0.
+//! - Sticky ([`Amf0Decoder::sticky_avmplus`]): a top-level `0x11` switches the
+//!   decoder to AMF3 for the rest of the message, as FMS does. Call
+//!   [`Amf0Decoder::reset`] between messages to switch back.
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use std::collections::HashMap;
 
-use super::value::AmfValue;
-use crate::error::AmfError;
+use super::amf3::Amf3Decoder;
+use super::object::AmfObject;
+use super::value::{AmfValue, PropertyOrder};
+use crate::error::{AmfError, DecodeError};
+use crate::limits::{DecodeLimits, DEFAULT_MAX_OUTPUT_SIZE, DEFAULT_MAX_STRING_LEN};
 
 // AMF0 type markers
 const MARKER_NUMBER: u8 = 0x00;
@@ -52,14 +61,42 @@ const MARKER_AVMPLUS: u8 = 0x11;
 /// Maximum nesting depth for objects/arrays (prevent stack overflow)
 const MAX_NESTING_DEPTH: usize = 64;
 
+/// Output size charged for every decoded value, on top of owned string data
+pub(super) const VALUE_SIZE: usize = std::mem::size_of::<AmfValue>();
+
+/// Default cap on the declared element count of a strict array
+pub const DEFAULT_MAX_COLLECTION_LEN: usize = 1024 * 1024;
+
 /// AMF0 decoder with lenient parsing mode
 pub struct Amf0Decoder {
     /// Reference table for object references
     references: Vec<AmfValue>,
+    /// Decoded size of each reference table entry
+    reference_sizes: Vec<usize>,
     /// Enable lenient parsing for encoder quirks
     lenient: bool,
     /// Current nesting depth
     depth: usize,
+    /// Maximum declared strict array length
+    max_collection_len: usize,
+    /// Maximum declared string length
+    max_string_len: usize,
+    /// Maximum nesting depth
+    max_depth: usize,
+    /// Maximum approximate size of decoded values
+    max_output_size: usize,
+    /// Approximate size of values decoded since the last reset
+    output_size: usize,
+    /// Decoder for AMF3 values following an AVM+ marker
+    avmplus: Amf3Decoder,
+    /// Stay in AMF3 after a top-level AVM+ marker
+    sticky_avmplus: bool,
+    /// Whether a sticky AVM+ switch has happened in this message
+    in_avmplus: bool,
+    /// Malformed values recovered from in lenient mode
+    recovered_errors: u64,
+    /// Log recoveries with `tracing::warn`
+    warn_on_recovery: bool,
 }
 
 impl Amf0Decoder {
@@ -67,8 +104,19 @@ impl Amf0Decoder {
     pub fn new() -> Self {
         Self {
             references: Vec::new(),
+            reference_sizes: Vec::new(),
             lenient: true, // Default to lenient for OBS/encoder compatibility
             depth: 0,
+            max_collection_len: DEFAULT_MAX_COLLECTION_LEN,
+            max_string_len: DEFAULT_MAX_STRING_LEN,
+            max_depth: MAX_NESTING_DEPTH,
+            max_output_size: DEFAULT_MAX_OUTPUT_SIZE,
+            output_size: 0,
+            avmplus: Amf3Decoder::new(),
+            sticky_avmplus: false,
+            in_avmplus: false,
+            recovered_errors: 0,
+            warn_on_recovery: false,
         }
     }
 
@@ -76,15 +124,100 @@ impl Amf0Decoder {
     pub fn with_lenient(lenient: bool) -> Self {
         Self {
             references: Vec::new(),
+            reference_sizes: Vec::new(),
             lenient,
             depth: 0,
+            max_collection_len: DEFAULT_MAX_COLLECTION_LEN,
+            max_string_len: DEFAULT_MAX_STRING_LEN,
+            max_depth: MAX_NESTING_DEPTH,
+            max_output_size: DEFAULT_MAX_OUTPUT_SIZE,
+            output_size: 0,
+            avmplus: Amf3Decoder::new(),
+            sticky_avmplus: false,
+            in_avmplus: false,
+            recovered_errors: 0,
+            warn_on_recovery: false,
         }
     }
 
+    /// Set the maximum declared strict array length
+    ///
+    /// Arrays declaring more elements than this fail with
+    /// `AmfError::LengthExceeded` before any element is decoded.
+    pub fn max_collection_len(mut self, len: usize) -> Self {
+        self.max_collection_len = len;
+        self.avmplus = self.avmplus.max_collection_len(len);
+        self
+    }
+
+    /// Set the maximum declared string length in bytes
+    ///
+    /// Applies to strings, long strings, XML documents and property names.
+    /// Longer strings fail with `AmfError::LengthExceeded` before anything
+    /// is read.
+    pub fn max_string_len(mut self, len: usize) -> Self {
+        self.max_string_len = len;
+        self.avmplus = self.avmplus.max_string_len(len);
+        self
+    }
+
+    /// Set the maximum nesting depth of objects and arrays
+    ///
+    /// Deeper values fail with `AmfError::NestingTooDeep`.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self.avmplus = self.avmplus.max_depth(depth);
+        self
+    }
+
+    /// Set the maximum approximate size of the values decoded between resets
+    ///
+    /// See [`DecodeLimits::max_output_size`] for how size is counted.
+    /// Exceeding it fails with `AmfError::OutputTooLarge`. Defaults to
+    /// [`DEFAULT_MAX_OUTPUT_SIZE`].
+    pub fn max_output_size(mut self, size: usize) -> Self {
+        self.max_output_size = size;
+        self.avmplus = self.avmplus.max_output_size(size);
+        self
+    }
+
+    /// Stay in AMF3 for the rest of the message after a top-level AVM+ marker
+    ///
+    /// When disabled (the default) only the value directly after `0x11` is
+    /// decoded as AMF3.
+    pub fn sticky_avmplus(mut self, sticky: bool) -> Self {
+        self.sticky_avmplus = sticky;
+        self
+    }
+
+    /// Log each lenient recovery with `tracing::warn`
+    ///
+    /// Warnings are rate-limited across all decoders, so a misbehaving
+    /// encoder cannot flood the log.
+    pub fn warn_on_recovery(mut self, warn: bool) -> Self {
+        self.warn_on_recovery = warn;
+        self.avmplus = self.avmplus.warn_on_recovery(warn);
+        self
+    }
+
+    /// Get the number of malformed values recovered from in lenient mode
+    ///
+    /// Counts unknown markers decoded as `Undefined` and objects closed
+    /// without their end marker, including inside AMF3 values. Kept across
+    /// [`reset`](Self::reset), so a long-lived decoder tallies how
+    /// non-conformant an encoder is.
+    pub fn recovered_errors(&self) -> u64 {
+        self.recovered_errors + self.avmplus.recovered_errors()
+    }
+
     /// Reset decoder state (call between messages)
     pub fn reset(&mut self) {
         self.references.clear();
+        self.reference_sizes.clear();
         self.depth = 0;
+        self.output_size = 0;
+        self.avmplus.reset();
+        self.in_avmplus = false;
     }
 
     /// Decode a single AMF0 value from the buffer
@@ -93,10 +226,19 @@ impl Amf0Decoder {
             return Err(AmfError::UnexpectedEof);
         }
 
+        if self.in_avmplus && self.depth == 0 {
+            return self.decode_amf3(buf);
+        }
+
         self.depth += 1;
-        if self.depth > MAX_NESTING_DEPTH {
+        if self.depth > self.max_depth {
+            self.depth -= 1;
             return Err(AmfError::NestingTooDeep);
         }
+        if let Err(e) = self.charge(VALUE_SIZE) {
+            self.depth -= 1;
+            return Err(e);
+        }
 
         let marker = buf.get_u8();
         let result = self.decode_value(marker, buf);
@@ -113,6 +255,26 @@ impl Amf0Decoder {
         Ok(values)
     }
 
+    /// Decode a single value, reporting where in `buf` decoding failed
+    pub fn decode_with_context(
+        &mut self,
+        buf: &mut Bytes,
+    ) -> Result<AmfValue, DecodeError<AmfError>> {
+        let input = buf.clone();
+        self.decode(buf)
+            .map_err(|e| DecodeError::new(e, &input, input.len() - buf.len()))
+    }
+
+    /// Decode all values, reporting where in `buf` decoding failed
+    pub fn decode_all_with_context(
+        &mut self,
+        buf: &mut Bytes,
+    ) -> Result<Vec<AmfValue>, DecodeError<AmfError>> {
+        let input = buf.clone();
+        self.decode_all(buf)
+            .map_err(|e| DecodeError::new(e, &input, input.len() - buf.len()))
+    }
+
     fn decode_value(&mut self, marker: u8, buf: &mut Bytes) -> Result<AmfValue, AmfError> {
         match marker {
             MARKER_NUMBER => self.decode_number(buf),
@@ -129,14 +291,11 @@ impl Amf0Decoder {
             MARKER_UNSUPPORTED => Ok(AmfValue::Undefined),
             MARKER_XML_DOCUMENT => self.decode_xml(buf),
             MARKER_TYPED_OBJECT => self.decode_typed_object(buf),
-            MARKER_AVMPLUS => {
-                // AMF3 value embedded in AMF0 stream
-                // For now, skip and return null (full AMF3 support in amf3.rs)
-                Ok(AmfValue::Null)
-            }
+            MARKER_AVMPLUS => self.decode_avmplus(buf),
             _ => {
                 if self.lenient {
                     // Skip unknown marker in lenient mode
+                    self.recover("unknown marker");
                     Ok(AmfValue::Undefined)
                 } else {
                     Err(AmfError::UnknownMarker(marker))
@@ -170,11 +329,10 @@ impl Amf0Decoder {
     }
 
     fn decode_object(&mut self, buf: &mut Bytes) -> Result<AmfValue, AmfError> {
-        let mut properties = HashMap::new();
+        let mut properties = AmfObject::new();
 
         // Track this object for potential references
-        let obj_index = self.references.len();
-        self.references.push(AmfValue::Null); // Placeholder
+        let (obj_index, start) = self.reserve_reference();
 
         loop {
             let key = self.read_utf8(buf)?;
@@ -184,6 +342,7 @@ impl Amf0Decoder {
                 if buf.is_empty() {
                     if self.lenient {
                         // OBS sometimes omits the object end marker
+                        self.recover("missing object end");
                         break;
                     }
                     return Err(AmfError::UnexpectedEof);
@@ -194,6 +353,7 @@ impl Amf0Decoder {
                 } else if self.lenient {
                     // Some encoders omit the end marker, treat as end
                     // Put the byte back conceptually by continuing
+                    self.recover("invalid object end");
                     break;
                 } else {
                     return Err(AmfError::InvalidObjectEnd);
@@ -205,7 +365,7 @@ impl Amf0Decoder {
         }
 
         let obj = AmfValue::Object(properties);
-        self.references[obj_index] = obj.clone();
+        self.complete_reference(obj_index, start, &obj);
         Ok(obj)
     }
 
@@ -218,10 +378,9 @@ impl Amf0Decoder {
         let _count = buf.get_u32();
 
         // Track for references
-        let arr_index = self.references.len();
-        self.references.push(AmfValue::Null);
+        let (arr_index, start) = self.reserve_reference();
 
-        let mut properties = HashMap::new();
+        let mut properties = AmfObject::new();
 
         loop {
             let key = self.read_utf8(buf)?;
@@ -229,6 +388,7 @@ impl Amf0Decoder {
             if key.is_empty() {
                 if buf.is_empty() {
                     if self.lenient {
+                        self.recover("missing object end");
                         break;
                     }
                     return Err(AmfError::UnexpectedEof);
@@ -237,6 +397,7 @@ impl Amf0Decoder {
                 if end_marker == MARKER_OBJECT_END {
                     break;
                 } else if self.lenient {
+                    self.recover("invalid object end");
                     break;
                 } else {
                     return Err(AmfError::InvalidObjectEnd);
@@ -248,7 +409,7 @@ impl Amf0Decoder {
         }
 
         let arr = AmfValue::EcmaArray(properties);
-        self.references[arr_index] = arr.clone();
+        self.complete_reference(arr_index, start, &arr);
         Ok(arr)
     }
 
@@ -258,10 +419,10 @@ impl Amf0Decoder {
         }
 
         let count = buf.get_u32() as usize;
+        self.check_collection_len(count, buf)?;
 
         // Track for references
-        let arr_index = self.references.len();
-        self.references.push(AmfValue::Null);
+        let (arr_index, start) = self.reserve_reference();
 
         let mut elements = Vec::with_capacity(count.min(1024)); // Cap initial allocation
         for _ in 0..count {
@@ -269,7 +430,7 @@ impl Amf0Decoder {
         }
 
         let arr = AmfValue::Array(elements);
-        self.references[arr_index] = arr.clone();
+        self.complete_reference(arr_index, start, &arr);
         Ok(arr)
     }
 
@@ -294,6 +455,8 @@ impl Amf0Decoder {
             return Err(AmfError::InvalidReference(index as u16));
         }
 
+        // A reference repeats the whole value, so it counts in full again
+        self.charge(self.reference_sizes[index])?;
         Ok(self.references[index].clone())
     }
 
@@ -306,10 +469,9 @@ impl Amf0Decoder {
         let class_name = self.read_utf8(buf)?;
 
         // Track for references
-        let obj_index = self.references.len();
-        self.references.push(AmfValue::Null);
+        let (obj_index, start) = self.reserve_reference();
 
-        let mut properties = HashMap::new();
+        let mut properties = AmfObject::new();
 
         loop {
             let key = self.read_utf8(buf)?;
@@ -317,6 +479,7 @@ impl Amf0Decoder {
             if key.is_empty() {
                 if buf.is_empty() {
                     if self.lenient {
+                        self.recover("missing object end");
                         break;
                     }
                     return Err(AmfError::UnexpectedEof);
@@ -325,6 +488,7 @@ impl Amf0Decoder {
                 if end_marker == MARKER_OBJECT_END {
                     break;
                 } else if self.lenient {
+                    self.recover("invalid object end");
                     break;
                 } else {
                     return Err(AmfError::InvalidObjectEnd);
@@ -339,10 +503,94 @@ impl Amf0Decoder {
             class_name,
             properties,
         };
-        self.references[obj_index] = obj.clone();
+        self.complete_reference(obj_index, start, &obj);
         Ok(obj)
     }
 
+    /// Decode the AMF3 value following an AVM+ marker
+    ///
+    /// The AMF3 reference tables are independent of AMF0's and start fresh
+    /// at each switch. Nesting depth carries over so that alternating
+    /// AMF0/AMF3 containers still hit `MAX_NESTING_DEPTH`.
+    fn decode_avmplus(&mut self, buf: &mut Bytes) -> Result<AmfValue, AmfError> {
+        if !self.in_avmplus {
+            self.avmplus.reset();
+        }
+        let value = self.decode_amf3(buf);
+
+        // Only a top-level marker switches the rest of the message
+        if self.sticky_avmplus && self.depth == 1 {
+            self.in_avmplus = true;
+        }
+        value
+    }
+
+    /// Decode an AMF3 value, carrying nesting depth and output size across
+    fn decode_amf3(&mut self, buf: &mut Bytes) -> Result<AmfValue, AmfError> {
+        self.avmplus.set_depth(self.depth);
+        self.avmplus.set_output_size(self.output_size);
+        let value = self.avmplus.decode(buf);
+        self.avmplus.set_depth(0);
+        self.output_size = self.avmplus.output_size();
+        value
+    }
+
+    /// Count a lenient recovery, and log it if enabled
+    fn recover(&mut self, what: &'static str) {
+        self.recovered_errors += 1;
+        if self.warn_on_recovery {
+            super::warn_recovery("amf0", what);
+        }
+    }
+
+    /// Account for `size` bytes of decoded output
+    fn charge(&mut self, size: usize) -> Result<(), AmfError> {
+        self.output_size = self.output_size.saturating_add(size);
+        if self.output_size > self.max_output_size {
+            return Err(AmfError::OutputTooLarge {
+                limit: self.max_output_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reserve a reference table slot for a value about to be decoded
+    ///
+    /// Returns the slot index and the output size at the start of the value.
+    fn reserve_reference(&mut self) -> (usize, usize) {
+        self.references.push(AmfValue::Null); // Placeholder
+        self.reference_sizes.push(0);
+        (self.references.len() - 1, self.output_size)
+    }
+
+    /// Fill a reserved reference slot with the decoded value and its size
+    fn complete_reference(&mut self, index: usize, start: usize, value: &AmfValue) {
+        self.references[index] = value.clone();
+        self.reference_sizes[index] = self.output_size - start;
+    }
+
+    /// Reject declared element counts that exceed the configured cap, or
+    /// that cannot fit in the remaining buffer (every element is at least
+    /// one byte long).
+    fn check_collection_len(&self, declared: usize, buf: &Bytes) -> Result<(), AmfError> {
+        let limit = self.max_collection_len.min(buf.remaining());
+        if declared > limit {
+            return Err(AmfError::LengthExceeded { declared, limit });
+        }
+        Ok(())
+    }
+
+    /// Reject declared string lengths above the configured cap
+    fn check_string_len(&self, declared: usize) -> Result<(), AmfError> {
+        if declared > self.max_string_len {
+            return Err(AmfError::LengthExceeded {
+                declared,
+                limit: self.max_string_len,
+            });
+        }
+        Ok(())
+    }
+
     /// Read UTF-8 string with 16-bit length prefix
     fn read_utf8(&mut self, buf: &mut Bytes) -> Result<String, AmfError> {
         if buf.remaining() < 2 {
@@ -350,12 +598,13 @@ impl Amf0Decoder {
         }
 
         let len = buf.get_u16() as usize;
+        self.check_string_len(len)?;
         if buf.remaining() < len {
             return Err(AmfError::UnexpectedEof);
         }
 
-        let bytes = buf.copy_to_bytes(len);
-        String::from_utf8(bytes.to_vec()).map_err(|_| AmfError::InvalidUtf8)
+        self.charge(len)?;
+        read_str(buf, len)
     }
 
     /// Read UTF-8 string with 32-bit length prefix
@@ -365,12 +614,13 @@ impl Amf0Decoder {
         }
 
         let len = buf.get_u32() as usize;
+        self.check_string_len(len)?;
         if buf.remaining() < len {
             return Err(AmfError::UnexpectedEof);
         }
 
-        let bytes = buf.copy_to_bytes(len);
-        String::from_utf8(bytes.to_vec()).map_err(|_| AmfError::InvalidUtf8)
+        self.charge(len)?;
+        read_str(buf, len)
     }
 }
 
@@ -383,23 +633,29 @@ impl Default for Amf0Decoder {
 /// AMF0 encoder
 pub struct Amf0Encoder {
     buf: BytesMut,
+    property_order: PropertyOrder,
 }
 
 impl Amf0Encoder {
     /// Create a new encoder
     pub fn new() -> Self {
-        Self {
-            buf: BytesMut::with_capacity(256),
-        }
+        Self::with_capacity(256)
     }
 
     /// Create encoder with specific capacity
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             buf: BytesMut::with_capacity(capacity),
+            property_order: PropertyOrder::default(),
         }
     }
 
+    /// Set the order in which object properties are written
+    pub fn property_order(mut self, order: PropertyOrder) -> Self {
+        self.property_order = order;
+        self
+    }
+
     /// Get the encoded bytes and reset encoder
     pub fn finish(&mut self) -> Bytes {
         self.buf.split().freeze()
@@ -450,10 +706,7 @@ impl Amf0Encoder {
             }
             AmfValue::Object(props) => {
                 self.buf.put_u8(MARKER_OBJECT);
-                for (key, val) in props {
-                    self.write_utf8(key);
-                    self.encode(val);
-                }
+                self.write_properties(props);
                 // Object end marker
                 self.buf.put_u16(0); // Empty key
                 self.buf.put_u8(MARKER_OBJECT_END);
@@ -461,10 +714,7 @@ impl Amf0Encoder {
             AmfValue::EcmaArray(props) => {
                 self.buf.put_u8(MARKER_ECMA_ARRAY);
                 self.buf.put_u32(props.len() as u32);
-                for (key, val) in props {
-                    self.write_utf8(key);
-                    self.encode(val);
-                }
+                self.write_properties(props);
                 self.buf.put_u16(0);
                 self.buf.put_u8(MARKER_OBJECT_END);
             }
@@ -491,10 +741,7 @@ impl Amf0Encoder {
             } => {
                 self.buf.put_u8(MARKER_TYPED_OBJECT);
                 self.write_utf8(class_name);
-                for (key, val) in properties {
-                    self.write_utf8(key);
-                    self.encode(val);
-                }
+                self.write_properties(properties);
                 self.buf.put_u16(0);
                 self.buf.put_u8(MARKER_OBJECT_END);
             }
@@ -512,6 +759,14 @@ impl Amf0Encoder {
         }
     }
 
+    /// Write key/value pairs in the configured property order
+    fn write_properties(&mut self, props: &AmfObject) {
+        for (key, val) in self.property_order.apply(props) {
+            self.write_utf8(key);
+            self.encode(val);
+        }
+    }
+
     /// Write UTF-8 string with 16-bit length prefix (no type marker)
     fn write_utf8(&mut self, s: &str) {
         let len = s.len().min(0xFFFF);
@@ -526,6 +781,15 @@ impl Default for Amf0Encoder {
     }
 }
 
+/// Read `len` bytes as a UTF-8 string, copying only into the final `String`
+pub(super) fn read_str(buf: &mut Bytes, len: usize) -> Result<String, AmfError> {
+    let s = std::str::from_utf8(&buf[..len])
+        .map_err(|_| AmfError::InvalidUtf8)?
+        .to_owned();
+    buf.advance(len);
+    Ok(s)
+}
+
 /// Convenience function to encode a single value
 pub fn encode(value: &AmfValue) -> Bytes {
     let mut encoder = Amf0Encoder::new();
@@ -554,6 +818,38 @@ pub fn decode_all(data: &[u8]) -> Result<Vec<AmfValue>, AmfError> {
     decoder.decode_all(&mut buf)
 }
 
+/// Decode a single value from untrusted input, enforcing `limits`
+pub fn decode_checked(data: &[u8], limits: DecodeLimits) -> Result<AmfValue, AmfError> {
+    let mut decoder = checked_decoder(data, limits)?;
+    let mut buf = Bytes::copy_from_slice(data);
+    decoder.decode(&mut buf)
+}
+
+/// Decode all values from untrusted input, enforcing `limits`
+///
+/// The output size limit covers all values together.
+pub fn decode_all_checked(data: &[u8], limits: DecodeLimits) -> Result<Vec<AmfValue>, AmfError> {
+    let mut decoder = checked_decoder(data, limits)?;
+    let mut buf = Bytes::copy_from_slice(data);
+    decoder.decode_all(&mut buf)
+}
+
+/// Check the input size and build a decoder bounded by `limits`
+fn checked_decoder(data: &[u8], limits: DecodeLimits) -> Result<Amf0Decoder, AmfError> {
+    if data.len() > limits.max_input_size {
+        return Err(AmfError::InputTooLarge {
+            size: data.len(),
+            limit: limits.max_input_size,
+        });
+    }
+
+    Ok(Amf0Decoder::new()
+        .max_depth(limits.max_depth)
+        .max_collection_len(limits.max_collection_len)
+        .max_string_len(limits.max_string_len)
+        .max_output_size(limits.max_output_size))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -592,7 +888,7 @@ mod tests {
 
     #[test]
     fn test_object_roundtrip() {
-        let mut props = HashMap::new();
+        let mut props = AmfObject::new();
         props.insert("name".to_string(), AmfValue::String("test".into()));
         props.insert("value".to_string(), AmfValue::Number(123.0));
         let value = AmfValue::Object(props);
@@ -662,9 +958,76 @@ mod tests {
         assert_eq!(decoded, AmfValue::Date(timestamp));
     }
 
+    /// `@setDataFrame` payload as ffmpeg writes it (`-f flv rtmp://...`)
+    const FFMPEG_METADATA: &[u8] = &[
+        0x02, 0x00, 0x0d, 0x40, 0x73, 0x65, 0x74, 0x44, 0x61, 0x74, 0x61, 0x46, 0x72, 0x61, 0x6d,
+        0x65, 0x02, 0x00, 0x0a, 0x6f, 0x6e, 0x4d, 0x65, 0x74, 0x61, 0x44, 0x61, 0x74, 0x61, 0x08,
+        0x00, 0x00, 0x00, 0x0d, 0x00, 0x08, 0x64, 0x75, 0x72, 0x61, 0x74, 0x69, 0x6f, 0x6e, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x77, 0x69, 0x64, 0x74, 0x68,
+        0x00, 0x40, 0x94, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x68, 0x65, 0x69, 0x67,
+        0x68, 0x74, 0x00, 0x40, 0x86, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x76, 0x69,
+        0x64, 0x65, 0x6f, 0x64, 0x61, 0x74, 0x61, 0x72, 0x61, 0x74, 0x65, 0x00, 0x40, 0xa3, 0x88,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09, 0x66, 0x72, 0x61, 0x6d, 0x65, 0x72, 0x61, 0x74,
+        0x65, 0x00, 0x40, 0x3e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x76, 0x69, 0x64,
+        0x65, 0x6f, 0x63, 0x6f, 0x64, 0x65, 0x63, 0x69, 0x64, 0x00, 0x40, 0x1c, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x0d, 0x61, 0x75, 0x64, 0x69, 0x6f, 0x64, 0x61, 0x74, 0x61, 0x72,
+        0x61, 0x74, 0x65, 0x00, 0x40, 0x60, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0f, 0x61,
+        0x75, 0x64, 0x69, 0x6f, 0x73, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x72, 0x61, 0x74, 0x65, 0x00,
+        0x40, 0xe7, 0x70, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0f, 0x61, 0x75, 0x64, 0x69, 0x6f,
+        0x73, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x73, 0x69, 0x7a, 0x65, 0x00, 0x40, 0x30, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x73, 0x74, 0x65, 0x72, 0x65, 0x6f, 0x01, 0x01, 0x00,
+        0x0c, 0x61, 0x75, 0x64, 0x69, 0x6f, 0x63, 0x6f, 0x64, 0x65, 0x63, 0x69, 0x64, 0x00, 0x40,
+        0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x65, 0x6e, 0x63, 0x6f, 0x64, 0x65,
+        0x72, 0x02, 0x00, 0x0d, 0x4c, 0x61, 0x76, 0x66, 0x36, 0x30, 0x2e, 0x31, 0x36, 0x2e, 0x31,
+        0x30, 0x30, 0x00, 0x08, 0x66, 0x69, 0x6c, 0x65, 0x73, 0x69, 0x7a, 0x65, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09,
+    ];
+
+    #[test]
+    fn test_ecma_array_marker_survives_round_trip() {
+        let values = decode_all(FFMPEG_METADATA).unwrap();
+        assert_eq!(values.len(), 3);
+        let props = match &values[2] {
+            AmfValue::EcmaArray(props) => props,
+            other => panic!("Expected EcmaArray, got {:?}", other),
+        };
+        assert_eq!(props.len(), 13);
+        assert_eq!(
+            props.get("encoder").unwrap().as_str(),
+            Some("Lavf60.16.100")
+        );
+
+        // Marker and declared count follow the two name strings
+        let encoded = encode_all(&values);
+        assert_eq!(encoded.len(), FFMPEG_METADATA.len());
+        assert_eq!(encoded[29], MARKER_ECMA_ARRAY);
+        assert_eq!(&encoded[30..34], &13u32.to_be_bytes());
+        assert_eq!(decode_all(&encoded).unwrap(), values);
+
+        #[cfg(feature = "preserve_order")]
+        assert_eq!(&encoded[..], FFMPEG_METADATA);
+    }
+
+    #[test]
+    fn test_array_markers() {
+        let strict = AmfValue::Array(vec![AmfValue::Number(1.0), AmfValue::Null]);
+        let encoded = encode(&strict);
+        assert_eq!(encoded[0], MARKER_STRICT_ARRAY);
+        assert_eq!(&encoded[1..5], &2u32.to_be_bytes());
+        assert_eq!(decode(&encoded).unwrap(), strict);
+
+        let mut props = AmfObject::new();
+        props.insert("0".to_string(), AmfValue::Number(1.0));
+        let ecma = AmfValue::EcmaArray(props);
+        let encoded = encode(&ecma);
+        assert_eq!(encoded[0], MARKER_ECMA_ARRAY);
+        assert_eq!(&encoded[1..5], &1u32.to_be_bytes());
+        assert_eq!(decode(&encoded).unwrap(), ecma);
+    }
+
     #[test]
     fn test_ecma_array_roundtrip() {
-        let mut props = HashMap::new();
+        let mut props = AmfObject::new();
         props.insert("width".to_string(), AmfValue::Number(1920.0));
         props.insert("height".to_string(), AmfValue::Number(1080.0));
         props.insert("codec".to_string(), AmfValue::String("h264".into()));
@@ -685,7 +1048,7 @@ mod tests {
 
     #[test]
     fn test_typed_object_roundtrip() {
-        let mut props = HashMap::new();
+        let mut props = AmfObject::new();
         props.insert("x".to_string(), AmfValue::Number(100.0));
         props.insert("y".to_string(), AmfValue::Number(200.0));
         let value = AmfValue::TypedObject {
@@ -719,10 +1082,10 @@ mod tests {
 
     #[test]
     fn test_nested_objects() {
-        let mut inner = HashMap::new();
+        let mut inner = AmfObject::new();
         inner.insert("key".to_string(), AmfValue::String("value".into()));
 
-        let mut outer = HashMap::new();
+        let mut outer = AmfObject::new();
         outer.insert("inner".to_string(), AmfValue::Object(inner));
         outer.insert("count".to_string(), AmfValue::Number(5.0));
 
@@ -771,7 +1134,7 @@ mod tests {
 
     #[test]
     fn test_empty_object() {
-        let value = AmfValue::Object(HashMap::new());
+        let value = AmfValue::Object(AmfObject::new());
         let encoded = encode(&value);
         let decoded = decode(&encoded).unwrap();
         if let AmfValue::Object(props) = decoded {
@@ -835,6 +1198,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::len_zero)]
     fn test_encoder_len_and_empty() {
         let mut encoder = Amf0Encoder::new();
         assert!(encoder.is_empty());
@@ -845,6 +1209,73 @@ mod tests {
         assert!(encoder.len() > 0);
     }
 
+    #[test]
+    fn test_sorted_encoding_is_stable() {
+        // Every map gets its own random hasher, so iteration order differs
+        let encode_sorted = |keys: &[&str]| {
+            let props: AmfObject = keys
+                .iter()
+                .map(|k| (k.to_string(), AmfValue::String(k.to_uppercase())))
+                .collect();
+            let mut encoder = Amf0Encoder::new().property_order(PropertyOrder::Sorted);
+            encoder.encode(&AmfValue::Object(props.clone()));
+            encoder.encode(&AmfValue::EcmaArray(props));
+            encoder.finish()
+        };
+
+        let keys = [
+            "width",
+            "height",
+            "framerate",
+            "videocodecid",
+            "audiocodecid",
+        ];
+        let mut reversed = keys;
+        reversed.reverse();
+
+        let expected = encode_sorted(&keys);
+        for _ in 0..16 {
+            assert_eq!(encode_sorted(&keys), expected);
+            assert_eq!(encode_sorted(&reversed), expected);
+        }
+
+        // First property of the object is "audiocodecid"
+        assert_eq!(&expected[1..3], &[0x00, 12]);
+        assert_eq!(&expected[3..15], b"audiocodecid");
+    }
+
+    #[cfg(feature = "preserve_order")]
+    #[test]
+    fn test_metadata_order_round_trip() {
+        // onMetaData as written by an encoder, deliberately not sorted
+        let keys = ["duration", "width", "height", "videocodecid", "encoder"];
+        let mut wire = BytesMut::new();
+        wire.put_u8(0x02);
+        wire.put_u16(10);
+        wire.put_slice(b"onMetaData");
+        wire.put_u8(0x08);
+        wire.put_u32(keys.len() as u32);
+        for (i, key) in keys.iter().enumerate() {
+            wire.put_u16(key.len() as u16);
+            wire.put_slice(key.as_bytes());
+            wire.put_u8(0x00);
+            wire.put_f64(i as f64);
+        }
+        wire.put_slice(&[0x00, 0x00, 0x09]);
+        let wire = wire.freeze();
+
+        let values = decode_all(&wire).unwrap();
+        let decoded: Vec<&str> = values[1]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(decoded, keys);
+
+        assert_eq!(encode_all(&values), wire);
+    }
+
     #[test]
     fn test_encoder_with_capacity() {
         let encoder = Amf0Encoder::with_capacity(1024);
@@ -873,6 +1304,35 @@ mod tests {
         assert!(matches!(result, Err(AmfError::UnexpectedEof)));
     }
 
+    #[test]
+    fn test_truncation_offset() {
+        // "ok", then an object whose string value declares 16 bytes but
+        // holds 3; the payload was cut at byte 12
+        let data = [
+            0x02, 0x00, 0x02, b'o', b'k', // "ok"
+            0x03, 0x00, 0x01, b'a', // object, key "a"
+            0x02, 0x00, 0x10, b'a', b'b', b'c', // truncated string
+        ];
+        let mut buf = Bytes::copy_from_slice(&data);
+        let err = Amf0Decoder::new()
+            .decode_all_with_context(&mut buf)
+            .unwrap_err();
+        assert!(matches!(err.kind(), AmfError::UnexpectedEof));
+        let context = err.context();
+        assert_eq!(context.offset, 12);
+        assert_eq!(context.hex_dump(), "6b 03 00 01 61 02 00 10 [61] 62 63");
+
+        // Offsets are into the buffer as passed in, not the value
+        let mut buf = Bytes::copy_from_slice(&data);
+        let mut decoder = Amf0Decoder::new();
+        decoder.decode_with_context(&mut buf).unwrap();
+        let err = decoder.decode_with_context(&mut buf).unwrap_err();
+        assert_eq!(err.context().offset, 7);
+
+        // The plain decoders report the bare error
+        assert!(matches!(decode_all(&data), Err(AmfError::UnexpectedEof)));
+    }
+
     #[test]
     fn test_lenient_mode_unknown_marker() {
         // In lenient mode (default), unknown markers return Undefined
@@ -881,6 +1341,35 @@ mod tests {
         assert_eq!(result, AmfValue::Undefined);
     }
 
+    #[test]
+    fn test_lenient_recovery_counted() {
+        let mut decoder = Amf0Decoder::new().warn_on_recovery(true);
+
+        // Well-formed values recover nothing
+        let mut buf = encode(&AmfValue::String("ok".into()));
+        decoder.decode(&mut buf).unwrap();
+        assert_eq!(decoder.recovered_errors(), 0);
+
+        // Unknown marker, then an object with a bad end marker
+        let mut buf = Bytes::from_static(&[0xFF, 0x03, 0x00, 0x00, 0x42]);
+        assert_eq!(decoder.decode(&mut buf).unwrap(), AmfValue::Undefined);
+        assert!(decoder.decode(&mut buf).unwrap().as_object().is_some());
+        assert_eq!(decoder.recovered_errors(), 2);
+
+        // An unknown AMF3 marker behind AVM+, and an ECMA array cut short
+        decoder.reset();
+        let mut buf = Bytes::from_static(&[0x11, 0x7F, 0x08, 0, 0, 0, 0, 0x00, 0x00]);
+        assert_eq!(decoder.decode(&mut buf).unwrap(), AmfValue::Undefined);
+        decoder.decode(&mut buf).unwrap();
+        assert_eq!(decoder.recovered_errors(), 4);
+
+        // Strict mode fails instead of recovering
+        let mut strict = Amf0Decoder::with_lenient(false);
+        let mut buf = Bytes::from_static(&[0xFF]);
+        assert!(strict.decode(&mut buf).is_err());
+        assert_eq!(strict.recovered_errors(), 0);
+    }
+
     #[test]
     fn test_strict_mode_unknown_marker() {
         let mut decoder = Amf0Decoder::with_lenient(false);
@@ -892,9 +1381,9 @@ mod tests {
     #[test]
     fn test_nesting_depth_limit() {
         // Create deeply nested objects that exceed the limit
-        let mut depth_test = AmfValue::Object(HashMap::new());
+        let mut depth_test = AmfValue::Object(AmfObject::new());
         for _ in 0..70 {
-            let mut wrapper = HashMap::new();
+            let mut wrapper = AmfObject::new();
             wrapper.insert("nested".to_string(), depth_test);
             depth_test = AmfValue::Object(wrapper);
         }
@@ -904,6 +1393,276 @@ mod tests {
         assert!(matches!(result, Err(AmfError::NestingTooDeep)));
     }
 
+    #[test]
+    fn test_strict_array_absurd_length() {
+        // Strict array declaring u32::MAX elements followed by a single null
+        let data = [MARKER_STRICT_ARRAY, 0xFF, 0xFF, 0xFF, 0xFF, MARKER_NULL];
+        let result = decode(&data);
+        assert!(matches!(
+            result,
+            Err(AmfError::LengthExceeded {
+                declared: 0xFFFF_FFFF,
+                limit: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_long_string_absurd_length() {
+        // A long string declaring just over the default cap, backed by
+        // enough bytes that the remaining-buffer check alone would pass
+        let declared = DEFAULT_MAX_STRING_LEN + 1;
+        let mut data = vec![MARKER_LONG_STRING];
+        data.extend_from_slice(&(declared as u32).to_be_bytes());
+        data.resize(5 + declared, b'a');
+        assert!(matches!(
+            decode(&data),
+            Err(AmfError::LengthExceeded {
+                declared: d,
+                limit: DEFAULT_MAX_STRING_LEN
+            }) if d == declared
+        ));
+
+        // Near-4GB declarations fail the same way, before any read
+        let data = [MARKER_LONG_STRING, 0xFF, 0xFF, 0xFF, 0xF0, b'a'];
+        assert!(matches!(
+            decode(&data),
+            Err(AmfError::LengthExceeded {
+                declared: 0xFFFF_FFF0,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_max_string_len() {
+        let value = AmfValue::String("x".repeat(64));
+        let encoded = encode(&value);
+
+        let mut decoder = Amf0Decoder::new().max_string_len(64);
+        assert_eq!(decoder.decode(&mut encoded.clone()).unwrap(), value);
+
+        let mut decoder = Amf0Decoder::new().max_string_len(32);
+        assert!(matches!(
+            decoder.decode(&mut encoded.clone()),
+            Err(AmfError::LengthExceeded {
+                declared: 64,
+                limit: 32
+            })
+        ));
+
+        let limits = DecodeLimits::default().max_string_len(32);
+        assert!(matches!(
+            decode_checked(&encoded, limits),
+            Err(AmfError::LengthExceeded {
+                declared: 64,
+                limit: 32
+            })
+        ));
+    }
+
+    #[test]
+    fn test_strict_array_max_collection_len() {
+        let value = AmfValue::Array(vec![AmfValue::Null; 8]);
+        let encoded = encode(&value);
+
+        let mut decoder = Amf0Decoder::new().max_collection_len(8);
+        assert_eq!(decoder.decode(&mut encoded.clone()).unwrap(), value);
+
+        let mut decoder = Amf0Decoder::new().max_collection_len(4);
+        let result = decoder.decode(&mut encoded.clone());
+        assert!(matches!(
+            result,
+            Err(AmfError::LengthExceeded {
+                declared: 8,
+                limit: 4
+            })
+        ));
+    }
+
+    /// Strict array whose elements each reference the previous one twice,
+    /// doubling the decoded size at every level
+    fn reference_bomb(levels: u16) -> Vec<u8> {
+        let mut data = vec![MARKER_STRICT_ARRAY];
+        data.extend_from_slice(&(levels as u32 + 1).to_be_bytes());
+
+        // Index 1: a single 64-byte string
+        data.extend_from_slice(&[MARKER_STRICT_ARRAY, 0, 0, 0, 1, MARKER_STRING, 0, 64]);
+        data.extend_from_slice(&[b'x'; 64]);
+
+        // Index k + 1: two references to index k
+        for k in 1..=levels {
+            data.extend_from_slice(&[MARKER_STRICT_ARRAY, 0, 0, 0, 2]);
+            for _ in 0..2 {
+                data.push(MARKER_REFERENCE);
+                data.extend_from_slice(&k.to_be_bytes());
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_decode_checked_reference_bomb() {
+        let data = reference_bomb(40);
+        let limits = DecodeLimits::default().max_output_size(1024 * 1024);
+
+        let result = decode_checked(&data, limits);
+        assert!(matches!(
+            result,
+            Err(AmfError::OutputTooLarge { limit: 1048576 })
+        ));
+    }
+
+    #[test]
+    fn test_decode_bounds_output_by_default() {
+        let result = decode(&reference_bomb(40));
+        assert!(matches!(
+            result,
+            Err(AmfError::OutputTooLarge {
+                limit: DEFAULT_MAX_OUTPUT_SIZE
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decode_checked_within_limits() {
+        let values = vec![
+            AmfValue::String("connect".into()),
+            AmfValue::Number(1.0),
+            AmfValue::Object(AmfObject::from([(
+                "app".to_string(),
+                AmfValue::String("live".into()),
+            )])),
+        ];
+        let encoded = encode_all(&values);
+
+        let decoded = decode_all_checked(&encoded, DecodeLimits::default()).unwrap();
+        assert_eq!(decoded, values);
+
+        // A few doublings stay well inside the default limits
+        assert!(decode_checked(&reference_bomb(4), DecodeLimits::default()).is_ok());
+    }
+
+    #[test]
+    fn test_decode_checked_input_too_large() {
+        let encoded = encode(&AmfValue::String("x".repeat(100)));
+        let limits = DecodeLimits::default().max_input_size(64);
+
+        assert!(matches!(
+            decode_checked(&encoded, limits),
+            Err(AmfError::InputTooLarge {
+                size: 103,
+                limit: 64
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decode_checked_max_depth() {
+        let mut value = AmfValue::Null;
+        for _ in 0..8 {
+            value = AmfValue::Array(vec![value]);
+        }
+        let encoded = encode(&value);
+
+        let limits = DecodeLimits::default().max_depth(9);
+        assert_eq!(decode_checked(&encoded, limits).unwrap(), value);
+
+        let limits = DecodeLimits::default().max_depth(8);
+        assert!(matches!(
+            decode_checked(&encoded, limits),
+            Err(AmfError::NestingTooDeep)
+        ));
+    }
+
+    #[test]
+    fn test_decode_checked_max_collection_len() {
+        let encoded = encode(&AmfValue::Array(vec![AmfValue::Null; 16]));
+        let limits = DecodeLimits::default().max_collection_len(15);
+
+        assert!(matches!(
+            decode_checked(&encoded, limits),
+            Err(AmfError::LengthExceeded {
+                declared: 16,
+                limit: 15
+            })
+        ));
+    }
+
+    #[test]
+    fn test_avmplus_single_value() {
+        use crate::amf::Amf3Encoder;
+
+        let mut amf3 = Amf3Encoder::new();
+        amf3.encode(&AmfValue::String("amf3".into()));
+
+        let mut data = vec![MARKER_AVMPLUS];
+        data.extend_from_slice(&amf3.finish());
+        data.extend_from_slice(&encode(&AmfValue::Number(1.0)));
+
+        // Per-value mode: back to AMF0 after the embedded value
+        let values = decode_all(&data).unwrap();
+        assert_eq!(
+            values,
+            vec![AmfValue::String("amf3".into()), AmfValue::Number(1.0)]
+        );
+    }
+
+    #[test]
+    fn test_avmplus_sticky() {
+        use crate::amf::Amf3Encoder;
+
+        let mut amf3 = Amf3Encoder::new();
+        amf3.encode(&AmfValue::String("onStatus".into()));
+        amf3.encode(&AmfValue::Integer(7));
+        amf3.encode(&AmfValue::Null);
+        amf3.encode(&AmfValue::String("onStatus".into())); // string reference
+
+        let mut data = encode(&AmfValue::String("_result".into())).to_vec();
+        data.push(MARKER_AVMPLUS);
+        data.extend_from_slice(&amf3.finish());
+        let mut buf = Bytes::from(data);
+
+        let mut decoder = Amf0Decoder::new().sticky_avmplus(true);
+        let values = decoder.decode_all(&mut buf).unwrap();
+        assert_eq!(
+            values,
+            vec![
+                AmfValue::String("_result".into()),
+                AmfValue::String("onStatus".into()),
+                AmfValue::Integer(7),
+                AmfValue::Null,
+                AmfValue::String("onStatus".into()),
+            ]
+        );
+
+        // Reset switches back to AMF0
+        decoder.reset();
+        let mut buf = encode(&AmfValue::Number(2.0));
+        assert_eq!(decoder.decode(&mut buf).unwrap(), AmfValue::Number(2.0));
+    }
+
+    #[test]
+    fn test_avmplus_nested_not_sticky() {
+        use crate::amf::Amf3Encoder;
+
+        // Object whose property value is an AVM+ switch
+        let mut amf3 = Amf3Encoder::new();
+        amf3.encode(&AmfValue::Boolean(true));
+
+        let mut data = vec![MARKER_OBJECT, 0x00, 0x01, b'a', MARKER_AVMPLUS];
+        data.extend_from_slice(&amf3.finish());
+        data.extend_from_slice(&[0x00, 0x00, MARKER_OBJECT_END]);
+        data.extend_from_slice(&encode(&AmfValue::Number(3.0)));
+        let mut buf = Bytes::from(data);
+
+        let mut decoder = Amf0Decoder::new().sticky_avmplus(true);
+        let values = decoder.decode_all(&mut buf).unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0].get("a"), Some(&AmfValue::Boolean(true)));
+        assert_eq!(values[1], AmfValue::Number(3.0));
+    }
+
     #[test]
     fn test_boolean_false() {
         let value = AmfValue::Boolean(false);
@@ -915,7 +1674,7 @@ mod tests {
     #[test]
     fn test_complex_rtmp_command() {
         // Simulate a typical connect command structure
-        let mut cmd_obj = HashMap::new();
+        let mut cmd_obj = AmfObject::new();
         cmd_obj.insert("app".to_string(), AmfValue::String("live".into()));
         cmd_obj.insert("type".to_string(), AmfValue::String("nonprivate".into()));
         cmd_obj.insert("flashVer".to_string(), AmfValue::String("FMLE/3.0".into()));
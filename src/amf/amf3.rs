@@ -26,8 +26,11 @@
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use std::collections::HashMap;
 
-use super::value::AmfValue;
-use crate::error::AmfError;
+use super::amf0::{read_str, VALUE_SIZE};
+use super::object::AmfObject;
+use super::value::{AmfValue, PropertyOrder};
+use crate::error::{AmfError, DecodeError};
+use crate::limits::{DecodeLimits, DEFAULT_MAX_OUTPUT_SIZE, DEFAULT_MAX_STRING_LEN};
 
 // AMF3 type markers
 const MARKER_UNDEFINED: u8 = 0x00;
@@ -47,6 +50,12 @@ const MARKER_BYTE_ARRAY: u8 = 0x0C;
 /// Maximum nesting depth
 const MAX_NESTING_DEPTH: usize = 64;
 
+/// Default cap on declared array element and sealed trait property counts
+pub const DEFAULT_MAX_COLLECTION_LEN: usize = 1024 * 1024;
+
+/// Default cap on declared ByteArray length
+pub const DEFAULT_MAX_BYTE_ARRAY_LEN: usize = 16 * 1024 * 1024;
+
 /// AMF3 29-bit integer bounds
 const AMF3_INT_MAX: i32 = 0x0FFFFFFF;
 const AMF3_INT_MIN: i32 = -0x10000000;
@@ -57,12 +66,30 @@ pub struct Amf3Decoder {
     string_refs: Vec<String>,
     /// Object reference table
     object_refs: Vec<AmfValue>,
+    /// Decoded size of each object reference table entry
+    object_ref_sizes: Vec<usize>,
     /// Trait reference table (class definitions)
     trait_refs: Vec<TraitDef>,
     /// Enable lenient parsing
     lenient: bool,
     /// Current nesting depth
     depth: usize,
+    /// Maximum declared array/trait length
+    max_collection_len: usize,
+    /// Maximum declared ByteArray length
+    max_byte_array_len: usize,
+    /// Maximum declared string and XML length
+    max_string_len: usize,
+    /// Maximum nesting depth
+    max_depth: usize,
+    /// Maximum approximate size of decoded values
+    max_output_size: usize,
+    /// Approximate size of values decoded since the last reset
+    output_size: usize,
+    /// Malformed values turned into `Undefined` in lenient mode
+    recovered_errors: u64,
+    /// Log recoveries with `tracing::warn`
+    warn_on_recovery: bool,
 }
 
 /// Trait definition for typed objects
@@ -79,9 +106,90 @@ impl Amf3Decoder {
         Self {
             string_refs: Vec::new(),
             object_refs: Vec::new(),
+            object_ref_sizes: Vec::new(),
             trait_refs: Vec::new(),
             lenient: true,
             depth: 0,
+            max_collection_len: DEFAULT_MAX_COLLECTION_LEN,
+            max_byte_array_len: DEFAULT_MAX_BYTE_ARRAY_LEN,
+            max_string_len: DEFAULT_MAX_STRING_LEN,
+            max_depth: MAX_NESTING_DEPTH,
+            max_output_size: DEFAULT_MAX_OUTPUT_SIZE,
+            output_size: 0,
+            recovered_errors: 0,
+            warn_on_recovery: false,
+        }
+    }
+
+    /// Set the maximum declared array element or sealed property count
+    pub fn max_collection_len(mut self, len: usize) -> Self {
+        self.max_collection_len = len;
+        self
+    }
+
+    /// Set the maximum declared ByteArray length in bytes
+    pub fn max_byte_array_len(mut self, len: usize) -> Self {
+        self.max_byte_array_len = len;
+        self
+    }
+
+    /// Set the maximum declared string and XML length in bytes
+    pub fn max_string_len(mut self, len: usize) -> Self {
+        self.max_string_len = len;
+        self
+    }
+
+    /// Set the maximum nesting depth of objects and arrays
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Set the maximum approximate size of the values decoded between resets
+    ///
+    /// See [`DecodeLimits::max_output_size`] for how size is counted.
+    /// Defaults to [`DEFAULT_MAX_OUTPUT_SIZE`].
+    pub fn max_output_size(mut self, size: usize) -> Self {
+        self.max_output_size = size;
+        self
+    }
+
+    /// Log each lenient recovery with `tracing::warn`
+    ///
+    /// Warnings are rate-limited across all decoders.
+    pub fn warn_on_recovery(mut self, warn: bool) -> Self {
+        self.warn_on_recovery = warn;
+        self
+    }
+
+    /// Get the number of malformed values recovered from in lenient mode
+    ///
+    /// Kept across [`reset`](Self::reset), so a long-lived decoder tallies a
+    /// whole session.
+    pub fn recovered_errors(&self) -> u64 {
+        self.recovered_errors
+    }
+
+    /// Set the starting nesting depth (used when embedded in AMF0)
+    pub(super) fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+    }
+
+    /// Set the output size decoded so far (used when embedded in AMF0)
+    pub(super) fn set_output_size(&mut self, size: usize) {
+        self.output_size = size;
+    }
+
+    /// Get the output size decoded so far
+    pub(super) fn output_size(&self) -> usize {
+        self.output_size
+    }
+
+    /// Count a lenient recovery, and log it if enabled
+    fn recover(&mut self, what: &'static str) {
+        self.recovered_errors += 1;
+        if self.warn_on_recovery {
+            super::warn_recovery("amf3", what);
         }
     }
 
@@ -89,8 +197,10 @@ impl Amf3Decoder {
     pub fn reset(&mut self) {
         self.string_refs.clear();
         self.object_refs.clear();
+        self.object_ref_sizes.clear();
         self.trait_refs.clear();
         self.depth = 0;
+        self.output_size = 0;
     }
 
     /// Decode a single AMF3 value
@@ -100,9 +210,14 @@ impl Amf3Decoder {
         }
 
         self.depth += 1;
-        if self.depth > MAX_NESTING_DEPTH {
+        if self.depth > self.max_depth {
+            self.depth -= 1;
             return Err(AmfError::NestingTooDeep);
         }
+        if let Err(e) = self.charge(VALUE_SIZE) {
+            self.depth -= 1;
+            return Err(e);
+        }
 
         let marker = buf.get_u8();
         let result = self.decode_value(marker, buf);
@@ -110,6 +225,16 @@ impl Amf3Decoder {
         result
     }
 
+    /// Decode a single value, reporting where in `buf` decoding failed
+    pub fn decode_with_context(
+        &mut self,
+        buf: &mut Bytes,
+    ) -> Result<AmfValue, DecodeError<AmfError>> {
+        let input = buf.clone();
+        self.decode(buf)
+            .map_err(|e| DecodeError::new(e, &input, input.len() - buf.len()))
+    }
+
     fn decode_value(&mut self, marker: u8, buf: &mut Bytes) -> Result<AmfValue, AmfError> {
         match marker {
             MARKER_UNDEFINED => Ok(AmfValue::Undefined),
@@ -126,6 +251,7 @@ impl Amf3Decoder {
             MARKER_XML | MARKER_XML_DOC => self.decode_xml(buf),
             _ => {
                 if self.lenient {
+                    self.recover("unknown marker");
                     Ok(AmfValue::Undefined)
                 } else {
                     Err(AmfError::UnknownMarker(marker))
@@ -162,11 +288,7 @@ impl Amf3Decoder {
 
         if header & 1 == 0 {
             // Reference
-            let idx = (header >> 1) as usize;
-            if idx >= self.object_refs.len() {
-                return Err(AmfError::InvalidReference(idx as u16));
-            }
-            return Ok(self.object_refs[idx].clone());
+            return self.object_reference((header >> 1) as usize);
         }
 
         if buf.remaining() < 8 {
@@ -175,7 +297,7 @@ impl Amf3Decoder {
 
         let timestamp = buf.get_f64();
         let value = AmfValue::Date(timestamp);
-        self.object_refs.push(value.clone());
+        self.push_object_ref(value.clone(), 0);
         Ok(value)
     }
 
@@ -184,21 +306,17 @@ impl Amf3Decoder {
 
         if header & 1 == 0 {
             // Reference
-            let idx = (header >> 1) as usize;
-            if idx >= self.object_refs.len() {
-                return Err(AmfError::InvalidReference(idx as u16));
-            }
-            return Ok(self.object_refs[idx].clone());
+            return self.object_reference((header >> 1) as usize);
         }
 
         let dense_count = (header >> 1) as usize;
+        self.check_len(dense_count, self.max_collection_len, buf)?;
 
         // Placeholder for self-reference
-        let arr_idx = self.object_refs.len();
-        self.object_refs.push(AmfValue::Null);
+        let (arr_idx, start) = self.reserve_object_ref();
 
         // Read associative portion (key-value pairs until empty string)
-        let mut assoc = HashMap::new();
+        let mut assoc = AmfObject::new();
         loop {
             let key = self.read_string(buf)?;
             if key.is_empty() {
@@ -224,7 +342,7 @@ impl Amf3Decoder {
             AmfValue::EcmaArray(assoc)
         };
 
-        self.object_refs[arr_idx] = value.clone();
+        self.complete_object_ref(arr_idx, start, &value);
         Ok(value)
     }
 
@@ -233,16 +351,11 @@ impl Amf3Decoder {
 
         if header & 1 == 0 {
             // Object reference
-            let idx = (header >> 1) as usize;
-            if idx >= self.object_refs.len() {
-                return Err(AmfError::InvalidReference(idx as u16));
-            }
-            return Ok(self.object_refs[idx].clone());
+            return self.object_reference((header >> 1) as usize);
         }
 
         // Placeholder for self-reference
-        let obj_idx = self.object_refs.len();
-        self.object_refs.push(AmfValue::Null);
+        let (obj_idx, start) = self.reserve_object_ref();
 
         let trait_def = if header & 2 == 0 {
             // Trait reference
@@ -255,6 +368,7 @@ impl Amf3Decoder {
             // Inline trait
             let is_dynamic = (header & 8) != 0;
             let sealed_count = (header >> 4) as usize;
+            self.check_len(sealed_count, self.max_collection_len, buf)?;
 
             let class_name = self.read_string(buf)?;
 
@@ -272,10 +386,12 @@ impl Amf3Decoder {
             trait_def
         };
 
-        let mut props = HashMap::new();
+        let mut props = AmfObject::new();
 
         // Read sealed properties
         for prop_name in &trait_def.properties {
+            // Trait references repeat the property names for every object
+            self.charge(prop_name.len())?;
             let value = self.decode(buf)?;
             props.insert(prop_name.clone(), value);
         }
@@ -301,7 +417,7 @@ impl Amf3Decoder {
             }
         };
 
-        self.object_refs[obj_idx] = value.clone();
+        self.complete_object_ref(obj_idx, start, &value);
         Ok(value)
     }
 
@@ -309,21 +425,25 @@ impl Amf3Decoder {
         let header = self.read_u29(buf)?;
 
         if header & 1 == 0 {
-            let idx = (header >> 1) as usize;
-            if idx >= self.object_refs.len() {
-                return Err(AmfError::InvalidReference(idx as u16));
-            }
-            return Ok(self.object_refs[idx].clone());
+            return self.object_reference((header >> 1) as usize);
         }
 
         let len = (header >> 1) as usize;
+        if len > self.max_byte_array_len {
+            return Err(AmfError::LengthExceeded {
+                declared: len,
+                limit: self.max_byte_array_len,
+            });
+        }
         if buf.remaining() < len {
             return Err(AmfError::UnexpectedEof);
         }
 
-        let data = buf.copy_to_bytes(len).to_vec();
+        self.charge(len)?;
+        let data = buf[..len].to_vec();
+        buf.advance(len);
         let value = AmfValue::ByteArray(data);
-        self.object_refs.push(value.clone());
+        self.push_object_ref(value.clone(), len);
         Ok(value)
     }
 
@@ -331,25 +451,77 @@ impl Amf3Decoder {
         let header = self.read_u29(buf)?;
 
         if header & 1 == 0 {
-            let idx = (header >> 1) as usize;
-            if idx >= self.object_refs.len() {
-                return Err(AmfError::InvalidReference(idx as u16));
-            }
-            return Ok(self.object_refs[idx].clone());
+            return self.object_reference((header >> 1) as usize);
         }
 
         let len = (header >> 1) as usize;
+        if len > self.max_string_len {
+            return Err(AmfError::LengthExceeded {
+                declared: len,
+                limit: self.max_string_len,
+            });
+        }
         if buf.remaining() < len {
             return Err(AmfError::UnexpectedEof);
         }
 
-        let bytes = buf.copy_to_bytes(len);
-        let s = String::from_utf8(bytes.to_vec()).map_err(|_| AmfError::InvalidUtf8)?;
+        self.charge(len)?;
+        let s = read_str(buf, len)?;
         let value = AmfValue::Xml(s);
-        self.object_refs.push(value.clone());
+        self.push_object_ref(value.clone(), len);
         Ok(value)
     }
 
+    /// Account for `size` bytes of decoded output
+    fn charge(&mut self, size: usize) -> Result<(), AmfError> {
+        self.output_size = self.output_size.saturating_add(size);
+        if self.output_size > self.max_output_size {
+            return Err(AmfError::OutputTooLarge {
+                limit: self.max_output_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Resolve an object reference, counting the value's size again
+    fn object_reference(&mut self, idx: usize) -> Result<AmfValue, AmfError> {
+        if idx >= self.object_refs.len() {
+            return Err(AmfError::InvalidReference(idx as u16));
+        }
+        self.charge(self.object_ref_sizes[idx])?;
+        Ok(self.object_refs[idx].clone())
+    }
+
+    /// Add a fully decoded value to the object reference table
+    fn push_object_ref(&mut self, value: AmfValue, size: usize) {
+        self.object_refs.push(value);
+        self.object_ref_sizes.push(size);
+    }
+
+    /// Reserve an object reference slot for a value about to be decoded
+    ///
+    /// Returns the slot index and the output size at the start of the value.
+    fn reserve_object_ref(&mut self) -> (usize, usize) {
+        self.push_object_ref(AmfValue::Null, 0);
+        (self.object_refs.len() - 1, self.output_size)
+    }
+
+    /// Fill a reserved object reference slot with the decoded value and its size
+    fn complete_object_ref(&mut self, idx: usize, start: usize, value: &AmfValue) {
+        self.object_refs[idx] = value.clone();
+        self.object_ref_sizes[idx] = self.output_size - start;
+    }
+
+    /// Reject declared counts that exceed `max`, or that cannot fit in the
+    /// remaining buffer (every element is at least one byte long).
+    fn check_len(&self, declared: usize, max: usize, buf: &Bytes) -> Result<(), AmfError> {
+        let limit = max.min(buf.remaining());
+        if declared > limit {
+            return Err(AmfError::LengthExceeded { declared, limit });
+        }
+        Ok(())
+    }
+
     /// Read AMF3 U29 variable-length integer
     fn read_u29(&mut self, buf: &mut Bytes) -> Result<u32, AmfError> {
         let mut value: u32 = 0;
@@ -386,6 +558,7 @@ impl Amf3Decoder {
             if idx >= self.string_refs.len() {
                 return Err(AmfError::InvalidReference(idx as u16));
             }
+            self.charge(self.string_refs[idx].len())?;
             return Ok(self.string_refs[idx].clone());
         }
 
@@ -394,12 +567,18 @@ impl Amf3Decoder {
             return Ok(String::new());
         }
 
+        if len > self.max_string_len {
+            return Err(AmfError::LengthExceeded {
+                declared: len,
+                limit: self.max_string_len,
+            });
+        }
         if buf.remaining() < len {
             return Err(AmfError::UnexpectedEof);
         }
 
-        let bytes = buf.copy_to_bytes(len);
-        let s = String::from_utf8(bytes.to_vec()).map_err(|_| AmfError::InvalidUtf8)?;
+        self.charge(len)?;
+        let s = read_str(buf, len)?;
 
         // Only non-empty strings go into reference table
         self.string_refs.push(s.clone());
@@ -417,6 +596,7 @@ impl Default for Amf3Decoder {
 pub struct Amf3Encoder {
     buf: BytesMut,
     string_refs: HashMap<String, usize>,
+    property_order: PropertyOrder,
 }
 
 impl Amf3Encoder {
@@ -425,9 +605,16 @@ impl Amf3Encoder {
         Self {
             buf: BytesMut::with_capacity(256),
             string_refs: HashMap::new(),
+            property_order: PropertyOrder::default(),
         }
     }
 
+    /// Set the order in which object properties are written
+    pub fn property_order(mut self, order: PropertyOrder) -> Self {
+        self.property_order = order;
+        self
+    }
+
     /// Get encoded bytes and reset
     pub fn finish(&mut self) -> Bytes {
         self.string_refs.clear();
@@ -470,13 +657,10 @@ impl Amf3Encoder {
             AmfValue::Object(props) | AmfValue::EcmaArray(props) => {
                 self.buf.put_u8(MARKER_OBJECT);
                 // Dynamic anonymous object
-                let header = (0 << 4) | (1 << 3) | (1 << 2) | (1 << 1) | 1;
+                let header = (1 << 3) | (1 << 2) | (1 << 1) | 1;
                 self.write_u29(header);
                 self.write_string(""); // Empty class name
-                for (key, val) in props {
-                    self.write_string(key);
-                    self.encode(val);
-                }
+                self.write_properties(props);
                 self.write_string(""); // End marker
             }
             AmfValue::TypedObject {
@@ -484,13 +668,10 @@ impl Amf3Encoder {
                 properties,
             } => {
                 self.buf.put_u8(MARKER_OBJECT);
-                let header = (0 << 4) | (1 << 3) | (1 << 2) | (1 << 1) | 1;
+                let header = (1 << 3) | (1 << 2) | (1 << 1) | 1;
                 self.write_u29(header);
                 self.write_string(class_name);
-                for (key, val) in properties {
-                    self.write_string(key);
-                    self.encode(val);
-                }
+                self.write_properties(properties);
                 self.write_string("");
             }
             AmfValue::Date(timestamp) => {
@@ -513,6 +694,14 @@ impl Amf3Encoder {
         }
     }
 
+    /// Write dynamic members in the configured property order
+    fn write_properties(&mut self, props: &AmfObject) {
+        for (key, val) in self.property_order.apply(props) {
+            self.write_string(key);
+            self.encode(val);
+        }
+    }
+
     /// Write U29 variable-length integer
     fn write_u29(&mut self, value: u32) {
         let value = value & 0x1FFFFFFF;
@@ -561,6 +750,24 @@ impl Default for Amf3Encoder {
     }
 }
 
+/// Decode a single AMF3 value from untrusted input, enforcing `limits`
+pub fn decode_checked(data: &[u8], limits: DecodeLimits) -> Result<AmfValue, AmfError> {
+    if data.len() > limits.max_input_size {
+        return Err(AmfError::InputTooLarge {
+            size: data.len(),
+            limit: limits.max_input_size,
+        });
+    }
+
+    let mut decoder = Amf3Decoder::new()
+        .max_depth(limits.max_depth)
+        .max_collection_len(limits.max_collection_len)
+        .max_string_len(limits.max_string_len)
+        .max_output_size(limits.max_output_size);
+    let mut buf = Bytes::copy_from_slice(data);
+    decoder.decode(&mut buf)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -693,6 +900,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_double_values() {
         let mut encoder = Amf3Encoder::new();
 
@@ -749,11 +957,35 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sorted_encoding_is_stable() {
+        let encode_sorted = || {
+            let props: AmfObject = ["c", "a", "d", "b"]
+                .iter()
+                .map(|k| (k.to_string(), AmfValue::Integer(1)))
+                .collect();
+            let mut encoder = Amf3Encoder::new().property_order(PropertyOrder::Sorted);
+            encoder.encode(&AmfValue::Object(props));
+            encoder.finish()
+        };
+
+        let expected = encode_sorted();
+        for _ in 0..16 {
+            assert_eq!(encode_sorted(), expected);
+        }
+
+        // Object marker, traits, empty class name, then "a" = 1
+        assert_eq!(
+            &expected[..6],
+            &[MARKER_OBJECT, 0x0F, 0x01, 0x03, b'a', MARKER_INTEGER]
+        );
+    }
+
     #[test]
     fn test_object_roundtrip() {
         let mut encoder = Amf3Encoder::new();
 
-        let mut props = HashMap::new();
+        let mut props = AmfObject::new();
         props.insert("name".to_string(), AmfValue::String("test".into()));
         props.insert("value".to_string(), AmfValue::Number(123.0));
 
@@ -905,6 +1137,204 @@ mod tests {
         assert!(matches!(result, Err(AmfError::UnexpectedEof)));
     }
 
+    #[test]
+    fn test_decode_with_context() {
+        // An integer, then a string declaring 4 bytes with 2 present
+        let data = [MARKER_INTEGER, 0x01, MARKER_STRING, 0x09, b'a', b'b'];
+        let mut buf = Bytes::copy_from_slice(&data);
+        let mut decoder = Amf3Decoder::new();
+        decoder.decode_with_context(&mut buf).unwrap();
+
+        let err = decoder.decode_with_context(&mut buf).unwrap_err();
+        assert!(matches!(err.kind(), AmfError::UnexpectedEof));
+        assert_eq!(err.context().hex_dump(), "06 09 [61] 62");
+    }
+
+    #[test]
+    fn test_byte_array_absurd_length() {
+        // ByteArray declaring 0x0FFFFFFF bytes (max U29 >> 1) with a tiny payload
+        let mut encoder = Amf3Encoder::new();
+        encoder.buf.put_u8(MARKER_BYTE_ARRAY);
+        encoder.write_u29(0x1FFFFFFF);
+        encoder.buf.put_slice(&[0x00, 0x01]);
+        let mut buf = encoder.finish();
+
+        let mut decoder = Amf3Decoder::new();
+        let result = decoder.decode(&mut buf);
+        assert!(matches!(
+            result,
+            Err(AmfError::LengthExceeded {
+                declared: 0x0FFFFFFF,
+                limit: DEFAULT_MAX_BYTE_ARRAY_LEN
+            })
+        ));
+    }
+
+    #[test]
+    fn test_string_max_len() {
+        let value = AmfValue::String("x".repeat(32));
+        let mut encoder = Amf3Encoder::new();
+        encoder.encode(&value);
+        let encoded = encoder.finish();
+
+        let mut decoder = Amf3Decoder::new().max_string_len(32);
+        assert_eq!(decoder.decode(&mut encoded.clone()).unwrap(), value);
+
+        let mut decoder = Amf3Decoder::new().max_string_len(16);
+        assert!(matches!(
+            decoder.decode(&mut encoded.clone()),
+            Err(AmfError::LengthExceeded {
+                declared: 32,
+                limit: 16
+            })
+        ));
+
+        // The largest U29 length, backed by a single byte
+        let data = [MARKER_STRING, 0xFF, 0xFF, 0xFF, 0xFF, b'a'];
+        assert!(matches!(
+            Amf3Decoder::new().decode(&mut Bytes::copy_from_slice(&data)),
+            Err(AmfError::LengthExceeded {
+                declared: 0x0FFF_FFFF,
+                limit: DEFAULT_MAX_STRING_LEN
+            })
+        ));
+    }
+
+    #[test]
+    fn test_byte_array_max_len() {
+        let value = AmfValue::ByteArray(vec![0xAB; 32]);
+        let mut encoder = Amf3Encoder::new();
+        encoder.encode(&value);
+        let encoded = encoder.finish();
+
+        let mut decoder = Amf3Decoder::new().max_byte_array_len(32);
+        assert_eq!(decoder.decode(&mut encoded.clone()).unwrap(), value);
+
+        let mut decoder = Amf3Decoder::new().max_byte_array_len(16);
+        assert!(matches!(
+            decoder.decode(&mut encoded.clone()),
+            Err(AmfError::LengthExceeded {
+                declared: 32,
+                limit: 16
+            })
+        ));
+    }
+
+    #[test]
+    fn test_array_absurd_length() {
+        // Dense array declaring 0x0FFFFFFF elements, empty assoc part, one element
+        let mut encoder = Amf3Encoder::new();
+        encoder.buf.put_u8(MARKER_ARRAY);
+        encoder.write_u29(0x1FFFFFFF);
+        encoder.buf.put_slice(&[0x01, MARKER_NULL]);
+        let mut buf = encoder.finish();
+
+        let mut decoder = Amf3Decoder::new();
+        let result = decoder.decode(&mut buf);
+        assert!(matches!(
+            result,
+            Err(AmfError::LengthExceeded {
+                declared: 0x0FFFFFFF,
+                limit: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_array_max_collection_len() {
+        let value = AmfValue::Array(vec![AmfValue::Integer(1); 4]);
+        let mut encoder = Amf3Encoder::new();
+        encoder.encode(&value);
+        let encoded = encoder.finish();
+
+        let mut decoder = Amf3Decoder::new().max_collection_len(2);
+        assert!(matches!(
+            decoder.decode(&mut encoded.clone()),
+            Err(AmfError::LengthExceeded {
+                declared: 4,
+                limit: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decode_checked_reference_bomb() {
+        // Dense array whose elements each reference the previous one twice
+        let levels = 40;
+        let mut encoder = Amf3Encoder::new();
+        encoder.buf.put_u8(MARKER_ARRAY);
+        encoder.write_u29(((levels + 1) << 1) | 1);
+        encoder.buf.put_u8(0x01); // empty assoc part
+
+        // Object index 1: a single 64-byte string
+        encoder.buf.put_slice(&[MARKER_ARRAY, 0x03, 0x01]);
+        encoder.encode(&AmfValue::String("x".repeat(64)));
+
+        // Object index k + 1: two references to index k
+        for k in 1..=levels {
+            encoder.buf.put_slice(&[MARKER_ARRAY, 0x05, 0x01]);
+            for _ in 0..2 {
+                encoder.buf.put_u8(MARKER_ARRAY);
+                encoder.write_u29(k << 1);
+            }
+        }
+        let data = encoder.finish();
+
+        let limits = DecodeLimits::default().max_output_size(1024 * 1024);
+        assert!(matches!(
+            decode_checked(&data, limits),
+            Err(AmfError::OutputTooLarge { limit: 1048576 })
+        ));
+    }
+
+    #[test]
+    fn test_decode_checked_string_reference_bomb() {
+        // One 1KB string, then a thousand references to it
+        let mut encoder = Amf3Encoder::new();
+        encoder.buf.put_u8(MARKER_ARRAY);
+        encoder.write_u29((1001 << 1) | 1);
+        encoder.buf.put_u8(0x01);
+        encoder.encode(&AmfValue::String("x".repeat(1024)));
+        for _ in 0..1000 {
+            encoder.buf.put_slice(&[MARKER_STRING, 0x00]);
+        }
+        let data = encoder.finish();
+
+        assert!(decode_checked(&data, DecodeLimits::default()).is_ok());
+
+        let limits = DecodeLimits::default().max_output_size(512 * 1024);
+        assert!(matches!(
+            decode_checked(&data, limits),
+            Err(AmfError::OutputTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_checked_input_and_depth() {
+        let mut value = AmfValue::Null;
+        for _ in 0..8 {
+            value = AmfValue::Array(vec![value]);
+        }
+        let mut encoder = Amf3Encoder::new();
+        encoder.encode(&value);
+        let encoded = encoder.finish();
+
+        let limits = DecodeLimits::default().max_depth(9);
+        assert_eq!(decode_checked(&encoded, limits).unwrap(), value);
+
+        let limits = DecodeLimits::default().max_depth(8);
+        assert!(matches!(
+            decode_checked(&encoded, limits),
+            Err(AmfError::NestingTooDeep)
+        ));
+
+        let limits = DecodeLimits::default().max_input_size(4);
+        assert!(matches!(
+            decode_checked(&encoded, limits),
+            Err(AmfError::InputTooLarge { size, limit: 4 }) if size == encoded.len()
+        ));
+    }
+
     #[test]
     fn test_lenient_mode_unknown_marker() {
         let mut decoder = Amf3Decoder::new();
@@ -917,7 +1347,7 @@ mod tests {
     fn test_ecma_array_as_object() {
         let mut encoder = Amf3Encoder::new();
 
-        let mut props = HashMap::new();
+        let mut props = AmfObject::new();
         props.insert("key1".to_string(), AmfValue::Number(1.0));
         props.insert("key2".to_string(), AmfValue::String("value".into()));
 
@@ -9,8 +9,52 @@
 
 pub mod amf0;
 pub mod amf3;
+pub mod object;
 pub mod value;
 
 pub use amf0::{Amf0Decoder, Amf0Encoder};
 pub use amf3::{Amf3Decoder, Amf3Encoder};
-pub use value::AmfValue;
+pub use object::AmfObject;
+pub use value::{AmfEncoding, AmfValue, PropertyOrder};
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Minimum time between lenient recovery warnings
+///
+/// Decoders usually live for a single message, so the limit is shared by
+/// all of them.
+const RECOVERY_WARN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Log a lenient recovery, at most once per [`RECOVERY_WARN_INTERVAL`]
+///
+/// Recoveries in between are counted and reported with the next warning.
+fn warn_recovery(format: &'static str, what: &'static str) {
+    static START: OnceLock<Instant> = OnceLock::new();
+    static NEXT_WARN_MS: AtomicU64 = AtomicU64::new(0);
+    static SUPPRESSED: AtomicU64 = AtomicU64::new(0);
+
+    let now = START.get_or_init(Instant::now).elapsed().as_millis() as u64;
+    let next = NEXT_WARN_MS.load(Ordering::Relaxed);
+    let due = now >= next
+        && NEXT_WARN_MS
+            .compare_exchange(
+                next,
+                now + RECOVERY_WARN_INTERVAL.as_millis() as u64,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok();
+    if !due {
+        SUPPRESSED.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+
+    tracing::warn!(
+        format,
+        recovery = what,
+        suppressed = SUPPRESSED.swap(0, Ordering::Relaxed),
+        "Recovered from malformed AMF in lenient mode"
+    );
+}
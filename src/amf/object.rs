@@ -0,0 +1,289 @@
+//! AMF object property map
+//!
+//! [`AmfObject`] holds the properties of AMF objects, typed objects and ECMA
+//! arrays. Its API is the same whichever map backs it, so enabling the
+//! `preserve_order` feature anywhere in a dependency graph does not change
+//! the types other crates build against.
+
+use std::collections::HashMap;
+
+use super::value::AmfValue;
+
+#[cfg(not(feature = "preserve_order"))]
+type Map = HashMap<String, AmfValue>;
+
+#[cfg(feature = "preserve_order")]
+type Map = indexmap::IndexMap<String, AmfValue>;
+
+/// Property map of AMF objects and ECMA arrays
+///
+/// Backed by a `HashMap` by default. With the `preserve_order` feature
+/// properties keep the order they were decoded or inserted in, so a
+/// decode/encode round-trip reproduces the original field order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AmfObject {
+    map: Map,
+}
+
+impl AmfObject {
+    /// Create an empty object
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty object with room for `capacity` properties
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            map: Map::with_capacity(capacity),
+        }
+    }
+
+    /// Number of properties
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Check if the object has no properties
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Get a property
+    pub fn get(&self, key: &str) -> Option<&AmfValue> {
+        self.map.get(key)
+    }
+
+    /// Get a mutable reference to a property
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut AmfValue> {
+        self.map.get_mut(key)
+    }
+
+    /// Check if a property is present
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Set a property, returning its previous value
+    ///
+    /// Replacing a property keeps its position.
+    pub fn insert(&mut self, key: String, value: AmfValue) -> Option<AmfValue> {
+        self.map.insert(key, value)
+    }
+
+    /// Remove a property, returning its value
+    ///
+    /// The remaining properties keep their order.
+    pub fn remove(&mut self, key: &str) -> Option<AmfValue> {
+        #[cfg(not(feature = "preserve_order"))]
+        return self.map.remove(key);
+        #[cfg(feature = "preserve_order")]
+        return self.map.shift_remove(key);
+    }
+
+    /// Keep only the properties for which `f` returns true
+    pub fn retain(&mut self, mut f: impl FnMut(&str, &mut AmfValue) -> bool) {
+        self.map.retain(|k, v| f(k, v));
+    }
+
+    /// Remove every property
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Iterate over the properties
+    pub fn iter(&self) -> Iter<'_> {
+        Iter(self.map.iter())
+    }
+
+    /// Iterate over the properties, with mutable values
+    pub fn iter_mut(&mut self) -> IterMut<'_> {
+        IterMut(self.map.iter_mut())
+    }
+
+    /// Iterate over the property names
+    pub fn keys(&self) -> Keys<'_> {
+        Keys(self.map.keys())
+    }
+
+    /// Iterate over the property values
+    pub fn values(&self) -> Values<'_> {
+        Values(self.map.values())
+    }
+
+    /// Iterate over the property values mutably
+    pub fn values_mut(&mut self) -> ValuesMut<'_> {
+        ValuesMut(self.map.values_mut())
+    }
+}
+
+impl From<HashMap<String, AmfValue>> for AmfObject {
+    fn from(map: HashMap<String, AmfValue>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<const N: usize> From<[(String, AmfValue); N]> for AmfObject {
+    fn from(properties: [(String, AmfValue); N]) -> Self {
+        properties.into_iter().collect()
+    }
+}
+
+impl From<AmfObject> for HashMap<String, AmfValue> {
+    fn from(object: AmfObject) -> Self {
+        object.into_iter().collect()
+    }
+}
+
+impl FromIterator<(String, AmfValue)> for AmfObject {
+    fn from_iter<I: IntoIterator<Item = (String, AmfValue)>>(iter: I) -> Self {
+        Self {
+            map: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Extend<(String, AmfValue)> for AmfObject {
+    fn extend<I: IntoIterator<Item = (String, AmfValue)>>(&mut self, iter: I) {
+        self.map.extend(iter);
+    }
+}
+
+impl IntoIterator for AmfObject {
+    type Item = (String, AmfValue);
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter(self.map.into_iter())
+    }
+}
+
+impl<'a> IntoIterator for &'a AmfObject {
+    type Item = (&'a String, &'a AmfValue);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut AmfObject {
+    type Item = (&'a String, &'a mut AmfValue);
+    type IntoIter = IterMut<'a>;
+
+    fn into_iter(self) -> IterMut<'a> {
+        self.iter_mut()
+    }
+}
+
+/// Wrap an iterator of the backing map so its type does not leak
+macro_rules! map_iter {
+    ($(#[$doc:meta])* $name:ident $(<$lt:lifetime>)?, $hash:ty, $index:ty, $item:ty) => {
+        $(#[$doc])*
+        #[derive(Debug)]
+        pub struct $name$(<$lt>)?(
+            #[cfg(not(feature = "preserve_order"))] $hash,
+            #[cfg(feature = "preserve_order")] $index,
+        );
+
+        impl$(<$lt>)? Iterator for $name$(<$lt>)? {
+            type Item = $item;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.0.next()
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.0.size_hint()
+            }
+        }
+
+        impl$(<$lt>)? ExactSizeIterator for $name$(<$lt>)? {}
+    };
+}
+
+map_iter!(
+    /// Iterator over the properties of an [`AmfObject`]
+    Iter<'a>,
+    std::collections::hash_map::Iter<'a, String, AmfValue>,
+    indexmap::map::Iter<'a, String, AmfValue>,
+    (&'a String, &'a AmfValue)
+);
+map_iter!(
+    /// Mutable iterator over the properties of an [`AmfObject`]
+    IterMut<'a>,
+    std::collections::hash_map::IterMut<'a, String, AmfValue>,
+    indexmap::map::IterMut<'a, String, AmfValue>,
+    (&'a String, &'a mut AmfValue)
+);
+map_iter!(
+    /// Owning iterator over the properties of an [`AmfObject`]
+    IntoIter,
+    std::collections::hash_map::IntoIter<String, AmfValue>,
+    indexmap::map::IntoIter<String, AmfValue>,
+    (String, AmfValue)
+);
+map_iter!(
+    /// Iterator over the property names of an [`AmfObject`]
+    Keys<'a>,
+    std::collections::hash_map::Keys<'a, String, AmfValue>,
+    indexmap::map::Keys<'a, String, AmfValue>,
+    &'a String
+);
+map_iter!(
+    /// Iterator over the property values of an [`AmfObject`]
+    Values<'a>,
+    std::collections::hash_map::Values<'a, String, AmfValue>,
+    indexmap::map::Values<'a, String, AmfValue>,
+    &'a AmfValue
+);
+map_iter!(
+    /// Mutable iterator over the property values of an [`AmfObject`]
+    ValuesMut<'a>,
+    std::collections::hash_map::ValuesMut<'a, String, AmfValue>,
+    indexmap::map::ValuesMut<'a, String, AmfValue>,
+    &'a mut AmfValue
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut obj = AmfObject::new();
+        assert!(obj.is_empty());
+        assert_eq!(obj.insert("width".into(), AmfValue::Number(1280.0)), None);
+        assert_eq!(obj.insert("height".into(), AmfValue::Number(720.0)), None);
+        assert_eq!(
+            obj.insert("width".into(), AmfValue::Number(1920.0)),
+            Some(AmfValue::Number(1280.0))
+        );
+
+        assert_eq!(obj.len(), 2);
+        assert_eq!(obj.get("width"), Some(&AmfValue::Number(1920.0)));
+        assert!(obj.contains_key("height"));
+        assert_eq!(obj.remove("height"), Some(AmfValue::Number(720.0)));
+        assert!(!obj.contains_key("height"));
+    }
+
+    #[test]
+    fn test_hashmap_conversions() {
+        let mut map = HashMap::new();
+        map.insert("app".to_string(), AmfValue::String("live".into()));
+
+        let obj = AmfObject::from(map.clone());
+        assert_eq!(obj.get("app").and_then(|v| v.as_str()), Some("live"));
+        assert_eq!(HashMap::from(obj), map);
+    }
+
+    #[cfg(feature = "preserve_order")]
+    #[test]
+    fn test_remove_keeps_order() {
+        let mut obj: AmfObject = ["a", "b", "c", "d"]
+            .into_iter()
+            .map(|k| (k.to_string(), AmfValue::Null))
+            .collect();
+        obj.remove("b");
+        assert_eq!(obj.keys().collect::<Vec<_>>(), ["a", "c", "d"]);
+    }
+}
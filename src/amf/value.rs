@@ -5,14 +5,19 @@
 
 use std::collections::HashMap;
 
+use crate::error::AmfError;
+
+use super::object::AmfObject;
+
 /// Unified AMF value representation
 ///
 /// This enum represents all value types supported by AMF0 and AMF3.
 /// Some types (like ByteArray, Dictionary) are AMF3-only but included
 /// for completeness.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub enum AmfValue {
     /// Null value (AMF0: 0x05, AMF3: 0x01)
+    #[default]
     Null,
 
     /// Undefined value (AMF0: 0x06, AMF3: 0x00)
@@ -27,19 +32,17 @@ pub enum AmfValue {
     /// UTF-8 string (AMF0: 0x02, AMF3: 0x06)
     String(String),
 
-    /// Ordered array with optional associative portion
-    /// In AMF0 this is either StrictArray (0x0A) or ECMAArray (0x08)
-    /// In AMF3 this is Array (0x09)
+    /// Ordered array (AMF0: StrictArray 0x0A, AMF3: dense Array 0x09)
     Array(Vec<AmfValue>),
 
     /// Key-value object (AMF0: 0x03, AMF3: 0x0A)
     /// Keys are always strings in AMF
-    Object(HashMap<String, AmfValue>),
+    Object(AmfObject),
 
     /// Typed object with class name
     TypedObject {
         class_name: String,
-        properties: HashMap<String, AmfValue>,
+        properties: AmfObject,
     },
 
     /// Date value as milliseconds since Unix epoch
@@ -55,9 +58,10 @@ pub enum AmfValue {
     /// Integer (AMF3 only: 0x04, 29-bit signed)
     Integer(i32),
 
-    /// ECMA Array - associative array with dense and sparse parts
-    /// Stored as (dense_length, properties)
-    EcmaArray(HashMap<String, AmfValue>),
+    /// Associative array (AMF0: ECMAArray 0x08), as used by `onMetaData`
+    /// AMF3 arrays with an associative portion decode to this; AMF3 encodes
+    /// it as an anonymous object
+    EcmaArray(AmfObject),
 }
 
 impl AmfValue {
@@ -87,7 +91,7 @@ impl AmfValue {
     }
 
     /// Try to get this value as an object reference
-    pub fn as_object(&self) -> Option<&HashMap<String, AmfValue>> {
+    pub fn as_object(&self) -> Option<&AmfObject> {
         match self {
             AmfValue::Object(m) => Some(m),
             AmfValue::EcmaArray(m) => Some(m),
@@ -97,7 +101,7 @@ impl AmfValue {
     }
 
     /// Try to get this value as a mutable object reference
-    pub fn as_object_mut(&mut self) -> Option<&mut HashMap<String, AmfValue>> {
+    pub fn as_object_mut(&mut self) -> Option<&mut AmfObject> {
         match self {
             AmfValue::Object(m) => Some(m),
             AmfValue::EcmaArray(m) => Some(m),
@@ -133,11 +137,97 @@ impl AmfValue {
     pub fn get_number(&self, key: &str) -> Option<f64> {
         self.get(key)?.as_number()
     }
+
+    /// Get a property from an object value converted to `T`
+    ///
+    /// Returns None if the property is missing or does not convert.
+    ///
+    /// ```
+    /// use rtmp_rs::amf::{AmfObject, AmfValue};
+    ///
+    /// let mut obj = AmfObject::new();
+    /// obj.insert("width".to_string(), AmfValue::Number(1920.0));
+    /// let metadata = AmfValue::Object(obj);
+    ///
+    /// assert_eq!(metadata.get_as::<i64>("width"), Some(1920));
+    /// assert_eq!(metadata.get_as::<String>("width"), None);
+    /// ```
+    pub fn get_as<'a, T: TryFrom<&'a AmfValue>>(&'a self, key: &str) -> Option<T> {
+        T::try_from(self.get(key)?).ok()
+    }
+
+    /// Name of this value's type, for conversion errors
+    fn type_name(&self) -> &'static str {
+        match self {
+            AmfValue::Null => "null",
+            AmfValue::Undefined => "undefined",
+            AmfValue::Boolean(_) => "boolean",
+            AmfValue::Number(_) => "number",
+            AmfValue::String(_) => "string",
+            AmfValue::Array(_) => "array",
+            AmfValue::Object(_) => "object",
+            AmfValue::TypedObject { .. } => "typed object",
+            AmfValue::Date(_) => "date",
+            AmfValue::Xml(_) => "xml",
+            AmfValue::ByteArray(_) => "byte array",
+            AmfValue::Integer(_) => "integer",
+            AmfValue::EcmaArray(_) => "ECMA array",
+        }
+    }
+
+    /// Error for a failed conversion to `expected`
+    fn mismatch(&self, expected: &'static str) -> AmfError {
+        AmfError::TypeMismatch {
+            expected,
+            found: self.type_name(),
+        }
+    }
+}
+
+impl TryFrom<&AmfValue> for f64 {
+    type Error = AmfError;
+
+    fn try_from(v: &AmfValue) -> Result<Self, AmfError> {
+        v.as_number().ok_or_else(|| v.mismatch("number"))
+    }
+}
+
+/// Accepts integers and numbers without a fractional part
+impl TryFrom<&AmfValue> for i64 {
+    type Error = AmfError;
+
+    fn try_from(v: &AmfValue) -> Result<Self, AmfError> {
+        match *v {
+            AmfValue::Integer(i) => Ok(i as i64),
+            AmfValue::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Ok(n as i64),
+            _ => Err(v.mismatch("integer")),
+        }
+    }
+}
+
+impl TryFrom<&AmfValue> for bool {
+    type Error = AmfError;
+
+    fn try_from(v: &AmfValue) -> Result<Self, AmfError> {
+        v.as_bool().ok_or_else(|| v.mismatch("boolean"))
+    }
+}
+
+impl TryFrom<&AmfValue> for String {
+    type Error = AmfError;
+
+    fn try_from(v: &AmfValue) -> Result<Self, AmfError> {
+        v.as_str()
+            .map(str::to_string)
+            .ok_or_else(|| v.mismatch("string"))
+    }
 }
 
-impl Default for AmfValue {
-    fn default() -> Self {
-        AmfValue::Null
+impl TryFrom<&AmfValue> for Vec<AmfValue> {
+    type Error = AmfError;
+
+    fn try_from(v: &AmfValue) -> Result<Self, AmfError> {
+        v.as_array().cloned().ok_or_else(|| v.mismatch("array"))
     }
 }
 
@@ -189,6 +279,73 @@ impl<V: Into<AmfValue>> From<HashMap<String, V>> for AmfValue {
     }
 }
 
+/// Order in which encoders write object properties
+///
+/// Without the `preserve_order` feature an [`AmfObject`] is hash ordered,
+/// so their iteration order changes from run to run. Encoders default to
+/// map order; the other variants make the output byte-for-byte stable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PropertyOrder {
+    /// Map iteration order (insertion order with `preserve_order`,
+    /// otherwise not stable between runs)
+    #[default]
+    Unordered,
+
+    /// Keys sorted lexicographically
+    Sorted,
+
+    /// Listed keys first, in list order, then the remaining keys sorted
+    Preferred(&'static [&'static str]),
+}
+
+impl PropertyOrder {
+    /// Stable order that keeps insertion order when the map preserves it
+    pub const STABLE: PropertyOrder = if cfg!(feature = "preserve_order") {
+        PropertyOrder::Unordered
+    } else {
+        PropertyOrder::Sorted
+    };
+
+    /// Get the properties of an object in this order
+    pub fn apply<'a>(&self, props: &'a AmfObject) -> Vec<(&'a str, &'a AmfValue)> {
+        let mut ordered: Vec<_> = props.iter().map(|(k, v)| (k.as_str(), v)).collect();
+        match self {
+            PropertyOrder::Unordered => {}
+            PropertyOrder::Sorted => ordered.sort_unstable_by_key(|(k, _)| *k),
+            PropertyOrder::Preferred(keys) => ordered.sort_unstable_by_key(|(k, _)| {
+                let rank = keys.iter().position(|p| p == k).unwrap_or(keys.len());
+                (rank, *k)
+            }),
+        }
+        ordered
+    }
+}
+
+/// Object encoding of a command or data message
+///
+/// Negotiated with `objectEncoding` in the connect command. AMF3 messages
+/// start with a zero byte and then carry AMF0 values, switching to AMF3
+/// per value with the avmplus marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AmfEncoding {
+    /// AMF0 (`objectEncoding` 0)
+    #[default]
+    Amf0,
+    /// AMF3 (`objectEncoding` 3)
+    Amf3,
+}
+
+impl AmfEncoding {
+    /// Get the encoding for a connect command's `objectEncoding`
+    pub fn from_object_encoding(object_encoding: f64) -> Self {
+        if object_encoding == 3.0 {
+            AmfEncoding::Amf3
+        } else {
+            AmfEncoding::Amf0
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -203,12 +360,62 @@ mod tests {
         assert_eq!(n.as_number(), Some(42.0));
         assert_eq!(n.as_str(), None);
 
-        let mut obj = HashMap::new();
+        let mut obj = AmfObject::new();
         obj.insert("key".to_string(), AmfValue::String("value".into()));
         let o = AmfValue::Object(obj);
         assert_eq!(o.get_string("key"), Some("value"));
     }
 
+    #[test]
+    fn test_try_from_conversions() {
+        let n = AmfValue::Number(30.0);
+        assert_eq!(f64::try_from(&n).unwrap(), 30.0);
+        assert_eq!(i64::try_from(&n).unwrap(), 30);
+        assert_eq!(i64::try_from(&AmfValue::Integer(-5)).unwrap(), -5);
+        assert_eq!(f64::try_from(&AmfValue::Integer(7)).unwrap(), 7.0);
+        assert!(bool::try_from(&AmfValue::Boolean(true)).unwrap());
+        assert_eq!(
+            String::try_from(&AmfValue::String("live".into())).unwrap(),
+            "live"
+        );
+        let arr = AmfValue::Array(vec![AmfValue::Null]);
+        assert_eq!(
+            Vec::<AmfValue>::try_from(&arr).unwrap(),
+            vec![AmfValue::Null]
+        );
+
+        // Wrong types and lossy numbers are rejected
+        assert!(matches!(
+            f64::try_from(&AmfValue::String("1".into())),
+            Err(AmfError::TypeMismatch {
+                expected: "number",
+                found: "string"
+            })
+        ));
+        assert!(i64::try_from(&AmfValue::Number(29.97)).is_err());
+        assert!(i64::try_from(&AmfValue::Number(f64::NAN)).is_err());
+        assert!(i64::try_from(&AmfValue::Number(f64::INFINITY)).is_err());
+        assert!(bool::try_from(&AmfValue::Number(1.0)).is_err());
+        assert!(String::try_from(&AmfValue::Null).is_err());
+        assert!(Vec::<AmfValue>::try_from(&AmfValue::Object(AmfObject::new())).is_err());
+    }
+
+    #[test]
+    fn test_get_as() {
+        let mut obj = AmfObject::new();
+        obj.insert("app".to_string(), AmfValue::String("live".into()));
+        obj.insert("fpad".to_string(), AmfValue::Boolean(false));
+        obj.insert("audioCodecs".to_string(), AmfValue::Number(3575.0));
+        let connect = AmfValue::Object(obj);
+
+        assert_eq!(connect.get_as::<String>("app"), Some("live".to_string()));
+        assert_eq!(connect.get_as::<bool>("fpad"), Some(false));
+        assert_eq!(connect.get_as::<i64>("audioCodecs"), Some(3575));
+        assert_eq!(connect.get_as::<f64>("app"), None);
+        assert_eq!(connect.get_as::<String>("missing"), None);
+        assert_eq!(AmfValue::Null.get_as::<String>("app"), None);
+    }
+
     #[test]
     fn test_from_conversions() {
         let v: AmfValue = "test".into();
@@ -236,12 +443,12 @@ mod tests {
         assert_eq!(arr.as_array().unwrap().len(), 2);
 
         assert!(AmfValue::Null.as_array().is_none());
-        assert!(AmfValue::Object(HashMap::new()).as_array().is_none());
+        assert!(AmfValue::Object(AmfObject::new()).as_array().is_none());
     }
 
     #[test]
     fn test_as_object_mut() {
-        let mut obj = AmfValue::Object(HashMap::new());
+        let mut obj = AmfValue::Object(AmfObject::new());
         if let Some(map) = obj.as_object_mut() {
             map.insert("key".to_string(), AmfValue::String("value".into()));
         }
@@ -260,7 +467,7 @@ mod tests {
 
     #[test]
     fn test_get_number() {
-        let mut obj = HashMap::new();
+        let mut obj = AmfObject::new();
         obj.insert("count".to_string(), AmfValue::Number(42.0));
         obj.insert("name".to_string(), AmfValue::String("test".into()));
         let amf = AmfValue::Object(obj);
@@ -328,7 +535,7 @@ mod tests {
 
     #[test]
     fn test_as_object_with_typed_object() {
-        let mut props = HashMap::new();
+        let mut props = AmfObject::new();
         props.insert("x".to_string(), AmfValue::Number(10.0));
 
         let typed = AmfValue::TypedObject {
@@ -343,7 +550,7 @@ mod tests {
 
     #[test]
     fn test_as_object_with_ecma_array() {
-        let mut props = HashMap::new();
+        let mut props = AmfObject::new();
         props.insert("key".to_string(), AmfValue::String("value".into()));
 
         let ecma = AmfValue::EcmaArray(props);
@@ -363,7 +570,7 @@ mod tests {
     #[test]
     fn test_amf_value_clone() {
         let original = AmfValue::Object({
-            let mut m = HashMap::new();
+            let mut m = AmfObject::new();
             m.insert(
                 "nested".to_string(),
                 AmfValue::Array(vec![AmfValue::Number(1.0), AmfValue::String("test".into())]),
@@ -375,6 +582,27 @@ mod tests {
         assert_eq!(original, cloned);
     }
 
+    #[test]
+    fn test_property_order() {
+        let props: AmfObject = ["tcUrl", "zeta", "app", "beta"]
+            .iter()
+            .map(|k| (k.to_string(), AmfValue::Null))
+            .collect();
+        let keys = |order: PropertyOrder| -> Vec<&str> {
+            order.apply(&props).into_iter().map(|(k, _)| k).collect()
+        };
+
+        assert_eq!(keys(PropertyOrder::Unordered).len(), 4);
+        assert_eq!(
+            keys(PropertyOrder::Sorted),
+            vec!["app", "beta", "tcUrl", "zeta"]
+        );
+        assert_eq!(
+            keys(PropertyOrder::Preferred(&["zeta", "tcUrl", "missing"])),
+            vec!["zeta", "tcUrl", "app", "beta"]
+        );
+    }
+
     #[test]
     fn test_amf_value_partial_eq() {
         assert_eq!(AmfValue::Null, AmfValue::Null);
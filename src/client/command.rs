@@ -0,0 +1,260 @@
+//! Client command construction
+//!
+//! [`CommandBuilder`] produces the commands a client sends while setting up
+//! a session (`connect`, `createStream`, `publish`, `play`, ...), filled in
+//! the way servers expect them. [`RtmpConnector`](super::RtmpConnector)
+//! uses it for its own command flow; custom flows can use it to avoid
+//! assembling AMF by hand.
+
+use bytes::Bytes;
+
+use crate::amf::{AmfObject, AmfValue};
+use crate::protocol::constants::*;
+use crate::protocol::message::{Command, RtmpMessage};
+
+/// Builder for client commands
+///
+/// # Example
+///
+/// ```
+/// use rtmp_rs::client::CommandBuilder;
+///
+/// let connect = CommandBuilder::connect("live", "rtmp://localhost/live")
+///     .flash_ver("FMLE/3.0 (compatible; FMSc/1.0)")
+///     .object_encoding(0.0)
+///     .transaction_id(1.0)
+///     .build();
+/// assert_eq!(connect.name, "connect");
+///
+/// // AMF0 command payload, ready to be chunked onto message stream 1
+/// let (message_type, payload) = CommandBuilder::publish("stream", "live")
+///     .stream_id(1)
+///     .encode();
+/// ```
+#[derive(Debug, Clone)]
+pub struct CommandBuilder {
+    command: Command,
+}
+
+impl CommandBuilder {
+    /// Start a command with a null command object and no arguments
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            command: Command {
+                name: name.into(),
+                transaction_id: 0.0,
+                command_object: AmfValue::Null,
+                arguments: Vec::new(),
+                stream_id: 0,
+            },
+        }
+    }
+
+    /// `connect` to `app`, with the command object fields of a typical
+    /// publishing client
+    pub fn connect(app: impl Into<String>, tc_url: impl Into<String>) -> Self {
+        let mut obj = AmfObject::new();
+        obj.insert("app".to_string(), AmfValue::String(app.into()));
+        obj.insert("type".to_string(), AmfValue::String("nonprivate".into()));
+        obj.insert(
+            "flashVer".to_string(),
+            AmfValue::String("LNX 9,0,124,2".into()),
+        );
+        obj.insert("tcUrl".to_string(), AmfValue::String(tc_url.into()));
+        obj.insert("fpad".to_string(), AmfValue::Boolean(false));
+        obj.insert("capabilities".to_string(), AmfValue::Number(15.0));
+        obj.insert("audioCodecs".to_string(), AmfValue::Number(3191.0));
+        obj.insert("videoCodecs".to_string(), AmfValue::Number(252.0));
+        obj.insert("videoFunction".to_string(), AmfValue::Number(1.0));
+
+        let mut builder = Self::new(CMD_CONNECT);
+        builder.command.command_object = AmfValue::Object(obj);
+        builder
+    }
+
+    /// `createStream`
+    pub fn create_stream() -> Self {
+        Self::new(CMD_CREATE_STREAM)
+    }
+
+    /// `deleteStream` for message stream `stream_id`
+    pub fn delete_stream(stream_id: u32) -> Self {
+        Self::new(CMD_DELETE_STREAM).argument(AmfValue::Number(stream_id as f64))
+    }
+
+    /// `releaseStream`, sent before publishing for servers that expect it
+    pub fn release_stream(stream_name: impl Into<String>) -> Self {
+        Self::new(CMD_RELEASE_STREAM).argument(AmfValue::String(stream_name.into()))
+    }
+
+    /// `FCPublish`, sent before publishing for servers that expect it
+    pub fn fc_publish(stream_name: impl Into<String>) -> Self {
+        Self::new(CMD_FC_PUBLISH).argument(AmfValue::String(stream_name.into()))
+    }
+
+    /// `publish` a stream; `publish_type` is "live", "record" or "append"
+    pub fn publish(stream_name: impl Into<String>, publish_type: impl Into<String>) -> Self {
+        Self::new(CMD_PUBLISH)
+            .argument(AmfValue::String(stream_name.into()))
+            .argument(AmfValue::String(publish_type.into()))
+    }
+
+    /// `play` a stream from the live edge until it ends
+    pub fn play(stream_name: impl Into<String>) -> Self {
+        Self::new(CMD_PLAY)
+            .argument(AmfValue::String(stream_name.into()))
+            .argument(AmfValue::Number(-2.0)) // Start: live or recorded
+            .argument(AmfValue::Number(-1.0)) // Duration: play until end
+            .argument(AmfValue::Boolean(true)) // Reset
+    }
+
+    /// Set the Flash version string (`connect`)
+    pub fn flash_ver(self, ver: impl Into<String>) -> Self {
+        self.property("flashVer", AmfValue::String(ver.into()))
+    }
+
+    /// Set the AMF encoding of the session, 0 or 3 (`connect`)
+    pub fn object_encoding(self, encoding: f64) -> Self {
+        self.property("objectEncoding", AmfValue::Number(encoding))
+    }
+
+    /// Set a field of the command object, creating the object if needed
+    pub fn property(mut self, name: impl Into<String>, value: AmfValue) -> Self {
+        if self.command.command_object.as_object().is_none() {
+            self.command.command_object = AmfValue::Object(AmfObject::new());
+        }
+        if let Some(obj) = self.command.command_object.as_object_mut() {
+            obj.insert(name.into(), value);
+        }
+        self
+    }
+
+    /// Append an argument after the command object
+    pub fn argument(mut self, value: AmfValue) -> Self {
+        self.command.arguments.push(value);
+        self
+    }
+
+    /// Set the transaction ID (0 for commands without a response)
+    pub fn transaction_id(mut self, id: f64) -> Self {
+        self.command.transaction_id = id;
+        self
+    }
+
+    /// Set the message stream the command is sent on
+    pub fn stream_id(mut self, stream_id: u32) -> Self {
+        self.command.stream_id = stream_id;
+        self
+    }
+
+    /// Build the command
+    pub fn build(self) -> Command {
+        self.command
+    }
+
+    /// Build the command and encode it as an AMF0 command message
+    ///
+    /// Returns the message type and payload.
+    pub fn encode(self) -> (u8, Bytes) {
+        RtmpMessage::Command(self.command).encode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::protocol::chunk::RtmpChunk;
+    use crate::protocol::message::ConnectParams;
+
+    /// Decode an encoded command the way a server does
+    fn decode(message_type: u8, payload: Bytes, stream_id: u32) -> Command {
+        let chunk = RtmpChunk {
+            csid: CSID_COMMAND,
+            timestamp: 0,
+            message_type,
+            stream_id,
+            payload,
+        };
+        match RtmpMessage::from_chunk(&chunk).unwrap() {
+            RtmpMessage::Command(cmd) => cmd,
+            other => panic!("expected a command, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_connect_command() {
+        let (message_type, payload) = CommandBuilder::connect("live", "rtmp://localhost/live")
+            .flash_ver("FMLE/3.0 (compatible; FMSc/1.0)")
+            .object_encoding(3.0)
+            .transaction_id(1.0)
+            .encode();
+        assert_eq!(message_type, MSG_COMMAND_AMF0);
+
+        let cmd = decode(message_type, payload, 0);
+        assert_eq!(cmd.name, CMD_CONNECT);
+        assert_eq!(cmd.transaction_id, 1.0);
+
+        let params = ConnectParams::from_amf(&cmd.command_object);
+        assert_eq!(params.app, "live");
+        assert_eq!(params.tc_url.as_deref(), Some("rtmp://localhost/live"));
+        assert_eq!(
+            params.flash_ver.as_deref(),
+            Some("FMLE/3.0 (compatible; FMSc/1.0)")
+        );
+        assert_eq!(params.object_encoding, 3.0);
+        assert_eq!(params.audio_codecs, 3191);
+        assert_eq!(params.video_codecs, 252);
+    }
+
+    #[test]
+    fn test_publish_sequence() {
+        let cmd = CommandBuilder::create_stream().transaction_id(2.0).build();
+        assert_eq!(cmd.name, CMD_CREATE_STREAM);
+        assert_eq!(cmd.command_object, AmfValue::Null);
+        assert!(cmd.arguments.is_empty());
+
+        for builder in [
+            CommandBuilder::release_stream("key"),
+            CommandBuilder::fc_publish("key"),
+        ] {
+            let cmd = builder.build();
+            assert_eq!(cmd.command_object, AmfValue::Null);
+            assert_eq!(cmd.arguments, vec![AmfValue::String("key".into())]);
+            assert_eq!(cmd.stream_id, 0);
+        }
+
+        let (message_type, payload) = CommandBuilder::publish("key", "live").stream_id(1).encode();
+        let cmd = decode(message_type, payload, 1);
+        assert_eq!(cmd.name, CMD_PUBLISH);
+        assert_eq!(cmd.transaction_id, 0.0);
+        assert_eq!(cmd.command_object, AmfValue::Null);
+        assert_eq!(
+            cmd.arguments,
+            vec![
+                AmfValue::String("key".into()),
+                AmfValue::String("live".into())
+            ]
+        );
+        assert_eq!(cmd.stream_id, 1);
+    }
+
+    #[test]
+    fn test_play_and_delete_stream() {
+        let cmd = CommandBuilder::play("key").stream_id(1).build();
+        assert_eq!(cmd.name, CMD_PLAY);
+        assert_eq!(
+            cmd.arguments,
+            vec![
+                AmfValue::String("key".into()),
+                AmfValue::Number(-2.0),
+                AmfValue::Number(-1.0),
+                AmfValue::Boolean(true)
+            ]
+        );
+
+        let cmd = CommandBuilder::delete_stream(1).build();
+        assert_eq!(cmd.name, CMD_DELETE_STREAM);
+        assert_eq!(cmd.arguments, vec![AmfValue::Number(1.0)]);
+    }
+}
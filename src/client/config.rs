@@ -4,19 +4,46 @@ use std::time::Duration;
 
 use crate::media::fourcc::{AudioFourCc, VideoFourCc};
 use crate::protocol::enhanced::{CapsEx, EnhancedRtmpMode, FourCcCapability};
+use crate::protocol::url::parse_rtmp_url;
+pub use crate::protocol::url::ParsedUrl;
 
 /// Client configuration
+///
+/// With the `serde` feature this can be loaded from a config file, with
+/// durations such as `"5s"`. The advertised E-RTMP codec lists can only be
+/// set in code.
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
 pub struct ClientConfig {
     /// RTMP URL to connect to (rtmp://host[:port]/app/stream)
     pub url: String,
 
     /// Connection timeout
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::serde_util::duration")
+    )]
     pub connect_timeout: Duration,
 
     /// Read timeout
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::serde_util::duration")
+    )]
     pub read_timeout: Duration,
 
+    /// How long to wait for the server to answer a command (connect,
+    /// createStream, play, publish) before giving up
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::serde_util::duration")
+    )]
+    pub command_timeout: Duration,
+
     /// Enable TCP_NODELAY
     pub tcp_nodelay: bool,
 
@@ -43,6 +70,11 @@ pub struct ClientConfig {
 ///
 /// Configure which E-RTMP features and codecs the client supports.
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
 pub struct EnhancedClientCapabilities {
     /// Support for NetConnection.Connect.ReconnectRequest
     pub reconnect: bool,
@@ -54,9 +86,11 @@ pub struct EnhancedClientCapabilities {
     pub modex: bool,
 
     /// Video codecs supported with their capabilities
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub video_codecs: Vec<(VideoFourCc, FourCcCapability)>,
 
     /// Audio codecs supported with their capabilities
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub audio_codecs: Vec<(AudioFourCc, FourCcCapability)>,
 }
 
@@ -160,6 +194,7 @@ impl Default for ClientConfig {
             url: String::new(),
             connect_timeout: Duration::from_secs(10),
             read_timeout: Duration::from_secs(30),
+            command_timeout: Duration::from_secs(10),
             tcp_nodelay: true,
             flash_ver: "LNX 9,0,124,2".to_string(),
             swf_url: None,
@@ -180,6 +215,12 @@ impl ClientConfig {
         }
     }
 
+    /// Set how long to wait for the server to answer a command.
+    pub fn command_timeout(mut self, timeout: Duration) -> Self {
+        self.command_timeout = timeout;
+        self
+    }
+
     /// Set Enhanced RTMP mode.
     ///
     /// - `Auto`: Negotiate E-RTMP if server supports it (default)
@@ -197,41 +238,13 @@ impl ClientConfig {
     }
 
     /// Parse URL into components
+    ///
+    /// See [`ParsedUrl`] for how the path is split into app and stream.
     pub fn parse_url(&self) -> Option<ParsedUrl> {
-        // rtmp://host[:port]/app[/stream]
-        let url = self.url.strip_prefix("rtmp://")?;
-
-        let (host_port, path) = url.split_once('/')?;
-        let (host, port) = if let Some((h, p)) = host_port.split_once(':') {
-            (h.to_string(), p.parse().ok()?)
-        } else {
-            (host_port.to_string(), 1935)
-        };
-
-        let (app, stream_key) = if let Some((a, s)) = path.split_once('/') {
-            (a.to_string(), Some(s.to_string()))
-        } else {
-            (path.to_string(), None)
-        };
-
-        Some(ParsedUrl {
-            host,
-            port,
-            app,
-            stream_key,
-        })
+        parse_rtmp_url(&self.url)
     }
 }
 
-/// Parsed RTMP URL components
-#[derive(Debug, Clone)]
-pub struct ParsedUrl {
-    pub host: String,
-    pub port: u16,
-    pub app: String,
-    pub stream_key: Option<String>,
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -343,4 +356,24 @@ mod tests {
         assert_eq!(config.enhanced_rtmp, EnhancedRtmpMode::Auto);
         assert_eq!(config.enhanced_capabilities.video_codecs.len(), 1);
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_client_config() {
+        let config: ClientConfig = serde_json::from_str(
+            r#"{
+                "url": "rtmp://example.com/live/key",
+                "connect_timeout": 3,
+                "command_timeout": "2500ms",
+                "enhanced_rtmp": "enhanced_only"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.url, "rtmp://example.com/live/key");
+        assert_eq!(config.connect_timeout, Duration::from_secs(3));
+        assert_eq!(config.command_timeout, Duration::from_millis(2500));
+        assert_eq!(config.enhanced_rtmp, EnhancedRtmpMode::EnhancedOnly);
+        assert_eq!(config.read_timeout, ClientConfig::default().read_timeout);
+    }
 }
@@ -3,28 +3,32 @@
 //! Low-level client for connecting to RTMP servers.
 
 use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
 
 use bytes::{Buf, Bytes, BytesMut};
 use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
 use tokio::net::TcpStream;
 use tokio::time::timeout;
 
-use crate::amf::AmfValue;
-use crate::error::{Error, Result};
+use crate::amf::{AmfObject, AmfValue};
+use crate::error::{ClientError, Error, Result};
 use crate::protocol::chunk::{ChunkDecoder, ChunkEncoder, RtmpChunk};
 use crate::protocol::constants::*;
 use crate::protocol::enhanced::{EnhancedCapabilities, EnhancedRtmpMode};
 use crate::protocol::handshake::{Handshake, HandshakeRole};
-use crate::protocol::message::{Command, ConnectParams, RtmpMessage};
+use crate::protocol::message::{Command, ConnectParams, DataMessage, RtmpMessage};
+use crate::transport::Transport;
 
+use super::command::CommandBuilder;
 use super::config::{ClientConfig, ParsedUrl};
 
 /// RTMP client connector
-pub struct RtmpConnector {
+pub struct RtmpConnector<S: Transport = TcpStream> {
     config: ClientConfig,
     parsed_url: ParsedUrl,
-    reader: BufReader<tokio::io::ReadHalf<TcpStream>>,
-    writer: BufWriter<tokio::io::WriteHalf<TcpStream>>,
+    reader: BufReader<tokio::io::ReadHalf<S>>,
+    writer: BufWriter<tokio::io::WriteHalf<S>>,
     read_buf: BytesMut,
     write_buf: BytesMut,
     chunk_decoder: ChunkDecoder,
@@ -32,6 +36,10 @@ pub struct RtmpConnector {
     stream_id: u32,
     /// Negotiated E-RTMP capabilities (if E-RTMP is active)
     enhanced_capabilities: Option<EnhancedCapabilities>,
+    /// Next transaction ID to hand out
+    next_transaction_id: u64,
+    /// Commands awaiting `_result`/`_error` (transaction ID -> command name)
+    pending_calls: HashMap<u64, String>,
 }
 
 impl RtmpConnector {
@@ -52,7 +60,21 @@ impl RtmpConnector {
             socket.set_nodelay(true)?;
         }
 
-        let (read_half, write_half) = tokio::io::split(socket);
+        RtmpConnector::connect_with_transport(config, socket).await
+    }
+}
+
+impl<S: Transport> RtmpConnector<S> {
+    /// Connect over an already established transport
+    ///
+    /// The URL in `config` still supplies the app name and tcUrl; its host
+    /// and port are not used.
+    pub async fn connect_with_transport(config: ClientConfig, transport: S) -> Result<Self> {
+        let parsed_url = config
+            .parse_url()
+            .ok_or_else(|| Error::Config("Invalid RTMP URL".into()))?;
+
+        let (read_half, write_half) = tokio::io::split(transport);
 
         let mut connector = Self {
             config,
@@ -65,6 +87,8 @@ impl RtmpConnector {
             chunk_encoder: ChunkEncoder::new(),
             stream_id: 0,
             enhanced_capabilities: None,
+            next_transaction_id: 1,
+            pending_calls: HashMap::new(),
         };
 
         connector.do_handshake().await?;
@@ -116,67 +140,33 @@ impl RtmpConnector {
 
     /// Send connect command
     async fn do_connect(&mut self) -> Result<()> {
-        let mut obj = HashMap::new();
-        obj.insert(
-            "app".to_string(),
-            AmfValue::String(self.parsed_url.app.clone()),
-        );
-        obj.insert("type".to_string(), AmfValue::String("nonprivate".into()));
-        obj.insert(
-            "flashVer".to_string(),
-            AmfValue::String(self.config.flash_ver.clone()),
-        );
-        obj.insert(
-            "tcUrl".to_string(),
-            AmfValue::String(self.config.url.clone()),
-        );
-        obj.insert("fpad".to_string(), AmfValue::Boolean(false));
-        obj.insert("capabilities".to_string(), AmfValue::Number(15.0));
-        obj.insert("audioCodecs".to_string(), AmfValue::Number(3191.0));
-        obj.insert("videoCodecs".to_string(), AmfValue::Number(252.0));
-        obj.insert("videoFunction".to_string(), AmfValue::Number(1.0));
+        let mut cmd = CommandBuilder::connect(&self.parsed_url.app, &self.config.url)
+            .flash_ver(&self.config.flash_ver)
+            .transaction_id(self.begin_call(CMD_CONNECT))
+            .build();
 
         // Add E-RTMP fields if not in LegacyOnly mode
         let client_caps = if !matches!(self.config.enhanced_rtmp, EnhancedRtmpMode::LegacyOnly) {
             let caps = self.config.enhanced_capabilities.to_enhanced_capabilities();
-            self.add_ertmp_fields(&mut obj, &caps);
+            if let Some(obj) = cmd.command_object.as_object_mut() {
+                self.add_ertmp_fields(obj, &caps);
+            }
             Some(caps)
         } else {
             None
         };
 
-        let cmd = Command {
-            name: CMD_CONNECT.to_string(),
-            transaction_id: 1.0,
-            command_object: AmfValue::Object(obj),
-            arguments: vec![],
-            stream_id: 0,
-        };
-
         self.send_command(&cmd).await?;
 
         // Wait for connect result
-        loop {
-            let msg = self.read_message().await?;
-            match msg {
-                RtmpMessage::Command(cmd) if cmd.name == CMD_RESULT => {
-                    // Parse server's E-RTMP response
-                    self.handle_connect_result(&cmd, client_caps.as_ref())?;
-                    break;
-                }
-                RtmpMessage::Command(cmd) if cmd.name == CMD_ERROR => {
-                    return Err(Error::Rejected("Connect rejected".into()));
-                }
-                RtmpMessage::SetChunkSize(size) => {
-                    self.chunk_decoder.set_chunk_size(size);
-                }
-                RtmpMessage::WindowAckSize(_) | RtmpMessage::SetPeerBandwidth { .. } => {
-                    // Ignore these during connect
-                }
-                _ => {}
-            }
+        let response = self.wait_for_response(cmd.transaction_id).await?;
+        if response.name == CMD_ERROR {
+            return Err(Error::Rejected("Connect rejected".into()));
         }
 
+        // Parse server's E-RTMP response
+        self.handle_connect_result(&response, client_caps.as_ref())?;
+
         // Set our chunk size
         self.chunk_encoder.set_chunk_size(RECOMMENDED_CHUNK_SIZE);
         self.send_message(&RtmpMessage::SetChunkSize(RECOMMENDED_CHUNK_SIZE))
@@ -186,7 +176,7 @@ impl RtmpConnector {
     }
 
     /// Add E-RTMP fields to the connect command object.
-    fn add_ertmp_fields(&self, obj: &mut HashMap<String, AmfValue>, caps: &EnhancedCapabilities) {
+    fn add_ertmp_fields(&self, obj: &mut AmfObject, caps: &EnhancedCapabilities) {
         // Add capsEx
         obj.insert(
             "capsEx".to_string(),
@@ -212,7 +202,7 @@ impl RtmpConnector {
 
         // Add videoFourCcInfoMap (modern E-RTMP)
         if !caps.video_codecs.is_empty() {
-            let mut video_map: HashMap<String, AmfValue> = HashMap::new();
+            let mut video_map: AmfObject = AmfObject::new();
             for (codec, capability) in &caps.video_codecs {
                 video_map.insert(
                     codec.as_fourcc_str().to_string(),
@@ -227,7 +217,7 @@ impl RtmpConnector {
 
         // Add audioFourCcInfoMap (modern E-RTMP)
         if !caps.audio_codecs.is_empty() {
-            let mut audio_map: HashMap<String, AmfValue> = HashMap::new();
+            let mut audio_map: AmfObject = AmfObject::new();
             for (codec, capability) in &caps.audio_codecs {
                 audio_map.insert(
                     codec.as_fourcc_str().to_string(),
@@ -282,28 +272,23 @@ impl RtmpConnector {
 
     /// Create a stream for publishing or playing
     pub async fn create_stream(&mut self) -> Result<u32> {
-        let cmd = Command {
-            name: CMD_CREATE_STREAM.to_string(),
-            transaction_id: 2.0,
-            command_object: AmfValue::Null,
-            arguments: vec![],
-            stream_id: 0,
-        };
+        let cmd = CommandBuilder::create_stream()
+            .transaction_id(self.begin_call(CMD_CREATE_STREAM))
+            .build();
 
         self.send_command(&cmd).await?;
 
         // Wait for result
-        loop {
-            let msg = self.read_message().await?;
-            if let RtmpMessage::Command(result) = msg {
-                if result.name == CMD_RESULT && result.transaction_id == 2.0 {
-                    if let Some(id) = result.arguments.first().and_then(|v| v.as_number()) {
-                        self.stream_id = id as u32;
-                        return Ok(self.stream_id);
-                    }
-                }
-            }
-        }
+        let response = self.wait_for_response(cmd.transaction_id).await?;
+        let stream_id = response
+            .arguments
+            .first()
+            .and_then(|v| v.as_number())
+            .filter(|_| response.name == CMD_RESULT)
+            .ok_or_else(|| Error::Rejected("createStream failed".into()))?;
+
+        self.stream_id = stream_id as u32;
+        Ok(self.stream_id)
     }
 
     /// Start playing a stream
@@ -322,38 +307,33 @@ impl RtmpConnector {
         .await?;
 
         // Send play command
-        let cmd = Command {
-            name: CMD_PLAY.to_string(),
-            transaction_id: 0.0,
-            command_object: AmfValue::Null,
-            arguments: vec![
-                AmfValue::String(stream_name.to_string()),
-                AmfValue::Number(-2.0),  // Start: live or recorded
-                AmfValue::Number(-1.0),  // Duration: play until end
-                AmfValue::Boolean(true), // Reset
-            ],
-            stream_id: self.stream_id,
-        };
+        let cmd = CommandBuilder::play(stream_name)
+            .stream_id(self.stream_id)
+            .build();
 
         self.send_command(&cmd).await?;
 
         // Wait for onStatus
-        loop {
-            let msg = self.read_message().await?;
-            if let RtmpMessage::Command(status) = msg {
-                if status.name == CMD_ON_STATUS {
-                    if let Some(info) = status.arguments.first().and_then(|v| v.as_object()) {
-                        if let Some(code) = info.get("code").and_then(|v| v.as_str()) {
-                            if code == NS_PLAY_START {
-                                return Ok(());
-                            } else if code.contains("Failed") || code.contains("Error") {
-                                return Err(Error::Rejected(code.to_string()));
+        let limit = self.config.command_timeout;
+        with_command_timeout(limit, CMD_PLAY, cmd.transaction_id, async {
+            loop {
+                let msg = self.read_message().await?;
+                if let RtmpMessage::Command(status) = msg {
+                    if status.name == CMD_ON_STATUS {
+                        if let Some(info) = status.arguments.first().and_then(|v| v.as_object()) {
+                            if let Some(code) = info.get("code").and_then(|v| v.as_str()) {
+                                if code == NS_PLAY_START {
+                                    return Ok(());
+                                } else if code.contains("Failed") || code.contains("Error") {
+                                    return Err(Error::Rejected(code.to_string()));
+                                }
                             }
                         }
                     }
                 }
             }
-        }
+        })
+        .await
     }
 
     /// Start publishing a stream
@@ -363,71 +343,94 @@ impl RtmpConnector {
         }
 
         // releaseStream (some servers require this)
-        let release_cmd = Command {
-            name: CMD_RELEASE_STREAM.to_string(),
-            transaction_id: 3.0,
-            command_object: AmfValue::Null,
-            arguments: vec![AmfValue::String(stream_name.to_string())],
-            stream_id: 0,
-        };
+        let release_cmd = CommandBuilder::release_stream(stream_name)
+            .transaction_id(self.allocate_transaction_id())
+            .build();
         self.send_command(&release_cmd).await?;
 
         // FCPublish (some servers require this)
-        let fc_cmd = Command {
-            name: CMD_FC_PUBLISH.to_string(),
-            transaction_id: 4.0,
-            command_object: AmfValue::Null,
-            arguments: vec![AmfValue::String(stream_name.to_string())],
-            stream_id: 0,
-        };
+        let fc_cmd = CommandBuilder::fc_publish(stream_name)
+            .transaction_id(self.allocate_transaction_id())
+            .build();
         self.send_command(&fc_cmd).await?;
 
         // Send publish command
-        let cmd = Command {
-            name: CMD_PUBLISH.to_string(),
-            transaction_id: 0.0,
-            command_object: AmfValue::Null,
-            arguments: vec![
-                AmfValue::String(stream_name.to_string()),
-                AmfValue::String("live".to_string()),
-            ],
-            stream_id: self.stream_id,
-        };
+        let cmd = CommandBuilder::publish(stream_name, "live")
+            .stream_id(self.stream_id)
+            .build();
         self.send_command(&cmd).await?;
 
         // Wait for onStatus with NetStream.Publish.Start
-        loop {
-            let msg = self.read_message().await?;
-            match msg {
-                RtmpMessage::Command(status) if status.name == CMD_ON_STATUS => {
-                    if let Some(info) = status.arguments.first().and_then(|v| v.as_object()) {
-                        if let Some(code) = info.get("code").and_then(|v| v.as_str()) {
-                            if code == NS_PUBLISH_START {
-                                return Ok(());
-                            } else if code.contains("Failed")
-                                || code.contains("Error")
-                                || code == NS_PUBLISH_BAD_NAME
-                            {
-                                return Err(Error::Rejected(code.to_string()));
+        let limit = self.config.command_timeout;
+        with_command_timeout(limit, CMD_PUBLISH, cmd.transaction_id, async {
+            loop {
+                let msg = self.read_message().await?;
+                match msg {
+                    RtmpMessage::Command(status) if status.name == CMD_ON_STATUS => {
+                        if let Some(info) = status.arguments.first().and_then(|v| v.as_object()) {
+                            if let Some(code) = info.get("code").and_then(|v| v.as_str()) {
+                                if code == NS_PUBLISH_START {
+                                    return Ok(());
+                                } else if code.contains("Failed")
+                                    || code.contains("Error")
+                                    || code == NS_PUBLISH_BAD_NAME
+                                {
+                                    return Err(Error::Rejected(code.to_string()));
+                                }
                             }
                         }
                     }
+                    RtmpMessage::Command(cmd)
+                        if cmd.name == CMD_RESULT || cmd.name == CMD_ON_FC_PUBLISH =>
+                    {
+                        // Responses to releaseStream/FCPublish - ignore
+                    }
+                    RtmpMessage::Command(cmd) if cmd.name == CMD_ERROR => {
+                        return Err(Error::Rejected("Publish rejected".into()));
+                    }
+                    RtmpMessage::SetChunkSize(size) => {
+                        self.chunk_decoder.set_chunk_size(size);
+                    }
+                    RtmpMessage::WindowAckSize(_) | RtmpMessage::SetPeerBandwidth { .. } => {}
+                    _ => {}
                 }
-                RtmpMessage::Command(cmd)
-                    if cmd.name == CMD_RESULT || cmd.name == CMD_ON_FC_PUBLISH =>
-                {
-                    // Responses to releaseStream/FCPublish - ignore
-                }
-                RtmpMessage::Command(cmd) if cmd.name == CMD_ERROR => {
-                    return Err(Error::Rejected("Publish rejected".into()));
-                }
-                RtmpMessage::SetChunkSize(size) => {
-                    self.chunk_decoder.set_chunk_size(size);
-                }
-                RtmpMessage::WindowAckSize(_) | RtmpMessage::SetPeerBandwidth { .. } => {}
-                _ => {}
             }
+        })
+        .await
+    }
+
+    /// Delete the current stream, ending publishing or playback on it
+    ///
+    /// The connection stays open; the next `publish` or `play` creates a
+    /// new stream.
+    pub async fn delete_stream(&mut self) -> Result<()> {
+        if self.stream_id == 0 {
+            return Ok(());
         }
+
+        let cmd = CommandBuilder::delete_stream(self.stream_id)
+            .transaction_id(self.allocate_transaction_id())
+            .build();
+        self.send_command(&cmd).await?;
+        self.stream_id = 0;
+
+        Ok(())
+    }
+
+    /// Tell the server how much the player buffers, in milliseconds
+    ///
+    /// Applies to the current stream and to streams played later. `play`
+    /// already announces [`ClientConfig::buffer_length`]; call this to
+    /// change it during playback.
+    pub async fn set_buffer_length(&mut self, buffer_ms: u32) -> Result<()> {
+        self.config.buffer_length = buffer_ms;
+        self.send_message(&RtmpMessage::UserControl(
+            crate::protocol::message::UserControlEvent::SetBufferLength {
+                stream_id: self.stream_id,
+                buffer_ms,
+            },
+        ))
+        .await
     }
 
     /// Send audio data on the published stream.
@@ -451,12 +454,71 @@ impl RtmpConnector {
         Ok(())
     }
 
+    /// Send video data on the published stream.
+    ///
+    /// `data` is the FLV video tag body (header byte + payload).
+    /// `timestamp` is in milliseconds.
+    pub async fn send_video_data(&mut self, data: Bytes, timestamp: u32) -> Result<()> {
+        let chunk = RtmpChunk {
+            csid: CSID_VIDEO,
+            timestamp,
+            message_type: crate::protocol::constants::MSG_VIDEO,
+            stream_id: self.stream_id,
+            payload: data,
+        };
+
+        self.write_buf.clear();
+        self.chunk_encoder.encode(&chunk, &mut self.write_buf);
+        self.writer.write_all(&self.write_buf).await?;
+        self.writer.flush().await?;
+
+        Ok(())
+    }
+
+    /// Send stream metadata (`@setDataFrame`/`onMetaData`) on the published
+    /// stream.
+    pub async fn send_metadata(&mut self, metadata: &AmfObject) -> Result<()> {
+        let data = DataMessage {
+            name: CMD_SET_DATA_FRAME.to_string(),
+            values: vec![
+                AmfValue::String(CMD_ON_METADATA.to_string()),
+                AmfValue::EcmaArray(metadata.clone()),
+            ],
+            stream_id: self.stream_id,
+        };
+        self.send_message(&RtmpMessage::Data(data)).await
+    }
+
+    /// Remove the stream metadata (`@clearDataFrame`) on the published
+    /// stream.
+    pub async fn clear_metadata(&mut self) -> Result<()> {
+        let data = DataMessage {
+            name: CMD_CLEAR_DATA_FRAME.to_string(),
+            values: vec![AmfValue::String(CMD_ON_METADATA.to_string())],
+            stream_id: self.stream_id,
+        };
+        self.send_message(&RtmpMessage::Data(data)).await
+    }
+
     /// Read the next RTMP message
     pub async fn read_message(&mut self) -> Result<RtmpMessage> {
         loop {
             // Try to decode from buffer
-            while let Some(chunk) = self.chunk_decoder.decode(&mut self.read_buf)? {
-                return RtmpMessage::from_chunk(&chunk);
+            let buf_len_before = self.read_buf.len();
+            if let Some(chunk) = self.chunk_decoder.decode(&mut self.read_buf)? {
+                let msg = RtmpMessage::from_chunk(&chunk)?;
+
+                // Drop any partially reassembled message the peer aborted
+                if let RtmpMessage::Abort { csid } = msg {
+                    self.chunk_decoder.abort(csid);
+                }
+
+                return Ok(msg);
+            }
+
+            // A partial chunk was consumed; more chunks may already be buffered
+            if self.read_buf.len() < buf_len_before {
+                continue;
             }
 
             // Need more data
@@ -480,11 +542,19 @@ impl RtmpConnector {
             _ => CSID_COMMAND,
         };
 
+        // Stream commands such as publish and play must arrive on the
+        // message stream they target
+        let stream_id = match msg {
+            RtmpMessage::Command(cmd) | RtmpMessage::CommandAmf3(cmd) => cmd.stream_id,
+            RtmpMessage::Data(data) | RtmpMessage::DataAmf3(data) => data.stream_id,
+            _ => 0,
+        };
+
         let chunk = RtmpChunk {
             csid,
             timestamp: 0,
             message_type: msg_type,
-            stream_id: 0,
+            stream_id,
             payload,
         };
 
@@ -496,8 +566,58 @@ impl RtmpConnector {
         Ok(())
     }
 
+    /// Hand out the next transaction ID without awaiting a response
+    fn allocate_transaction_id(&mut self) -> f64 {
+        let id = self.next_transaction_id;
+        self.next_transaction_id += 1;
+        id as f64
+    }
+
+    /// Allocate a transaction ID for a command whose `_result`/`_error`
+    /// will be awaited with [`wait_for_response`](Self::wait_for_response)
+    fn begin_call(&mut self, command: &str) -> f64 {
+        let id = self.allocate_transaction_id();
+        self.pending_calls.insert(id as u64, command.to_string());
+        id
+    }
+
+    /// Wait for the `_result` or `_error` answering a pending call
+    ///
+    /// Fails with [`ClientError::CommandTimeout`] if no response arrives
+    /// within `command_timeout`.
+    async fn wait_for_response(&mut self, transaction_id: f64) -> Result<Command> {
+        let key = transaction_id as u64;
+        let limit = self.config.command_timeout;
+        let command = self.pending_calls.get(&key).cloned().unwrap_or_default();
+
+        let result = with_command_timeout(limit, &command, transaction_id, async {
+            loop {
+                match self.read_message().await? {
+                    RtmpMessage::Command(cmd)
+                        if cmd.name == CMD_RESULT || cmd.name == CMD_ERROR =>
+                    {
+                        let id = cmd.transaction_id as u64;
+                        if self.pending_calls.remove(&id).is_none() {
+                            tracing::debug!(transaction_id = id, "Response to unknown call");
+                        } else if id == key {
+                            return Ok(cmd);
+                        }
+                    }
+                    RtmpMessage::SetChunkSize(size) => {
+                        self.chunk_decoder.set_chunk_size(size);
+                    }
+                    _ => {}
+                }
+            }
+        })
+        .await;
+
+        self.pending_calls.remove(&key);
+        result
+    }
+
     /// Send a command
-    async fn send_command(&mut self, cmd: &Command) -> Result<()> {
+    pub(crate) async fn send_command(&mut self, cmd: &Command) -> Result<()> {
         self.send_message(&RtmpMessage::Command(cmd.clone())).await
     }
 
@@ -519,3 +639,98 @@ impl RtmpConnector {
         self.enhanced_capabilities.as_ref()
     }
 }
+
+/// Wait for a server response to `command`, failing once `limit` has passed
+async fn with_command_timeout<T>(
+    limit: Duration,
+    command: &str,
+    transaction_id: f64,
+    wait: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    timeout(limit, wait)
+        .await
+        .map_err(|_| ClientError::CommandTimeout {
+            command: command.to_string(),
+            transaction_id,
+        })?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crate::protocol::message::PlayParams;
+    use crate::registry::StreamRegistry;
+    use crate::server::{AuthResult, RtmpHandler, ServerConfig};
+    use crate::session::SessionContext;
+    use crate::test_util::spawn_session;
+    use crate::transport::DuplexTransport;
+
+    /// Handler that never answers the configured command
+    struct Unresponsive {
+        stall_connect: bool,
+    }
+
+    impl RtmpHandler for Unresponsive {
+        async fn on_connect(&self, _ctx: &SessionContext, _params: &ConnectParams) -> AuthResult {
+            if self.stall_connect {
+                std::future::pending::<()>().await;
+            }
+            AuthResult::Accept
+        }
+
+        async fn on_play(&self, _ctx: &SessionContext, _params: &PlayParams) -> AuthResult {
+            std::future::pending().await
+        }
+    }
+
+    fn spawn_server(handler: Unresponsive) -> DuplexTransport {
+        let registry = Arc::new(StreamRegistry::new());
+        spawn_session(1, ServerConfig::default(), Arc::new(handler), registry).0
+    }
+
+    fn config() -> ClientConfig {
+        ClientConfig::new("rtmp://localhost/live").command_timeout(Duration::from_millis(100))
+    }
+
+    #[tokio::test]
+    async fn test_connect_times_out_without_result() {
+        let transport = spawn_server(Unresponsive {
+            stall_connect: true,
+        });
+
+        let err = match RtmpConnector::connect_with_transport(config(), transport).await {
+            Ok(_) => panic!("connect should time out"),
+            Err(e) => e,
+        };
+        match err {
+            Error::Client(ClientError::CommandTimeout {
+                command,
+                transaction_id,
+            }) => {
+                assert_eq!(command, CMD_CONNECT);
+                assert_eq!(transaction_id, 1.0);
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_play_times_out_without_status() {
+        let transport = spawn_server(Unresponsive {
+            stall_connect: false,
+        });
+        let mut client = RtmpConnector::connect_with_transport(config(), transport)
+            .await
+            .unwrap();
+
+        let err = client.play("test").await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Client(ClientError::CommandTimeout { ref command, .. }) if command == CMD_PLAY
+        ));
+        assert!(client.pending_calls.is_empty());
+    }
+}
@@ -0,0 +1,358 @@
+//! Republish an FLV file as a live stream
+//!
+//! [`FlvSource`] reads tags from an FLV file and sends them through an
+//! [`RtmpPublisher`] in real time, like `ffmpeg -re -i file.flv -c copy -f flv`.
+//! Timestamps are rebased to start at zero and keep increasing across
+//! loops, so players see one continuous stream.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::amf::Amf0Decoder;
+use crate::error::Result;
+use crate::media::flv::{FlvReader, FlvTag, FlvTagType};
+use crate::protocol::constants::CMD_ON_METADATA;
+use crate::transport::Transport;
+
+use super::publisher::RtmpPublisher;
+
+/// Tags read ahead of the send loop
+const READ_AHEAD_TAGS: usize = 64;
+
+/// FLV file fed into a publisher as a live source
+///
+/// # Example
+/// ```no_run
+/// use rtmp_rs::client::{ClientConfig, FlvSource, RtmpPublisher};
+///
+/// # async fn example() -> rtmp_rs::error::Result<()> {
+/// let config = ClientConfig::new("rtmp://localhost/live/stream_key");
+/// let (mut publisher, _events) = RtmpPublisher::new(config);
+/// publisher.connect().await?;
+///
+/// let mut source = FlvSource::open("input.flv")?.looping(true);
+/// source.publish(&mut publisher).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FlvSource<R: Read + Seek> {
+    reader: Option<FlvReader<R>>,
+    looping: bool,
+    /// Offset added to file timestamps (grows by one file length per loop)
+    offset: u32,
+    /// First timestamp of the file, subtracted when rebasing
+    first_ts: Option<u32>,
+    /// Last timestamp sent, after rebasing
+    last_ts: u32,
+    /// Gap between the last two tags of the same type, used to space loops
+    frame_interval: u32,
+    last_audio_ts: Option<u32>,
+    last_video_ts: Option<u32>,
+}
+
+impl FlvSource<BufReader<File>> {
+    /// Open an FLV file
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> FlvSource<R> {
+    /// Create a source from a reader positioned at the start of an FLV file
+    pub fn new(inner: R) -> io::Result<Self> {
+        Ok(Self {
+            reader: Some(FlvReader::new(inner)?),
+            looping: false,
+            offset: 0,
+            first_ts: None,
+            last_ts: 0,
+            frame_interval: 0,
+            last_audio_ts: None,
+            last_video_ts: None,
+        })
+    }
+
+    /// Start over from the beginning of the file when it ends
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// Read the next tag with its timestamp rebased
+    ///
+    /// Returns `Ok(None)` at the end of the file, or never when looping
+    /// (unless the file has no tags at all).
+    pub fn next_tag(&mut self) -> io::Result<Option<FlvTag>> {
+        let mut rewound = false;
+        loop {
+            let reader = match self.reader.as_mut() {
+                Some(reader) => reader,
+                None => return Ok(None),
+            };
+            match reader.read_tag()? {
+                Some(tag) => return Ok(Some(self.rebase(tag))),
+                None if self.looping && !rewound => {
+                    self.rewind()?;
+                    rewound = true;
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Send the file through `publisher`, paced by its timestamps
+    ///
+    /// `onMetaData` script tags are sent as stream metadata; other script
+    /// tags are skipped. Returns at the end of the file, or on the first
+    /// error when looping.
+    ///
+    /// The file is read on a blocking task a few tags ahead of the send
+    /// loop. If the returned future is dropped before it completes, the
+    /// source is left as if it had reached the end of the file.
+    pub async fn publish<S: Transport>(&mut self, publisher: &mut RtmpPublisher<S>) -> Result<()>
+    where
+        R: Send + 'static,
+    {
+        let (tx, mut rx) = mpsc::channel(READ_AHEAD_TAGS);
+        let base = self.last_ts;
+
+        // Everything but the reader is `Copy`, so this leaves `self` at its
+        // end until the reading task hands the source back
+        let mut source = Self {
+            reader: self.reader.take(),
+            ..*self
+        };
+        let reading = tokio::task::spawn_blocking(move || {
+            while let Some(tag) = source.next_tag().transpose() {
+                let failed = tag.is_err();
+                if tx.blocking_send(tag).is_err() || failed {
+                    break;
+                }
+            }
+            source
+        });
+
+        let result = send_tags(&mut rx, base, publisher).await;
+        // Unblocks the reading task if the send loop stopped early
+        drop(rx);
+        *self = reading.await.map_err(io::Error::other)?;
+        result
+    }
+
+    /// Seek back to the first tag and move the offset past the last one
+    fn rewind(&mut self) -> io::Result<()> {
+        let Some(reader) = self.reader.take() else {
+            return Ok(());
+        };
+        if self.first_ts.is_none() {
+            // Nothing was read; looping would spin on an empty file
+            return Ok(());
+        }
+
+        let mut inner = reader.into_inner();
+        inner.seek(SeekFrom::Start(0))?;
+        self.reader = Some(FlvReader::new(inner)?);
+
+        self.offset = self.last_ts.saturating_add(self.frame_interval.max(1));
+        self.first_ts = None;
+        self.last_audio_ts = None;
+        self.last_video_ts = None;
+        Ok(())
+    }
+
+    /// Shift a tag's timestamp so the stream starts at zero and keeps
+    /// counting up across loops
+    fn rebase(&mut self, mut tag: FlvTag) -> FlvTag {
+        let first_ts = *self.first_ts.get_or_insert(tag.timestamp);
+        let timestamp = self
+            .offset
+            .saturating_add(tag.timestamp.saturating_sub(first_ts));
+
+        let last = match tag.tag_type {
+            FlvTagType::Audio => Some(&mut self.last_audio_ts),
+            FlvTagType::Video => Some(&mut self.last_video_ts),
+            FlvTagType::Script => None,
+        };
+        if let Some(last) = last {
+            if let Some(prev) = last.replace(timestamp) {
+                self.frame_interval = timestamp.saturating_sub(prev);
+            }
+        }
+
+        self.last_ts = self.last_ts.max(timestamp);
+        tag.timestamp = timestamp;
+        tag
+    }
+}
+
+/// Send tags from the reading task, each at its timestamp after the first
+async fn send_tags<S: Transport>(
+    rx: &mut mpsc::Receiver<io::Result<FlvTag>>,
+    base: u32,
+    publisher: &mut RtmpPublisher<S>,
+) -> Result<()> {
+    let start = Instant::now();
+
+    while let Some(tag) = rx.recv().await {
+        let tag = tag?;
+        let due = start + Duration::from_millis(tag.timestamp.saturating_sub(base) as u64);
+        tokio::time::sleep_until(due).await;
+
+        match tag.tag_type {
+            FlvTagType::Audio => publisher.send_audio(tag.data, tag.timestamp).await?,
+            FlvTagType::Video => publisher.send_video(tag.data, tag.timestamp).await?,
+            FlvTagType::Script => {
+                if tag.script_name() != Some(CMD_ON_METADATA) {
+                    continue;
+                }
+                let mut data = tag.data.clone();
+                let mut decoder = Amf0Decoder::new();
+                let _name = decoder.decode(&mut data)?;
+                if let Some(metadata) = decoder.decode(&mut data)?.as_object() {
+                    publisher.send_metadata(metadata).await?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+
+    use crate::amf::{AmfObject, AmfValue};
+    use crate::client::{ClientConfig, RtmpConnector};
+    use crate::media::flv::FlvWriter;
+    use crate::media::metadata::encode_on_metadata;
+    use crate::protocol::message::RtmpMessage;
+    use crate::registry::StreamRegistry;
+    use crate::server::config::ServerConfig;
+    use crate::server::handler::RtmpHandler;
+    use crate::test_util::spawn_session;
+
+    struct AcceptAll;
+
+    impl RtmpHandler for AcceptAll {}
+
+    /// A short FLV: metadata, AVC and AAC headers, then a GOP starting at 1000ms
+    fn sample_flv() -> Vec<u8> {
+        let mut metadata = AmfObject::new();
+        metadata.insert("width".into(), AmfValue::Number(1280.0));
+
+        let mut writer = FlvWriter::new(Vec::new()).unwrap();
+        for tag in [
+            FlvTag::script(1000, encode_on_metadata(&metadata)),
+            FlvTag::video(1000, Bytes::from_static(&[0x17, 0x00, 0, 0, 0, 0x01])),
+            FlvTag::audio(1000, Bytes::from_static(&[0xAF, 0x00, 0x12, 0x10])),
+            FlvTag::video(1000, Bytes::from_static(&[0x17, 0x01, 0, 0, 0, 0x65])),
+            FlvTag::audio(1023, Bytes::from_static(&[0xAF, 0x01, 0x21])),
+            FlvTag::video(1033, Bytes::from_static(&[0x27, 0x01, 0, 0, 0, 0x41])),
+            FlvTag::video(1066, Bytes::from_static(&[0x27, 0x01, 0, 0, 0, 0x41])),
+        ] {
+            writer.write_tag(&tag).unwrap();
+        }
+        writer.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_rebase_and_loop() {
+        let mut source = FlvSource::new(Cursor::new(sample_flv()))
+            .unwrap()
+            .looping(true);
+
+        let timestamps: Vec<u32> = (0..14)
+            .map(|_| source.next_tag().unwrap().unwrap().timestamp)
+            .collect();
+        // Starts at zero; the second pass follows one frame after the first
+        assert_eq!(
+            timestamps,
+            vec![0, 0, 0, 0, 23, 33, 66, 99, 99, 99, 99, 122, 132, 165]
+        );
+    }
+
+    #[test]
+    fn test_ends_without_looping() {
+        let mut source = FlvSource::new(Cursor::new(sample_flv())).unwrap();
+        let mut count = 0;
+        while source.next_tag().unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 7);
+    }
+
+    #[tokio::test]
+    async fn test_publish_flv_and_play_back() {
+        let registry = Arc::new(StreamRegistry::new());
+
+        let (transport, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(AcceptAll),
+            registry.clone(),
+        );
+        let config = ClientConfig::new("rtmp://localhost/live/test");
+        let (mut publisher, _events) = RtmpPublisher::connect_with_transport(config, transport)
+            .await
+            .unwrap();
+
+        let (transport, _) = spawn_session(
+            2,
+            ServerConfig::default(),
+            Arc::new(AcceptAll),
+            registry.clone(),
+        );
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut player = RtmpConnector::connect_with_transport(config, transport)
+            .await
+            .unwrap();
+        player.play("test").await.unwrap();
+
+        let mut source = FlvSource::new(Cursor::new(sample_flv())).unwrap();
+        let started = std::time::Instant::now();
+        source.publish(&mut publisher).await.unwrap();
+        // Paced by the file's timestamps
+        assert!(started.elapsed() >= std::time::Duration::from_millis(60));
+        // The reading task hands the source back where it stopped
+        assert_eq!(source.last_ts, 66);
+
+        let mut metadata = None;
+        let mut video = Vec::new();
+        let mut audio = Vec::new();
+        while video.len() < 4 || audio.len() < 2 {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), player.read_message())
+                    .await
+                    .expect("playback stalled")
+                    .unwrap();
+            match msg {
+                RtmpMessage::Data(data) if data.name == CMD_ON_METADATA => {
+                    metadata = data.values.first().and_then(|v| v.as_object()).cloned();
+                }
+                RtmpMessage::Video { timestamp, data } => video.push((timestamp, data)),
+                RtmpMessage::Audio { timestamp, .. } => audio.push(timestamp),
+                _ => {}
+            }
+        }
+
+        assert_eq!(
+            metadata.and_then(|m| m.get("width").and_then(|w| w.as_number())),
+            Some(1280.0)
+        );
+        let timestamps: Vec<u32> = video.iter().map(|(ts, _)| *ts).collect();
+        assert_eq!(timestamps, vec![0, 0, 33, 66]);
+        assert_eq!(video[0].1[1], 0x00);
+        assert_eq!(audio, vec![0, 23]);
+    }
+}
@@ -4,12 +4,16 @@
 //! - Pulling streams from remote RTMP servers
 //! - Connecting to any RTMP server for transcoding, relaying, etc.
 
+pub mod command;
 pub mod config;
 pub mod connector;
+pub mod flv_source;
 pub mod publisher;
 pub mod puller;
 
+pub use command::CommandBuilder;
 pub use config::ClientConfig;
 pub use connector::RtmpConnector;
+pub use flv_source::FlvSource;
 pub use publisher::{PublishEvent, RtmpPublisher};
 pub use puller::{ClientEvent, RtmpPuller};
@@ -3,9 +3,12 @@
 //! High-level API for publishing audio (and optionally video) streams to RTMP servers.
 
 use bytes::Bytes;
+use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 
+use crate::amf::AmfObject;
 use crate::error::{Error, Result};
+use crate::transport::Transport;
 
 use super::config::ClientConfig;
 use super::connector::RtmpConnector;
@@ -50,10 +53,10 @@ pub enum PublishEvent {
 /// # Ok(())
 /// # }
 /// ```
-pub struct RtmpPublisher {
+pub struct RtmpPublisher<S: Transport = TcpStream> {
     config: ClientConfig,
     event_tx: mpsc::Sender<PublishEvent>,
-    connector: Option<RtmpConnector>,
+    connector: Option<RtmpConnector<S>>,
 }
 
 impl RtmpPublisher {
@@ -77,7 +80,35 @@ impl RtmpPublisher {
     /// After this returns successfully, you can call `send_audio()` to
     /// send audio frames.
     pub async fn connect(&mut self) -> Result<()> {
-        let mut connector = RtmpConnector::connect(self.config.clone()).await?;
+        let connector = RtmpConnector::connect(self.config.clone()).await?;
+        self.start(connector).await
+    }
+}
+
+impl<S: Transport> RtmpPublisher<S> {
+    /// Connect over an already established transport and start publishing.
+    ///
+    /// Returns the connected publisher and a receiver for events. The URL
+    /// in `config` still supplies the app and stream name.
+    pub async fn connect_with_transport(
+        config: ClientConfig,
+        transport: S,
+    ) -> Result<(Self, mpsc::Receiver<PublishEvent>)> {
+        let (tx, rx) = mpsc::channel(256);
+        let connector = RtmpConnector::connect_with_transport(config.clone(), transport).await?;
+
+        let mut publisher = Self {
+            config,
+            event_tx: tx,
+            connector: None,
+        };
+        publisher.start(connector).await?;
+
+        Ok((publisher, rx))
+    }
+
+    /// Publish the stream named in the URL over a fresh connection
+    async fn start(&mut self, mut connector: RtmpConnector<S>) -> Result<()> {
         let _ = self.event_tx.send(PublishEvent::Connected).await;
 
         let stream_name = self
@@ -93,6 +124,15 @@ impl RtmpPublisher {
         Ok(())
     }
 
+    /// Get the connector, or fail when not connected
+    fn connector(&mut self) -> Result<&mut RtmpConnector<S>> {
+        self.connector.as_mut().ok_or_else(|| {
+            Error::Protocol(crate::error::ProtocolError::UnexpectedMessage(
+                "Not connected".into(),
+            ))
+        })
+    }
+
     /// Send an AAC audio frame.
     ///
     /// The `data` should be the raw FLV audio tag body:
@@ -102,14 +142,24 @@ impl RtmpPublisher {
     ///
     /// `timestamp` is in milliseconds.
     pub async fn send_audio(&mut self, data: Bytes, timestamp: u32) -> Result<()> {
-        let connector = self
-            .connector
-            .as_mut()
-            .ok_or_else(|| Error::Protocol(crate::error::ProtocolError::UnexpectedMessage(
-                "Not connected".into(),
-            )))?;
+        self.connector()?.send_audio_data(data, timestamp).await
+    }
+
+    /// Send a video frame.
+    ///
+    /// The `data` should be the raw FLV video tag body:
+    /// - First byte: frame type and codec (e.g., `0x17` for an AVC keyframe)
+    /// - For the sequence header: `0x17 0x00` + composition time +
+    ///   AVCDecoderConfigurationRecord
+    ///
+    /// `timestamp` is in milliseconds.
+    pub async fn send_video(&mut self, data: Bytes, timestamp: u32) -> Result<()> {
+        self.connector()?.send_video_data(data, timestamp).await
+    }
 
-        connector.send_audio_data(data, timestamp).await
+    /// Send stream metadata (`onMetaData`).
+    pub async fn send_metadata(&mut self, metadata: &AmfObject) -> Result<()> {
+        self.connector()?.send_metadata(metadata).await
     }
 
     /// Send the AAC sequence header.
@@ -119,10 +169,7 @@ impl RtmpPublisher {
     ///
     /// `audio_specific_config` is typically 2 bytes describing the AAC profile,
     /// sample rate, and channel configuration.
-    pub async fn send_aac_sequence_header(
-        &mut self,
-        audio_specific_config: &[u8],
-    ) -> Result<()> {
+    pub async fn send_aac_sequence_header(&mut self, audio_specific_config: &[u8]) -> Result<()> {
         let mut data = Vec::with_capacity(2 + audio_specific_config.len());
         // FLV audio tag header: AAC (0xA=10 shifted left 4), 44100Hz (3<<2), stereo (1<<1), 16-bit (1)
         // = 0xAF
@@ -3,10 +3,12 @@
 //! High-level API for pulling streams from RTMP servers.
 
 use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
 
 use crate::error::Result;
-use crate::media::{AacData, FlvTag, H264Data};
+use crate::media::{AudioFrame, FlvTag, H264Data};
 use crate::protocol::message::RtmpMessage;
+use crate::transport::Transport;
 
 use super::config::ClientConfig;
 use super::connector::RtmpConnector;
@@ -18,13 +20,13 @@ pub enum ClientEvent {
     Connected,
 
     /// Stream metadata received
-    Metadata(std::collections::HashMap<String, crate::amf::AmfValue>),
+    Metadata(crate::amf::AmfObject),
 
     /// Video frame received
     VideoFrame { timestamp: u32, data: H264Data },
 
     /// Audio frame received
-    AudioFrame { timestamp: u32, data: AacData },
+    AudioFrame { timestamp: u32, data: AudioFrame },
 
     /// Raw video tag (if configured)
     VideoTag(FlvTag),
@@ -70,12 +72,41 @@ impl RtmpPuller {
         self.event_rx.take()
     }
 
+    /// Pull a stream in the background, as a stream of events
+    ///
+    /// A failure to connect or play ends the stream with
+    /// [`ClientEvent::Error`]. Must be called within a Tokio runtime. The
+    /// receiver from [`Self::new`] can be wrapped the same way with
+    /// [`ReceiverStream::new`].
+    pub fn stream(config: ClientConfig) -> ReceiverStream<ClientEvent> {
+        let (puller, rx) = Self::new(config);
+        let tx = puller.event_tx.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = puller.start().await {
+                let _ = tx.send(ClientEvent::Error(e.to_string())).await;
+            }
+        });
+
+        ReceiverStream::new(rx)
+    }
+
     /// Start pulling the stream
     pub async fn start(&self) -> Result<()> {
-        let tx = self.event_tx.clone();
+        let connector = RtmpConnector::connect(self.config.clone()).await?;
+        self.run(connector).await
+    }
+
+    /// Start pulling the stream over an established transport
+    pub async fn start_with_transport<S: Transport>(&self, transport: S) -> Result<()> {
+        let connector =
+            RtmpConnector::connect_with_transport(self.config.clone(), transport).await?;
+        self.run(connector).await
+    }
 
-        // Connect
-        let mut connector = RtmpConnector::connect(self.config.clone()).await?;
+    /// Play the stream and forward its messages as events
+    async fn run<S: Transport>(&self, mut connector: RtmpConnector<S>) -> Result<()> {
+        let tx = self.event_tx.clone();
         let _ = tx.send(ClientEvent::Connected).await;
 
         // Get stream name from URL
@@ -134,23 +165,21 @@ impl RtmpPuller {
                 let _ = tx.send(ClientEvent::AudioTag(tag)).await;
 
                 // Parse and send frame
-                if data.len() >= 2 && (data[0] >> 4) == 10 {
-                    if let Ok(aac) = AacData::parse(data.slice(1..)) {
-                        let _ = tx
-                            .send(ClientEvent::AudioFrame {
-                                timestamp,
-                                data: aac,
-                            })
-                            .await;
-                    }
+                if let Ok(audio) = AudioFrame::parse(data) {
+                    let _ = tx
+                        .send(ClientEvent::AudioFrame {
+                            timestamp,
+                            data: audio,
+                        })
+                        .await;
                 }
             }
 
-            RtmpMessage::Data(data) | RtmpMessage::DataAmf3(data) => {
-                if data.name == "onMetaData" || data.name == "@setDataFrame" {
-                    if let Some(metadata) = data.values.first().and_then(|v| v.as_object()) {
-                        let _ = tx.send(ClientEvent::Metadata(metadata.clone())).await;
-                    }
+            RtmpMessage::Data(data) | RtmpMessage::DataAmf3(data)
+                if data.name == "onMetaData" || data.name == "@setDataFrame" =>
+            {
+                if let Some(metadata) = data.values.first().and_then(|v| v.as_object()) {
+                    let _ = tx.send(ClientEvent::Metadata(metadata.clone())).await;
                 }
             }
 
@@ -176,3 +205,76 @@ impl RtmpPuller {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use tokio_stream::StreamExt;
+
+    use crate::registry::{BroadcastFrame, StreamKey, StreamRegistry};
+    use crate::server::{RtmpHandler, ServerConfig};
+    use crate::test_util::{spawn_session, wait_until};
+
+    struct Accept;
+
+    impl RtmpHandler for Accept {}
+
+    #[tokio::test]
+    async fn test_event_stream_counts_frames() {
+        let registry = Arc::new(StreamRegistry::new());
+        let key = StreamKey::new("live", "test");
+        registry.register_publisher(&key, 99).await.unwrap();
+
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(Accept),
+            registry.clone(),
+        );
+
+        let (puller, rx) = RtmpPuller::new(ClientConfig::new("rtmp://localhost/live/test"));
+        tokio::spawn(async move { puller.start_with_transport(client_side).await });
+        let mut events = ReceiverStream::new(rx);
+
+        // Frames sent before the subscription would be lost
+        assert!(matches!(events.next().await, Some(ClientEvent::Connected)));
+        wait_until(std::time::Duration::from_secs(5), || async {
+            registry
+                .get_stream_stats(&key)
+                .await
+                .unwrap()
+                .subscriber_count
+                > 0
+        })
+        .await;
+
+        let video = |ts: u32, keyframe: bool| {
+            let first = if keyframe { 0x17 } else { 0x27 };
+            BroadcastFrame::video(ts, Bytes::from(vec![first, 0x01, 0, 0, 0]), keyframe, false)
+        };
+        registry.broadcast(&key, video(0, true)).await;
+        for n in 1..5 {
+            registry
+                .broadcast(
+                    &key,
+                    BroadcastFrame::audio(n * 20, Bytes::from_static(&[0xAF, 0x01, 0x21]), false),
+                )
+                .await;
+            registry.broadcast(&key, video(n * 33, false)).await;
+        }
+
+        let frames: Vec<u32> = events
+            .filter_map(|event| match event {
+                ClientEvent::VideoFrame { timestamp, .. } => Some(timestamp),
+                _ => None,
+            })
+            .take(5)
+            .collect()
+            .await;
+        assert_eq!(frames, [0, 33, 66, 99, 132]);
+    }
+}
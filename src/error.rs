@@ -19,6 +19,8 @@ pub enum Error {
     Handshake(HandshakeError),
     /// Media parsing error
     Media(MediaError),
+    /// Client command failure
+    Client(ClientError),
     /// Connection rejected by peer or handler
     Rejected(String),
     /// Operation timed out
@@ -37,6 +39,7 @@ impl fmt::Display for Error {
             Error::Amf(e) => write!(f, "AMF error: {}", e),
             Error::Handshake(e) => write!(f, "Handshake error: {}", e),
             Error::Media(e) => write!(f, "Media error: {}", e),
+            Error::Client(e) => write!(f, "Client error: {}", e),
             Error::Rejected(msg) => write!(f, "Connection rejected: {}", msg),
             Error::Timeout => write!(f, "Operation timed out"),
             Error::ConnectionClosed => write!(f, "Connection closed"),
@@ -84,6 +87,12 @@ impl From<MediaError> for Error {
     }
 }
 
+impl From<ClientError> for Error {
+    fn from(err: ClientError) -> Self {
+        Error::Client(err)
+    }
+}
+
 /// Protocol-level errors
 #[derive(Debug)]
 pub enum ProtocolError {
@@ -95,6 +104,7 @@ pub enum ProtocolError {
     MissingField(String),
     InvalidCommand(String),
     StreamNotFound(u32),
+    InvalidProxyHeader(String),
 }
 
 impl fmt::Display for ProtocolError {
@@ -110,6 +120,9 @@ impl fmt::Display for ProtocolError {
             ProtocolError::MissingField(field) => write!(f, "Missing required field: {}", field),
             ProtocolError::InvalidCommand(cmd) => write!(f, "Invalid command: {}", cmd),
             ProtocolError::StreamNotFound(id) => write!(f, "Stream not found: {}", id),
+            ProtocolError::InvalidProxyHeader(reason) => {
+                write!(f, "Invalid PROXY protocol header: {}", reason)
+            }
         }
     }
 }
@@ -125,6 +138,26 @@ pub enum AmfError {
     InvalidReference(u16),
     NestingTooDeep,
     InvalidObjectEnd,
+    /// A declared array/byte-array length exceeds the configured cap or
+    /// cannot possibly fit in the remaining buffer
+    LengthExceeded {
+        declared: usize,
+        limit: usize,
+    },
+    /// Input is longer than the `DecodeLimits` allow
+    InputTooLarge {
+        size: usize,
+        limit: usize,
+    },
+    /// Decoded values would exceed the `DecodeLimits` output size
+    OutputTooLarge {
+        limit: usize,
+    },
+    /// A value could not be converted to the requested Rust type
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
 }
 
 impl fmt::Display for AmfError {
@@ -136,12 +169,131 @@ impl fmt::Display for AmfError {
             AmfError::InvalidReference(idx) => write!(f, "Invalid AMF reference: {}", idx),
             AmfError::NestingTooDeep => write!(f, "AMF nesting too deep"),
             AmfError::InvalidObjectEnd => write!(f, "Invalid object end marker"),
+            AmfError::LengthExceeded { declared, limit } => {
+                write!(
+                    f,
+                    "AMF declared length {} exceeds limit {}",
+                    declared, limit
+                )
+            }
+            AmfError::InputTooLarge { size, limit } => {
+                write!(f, "AMF input of {} bytes exceeds limit {}", size, limit)
+            }
+            AmfError::OutputTooLarge { limit } => {
+                write!(f, "Decoded AMF output exceeds limit {}", limit)
+            }
+            AmfError::TypeMismatch { expected, found } => {
+                write!(f, "Expected AMF {}, found {}", expected, found)
+            }
         }
     }
 }
 
 impl std::error::Error for AmfError {}
 
+/// Where in its input a decode error occurred
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// Byte offset into the input
+    pub offset: usize,
+    /// Input bytes around the offset
+    pub bytes: Vec<u8>,
+    /// Offset of the first of `bytes` in the input
+    pub bytes_start: usize,
+}
+
+impl ErrorContext {
+    /// Bytes kept on either side of the offset
+    const RADIUS: usize = 8;
+
+    pub(crate) fn new(input: &[u8], offset: usize) -> Self {
+        let offset = offset.min(input.len());
+        let start = offset.saturating_sub(Self::RADIUS);
+        let end = (offset + Self::RADIUS).min(input.len());
+        Self {
+            offset,
+            bytes: input[start..end].to_vec(),
+            bytes_start: start,
+        }
+    }
+
+    /// Hex dump of the surrounding bytes
+    ///
+    /// The byte at the offset is bracketed; an offset at the end of the
+    /// input shows as `[<eof>]`.
+    pub fn hex_dump(&self) -> String {
+        let at = self.offset - self.bytes_start;
+        let mut parts: Vec<String> = self
+            .bytes
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                if i == at {
+                    format!("[{:02x}]", b)
+                } else {
+                    format!("{:02x}", b)
+                }
+            })
+            .collect();
+        if at == self.bytes.len() {
+            parts.push("[<eof>]".to_string());
+        }
+        parts.join(" ")
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "offset {}: {}", self.offset, self.hex_dump())
+    }
+}
+
+/// A decode error and where in its input it occurred
+///
+/// Returned by the decoders' `*_with_context` methods. The offset is into
+/// the buffer that was passed in.
+#[derive(Debug)]
+pub struct DecodeError<E> {
+    kind: E,
+    context: ErrorContext,
+}
+
+impl<E> DecodeError<E> {
+    pub(crate) fn new(kind: E, input: &[u8], offset: usize) -> Self {
+        Self {
+            kind,
+            context: ErrorContext::new(input, offset),
+        }
+    }
+
+    /// The underlying error
+    pub fn kind(&self) -> &E {
+        &self.kind
+    }
+
+    /// Where in the input the error occurred
+    pub fn context(&self) -> &ErrorContext {
+        &self.context
+    }
+
+    /// Drop the context, keeping the underlying error
+    pub fn into_kind(self) -> E {
+        self.kind
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for DecodeError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.kind, self.context)
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for DecodeError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.kind)
+    }
+}
+
 /// Handshake-specific errors
 #[derive(Debug)]
 pub enum HandshakeError {
@@ -170,9 +322,25 @@ pub enum MediaError {
     InvalidFlvTag,
     InvalidAvcPacket,
     InvalidAacPacket,
+    InvalidMp3Frame,
     UnsupportedCodec(String),
     InvalidNalu,
     MissingSequenceHeader,
+    /// AVC decoder configuration record declares more SPS data than it holds
+    SpsOverrun,
+    /// AVC decoder configuration record declares more PPS data than it holds
+    PpsOverrun,
+    /// Input is longer than the `DecodeLimits` allow
+    InputTooLarge {
+        size: usize,
+        limit: usize,
+    },
+    /// A packet holds more parameter sets or NAL units than the
+    /// `DecodeLimits` allow
+    LengthExceeded {
+        declared: usize,
+        limit: usize,
+    },
     /// Invalid enhanced video packet (E-RTMP)
     InvalidEnhancedVideoPacket,
     /// Invalid enhanced audio packet (E-RTMP)
@@ -189,9 +357,18 @@ impl fmt::Display for MediaError {
             MediaError::InvalidFlvTag => write!(f, "Invalid FLV tag"),
             MediaError::InvalidAvcPacket => write!(f, "Invalid AVC packet"),
             MediaError::InvalidAacPacket => write!(f, "Invalid AAC packet"),
+            MediaError::InvalidMp3Frame => write!(f, "Invalid MP3 frame"),
             MediaError::UnsupportedCodec(c) => write!(f, "Unsupported codec: {}", c),
             MediaError::InvalidNalu => write!(f, "Invalid NAL unit"),
             MediaError::MissingSequenceHeader => write!(f, "Missing sequence header"),
+            MediaError::SpsOverrun => write!(f, "SPS list overruns AVC decoder config"),
+            MediaError::PpsOverrun => write!(f, "PPS list overruns AVC decoder config"),
+            MediaError::InputTooLarge { size, limit } => {
+                write!(f, "Media input of {} bytes exceeds limit {}", size, limit)
+            }
+            MediaError::LengthExceeded { declared, limit } => {
+                write!(f, "Media packet count {} exceeds limit {}", declared, limit)
+            }
             MediaError::InvalidEnhancedVideoPacket => write!(f, "Invalid enhanced video packet"),
             MediaError::InvalidEnhancedAudioPacket => write!(f, "Invalid enhanced audio packet"),
             MediaError::UnsupportedVideoCodec => write!(f, "Unsupported video codec FOURCC"),
@@ -202,6 +379,70 @@ impl fmt::Display for MediaError {
 
 impl std::error::Error for MediaError {}
 
+/// AVCC framing errors from
+/// [`NaluIterator::next_checked`](crate::media::h264::NaluIterator::next_checked)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NaluError {
+    /// Bytes remain, but fewer than a length prefix
+    TruncatedLength { offset: usize, remaining: usize },
+    /// A NAL unit's length runs past the end of the data
+    TruncatedNalu {
+        offset: usize,
+        length: usize,
+        remaining: usize,
+    },
+}
+
+impl fmt::Display for NaluError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NaluError::TruncatedLength { offset, remaining } => write!(
+                f,
+                "Truncated NAL unit length at offset {}: {} bytes left",
+                offset, remaining
+            ),
+            NaluError::TruncatedNalu {
+                offset,
+                length,
+                remaining,
+            } => write!(
+                f,
+                "NAL unit at offset {} declares {} bytes, {} left",
+                offset, length, remaining
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NaluError {}
+
+/// Client-side command errors
+#[derive(Debug)]
+pub enum ClientError {
+    /// The server sent no response to a command within `command_timeout`
+    CommandTimeout {
+        command: String,
+        transaction_id: f64,
+    },
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::CommandTimeout {
+                command,
+                transaction_id,
+            } => write!(
+                f,
+                "No response to {} (transaction {}) before timeout",
+                command, transaction_id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -235,6 +476,14 @@ mod tests {
         assert!(err.to_string().contains("Media error"));
         assert!(err.to_string().contains("VP9"));
 
+        // Test Error::Client display
+        let err = Error::Client(ClientError::CommandTimeout {
+            command: "createStream".into(),
+            transaction_id: 2.0,
+        });
+        assert!(err.to_string().contains("Client error"));
+        assert!(err.to_string().contains("createStream"));
+
         // Test Error::Rejected display
         let err = Error::Rejected("stream key invalid".into());
         assert!(err.to_string().contains("Connection rejected"));
@@ -294,6 +543,14 @@ mod tests {
         let media_err = MediaError::InvalidFlvTag;
         let err: Error = media_err.into();
         assert!(matches!(err, Error::Media(_)));
+
+        // Test From<ClientError>
+        let client_err = ClientError::CommandTimeout {
+            command: "play".into(),
+            transaction_id: 0.0,
+        };
+        let err: Error = client_err.into();
+        assert!(matches!(err, Error::Client(_)));
     }
 
     #[test]
@@ -345,6 +602,45 @@ mod tests {
         assert!(AmfError::NestingTooDeep.to_string().contains("deep"));
 
         assert!(AmfError::InvalidObjectEnd.to_string().contains("end"));
+
+        let err = AmfError::LengthExceeded {
+            declared: 4_000_000_000,
+            limit: 16,
+        };
+        assert!(err.to_string().contains("4000000000"));
+        assert!(err.to_string().contains("16"));
+
+        let err = AmfError::InputTooLarge {
+            size: 2048,
+            limit: 1024,
+        };
+        assert!(err.to_string().contains("2048"));
+        assert!(AmfError::OutputTooLarge { limit: 512 }
+            .to_string()
+            .contains("512"));
+    }
+
+    #[test]
+    fn test_error_context() {
+        let input: Vec<u8> = (0..20).collect();
+        let err = DecodeError::new(AmfError::UnexpectedEof, &input, 10);
+        let context = err.context();
+        assert_eq!(context.offset, 10);
+        assert_eq!(context.bytes_start, 2);
+        assert_eq!(
+            context.hex_dump(),
+            "02 03 04 05 06 07 08 09 [0a] 0b 0c 0d 0e 0f 10 11"
+        );
+        assert!(matches!(err.kind(), AmfError::UnexpectedEof));
+        assert_eq!(
+            err.to_string(),
+            "Unexpected end of AMF data at offset 10: \
+             02 03 04 05 06 07 08 09 [0a] 0b 0c 0d 0e 0f 10 11"
+        );
+        assert!(matches!(err.into_kind(), AmfError::UnexpectedEof));
+
+        let err = DecodeError::new(AmfError::UnexpectedEof, &input, 20);
+        assert_eq!(err.context().hex_dump(), "0c 0d 0e 0f 10 11 12 13 [<eof>]");
     }
 
     #[test]
@@ -369,6 +665,7 @@ mod tests {
         assert!(MediaError::InvalidFlvTag.to_string().contains("FLV"));
         assert!(MediaError::InvalidAvcPacket.to_string().contains("AVC"));
         assert!(MediaError::InvalidAacPacket.to_string().contains("AAC"));
+        assert!(MediaError::InvalidMp3Frame.to_string().contains("MP3"));
         assert!(MediaError::UnsupportedCodec("HEVC".into())
             .to_string()
             .contains("HEVC"));
@@ -376,6 +673,20 @@ mod tests {
         assert!(MediaError::MissingSequenceHeader
             .to_string()
             .contains("sequence"));
+        assert!(MediaError::SpsOverrun.to_string().contains("SPS"));
+        assert!(MediaError::PpsOverrun.to_string().contains("PPS"));
+        assert!(MediaError::InputTooLarge {
+            size: 2048,
+            limit: 1024
+        }
+        .to_string()
+        .contains("2048"));
+        assert!(MediaError::LengthExceeded {
+            declared: 300,
+            limit: 8
+        }
+        .to_string()
+        .contains("300"));
         assert!(MediaError::InvalidEnhancedVideoPacket
             .to_string()
             .contains("enhanced video"));
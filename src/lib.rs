@@ -38,19 +38,30 @@
 pub mod amf;
 pub mod client;
 pub mod error;
+pub mod limits;
 pub mod media;
 pub mod protocol;
 pub mod registry;
 pub mod server;
 pub mod session;
 pub mod stats;
+pub mod transport;
+
+#[cfg(feature = "serde")]
+mod serde_util;
+
+#[cfg(test)]
+mod test_util;
 
 // Re-export main types for convenience
 pub use client::config::ClientConfig;
 pub use client::connector::RtmpConnector;
 pub use client::puller::{ClientEvent, RtmpPuller};
 pub use error::{Error, Result};
-pub use registry::{BroadcastFrame, RegistryConfig, StreamKey, StreamRegistry};
+pub use limits::DecodeLimits;
+pub use registry::{BroadcastFrame, CatchupStrategy, RegistryConfig, StreamKey, StreamRegistry};
 pub use server::config::ServerConfig;
-pub use server::handler::{AuthResult, RtmpHandler};
+pub use server::handler::{AuthResult, DisconnectReason, RtmpHandler};
 pub use server::listener::RtmpServer;
+pub use server::record::RecordConfig;
+pub use session::SessionRegistry;
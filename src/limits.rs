@@ -0,0 +1,130 @@
+//! Resource limits for decoding untrusted input
+//!
+//! The regular decoders are tuned for well-behaved encoders. The
+//! `decode_checked` entry points take a [`DecodeLimits`] and fail with a
+//! typed error as soon as a bound is crossed, so input from an unknown peer
+//! (or a fuzzer) cannot make them run long or allocate without bound.
+//!
+//! | Limit                | AMF0 / AMF3                        | Media parsers                 |
+//! |----------------------|------------------------------------|-------------------------------|
+//! | `max_input_size`     | `AmfError::InputTooLarge`          | `MediaError::InputTooLarge`   |
+//! | `max_depth`          | `AmfError::NestingTooDeep`         | not applicable                |
+//! | `max_collection_len` | `AmfError::LengthExceeded`         | `MediaError::LengthExceeded`  |
+//! | `max_string_len`     | `AmfError::LengthExceeded`         | not applicable                |
+//! | `max_output_size`    | `AmfError::OutputTooLarge`         | bounded by input (see below)  |
+//!
+//! The media parsers hand out slices of their input rather than copies, so
+//! their output can never exceed `max_input_size`. AMF output can: references
+//! let a few input bytes repeat an already decoded value, which is why it is
+//! accounted separately.
+
+use crate::error::MediaError;
+use crate::protocol::constants::MAX_MESSAGE_SIZE;
+
+/// Default cap on the declared length of an AMF string in bytes
+///
+/// Also applied by the regular AMF decoders.
+pub const DEFAULT_MAX_STRING_LEN: usize = 4 * 1024 * 1024;
+
+/// Default cap on the approximate size of decoded AMF values
+///
+/// Also applied by the regular AMF decoders, so a reference-heavy message
+/// cannot expand without bound.
+pub const DEFAULT_MAX_OUTPUT_SIZE: usize = 64 * 1024 * 1024;
+
+/// Bounds enforced by the `decode_checked` entry points
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Maximum input length in bytes
+    pub max_input_size: usize,
+
+    /// Maximum nesting depth of AMF objects and arrays
+    pub max_depth: usize,
+
+    /// Maximum declared element count of any collection
+    ///
+    /// Applies to AMF arrays and sealed trait properties, and to the number
+    /// of parameter sets or NAL units in a video packet.
+    pub max_collection_len: usize,
+
+    /// Maximum declared length in bytes of an AMF string or XML document
+    pub max_string_len: usize,
+
+    /// Maximum approximate size in bytes of the decoded values
+    ///
+    /// Each AMF value counts as the size of [`AmfValue`](crate::amf::AmfValue)
+    /// plus the length of any string or byte data it owns. Values reached
+    /// through references count again every time they are referenced.
+    pub max_output_size: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_input_size: MAX_MESSAGE_SIZE as usize,
+            max_depth: 64,
+            max_collection_len: 64 * 1024,
+            max_string_len: DEFAULT_MAX_STRING_LEN,
+            max_output_size: DEFAULT_MAX_OUTPUT_SIZE,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// Create limits with default values
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum input length
+    pub fn max_input_size(mut self, size: usize) -> Self {
+        self.max_input_size = size;
+        self
+    }
+
+    /// Set the maximum nesting depth
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Set the maximum declared collection length
+    pub fn max_collection_len(mut self, len: usize) -> Self {
+        self.max_collection_len = len;
+        self
+    }
+
+    /// Set the maximum declared AMF string length
+    pub fn max_string_len(mut self, len: usize) -> Self {
+        self.max_string_len = len;
+        self
+    }
+
+    /// Set the maximum decoded output size
+    pub fn max_output_size(mut self, size: usize) -> Self {
+        self.max_output_size = size;
+        self
+    }
+
+    /// Reject media input longer than `max_input_size`
+    pub(crate) fn check_media_input(&self, len: usize) -> Result<(), MediaError> {
+        if len > self.max_input_size {
+            return Err(MediaError::InputTooLarge {
+                size: len,
+                limit: self.max_input_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reject media packets holding more than `max_collection_len` entries
+    pub(crate) fn check_media_len(&self, declared: usize) -> Result<(), MediaError> {
+        if declared > self.max_collection_len {
+            return Err(MediaError::LengthExceeded {
+                declared,
+                limit: self.max_collection_len,
+            });
+        }
+        Ok(())
+    }
+}
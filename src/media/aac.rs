@@ -17,6 +17,9 @@
 use bytes::{Buf, Bytes};
 
 use crate::error::{MediaError, Result};
+use crate::limits::DecodeLimits;
+
+use super::h264::BitReader;
 
 /// AAC packet type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -82,11 +85,11 @@ impl AacProfile {
 /// AudioSpecificConfig (from sequence header)
 #[derive(Debug, Clone)]
 pub struct AudioSpecificConfig {
-    /// Audio object type (profile)
+    /// Audio object type (profile) of the core coder
     pub audio_object_type: u8,
     /// Sampling frequency index
     pub sampling_frequency_index: u8,
-    /// Sampling frequency in Hz
+    /// Sampling frequency of the core coder in Hz
     pub sampling_frequency: u32,
     /// Channel configuration (1=mono, 2=stereo, etc.)
     pub channel_configuration: u8,
@@ -96,6 +99,10 @@ pub struct AudioSpecificConfig {
     pub depends_on_core_coder: bool,
     /// Extension flag
     pub extension_flag: bool,
+    /// Extension audio object type (5 = SBR), if signaled
+    pub extension_audio_object_type: Option<u8>,
+    /// Output sampling frequency of the SBR extension in Hz, if signaled
+    pub extension_sampling_frequency: Option<u32>,
     /// Raw config bytes
     pub raw: Bytes,
 }
@@ -108,55 +115,61 @@ impl AudioSpecificConfig {
     ];
 
     /// Parse from AAC sequence header data
+    ///
+    /// Recognizes both ways HE-AAC signals SBR: explicitly, with an SBR or
+    /// PS object type wrapping the core object type, and backward
+    /// compatibly, with a sync extension trailing the core config.
     pub fn parse(data: Bytes) -> Result<Self> {
         if data.len() < 2 {
             return Err(MediaError::InvalidAacPacket.into());
         }
 
-        // AudioSpecificConfig is bit-packed
-        // audioObjectType: 5 bits
+        // AudioSpecificConfig is bit-packed (ISO/IEC 14496-3 1.6.2.1)
+        // audioObjectType: 5 bits (31 = escape, 6 more bits follow)
         // samplingFrequencyIndex: 4 bits
         // if (samplingFrequencyIndex == 0xf) samplingFrequency: 24 bits
         // channelConfiguration: 4 bits
-        // ... more optional fields
-
-        let b0 = data[0];
-        let b1 = data[1];
+        // if (audioObjectType == 5 || 29) extension frequency + core type
+        // GASpecificConfig, then an optional sync extension
+        let mut r = BitReader::new(&data);
+        let mut config = read_core_config(&mut r).ok_or(MediaError::InvalidAacPacket)?;
+
+        // Channel configuration 0 lists the channels in a program config
+        // element. Configs that stop short of it are still accepted.
+        let program_located = config.channel_configuration != 0
+            || read_program_channels(&mut r, data.len()).is_some();
+
+        // Backward compatible signaling, which can only be located when
+        // everything before it was parsed
+        if config.extension_audio_object_type.is_none() && program_located {
+            if let Some(frequency) =
+                read_sync_extension(&mut r, config.audio_object_type, config.extension_flag)
+            {
+                config.extension_audio_object_type = Some(5);
+                config.extension_sampling_frequency = Some(frequency);
+            }
+        }
 
-        let audio_object_type = (b0 >> 3) & 0x1F;
-        let sampling_frequency_index = ((b0 & 0x07) << 1) | ((b1 >> 7) & 0x01);
+        config.raw = data;
+        Ok(config)
+    }
 
-        let sampling_frequency = if sampling_frequency_index == 0x0F {
-            // Explicit frequency in next 24 bits
-            if data.len() < 5 {
-                return Err(MediaError::InvalidAacPacket.into());
-            }
-            let f0 = (data[1] & 0x7F) as u32;
-            let f1 = data[2] as u32;
-            let f2 = data[3] as u32;
-            let f3 = (data[4] >> 1) as u32;
-            (f0 << 17) | (f1 << 9) | (f2 << 1) | f3
-        } else if (sampling_frequency_index as usize) < Self::SAMPLING_FREQUENCIES.len() {
-            Self::SAMPLING_FREQUENCIES[sampling_frequency_index as usize]
-        } else {
-            return Err(MediaError::InvalidAacPacket.into());
-        };
+    /// Check if SBR (HE-AAC) is signaled
+    pub fn has_sbr(&self) -> bool {
+        self.extension_audio_object_type == Some(5)
+    }
 
-        let channel_configuration = (b1 >> 3) & 0x0F;
-        let frame_length_flag = (b1 & 0x04) != 0;
-        let depends_on_core_coder = (b1 & 0x02) != 0;
-        let extension_flag = (b1 & 0x01) != 0;
+    /// Sampling frequency of the core coder in Hz
+    pub fn core_sample_rate(&self) -> u32 {
+        self.sampling_frequency
+    }
 
-        Ok(AudioSpecificConfig {
-            audio_object_type,
-            sampling_frequency_index,
-            sampling_frequency,
-            channel_configuration,
-            frame_length_flag,
-            depends_on_core_coder,
-            extension_flag,
-            raw: data,
-        })
+    /// Sampling frequency of the decoded output in Hz
+    ///
+    /// For HE-AAC this is the SBR rate, usually double the core rate.
+    pub fn output_sample_rate(&self) -> u32 {
+        self.extension_sampling_frequency
+            .unwrap_or(self.sampling_frequency)
     }
 
     /// Get the profile
@@ -165,20 +178,36 @@ impl AudioSpecificConfig {
     }
 
     /// Get channel count
+    ///
+    /// 0 if unknown: a reserved channel configuration, or configuration 0
+    /// without a parsable program config element.
     pub fn channels(&self) -> u8 {
         match self.channel_configuration {
-            0 => 0, // Defined in stream
-            1 => 1, // Mono
-            2 => 2, // Stereo
-            3 => 3, // 3.0
-            4 => 4, // 4.0
-            5 => 5, // 5.0
-            6 => 6, // 5.1
-            7 => 8, // 7.1
+            0 => self.program_channels().unwrap_or(0), // Defined in stream
+            1 => 1,                                    // Mono
+            2 => 2,                                    // Stereo
+            3 => 3,                                    // 3.0
+            4 => 4,                                    // 4.0
+            5 => 5,                                    // 5.0
+            6 => 6,                                    // 5.1
+            7 => 8,                                    // 7.1
             _ => 0,
         }
     }
 
+    /// Channel count declared by the program config element
+    ///
+    /// None unless the channel configuration is 0 and the element can be
+    /// read from the raw config bytes.
+    pub fn program_channels(&self) -> Option<u8> {
+        if self.channel_configuration != 0 {
+            return None;
+        }
+        let mut r = BitReader::new(&self.raw);
+        read_core_config(&mut r)?;
+        read_program_channels(&mut r, self.raw.len())
+    }
+
     /// Get samples per frame
     pub fn samples_per_frame(&self) -> u32 {
         if self.frame_length_flag {
@@ -189,6 +218,140 @@ impl AudioSpecificConfig {
     }
 }
 
+/// Read the config up to the program config element, leaving `raw` empty
+fn read_core_config(r: &mut BitReader<'_>) -> Option<AudioSpecificConfig> {
+    let mut audio_object_type = read_object_type(r)?;
+    let (sampling_frequency_index, sampling_frequency) = read_frequency(r)?;
+    let channel_configuration = r.read_bits(4)? as u8;
+
+    let mut extension_audio_object_type = None;
+    let mut extension_sampling_frequency = None;
+
+    // Explicit hierarchical signaling: SBR (5) or PS (29) wraps the core
+    if audio_object_type == 5 || audio_object_type == 29 {
+        extension_audio_object_type = Some(5);
+        extension_sampling_frequency = Some(read_frequency(r)?.1);
+        audio_object_type = read_object_type(r)?;
+        if audio_object_type == 22 {
+            // extensionChannelConfiguration
+            r.skip_bits(4)?;
+        }
+    }
+
+    let frame_length_flag = r.read_bit()?;
+    let depends_on_core_coder = r.read_bit()?;
+    if depends_on_core_coder {
+        r.skip_bits(14)?; // coreCoderDelay
+    }
+    let extension_flag = r.read_bit()?;
+
+    Some(AudioSpecificConfig {
+        audio_object_type,
+        sampling_frequency_index,
+        sampling_frequency,
+        channel_configuration,
+        frame_length_flag,
+        depends_on_core_coder,
+        extension_flag,
+        extension_audio_object_type,
+        extension_sampling_frequency,
+        raw: Bytes::new(),
+    })
+}
+
+/// Read an audio object type, following the escape value 31
+fn read_object_type(r: &mut BitReader<'_>) -> Option<u8> {
+    let object_type = r.read_bits(5)? as u8;
+    if object_type == 31 {
+        Some(32 + r.read_bits(6)? as u8)
+    } else {
+        Some(object_type)
+    }
+}
+
+/// Read a sampling frequency index and the frequency it stands for
+fn read_frequency(r: &mut BitReader<'_>) -> Option<(u8, u32)> {
+    let index = r.read_bits(4)? as u8;
+    let frequency = if index == 0x0F {
+        // Explicit frequency in the next 24 bits
+        r.read_bits(24)?
+    } else {
+        AudioSpecificConfig::SAMPLING_FREQUENCIES[index as usize]
+    };
+    Some((index, frequency))
+}
+
+/// Read a program config element and count the channels it declares
+///
+/// `config_len` is the AudioSpecificConfig size, which the element's
+/// byte alignment is relative to. Returns None if the element is cut off.
+fn read_program_channels(r: &mut BitReader<'_>, config_len: usize) -> Option<u8> {
+    r.skip_bits(10)?; // element_instance_tag, object_type, sampling_frequency_index
+    let front = r.read_bits(4)?;
+    let side = r.read_bits(4)?;
+    let back = r.read_bits(4)?;
+    let lfe = r.read_bits(2)?;
+    let assoc_data = r.read_bits(3)?;
+    let valid_cc = r.read_bits(4)?;
+
+    // Mono and stereo mixdown element numbers
+    for _ in 0..2 {
+        if r.read_bit()? {
+            r.skip_bits(4)?;
+        }
+    }
+    if r.read_bit()? {
+        r.skip_bits(3)?; // matrix_mixdown_idx, pseudo_surround_enable
+    }
+
+    // Front, side and back elements are single (1) or channel pairs (2)
+    let mut channels = lfe;
+    for _ in 0..front + side + back {
+        channels += if r.read_bit()? { 2 } else { 1 };
+        r.skip_bits(4)?; // element_tag_select
+    }
+    r.skip_bits(4 * (lfe + assoc_data) as usize)?;
+    r.skip_bits(5 * valid_cc as usize)?;
+
+    let position = config_len * 8 - r.bits_left();
+    r.skip_bits((8 - position % 8) % 8)?;
+    let comment_len = r.read_bits(8)?;
+    r.skip_bits(8 * comment_len as usize)?;
+
+    Some(channels as u8)
+}
+
+/// Skip the rest of GASpecificConfig and read an SBR sync extension
+///
+/// Returns the SBR output frequency if the extension is present and
+/// flags SBR.
+fn read_sync_extension(
+    r: &mut BitReader<'_>,
+    audio_object_type: u8,
+    extension_flag: bool,
+) -> Option<u32> {
+    if audio_object_type == 6 || audio_object_type == 20 {
+        r.skip_bits(3)?; // layerNr
+    }
+    if extension_flag {
+        if audio_object_type == 22 {
+            r.skip_bits(16)?; // numOfSubFrame, layer_length
+        }
+        if matches!(audio_object_type, 17 | 19 | 20 | 23) {
+            r.skip_bits(3)?; // resilience flags
+        }
+        r.skip_bits(1)?; // extensionFlag3
+    }
+
+    if r.bits_left() < 16 || r.read_bits(11)? != 0x2B7 {
+        return None;
+    }
+    if read_object_type(r)? != 5 || !r.read_bit()? {
+        return None;
+    }
+    Some(read_frequency(r)?.1)
+}
+
 /// Parsed AAC data
 #[derive(Debug, Clone)]
 pub enum AacData {
@@ -221,6 +384,14 @@ impl AacData {
         }
     }
 
+    /// Parse from untrusted input, enforcing `limits`
+    ///
+    /// AAC packets hold no collections, so only the input size applies.
+    pub fn decode_checked(data: Bytes, limits: DecodeLimits) -> Result<Self> {
+        limits.check_media_input(data.len())?;
+        Self::parse(data)
+    }
+
     /// Check if this is a sequence header
     pub fn is_sequence_header(&self) -> bool {
         matches!(self, AacData::SequenceHeader(_))
@@ -280,6 +451,79 @@ mod tests {
         assert_eq!(config.profile(), Some(AacProfile::Lc));
     }
 
+    #[test]
+    fn test_audio_specific_config_explicit_frequency() {
+        // AAC-LC, explicit 44100 Hz, stereo
+        let data = Bytes::from_static(&[0x17, 0x80, 0x56, 0x22, 0x10]);
+
+        let config = AudioSpecificConfig::parse(data).unwrap();
+        assert_eq!(config.sampling_frequency_index, 0x0F);
+        assert_eq!(config.sampling_frequency, 44100);
+        assert_eq!(config.channel_configuration, 2);
+        assert!(!config.has_sbr());
+        assert_eq!(config.output_sample_rate(), 44100);
+    }
+
+    #[test]
+    fn test_audio_specific_config_explicit_sbr() {
+        // HE-AAC: SBR object type, 22050 Hz core, 44100 Hz output, AAC-LC
+        // core coder, stereo
+        let data = Bytes::from_static(&[0x2B, 0x92, 0x08, 0x00]);
+
+        let config = AudioSpecificConfig::parse(data).unwrap();
+        assert_eq!(config.audio_object_type, 2);
+        assert_eq!(config.profile(), Some(AacProfile::Lc));
+        assert_eq!(config.channels(), 2);
+        assert!(config.has_sbr());
+        assert_eq!(config.extension_audio_object_type, Some(5));
+        assert_eq!(config.core_sample_rate(), 22050);
+        assert_eq!(config.output_sample_rate(), 44100);
+    }
+
+    #[test]
+    fn test_audio_specific_config_backward_compatible_sbr() {
+        // AAC-LC 22050 Hz stereo followed by a sync extension (0x2B7)
+        // flagging SBR at 44100 Hz
+        let data = Bytes::from_static(&[0x13, 0x90, 0x56, 0xE5, 0xA0]);
+
+        let config = AudioSpecificConfig::parse(data).unwrap();
+        assert_eq!(config.audio_object_type, 2);
+        assert!(config.has_sbr());
+        assert_eq!(config.core_sample_rate(), 22050);
+        assert_eq!(config.output_sample_rate(), 44100);
+
+        // Without the extension the output rate is the core rate
+        let config = AudioSpecificConfig::parse(Bytes::from_static(&[0x13, 0x90])).unwrap();
+        assert!(!config.has_sbr());
+        assert_eq!(config.output_sample_rate(), 22050);
+    }
+
+    #[test]
+    fn test_audio_specific_config_program_config_element() {
+        // AAC-LC 48000 Hz, channel configuration 0, then a PCE with a
+        // center SCE and a front CPE, a back CPE and one LFE (5.1)
+        let data =
+            Bytes::from_static(&[0x11, 0x80, 0x04, 0xC8, 0x05, 0x00, 0x01, 0x08, 0x80, 0x00]);
+
+        let config = AudioSpecificConfig::parse(data).unwrap();
+        assert_eq!(config.channel_configuration, 0);
+        assert_eq!(config.program_channels(), Some(6));
+        assert_eq!(config.channels(), 6);
+        assert_eq!(config.sampling_frequency, 48000);
+
+        // Without the element the count stays unknown
+        let config = AudioSpecificConfig::parse(Bytes::from_static(&[0x11, 0x80])).unwrap();
+        assert_eq!(config.program_channels(), None);
+        assert_eq!(config.channels(), 0);
+    }
+
+    #[test]
+    fn test_audio_specific_config_truncated_sbr() {
+        // SBR object type with the core object type cut off
+        let data = Bytes::from_static(&[0x2B, 0x92]);
+        assert!(AudioSpecificConfig::parse(data).is_err());
+    }
+
     #[test]
     fn test_adts_header() {
         let config = AudioSpecificConfig {
@@ -290,6 +534,8 @@ mod tests {
             frame_length_flag: false,
             depends_on_core_coder: false,
             extension_flag: false,
+            extension_audio_object_type: None,
+            extension_sampling_frequency: None,
             raw: Bytes::new(),
         };
 
@@ -384,6 +630,8 @@ mod tests {
             frame_length_flag: false,
             depends_on_core_coder: false,
             extension_flag: false,
+            extension_audio_object_type: None,
+            extension_sampling_frequency: None,
             raw: Bytes::new(),
         };
         assert_eq!(config.channels(), 0);
@@ -409,6 +657,8 @@ mod tests {
                 frame_length_flag: false,
                 depends_on_core_coder: false,
                 extension_flag: false,
+                extension_audio_object_type: None,
+                extension_sampling_frequency: None,
                 raw: Bytes::new(),
             };
             assert_eq!(config.channels(), expected_channels);
@@ -425,6 +675,8 @@ mod tests {
             frame_length_flag: false, // 1024 samples
             depends_on_core_coder: false,
             extension_flag: false,
+            extension_audio_object_type: None,
+            extension_sampling_frequency: None,
             raw: Bytes::new(),
         };
         assert_eq!(config_1024.samples_per_frame(), 1024);
@@ -437,6 +689,8 @@ mod tests {
             frame_length_flag: true, // 960 samples
             depends_on_core_coder: false,
             extension_flag: false,
+            extension_audio_object_type: None,
+            extension_sampling_frequency: None,
             raw: Bytes::new(),
         };
         assert_eq!(config_960.samples_per_frame(), 960);
@@ -452,6 +706,8 @@ mod tests {
             frame_length_flag: false,
             depends_on_core_coder: false,
             extension_flag: false,
+            extension_audio_object_type: None,
+            extension_sampling_frequency: None,
             raw: Bytes::new(),
         };
         assert_eq!(config.profile(), Some(AacProfile::Lc));
@@ -464,6 +720,8 @@ mod tests {
             frame_length_flag: false,
             depends_on_core_coder: false,
             extension_flag: false,
+            extension_audio_object_type: None,
+            extension_sampling_frequency: None,
             raw: Bytes::new(),
         };
         assert!(config_unknown.profile().is_none());
@@ -519,6 +777,23 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_aac_data_decode_checked() {
+        let data = Bytes::from_static(&[0x01, 0x21, 0x10, 0x04, 0x60]);
+
+        let limits = DecodeLimits::default().max_input_size(5);
+        assert!(AacData::decode_checked(data.clone(), limits).is_ok());
+
+        let limits = DecodeLimits::default().max_input_size(4);
+        assert!(matches!(
+            AacData::decode_checked(data, limits),
+            Err(crate::error::Error::Media(MediaError::InputTooLarge {
+                size: 5,
+                limit: 4
+            }))
+        ));
+    }
+
     #[test]
     fn test_audio_specific_config_too_short() {
         let data = Bytes::from_static(&[0x12]); // Only 1 byte
@@ -536,6 +811,8 @@ mod tests {
             frame_length_flag: false,
             depends_on_core_coder: false,
             extension_flag: false,
+            extension_audio_object_type: None,
+            extension_sampling_frequency: None,
             raw: Bytes::new(),
         };
 
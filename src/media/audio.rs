@@ -0,0 +1,129 @@
+//! G.711 audio frame parsing
+//!
+//! Legacy RTMP audio messages start with a single format byte:
+//! ```text
+//! +-----------+----------+----------+----------+
+//! |SoundFormat|SoundRate |SoundSize |SoundType | AudioData...
+//! | (4 bits)  | (2 bits) | (1 bit)  | (1 bit)  |
+//! +-----------+----------+----------+----------+
+//! ```
+//!
+//! G.711 has no codec-specific payload, so [`G711Frame`] takes its law and
+//! channel count from this byte. [`AudioFrame`](super::AudioFrame)
+//! dispatches on SoundFormat to deliver G.711 alongside AAC and MP3.
+
+use bytes::Bytes;
+
+use crate::error::{MediaError, Result};
+
+use super::flv::AudioFormat;
+
+/// G.711 companding law
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum G711Law {
+    /// A-law (PCMA)
+    ALaw,
+    /// mu-law (PCMU)
+    MuLaw,
+}
+
+/// A G.711 audio frame
+#[derive(Debug, Clone)]
+pub struct G711Frame {
+    /// Companding law
+    pub law: G711Law,
+    /// Sample rate in Hz
+    pub sample_rate: u32,
+    /// Number of channels
+    pub channels: u8,
+    /// Encoded samples (one byte per sample per channel)
+    pub data: Bytes,
+}
+
+impl G711Frame {
+    /// G.711 sample rate
+    ///
+    /// The 2-bit FLV SoundRate field cannot express 8 kHz, so encoders put
+    /// arbitrary values there. G.711 in FLV is always 8 kHz.
+    pub const SAMPLE_RATE: u32 = 8000;
+
+    /// Parse from RTMP audio data (including the format byte)
+    pub fn parse(data: Bytes) -> Result<Self> {
+        if data.len() < 2 {
+            return Err(MediaError::InvalidFlvTag.into());
+        }
+
+        let b0 = data[0];
+        let law = match AudioFormat::from_byte(b0) {
+            Some(AudioFormat::G711ALaw) => G711Law::ALaw,
+            Some(AudioFormat::G711MuLaw) => G711Law::MuLaw,
+            _ => {
+                return Err(
+                    MediaError::UnsupportedCodec(format!("audio format {}", b0 >> 4)).into(),
+                )
+            }
+        };
+
+        Ok(G711Frame {
+            law,
+            sample_rate: Self::SAMPLE_RATE,
+            channels: if b0 & 0x01 != 0 { 2 } else { 1 },
+            data: data.slice(1..),
+        })
+    }
+
+    /// Duration of this frame in milliseconds
+    pub fn duration_ms(&self) -> u32 {
+        // RTMP messages reach 16 MiB, so milliseconds overflow u32 math
+        let samples = self.data.len() as u64 / self.channels.max(1) as u64;
+        let ms = (samples * 1000)
+            .checked_div(self.sample_rate as u64)
+            .unwrap_or(0);
+        u32::try_from(ms).unwrap_or(u32::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_g711_alaw() {
+        // SoundFormat=7 (A-law), rate=0, 16-bit, mono
+        let mut tag = vec![0x72];
+        tag.extend_from_slice(&[0xD5; 160]);
+
+        let frame = G711Frame::parse(Bytes::from(tag)).unwrap();
+        assert_eq!(frame.law, G711Law::ALaw);
+        assert_eq!(frame.sample_rate, 8000);
+        assert_eq!(frame.channels, 1);
+        assert_eq!(frame.data.len(), 160);
+        assert_eq!(frame.duration_ms(), 20);
+    }
+
+    #[test]
+    fn test_parse_g711_mulaw_stereo() {
+        // SoundFormat=8 (mu-law), stereo
+        let frame = G711Frame::parse(Bytes::from_static(&[0x83, 0xFF, 0xFF, 0x7F, 0x7F])).unwrap();
+        assert_eq!(frame.law, G711Law::MuLaw);
+        assert_eq!(frame.channels, 2);
+        assert_eq!(frame.data.as_ref(), &[0xFF, 0xFF, 0x7F, 0x7F]);
+    }
+
+    #[test]
+    fn test_g711_duration_of_large_message() {
+        // 16 MiB of mono A-law samples
+        let mut tag = vec![0x72];
+        tag.resize(1 + 16 * 1024 * 1024, 0xD5);
+
+        let frame = G711Frame::parse(Bytes::from(tag)).unwrap();
+        assert_eq!(frame.duration_ms(), 2_097_152);
+    }
+
+    #[test]
+    fn test_parse_unsupported() {
+        // AAC is not G.711
+        assert!(G711Frame::parse(Bytes::from_static(&[0xAF, 0x01, 0x21])).is_err());
+        assert!(G711Frame::parse(Bytes::from_static(&[0x72])).is_err());
+    }
+}
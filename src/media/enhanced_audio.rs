@@ -11,6 +11,7 @@ use bytes::{Buf, Bytes};
 
 use crate::error::{MediaError, Result};
 use crate::media::fourcc::AudioFourCc;
+use crate::media::modex::{parse_mod_ex, ModEx};
 
 /// Sound format value that signals enhanced audio mode.
 pub const SOUND_FORMAT_EX_HEADER: u8 = 9;
@@ -107,6 +108,8 @@ pub enum EnhancedAudioData {
         codec: AudioFourCc,
         /// Codec-specific configuration data
         config: Bytes,
+        /// ModEx signals that preceded the packet
+        mod_ex: Vec<ModEx>,
     },
 
     /// Coded audio frame.
@@ -115,12 +118,16 @@ pub enum EnhancedAudioData {
         codec: AudioFourCc,
         /// Frame data
         data: Bytes,
+        /// ModEx signals that preceded the packet
+        mod_ex: Vec<ModEx>,
     },
 
     /// End of sequence marker.
     SequenceEnd {
         /// Audio codec
         codec: AudioFourCc,
+        /// ModEx signals that preceded the packet
+        mod_ex: Vec<ModEx>,
     },
 
     /// Multichannel configuration.
@@ -133,6 +140,8 @@ pub enum EnhancedAudioData {
         channel_count: u8,
         /// Channel mapping (if Custom order)
         channel_mapping: Option<Bytes>,
+        /// ModEx signals that preceded the packet
+        mod_ex: Vec<ModEx>,
     },
 
     /// Multitrack audio container.
@@ -141,6 +150,8 @@ pub enum EnhancedAudioData {
         multitrack_type: AudioMultitrackType,
         /// Individual tracks
         tracks: Vec<AudioTrack>,
+        /// ModEx signals that preceded the packet
+        mod_ex: Vec<ModEx>,
     },
 }
 
@@ -190,16 +201,25 @@ impl EnhancedAudioData {
             return Err(MediaError::InvalidEnhancedAudioPacket.into());
         }
 
-        let packet_type =
+        let mut packet_type =
             AudioPacketType::from_byte(first_byte).ok_or(MediaError::InvalidEnhancedAudioPacket)?;
 
         let mut cursor = data.slice(1..);
 
+        // ModEx signals come first and announce the real packet type
+        let mut mod_ex = Vec::new();
+        if packet_type == AudioPacketType::ModEx {
+            let (signals, next) =
+                parse_mod_ex(&mut cursor).ok_or(MediaError::InvalidEnhancedAudioPacket)?;
+            mod_ex = signals;
+            packet_type =
+                AudioPacketType::from_byte(next).ok_or(MediaError::InvalidEnhancedAudioPacket)?;
+        }
+
         // Handle packet types
         match packet_type {
-            AudioPacketType::ModEx => Self::parse_with_modex(cursor),
-            AudioPacketType::Multitrack => Self::parse_multitrack(cursor),
-            AudioPacketType::MultichannelConfig => Self::parse_multichannel_config(cursor),
+            AudioPacketType::Multitrack => Self::parse_multitrack(cursor, mod_ex),
+            AudioPacketType::MultichannelConfig => Self::parse_multichannel_config(cursor, mod_ex),
             _ => {
                 // Regular enhanced audio: FOURCC follows
                 if cursor.len() < 4 {
@@ -211,44 +231,13 @@ impl EnhancedAudioData {
                     .ok_or(MediaError::UnsupportedAudioCodec)?;
                 cursor.advance(4);
 
-                Self::parse_by_packet_type(packet_type, codec, cursor)
+                Self::parse_by_packet_type(packet_type, codec, cursor, mod_ex)
             }
         }
     }
 
-    /// Parse with ModEx prefix handling.
-    fn parse_with_modex(mut data: Bytes) -> Result<Self> {
-        if data.is_empty() {
-            return Err(MediaError::InvalidEnhancedAudioPacket.into());
-        }
-
-        let modex_type = data[0];
-        data.advance(1);
-
-        // TimestampOffsetNano (type 0) has 3 bytes of nanosecond offset
-        if modex_type == 0 {
-            if data.len() < 3 {
-                return Err(MediaError::InvalidEnhancedAudioPacket.into());
-            }
-            data.advance(3);
-        }
-
-        // After ModEx, parse FOURCC
-        if data.len() < 4 {
-            return Err(MediaError::InvalidEnhancedAudioPacket.into());
-        }
-
-        let fourcc_bytes = [data[0], data[1], data[2], data[3]];
-        if let Some(codec) = AudioFourCc::from_bytes(&fourcc_bytes) {
-            data.advance(4);
-            Ok(EnhancedAudioData::Frame { codec, data })
-        } else {
-            Err(MediaError::InvalidEnhancedAudioPacket.into())
-        }
-    }
-
     /// Parse multichannel configuration.
-    fn parse_multichannel_config(mut data: Bytes) -> Result<Self> {
+    fn parse_multichannel_config(mut data: Bytes, mod_ex: Vec<ModEx>) -> Result<Self> {
         if data.len() < 6 {
             // Need at least FOURCC (4) + channelOrder (1) + channelCount (1)
             return Err(MediaError::InvalidEnhancedAudioPacket.into());
@@ -277,11 +266,12 @@ impl EnhancedAudioData {
             channel_order,
             channel_count,
             channel_mapping,
+            mod_ex,
         })
     }
 
     /// Parse multitrack audio container.
-    fn parse_multitrack(mut data: Bytes) -> Result<Self> {
+    fn parse_multitrack(mut data: Bytes, mod_ex: Vec<ModEx>) -> Result<Self> {
         if data.is_empty() {
             return Err(MediaError::InvalidEnhancedAudioPacket.into());
         }
@@ -384,6 +374,7 @@ impl EnhancedAudioData {
         Ok(EnhancedAudioData::Multitrack {
             multitrack_type,
             tracks,
+            mod_ex,
         })
     }
 
@@ -407,14 +398,20 @@ impl EnhancedAudioData {
         packet_type: AudioPacketType,
         codec: AudioFourCc,
         data: Bytes,
+        mod_ex: Vec<ModEx>,
     ) -> Result<Self> {
         match packet_type {
             AudioPacketType::SequenceStart => Ok(EnhancedAudioData::SequenceHeader {
                 codec,
                 config: data,
+                mod_ex,
+            }),
+            AudioPacketType::SequenceEnd => Ok(EnhancedAudioData::SequenceEnd { codec, mod_ex }),
+            AudioPacketType::CodedFrames => Ok(EnhancedAudioData::Frame {
+                codec,
+                data,
+                mod_ex,
             }),
-            AudioPacketType::SequenceEnd => Ok(EnhancedAudioData::SequenceEnd { codec }),
-            AudioPacketType::CodedFrames => Ok(EnhancedAudioData::Frame { codec, data }),
             _ => Err(MediaError::InvalidEnhancedAudioPacket.into()),
         }
     }
@@ -424,12 +421,23 @@ impl EnhancedAudioData {
         matches!(self, EnhancedAudioData::SequenceHeader { .. })
     }
 
+    /// Get the ModEx signals that preceded the packet.
+    pub fn mod_ex(&self) -> &[ModEx] {
+        match self {
+            EnhancedAudioData::SequenceHeader { mod_ex, .. }
+            | EnhancedAudioData::Frame { mod_ex, .. }
+            | EnhancedAudioData::SequenceEnd { mod_ex, .. }
+            | EnhancedAudioData::MultichannelConfig { mod_ex, .. }
+            | EnhancedAudioData::Multitrack { mod_ex, .. } => mod_ex,
+        }
+    }
+
     /// Get the codec if available.
     pub fn codec(&self) -> Option<AudioFourCc> {
         match self {
             EnhancedAudioData::SequenceHeader { codec, .. } => Some(*codec),
             EnhancedAudioData::Frame { codec, .. } => Some(*codec),
-            EnhancedAudioData::SequenceEnd { codec } => Some(*codec),
+            EnhancedAudioData::SequenceEnd { codec, .. } => Some(*codec),
             EnhancedAudioData::MultichannelConfig { codec, .. } => Some(*codec),
             EnhancedAudioData::Multitrack { .. } => None,
         }
@@ -528,7 +536,7 @@ mod tests {
         let parsed = EnhancedAudioData::parse(Bytes::from(data)).unwrap();
 
         match parsed {
-            EnhancedAudioData::SequenceHeader { codec, config } => {
+            EnhancedAudioData::SequenceHeader { codec, config, .. } => {
                 assert_eq!(codec, AudioFourCc::Opus);
                 assert_eq!(config.as_ref(), &[0x01, 0x02, 0x03, 0x04]);
             }
@@ -547,7 +555,7 @@ mod tests {
         let parsed = EnhancedAudioData::parse(Bytes::from(data)).unwrap();
 
         match parsed {
-            EnhancedAudioData::Frame { codec, data } => {
+            EnhancedAudioData::Frame { codec, data, .. } => {
                 assert_eq!(codec, AudioFourCc::Aac);
                 assert_eq!(data.as_ref(), &[0xAA, 0xBB, 0xCC]);
             }
@@ -564,7 +572,7 @@ mod tests {
         let parsed = EnhancedAudioData::parse(Bytes::from(data)).unwrap();
 
         match parsed {
-            EnhancedAudioData::SequenceEnd { codec } => {
+            EnhancedAudioData::SequenceEnd { codec, .. } => {
                 assert_eq!(codec, AudioFourCc::Flac);
             }
             _ => panic!("Expected SequenceEnd"),
@@ -587,6 +595,7 @@ mod tests {
                 channel_order,
                 channel_count,
                 channel_mapping,
+                ..
             } => {
                 assert_eq!(codec, AudioFourCc::Opus);
                 assert_eq!(channel_order, AudioChannelOrder::Native);
@@ -622,6 +631,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_mod_ex_prefixed_frame() {
+        // 0x97 = SoundFormat(9) | ModEx(7)
+        let mut data = vec![0x97];
+        data.extend_from_slice(&[0x02, 0x00, 0x01, 0xF4]); // 3 bytes: 500ns
+        data.push(0x01); // TimestampOffsetNano, then CodedFrames
+        data.extend_from_slice(b"Opus");
+        data.extend_from_slice(&[0xAA, 0xBB]);
+
+        let parsed = EnhancedAudioData::parse(Bytes::from(data)).unwrap();
+
+        assert_eq!(parsed.mod_ex().len(), 1);
+        assert_eq!(parsed.mod_ex()[0].timestamp_offset_nano(), Some(500));
+        match parsed {
+            EnhancedAudioData::Frame { codec, data, .. } => {
+                assert_eq!(codec, AudioFourCc::Opus);
+                assert_eq!(data.as_ref(), &[0xAA, 0xBB]);
+            }
+            _ => panic!("Expected Frame"),
+        }
+    }
+
     #[test]
     fn test_parse_error_empty() {
         let result = EnhancedAudioData::parse(Bytes::new());
@@ -657,12 +688,14 @@ mod tests {
         let header = EnhancedAudioData::SequenceHeader {
             codec: AudioFourCc::Opus,
             config: Bytes::new(),
+            mod_ex: Vec::new(),
         };
         assert!(header.is_sequence_header());
 
         let frame = EnhancedAudioData::Frame {
             codec: AudioFourCc::Opus,
             data: Bytes::new(),
+            mod_ex: Vec::new(),
         };
         assert!(!frame.is_sequence_header());
     }
@@ -672,12 +705,14 @@ mod tests {
         let header = EnhancedAudioData::SequenceHeader {
             codec: AudioFourCc::Flac,
             config: Bytes::new(),
+            mod_ex: Vec::new(),
         };
         assert_eq!(header.codec(), Some(AudioFourCc::Flac));
 
         let frame = EnhancedAudioData::Frame {
             codec: AudioFourCc::Ac3,
             data: Bytes::new(),
+            mod_ex: Vec::new(),
         };
         assert_eq!(frame.codec(), Some(AudioFourCc::Ac3));
 
@@ -686,12 +721,14 @@ mod tests {
             channel_order: AudioChannelOrder::Native,
             channel_count: 2,
             channel_mapping: None,
+            mod_ex: Vec::new(),
         };
         assert_eq!(multichannel.codec(), Some(AudioFourCc::Opus));
 
         let multitrack = EnhancedAudioData::Multitrack {
             multitrack_type: AudioMultitrackType::OneTrack,
             tracks: vec![],
+            mod_ex: Vec::new(),
         };
         assert_eq!(multitrack.codec(), None);
     }
@@ -12,6 +12,7 @@ use bytes::{Buf, Bytes};
 
 use crate::error::{MediaError, Result};
 use crate::media::fourcc::VideoFourCc;
+use crate::media::modex::{parse_mod_ex, ModEx};
 
 /// Enhanced video packet type (lower 4 bits when isExVideoHeader=1).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -127,6 +128,8 @@ pub enum EnhancedVideoData {
         frame_type: ExVideoFrameType,
         /// Codec-specific configuration data
         config: Bytes,
+        /// ModEx signals that preceded the packet
+        mod_ex: Vec<ModEx>,
     },
 
     /// Coded video frame.
@@ -139,18 +142,24 @@ pub enum EnhancedVideoData {
         composition_time: i32,
         /// Frame data (NALUs for AVC/HEVC, OBUs for AV1, etc.)
         data: Bytes,
+        /// ModEx signals that preceded the packet
+        mod_ex: Vec<ModEx>,
     },
 
     /// End of sequence marker.
     SequenceEnd {
         /// Video codec
         codec: VideoFourCc,
+        /// ModEx signals that preceded the packet
+        mod_ex: Vec<ModEx>,
     },
 
     /// Metadata frame (HDR info, etc.).
     Metadata {
         /// Raw metadata bytes (AMF encoded)
         data: Bytes,
+        /// ModEx signals that preceded the packet
+        mod_ex: Vec<ModEx>,
     },
 
     /// Multitrack video container.
@@ -159,6 +168,8 @@ pub enum EnhancedVideoData {
         multitrack_type: AvMultitrackType,
         /// Individual tracks
         tracks: Vec<VideoTrack>,
+        /// ModEx signals that preceded the packet
+        mod_ex: Vec<ModEx>,
     },
 }
 
@@ -213,20 +224,28 @@ impl EnhancedVideoData {
         let frame_type = ExVideoFrameType::from_byte(first_byte)
             .ok_or(MediaError::InvalidEnhancedVideoPacket)?;
 
-        let packet_type =
+        let mut packet_type =
             VideoPacketType::from_byte(first_byte).ok_or(MediaError::InvalidEnhancedVideoPacket)?;
 
         let mut cursor = data.slice(1..);
 
+        // ModEx signals come first and announce the real packet type
+        let mut mod_ex = Vec::new();
+        if packet_type == VideoPacketType::ModEx {
+            let (signals, next) =
+                parse_mod_ex(&mut cursor).ok_or(MediaError::InvalidEnhancedVideoPacket)?;
+            mod_ex = signals;
+            packet_type =
+                VideoPacketType::from_byte(next).ok_or(MediaError::InvalidEnhancedVideoPacket)?;
+        }
+
         // Handle packet types
         match packet_type {
-            VideoPacketType::ModEx => {
-                // ModEx signals that extensions follow
-                // For now, skip ModEx bytes and parse the actual content
-                Self::parse_with_modex(cursor, frame_type)
-            }
-            VideoPacketType::Multitrack => Self::parse_multitrack(cursor, frame_type),
-            VideoPacketType::Metadata => Ok(EnhancedVideoData::Metadata { data: cursor }),
+            VideoPacketType::Multitrack => Self::parse_multitrack(cursor, frame_type, mod_ex),
+            VideoPacketType::Metadata => Ok(EnhancedVideoData::Metadata {
+                data: cursor,
+                mod_ex,
+            }),
             _ => {
                 // Regular enhanced video: FOURCC follows
                 if cursor.len() < 4 {
@@ -238,56 +257,17 @@ impl EnhancedVideoData {
                     .ok_or(MediaError::UnsupportedVideoCodec)?;
                 cursor.advance(4);
 
-                Self::parse_by_packet_type(packet_type, codec, frame_type, cursor)
-            }
-        }
-    }
-
-    /// Parse with ModEx prefix handling.
-    fn parse_with_modex(mut data: Bytes, frame_type: ExVideoFrameType) -> Result<Self> {
-        // ModEx format: one or more extension bytes followed by actual packet
-        // For now, we just skip the ModEx byte and try to parse what follows
-        // A full implementation would parse TimestampOffsetNano etc.
-
-        if data.is_empty() {
-            return Err(MediaError::InvalidEnhancedVideoPacket.into());
-        }
-
-        let modex_type = data[0];
-        data.advance(1);
-
-        // TimestampOffsetNano (type 0) has 3 bytes of nanosecond offset
-        if modex_type == 0 {
-            if data.len() < 3 {
-                return Err(MediaError::InvalidEnhancedVideoPacket.into());
+                Self::parse_by_packet_type(packet_type, codec, frame_type, cursor, mod_ex)
             }
-            // Skip the 3-byte nanosecond offset for now
-            data.advance(3);
-        }
-
-        // After ModEx data, check if there's another packet type byte or FOURCC
-        if data.len() < 4 {
-            return Err(MediaError::InvalidEnhancedVideoPacket.into());
-        }
-
-        // Try to parse as FOURCC
-        let fourcc_bytes = [data[0], data[1], data[2], data[3]];
-        if let Some(codec) = VideoFourCc::from_bytes(&fourcc_bytes) {
-            data.advance(4);
-            // Assume CodedFramesX after ModEx (composition time = 0)
-            Ok(EnhancedVideoData::Frame {
-                codec,
-                frame_type,
-                composition_time: 0,
-                data,
-            })
-        } else {
-            Err(MediaError::InvalidEnhancedVideoPacket.into())
         }
     }
 
     /// Parse multitrack video container.
-    fn parse_multitrack(mut data: Bytes, _frame_type: ExVideoFrameType) -> Result<Self> {
+    fn parse_multitrack(
+        mut data: Bytes,
+        _frame_type: ExVideoFrameType,
+        mod_ex: Vec<ModEx>,
+    ) -> Result<Self> {
         if data.is_empty() {
             return Err(MediaError::InvalidEnhancedVideoPacket.into());
         }
@@ -402,6 +382,7 @@ impl EnhancedVideoData {
         Ok(EnhancedVideoData::Multitrack {
             multitrack_type,
             tracks,
+            mod_ex,
         })
     }
 
@@ -441,14 +422,16 @@ impl EnhancedVideoData {
         codec: VideoFourCc,
         frame_type: ExVideoFrameType,
         mut data: Bytes,
+        mod_ex: Vec<ModEx>,
     ) -> Result<Self> {
         match packet_type {
             VideoPacketType::SequenceStart => Ok(EnhancedVideoData::SequenceHeader {
                 codec,
                 frame_type,
                 config: data,
+                mod_ex,
             }),
-            VideoPacketType::SequenceEnd => Ok(EnhancedVideoData::SequenceEnd { codec }),
+            VideoPacketType::SequenceEnd => Ok(EnhancedVideoData::SequenceEnd { codec, mod_ex }),
             VideoPacketType::CodedFramesX => {
                 // Composition time is implicitly 0
                 Ok(EnhancedVideoData::Frame {
@@ -456,6 +439,7 @@ impl EnhancedVideoData {
                     frame_type,
                     composition_time: 0,
                     data,
+                    mod_ex,
                 })
             }
             VideoPacketType::CodedFrames => {
@@ -469,6 +453,7 @@ impl EnhancedVideoData {
                     frame_type,
                     composition_time,
                     data,
+                    mod_ex,
                 })
             }
             VideoPacketType::Mpeg2TsSequenceStart => {
@@ -477,6 +462,7 @@ impl EnhancedVideoData {
                     codec,
                     frame_type,
                     config: data,
+                    mod_ex,
                 })
             }
             _ => Err(MediaError::InvalidEnhancedVideoPacket.into()),
@@ -499,12 +485,23 @@ impl EnhancedVideoData {
         matches!(self, EnhancedVideoData::SequenceHeader { .. })
     }
 
+    /// Get the ModEx signals that preceded the packet.
+    pub fn mod_ex(&self) -> &[ModEx] {
+        match self {
+            EnhancedVideoData::SequenceHeader { mod_ex, .. }
+            | EnhancedVideoData::Frame { mod_ex, .. }
+            | EnhancedVideoData::SequenceEnd { mod_ex, .. }
+            | EnhancedVideoData::Metadata { mod_ex, .. }
+            | EnhancedVideoData::Multitrack { mod_ex, .. } => mod_ex,
+        }
+    }
+
     /// Get the codec if available.
     pub fn codec(&self) -> Option<VideoFourCc> {
         match self {
             EnhancedVideoData::SequenceHeader { codec, .. } => Some(*codec),
             EnhancedVideoData::Frame { codec, .. } => Some(*codec),
-            EnhancedVideoData::SequenceEnd { codec } => Some(*codec),
+            EnhancedVideoData::SequenceEnd { codec, .. } => Some(*codec),
             EnhancedVideoData::Metadata { .. } => None,
             EnhancedVideoData::Multitrack { .. } => None,
         }
@@ -660,6 +657,7 @@ mod tests {
                 codec,
                 frame_type,
                 config,
+                ..
             } => {
                 assert_eq!(codec, VideoFourCc::Hevc);
                 assert_eq!(frame_type, ExVideoFrameType::Keyframe);
@@ -685,6 +683,7 @@ mod tests {
                 frame_type,
                 composition_time,
                 data,
+                ..
             } => {
                 assert_eq!(codec, VideoFourCc::Av1);
                 assert_eq!(frame_type, ExVideoFrameType::InterFrame);
@@ -712,6 +711,7 @@ mod tests {
                 frame_type,
                 composition_time,
                 data,
+                ..
             } => {
                 assert_eq!(codec, VideoFourCc::Vp9);
                 assert_eq!(frame_type, ExVideoFrameType::Keyframe);
@@ -752,7 +752,7 @@ mod tests {
         let parsed = EnhancedVideoData::parse(Bytes::from(data)).unwrap();
 
         match parsed {
-            EnhancedVideoData::SequenceEnd { codec } => {
+            EnhancedVideoData::SequenceEnd { codec, .. } => {
                 assert_eq!(codec, VideoFourCc::Hevc);
             }
             _ => panic!("Expected SequenceEnd"),
@@ -768,13 +768,57 @@ mod tests {
         let parsed = EnhancedVideoData::parse(Bytes::from(data)).unwrap();
 
         match parsed {
-            EnhancedVideoData::Metadata { data } => {
+            EnhancedVideoData::Metadata { data, .. } => {
                 assert_eq!(data.as_ref(), &[0x01, 0x02, 0x03]);
             }
             _ => panic!("Expected Metadata"),
         }
     }
 
+    #[test]
+    fn test_parse_mod_ex_prefixed_hevc_frame() {
+        // 0x97 = isExHeader(1) + Keyframe(001) + ModEx(0111)
+        let mut data = vec![0x97];
+        data.extend_from_slice(&[0x02, 0x00, 0x03, 0xE8]); // 3 bytes: 1000ns
+        data.push(0x07); // TimestampOffsetNano, another ModEx follows
+        data.extend_from_slice(&[0x01, 0xAB, 0xCD]); // 2 bytes of unknown data
+        data.push(0x51); // type 5, then CodedFrames
+        data.extend_from_slice(b"hvc1");
+        data.extend_from_slice(&[0x00, 0x00, 0x21]); // Composition time = 33
+        data.extend_from_slice(&[0x26, 0x01, 0xAF]); // HEVC IDR slice
+
+        let parsed = EnhancedVideoData::parse(Bytes::from(data)).unwrap();
+
+        let mod_ex = parsed.mod_ex().to_vec();
+        match parsed {
+            EnhancedVideoData::Frame {
+                codec,
+                frame_type,
+                composition_time,
+                data,
+                ..
+            } => {
+                assert_eq!(codec, VideoFourCc::Hevc);
+                assert_eq!(frame_type, ExVideoFrameType::Keyframe);
+                assert_eq!(composition_time, 33);
+                assert_eq!(data.as_ref(), &[0x26, 0x01, 0xAF]);
+            }
+            _ => panic!("Expected Frame"),
+        }
+
+        assert_eq!(mod_ex.len(), 2);
+        assert_eq!(mod_ex[0].timestamp_offset_nano(), Some(1000));
+        assert_eq!(mod_ex[1].mod_ex_type, 5);
+        assert_eq!(mod_ex[1].data.as_ref(), &[0xAB, 0xCD]);
+    }
+
+    #[test]
+    fn test_parse_mod_ex_truncated() {
+        // ModEx announced but the signal is cut short
+        let data = Bytes::from_static(&[0x97, 0x02, 0x00]);
+        assert!(EnhancedVideoData::parse(data).is_err());
+    }
+
     #[test]
     fn test_parse_error_empty() {
         let result = EnhancedVideoData::parse(Bytes::new());
@@ -813,6 +857,7 @@ mod tests {
             codec: VideoFourCc::Hevc,
             frame_type: ExVideoFrameType::Keyframe,
             config: Bytes::new(),
+            mod_ex: Vec::new(),
         };
         assert!(data.is_keyframe());
 
@@ -822,6 +867,7 @@ mod tests {
             frame_type: ExVideoFrameType::Keyframe,
             composition_time: 0,
             data: Bytes::new(),
+            mod_ex: Vec::new(),
         };
         assert!(data.is_keyframe());
 
@@ -831,12 +877,14 @@ mod tests {
             frame_type: ExVideoFrameType::InterFrame,
             composition_time: 0,
             data: Bytes::new(),
+            mod_ex: Vec::new(),
         };
         assert!(!data.is_keyframe());
 
         // Sequence end
         let data = EnhancedVideoData::SequenceEnd {
             codec: VideoFourCc::Hevc,
+            mod_ex: Vec::new(),
         };
         assert!(!data.is_keyframe());
     }
@@ -847,6 +895,7 @@ mod tests {
             codec: VideoFourCc::Hevc,
             frame_type: ExVideoFrameType::Keyframe,
             config: Bytes::new(),
+            mod_ex: Vec::new(),
         };
         assert!(header.is_sequence_header());
 
@@ -855,6 +904,7 @@ mod tests {
             frame_type: ExVideoFrameType::Keyframe,
             composition_time: 0,
             data: Bytes::new(),
+            mod_ex: Vec::new(),
         };
         assert!(!frame.is_sequence_header());
     }
@@ -865,10 +915,14 @@ mod tests {
             codec: VideoFourCc::Av1,
             frame_type: ExVideoFrameType::Keyframe,
             config: Bytes::new(),
+            mod_ex: Vec::new(),
         };
         assert_eq!(header.codec(), Some(VideoFourCc::Av1));
 
-        let metadata = EnhancedVideoData::Metadata { data: Bytes::new() };
+        let metadata = EnhancedVideoData::Metadata {
+            data: Bytes::new(),
+            mod_ex: Vec::new(),
+        };
         assert_eq!(metadata.codec(), None);
     }
 
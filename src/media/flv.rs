@@ -25,9 +25,36 @@
 //! | (4 bits)  | (2 bits)| (1 bit)  | (1 bit)  |
 //! +----------+----------+----------+----------+
 //! ```
+//!
+//! FLV files are written and read with [`FlvWriter`] and [`FlvReader`]:
+//! ```text
+//! | Header (9) | PrevTagSize0 (4) | Tag 1 (11+N) | PrevTagSize1 (4) | ...
+//! ```
+
+use std::io::{self, Read, Write};
 
 use bytes::Bytes;
 
+use super::aac::AudioSpecificConfig;
+
+/// FLV file signature ("FLV")
+const FLV_SIGNATURE: [u8; 3] = *b"FLV";
+
+/// FLV file version
+const FLV_VERSION: u8 = 1;
+
+/// FLV header size (also the data offset written in the header)
+const FLV_HEADER_SIZE: usize = 9;
+
+/// FLV tag header size
+const FLV_TAG_HEADER_SIZE: usize = 11;
+
+/// Header flag: file contains audio tags
+const FLV_FLAG_AUDIO: u8 = 0x04;
+
+/// Header flag: file contains video tags
+const FLV_FLAG_VIDEO: u8 = 0x01;
+
 /// FLV tag type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FlvTagType {
@@ -200,6 +227,37 @@ impl AudioSampleRate {
     }
 }
 
+/// Audio parameters of an FLV audio tag
+///
+/// Decoded from the tag header byte; see [`FlvTag::audio_params`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioParams {
+    /// Sound format
+    pub format: AudioFormat,
+    /// Sample rate in Hz
+    pub sample_rate_hz: u32,
+    /// Bits per sample (8 or 16)
+    pub sample_size_bits: u8,
+    /// Channel count (1 or 2 from the header byte; AAC configs may declare
+    /// more)
+    pub channels: u8,
+}
+
+impl AudioParams {
+    /// Take the sample rate and channel count from an AAC sequence header
+    ///
+    /// The header byte of AAC tags always reads 44.1 kHz stereo; the real
+    /// values are in the AudioSpecificConfig.
+    pub fn with_aac_config(mut self, config: &AudioSpecificConfig) -> Self {
+        self.sample_rate_hz = config.output_sample_rate();
+        // Zero means the config does not say
+        if config.channels() != 0 {
+            self.channels = config.channels();
+        }
+        self
+    }
+}
+
 impl FlvTag {
     /// Create a new video tag
     pub fn video(timestamp: u32, data: Bytes) -> Self {
@@ -219,6 +277,15 @@ impl FlvTag {
         }
     }
 
+    /// Create a new script data tag (onMetaData, etc.)
+    pub fn script(timestamp: u32, data: Bytes) -> Self {
+        Self {
+            tag_type: FlvTagType::Script,
+            timestamp,
+            data,
+        }
+    }
+
     /// Check if this is a video tag
     pub fn is_video(&self) -> bool {
         self.tag_type == FlvTagType::Video
@@ -256,6 +323,51 @@ impl FlvTag {
         }
     }
 
+    /// For audio tags, get the audio parameters from the header byte
+    ///
+    /// Formats with a fixed rate or layout (Nellymoser 8/16 kHz, 8 kHz MP3,
+    /// G.711, Speex) report it whatever the flags say. For AAC sequence
+    /// headers the rate and channels come from the AudioSpecificConfig;
+    /// other AAC tags report the header flags, which
+    /// [`AudioParams::with_aac_config`] can correct. None for enhanced
+    /// audio, which signals its codec by FourCC.
+    pub fn audio_params(&self) -> Option<AudioParams> {
+        let format = self.audio_format()?;
+        let b = self.data[0];
+        let mut params = AudioParams {
+            format,
+            sample_rate_hz: AudioSampleRate::from_byte(b).to_hz(),
+            sample_size_bits: if b & 0x02 != 0 { 16 } else { 8 },
+            channels: if b & 0x01 != 0 { 2 } else { 1 },
+        };
+
+        match format {
+            AudioFormat::Nellymoser16kMono => {
+                params.sample_rate_hz = 16000;
+                params.channels = 1;
+            }
+            AudioFormat::Nellymoser8kMono => {
+                params.sample_rate_hz = 8000;
+                params.channels = 1;
+            }
+            AudioFormat::Mp38k | AudioFormat::G711ALaw | AudioFormat::G711MuLaw => {
+                params.sample_rate_hz = 8000;
+            }
+            AudioFormat::Speex => {
+                params.sample_rate_hz = 16000;
+                params.channels = 1;
+            }
+            AudioFormat::Aac if self.is_aac_sequence_header() => {
+                if let Ok(config) = AudioSpecificConfig::parse(self.data.slice(2..)) {
+                    params = params.with_aac_config(&config);
+                }
+            }
+            _ => {}
+        }
+
+        Some(params)
+    }
+
     /// Check if this is a keyframe
     pub fn is_keyframe(&self) -> bool {
         self.video_frame_type()
@@ -283,12 +395,238 @@ impl FlvTag {
         }
     }
 
+    /// For script tags, get the name the data starts with (e.g. "onMetaData")
+    pub fn script_name(&self) -> Option<&str> {
+        if self.tag_type == FlvTagType::Script {
+            script_data_name(&self.data)
+        } else {
+            None
+        }
+    }
+
     /// Get the size of the tag data
     pub fn size(&self) -> usize {
         self.data.len()
     }
 }
 
+/// Read the AMF0 string that script data starts with
+pub(crate) fn script_data_name(data: &[u8]) -> Option<&str> {
+    match data {
+        [0x02, hi, lo, rest @ ..] => {
+            let len = u16::from_be_bytes([*hi, *lo]) as usize;
+            rest.get(..len)
+                .and_then(|name| std::str::from_utf8(name).ok())
+        }
+        _ => None,
+    }
+}
+
+impl FlvTagType {
+    /// FLV tag type code
+    pub fn to_byte(self) -> u8 {
+        match self {
+            FlvTagType::Audio => 8,
+            FlvTagType::Video => 9,
+            FlvTagType::Script => 18,
+        }
+    }
+
+    /// Parse an FLV tag type code
+    pub fn from_byte(b: u8) -> Option<Self> {
+        match b & 0x1F {
+            8 => Some(FlvTagType::Audio),
+            9 => Some(FlvTagType::Video),
+            18 => Some(FlvTagType::Script),
+            _ => None,
+        }
+    }
+}
+
+/// FLV file writer
+///
+/// Writes the file header on creation, then one tag (plus its trailing
+/// PreviousTagSize) per [`write_tag`](Self::write_tag) call.
+#[derive(Debug)]
+pub struct FlvWriter<W: Write> {
+    inner: W,
+    bytes_written: u64,
+    tags_written: u64,
+}
+
+impl<W: Write> FlvWriter<W> {
+    /// Create a writer for a file with audio and video
+    pub fn new(inner: W) -> io::Result<Self> {
+        Self::with_flags(inner, true, true)
+    }
+
+    /// Create a writer, declaring which track types the file contains
+    pub fn with_flags(mut inner: W, has_audio: bool, has_video: bool) -> io::Result<Self> {
+        let mut flags = 0;
+        if has_audio {
+            flags |= FLV_FLAG_AUDIO;
+        }
+        if has_video {
+            flags |= FLV_FLAG_VIDEO;
+        }
+
+        let mut header = [0u8; FLV_HEADER_SIZE + 4];
+        header[..3].copy_from_slice(&FLV_SIGNATURE);
+        header[3] = FLV_VERSION;
+        header[4] = flags;
+        header[5..9].copy_from_slice(&(FLV_HEADER_SIZE as u32).to_be_bytes());
+        // PreviousTagSize0 stays zero
+        inner.write_all(&header)?;
+
+        Ok(Self {
+            inner,
+            bytes_written: header.len() as u64,
+            tags_written: 0,
+        })
+    }
+
+    /// Write a tag
+    ///
+    /// Tags larger than the 24-bit size field are rejected.
+    pub fn write_tag(&mut self, tag: &FlvTag) -> io::Result<()> {
+        let size = tag.data.len();
+        if size > 0xFF_FFFF {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "FLV tag data exceeds 16 MiB",
+            ));
+        }
+
+        let mut header = [0u8; FLV_TAG_HEADER_SIZE];
+        header[0] = tag.tag_type.to_byte();
+        header[1..4].copy_from_slice(&(size as u32).to_be_bytes()[1..]);
+        // Lower 24 bits of the timestamp, then the extension byte
+        header[4..7].copy_from_slice(&tag.timestamp.to_be_bytes()[1..]);
+        header[7] = (tag.timestamp >> 24) as u8;
+        // Stream ID (always 0)
+
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&tag.data)?;
+        self.inner
+            .write_all(&((FLV_TAG_HEADER_SIZE + size) as u32).to_be_bytes())?;
+
+        self.bytes_written += (FLV_TAG_HEADER_SIZE + size + 4) as u64;
+        self.tags_written += 1;
+        Ok(())
+    }
+
+    /// Total bytes written, including the file header
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Number of tags written
+    pub fn tags_written(&self) -> u64 {
+        self.tags_written
+    }
+
+    /// Flush the underlying writer
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Flush and return the underlying writer
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// FLV file reader
+///
+/// Validates the file header on creation and then yields tags in file
+/// order. Tags of unknown types are skipped.
+#[derive(Debug)]
+pub struct FlvReader<R: Read> {
+    inner: R,
+    has_audio: bool,
+    has_video: bool,
+}
+
+impl<R: Read> FlvReader<R> {
+    /// Create a reader, consuming the file header
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let mut header = [0u8; FLV_HEADER_SIZE];
+        inner.read_exact(&mut header)?;
+        if header[..3] != FLV_SIGNATURE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "missing FLV signature",
+            ));
+        }
+
+        // Skip anything between the header and the first PreviousTagSize
+        let data_offset = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) as u64;
+        let extra = data_offset.saturating_sub(FLV_HEADER_SIZE as u64);
+        io::copy(&mut (&mut inner).take(extra + 4), &mut io::sink())?;
+
+        Ok(Self {
+            inner,
+            has_audio: header[4] & FLV_FLAG_AUDIO != 0,
+            has_video: header[4] & FLV_FLAG_VIDEO != 0,
+        })
+    }
+
+    /// Whether the header declares audio tags
+    pub fn has_audio(&self) -> bool {
+        self.has_audio
+    }
+
+    /// Whether the header declares video tags
+    pub fn has_video(&self) -> bool {
+        self.has_video
+    }
+
+    /// Unwrap the underlying reader, positioned after the last tag read
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read the next tag
+    ///
+    /// Returns `Ok(None)` at a clean end of file.
+    pub fn read_tag(&mut self) -> io::Result<Option<FlvTag>> {
+        loop {
+            let mut header = [0u8; FLV_TAG_HEADER_SIZE];
+            match self.inner.read_exact(&mut header[..1]) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            }
+            self.inner.read_exact(&mut header[1..])?;
+
+            let size = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+            let timestamp = u32::from_be_bytes([header[7], header[4], header[5], header[6]]);
+
+            let mut data = vec![0u8; size];
+            self.inner.read_exact(&mut data)?;
+            let mut prev_size = [0u8; 4];
+            self.inner.read_exact(&mut prev_size)?;
+
+            if let Some(tag_type) = FlvTagType::from_byte(header[0]) {
+                return Ok(Some(FlvTag {
+                    tag_type,
+                    timestamp,
+                    data: Bytes::from(data),
+                }));
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for FlvReader<R> {
+    type Item = io::Result<FlvTag>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_tag().transpose()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -319,6 +657,50 @@ mod tests {
         assert!(!frame.is_avc_sequence_header());
     }
 
+    #[test]
+    fn test_flv_writer_reader_round_trip() {
+        let tags = [
+            FlvTag::script(0, Bytes::from_static(&[0x02, 0x00, 0x00])),
+            FlvTag::video(0, Bytes::from_static(&[0x17, 0x00, 0x00, 0x00, 0x00])),
+            FlvTag::audio(23, Bytes::from_static(&[0xAF, 0x01, 0x21])),
+            // Needs the timestamp extension byte
+            FlvTag::video(0x0100_0010, Bytes::from_static(&[0x27, 0x01])),
+        ];
+
+        let mut writer = FlvWriter::new(Vec::new()).unwrap();
+        for tag in &tags {
+            writer.write_tag(tag).unwrap();
+        }
+        assert_eq!(writer.tags_written(), 4);
+        let file = writer.into_inner().unwrap();
+        assert_eq!(&file[..5], b"FLV\x01\x05");
+
+        let reader = FlvReader::new(&file[..]).unwrap();
+        assert!(reader.has_audio() && reader.has_video());
+        let read: Vec<FlvTag> = reader.collect::<io::Result<_>>().unwrap();
+
+        assert_eq!(read.len(), tags.len());
+        for (a, b) in read.iter().zip(&tags) {
+            assert_eq!(a.tag_type, b.tag_type);
+            assert_eq!(a.timestamp, b.timestamp);
+            assert_eq!(a.data, b.data);
+        }
+    }
+
+    #[test]
+    fn test_flv_reader_rejects_bad_input() {
+        assert!(FlvReader::new(&b"NOT_AN_FLV_FILE"[..]).is_err());
+
+        // Tag cut off mid-payload
+        let mut writer = FlvWriter::new(Vec::new()).unwrap();
+        writer
+            .write_tag(&FlvTag::video(0, Bytes::from_static(&[0x17, 0x01, 0x02])))
+            .unwrap();
+        let file = writer.into_inner().unwrap();
+        let mut reader = FlvReader::new(&file[..file.len() - 6]).unwrap();
+        assert!(reader.read_tag().is_err());
+    }
+
     #[test]
     fn test_aac_sequence_header() {
         let header = FlvTag::audio(0, Bytes::from_static(&[0xAF, 0x00, 0x12, 0x10]));
@@ -486,6 +868,79 @@ mod tests {
         assert!(video.audio_format().is_none());
     }
 
+    #[test]
+    fn test_audio_params_rate_size_channels() {
+        let params = |b: u8| FlvTag::audio(0, Bytes::from(vec![b, 0xFF])).audio_params();
+
+        // MP3, every rate, 16-bit stereo
+        for (rate_bits, hz) in [(0, 5512), (1, 11025), (2, 22050), (3, 44100)] {
+            let p = params(0x20 | rate_bits << 2 | 0x03).unwrap();
+            assert_eq!(p.format, AudioFormat::Mp3);
+            assert_eq!(p.sample_rate_hz, hz);
+            assert_eq!(p.sample_size_bits, 16);
+            assert_eq!(p.channels, 2);
+        }
+
+        // Linear PCM, 22 kHz 8-bit mono
+        let p = params(0x38).unwrap();
+        assert_eq!(p.format, AudioFormat::LinearPcmLe);
+        assert_eq!(p.sample_rate_hz, 22050);
+        assert_eq!(p.sample_size_bits, 8);
+        assert_eq!(p.channels, 1);
+
+        // ADPCM, 11 kHz 16-bit stereo
+        let p = params(0x17).unwrap();
+        assert_eq!(
+            (p.sample_rate_hz, p.sample_size_bits, p.channels),
+            (11025, 16, 2)
+        );
+    }
+
+    #[test]
+    fn test_audio_params_fixed_formats() {
+        let params = |b: u8| FlvTag::audio(0, Bytes::from(vec![b, 0xFF])).audio_params();
+
+        // G.711 is 8 kHz whatever the rate bits say; channels follow the flag
+        let p = params(0x7E).unwrap();
+        assert_eq!(p.format, AudioFormat::G711ALaw);
+        assert_eq!((p.sample_rate_hz, p.channels), (8000, 1));
+        let p = params(0x8F).unwrap();
+        assert_eq!(p.format, AudioFormat::G711MuLaw);
+        assert_eq!((p.sample_rate_hz, p.channels), (8000, 2));
+
+        assert_eq!(params(0x4F).unwrap().sample_rate_hz, 16000);
+        assert_eq!(params(0x4F).unwrap().channels, 1);
+        assert_eq!(params(0x5F).unwrap().sample_rate_hz, 8000);
+        assert_eq!(params(0xEF).unwrap().sample_rate_hz, 8000);
+        assert_eq!(params(0xBF).unwrap().sample_rate_hz, 16000);
+        assert_eq!(params(0xBF).unwrap().channels, 1);
+
+        // Enhanced audio and non-audio tags have none
+        assert_eq!(params(0x90), None);
+        assert_eq!(
+            FlvTag::video(0, Bytes::from_static(&[0x17, 0x00])).audio_params(),
+            None
+        );
+        assert_eq!(FlvTag::audio(0, Bytes::new()).audio_params(), None);
+    }
+
+    #[test]
+    fn test_audio_params_aac_config() {
+        // 48 kHz mono AAC-LC: the header byte still claims 44.1 kHz stereo
+        let header = FlvTag::audio(0, Bytes::from_static(&[0xAF, 0x00, 0x11, 0x88]));
+        let p = header.audio_params().unwrap();
+        assert_eq!(p.format, AudioFormat::Aac);
+        assert_eq!((p.sample_rate_hz, p.channels), (48000, 1));
+
+        let frame = FlvTag::audio(23, Bytes::from_static(&[0xAF, 0x01, 0x21]));
+        let p = frame.audio_params().unwrap();
+        assert_eq!((p.sample_rate_hz, p.channels), (44100, 2));
+
+        let config = AudioSpecificConfig::parse(Bytes::from_static(&[0x11, 0x88])).unwrap();
+        let p = p.with_aac_config(&config);
+        assert_eq!((p.sample_rate_hz, p.channels), (48000, 1));
+    }
+
     #[test]
     fn test_flv_tag_empty_data() {
         let empty_video = FlvTag::video(0, Bytes::new());
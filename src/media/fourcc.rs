@@ -23,6 +23,7 @@ impl FourCC {
     /// Create a FOURCC from a 4-character string.
     ///
     /// Returns None if the string is not exactly 4 ASCII characters.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         let bytes = s.as_bytes();
         if bytes.len() == 4 && bytes.iter().all(|b| b.is_ascii()) {
@@ -0,0 +1,403 @@
+//! Codec-agnostic parsed media frames
+//!
+//! [`VideoFrame`] and [`AudioFrame`] cover both legacy FLV and Enhanced RTMP
+//! payloads, so a consumer of parsed frames does not need to care how the
+//! codec was signaled. Codecs without a dedicated representation keep their
+//! enhanced form.
+
+use bytes::Bytes;
+
+use crate::error::{MediaError, Result};
+
+use super::aac::{AacData, AudioSpecificConfig};
+use super::audio::G711Frame;
+use super::enhanced_audio::EnhancedAudioData;
+use super::enhanced_video::EnhancedVideoData;
+use super::flv::AudioFormat;
+use super::fourcc::{AudioFourCc, VideoFourCc};
+use super::h264::{AvcConfig, H264Data, PictureTiming};
+use super::mp3::Mp3Data;
+
+/// Parsed HEVC/H.265 data
+#[derive(Debug, Clone)]
+pub enum HevcData {
+    /// Sequence header (HEVCDecoderConfigurationRecord)
+    SequenceHeader(Bytes),
+
+    /// Video frame (one or more NAL units)
+    Frame {
+        /// Whether this is a keyframe
+        keyframe: bool,
+        /// Composition time offset (for B-frames)
+        composition_time: i32,
+        /// NAL units in length-prefixed format
+        nalus: Bytes,
+    },
+
+    /// End of sequence marker
+    EndOfSequence,
+}
+
+/// Parsed AV1 data
+#[derive(Debug, Clone)]
+pub enum Av1Data {
+    /// Sequence header (AV1CodecConfigurationRecord)
+    SequenceHeader(Bytes),
+
+    /// Video frame
+    Frame {
+        /// Whether this is a keyframe
+        keyframe: bool,
+        /// OBUs in low overhead bitstream format
+        obus: Bytes,
+    },
+
+    /// End of sequence marker
+    EndOfSequence,
+}
+
+/// Parsed Opus data
+#[derive(Debug, Clone)]
+pub enum OpusData {
+    /// Sequence header (Opus identification header, "OpusHead")
+    SequenceHeader(Bytes),
+
+    /// Opus packet
+    Frame {
+        /// Raw Opus packet
+        data: Bytes,
+    },
+
+    /// End of sequence marker
+    EndOfSequence,
+}
+
+/// Parsed video frame of any codec
+#[derive(Debug, Clone)]
+pub enum VideoFrame {
+    /// H.264/AVC, legacy or enhanced
+    Avc(H264Data),
+    /// HEVC/H.265
+    Hevc(HevcData),
+    /// AV1
+    Av1(Av1Data),
+    /// Any other enhanced payload (VP8/VP9, metadata, multitrack)
+    Other(EnhancedVideoData),
+}
+
+impl VideoFrame {
+    /// Parse from RTMP video data (including the header byte)
+    ///
+    /// Accepts legacy AVC and Enhanced RTMP payloads.
+    pub fn parse(data: Bytes) -> Result<Self> {
+        if data.is_empty() {
+            return Err(MediaError::InvalidFlvTag.into());
+        }
+
+        if EnhancedVideoData::is_enhanced(data[0]) {
+            return Self::from_enhanced(EnhancedVideoData::parse(data)?);
+        }
+
+        match data[0] & 0x0F {
+            7 => Ok(VideoFrame::Avc(H264Data::parse(data.slice(1..))?)),
+            codec => Err(MediaError::UnsupportedCodec(format!("video codec {}", codec)).into()),
+        }
+    }
+
+    /// Convert already parsed enhanced video data
+    ///
+    /// Fails only if an AVC sequence header is malformed.
+    pub fn from_enhanced(data: EnhancedVideoData) -> Result<Self> {
+        let frame = match data {
+            EnhancedVideoData::SequenceHeader {
+                codec: VideoFourCc::Avc,
+                config,
+                ..
+            } => VideoFrame::Avc(H264Data::SequenceHeader(AvcConfig::parse(config)?)),
+            EnhancedVideoData::Frame {
+                codec: VideoFourCc::Avc,
+                frame_type,
+                composition_time,
+                data,
+                ..
+            } => VideoFrame::Avc(H264Data::Frame {
+                keyframe: frame_type.is_keyframe(),
+                composition_time,
+                nalus: data,
+            }),
+            EnhancedVideoData::SequenceEnd {
+                codec: VideoFourCc::Avc,
+                ..
+            } => VideoFrame::Avc(H264Data::EndOfSequence),
+
+            EnhancedVideoData::SequenceHeader {
+                codec: VideoFourCc::Hevc,
+                config,
+                ..
+            } => VideoFrame::Hevc(HevcData::SequenceHeader(config)),
+            EnhancedVideoData::Frame {
+                codec: VideoFourCc::Hevc,
+                frame_type,
+                composition_time,
+                data,
+                ..
+            } => VideoFrame::Hevc(HevcData::Frame {
+                keyframe: frame_type.is_keyframe(),
+                composition_time,
+                nalus: data,
+            }),
+            EnhancedVideoData::SequenceEnd {
+                codec: VideoFourCc::Hevc,
+                ..
+            } => VideoFrame::Hevc(HevcData::EndOfSequence),
+
+            EnhancedVideoData::SequenceHeader {
+                codec: VideoFourCc::Av1,
+                config,
+                ..
+            } => VideoFrame::Av1(Av1Data::SequenceHeader(config)),
+            EnhancedVideoData::Frame {
+                codec: VideoFourCc::Av1,
+                frame_type,
+                data,
+                ..
+            } => VideoFrame::Av1(Av1Data::Frame {
+                keyframe: frame_type.is_keyframe(),
+                obus: data,
+            }),
+            EnhancedVideoData::SequenceEnd {
+                codec: VideoFourCc::Av1,
+                ..
+            } => VideoFrame::Av1(Av1Data::EndOfSequence),
+
+            other => VideoFrame::Other(other),
+        };
+
+        Ok(frame)
+    }
+
+    /// Check if this is a keyframe
+    pub fn is_keyframe(&self) -> bool {
+        match self {
+            VideoFrame::Avc(avc) => avc.is_keyframe(),
+            VideoFrame::Hevc(HevcData::Frame { keyframe, .. })
+            | VideoFrame::Av1(Av1Data::Frame { keyframe, .. }) => *keyframe,
+            VideoFrame::Hevc(_) | VideoFrame::Av1(_) => false,
+            VideoFrame::Other(other) => other.is_keyframe(),
+        }
+    }
+
+    /// Decode the picture timing SEI (timecode) of an AVC frame
+    ///
+    /// See [`H264Data::picture_timing`]; `config` is the stream's AVC
+    /// sequence header, e.g. from `StreamContext::video_config`.
+    pub fn picture_timing(&self, config: &AvcConfig) -> Option<PictureTiming> {
+        match self {
+            VideoFrame::Avc(avc) => avc.picture_timing(config),
+            _ => None,
+        }
+    }
+
+    /// Check if this is a sequence header
+    pub fn is_sequence_header(&self) -> bool {
+        match self {
+            VideoFrame::Avc(avc) => avc.is_sequence_header(),
+            VideoFrame::Hevc(hevc) => matches!(hevc, HevcData::SequenceHeader(_)),
+            VideoFrame::Av1(av1) => matches!(av1, Av1Data::SequenceHeader(_)),
+            VideoFrame::Other(other) => other.is_sequence_header(),
+        }
+    }
+}
+
+/// Parsed audio frame of any codec
+#[derive(Debug, Clone)]
+pub enum AudioFrame {
+    /// AAC, legacy or enhanced
+    Aac(AacData),
+    /// Opus
+    Opus(OpusData),
+    /// G.711 A-law or mu-law
+    G711(G711Frame),
+    /// MP3
+    Mp3(Mp3Data),
+    /// Any other enhanced payload (FLAC, AC-3, E-AC-3, multichannel
+    /// configuration, multitrack)
+    Other(EnhancedAudioData),
+}
+
+impl AudioFrame {
+    /// Parse from RTMP audio data (including the header byte)
+    ///
+    /// Accepts legacy AAC, G.711 and MP3 as well as Enhanced RTMP payloads.
+    pub fn parse(data: Bytes) -> Result<Self> {
+        if data.is_empty() {
+            return Err(MediaError::InvalidFlvTag.into());
+        }
+
+        if EnhancedAudioData::is_enhanced(data[0]) {
+            Self::from_enhanced(EnhancedAudioData::parse(data)?)
+        } else {
+            Self::parse_legacy(data)
+        }
+    }
+
+    /// Parse a legacy payload by its SoundFormat
+    fn parse_legacy(data: Bytes) -> Result<Self> {
+        if data.len() < 2 {
+            return Err(MediaError::InvalidFlvTag.into());
+        }
+
+        match AudioFormat::from_byte(data[0]) {
+            Some(AudioFormat::Aac) => Ok(AudioFrame::Aac(AacData::parse(data.slice(1..))?)),
+            Some(AudioFormat::Mp3 | AudioFormat::Mp38k) => {
+                Ok(AudioFrame::Mp3(Mp3Data::parse(data.slice(1..))?))
+            }
+            Some(AudioFormat::G711ALaw | AudioFormat::G711MuLaw) => {
+                Ok(AudioFrame::G711(G711Frame::parse(data)?))
+            }
+            _ => Err(MediaError::UnsupportedCodec(format!("audio format {}", data[0] >> 4)).into()),
+        }
+    }
+
+    /// Convert already parsed enhanced audio data
+    ///
+    /// Fails if an AAC sequence header or MP3 payload is malformed.
+    pub fn from_enhanced(data: EnhancedAudioData) -> Result<Self> {
+        let frame = match data {
+            EnhancedAudioData::SequenceHeader {
+                codec: AudioFourCc::Aac,
+                config,
+                ..
+            } => AudioFrame::Aac(AacData::SequenceHeader(AudioSpecificConfig::parse(config)?)),
+            EnhancedAudioData::Frame {
+                codec: AudioFourCc::Aac,
+                data,
+                ..
+            } => AudioFrame::Aac(AacData::Frame { data }),
+
+            EnhancedAudioData::SequenceHeader {
+                codec: AudioFourCc::Opus,
+                config,
+                ..
+            } => AudioFrame::Opus(OpusData::SequenceHeader(config)),
+            EnhancedAudioData::Frame {
+                codec: AudioFourCc::Opus,
+                data,
+                ..
+            } => AudioFrame::Opus(OpusData::Frame { data }),
+            EnhancedAudioData::SequenceEnd {
+                codec: AudioFourCc::Opus,
+                ..
+            } => AudioFrame::Opus(OpusData::EndOfSequence),
+
+            EnhancedAudioData::Frame {
+                codec: AudioFourCc::Mp3,
+                data,
+                ..
+            } => AudioFrame::Mp3(Mp3Data::parse(data)?),
+
+            other => AudioFrame::Other(other),
+        };
+
+        Ok(frame)
+    }
+
+    /// Check if this is a sequence header
+    pub fn is_sequence_header(&self) -> bool {
+        match self {
+            AudioFrame::Aac(aac) => aac.is_sequence_header(),
+            AudioFrame::Opus(opus) => matches!(opus, OpusData::SequenceHeader(_)),
+            AudioFrame::G711(_) | AudioFrame::Mp3(_) => false,
+            AudioFrame::Other(other) => other.is_sequence_header(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hevc_keyframe() {
+        // Enhanced keyframe, CodedFramesX, "hvc1", one length-prefixed NALU
+        let data = Bytes::from_static(&[
+            0x93, b'h', b'v', b'c', b'1', 0x00, 0x00, 0x00, 0x02, 0x26, 0x01,
+        ]);
+        let frame = VideoFrame::parse(data).unwrap();
+
+        assert!(frame.is_keyframe());
+        assert!(!frame.is_sequence_header());
+        match frame {
+            VideoFrame::Hevc(HevcData::Frame {
+                keyframe,
+                composition_time,
+                nalus,
+            }) => {
+                assert!(keyframe);
+                assert_eq!(composition_time, 0);
+                assert_eq!(&nalus[..], &[0x00, 0x00, 0x00, 0x02, 0x26, 0x01]);
+            }
+            other => panic!("expected HEVC frame, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_av1_sequence_header() {
+        // Enhanced keyframe, SequenceStart, "av01"
+        let data = Bytes::from_static(&[0x90, b'a', b'v', b'0', b'1', 0x81, 0x00, 0x0C, 0x00]);
+        let frame = VideoFrame::parse(data).unwrap();
+
+        assert!(frame.is_sequence_header());
+        assert!(matches!(frame, VideoFrame::Av1(Av1Data::SequenceHeader(ref c)) if c.len() == 4));
+    }
+
+    #[test]
+    fn test_legacy_avc_and_other_codecs() {
+        // Legacy AVC keyframe NALU packet
+        let data =
+            Bytes::from_static(&[0x17, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x65]);
+        let frame = VideoFrame::parse(data).unwrap();
+        assert!(matches!(frame, VideoFrame::Avc(H264Data::Frame { .. })));
+        assert!(frame.is_keyframe());
+
+        // VP9 has no dedicated representation
+        let data = Bytes::from_static(&[0x93, b'v', b'p', b'0', b'9', 0xAA]);
+        assert!(matches!(VideoFrame::parse(data), Ok(VideoFrame::Other(_))));
+
+        // Legacy codecs other than AVC are not parsed
+        assert!(VideoFrame::parse(Bytes::from_static(&[0x12, 0x00])).is_err());
+    }
+
+    #[test]
+    fn test_opus_and_legacy_audio() {
+        // Enhanced audio (SoundFormat 9), CodedFrames, "Opus"
+        let data = Bytes::from_static(&[0x91, b'O', b'p', b'u', b's', 0xFC, 0xFF]);
+        let frame = AudioFrame::parse(data).unwrap();
+        assert!(matches!(frame, AudioFrame::Opus(OpusData::Frame { ref data }) if data.len() == 2));
+        assert!(!frame.is_sequence_header());
+
+        // Legacy AAC sequence header (44.1kHz stereo AAC-LC)
+        let data = Bytes::from_static(&[0xAF, 0x00, 0x12, 0x10]);
+        let frame = AudioFrame::parse(data).unwrap();
+        assert!(matches!(frame, AudioFrame::Aac(AacData::SequenceHeader(_))));
+        assert!(frame.is_sequence_header());
+    }
+
+    #[test]
+    fn test_legacy_g711_and_mp3_audio() {
+        // SoundFormat=7 (A-law), mono
+        let data = Bytes::from_static(&[0x72, 0xD5, 0xD5]);
+        let frame = AudioFrame::parse(data).unwrap();
+        assert!(matches!(frame, AudioFrame::G711(ref g711) if g711.channels == 1));
+        assert!(!frame.is_sequence_header());
+
+        // SoundFormat=2 (MP3), 44 kHz, stereo; one 417-byte frame
+        let mut tag = vec![0x2F, 0xFF, 0xFB, 0x90, 0x64];
+        tag.resize(1 + 417, 0);
+        let frame = AudioFrame::parse(Bytes::from(tag)).unwrap();
+        assert!(matches!(frame, AudioFrame::Mp3(ref mp3) if mp3.frames.len() == 1));
+
+        // Speex has no parsed representation
+        assert!(AudioFrame::parse(Bytes::from_static(&[0xB2, 0x00])).is_err());
+    }
+}
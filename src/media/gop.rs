@@ -11,7 +11,21 @@
 use bytes::Bytes;
 use std::collections::VecDeque;
 
-use super::flv::FlvTag;
+use super::flv::{FlvTag, FlvTagType};
+
+/// Default media time without video before a stream counts as audio-only
+pub const DEFAULT_AUDIO_ONLY_GRACE_MS: u32 = 1000;
+
+/// Which media a stream carries, as far as the buffer has seen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamKind {
+    /// No video yet, and not enough audio to rule it out
+    Unknown,
+    /// Audio kept arriving for the grace window without any video
+    AudioOnly,
+    /// Video arrived (with or without audio)
+    Video,
+}
 
 /// A buffered media frame
 #[derive(Debug, Clone)]
@@ -33,12 +47,18 @@ pub struct GopBuffer {
     video_header: Option<FlvTag>,
     /// Audio sequence header
     audio_header: Option<FlvTag>,
-    /// Metadata
-    metadata: Option<Bytes>,
+    /// Metadata (onMetaData script tag)
+    metadata: Option<FlvTag>,
     /// Buffered frames since last keyframe
     frames: VecDeque<BufferedFrame>,
     /// Whether we have a complete GOP (started with keyframe)
     has_complete_gop: bool,
+    /// Whether any video frame arrived
+    has_video: bool,
+    /// Timestamps of the first and latest audio frames
+    audio_span: Option<(u32, u32)>,
+    /// Media time without video before the stream counts as audio-only
+    audio_only_grace_ms: u32,
 }
 
 impl GopBuffer {
@@ -57,9 +77,32 @@ impl GopBuffer {
             metadata: None,
             frames: VecDeque::new(),
             has_complete_gop: false,
+            has_video: false,
+            audio_span: None,
+            audio_only_grace_ms: DEFAULT_AUDIO_ONLY_GRACE_MS,
         }
     }
 
+    /// Set how much audio, in milliseconds of media time, must arrive
+    /// without video before the stream counts as audio-only
+    pub fn set_audio_only_grace(&mut self, grace_ms: u32) {
+        self.audio_only_grace_ms = grace_ms;
+    }
+
+    /// Record a video frame that is not buffered
+    pub fn note_video(&mut self) {
+        self.has_video = true;
+    }
+
+    /// Record an audio frame
+    ///
+    /// Audio frames are not buffered; they only tell audio-only streams
+    /// apart from streams whose video has yet to start.
+    pub fn note_audio(&mut self, timestamp: u32) {
+        let span = self.audio_span.get_or_insert((timestamp, timestamp));
+        span.1 = timestamp;
+    }
+
     /// Set the video sequence header
     pub fn set_video_header(&mut self, tag: FlvTag) {
         self.video_header = Some(tag);
@@ -71,8 +114,15 @@ impl GopBuffer {
     }
 
     /// Set metadata
+    ///
+    /// The encoded onMetaData body is wrapped in a script tag.
     pub fn set_metadata(&mut self, metadata: Bytes) {
-        self.metadata = Some(metadata);
+        self.metadata = Some(FlvTag::script(0, metadata));
+    }
+
+    /// Remove the metadata
+    pub fn clear_metadata(&mut self) {
+        self.metadata = None;
     }
 
     /// Add a frame to the buffer
@@ -82,6 +132,12 @@ impl GopBuffer {
     pub fn push(&mut self, tag: FlvTag) -> bool {
         let size = tag.size();
 
+        match tag.tag_type {
+            FlvTagType::Video => self.note_video(),
+            FlvTagType::Audio if !tag.is_aac_sequence_header() => self.note_audio(tag.timestamp),
+            _ => {}
+        }
+
         // Handle keyframes - start a new GOP
         if tag.is_keyframe() {
             self.clear_frames();
@@ -121,6 +177,8 @@ impl GopBuffer {
         self.video_header = None;
         self.audio_header = None;
         self.metadata = None;
+        self.has_video = false;
+        self.audio_span = None;
     }
 
     /// Get the video sequence header
@@ -135,6 +193,11 @@ impl GopBuffer {
 
     /// Get metadata
     pub fn metadata(&self) -> Option<&Bytes> {
+        self.metadata.as_ref().map(|tag| &tag.data)
+    }
+
+    /// Get metadata as a script tag
+    pub fn metadata_tag(&self) -> Option<&FlvTag> {
         self.metadata.as_ref()
     }
 
@@ -143,21 +206,50 @@ impl GopBuffer {
         self.has_complete_gop
     }
 
+    /// Check which media the stream carries
+    pub fn stream_kind(&self) -> StreamKind {
+        if self.has_video {
+            return StreamKind::Video;
+        }
+        match self.audio_span {
+            Some((first, latest)) if latest.wrapping_sub(first) >= self.audio_only_grace_ms => {
+                StreamKind::AudioOnly
+            }
+            _ => StreamKind::Unknown,
+        }
+    }
+
     /// Check if the buffer is ready for late-joiners
     ///
-    /// Returns true if we have at least the video header and a complete GOP.
+    /// Returns true if we have at least the video header and a complete GOP,
+    /// or for audio-only streams, the audio header.
     pub fn is_ready(&self) -> bool {
-        self.video_header.is_some() && self.has_complete_gop
+        self.is_ready_with_headers(self.video_header.is_some(), self.audio_header.is_some())
+    }
+
+    /// Check if the buffer is ready for late-joiners, with the sequence
+    /// headers kept elsewhere
+    ///
+    /// For owners that cache the headers themselves, like the registry's
+    /// stream entries.
+    pub fn is_ready_with_headers(&self, has_video_header: bool, has_audio_header: bool) -> bool {
+        match self.stream_kind() {
+            StreamKind::AudioOnly => has_audio_header,
+            StreamKind::Video | StreamKind::Unknown => has_video_header && self.has_complete_gop,
+        }
     }
 
     /// Get all buffered frames for a late-joiner
     ///
-    /// Returns sequence headers followed by all buffered frames.
+    /// Returns metadata, then sequence headers (video, audio), followed by
+    /// all buffered frames.
     pub fn get_catchup_data(&self) -> Vec<FlvTag> {
         let mut result = Vec::with_capacity(self.frames.len() + 3);
 
         // Add metadata first (if available)
-        // Note: Metadata is stored as Bytes, not FlvTag - skip for now
+        if let Some(m) = &self.metadata {
+            result.push(m.clone());
+        }
 
         // Add sequence headers
         if let Some(h) = &self.video_header {
@@ -175,6 +267,16 @@ impl GopBuffer {
         result
     }
 
+    /// Get the keyframe that starts the buffered GOP
+    ///
+    /// None until a keyframe arrives, or once it was dropped to make room.
+    pub fn keyframe(&self) -> Option<&FlvTag> {
+        self.frames
+            .front()
+            .map(|frame| &frame.tag)
+            .filter(|tag| tag.is_keyframe())
+    }
+
     /// Get the number of buffered frames
     pub fn frame_count(&self) -> usize {
         self.frames.len()
@@ -220,6 +322,7 @@ impl Default for GopBuffer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::media::flv::FlvTagType;
 
     fn make_tag(timestamp: u32, is_keyframe: bool, size: usize) -> FlvTag {
         let mut data = vec![0u8; size];
@@ -414,6 +517,26 @@ mod tests {
         assert!(catchup[2].is_keyframe());
     }
 
+    #[test]
+    fn test_gop_buffer_catchup_starts_with_metadata() {
+        let mut buffer = GopBuffer::new();
+
+        buffer.set_video_header(FlvTag::video(0, Bytes::from_static(&[0x17, 0x00])));
+        buffer.set_audio_header(FlvTag::audio(0, Bytes::from_static(&[0xAF, 0x00])));
+        buffer.push(make_tag(0, true, 100));
+        buffer.set_metadata(Bytes::from_static(b"meta"));
+
+        let catchup = buffer.get_catchup_data();
+
+        // metadata + video header + audio header + keyframe
+        assert_eq!(catchup.len(), 4);
+        assert_eq!(catchup[0].tag_type, FlvTagType::Script);
+        assert_eq!(catchup[0].data, Bytes::from_static(b"meta"));
+        assert!(catchup[1].is_avc_sequence_header());
+        assert!(catchup[2].is_aac_sequence_header());
+        assert!(catchup[3].is_keyframe());
+    }
+
     #[test]
     fn test_gop_buffer_get_catchup_data_without_headers() {
         let mut buffer = GopBuffer::new();
@@ -485,8 +608,21 @@ mod tests {
         // Audio-only stream might set audio header
         buffer.set_audio_header(FlvTag::audio(0, Bytes::from_static(&[0xAF, 0x00])));
 
-        // is_ready requires video_header, so audio-only won't be "ready"
-        // This is intentional - late joiners need video keyframe typically
+        // Video might still start
+        assert!(!buffer.is_ready());
+        buffer.note_audio(0);
+        buffer.note_audio(500);
+        assert_eq!(buffer.stream_kind(), StreamKind::Unknown);
+        assert!(!buffer.is_ready());
+
+        // A grace window of audio without video settles it
+        buffer.note_audio(1000);
+        assert_eq!(buffer.stream_kind(), StreamKind::AudioOnly);
+        assert!(buffer.is_ready());
+
+        // Video turning up after all needs its GOP again
+        buffer.push(make_tag(1023, false, 100));
+        assert_eq!(buffer.stream_kind(), StreamKind::Video);
         assert!(!buffer.is_ready());
     }
 }
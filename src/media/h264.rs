@@ -25,7 +25,8 @@
 
 use bytes::{Buf, Bytes};
 
-use crate::error::{MediaError, Result};
+use crate::error::{MediaError, NaluError, Result};
+use crate::limits::DecodeLimits;
 
 /// AVC packet type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -168,34 +169,16 @@ impl AvcConfig {
 
         // Parse SPS
         let num_sps = (data.get_u8() & 0x1F) as usize;
-        let mut sps = Vec::with_capacity(num_sps);
-        for _ in 0..num_sps {
-            if data.len() < 2 {
-                return Err(MediaError::InvalidAvcPacket.into());
-            }
-            let sps_len = data.get_u16() as usize;
-            if data.len() < sps_len {
-                return Err(MediaError::InvalidAvcPacket.into());
-            }
-            sps.push(data.copy_to_bytes(sps_len));
-        }
+        parameter_sets_end(&data, num_sps).ok_or(MediaError::SpsOverrun)?;
+        let sps = split_parameter_sets(&mut data, num_sps);
 
         // Parse PPS
         if data.is_empty() {
-            return Err(MediaError::InvalidAvcPacket.into());
+            return Err(MediaError::PpsOverrun.into());
         }
         let num_pps = data.get_u8() as usize;
-        let mut pps = Vec::with_capacity(num_pps);
-        for _ in 0..num_pps {
-            if data.len() < 2 {
-                return Err(MediaError::InvalidAvcPacket.into());
-            }
-            let pps_len = data.get_u16() as usize;
-            if data.len() < pps_len {
-                return Err(MediaError::InvalidAvcPacket.into());
-            }
-            pps.push(data.copy_to_bytes(pps_len));
-        }
+        parameter_sets_end(&data, num_pps).ok_or(MediaError::PpsOverrun)?;
+        let pps = split_parameter_sets(&mut data, num_pps);
 
         Ok(AvcConfig {
             profile,
@@ -208,6 +191,16 @@ impl AvcConfig {
         })
     }
 
+    /// Parse from untrusted input, enforcing `limits`
+    ///
+    /// The SPS and PPS counts together must fit `max_collection_len`.
+    pub fn decode_checked(data: Bytes, limits: DecodeLimits) -> Result<Self> {
+        limits.check_media_input(data.len())?;
+        let config = Self::parse(data)?;
+        limits.check_media_len(config.sps.len() + config.pps.len())?;
+        Ok(config)
+    }
+
     /// Get profile name
     pub fn profile_name(&self) -> &'static str {
         match self.profile {
@@ -226,6 +219,572 @@ impl AvcConfig {
     pub fn level_string(&self) -> String {
         format!("{}.{}", self.level / 10, self.level % 10)
     }
+
+    /// Get the coded picture dimensions (width, height) in pixels
+    ///
+    /// Parsed from the first SPS, with frame cropping applied.
+    /// Returns `None` if there is no SPS or it cannot be parsed.
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        parse_sps(self.sps.first()?).map(|sps| sps.dimensions)
+    }
+
+    /// Get the frame rate signalled in the first SPS's VUI timing info
+    ///
+    /// Unlike the `framerate` in `onMetaData`, this comes from the
+    /// bitstream itself. Returns `None` if the SPS has no timing info.
+    pub fn frame_rate(&self) -> Option<f64> {
+        let (num_units_in_tick, time_scale) = parse_sps(self.sps.first()?)?.vui.timing?;
+        // One frame is two ticks
+        Some(time_scale as f64 / (2.0 * num_units_in_tick as f64))
+    }
+
+    /// Get the sample (pixel) aspect ratio from the first SPS's VUI
+    ///
+    /// Returns `None` if the SPS leaves it unspecified.
+    pub fn sample_aspect_ratio(&self) -> Option<(u32, u32)> {
+        parse_sps(self.sps.first()?)?.vui.sample_aspect_ratio
+    }
+
+    /// Fields of the first SPS's VUI that shape picture timing SEI
+    fn pic_timing_params(&self) -> Option<PicTimingParams> {
+        parse_sps(self.sps.first()?)?.vui.pic_timing
+    }
+}
+
+/// Picture timing SEI (payload type 1) of an H.264 frame
+///
+/// Decoded with [`H264Data::picture_timing`]. Which fields are present is
+/// decided by the SPS VUI, not by the SEI itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PictureTiming {
+    /// CPB removal delay, in clock ticks (with HRD parameters in the VUI)
+    pub cpb_removal_delay: Option<u32>,
+    /// DPB output delay, in clock ticks (with HRD parameters in the VUI)
+    pub dpb_output_delay: Option<u32>,
+    /// Picture structure, 0 for a frame (with `pic_struct_present_flag`)
+    pub pic_struct: Option<u8>,
+    /// Clock timestamps, one per field or repeated frame that carries one
+    pub clock_timestamps: Vec<ClockTimestamp>,
+}
+
+impl PictureTiming {
+    /// Timecode of the picture: its first clock timestamp
+    pub fn timecode(&self) -> Option<&ClockTimestamp> {
+        self.clock_timestamps.first()
+    }
+}
+
+/// Clock timestamp of a picture timing SEI, i.e. an SMPTE-style timecode
+///
+/// Encoders that do not send a full timestamp may leave out the hours,
+/// minutes or seconds, which then keep their previous values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockTimestamp {
+    /// Scan type: 0 progressive, 1 interlaced, 2 unknown
+    pub ct_type: u8,
+    /// How `n_frames` counts, e.g. 4 for drop-frame counting
+    pub counting_type: u8,
+    /// Set if this timestamp does not follow from the previous one
+    pub discontinuity: bool,
+    /// Set if frame counts were dropped before this one (drop-frame)
+    pub cnt_dropped: bool,
+    /// Frame number within the second
+    pub n_frames: u8,
+    /// Seconds, 0-59
+    pub seconds: Option<u8>,
+    /// Minutes, 0-59
+    pub minutes: Option<u8>,
+    /// Hours, 0-23
+    pub hours: Option<u8>,
+    /// Offset from the timecode, in clock ticks
+    pub time_offset: i32,
+}
+
+impl std::fmt::Display for ClockTimestamp {
+    /// Formats as `HH:MM:SS:FF`, with `;` before the frames for
+    /// drop-frame timecode and `--` for parts that were left out
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let part = |v: Option<u8>| v.map_or_else(|| "--".to_string(), |v| format!("{:02}", v));
+        let separator = if self.cnt_dropped { ';' } else { ':' };
+        write!(
+            f,
+            "{}:{}:{}{}{:02}",
+            part(self.hours),
+            part(self.minutes),
+            part(self.seconds),
+            separator,
+            self.n_frames
+        )
+    }
+}
+
+/// SPS fields needed to decode a picture timing SEI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PicTimingParams {
+    /// Bit lengths of cpb_removal_delay and dpb_output_delay, if the VUI
+    /// has HRD parameters
+    delay_lengths: Option<(u32, u32)>,
+    /// Bit length of time_offset in clock timestamps
+    time_offset_length: u32,
+    /// Whether pic_struct and clock timestamps are present
+    pic_struct_present: bool,
+}
+
+/// Fields parsed from an SPS
+struct SpsInfo {
+    /// Picture dimensions, with cropping applied
+    dimensions: (u32, u32),
+    /// Fields parsed from the VUI
+    vui: VuiInfo,
+}
+
+/// Fields parsed from an SPS's VUI, each `None` if absent
+#[derive(Debug, Default)]
+struct VuiInfo {
+    /// Sample aspect ratio (width, height)
+    sample_aspect_ratio: Option<(u32, u32)>,
+    /// num_units_in_tick and time_scale, both nonzero
+    timing: Option<(u32, u32)>,
+    /// Picture timing SEI parameters
+    pic_timing: Option<PicTimingParams>,
+}
+
+/// Sample aspect ratios for aspect_ratio_idc 1 to 16 (Table E-1)
+const SAMPLE_ASPECT_RATIOS: [(u32, u32); 16] = [
+    (1, 1),
+    (12, 11),
+    (10, 11),
+    (16, 11),
+    (40, 33),
+    (24, 11),
+    (20, 11),
+    (32, 11),
+    (80, 33),
+    (18, 11),
+    (15, 11),
+    (64, 33),
+    (160, 99),
+    (4, 3),
+    (3, 2),
+    (2, 1),
+];
+
+/// Validate a list of `count` length-prefixed parameter sets
+///
+/// Returns the number of bytes the list occupies, or `None` if the
+/// declared count or any declared length overruns `data`.
+fn parameter_sets_end(data: &[u8], count: usize) -> Option<usize> {
+    // Every entry needs at least its 2-byte length prefix
+    if count * 2 > data.len() {
+        return None;
+    }
+
+    let mut offset = 0;
+    for _ in 0..count {
+        let len_bytes = data.get(offset..offset + 2)?;
+        let len = u16::from_be_bytes([len_bytes[0], len_bytes[1]]) as usize;
+        offset += 2 + len;
+        if offset > data.len() {
+            return None;
+        }
+    }
+    Some(offset)
+}
+
+/// Split `count` parameter sets already validated by [`parameter_sets_end`]
+fn split_parameter_sets(data: &mut Bytes, count: usize) -> Vec<Bytes> {
+    (0..count)
+        .map(|_| {
+            let len = data.get_u16() as usize;
+            data.split_to(len)
+        })
+        .collect()
+}
+
+/// Strip emulation prevention bytes (00 00 03) from a NAL unit payload
+fn nalu_to_rbsp(payload: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(payload.len());
+    let mut zeros = 0;
+    for &b in payload {
+        if zeros >= 2 && b == 0x03 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        rbsp.push(b);
+    }
+    rbsp
+}
+
+/// Parse an SPS NAL unit (including the NAL header)
+fn parse_sps(sps: &[u8]) -> Option<SpsInfo> {
+    if sps.len() < 4 {
+        return None;
+    }
+
+    let rbsp = nalu_to_rbsp(&sps[1..]);
+    let mut r = BitReader::new(&rbsp);
+    let profile_idc = r.read_bits(8)?;
+    r.skip_bits(16)?; // constraint flags + level_idc
+    r.read_ue()?; // seq_parameter_set_id
+
+    let mut chroma_format_idc = 1;
+    let mut separate_colour_plane = false;
+    if matches!(
+        profile_idc,
+        100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135
+    ) {
+        chroma_format_idc = r.read_ue()?;
+        if chroma_format_idc == 3 {
+            separate_colour_plane = r.read_bit()?;
+        }
+        r.read_ue()?; // bit_depth_luma_minus8
+        r.read_ue()?; // bit_depth_chroma_minus8
+        r.skip_bits(1)?; // qpprime_y_zero_transform_bypass_flag
+        if r.read_bit()? {
+            // seq_scaling_matrix_present_flag
+            let count = if chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..count {
+                if r.read_bit()? {
+                    let size = if i < 6 { 16 } else { 64 };
+                    skip_scaling_list(&mut r, size)?;
+                }
+            }
+        }
+    }
+
+    r.read_ue()?; // log2_max_frame_num_minus4
+    match r.read_ue()? {
+        0 => {
+            r.read_ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            r.skip_bits(1)?; // delta_pic_order_always_zero_flag
+            r.read_se()?; // offset_for_non_ref_pic
+            r.read_se()?; // offset_for_top_to_bottom_field
+            let cycle = r.read_ue()?;
+            for _ in 0..cycle {
+                r.read_se()?; // offset_for_ref_frame
+            }
+        }
+        _ => {}
+    }
+    r.read_ue()?; // max_num_ref_frames
+    r.skip_bits(1)?; // gaps_in_frame_num_value_allowed_flag
+
+    let width_in_mbs = r.read_ue()?.checked_add(1)?;
+    let height_in_map_units = r.read_ue()?.checked_add(1)?;
+    let frame_mbs_only = r.read_bit()?;
+    if !frame_mbs_only {
+        r.skip_bits(1)?; // mb_adaptive_frame_field_flag
+    }
+    r.skip_bits(1)?; // direct_8x8_inference_flag
+
+    let (mut crop_left, mut crop_right, mut crop_top, mut crop_bottom) = (0, 0, 0, 0);
+    if r.read_bit()? {
+        crop_left = r.read_ue()?;
+        crop_right = r.read_ue()?;
+        crop_top = r.read_ue()?;
+        crop_bottom = r.read_ue()?;
+    }
+
+    let field_factor = if frame_mbs_only { 1 } else { 2 };
+    let (crop_unit_x, crop_unit_y): (u32, u32) = if chroma_format_idc == 0 || separate_colour_plane
+    {
+        (1, field_factor)
+    } else {
+        let sub_width = if chroma_format_idc == 3 { 1 } else { 2 };
+        let sub_height = if chroma_format_idc == 1 { 2 } else { 1 };
+        (sub_width, sub_height * field_factor)
+    };
+
+    // Exp-Golomb values reach 2^32, so a crafted SPS can overflow any of these
+    let width = width_in_mbs
+        .checked_mul(16)?
+        .checked_sub(crop_unit_x.checked_mul(crop_left.checked_add(crop_right)?)?)?;
+    let height = height_in_map_units
+        .checked_mul(16 * field_factor)?
+        .checked_sub(crop_unit_y.checked_mul(crop_top.checked_add(crop_bottom)?)?)?;
+
+    // A VUI that fails to parse only costs the fields past the error
+    let mut vui = VuiInfo::default();
+    if r.read_bit() == Some(true) {
+        parse_vui(&mut r, &mut vui);
+    }
+
+    Some(SpsInfo {
+        dimensions: (width, height),
+        vui,
+    })
+}
+
+/// Parse vui_parameters() up to pic_struct_present_flag into `vui`
+fn parse_vui(r: &mut BitReader<'_>, vui: &mut VuiInfo) -> Option<()> {
+    if r.read_bit()? {
+        // aspect_ratio_info_present_flag
+        let sar = match r.read_bits(8)? {
+            255 => Some((r.read_bits(16)?, r.read_bits(16)?)),
+            idc @ 1..=16 => Some(SAMPLE_ASPECT_RATIOS[idc as usize - 1]),
+            _ => None,
+        };
+        vui.sample_aspect_ratio = sar.filter(|&(w, h)| w != 0 && h != 0);
+    }
+    if r.read_bit()? {
+        r.skip_bits(1)?; // overscan_appropriate_flag
+    }
+    if r.read_bit()? {
+        // video_signal_type_present_flag
+        r.skip_bits(4)?; // video_format, video_full_range_flag
+        if r.read_bit()? {
+            r.skip_bits(24)?; // colour_primaries, transfer, matrix
+        }
+    }
+    if r.read_bit()? {
+        r.read_ue()?; // chroma_sample_loc_type_top_field
+        r.read_ue()?; // chroma_sample_loc_type_bottom_field
+    }
+    if r.read_bit()? {
+        // timing_info_present_flag
+        let num_units_in_tick = r.read_bits(32)?;
+        let time_scale = r.read_bits(32)?;
+        r.skip_bits(1)?; // fixed_frame_rate_flag
+        if num_units_in_tick != 0 && time_scale != 0 {
+            vui.timing = Some((num_units_in_tick, time_scale));
+        }
+    }
+
+    let mut delay_lengths = None;
+    let mut time_offset_length = 0;
+    for _ in 0..2 {
+        // nal_hrd_parameters_present_flag, vcl_hrd_parameters_present_flag
+        if r.read_bit()? {
+            let (cpb_len, dpb_len, offset_len) = parse_hrd_lengths(r)?;
+            delay_lengths = Some((cpb_len, dpb_len));
+            time_offset_length = offset_len;
+        }
+    }
+    if delay_lengths.is_some() {
+        r.skip_bits(1)?; // low_delay_hrd_flag
+    }
+    let pic_struct_present = r.read_bit()?;
+
+    vui.pic_timing = Some(PicTimingParams {
+        delay_lengths,
+        time_offset_length,
+        pic_struct_present,
+    });
+    Some(())
+}
+
+/// Parse hrd_parameters(), returning the bit lengths of
+/// cpb_removal_delay, dpb_output_delay and time_offset
+fn parse_hrd_lengths(r: &mut BitReader<'_>) -> Option<(u32, u32, u32)> {
+    let cpb_cnt = r.read_ue()? + 1;
+    if cpb_cnt > 32 {
+        return None;
+    }
+    r.skip_bits(8)?; // bit_rate_scale, cpb_size_scale
+    for _ in 0..cpb_cnt {
+        r.read_ue()?; // bit_rate_value_minus1
+        r.read_ue()?; // cpb_size_value_minus1
+        r.skip_bits(1)?; // cbr_flag
+    }
+    r.skip_bits(5)?; // initial_cpb_removal_delay_length_minus1
+    let cpb_len = r.read_bits(5)? + 1;
+    let dpb_len = r.read_bits(5)? + 1;
+    let offset_len = r.read_bits(5)?;
+    Some((cpb_len, dpb_len, offset_len))
+}
+
+/// Parse a pic_timing() SEI payload
+fn parse_pic_timing(payload: &[u8], params: PicTimingParams) -> Option<PictureTiming> {
+    let mut r = BitReader::new(payload);
+
+    let (cpb_removal_delay, dpb_output_delay) = match params.delay_lengths {
+        Some((cpb_len, dpb_len)) => (Some(r.read_bits(cpb_len)?), Some(r.read_bits(dpb_len)?)),
+        None => (None, None),
+    };
+
+    let mut pic_struct = None;
+    let mut clock_timestamps = Vec::new();
+    if params.pic_struct_present {
+        let value = r.read_bits(4)? as u8;
+        pic_struct = Some(value);
+        let num_clock_ts = match value {
+            0..=2 => 1,
+            3 | 4 | 7 => 2,
+            5 | 6 | 8 => 3,
+            _ => 0,
+        };
+        for _ in 0..num_clock_ts {
+            if r.read_bit()? {
+                clock_timestamps.push(parse_clock_timestamp(&mut r, params.time_offset_length)?);
+            }
+        }
+    }
+
+    Some(PictureTiming {
+        cpb_removal_delay,
+        dpb_output_delay,
+        pic_struct,
+        clock_timestamps,
+    })
+}
+
+/// Parse one clock timestamp of a pic_timing() SEI, after its flag
+fn parse_clock_timestamp(r: &mut BitReader<'_>, time_offset_length: u32) -> Option<ClockTimestamp> {
+    let ct_type = r.read_bits(2)? as u8;
+    r.skip_bits(1)?; // nuit_field_based_flag
+    let counting_type = r.read_bits(5)? as u8;
+    let full_timestamp = r.read_bit()?;
+    let discontinuity = r.read_bit()?;
+    let cnt_dropped = r.read_bit()?;
+    let n_frames = r.read_bits(8)? as u8;
+
+    let (mut seconds, mut minutes, mut hours) = (None, None, None);
+    if full_timestamp {
+        seconds = Some(r.read_bits(6)? as u8);
+        minutes = Some(r.read_bits(6)? as u8);
+        hours = Some(r.read_bits(5)? as u8);
+    } else if r.read_bit()? {
+        seconds = Some(r.read_bits(6)? as u8);
+        if r.read_bit()? {
+            minutes = Some(r.read_bits(6)? as u8);
+            if r.read_bit()? {
+                hours = Some(r.read_bits(5)? as u8);
+            }
+        }
+    }
+
+    let time_offset = if time_offset_length > 0 {
+        // Two's complement of time_offset_length bits
+        let raw = r.read_bits(time_offset_length)?;
+        let shift = 32 - time_offset_length;
+        ((raw << shift) as i32) >> shift
+    } else {
+        0
+    };
+
+    Some(ClockTimestamp {
+        ct_type,
+        counting_type,
+        discontinuity,
+        cnt_dropped,
+        n_frames,
+        seconds,
+        minutes,
+        hours,
+        time_offset,
+    })
+}
+
+/// Find a picture timing message in an SEI NAL unit (including the NAL
+/// header) and return its payload
+fn find_pic_timing_payload(sei: &[u8]) -> Option<Vec<u8>> {
+    let rbsp = nalu_to_rbsp(sei.get(1..)?);
+    let mut data = rbsp.as_slice();
+
+    // sei_message()s until the trailing bits
+    while data.len() > 1 || data.first().is_some_and(|&b| b != 0x80) {
+        let payload_type = read_sei_varint(&mut data)?;
+        let payload_size = read_sei_varint(&mut data)? as usize;
+        let payload = data.get(..payload_size)?;
+        if payload_type == 1 {
+            return Some(payload.to_vec());
+        }
+        data = &data[payload_size..];
+    }
+    None
+}
+
+/// Read an SEI payload type or size: 0xFF bytes add 255 until a final byte
+fn read_sei_varint(data: &mut &[u8]) -> Option<u32> {
+    let mut value = 0u32;
+    loop {
+        let (&b, rest) = data.split_first()?;
+        *data = rest;
+        value = value.checked_add(b as u32)?;
+        if b != 0xFF {
+            return Some(value);
+        }
+    }
+}
+
+/// Skip a scaling_list() structure in an SPS
+fn skip_scaling_list(r: &mut BitReader<'_>, size: usize) -> Option<()> {
+    let mut last_scale = 8i32;
+    let mut next_scale = 8i32;
+    for _ in 0..size {
+        if next_scale != 0 {
+            let delta = r.read_se()?;
+            next_scale = (last_scale + delta + 256) % 256;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Some(())
+}
+
+/// MSB-first bit reader with Exp-Golomb support
+///
+/// Used for SPS parsing here and for AudioSpecificConfig in the AAC module.
+pub(super) struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub(super) fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Number of unread bits
+    pub(super) fn bits_left(&self) -> usize {
+        (self.data.len() * 8).saturating_sub(self.pos)
+    }
+
+    pub(super) fn read_bit(&mut self) -> Option<bool> {
+        let byte = *self.data.get(self.pos / 8)?;
+        let bit = (byte >> (7 - (self.pos % 8))) & 1;
+        self.pos += 1;
+        Some(bit == 1)
+    }
+
+    pub(super) fn read_bits(&mut self, n: u32) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..n {
+            value = (value << 1) | self.read_bit()? as u32;
+        }
+        Some(value)
+    }
+
+    pub(super) fn skip_bits(&mut self, n: usize) -> Option<()> {
+        if self.pos + n > self.data.len() * 8 {
+            return None;
+        }
+        self.pos += n;
+        Some(())
+    }
+
+    /// Unsigned Exp-Golomb code
+    fn read_ue(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+        while !self.read_bit()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return None;
+            }
+        }
+        Some((1u32 << leading_zeros) - 1 + self.read_bits(leading_zeros)?)
+    }
+
+    /// Signed Exp-Golomb code
+    fn read_se(&mut self) -> Option<i32> {
+        let k = self.read_ue()?;
+        let magnitude = k.div_ceil(2) as i32;
+        Some(if k % 2 == 1 { magnitude } else { -magnitude })
+    }
 }
 
 impl H264Data {
@@ -268,6 +827,37 @@ impl H264Data {
         }
     }
 
+    /// Parse from untrusted input, enforcing `limits`
+    ///
+    /// Frames may hold at most `max_collection_len` NAL units (assuming
+    /// 4-byte lengths, as [`H264Data::parse`] does for keyframe detection).
+    pub fn decode_checked(data: Bytes, limits: DecodeLimits) -> Result<Self> {
+        limits.check_media_input(data.len())?;
+
+        match Self::parse(data)? {
+            H264Data::SequenceHeader(config) => {
+                limits.check_media_len(config.sps.len() + config.pps.len())?;
+                Ok(H264Data::SequenceHeader(config))
+            }
+            H264Data::Frame {
+                keyframe,
+                composition_time,
+                nalus,
+            } => {
+                let count = NaluIterator::new(&nalus, 4)
+                    .take(limits.max_collection_len.saturating_add(1))
+                    .count();
+                limits.check_media_len(count)?;
+                Ok(H264Data::Frame {
+                    keyframe,
+                    composition_time,
+                    nalus,
+                })
+            }
+            H264Data::EndOfSequence => Ok(H264Data::EndOfSequence),
+        }
+    }
+
     /// Check if NAL units contain an IDR frame
     fn contains_idr(data: &Bytes) -> bool {
         let mut offset = 0;
@@ -296,6 +886,29 @@ impl H264Data {
         false
     }
 
+    /// Decode the picture timing SEI of a frame
+    ///
+    /// `config` is the stream's sequence header; its SPS VUI says which
+    /// fields the SEI carries. Returns `None` for anything but a frame, if
+    /// the frame has no picture timing SEI, or if the VUI has neither HRD
+    /// parameters nor `pic_struct_present_flag`.
+    pub fn picture_timing(&self, config: &AvcConfig) -> Option<PictureTiming> {
+        let H264Data::Frame { nalus, .. } = self else {
+            return None;
+        };
+        let params = config.pic_timing_params()?;
+        if params.delay_lengths.is_none() && !params.pic_struct_present {
+            return None;
+        }
+
+        NaluIterator::new(nalus, config.nalu_length_size)
+            .filter(|nalu| {
+                nalu.first().and_then(|&b| NaluType::from_byte(b)) == Some(NaluType::Sei)
+            })
+            .find_map(find_pic_timing_payload)
+            .and_then(|payload| parse_pic_timing(&payload, params))
+    }
+
     /// Check if this is a keyframe
     pub fn is_keyframe(&self) -> bool {
         match self {
@@ -312,6 +925,9 @@ impl H264Data {
 }
 
 /// Iterator over NAL units in AVCC format
+///
+/// Iteration stops at the first length prefix that overruns the data; use
+/// [`next_checked`](Self::next_checked) to tell that apart from a clean end.
 pub struct NaluIterator<'a> {
     data: &'a [u8],
     offset: usize,
@@ -326,30 +942,53 @@ impl<'a> NaluIterator<'a> {
             nalu_length_size: nalu_length_size as usize,
         }
     }
-}
-
-impl<'a> Iterator for NaluIterator<'a> {
-    type Item = &'a [u8];
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.offset + self.nalu_length_size > self.data.len() {
+    /// Get the next NAL unit, reporting truncated data
+    ///
+    /// Returns None once the data is used up exactly, and an error for
+    /// trailing bytes that do not hold a whole NAL unit. Iteration ends
+    /// after an error.
+    pub fn next_checked(&mut self) -> Option<std::result::Result<&'a [u8], NaluError>> {
+        let start = self.offset;
+        let remaining = self.data.len() - start;
+        if remaining == 0 {
             return None;
         }
 
+        // Nothing after an error is trusted
+        self.offset = self.data.len();
+        if remaining < self.nalu_length_size {
+            return Some(Err(NaluError::TruncatedLength {
+                offset: start,
+                remaining,
+            }));
+        }
+
         // Read length (big-endian)
         let mut len: usize = 0;
         for i in 0..self.nalu_length_size {
-            len = (len << 8) | (self.data[self.offset + i] as usize);
+            len = (len << 8) | (self.data[start + i] as usize);
         }
-        self.offset += self.nalu_length_size;
 
-        if self.offset + len > self.data.len() {
-            return None;
+        let nalu_start = start + self.nalu_length_size;
+        if len > self.data.len() - nalu_start {
+            return Some(Err(NaluError::TruncatedNalu {
+                offset: start,
+                length: len,
+                remaining: self.data.len() - nalu_start,
+            }));
         }
 
-        let nalu = &self.data[self.offset..self.offset + len];
-        self.offset += len;
-        Some(nalu)
+        self.offset = nalu_start + len;
+        Some(Ok(&self.data[nalu_start..self.offset]))
+    }
+}
+
+impl<'a> Iterator for NaluIterator<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_checked()?.ok()
     }
 }
 
@@ -395,6 +1034,339 @@ mod tests {
         assert_eq!(config.raw, data);
     }
 
+    #[test]
+    fn test_avc_config_dimensions() {
+        // Baseline 1920x1088 coded, cropped to 1080
+        let baseline = AvcConfig {
+            profile: 66,
+            compatibility: 0xC0,
+            level: 40,
+            nalu_length_size: 4,
+            sps: vec![Bytes::from_static(&[
+                0x67, 0x42, 0xC0, 0x28, 0xF4, 0x03, 0xC0, 0x11, 0x3F, 0x2A,
+            ])],
+            pps: vec![],
+            raw: Bytes::new(),
+        };
+        assert_eq!(baseline.dimensions(), Some((1920, 1080)));
+
+        // High profile (chroma_format_idc present), 1280x720, no cropping
+        let high = AvcConfig {
+            profile: 100,
+            sps: vec![Bytes::from_static(&[
+                0x67, 0x64, 0x00, 0x1F, 0xAE, 0x59, 0x40, 0x50, 0x05, 0xB9,
+            ])],
+            ..baseline.clone()
+        };
+        assert_eq!(high.dimensions(), Some((1280, 720)));
+
+        // Truncated SPS
+        let truncated = AvcConfig {
+            sps: vec![Bytes::from_static(&[0x67, 0x64, 0x00, 0x1F])],
+            ..baseline.clone()
+        };
+        assert_eq!(truncated.dimensions(), None);
+
+        let no_sps = AvcConfig {
+            sps: vec![],
+            ..baseline
+        };
+        assert_eq!(no_sps.dimensions(), None);
+    }
+
+    /// MSB-first bit writer for building test bitstreams
+    #[derive(Default)]
+    struct BitWriter {
+        bits: Vec<bool>,
+    }
+
+    impl BitWriter {
+        fn put(&mut self, n: u32, value: u32) -> &mut Self {
+            for i in (0..n).rev() {
+                self.bits.push((value >> i) & 1 == 1);
+            }
+            self
+        }
+
+        fn put_ue(&mut self, value: u32) -> &mut Self {
+            let code = value + 1;
+            let len = 32 - code.leading_zeros();
+            self.put(len - 1, 0).put(len, code)
+        }
+
+        /// Append rbsp_trailing_bits and return the bytes
+        // `is_multiple_of` needs a newer toolchain than the crate supports
+        #[allow(clippy::manual_is_multiple_of)]
+        fn finish_rbsp(&mut self) -> Vec<u8> {
+            self.put(1, 1);
+            while self.bits.len() % 8 != 0 {
+                self.bits.push(false);
+            }
+            self.bytes()
+        }
+
+        fn bytes(&self) -> Vec<u8> {
+            self.bits
+                .chunks(8)
+                .map(|byte| byte.iter().fold(0, |acc, &bit| (acc << 1) | bit as u8))
+                .collect()
+        }
+    }
+
+    /// Baseline SPS with the given macroblock counts and frame cropping
+    fn sps_with_size(width_in_mbs_minus1: u32, crop: Option<(u32, u32)>) -> Bytes {
+        let mut w = BitWriter::default();
+        w.put(8, 66).put(8, 0).put(8, 31); // profile, constraints, level
+        w.put_ue(0).put_ue(0).put_ue(2).put_ue(1); // id, frame_num, poc type, refs
+        w.put(1, 0); // gaps_in_frame_num_value_allowed_flag
+        w.put_ue(width_in_mbs_minus1).put_ue(44);
+        w.put(1, 1).put(1, 1); // frame_mbs_only, direct_8x8_inference
+        match crop {
+            Some((left, right)) => {
+                w.put(1, 1).put_ue(left).put_ue(right).put_ue(0).put_ue(0);
+            }
+            None => {
+                w.put(1, 0);
+            }
+        }
+        w.put(1, 0); // vui_parameters_present_flag
+        Bytes::from(nalu(0x67, &w.finish_rbsp()))
+    }
+
+    #[test]
+    fn test_sps_dimension_overflow() {
+        let config = |sps: Bytes| AvcConfig {
+            profile: 66,
+            compatibility: 0,
+            level: 31,
+            nalu_length_size: 4,
+            sps: vec![sps],
+            pps: vec![],
+            raw: Bytes::new(),
+        };
+
+        assert_eq!(
+            config(sps_with_size(79, None)).dimensions(),
+            Some((1280, 720))
+        );
+
+        // Width in macroblocks times 16 exceeds u32
+        assert_eq!(config(sps_with_size(0x1000_0000, None)).dimensions(), None);
+
+        // Crop offsets whose sum, or sum times the crop unit, exceeds u32
+        let huge = u32::MAX - 1;
+        assert_eq!(
+            config(sps_with_size(79, Some((huge, huge)))).dimensions(),
+            None
+        );
+        assert_eq!(
+            config(sps_with_size(79, Some((1 << 31, 0)))).dimensions(),
+            None
+        );
+    }
+
+    /// Wrap an RBSP in a NAL unit, inserting emulation prevention bytes
+    fn nalu(header: u8, rbsp: &[u8]) -> Vec<u8> {
+        let mut out = vec![header];
+        let mut zeros = 0;
+        for &b in rbsp {
+            if zeros >= 2 && b <= 0x03 {
+                out.push(0x03);
+                zeros = 0;
+            }
+            zeros = if b == 0 { zeros + 1 } else { 0 };
+            out.push(b);
+        }
+        out
+    }
+
+    /// Baseline 1280x720 SPS; `pic_struct_present` adds a VUI with NAL HRD
+    /// parameters (24 bit CPB delay, 5 bit DPB delay, 8 bit time offset)
+    fn sps_with_vui(pic_struct_present: bool) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.put(8, 66).put(8, 0).put(8, 31); // profile, constraints, level
+        w.put_ue(0); // seq_parameter_set_id
+        w.put_ue(0); // log2_max_frame_num_minus4
+        w.put_ue(2); // pic_order_cnt_type
+        w.put_ue(1); // max_num_ref_frames
+        w.put(1, 0); // gaps_in_frame_num_value_allowed_flag
+        w.put_ue(79).put_ue(44); // 80x45 macroblocks
+        w.put(1, 1).put(1, 1); // frame_mbs_only, direct_8x8_inference
+        w.put(1, 0); // frame_cropping_flag
+        w.put(1, 1); // vui_parameters_present_flag
+        w.put(1, 0).put(1, 0).put(1, 0).put(1, 0); // aspect, overscan, signal, chroma loc
+        w.put(1, 1).put(32, 1001).put(32, 60000).put(1, 1); // timing info
+        w.put(1, 1); // nal_hrd_parameters_present_flag
+        w.put_ue(0).put(4, 0).put(4, 0); // cpb_cnt_minus1, scales
+        w.put_ue(0).put_ue(0).put(1, 0); // bit rate, cpb size, cbr
+        w.put(5, 23).put(5, 23).put(5, 4).put(5, 8); // delay and offset lengths
+        w.put(1, 0); // vcl_hrd_parameters_present_flag
+        w.put(1, 0); // low_delay_hrd_flag
+        w.put(1, pic_struct_present as u32);
+        w.put(1, 0); // bitstream_restriction_flag
+        nalu(0x67, &w.finish_rbsp())
+    }
+
+    #[test]
+    fn test_picture_timing_sei() {
+        // pic_timing(): delays, then a frame with one clock timestamp
+        let mut pic_timing = BitWriter::default();
+        pic_timing.put(24, 2).put(5, 4); // cpb_removal_delay, dpb_output_delay
+        pic_timing.put(4, 0); // pic_struct: frame
+        pic_timing.put(1, 1); // clock_timestamp_flag
+        pic_timing.put(2, 0).put(1, 0).put(5, 4); // ct_type, nuit, counting_type
+        pic_timing.put(1, 1).put(1, 0).put(1, 1); // full, discontinuity, cnt_dropped
+        pic_timing.put(8, 12); // n_frames
+        pic_timing.put(6, 34).put(6, 56).put(5, 7); // seconds, minutes, hours
+        pic_timing.put(8, 0xFD); // time_offset: -3
+        let pic_timing = pic_timing.finish_rbsp();
+
+        // An unregistered user data message precedes the picture timing
+        let mut sei = vec![5, 17];
+        sei.extend_from_slice(&[0u8; 17]);
+        sei.push(1);
+        sei.push(pic_timing.len() as u8);
+        sei.extend_from_slice(&pic_timing);
+        sei.push(0x80);
+        let sei = nalu(0x06, &sei);
+
+        let mut nalus = Vec::new();
+        for nal in [&sei[..], &[0x65, 0x88, 0x84]] {
+            nalus.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+            nalus.extend_from_slice(nal);
+        }
+        let frame = H264Data::Frame {
+            keyframe: true,
+            composition_time: 0,
+            nalus: Bytes::from(nalus),
+        };
+
+        let config = AvcConfig {
+            profile: 66,
+            compatibility: 0,
+            level: 31,
+            nalu_length_size: 4,
+            sps: vec![Bytes::from(sps_with_vui(true))],
+            pps: vec![],
+            raw: Bytes::new(),
+        };
+        assert_eq!(config.dimensions(), Some((1280, 720)));
+
+        let timing = frame.picture_timing(&config).expect("no picture timing");
+        assert_eq!(timing.cpb_removal_delay, Some(2));
+        assert_eq!(timing.dpb_output_delay, Some(4));
+        assert_eq!(timing.pic_struct, Some(0));
+        let timecode = timing.timecode().unwrap();
+        assert_eq!(timecode.counting_type, 4);
+        assert!(timecode.cnt_dropped);
+        assert!(!timecode.discontinuity);
+        assert_eq!(timecode.hours, Some(7));
+        assert_eq!(timecode.minutes, Some(56));
+        assert_eq!(timecode.seconds, Some(34));
+        assert_eq!(timecode.n_frames, 12);
+        assert_eq!(timecode.time_offset, -3);
+        assert_eq!(timecode.to_string(), "07:56:34;12");
+
+        // Without pic_struct_present_flag the same bits are delays only
+        let no_pic_struct = AvcConfig {
+            sps: vec![Bytes::from(sps_with_vui(false))],
+            ..config.clone()
+        };
+        let timing = frame.picture_timing(&no_pic_struct).unwrap();
+        assert_eq!(timing.cpb_removal_delay, Some(2));
+        assert_eq!(timing.pic_struct, None);
+        assert!(timing.timecode().is_none());
+
+        // An SPS without VUI gives nothing to decode against
+        let no_vui = AvcConfig {
+            sps: vec![Bytes::from_static(&[
+                0x67, 0x42, 0xC0, 0x28, 0xF4, 0x03, 0xC0, 0x11, 0x3F, 0x2A,
+            ])],
+            ..config
+        };
+        assert_eq!(frame.picture_timing(&no_vui), None);
+    }
+
+    /// Baseline 1280x720 SPS with a VUI of the given aspect_ratio_idc
+    /// (and SAR for 255) and timing info, with nothing after it
+    fn sps_with_vui_fields(aspect: Option<(u32, u32, u32)>, timing: Option<(u32, u32)>) -> Bytes {
+        let mut w = BitWriter::default();
+        w.put(8, 66).put(8, 0).put(8, 31); // profile, constraints, level
+        w.put_ue(0).put_ue(0).put_ue(2).put_ue(1); // id, frame_num, poc type, refs
+        w.put(1, 0); // gaps_in_frame_num_value_allowed_flag
+        w.put_ue(79).put_ue(44); // 80x45 macroblocks
+        w.put(1, 1).put(1, 1).put(1, 0); // frame_mbs_only, direct_8x8, cropping
+        w.put(1, 1); // vui_parameters_present_flag
+        match aspect {
+            Some((idc, sar_width, sar_height)) => {
+                w.put(1, 1).put(8, idc);
+                if idc == 255 {
+                    w.put(16, sar_width).put(16, sar_height);
+                }
+            }
+            None => {
+                w.put(1, 0);
+            }
+        }
+        w.put(1, 0).put(1, 0).put(1, 0); // overscan, signal, chroma loc
+        match timing {
+            Some((num_units_in_tick, time_scale)) => {
+                w.put(1, 1)
+                    .put(32, num_units_in_tick)
+                    .put(32, time_scale)
+                    .put(1, 1);
+            }
+            None => {
+                w.put(1, 0);
+            }
+        }
+        w.put(1, 0).put(1, 0); // nal and vcl hrd_parameters_present_flag
+        w.put(1, 0).put(1, 0); // pic_struct_present, bitstream_restriction
+        Bytes::from(nalu(0x67, &w.finish_rbsp()))
+    }
+
+    #[test]
+    fn test_vui_frame_rate_and_aspect_ratio() {
+        let config = |sps: Bytes| AvcConfig {
+            profile: 66,
+            compatibility: 0,
+            level: 31,
+            nalu_length_size: 4,
+            sps: vec![sps],
+            pps: vec![],
+            raw: Bytes::new(),
+        };
+
+        // 29.97fps, HRD parameters after the timing info
+        let ntsc = config(Bytes::from(sps_with_vui(true)));
+        let fps = ntsc.frame_rate().unwrap();
+        assert!((fps - 29.97).abs() < 0.001, "{}", fps);
+        assert_eq!(ntsc.sample_aspect_ratio(), None);
+
+        // Extended SAR
+        let pal = config(sps_with_vui_fields(Some((255, 64, 45)), Some((1, 50))));
+        assert_eq!(pal.frame_rate(), Some(25.0));
+        assert_eq!(pal.sample_aspect_ratio(), Some((64, 45)));
+        assert_eq!(pal.dimensions(), Some((1280, 720)));
+
+        // SAR from the table, no timing info
+        let anamorphic = config(sps_with_vui_fields(Some((14, 0, 0)), None));
+        assert_eq!(anamorphic.frame_rate(), None);
+        assert_eq!(anamorphic.sample_aspect_ratio(), Some((4, 3)));
+
+        // Unspecified SAR and a zero tick are ignored
+        let unspecified = config(sps_with_vui_fields(Some((0, 0, 0)), Some((0, 50))));
+        assert_eq!(unspecified.sample_aspect_ratio(), None);
+        assert_eq!(unspecified.frame_rate(), None);
+
+        // No VUI at all
+        let no_vui = config(Bytes::from_static(&[
+            0x67, 0x42, 0xC0, 0x28, 0xF4, 0x03, 0xC0, 0x11, 0x3F, 0x2A,
+        ]));
+        assert_eq!(no_vui.frame_rate(), None);
+        assert_eq!(no_vui.sample_aspect_ratio(), None);
+    }
+
     #[test]
     fn test_avc_packet_type() {
         assert_eq!(
@@ -513,6 +1485,60 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_h264_decode_checked_nalu_count() {
+        // Video tag body holding eight empty NAL units
+        let mut body = vec![0x01, 0x00, 0x00, 0x00];
+        body.extend_from_slice(&[0x00; 4 * 8]);
+        let data = Bytes::from(body);
+
+        let limits = DecodeLimits::default().max_collection_len(8);
+        assert!(H264Data::decode_checked(data.clone(), limits).is_ok());
+
+        let limits = DecodeLimits::default().max_collection_len(7);
+        assert!(matches!(
+            H264Data::decode_checked(data, limits),
+            Err(crate::error::Error::Media(MediaError::LengthExceeded {
+                declared: 8,
+                limit: 7
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_h264_decode_checked_input_size() {
+        let mut body = vec![0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05];
+        body.extend_from_slice(&[0x65, 0x88, 0x84, 0x00, 0x00]);
+        let data = Bytes::from(body);
+
+        let limits = DecodeLimits::default().max_input_size(12);
+        assert!(matches!(
+            H264Data::decode_checked(data, limits),
+            Err(crate::error::Error::Media(MediaError::InputTooLarge {
+                size: 13,
+                limit: 12
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_avc_config_decode_checked_parameter_set_count() {
+        let data = Bytes::from_static(&AVC_CONFIG);
+
+        let limits = DecodeLimits::default().max_collection_len(2);
+        let config = AvcConfig::decode_checked(data.clone(), limits).unwrap();
+        assert_eq!(config.sps.len() + config.pps.len(), 2);
+
+        let limits = DecodeLimits::default().max_collection_len(1);
+        assert!(matches!(
+            parse_error_checked(&AVC_CONFIG, limits),
+            MediaError::LengthExceeded {
+                declared: 2,
+                limit: 1
+            }
+        ));
+    }
+
     #[test]
     fn test_h264_data_nalu_p_frame() {
         // Create NALU data with non-IDR slice
@@ -659,6 +1685,90 @@ mod tests {
         assert!(result.is_err());
     }
 
+    /// Valid record with one 4-byte SPS and one 3-byte PPS
+    const AVC_CONFIG: [u8; 18] = [
+        0x01, 0x64, 0x00, 0x1F, 0xFF, 0xE1, 0x00, 0x04, 0x67, 0x64, 0x00, 0x1F, 0x01, 0x00, 0x03,
+        0x68, 0xEF, 0x38,
+    ];
+
+    fn parse_error(data: &[u8]) -> MediaError {
+        match AvcConfig::parse(Bytes::copy_from_slice(data)) {
+            Err(crate::error::Error::Media(e)) => e,
+            other => panic!("expected media error for {:02X?}, got {:?}", data, other),
+        }
+    }
+
+    fn parse_error_checked(data: &[u8], limits: DecodeLimits) -> MediaError {
+        match AvcConfig::decode_checked(Bytes::copy_from_slice(data), limits) {
+            Err(crate::error::Error::Media(e)) => e,
+            other => panic!("expected media error for {:02X?}, got {:?}", data, other),
+        }
+    }
+
+    #[test]
+    fn test_avc_config_sps_count_overrun() {
+        // 31 SPS declared but only room for a few length prefixes
+        let mut data = AVC_CONFIG;
+        data[5] = 0xFF;
+        assert!(matches!(parse_error(&data), MediaError::SpsOverrun));
+    }
+
+    #[test]
+    fn test_avc_config_sps_length_overrun() {
+        let mut data = AVC_CONFIG;
+        data[7] = 0xF0; // SPS length 240
+        assert!(matches!(parse_error(&data), MediaError::SpsOverrun));
+    }
+
+    #[test]
+    fn test_avc_config_pps_length_overrun() {
+        let mut data = AVC_CONFIG;
+        data[14] = 0x20; // PPS length 32
+        assert!(matches!(parse_error(&data), MediaError::PpsOverrun));
+    }
+
+    #[test]
+    fn test_avc_config_pps_count_overrun() {
+        let mut data = AVC_CONFIG;
+        data[12] = 0xFF; // 255 PPS
+        assert!(matches!(parse_error(&data), MediaError::PpsOverrun));
+    }
+
+    #[test]
+    fn test_avc_config_truncated_records() {
+        // Every truncation of a valid record is rejected without panicking
+        for len in 0..AVC_CONFIG.len() {
+            let result = AvcConfig::parse(Bytes::copy_from_slice(&AVC_CONFIG[..len]));
+            assert!(result.is_err(), "truncated to {} bytes", len);
+        }
+
+        // Cut inside the SPS list, before the PPS count, and inside the PPS list
+        assert!(matches!(
+            parse_error(&AVC_CONFIG[..10]),
+            MediaError::SpsOverrun
+        ));
+        assert!(matches!(
+            parse_error(&AVC_CONFIG[..12]),
+            MediaError::PpsOverrun
+        ));
+        assert!(matches!(
+            parse_error(&AVC_CONFIG[..16]),
+            MediaError::PpsOverrun
+        ));
+    }
+
+    #[test]
+    fn test_avc_config_mutated_records() {
+        // Corrupting any single byte may change the result but never panics
+        for i in 0..AVC_CONFIG.len() {
+            for value in [0x00, 0x01, 0x7F, 0x80, 0xFF] {
+                let mut data = AVC_CONFIG;
+                data[i] = value;
+                let _ = AvcConfig::parse(Bytes::copy_from_slice(&data));
+            }
+        }
+    }
+
     #[test]
     fn test_nalu_iterator() {
         // Create AVCC-format data with multiple NALUs
@@ -713,4 +1823,44 @@ mod tests {
         let mut iter = NaluIterator::new(data, 4);
         assert!(iter.next().is_none()); // Should return None for truncated data
     }
+
+    #[test]
+    fn test_nalu_iterator_next_checked() {
+        // A whole NALU, then one whose length overruns the data
+        let data: &[u8] = &[
+            0x00, 0x00, 0x00, 0x02, // length = 2
+            0x65, 0x88, // IDR NALU
+            0x00, 0x00, 0x00, 0x05, // length = 5
+            0x41, 0x9A, // Only 2 bytes
+        ];
+
+        let mut iter = NaluIterator::new(data, 4);
+        assert_eq!(iter.next_checked(), Some(Ok(&data[4..6])));
+        assert_eq!(
+            iter.next_checked(),
+            Some(Err(NaluError::TruncatedNalu {
+                offset: 6,
+                length: 5,
+                remaining: 2,
+            }))
+        );
+        assert_eq!(iter.next_checked(), None);
+
+        // Fewer bytes left than a length prefix
+        let mut iter = NaluIterator::new(&data[..8], 4);
+        assert!(iter.next_checked().unwrap().is_ok());
+        assert_eq!(
+            iter.next_checked(),
+            Some(Err(NaluError::TruncatedLength {
+                offset: 6,
+                remaining: 2,
+            }))
+        );
+
+        // A clean end is not an error, and next() still skips truncation
+        let mut iter = NaluIterator::new(&data[..6], 4);
+        assert!(iter.next_checked().unwrap().is_ok());
+        assert_eq!(iter.next_checked(), None);
+        assert_eq!(NaluIterator::new(data, 4).count(), 1);
+    }
 }
@@ -0,0 +1,123 @@
+//! onMetaData construction
+//!
+//! Players use the `onMetaData` script tag to learn resolution and codec
+//! details up front. When relaying a stream whose publisher never sent one,
+//! an equivalent can be built from the parsed sequence headers.
+
+use bytes::Bytes;
+
+use crate::amf::{Amf0Encoder, AmfObject, AmfValue, PropertyOrder};
+
+use super::aac::AudioSpecificConfig;
+use super::h264::AvcConfig;
+
+/// FLV video codec ID for AVC
+const VIDEO_CODEC_ID_AVC: f64 = 7.0;
+
+/// FLV audio codec ID for AAC
+const AUDIO_CODEC_ID_AAC: f64 = 10.0;
+
+/// Build onMetaData properties from parsed sequence headers
+///
+/// Fields that cannot be derived (e.g. dimensions from an unparseable SPS)
+/// are omitted.
+pub fn from_sequence_headers(
+    video: Option<&AvcConfig>,
+    audio: Option<&AudioSpecificConfig>,
+) -> AmfObject {
+    let mut props = AmfObject::new();
+
+    if let Some(avc) = video {
+        props.insert(
+            "videocodecid".to_string(),
+            AmfValue::Number(VIDEO_CODEC_ID_AVC),
+        );
+        if let Some((width, height)) = avc.dimensions() {
+            props.insert("width".to_string(), AmfValue::Number(width as f64));
+            props.insert("height".to_string(), AmfValue::Number(height as f64));
+        }
+        if let Some(fps) = avc.frame_rate() {
+            props.insert("framerate".to_string(), AmfValue::Number(fps));
+        }
+    }
+
+    if let Some(asc) = audio {
+        props.insert(
+            "audiocodecid".to_string(),
+            AmfValue::Number(AUDIO_CODEC_ID_AAC),
+        );
+        props.insert(
+            "audiosamplerate".to_string(),
+            AmfValue::Number(asc.output_sample_rate() as f64),
+        );
+        let channels = asc.channels();
+        if channels > 0 {
+            props.insert(
+                "audiochannels".to_string(),
+                AmfValue::Number(channels as f64),
+            );
+            props.insert("stereo".to_string(), AmfValue::Boolean(channels >= 2));
+        }
+    }
+
+    props
+}
+
+/// Encode properties as an `onMetaData` data message payload
+///
+/// The result is what subscribers receive as the body of the AMF0 data
+/// message (and what an FLV script tag contains). Properties are written
+/// in sorted key order so the output is stable.
+pub fn encode_on_metadata(props: &AmfObject) -> Bytes {
+    let mut encoder = Amf0Encoder::new().property_order(PropertyOrder::STABLE);
+    encoder.encode(&AmfValue::String("onMetaData".to_string()));
+    encoder.encode(&AmfValue::EcmaArray(props.clone()));
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_sequence_headers() {
+        let avc = AvcConfig {
+            profile: 66,
+            compatibility: 0xC0,
+            level: 40,
+            nalu_length_size: 4,
+            sps: vec![Bytes::from_static(&[
+                0x67, 0x42, 0xC0, 0x28, 0xF4, 0x03, 0xC0, 0x11, 0x3F, 0x2A,
+            ])],
+            pps: vec![],
+            raw: Bytes::new(),
+        };
+        // AAC-LC, 44100 Hz, stereo
+        let asc = AudioSpecificConfig::parse(Bytes::from_static(&[0x12, 0x10])).unwrap();
+
+        let props = from_sequence_headers(Some(&avc), Some(&asc));
+        assert_eq!(props.get("width"), Some(&AmfValue::Number(1920.0)));
+        assert_eq!(props.get("height"), Some(&AmfValue::Number(1080.0)));
+        assert_eq!(props.get("videocodecid"), Some(&AmfValue::Number(7.0)));
+        assert_eq!(props.get("audiocodecid"), Some(&AmfValue::Number(10.0)));
+        assert_eq!(
+            props.get("audiosamplerate"),
+            Some(&AmfValue::Number(44100.0))
+        );
+        assert_eq!(props.get("audiochannels"), Some(&AmfValue::Number(2.0)));
+        assert_eq!(props.get("stereo"), Some(&AmfValue::Boolean(true)));
+
+        assert!(from_sequence_headers(None, None).is_empty());
+    }
+
+    #[test]
+    fn test_encode_on_metadata() {
+        let mut props = AmfObject::new();
+        props.insert("width".to_string(), AmfValue::Number(640.0));
+
+        let data = encode_on_metadata(&props);
+        let values = crate::amf::amf0::decode_all(&data).unwrap();
+        assert_eq!(values[0], AmfValue::String("onMetaData".to_string()));
+        assert_eq!(values[1].get_number("width"), Some(640.0));
+    }
+}
@@ -1,25 +1,40 @@
 //! Media handling for RTMP
 //!
 //! This module provides:
-//! - FLV tag parsing and generation
-//! - H.264/AVC NALU parsing
+//! - FLV tag parsing and generation, FLV file reading and writing
+//! - Keyframe-aligned FLV segmenting
+//! - H.264/AVC NALU parsing, including picture timing SEI timecodes
 //! - AAC frame parsing
+//! - G.711 and MP3 frame parsing
 //! - GOP buffering for late-joiner support
+//! - onMetaData construction from sequence headers
 //! - FOURCC codec identifiers for Enhanced RTMP
-//! - Enhanced video/audio parsing for E-RTMP
+//! - Codec-agnostic parsed video/audio frames
+//! - Enhanced video/audio parsing for E-RTMP, including ModEx signals
 
 pub mod aac;
+pub mod audio;
 pub mod enhanced_audio;
 pub mod enhanced_video;
 pub mod flv;
 pub mod fourcc;
+pub mod frame;
 pub mod gop;
 pub mod h264;
+pub mod metadata;
+pub mod modex;
+pub mod mp3;
+pub mod segment;
 
 pub use aac::{AacData, AacPacketType, AudioSpecificConfig};
+pub use audio::{G711Frame, G711Law};
 pub use enhanced_audio::{AudioPacketType, EnhancedAudioData};
 pub use enhanced_video::{AvMultitrackType, EnhancedVideoData, ExVideoFrameType, VideoPacketType};
-pub use flv::{FlvTag, FlvTagType};
+pub use flv::{AudioParams, FlvReader, FlvTag, FlvTagType, FlvWriter};
 pub use fourcc::{AudioFourCc, FourCC, VideoFourCc};
-pub use gop::GopBuffer;
-pub use h264::{AvcPacketType, H264Data, NaluType};
+pub use frame::{AudioFrame, Av1Data, HevcData, OpusData, VideoFrame};
+pub use gop::{GopBuffer, StreamKind};
+pub use h264::{AvcConfig, AvcPacketType, ClockTimestamp, H264Data, NaluType, PictureTiming};
+pub use modex::ModEx;
+pub use mp3::{Mp3Data, Mp3Frame, Mp3FrameHeader};
+pub use segment::{Segment, SegmentingFlvWriter};
@@ -0,0 +1,177 @@
+//! Enhanced RTMP ModEx signals
+//!
+//! A `ModEx` packet type in an enhanced audio or video tag means one or more
+//! modifier extensions precede the real packet type. Each signal is encoded
+//! as:
+//!
+//! ```text
+//! UI8   size - 1 (0xFF: a UI16 of size - 1 follows)
+//! UI8[] data
+//! UB4   ModEx type | UB4 next packet type
+//! ```
+//!
+//! Signals chain while the next packet type is still `ModEx`.
+//!
+//! Reference: E-RTMP v2 specification - "ModEx"
+
+use bytes::{Buf, Bytes};
+
+/// ModEx type carrying a nanosecond offset to the tag timestamp
+pub const MOD_EX_TIMESTAMP_OFFSET_NANO: u8 = 0;
+
+/// Exclusive upper bound of a nanosecond timestamp offset (one millisecond)
+pub const MAX_TIMESTAMP_OFFSET_NANO: u32 = 1_000_000;
+
+/// Packet type value announcing a ModEx signal (audio and video alike)
+pub(crate) const MOD_EX_PACKET_TYPE: u8 = 7;
+
+/// A single ModEx signal
+///
+/// Unknown types are kept as raw bytes so they can be inspected or
+/// forwarded unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModEx {
+    /// ModEx type (upper 4 bits of the trailing byte)
+    pub mod_ex_type: u8,
+    /// Raw signal data
+    pub data: Bytes,
+}
+
+impl ModEx {
+    /// Get the nanosecond timestamp offset if this is a TimestampOffsetNano signal
+    ///
+    /// The offset refines a millisecond timestamp, so values of a full
+    /// millisecond or more are invalid and ignored.
+    pub fn timestamp_offset_nano(&self) -> Option<u32> {
+        if self.mod_ex_type != MOD_EX_TIMESTAMP_OFFSET_NANO || self.data.len() < 3 {
+            return None;
+        }
+        let d = &self.data;
+        let nanos = ((d[0] as u32) << 16) | ((d[1] as u32) << 8) | (d[2] as u32);
+        (nanos < MAX_TIMESTAMP_OFFSET_NANO).then_some(nanos)
+    }
+}
+
+/// Get the nanosecond timestamp offset of an enhanced audio or video payload
+///
+/// `data` starts at the tag header byte. Returns `None` unless the packet
+/// begins with ModEx signals and one of them carries a valid offset.
+pub fn timestamp_offset_nano(data: &Bytes) -> Option<u32> {
+    if data.first().map(|b| b & 0x0F) != Some(MOD_EX_PACKET_TYPE) {
+        return None;
+    }
+    let (signals, _) = parse_mod_ex(&mut data.slice(1..))?;
+    signals.iter().find_map(ModEx::timestamp_offset_nano)
+}
+
+/// Parse a chain of ModEx signals
+///
+/// `data` must start right after the header byte that announced ModEx. On
+/// success it is advanced past the signals, and the packet type nibble
+/// that follows them is returned. Returns `None` if the input is truncated.
+pub(crate) fn parse_mod_ex(data: &mut Bytes) -> Option<(Vec<ModEx>, u8)> {
+    let mut signals = Vec::new();
+
+    loop {
+        if data.is_empty() {
+            return None;
+        }
+        let mut size = data.get_u8() as usize + 1;
+        if size == 256 {
+            if data.len() < 2 {
+                return None;
+            }
+            size = data.get_u16() as usize + 1;
+        }
+
+        // Signal data plus the type/packet type byte
+        if data.len() < size + 1 {
+            return None;
+        }
+        let signal = data.split_to(size);
+        let type_byte = data.get_u8();
+
+        signals.push(ModEx {
+            mod_ex_type: type_byte >> 4,
+            data: signal,
+        });
+
+        let packet_type = type_byte & 0x0F;
+        if packet_type != MOD_EX_PACKET_TYPE {
+            return Some((signals, packet_type));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp_offset_nano() {
+        // size-1 = 2, offset 0x0186A0 (100000ns), type 0 / next packet type 3
+        let mut data = Bytes::from_static(&[0x02, 0x01, 0x86, 0xA0, 0x03, 0xAA]);
+        let (signals, packet_type) = parse_mod_ex(&mut data).unwrap();
+
+        assert_eq!(packet_type, 3);
+        assert_eq!(signals.len(), 1);
+        assert_eq!(signals[0].timestamp_offset_nano(), Some(100_000));
+        assert_eq!(&data[..], &[0xAA]);
+    }
+
+    #[test]
+    fn test_parse_chained_and_unknown() {
+        let mut data = Bytes::from_static(&[
+            0x00, 0x42, 0x57, // 1 byte of type 5, another ModEx follows
+            0x01, 0x00, 0x10, 0x01, // 2 bytes of type 0, then CodedFrames
+        ]);
+        let (signals, packet_type) = parse_mod_ex(&mut data).unwrap();
+
+        assert_eq!(packet_type, 1);
+        assert_eq!(signals.len(), 2);
+        assert_eq!(signals[0].mod_ex_type, 5);
+        assert_eq!(&signals[0].data[..], &[0x42]);
+        assert_eq!(signals[0].timestamp_offset_nano(), None);
+        assert_eq!(signals[1].mod_ex_type, 0);
+        assert_eq!(signals[1].timestamp_offset_nano(), None); // too short
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn test_parse_extended_size() {
+        let mut raw = vec![0xFF, 0x01, 0x00]; // UI16 size - 1 = 256
+        raw.extend_from_slice(&[0x11; 257]);
+        raw.push(0x61);
+        let mut data = Bytes::from(raw);
+
+        let (signals, packet_type) = parse_mod_ex(&mut data).unwrap();
+        assert_eq!(packet_type, 1);
+        assert_eq!(signals[0].mod_ex_type, 6);
+        assert_eq!(signals[0].data.len(), 257);
+    }
+
+    #[test]
+    fn test_payload_timestamp_offset_nano() {
+        // Enhanced HEVC frame: ModEx(500000ns) then CodedFramesX
+        let data =
+            Bytes::from_static(&[0x97, 0x02, 0x07, 0xA1, 0x20, 0x03, b'h', b'v', b'c', b'1']);
+        assert_eq!(timestamp_offset_nano(&data), Some(500_000));
+
+        // No ModEx packet type
+        let data = Bytes::from_static(&[0x93, b'h', b'v', b'c', b'1']);
+        assert_eq!(timestamp_offset_nano(&data), None);
+
+        // A full millisecond is out of range
+        let data = Bytes::from_static(&[0x97, 0x02, 0x0F, 0x42, 0x40, 0x03]);
+        assert_eq!(timestamp_offset_nano(&data), None);
+    }
+
+    #[test]
+    fn test_parse_truncated() {
+        assert!(parse_mod_ex(&mut Bytes::new()).is_none());
+        assert!(parse_mod_ex(&mut Bytes::from_static(&[0x02, 0x01, 0x86])).is_none());
+        assert!(parse_mod_ex(&mut Bytes::from_static(&[0xFF, 0x01])).is_none());
+        // Chain that never ends
+        assert!(parse_mod_ex(&mut Bytes::from_static(&[0x00, 0x00, 0x07])).is_none());
+    }
+}
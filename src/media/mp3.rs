@@ -0,0 +1,388 @@
+//! MP3 (MPEG audio) frame parsing
+//!
+//! Older encoders publish MP3 audio (SoundFormat 2, or 14 for 8 kHz MP3).
+//! Each RTMP message carries one or more complete MPEG audio frames after
+//! the FLV audio byte.
+//!
+//! MPEG Audio Frame Header:
+//! ```text
+//! +------------+---------+-------+-----+---------+------------+-----+-----+---------+------+
+//! | Sync (11)  | Ver (2) | Layer | CRC | Bitrate | SampleRate | Pad | Priv| ChMode  | ...  |
+//! | 0x7FF      |         | (2)   | (1) | (4)     | (2)        | (1) | (1) | (2)     | (6)  |
+//! +------------+---------+-------+-----+---------+------------+-----+-----+---------+------+
+//! ```
+//!
+//! Bitrate index 0 means "free format": the frame length is not derivable
+//! from the header and is found by scanning for the next sync word.
+
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::error::{MediaError, Result};
+
+/// MPEG audio version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MpegVersion {
+    /// MPEG-1
+    Mpeg1,
+    /// MPEG-2 (LSF)
+    Mpeg2,
+    /// MPEG-2.5 (unofficial extension)
+    Mpeg25,
+}
+
+/// MPEG audio layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MpegLayer {
+    Layer1,
+    Layer2,
+    Layer3,
+}
+
+/// MPEG audio channel mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelMode {
+    Stereo,
+    JointStereo,
+    DualChannel,
+    Mono,
+}
+
+/// Bitrates in kbps, indexed by [table][bitrate_index]
+const BITRATES: [[u32; 15]; 5] = [
+    // MPEG-1 Layer I
+    [
+        0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+    ],
+    // MPEG-1 Layer II
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+    ],
+    // MPEG-1 Layer III
+    [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ],
+    // MPEG-2/2.5 Layer I
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+    ],
+    // MPEG-2/2.5 Layer II & III
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+/// Sample rates in Hz for MPEG-1; halved for MPEG-2, quartered for MPEG-2.5
+const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+/// Parsed MPEG audio frame header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mp3FrameHeader {
+    /// MPEG version
+    pub version: MpegVersion,
+    /// Layer
+    pub layer: MpegLayer,
+    /// Whether a 16-bit CRC follows the header
+    pub has_crc: bool,
+    /// Bitrate in bits per second (0 for free format)
+    pub bitrate: u32,
+    /// Sample rate in Hz
+    pub sample_rate: u32,
+    /// Whether the frame has a padding slot
+    pub padding: bool,
+    /// Channel mode
+    pub channel_mode: ChannelMode,
+}
+
+impl Mp3FrameHeader {
+    /// Header size in bytes
+    pub const SIZE: usize = 4;
+
+    /// Parse a 4-byte frame header
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < Self::SIZE || data[0] != 0xFF || (data[1] & 0xE0) != 0xE0 {
+            return Err(MediaError::InvalidMp3Frame.into());
+        }
+
+        let version = match (data[1] >> 3) & 0x03 {
+            0 => MpegVersion::Mpeg25,
+            2 => MpegVersion::Mpeg2,
+            3 => MpegVersion::Mpeg1,
+            _ => return Err(MediaError::InvalidMp3Frame.into()),
+        };
+        let layer = match (data[1] >> 1) & 0x03 {
+            1 => MpegLayer::Layer3,
+            2 => MpegLayer::Layer2,
+            3 => MpegLayer::Layer1,
+            _ => return Err(MediaError::InvalidMp3Frame.into()),
+        };
+        let has_crc = data[1] & 0x01 == 0;
+
+        let bitrate_index = (data[2] >> 4) as usize;
+        if bitrate_index == 0x0F {
+            return Err(MediaError::InvalidMp3Frame.into());
+        }
+        let table = match (version, layer) {
+            (MpegVersion::Mpeg1, MpegLayer::Layer1) => 0,
+            (MpegVersion::Mpeg1, MpegLayer::Layer2) => 1,
+            (MpegVersion::Mpeg1, MpegLayer::Layer3) => 2,
+            (_, MpegLayer::Layer1) => 3,
+            _ => 4,
+        };
+        let bitrate = BITRATES[table][bitrate_index] * 1000;
+
+        let rate_index = ((data[2] >> 2) & 0x03) as usize;
+        if rate_index == 3 {
+            return Err(MediaError::InvalidMp3Frame.into());
+        }
+        let sample_rate = match version {
+            MpegVersion::Mpeg1 => SAMPLE_RATES[rate_index],
+            MpegVersion::Mpeg2 => SAMPLE_RATES[rate_index] / 2,
+            MpegVersion::Mpeg25 => SAMPLE_RATES[rate_index] / 4,
+        };
+
+        let padding = data[2] & 0x02 != 0;
+        let channel_mode = match data[3] >> 6 {
+            0 => ChannelMode::Stereo,
+            1 => ChannelMode::JointStereo,
+            2 => ChannelMode::DualChannel,
+            _ => ChannelMode::Mono,
+        };
+
+        Ok(Mp3FrameHeader {
+            version,
+            layer,
+            has_crc,
+            bitrate,
+            sample_rate,
+            padding,
+            channel_mode,
+        })
+    }
+
+    /// Check if this is a free-format frame (bitrate not signalled)
+    pub fn is_free_format(&self) -> bool {
+        self.bitrate == 0
+    }
+
+    /// Get channel count
+    pub fn channels(&self) -> u8 {
+        match self.channel_mode {
+            ChannelMode::Mono => 1,
+            _ => 2,
+        }
+    }
+
+    /// Get samples per frame
+    pub fn samples_per_frame(&self) -> u32 {
+        match (self.layer, self.version) {
+            (MpegLayer::Layer1, _) => 384,
+            (MpegLayer::Layer2, _) => 1152,
+            (MpegLayer::Layer3, MpegVersion::Mpeg1) => 1152,
+            (MpegLayer::Layer3, _) => 576,
+        }
+    }
+
+    /// Get the frame length in bytes, including the header
+    ///
+    /// Returns `None` for free-format frames.
+    pub fn frame_len(&self) -> Option<usize> {
+        if self.is_free_format() {
+            return None;
+        }
+
+        let len = match self.layer {
+            MpegLayer::Layer1 => (12 * self.bitrate / self.sample_rate + self.padding as u32) * 4,
+            _ => {
+                self.samples_per_frame() / 8 * self.bitrate / self.sample_rate + self.padding as u32
+            }
+        };
+        Some(len as usize)
+    }
+
+    /// Get the playback duration of one frame
+    pub fn duration(&self) -> Duration {
+        Duration::from_nanos(
+            self.samples_per_frame() as u64 * 1_000_000_000 / self.sample_rate as u64,
+        )
+    }
+
+    /// Check if another header could belong to the same stream
+    fn same_stream(&self, other: &Mp3FrameHeader) -> bool {
+        self.version == other.version
+            && self.layer == other.layer
+            && self.sample_rate == other.sample_rate
+    }
+}
+
+/// A single MPEG audio frame
+#[derive(Debug, Clone)]
+pub struct Mp3Frame {
+    /// Parsed header
+    pub header: Mp3FrameHeader,
+    /// Complete frame bytes, including the header
+    pub data: Bytes,
+}
+
+/// Parsed MP3 audio data from one RTMP message
+#[derive(Debug, Clone)]
+pub struct Mp3Data {
+    /// Frames in the message, in order
+    pub frames: Vec<Mp3Frame>,
+}
+
+impl Mp3Data {
+    /// Parse from RTMP audio data (after format byte)
+    ///
+    /// Splits the payload on frame boundaries. A truncated trailing frame is
+    /// an error.
+    pub fn parse(data: Bytes) -> Result<Self> {
+        let mut frames = Vec::new();
+        let mut offset = 0;
+
+        while offset < data.len() {
+            let header = Mp3FrameHeader::parse(&data[offset..])?;
+
+            let len = match header.frame_len() {
+                Some(len) => len,
+                None => Self::find_free_format_len(&header, &data[offset..]),
+            };
+            if len < Mp3FrameHeader::SIZE || offset + len > data.len() {
+                return Err(MediaError::InvalidMp3Frame.into());
+            }
+
+            frames.push(Mp3Frame {
+                header,
+                data: data.slice(offset..offset + len),
+            });
+            offset += len;
+        }
+
+        if frames.is_empty() {
+            return Err(MediaError::InvalidMp3Frame.into());
+        }
+
+        Ok(Mp3Data { frames })
+    }
+
+    /// Find the length of a free-format frame by scanning for the next
+    /// matching sync word; the last frame runs to the end of the buffer.
+    fn find_free_format_len(header: &Mp3FrameHeader, data: &[u8]) -> usize {
+        (Mp3FrameHeader::SIZE..data.len().saturating_sub(Mp3FrameHeader::SIZE - 1))
+            .find(|&i| {
+                Mp3FrameHeader::parse(&data[i..])
+                    .map(|next| next.is_free_format() && header.same_stream(&next))
+                    .unwrap_or(false)
+            })
+            .unwrap_or(data.len())
+    }
+
+    /// Get the header of the first frame
+    pub fn header(&self) -> &Mp3FrameHeader {
+        &self.frames[0].header
+    }
+
+    /// Get the total playback duration of all frames
+    pub fn duration(&self) -> Duration {
+        self.frames.iter().map(|f| f.header.duration()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a frame of `len` bytes starting with the given header
+    fn make_frame(header: [u8; 4], len: usize) -> Vec<u8> {
+        let mut frame = header.to_vec();
+        frame.resize(len, 0);
+        frame
+    }
+
+    #[test]
+    fn test_parse_mpeg1_layer3_header() {
+        // 128 kbps, 44.1 kHz, joint stereo, no CRC
+        let header = Mp3FrameHeader::parse(&[0xFF, 0xFB, 0x90, 0x64]).unwrap();
+        assert_eq!(header.version, MpegVersion::Mpeg1);
+        assert_eq!(header.layer, MpegLayer::Layer3);
+        assert!(!header.has_crc);
+        assert_eq!(header.bitrate, 128_000);
+        assert_eq!(header.sample_rate, 44100);
+        assert!(!header.padding);
+        assert_eq!(header.channel_mode, ChannelMode::JointStereo);
+        assert_eq!(header.channels(), 2);
+        assert_eq!(header.samples_per_frame(), 1152);
+        assert_eq!(header.frame_len(), Some(417));
+        assert_eq!(header.duration().as_micros(), 26122);
+    }
+
+    #[test]
+    fn test_parse_padding() {
+        let header = Mp3FrameHeader::parse(&[0xFF, 0xFB, 0x92, 0x64]).unwrap();
+        assert!(header.padding);
+        assert_eq!(header.frame_len(), Some(418));
+    }
+
+    #[test]
+    fn test_parse_mpeg2_layer3_mono() {
+        // 32 kbps, 24 kHz, mono
+        let header = Mp3FrameHeader::parse(&[0xFF, 0xF3, 0x44, 0xC4]).unwrap();
+        assert_eq!(header.version, MpegVersion::Mpeg2);
+        assert_eq!(header.bitrate, 32_000);
+        assert_eq!(header.sample_rate, 24000);
+        assert_eq!(header.channels(), 1);
+        assert_eq!(header.samples_per_frame(), 576);
+        assert_eq!(header.frame_len(), Some(96));
+        assert_eq!(header.duration(), Duration::from_millis(24));
+    }
+
+    #[test]
+    fn test_parse_invalid_headers() {
+        // No sync
+        assert!(Mp3FrameHeader::parse(&[0xFF, 0x1B, 0x90, 0x64]).is_err());
+        // Reserved version
+        assert!(Mp3FrameHeader::parse(&[0xFF, 0xEB, 0x90, 0x64]).is_err());
+        // Reserved layer
+        assert!(Mp3FrameHeader::parse(&[0xFF, 0xF9, 0x90, 0x64]).is_err());
+        // Bad bitrate index
+        assert!(Mp3FrameHeader::parse(&[0xFF, 0xFB, 0xF0, 0x64]).is_err());
+        // Reserved sample rate
+        assert!(Mp3FrameHeader::parse(&[0xFF, 0xFB, 0x9C, 0x64]).is_err());
+        // Too short
+        assert!(Mp3FrameHeader::parse(&[0xFF, 0xFB]).is_err());
+    }
+
+    #[test]
+    fn test_split_frames_with_padding() {
+        let mut data = make_frame([0xFF, 0xFB, 0x90, 0x64], 417);
+        data.extend(make_frame([0xFF, 0xFB, 0x92, 0x64], 418));
+
+        let mp3 = Mp3Data::parse(Bytes::from(data)).unwrap();
+        assert_eq!(mp3.frames.len(), 2);
+        assert_eq!(mp3.frames[0].data.len(), 417);
+        assert_eq!(mp3.frames[1].data.len(), 418);
+        assert!(mp3.frames[1].header.padding);
+        assert_eq!(mp3.duration(), mp3.header().duration() * 2);
+    }
+
+    #[test]
+    fn test_split_free_format_frames() {
+        let header = [0xFF, 0xFB, 0x00, 0x64];
+        assert!(Mp3FrameHeader::parse(&header).unwrap().is_free_format());
+        assert_eq!(Mp3FrameHeader::parse(&header).unwrap().frame_len(), None);
+
+        let mut data = make_frame(header, 300);
+        data.extend(make_frame(header, 250));
+
+        let mp3 = Mp3Data::parse(Bytes::from(data)).unwrap();
+        assert_eq!(mp3.frames.len(), 2);
+        assert_eq!(mp3.frames[0].data.len(), 300);
+        assert_eq!(mp3.frames[1].data.len(), 250);
+    }
+
+    #[test]
+    fn test_truncated_frame() {
+        let data = make_frame([0xFF, 0xFB, 0x90, 0x64], 200);
+        assert!(Mp3Data::parse(Bytes::from(data)).is_err());
+        assert!(Mp3Data::parse(Bytes::new()).is_err());
+    }
+}
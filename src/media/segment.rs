@@ -0,0 +1,404 @@
+//! Keyframe-aligned FLV segmenting
+//!
+//! [`SegmentingFlvWriter`] splits a stream into FLV files of roughly a
+//! target duration. A new segment only starts at a keyframe (or at any
+//! audio tag for audio-only streams), so every segment is independently
+//! decodable and can feed an HLS packager. Each segment begins with the
+//! latest `onMetaData` and sequence headers, and its timestamps start at
+//! zero.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::enhanced_audio::EnhancedAudioData;
+use super::enhanced_video::EnhancedVideoData;
+use super::flv::{FlvTag, FlvTagType, FlvWriter};
+
+/// A completed (or in-progress) segment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// File the segment was written to
+    pub path: PathBuf,
+    /// Segment sequence number, starting at 0
+    pub index: u64,
+    /// Stream timestamp of the first tag in milliseconds
+    pub start: u32,
+    /// Stream timestamp where the segment ends in milliseconds
+    ///
+    /// The start of the next segment, or the last tag for the final one.
+    pub end: u32,
+}
+
+impl Segment {
+    /// Length of the segment
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.end.wrapping_sub(self.start) as u64)
+    }
+}
+
+type SegmentPaths = Box<dyn FnMut(u64) -> PathBuf + Send>;
+type SegmentCallback = Box<dyn FnMut(&Segment) + Send>;
+
+/// FLV writer that rolls to a new file on keyframes
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use rtmp_rs::media::SegmentingFlvWriter;
+///
+/// let mut writer = SegmentingFlvWriter::new(Duration::from_secs(6), |index| {
+///     format!("out/segment-{:05}.flv", index).into()
+/// })
+/// .on_segment(|segment| println!("{} ({:?})", segment.path.display(), segment.duration()));
+/// // writer.write_tag(&tag)? for every tag, then:
+/// writer.finish().unwrap();
+/// ```
+pub struct SegmentingFlvWriter {
+    target: Duration,
+    paths: SegmentPaths,
+    on_segment: Option<SegmentCallback>,
+    file: Option<FlvWriter<BufWriter<File>>>,
+    current: Option<Segment>,
+    next_index: u64,
+    has_video: bool,
+    metadata: Option<FlvTag>,
+    video_header: Option<FlvTag>,
+    audio_header: Option<FlvTag>,
+}
+
+impl SegmentingFlvWriter {
+    /// Create a writer targeting segments of `target` length
+    ///
+    /// `paths` names the file for each segment index; parent directories
+    /// are created as needed.
+    pub fn new(target: Duration, paths: impl FnMut(u64) -> PathBuf + Send + 'static) -> Self {
+        Self {
+            target,
+            paths: Box::new(paths),
+            on_segment: None,
+            file: None,
+            current: None,
+            next_index: 0,
+            has_video: false,
+            metadata: None,
+            video_header: None,
+            audio_header: None,
+        }
+    }
+
+    /// Call `f` with every completed segment
+    pub fn on_segment(mut self, f: impl FnMut(&Segment) + Send + 'static) -> Self {
+        self.on_segment = Some(Box::new(f));
+        self
+    }
+
+    /// The segment being written, if any
+    pub fn current_segment(&self) -> Option<&Segment> {
+        self.current.as_ref()
+    }
+
+    /// Write a tag, starting a new segment first if this tag is a boundary
+    pub fn write_tag(&mut self, tag: &FlvTag) -> io::Result<()> {
+        let is_header = is_sequence_header(tag);
+
+        // Cache what each new segment has to start with
+        match tag.tag_type {
+            FlvTagType::Script => self.metadata = Some(tag.clone()),
+            FlvTagType::Video if is_header => self.video_header = Some(tag.clone()),
+            FlvTagType::Audio if is_header => self.audio_header = Some(tag.clone()),
+            _ => {}
+        }
+        if tag.is_video() {
+            self.has_video = true;
+        }
+
+        if self.file.is_none() || (!is_header && self.is_boundary(tag)) {
+            self.start_segment(tag.timestamp)?;
+            if tag.tag_type == FlvTagType::Script || is_header {
+                // Already written as part of the segment preamble
+                return Ok(());
+            }
+        }
+
+        self.write_rebased(tag)
+    }
+
+    /// Write a timed script tag, such as a cue point, into the current segment
+    ///
+    /// Unlike script tags passed to [`write_tag`](Self::write_tag), it is not
+    /// kept as the stream's metadata and keeps its place on the timeline.
+    pub fn write_data_tag(&mut self, tag: &FlvTag) -> io::Result<()> {
+        if self.file.is_none() {
+            self.start_segment(tag.timestamp)?;
+        }
+        let (Some(file), Some(current)) = (self.file.as_mut(), self.current.as_mut()) else {
+            return Ok(());
+        };
+        file.write_tag(&FlvTag {
+            timestamp: tag.timestamp.wrapping_sub(current.start),
+            ..tag.clone()
+        })
+    }
+
+    /// Close the current segment
+    ///
+    /// Fires the segment callback for it. Must be called after the last tag.
+    pub fn finish(&mut self) -> io::Result<()> {
+        let Some(file) = self.file.take() else {
+            return Ok(());
+        };
+        file.into_inner()?;
+
+        if let Some(segment) = self.current.take() {
+            if let Some(on_segment) = self.on_segment.as_mut() {
+                on_segment(&segment);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check whether `tag` may start a segment and the target has elapsed
+    fn is_boundary(&self, tag: &FlvTag) -> bool {
+        let Some(current) = &self.current else {
+            return false;
+        };
+        let cut_point = match tag.tag_type {
+            FlvTagType::Video => is_keyframe(tag),
+            FlvTagType::Audio => !self.has_video,
+            FlvTagType::Script => false,
+        };
+        let elapsed = tag.timestamp.wrapping_sub(current.start);
+        cut_point && elapsed as u128 >= self.target.as_millis()
+    }
+
+    fn start_segment(&mut self, start: u32) -> io::Result<()> {
+        if let Some(current) = self.current.as_mut() {
+            current.end = start;
+        }
+        self.finish()?;
+
+        let path = (self.paths)(self.next_index);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = BufWriter::new(File::create(&path)?);
+        self.file = Some(FlvWriter::new(file)?);
+        self.current = Some(Segment {
+            path,
+            index: self.next_index,
+            start,
+            end: start,
+        });
+        self.next_index += 1;
+
+        let preamble: Vec<FlvTag> = [&self.metadata, &self.video_header, &self.audio_header]
+            .into_iter()
+            .flatten()
+            .map(|tag| FlvTag {
+                timestamp: start,
+                ..tag.clone()
+            })
+            .collect();
+        for tag in &preamble {
+            self.write_rebased(tag)?;
+        }
+        Ok(())
+    }
+
+    fn write_rebased(&mut self, tag: &FlvTag) -> io::Result<()> {
+        let (Some(file), Some(current)) = (self.file.as_mut(), self.current.as_mut()) else {
+            return Ok(());
+        };
+        let timestamp = match tag.tag_type {
+            FlvTagType::Script => 0,
+            _ => tag.timestamp.wrapping_sub(current.start),
+        };
+        if tag.tag_type != FlvTagType::Script {
+            current.end = tag.timestamp;
+        }
+        file.write_tag(&FlvTag {
+            timestamp,
+            ..tag.clone()
+        })
+    }
+}
+
+/// Check for a legacy or enhanced video keyframe
+fn is_keyframe(tag: &FlvTag) -> bool {
+    match tag.data.first() {
+        Some(&b) if EnhancedVideoData::is_enhanced(b) => EnhancedVideoData::parse(tag.data.clone())
+            .map(|v| v.is_keyframe())
+            .unwrap_or(false),
+        _ => tag.is_keyframe(),
+    }
+}
+
+/// Check for a legacy or enhanced audio/video sequence header
+fn is_sequence_header(tag: &FlvTag) -> bool {
+    let Some(&first) = tag.data.first() else {
+        return false;
+    };
+    match tag.tag_type {
+        FlvTagType::Video if EnhancedVideoData::is_enhanced(first) => {
+            EnhancedVideoData::parse(tag.data.clone())
+                .map(|v| v.is_sequence_header())
+                .unwrap_or(false)
+        }
+        FlvTagType::Video => tag.is_avc_sequence_header(),
+        FlvTagType::Audio if EnhancedAudioData::is_enhanced(first) => {
+            EnhancedAudioData::parse(tag.data.clone())
+                .map(|a| a.is_sequence_header())
+                .unwrap_or(false)
+        }
+        FlvTagType::Audio => tag.is_aac_sequence_header(),
+        FlvTagType::Script => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::flv::FlvReader;
+    use crate::test_util::temp_dir;
+    use bytes::Bytes;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    fn read(path: &Path) -> Vec<FlvTag> {
+        FlvReader::new(File::open(path).unwrap())
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap()
+    }
+
+    /// Write `duration_ms` of 25fps video with a keyframe every
+    /// `keyframe_ms`, plus audio, returning the completed segments
+    fn write_stream(
+        dir: &Path,
+        target: Duration,
+        keyframe_ms: u32,
+        duration_ms: u32,
+    ) -> Vec<Segment> {
+        let segments = Arc::new(Mutex::new(Vec::new()));
+        let seen = segments.clone();
+        let base = dir.to_path_buf();
+        let mut writer =
+            SegmentingFlvWriter::new(target, move |i| base.join(format!("seg-{}.flv", i)))
+                .on_segment(move |s| seen.lock().unwrap().push(s.clone()));
+
+        writer
+            .write_tag(&FlvTag::script(0, Bytes::from_static(b"\x02\x00\x00")))
+            .unwrap();
+        writer
+            .write_tag(&FlvTag::video(
+                0,
+                Bytes::from_static(&[0x17, 0x00, 0, 0, 0]),
+            ))
+            .unwrap();
+        writer
+            .write_tag(&FlvTag::audio(
+                0,
+                Bytes::from_static(&[0xAF, 0x00, 0x12, 0x10]),
+            ))
+            .unwrap();
+
+        for ts in (0..duration_ms).step_by(40) {
+            let video = if ts % keyframe_ms == 0 { 0x17 } else { 0x27 };
+            writer
+                .write_tag(&FlvTag::video(
+                    ts,
+                    Bytes::copy_from_slice(&[video, 0x01, 0, 0, 0]),
+                ))
+                .unwrap();
+            writer
+                .write_tag(&FlvTag::audio(
+                    ts + 5,
+                    Bytes::from_static(&[0xAF, 0x01, 0x21]),
+                ))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let segments = segments.lock().unwrap().clone();
+        segments
+    }
+
+    #[test]
+    fn test_segments_cut_after_target_on_keyframes() {
+        let dir = temp_dir("segment-uneven");
+        // Keyframes every 1.4s never line up with the 2s target
+        let segments = write_stream(&dir, Duration::from_secs(2), 1400, 8000);
+
+        let spans: Vec<(u32, u32)> = segments.iter().map(|s| (s.start, s.end)).collect();
+        assert_eq!(spans, vec![(0, 2800), (2800, 5600), (5600, 7965)]);
+        assert_eq!(segments[0].duration(), Duration::from_millis(2800));
+
+        let mut frames = 0;
+        for (i, segment) in segments.iter().enumerate() {
+            assert_eq!(segment.index, i as u64);
+            let tags = read(&segment.path);
+            assert_eq!(tags[0].tag_type, FlvTagType::Script);
+            assert!(tags[1].is_avc_sequence_header());
+            assert!(tags[2].is_aac_sequence_header());
+            assert!(tags[3].is_keyframe());
+            assert_eq!(tags[3].timestamp, 0);
+            frames += tags.len() - 3;
+        }
+        assert_eq!(frames, 400);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_keyframe_interval_longer_than_target() {
+        let dir = temp_dir("segment-long-gop");
+        let segments = write_stream(&dir, Duration::from_millis(500), 1200, 3000);
+
+        // Every keyframe is a cut, so segments follow the GOP length
+        let spans: Vec<(u32, u32)> = segments.iter().map(|s| (s.start, s.end)).collect();
+        assert_eq!(spans, vec![(0, 1200), (1200, 2400), (2400, 2965)]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_audio_only_segments() {
+        let dir = temp_dir("segment-audio");
+        let base = dir.clone();
+        let mut writer = SegmentingFlvWriter::new(Duration::from_secs(1), move |i| {
+            base.join(format!("{}.flv", i))
+        });
+
+        writer
+            .write_tag(&FlvTag::audio(
+                0,
+                Bytes::from_static(&[0xAF, 0x00, 0x12, 0x10]),
+            ))
+            .unwrap();
+        for ts in (0..2500).step_by(100) {
+            writer
+                .write_tag(&FlvTag::audio(ts, Bytes::from_static(&[0xAF, 0x01, 0x21])))
+                .unwrap();
+        }
+        assert_eq!(writer.current_segment().unwrap().start, 2000);
+        writer.finish().unwrap();
+        assert!(writer.current_segment().is_none());
+
+        let first = read(&dir.join("0.flv"));
+        assert!(first[0].is_aac_sequence_header());
+        assert_eq!(first.len(), 11);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_enhanced_keyframe_detection() {
+        // HEVC SequenceStart and CodedFramesX keyframe
+        let header = FlvTag::video(0, Bytes::from_static(&[0x90, b'h', b'v', b'c', b'1', 0x01]));
+        let key = FlvTag::video(0, Bytes::from_static(&[0x93, b'h', b'v', b'c', b'1', 0xAA]));
+        let inter = FlvTag::video(0, Bytes::from_static(&[0xA3, b'h', b'v', b'c', b'1', 0xAA]));
+
+        assert!(is_sequence_header(&header));
+        assert!(!is_sequence_header(&key));
+        assert!(is_keyframe(&key));
+        assert!(!is_keyframe(&inter));
+    }
+}
@@ -47,6 +47,19 @@ pub struct RtmpChunk {
     pub payload: Bytes,
 }
 
+/// Diagnostic view of one inbound chunk stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkStreamInfo {
+    /// Chunk stream ID
+    pub csid: u32,
+    /// Type of the last message header seen on this chunk stream
+    pub last_msg_type: u8,
+    /// Absolute timestamp of the last message header (milliseconds)
+    pub last_timestamp: u32,
+    /// Payload bytes received on this chunk stream
+    pub bytes: u64,
+}
+
 /// Per-chunk-stream state for reassembly
 #[derive(Debug, Clone, Default)]
 struct ChunkStreamState {
@@ -66,6 +79,19 @@ struct ChunkStreamState {
     partial_message: BytesMut,
     /// Expected total length of current message
     expected_length: u32,
+    /// Payload bytes received (decoder only)
+    bytes: u64,
+}
+
+impl ChunkStreamState {
+    fn info(&self, csid: u32) -> ChunkStreamInfo {
+        ChunkStreamInfo {
+            csid,
+            last_msg_type: self.message_type,
+            last_timestamp: self.timestamp,
+            bytes: self.bytes,
+        }
+    }
 }
 
 /// Chunk stream decoder
@@ -100,6 +126,14 @@ impl ChunkDecoder {
         self.chunk_size
     }
 
+    /// Set the largest message length a peer may declare
+    ///
+    /// Longer messages fail with [`ProtocolError::MessageTooLarge`] as soon
+    /// as their header is read, before any payload is buffered.
+    pub fn set_max_message_size(&mut self, size: u32) {
+        self.max_message_size = size;
+    }
+
     /// Try to decode a complete message from the buffer
     ///
     /// Returns Ok(Some(chunk)) if a complete message was decoded,
@@ -136,7 +170,19 @@ impl ChunkDecoder {
         };
 
         // Check if we have enough data for headers
-        let needs_extended = if fmt == 3 {
+        let continuation = fmt == 3 && !state.partial_message.is_empty();
+        let needs_extended = if continuation && state.has_extended_timestamp {
+            // Continuation chunks repeat the extended timestamp, but some
+            // encoders leave it out. Only consume it if it matches the
+            // value from the message's first chunk; until 4 bytes are
+            // buffered there is no telling.
+            if buf.len() < header_len + 4 {
+                return Ok(None);
+            }
+            let ts_bytes = &buf[header_len..header_len + 4];
+            u32::from_be_bytes([ts_bytes[0], ts_bytes[1], ts_bytes[2], ts_bytes[3]])
+                == state.timestamp_delta
+        } else if fmt == 3 {
             state.has_extended_timestamp
         } else if buf.len() > header_len + 2 {
             // Peek at timestamp field to check for extended timestamp
@@ -157,7 +203,7 @@ impl ChunkDecoder {
 
         // PEEK at message header to determine chunk data length BEFORE consuming anything
         // For fmt 3, we use state values; for others, we peek at the buffer
-        let (_peeked_message_length, peeked_expected_length) = match fmt {
+        let (peeked_message_length, peeked_expected_length) = match fmt {
             0 | 1 => {
                 // Message length is at offset: header_len + 3 (timestamp bytes)
                 let len_offset = header_len + 3;
@@ -180,6 +226,16 @@ impl ChunkDecoder {
             _ => unreachable!(),
         };
 
+        // Validate message size before waiting for a payload that may never
+        // fit in memory
+        if peeked_message_length > self.max_message_size {
+            return Err(ProtocolError::MessageTooLarge {
+                size: peeked_message_length,
+                max: self.max_message_size,
+            }
+            .into());
+        }
+
         // Calculate chunk data length
         let partial_len = state.partial_message.len() as u32;
         let remaining = peeked_expected_length.saturating_sub(partial_len);
@@ -233,9 +289,13 @@ impl ChunkDecoder {
         };
 
         // Handle extended timestamp (we already checked we have enough bytes)
-        let timestamp = if timestamp_field >= EXTENDED_TIMESTAMP_THRESHOLD
-            || (fmt == 3 && state.has_extended_timestamp)
-        {
+        let timestamp = if fmt == 3 && state.has_extended_timestamp {
+            if needs_extended {
+                buf.get_u32()
+            } else {
+                state.timestamp_delta
+            }
+        } else if timestamp_field >= EXTENDED_TIMESTAMP_THRESHOLD {
             state.has_extended_timestamp = true;
             buf.get_u32()
         } else {
@@ -246,7 +306,7 @@ impl ChunkDecoder {
         // Update state
         let absolute_timestamp = if fmt == 0 {
             timestamp
-        } else if fmt == 3 && !state.partial_message.is_empty() {
+        } else if continuation {
             // continuation chunk, timestamp stays the same
             state.timestamp
         } else {
@@ -258,14 +318,21 @@ impl ChunkDecoder {
         state.message_type = message_type;
         state.stream_id = stream_id;
         state.timestamp = absolute_timestamp;
+        state.bytes += chunk_data_len as u64;
 
-        // Validate message size
-        if message_length > self.max_message_size {
-            return Err(ProtocolError::MessageTooLarge {
-                size: message_length,
-                max: self.max_message_size,
-            }
-            .into());
+        // A message that fits in a single chunk is handed out as a slice of
+        // the read buffer instead of being copied into the reassembly buffer
+        if state.partial_message.is_empty() && chunk_data_len as u32 >= message_length {
+            let payload = buf.split_to(chunk_data_len).freeze();
+            state.expected_length = 0;
+
+            return Ok(Some(RtmpChunk {
+                csid,
+                timestamp: state.timestamp,
+                message_type: state.message_type,
+                stream_id: state.stream_id,
+                payload,
+            }));
         }
 
         // Initialize reassembly buffer if this is a new message
@@ -329,6 +396,16 @@ impl ChunkDecoder {
         }
     }
 
+    /// State of one chunk stream, if the peer has used it
+    pub fn chunk_stream(&self, csid: u32) -> Option<ChunkStreamInfo> {
+        self.streams.get(&csid).map(|state| state.info(csid))
+    }
+
+    /// State of every chunk stream the peer has used, in no particular order
+    pub fn chunk_streams(&self) -> impl Iterator<Item = ChunkStreamInfo> + '_ {
+        self.streams.iter().map(|(&csid, state)| state.info(csid))
+    }
+
     /// Abort a message on a chunk stream (when receiving Abort message)
     pub fn abort(&mut self, csid: u32) {
         if let Some(state) = self.streams.get_mut(&csid) {
@@ -384,26 +461,20 @@ impl ChunkEncoder {
         // Compute format based on state comparison
         let fmt = select_format(chunk, state);
 
-        // Determine if we need extended timestamp
-        let needs_extended = chunk.timestamp >= EXTENDED_TIMESTAMP_THRESHOLD;
-        let timestamp_field = if needs_extended {
-            EXTENDED_TIMESTAMP_THRESHOLD
-        } else {
+        // The header carries the absolute timestamp for format 0 and the
+        // delta otherwise; values at or above the threshold go in the
+        // extended timestamp field
+        let header_timestamp = if fmt == 0 {
             chunk.timestamp
-        };
-
-        let timestamp_delta = chunk.timestamp.wrapping_sub(state.timestamp);
-        let delta_field = if needs_extended {
-            EXTENDED_TIMESTAMP_THRESHOLD
         } else {
-            timestamp_delta
+            chunk.timestamp.wrapping_sub(state.timestamp)
         };
-
-        let had_extended_timestamp = state.has_extended_timestamp;
+        let needs_extended = header_timestamp >= EXTENDED_TIMESTAMP_THRESHOLD;
+        let timestamp_field = header_timestamp.min(EXTENDED_TIMESTAMP_THRESHOLD);
 
         // Update state before encoding
         state.timestamp = chunk.timestamp;
-        state.timestamp_delta = timestamp_delta;
+        state.timestamp_delta = header_timestamp;
         state.message_length = chunk.payload.len() as u32;
         state.message_type = chunk.message_type;
         state.stream_id = chunk.stream_id;
@@ -432,13 +503,13 @@ impl ChunkEncoder {
                     }
                     1 => {
                         // No stream ID
-                        write_u24(delta_field, buf);
+                        write_u24(timestamp_field, buf);
                         write_u24(payload_len as u32, buf);
                         buf.put_u8(chunk.message_type);
                     }
                     2 => {
                         // Timestamp delta only
-                        write_u24(delta_field, buf);
+                        write_u24(timestamp_field, buf);
                     }
                     3 => {
                         // No header
@@ -447,9 +518,10 @@ impl ChunkEncoder {
                 }
             }
 
-            // Write extended timestamp if needed
-            if needs_extended && (first_chunk || had_extended_timestamp) {
-                buf.put_u32(chunk.timestamp);
+            // Write extended timestamp if needed (repeated on continuation
+            // chunks, which is what the decoder expects)
+            if needs_extended {
+                buf.put_u32(header_timestamp);
             }
 
             // Write chunk data
@@ -479,9 +551,10 @@ fn select_format(chunk: &RtmpChunk, state: &ChunkStreamState) -> u8 {
         return 1;
     }
 
-    // If timestamp delta matches previous, use format 3
+    // If timestamp delta matches previous, use format 3 (only without an
+    // extended timestamp, whose meaning would be ambiguous)
     let delta = chunk.timestamp.wrapping_sub(state.timestamp);
-    if delta == state.timestamp_delta {
+    if delta == state.timestamp_delta && !state.has_extended_timestamp {
         return 3;
     }
 
@@ -499,7 +572,7 @@ fn write_basic_header(csid: u32, fmt: u8, buf: &mut BytesMut) {
         buf.put_u8(((csid_offset >> 8) & 0xFF) as u8);
     } else if csid >= 64 {
         // 2-byte header
-        buf.put_u8((fmt << 6) | 0);
+        buf.put_u8(fmt << 6);
         buf.put_u8((csid - 64) as u8);
     } else {
         // 1-byte header
@@ -569,6 +642,27 @@ mod tests {
         assert_eq!(decoded.payload, original.payload);
     }
 
+    #[test]
+    fn test_single_chunk_payload_is_not_copied() {
+        let chunk = RtmpChunk {
+            csid: CSID_AUDIO,
+            timestamp: 0,
+            message_type: MSG_AUDIO,
+            stream_id: 1,
+            payload: Bytes::from_static(&[0xAF; 100]),
+        };
+
+        let mut encoded = BytesMut::new();
+        ChunkEncoder::new().encode(&chunk, &mut encoded);
+        let wire = encoded.as_ptr_range();
+
+        let decoded = ChunkDecoder::new().decode(&mut encoded).unwrap().unwrap();
+
+        // The payload points into the read buffer rather than a new allocation
+        assert!(wire.contains(&decoded.payload.as_ptr()));
+        assert_eq!(decoded.payload, chunk.payload);
+    }
+
     #[test]
     fn test_large_message_chunking() {
         let large_payload = vec![0u8; 500]; // Larger than default chunk size (128)
@@ -696,6 +790,52 @@ mod tests {
         assert_eq!(decoded.timestamp, EXTENDED_TIMESTAMP_THRESHOLD);
     }
 
+    #[test]
+    fn test_extended_timestamp_delta_across_chunks() {
+        let mut encoder = ChunkEncoder::new();
+        let mut decoder = ChunkDecoder::new();
+
+        // Small first message, then a multi-chunk one whose delta needs the
+        // extended field
+        let first = RtmpChunk {
+            csid: CSID_VIDEO,
+            timestamp: 40,
+            message_type: MSG_VIDEO,
+            stream_id: 1,
+            payload: Bytes::from_static(b"small"),
+        };
+        let second = RtmpChunk {
+            csid: CSID_VIDEO,
+            timestamp: 0xFFFFFF + 40,
+            message_type: MSG_VIDEO,
+            stream_id: 1,
+            payload: Bytes::from(vec![0xAB; 300]),
+        };
+        let third = RtmpChunk {
+            csid: CSID_VIDEO,
+            timestamp: 0xFFFFFF + 80,
+            message_type: MSG_VIDEO,
+            stream_id: 1,
+            payload: Bytes::from_static(b"after"),
+        };
+
+        let mut encoded = BytesMut::new();
+        encoder.encode(&first, &mut encoded);
+        encoder.encode(&second, &mut encoded);
+        encoder.encode(&third, &mut encoded);
+
+        let mut decoded = Vec::new();
+        while !encoded.is_empty() {
+            if let Some(chunk) = decoder.decode(&mut encoded).unwrap() {
+                decoded.push(chunk);
+            }
+        }
+
+        let timestamps: Vec<u32> = decoded.iter().map(|c| c.timestamp).collect();
+        assert_eq!(timestamps, vec![40, 0xFFFFFF + 40, 0xFFFFFF + 80]);
+        assert_eq!(decoded[1].payload, second.payload);
+    }
+
     #[test]
     fn test_zero_timestamp() {
         let mut encoder = ChunkEncoder::new();
@@ -759,6 +899,63 @@ mod tests {
         assert_eq!(decoded.payload.as_ref(), b"new message");
     }
 
+    #[test]
+    fn test_abort_message_mid_stream() {
+        use crate::protocol::message::RtmpMessage;
+
+        let mut encoder = ChunkEncoder::new();
+        let mut decoder = ChunkDecoder::new();
+        let mut wire = BytesMut::new();
+
+        // First chunk of a 500-byte video message (12-byte header + 128 bytes)
+        let large_chunk = RtmpChunk {
+            csid: CSID_VIDEO,
+            timestamp: 0,
+            message_type: MSG_VIDEO,
+            stream_id: 1,
+            payload: Bytes::from(vec![0xAA; 500]),
+        };
+        let mut encoded = BytesMut::new();
+        encoder.encode(&large_chunk, &mut encoded);
+        wire.extend_from_slice(&encoded[..12 + DEFAULT_CHUNK_SIZE as usize]);
+
+        // Abort message for the video chunk stream
+        let (message_type, payload) = RtmpMessage::Abort { csid: CSID_VIDEO }.encode();
+        let abort_chunk = RtmpChunk {
+            csid: CSID_PROTOCOL_CONTROL,
+            timestamp: 0,
+            message_type,
+            stream_id: 0,
+            payload,
+        };
+        encoder.encode(&abort_chunk, &mut wire);
+
+        // Fresh message on the aborted chunk stream
+        let fresh_chunk = RtmpChunk {
+            csid: CSID_VIDEO,
+            timestamp: 40,
+            message_type: MSG_VIDEO,
+            stream_id: 1,
+            payload: Bytes::from_static(b"fresh"),
+        };
+        encoder.encode(&fresh_chunk, &mut wire);
+
+        // The partial chunk is consumed into the reassembly buffer
+        assert!(decoder.decode(&mut wire).unwrap().is_none());
+
+        let chunk = decoder.decode(&mut wire).unwrap().unwrap();
+        match RtmpMessage::from_chunk(&chunk).unwrap() {
+            RtmpMessage::Abort { csid } => decoder.abort(csid),
+            other => panic!("Expected Abort message, got {:?}", other),
+        }
+
+        let chunk = decoder.decode(&mut wire).unwrap().unwrap();
+        assert_eq!(chunk.csid, CSID_VIDEO);
+        assert_eq!(chunk.timestamp, 40);
+        assert_eq!(chunk.payload.as_ref(), b"fresh");
+        assert!(wire.is_empty());
+    }
+
     #[test]
     fn test_decode_empty_buffer() {
         let mut decoder = ChunkDecoder::new();
@@ -852,6 +1049,51 @@ mod tests {
         assert_eq!(decoded_cmd.message_type, MSG_COMMAND_AMF0);
     }
 
+    #[test]
+    fn test_chunk_stream_info() {
+        let mut encoder = ChunkEncoder::new();
+        let mut decoder = ChunkDecoder::new();
+        assert_eq!(decoder.chunk_streams().count(), 0);
+
+        let mut encoded = BytesMut::new();
+        for (csid, message_type, timestamp, payload) in [
+            (CSID_VIDEO, MSG_VIDEO, 0, &b"video"[..]),
+            (CSID_VIDEO, MSG_VIDEO, 33, &b"video2"[..]),
+            (CSID_AUDIO, MSG_AUDIO, 21, &b"audio"[..]),
+        ] {
+            let chunk = RtmpChunk {
+                csid,
+                timestamp,
+                message_type,
+                stream_id: 1,
+                payload: Bytes::copy_from_slice(payload),
+            };
+            encoder.encode(&chunk, &mut encoded);
+        }
+        while decoder.decode(&mut encoded).unwrap().is_some() {}
+
+        let mut streams: Vec<_> = decoder.chunk_streams().collect();
+        streams.sort_by_key(|s| s.csid);
+        assert_eq!(
+            streams,
+            vec![
+                ChunkStreamInfo {
+                    csid: CSID_AUDIO,
+                    last_msg_type: MSG_AUDIO,
+                    last_timestamp: 21,
+                    bytes: 5,
+                },
+                ChunkStreamInfo {
+                    csid: CSID_VIDEO,
+                    last_msg_type: MSG_VIDEO,
+                    last_timestamp: 33,
+                    bytes: 11,
+                },
+            ]
+        );
+        assert_eq!(decoder.chunk_stream(CSID_COMMAND), None);
+    }
+
     #[test]
     fn test_message_too_large_error() {
         let mut decoder = ChunkDecoder::new();
@@ -1017,4 +1259,156 @@ mod tests {
             b"test payload for incremental decode"
         );
     }
+
+    /// Decode every complete message in `wire`
+    fn decode_all(decoder: &mut ChunkDecoder, wire: &[u8]) -> Vec<RtmpChunk> {
+        let mut buf = BytesMut::from(wire);
+        let mut messages = Vec::new();
+        while !buf.is_empty() {
+            let len = buf.len();
+            if let Some(chunk) = decoder.decode(&mut buf).unwrap() {
+                messages.push(chunk);
+            }
+            assert!(buf.len() < len, "decoder stalled on a complete chunk");
+        }
+        messages
+    }
+
+    #[test]
+    fn test_decode_timestamp_deltas() {
+        let mut decoder = ChunkDecoder::new();
+        #[rustfmt::skip]
+        let wire = [
+            // fmt 0: timestamp 1000, length 2, audio, stream 1
+            0x04, 0x00, 0x03, 0xE8, 0x00, 0x00, 0x02, 0x08, 0x01, 0x00, 0x00, 0x00, 0xA0, 0xA1,
+            // fmt 2: delta 20
+            0x84, 0x00, 0x00, 0x14, 0xB0, 0xB1,
+            // fmt 3: delta 20 again, twice
+            0xC4, 0xC0, 0xC1,
+            0xC4, 0xD0, 0xD1,
+            // fmt 1: delta 10, length 1, video
+            0x44, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x01, 0x09, 0xE0,
+        ];
+
+        let messages = decode_all(&mut decoder, &wire);
+        let timestamps: Vec<u32> = messages.iter().map(|m| m.timestamp).collect();
+        assert_eq!(timestamps, vec![1000, 1020, 1040, 1060, 1070]);
+        assert!(messages.iter().all(|m| m.stream_id == 1));
+        assert_eq!(messages[3].payload.as_ref(), &[0xD0, 0xD1]);
+        assert_eq!(messages[4].message_type, MSG_VIDEO);
+        assert_eq!(messages[4].payload.as_ref(), &[0xE0]);
+    }
+
+    #[test]
+    fn test_decode_extended_timestamp_deltas() {
+        let mut decoder = ChunkDecoder::new();
+        #[rustfmt::skip]
+        let wire = [
+            // fmt 0: extended timestamp 0x01000000
+            0x04, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x02, 0x08, 0x01, 0x00, 0x00, 0x00,
+            0x01, 0x00, 0x00, 0x00, 0xA0, 0xA1,
+            // fmt 2: extended delta 0x01000000
+            0x84, 0xFF, 0xFF, 0xFF, 0x01, 0x00, 0x00, 0x00, 0xB0, 0xB1,
+            // fmt 3 starting a new message: repeats the extended delta
+            0xC4, 0x01, 0x00, 0x00, 0x00, 0xC0, 0xC1,
+            // fmt 2: back to a small delta of 40
+            0x84, 0x00, 0x00, 0x28, 0xD0, 0xD1,
+            // fmt 3: no extended field anymore
+            0xC4, 0xE0, 0xE1,
+        ];
+
+        let messages = decode_all(&mut decoder, &wire);
+        let timestamps: Vec<u32> = messages.iter().map(|m| m.timestamp).collect();
+        assert_eq!(
+            timestamps,
+            vec![
+                0x0100_0000,
+                0x0200_0000,
+                0x0300_0000,
+                0x0300_0028,
+                0x0300_0050
+            ]
+        );
+        let payloads: Vec<&[u8]> = messages.iter().map(|m| m.payload.as_ref()).collect();
+        assert_eq!(
+            payloads,
+            vec![
+                &[0xA0, 0xA1][..],
+                &[0xB0, 0xB1],
+                &[0xC0, 0xC1],
+                &[0xD0, 0xD1],
+                &[0xE0, 0xE1]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_continuation_extended_timestamp() {
+        #[rustfmt::skip]
+        let first_chunk = [
+            // fmt 0: extended timestamp 0x01000000, 8 byte message
+            0x04, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x08, 0x09, 0x01, 0x00, 0x00, 0x00,
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x03,
+        ];
+
+        // The continuation repeats the extended timestamp, as the spec
+        // requires, or leaves it out, as some encoders do
+        let continuations: [&[u8]; 2] = [
+            &[0xC4, 0x01, 0x00, 0x00, 0x00, 0x04, 0x05, 0x06, 0x07],
+            &[0xC4, 0x04, 0x05, 0x06, 0x07],
+        ];
+        for continuation in continuations {
+            let mut decoder = ChunkDecoder::new();
+            decoder.set_chunk_size(4);
+            let wire = [&first_chunk[..], continuation].concat();
+
+            let messages = decode_all(&mut decoder, &wire);
+            assert_eq!(messages.len(), 1);
+            assert_eq!(messages[0].timestamp, 0x0100_0000);
+            assert_eq!(messages[0].payload.as_ref(), &[0, 1, 2, 3, 4, 5, 6, 7]);
+        }
+    }
+
+    #[test]
+    fn test_encode_timestamp_header_sequence() {
+        let mut encoder = ChunkEncoder::new();
+        let mut decoder = ChunkDecoder::new();
+        let timestamps = [
+            1000,
+            1020,
+            1040,
+            1060,
+            0x0100_0000,
+            0x0200_0000,
+            0x0300_0000,
+            0x0300_0028,
+            0x0300_0050,
+        ];
+
+        let mut formats = Vec::new();
+        let mut wire = BytesMut::new();
+        for &timestamp in &timestamps {
+            let start = wire.len();
+            encoder.encode(
+                &RtmpChunk {
+                    csid: CSID_AUDIO,
+                    timestamp,
+                    message_type: MSG_AUDIO,
+                    stream_id: 1,
+                    payload: Bytes::from_static(b"aa"),
+                },
+                &mut wire,
+            );
+            formats.push(wire[start] >> 6);
+        }
+
+        // fmt 3 is never used while an extended timestamp is in play
+        assert_eq!(formats, vec![0, 2, 3, 3, 2, 2, 2, 2, 3]);
+
+        let decoded: Vec<u32> = decode_all(&mut decoder, &wire)
+            .iter()
+            .map(|m| m.timestamp)
+            .collect();
+        assert_eq!(decoded, timestamps);
+    }
 }
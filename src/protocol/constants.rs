@@ -142,6 +142,7 @@ pub const BANDWIDTH_LIMIT_DYNAMIC: u8 = 2;
 pub const CMD_CONNECT: &str = "connect";
 pub const CMD_CALL: &str = "call";
 pub const CMD_CLOSE: &str = "close";
+pub const CMD_CLOSE_STREAM: &str = "closeStream";
 pub const CMD_CREATE_STREAM: &str = "createStream";
 pub const CMD_DELETE_STREAM: &str = "deleteStream";
 pub const CMD_PLAY: &str = "play";
@@ -166,9 +167,17 @@ pub const CMD_RELEASE_STREAM: &str = "releaseStream";
 pub const CMD_ON_FC_PUBLISH: &str = "onFCPublish";
 pub const CMD_ON_FC_UNPUBLISH: &str = "onFCUnpublish";
 
+// Player/CDN extended commands
+pub const CMD_FC_SUBSCRIBE: &str = "FCSubscribe";
+pub const CMD_ON_FC_SUBSCRIBE: &str = "onFCSubscribe";
+pub const CMD_GET_STREAM_LENGTH: &str = "getStreamLength";
+
 // Data commands
 pub const CMD_SET_DATA_FRAME: &str = "@setDataFrame";
+pub const CMD_CLEAR_DATA_FRAME: &str = "@clearDataFrame";
 pub const CMD_ON_METADATA: &str = "onMetaData";
+pub const CMD_ON_CUE_POINT: &str = "onCuePoint";
+pub const CMD_ON_TEXT_DATA: &str = "onTextData";
 
 // ============================================================================
 // NetConnection Status Codes
@@ -178,6 +187,7 @@ pub const NC_CONNECT_SUCCESS: &str = "NetConnection.Connect.Success";
 pub const NC_CONNECT_REJECTED: &str = "NetConnection.Connect.Rejected";
 pub const NC_CONNECT_FAILED: &str = "NetConnection.Connect.Failed";
 pub const NC_CONNECT_CLOSED: &str = "NetConnection.Connect.Closed";
+pub const NC_CONNECT_RECONNECT_REQUEST: &str = "NetConnection.Connect.ReconnectRequest";
 
 // ============================================================================
 // NetStream Status Codes
@@ -189,8 +199,11 @@ pub const NS_PLAY_START: &str = "NetStream.Play.Start";
 pub const NS_PLAY_RESET: &str = "NetStream.Play.Reset";
 pub const NS_PLAY_STOP: &str = "NetStream.Play.Stop";
 pub const NS_PLAY_STREAM_NOT_FOUND: &str = "NetStream.Play.StreamNotFound";
+pub const NS_PLAY_UNPUBLISH_NOTIFY: &str = "NetStream.Play.UnpublishNotify";
 pub const NS_PAUSE_NOTIFY: &str = "NetStream.Pause.Notify";
 pub const NS_UNPAUSE_NOTIFY: &str = "NetStream.Unpause.Notify";
+pub const NS_SEEK_NOTIFY: &str = "NetStream.Seek.Notify";
+pub const NS_SEEK_FAILED: &str = "NetStream.Seek.Failed";
 
 // ============================================================================
 // Default Server Settings
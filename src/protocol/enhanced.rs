@@ -264,6 +264,11 @@ impl EnhancedCapabilities {
         self.enabled && self.caps_ex.supports_reconnect()
     }
 
+    /// Check if nanosecond timestamp offsets are supported.
+    pub fn supports_timestamp_nano_offset(&self) -> bool {
+        self.enabled && self.caps_ex.supports_timestamp_nano_offset()
+    }
+
     /// Compute intersection with another capability set.
     ///
     /// Used to negotiate common capabilities between client and server.
@@ -310,6 +315,11 @@ impl EnhancedCapabilities {
 ///
 /// Controls how the server/client handles E-RTMP capability negotiation.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum EnhancedRtmpMode {
     /// Automatically negotiate E-RTMP if peer supports it (default).
     #[default]
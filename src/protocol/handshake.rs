@@ -35,15 +35,48 @@ pub enum HandshakeRole {
     Server,
 }
 
+/// Which C0/S0 version bytes the peer may send
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum HandshakeVersionPolicy {
+    /// Only `RTMP_VERSION` (3)
+    Exact,
+    /// 3 or higher (some encoders send other values)
+    #[default]
+    #[cfg_attr(feature = "serde", serde(rename = "at_least_3"))]
+    AtLeast3,
+    /// Any version byte
+    Any,
+}
+
+impl HandshakeVersionPolicy {
+    /// Check if the policy allows `version`
+    pub fn accepts(self, version: u8) -> bool {
+        match self {
+            HandshakeVersionPolicy::Exact => version == RTMP_VERSION,
+            HandshakeVersionPolicy::AtLeast3 => version >= RTMP_VERSION,
+            HandshakeVersionPolicy::Any => true,
+        }
+    }
+}
+
 /// Handshake state machine
 #[derive(Debug)]
 pub struct Handshake {
     role: HandshakeRole,
     state: HandshakeState,
+    /// Peer version bytes to accept
+    version_policy: HandshakeVersionPolicy,
     /// Our C1/S1 packet (saved for verification)
     our_packet: Option<[u8; HANDSHAKE_SIZE]>,
     /// Peer's C1/S1 packet (saved for echo in C2/S2)
     peer_packet: Option<[u8; HANDSHAKE_SIZE]>,
+    /// Our clock when the peer's C1/S1 arrived
+    peer_packet_received_at: Option<u32>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -67,16 +100,41 @@ impl Handshake {
         Self {
             role,
             state: HandshakeState::Initial,
+            version_policy: HandshakeVersionPolicy::default(),
             our_packet: None,
             peer_packet: None,
+            peer_packet_received_at: None,
         }
     }
 
+    /// Set which peer version bytes to accept
+    pub fn with_version_policy(mut self, policy: HandshakeVersionPolicy) -> Self {
+        self.version_policy = policy;
+        self
+    }
+
     /// Check if handshake is complete
     pub fn is_done(&self) -> bool {
         self.state == HandshakeState::Done
     }
 
+    /// Timestamp field of the peer's C1/S1, once received
+    pub fn peer_timestamp(&self) -> Option<u32> {
+        self.peer_packet
+            .map(|p| u32::from_be_bytes([p[0], p[1], p[2], p[3]]))
+    }
+
+    /// Our receive time minus the peer's C1/S1 timestamp, in milliseconds
+    ///
+    /// Both are the low 32 bits of milliseconds since the Unix epoch, so
+    /// this estimates clock skew (plus one-way latency) only for peers that
+    /// put wall-clock time there; many send zero or their uptime.
+    pub fn peer_clock_skew_ms(&self) -> Option<i32> {
+        let received_at = self.peer_packet_received_at?;
+        let timestamp = self.peer_timestamp()?;
+        Some(received_at.wrapping_sub(timestamp) as i32)
+    }
+
     /// Get bytes needed before next state transition
     pub fn bytes_needed(&self) -> usize {
         match self.state {
@@ -149,17 +207,15 @@ impl Handshake {
 
                 // C0: Version check
                 let version = data.get_u8();
-                if version != RTMP_VERSION {
-                    // Be lenient - accept version 3-31 (some encoders send different values)
-                    if version < 3 {
-                        return Err(HandshakeError::InvalidVersion(version).into());
-                    }
+                if !self.version_policy.accepts(version) {
+                    return Err(HandshakeError::InvalidVersion(version).into());
                 }
 
                 // C1: Save peer packet
                 let mut c1 = [0u8; HANDSHAKE_SIZE];
                 data.copy_to_slice(&mut c1);
                 self.peer_packet = Some(c1);
+                self.peer_packet_received_at = Some(now_ms());
 
                 // Generate S0 + S1 + S2
                 let mut response = BytesMut::with_capacity(1 + HANDSHAKE_SIZE * 2);
@@ -187,7 +243,7 @@ impl Handshake {
 
                 // S0: Version check
                 let version = data.get_u8();
-                if version != RTMP_VERSION && version < 3 {
+                if !self.version_policy.accepts(version) {
                     return Err(HandshakeError::InvalidVersion(version).into());
                 }
 
@@ -195,6 +251,7 @@ impl Handshake {
                 let mut s1 = [0u8; HANDSHAKE_SIZE];
                 data.copy_to_slice(&mut s1);
                 self.peer_packet = Some(s1);
+                self.peer_packet_received_at = Some(now_ms());
 
                 // S2: Verify echo of C1 (lenient - just consume)
                 let mut s2 = [0u8; HANDSHAKE_SIZE];
@@ -236,6 +293,123 @@ impl Handshake {
             }
         }
     }
+
+    /// Size of the peer data `process` consumes in the current state
+    ///
+    /// Unlike `bytes_needed`, this counts the whole S0S1S2 block a client
+    /// reads in one go.
+    fn packet_len(&self) -> usize {
+        match (self.state, self.role) {
+            (HandshakeState::WaitingForPeerPacket, HandshakeRole::Client) => 1 + HANDSHAKE_SIZE * 2,
+            _ => self.bytes_needed(),
+        }
+    }
+}
+
+/// Result of feeding bytes to a [`HandshakeDriver`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HandshakeProgress {
+    /// Number of input bytes taken by the handshake
+    ///
+    /// Bytes past this belong to the chunk stream and must be kept.
+    pub consumed: usize,
+    /// Bytes to write to the peer (may be empty)
+    pub to_send: Bytes,
+    /// Whether the handshake is complete
+    pub done: bool,
+}
+
+/// Runtime-agnostic handshake driver
+///
+/// Wraps [`Handshake`] and buffers partial packets, so any I/O loop can
+/// drive it the same way: feed whatever bytes were read, write `to_send`,
+/// and stop once `done` is set.
+///
+/// ```
+/// use rtmp_rs::protocol::handshake::{HandshakeDriver, HandshakeRole};
+///
+/// let mut client = HandshakeDriver::new(HandshakeRole::Client);
+/// let mut server = HandshakeDriver::new(HandshakeRole::Server);
+///
+/// // The first call yields the opening packet (C0C1 for a client)
+/// let c0c1 = client.feed(&[]).unwrap().to_send;
+/// let s0s1s2 = server.feed(&c0c1).unwrap().to_send;
+/// let c2 = client.feed(&s0s1s2).unwrap().to_send;
+/// assert!(server.feed(&c2).unwrap().done);
+/// assert!(client.is_done());
+/// ```
+#[derive(Debug)]
+pub struct HandshakeDriver {
+    handshake: Handshake,
+    /// Partial peer packet
+    buffer: BytesMut,
+    started: bool,
+}
+
+impl HandshakeDriver {
+    /// Create a new driver
+    pub fn new(role: HandshakeRole) -> Self {
+        Self {
+            handshake: Handshake::new(role),
+            buffer: BytesMut::new(),
+            started: false,
+        }
+    }
+
+    /// Check if handshake is complete
+    pub fn is_done(&self) -> bool {
+        self.handshake.is_done()
+    }
+
+    /// Feed bytes received from the peer
+    ///
+    /// Takes only as many bytes as the handshake needs, buffering partial
+    /// packets between calls. A client should call this once with an empty
+    /// slice to get C0C1 before anything is read.
+    pub fn feed(&mut self, input: &[u8]) -> Result<HandshakeProgress> {
+        let mut to_send = BytesMut::new();
+        if !self.started {
+            self.started = true;
+            if let Some(initial) = self.handshake.generate_initial() {
+                to_send.put(initial);
+            }
+        }
+
+        let mut consumed = 0;
+        loop {
+            let needed = self.handshake.packet_len();
+            if needed == 0 {
+                break;
+            }
+
+            let take = (needed - self.buffer.len()).min(input.len() - consumed);
+            self.buffer
+                .extend_from_slice(&input[consumed..consumed + take]);
+            consumed += take;
+            if self.buffer.len() < needed {
+                break;
+            }
+
+            let mut packet = self.buffer.split().freeze();
+            if let Some(response) = self.handshake.process(&mut packet)? {
+                to_send.put(response);
+            }
+        }
+
+        Ok(HandshakeProgress {
+            consumed,
+            to_send: to_send.freeze(),
+            done: self.handshake.is_done(),
+        })
+    }
+}
+
+/// Handshake clock: milliseconds since the Unix epoch, truncated to 32 bits
+fn now_ms() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u32)
+        .unwrap_or(0)
 }
 
 /// Generate a handshake packet (C1 or S1)
@@ -248,10 +422,7 @@ fn generate_packet() -> [u8; HANDSHAKE_SIZE] {
     let mut packet = [0u8; HANDSHAKE_SIZE];
 
     // Timestamp: milliseconds since some epoch
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_millis() as u32)
-        .unwrap_or(0);
+    let timestamp = now_ms();
 
     packet[0..4].copy_from_slice(&timestamp.to_be_bytes());
 
@@ -281,10 +452,7 @@ fn generate_echo(peer_packet: &[u8; HANDSHAKE_SIZE]) -> [u8; HANDSHAKE_SIZE] {
     let mut echo = *peer_packet;
 
     // Bytes 4-7: Our receive timestamp
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_millis() as u32)
-        .unwrap_or(0);
+    let timestamp = now_ms();
 
     echo[4..8].copy_from_slice(&timestamp.to_be_bytes());
 
@@ -495,6 +663,35 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_version_policies() {
+        let cases = [
+            (HandshakeVersionPolicy::Exact, [false, true, false]),
+            (HandshakeVersionPolicy::AtLeast3, [false, true, true]),
+            (HandshakeVersionPolicy::Any, [true, true, true]),
+        ];
+
+        for (policy, expected) in cases {
+            for (version, accepted) in [2u8, 3, 6].into_iter().zip(expected) {
+                let mut server = Handshake::new(HandshakeRole::Server).with_version_policy(policy);
+                server.generate_initial();
+
+                let mut c0c1 = BytesMut::with_capacity(1 + HANDSHAKE_SIZE);
+                c0c1.put_u8(version);
+                c0c1.put_slice(&generate_packet());
+
+                let result = server.process(&mut c0c1.freeze());
+                assert_eq!(
+                    result.is_ok(),
+                    accepted,
+                    "{:?} with version {}",
+                    policy,
+                    version
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_lenient_version_acceptance() {
         let mut server = Handshake::new(HandshakeRole::Server);
@@ -519,6 +716,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::op_ref)]
     fn test_multiple_packets_different_random_data() {
         let packet1 = generate_packet();
         let packet2 = generate_packet();
@@ -530,6 +728,26 @@ mod tests {
         assert!(&packet2[8..100] != &[0u8; 92][..]);
     }
 
+    #[test]
+    fn test_peer_timestamp_and_clock_skew() {
+        let mut server = Handshake::new(HandshakeRole::Server);
+        server.generate_initial();
+        assert_eq!(server.peer_timestamp(), None);
+        assert_eq!(server.peer_clock_skew_ms(), None);
+
+        // C1 from a peer whose clock runs ten seconds behind ours
+        let stated = now_ms().wrapping_sub(10_000);
+        let mut c0c1 = BytesMut::with_capacity(1 + HANDSHAKE_SIZE);
+        c0c1.put_u8(RTMP_VERSION);
+        c0c1.put_u32(stated);
+        c0c1.put_slice(&[0u8; HANDSHAKE_SIZE - 4]);
+        server.process(&mut c0c1.freeze()).unwrap().unwrap();
+
+        assert_eq!(server.peer_timestamp(), Some(stated));
+        let skew = server.peer_clock_skew_ms().unwrap();
+        assert!((10_000..11_000).contains(&skew), "skew {}", skew);
+    }
+
     #[test]
     fn test_server_c2_processing() {
         let mut client = Handshake::new(HandshakeRole::Client);
@@ -554,6 +772,75 @@ mod tests {
         assert!(server.is_done());
     }
 
+    #[test]
+    fn test_driver_fragmented_handshake() {
+        for step in [1, 7, 100, HANDSHAKE_SIZE, 1 + HANDSHAKE_SIZE * 2] {
+            let mut client = HandshakeDriver::new(HandshakeRole::Client);
+            let mut server = HandshakeDriver::new(HandshakeRole::Server);
+
+            let mut to_server = client.feed(&[]).unwrap().to_send.to_vec();
+            let mut to_client = Vec::new();
+
+            // C0C1 -> S0S1S2 -> C2
+            for _ in 0..2 {
+                for piece in to_server.chunks(step) {
+                    let progress = server.feed(piece).unwrap();
+                    assert_eq!(progress.consumed, piece.len());
+                    to_client.extend_from_slice(&progress.to_send);
+                }
+                to_server.clear();
+
+                for piece in to_client.chunks(step) {
+                    let progress = client.feed(piece).unwrap();
+                    assert_eq!(progress.consumed, piece.len());
+                    to_server.extend_from_slice(&progress.to_send);
+                }
+                to_client.clear();
+            }
+
+            assert!(client.is_done(), "client not done with step {}", step);
+            assert!(server.is_done(), "server not done with step {}", step);
+        }
+    }
+
+    #[test]
+    fn test_driver_leaves_trailing_bytes() {
+        let mut client = HandshakeDriver::new(HandshakeRole::Client);
+        let mut server = HandshakeDriver::new(HandshakeRole::Server);
+
+        let c0c1 = client.feed(&[]).unwrap();
+        assert_eq!(c0c1.to_send.len(), 1 + HANDSHAKE_SIZE);
+        assert!(!c0c1.done);
+
+        let s0s1s2 = server.feed(&c0c1.to_send).unwrap();
+        assert_eq!(s0s1s2.consumed, 1 + HANDSHAKE_SIZE);
+        assert_eq!(s0s1s2.to_send.len(), 1 + HANDSHAKE_SIZE * 2);
+
+        let c2 = client.feed(&s0s1s2.to_send).unwrap();
+        assert!(c2.done);
+
+        // C2 and the first chunk arrive in the same read
+        let mut input = c2.to_send.to_vec();
+        input.extend_from_slice(&[0x02, 0x00, 0x00]);
+        let progress = server.feed(&input).unwrap();
+        assert!(progress.done);
+        assert!(progress.to_send.is_empty());
+        assert_eq!(progress.consumed, HANDSHAKE_SIZE);
+
+        // Nothing more is taken once done
+        assert_eq!(server.feed(&[0x02]).unwrap().consumed, 0);
+    }
+
+    #[test]
+    fn test_driver_invalid_version() {
+        let mut server = HandshakeDriver::new(HandshakeRole::Server);
+        let mut c0c1 = vec![0u8; 1 + HANDSHAKE_SIZE];
+        c0c1[0] = 2;
+
+        assert!(server.feed(&c0c1[..10]).is_ok());
+        assert!(server.feed(&c0c1[10..]).is_err());
+    }
+
     #[test]
     fn test_process_in_wrong_state() {
         let mut client = Handshake::new(HandshakeRole::Client);
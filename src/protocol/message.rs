@@ -11,7 +11,7 @@
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use std::collections::HashMap;
 
-use crate::amf::{Amf0Decoder, Amf0Encoder, AmfValue};
+use crate::amf::{Amf0Decoder, Amf0Encoder, AmfEncoding, AmfObject, AmfValue, PropertyOrder};
 use crate::error::{AmfError, ProtocolError, Result};
 use crate::media::fourcc::{AudioFourCc, VideoFourCc};
 use crate::protocol::chunk::RtmpChunk;
@@ -127,7 +127,14 @@ pub struct ConnectParams {
     /// Object encoding (AMF version)
     pub object_encoding: f64,
     /// Extra properties from connect object
-    pub extra: HashMap<String, AmfValue>,
+    pub extra: AmfObject,
+    /// The whole connect command object as received
+    ///
+    /// Includes the properties parsed into the fields above, for handlers
+    /// that sign or validate the raw object.
+    pub command_object: AmfValue,
+    /// Optional user arguments following the command object
+    pub arguments: Vec<AmfValue>,
 
     // =========================================================================
     // Enhanced RTMP (E-RTMP) fields
@@ -162,8 +169,13 @@ pub struct ConnectParams {
 
 impl ConnectParams {
     /// Parse from AMF command object
+    ///
+    /// `arguments` is left empty; the server fills it from the command.
     pub fn from_amf(obj: &AmfValue) -> Self {
-        let mut params = ConnectParams::default();
+        let mut params = ConnectParams {
+            command_object: obj.clone(),
+            ..Default::default()
+        };
 
         if let Some(map) = obj.as_object() {
             for (key, value) in map {
@@ -344,7 +356,10 @@ pub struct PublishParams {
 }
 
 /// Play command parameters
+///
+/// Built by the server from the play command; more fields may be added.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct PlayParams {
     /// Stream name
     pub stream_name: String,
@@ -356,11 +371,23 @@ pub struct PlayParams {
     pub reset: bool,
     /// Message stream ID
     pub stream_id: u32,
+    /// Buffer length the player announced for the stream before playing
+    /// (Set Buffer Length), in milliseconds
+    pub buffer_ms: Option<u32>,
 }
 
 impl RtmpMessage {
     /// Parse a message from a chunk
     pub fn from_chunk(chunk: &RtmpChunk) -> Result<Self> {
+        Self::from_chunk_with(chunk, &mut Amf0Decoder::new())
+    }
+
+    /// Parse a message from a chunk, decoding commands and data with `decoder`
+    ///
+    /// The decoder is reset before use, so one decoder can parse many
+    /// messages. Its [`recovered_errors`](Amf0Decoder::recovered_errors)
+    /// count keeps growing across them.
+    pub fn from_chunk_with(chunk: &RtmpChunk, decoder: &mut Amf0Decoder) -> Result<Self> {
         let mut payload = chunk.payload.clone();
 
         match chunk.message_type {
@@ -419,7 +446,7 @@ impl RtmpMessage {
             }),
 
             MSG_COMMAND_AMF0 => {
-                let cmd = Self::parse_command(&mut payload, chunk.stream_id)?;
+                let cmd = Self::parse_command(decoder, &mut payload, chunk.stream_id)?;
                 Ok(RtmpMessage::Command(cmd))
             }
 
@@ -428,12 +455,12 @@ impl RtmpMessage {
                 if !payload.is_empty() && payload[0] == 0x00 {
                     payload.advance(1);
                 }
-                let cmd = Self::parse_command(&mut payload, chunk.stream_id)?;
+                let cmd = Self::parse_command(decoder, &mut payload, chunk.stream_id)?;
                 Ok(RtmpMessage::CommandAmf3(cmd))
             }
 
             MSG_DATA_AMF0 => {
-                let data = Self::parse_data(&mut payload, chunk.stream_id)?;
+                let data = Self::parse_data(decoder, &mut payload, chunk.stream_id)?;
                 Ok(RtmpMessage::Data(data))
             }
 
@@ -441,7 +468,7 @@ impl RtmpMessage {
                 if !payload.is_empty() && payload[0] == 0x00 {
                     payload.advance(1);
                 }
-                let data = Self::parse_data(&mut payload, chunk.stream_id)?;
+                let data = Self::parse_data(decoder, &mut payload, chunk.stream_id)?;
                 Ok(RtmpMessage::DataAmf3(data))
             }
 
@@ -489,8 +516,12 @@ impl RtmpMessage {
     }
 
     /// Parse AMF0 command
-    fn parse_command(payload: &mut Bytes, stream_id: u32) -> Result<Command> {
-        let mut decoder = Amf0Decoder::new();
+    fn parse_command(
+        decoder: &mut Amf0Decoder,
+        payload: &mut Bytes,
+        stream_id: u32,
+    ) -> Result<Command> {
+        decoder.reset();
 
         // Command name
         let name = match decoder.decode(payload)? {
@@ -531,8 +562,12 @@ impl RtmpMessage {
     }
 
     /// Parse AMF0 data message
-    fn parse_data(payload: &mut Bytes, stream_id: u32) -> Result<DataMessage> {
-        let mut decoder = Amf0Decoder::new();
+    fn parse_data(
+        decoder: &mut Amf0Decoder,
+        payload: &mut Bytes,
+        stream_id: u32,
+    ) -> Result<DataMessage> {
+        decoder.reset();
 
         // Handler name
         let name = match decoder.decode(payload)? {
@@ -638,17 +673,9 @@ impl RtmpMessage {
 
             RtmpMessage::Video { data, .. } => (MSG_VIDEO, data.clone()),
 
-            RtmpMessage::Command(cmd) => {
-                let payload = encode_command(cmd);
-                (MSG_COMMAND_AMF0, payload)
-            }
+            RtmpMessage::Command(cmd) => (MSG_COMMAND_AMF0, cmd.encode(AmfEncoding::Amf0)),
 
-            RtmpMessage::CommandAmf3(cmd) => {
-                let mut buf = BytesMut::new();
-                buf.put_u8(0x00); // AMF3 marker
-                buf.put_slice(&encode_command(cmd));
-                (MSG_COMMAND_AMF3, buf.freeze())
-            }
+            RtmpMessage::CommandAmf3(cmd) => (MSG_COMMAND_AMF3, cmd.encode(AmfEncoding::Amf3)),
 
             RtmpMessage::Data(data) => {
                 let payload = encode_data(data);
@@ -669,21 +696,35 @@ impl RtmpMessage {
     }
 }
 
-/// Encode a command to AMF0 bytes
-fn encode_command(cmd: &Command) -> Bytes {
-    let mut encoder = Amf0Encoder::new();
-    encoder.encode(&AmfValue::String(cmd.name.clone()));
-    encoder.encode(&AmfValue::Number(cmd.transaction_id));
-    encoder.encode(&cmd.command_object);
-    for arg in &cmd.arguments {
-        encoder.encode(arg);
-    }
-    encoder.finish()
-}
+/// Conventional property order of command objects
+///
+/// Covers connect command objects, `_result` properties and status info
+/// objects; keys that never appear together only need a consistent
+/// relative order. Flash Media Server writes them in this order and some
+/// peers read `app` as the first property.
+pub const COMMAND_PROPERTY_ORDER: &[&str] = &[
+    "app",
+    "type",
+    "flashVer",
+    "swfUrl",
+    "tcUrl",
+    "fpad",
+    "fmsVer",
+    "capabilities",
+    "mode",
+    "audioCodecs",
+    "videoCodecs",
+    "videoFunction",
+    "pageUrl",
+    "level",
+    "code",
+    "description",
+    "objectEncoding",
+];
 
 /// Encode a data message to AMF0 bytes
 fn encode_data(data: &DataMessage) -> Bytes {
-    let mut encoder = Amf0Encoder::new();
+    let mut encoder = Amf0Encoder::new().property_order(PropertyOrder::STABLE);
     encoder.encode(&AmfValue::String(data.name.clone()));
     for value in &data.values {
         encoder.encode(value);
@@ -693,6 +734,33 @@ fn encode_data(data: &DataMessage) -> Bytes {
 
 /// Build common response messages
 impl Command {
+    /// Encode the command as a message payload
+    ///
+    /// Writes the name, transaction ID, command object and arguments, the
+    /// inverse of how commands are parsed. For [`AmfEncoding::Amf3`] the
+    /// payload starts with the zero byte of an AMF3 command message.
+    pub fn encode(&self, object_encoding: AmfEncoding) -> Bytes {
+        let mut encoder =
+            Amf0Encoder::new().property_order(PropertyOrder::Preferred(COMMAND_PROPERTY_ORDER));
+        encoder.encode(&AmfValue::String(self.name.clone()));
+        encoder.encode(&AmfValue::Number(self.transaction_id));
+        encoder.encode(&self.command_object);
+        for arg in &self.arguments {
+            encoder.encode(arg);
+        }
+        let body = encoder.finish();
+
+        match object_encoding {
+            AmfEncoding::Amf0 => body,
+            AmfEncoding::Amf3 => {
+                let mut buf = BytesMut::with_capacity(1 + body.len());
+                buf.put_u8(0x00);
+                buf.put_slice(&body);
+                buf.freeze()
+            }
+        }
+    }
+
     /// Create a _result response
     pub fn result(transaction_id: f64, properties: AmfValue, info: AmfValue) -> Self {
         Command {
@@ -717,7 +785,7 @@ impl Command {
 
     /// Create an onStatus response
     pub fn on_status(stream_id: u32, level: &str, code: &str, description: &str) -> Self {
-        let mut info = HashMap::new();
+        let mut info = AmfObject::new();
         info.insert("level".to_string(), AmfValue::String(level.to_string()));
         info.insert("code".to_string(), AmfValue::String(code.to_string()));
         info.insert(
@@ -797,7 +865,7 @@ impl ConnectResponseBuilder {
     pub fn build(self, transaction_id: f64) -> Command {
         let properties = self.build_properties();
 
-        let mut info = HashMap::new();
+        let mut info = AmfObject::new();
         info.insert("level".to_string(), AmfValue::String("status".to_string()));
         info.insert(
             "code".to_string(),
@@ -817,7 +885,7 @@ impl ConnectResponseBuilder {
 
     /// Build the properties object for the connect response.
     fn build_properties(&self) -> AmfValue {
-        let mut props = HashMap::new();
+        let mut props = AmfObject::new();
         props.insert("fmsVer".to_string(), AmfValue::String(self.fms_ver.clone()));
         props.insert(
             "capabilities".to_string(),
@@ -834,7 +902,7 @@ impl ConnectResponseBuilder {
 
             // videoFourCcInfoMap
             if !caps.video_codecs.is_empty() {
-                let video_map: HashMap<String, AmfValue> = caps
+                let video_map: AmfObject = caps
                     .video_codecs
                     .iter()
                     .map(|(fourcc, cap)| {
@@ -852,7 +920,7 @@ impl ConnectResponseBuilder {
 
             // audioFourCcInfoMap
             if !caps.audio_codecs.is_empty() {
-                let audio_map: HashMap<String, AmfValue> = caps
+                let audio_map: AmfObject = caps
                     .audio_codecs
                     .iter()
                     .map(|(fourcc, cap)| {
@@ -879,7 +947,7 @@ mod tests {
 
     #[test]
     fn test_connect_params_parsing() {
-        let mut obj = HashMap::new();
+        let mut obj = AmfObject::new();
         obj.insert("app".to_string(), AmfValue::String("live".into()));
         obj.insert(
             "tcUrl".to_string(),
@@ -903,7 +971,7 @@ mod tests {
             stream_id: 0,
         };
 
-        let payload = encode_command(&cmd);
+        let payload = cmd.encode(AmfEncoding::Amf0);
         let chunk = RtmpChunk {
             csid: CSID_COMMAND,
             timestamp: 0,
@@ -921,6 +989,100 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_connect_reencodes_byte_for_byte() {
+        // A connect as a Flash client sends it, written out by hand
+        let mut raw = vec![0x02, 0x00, 0x07];
+        raw.extend_from_slice(b"connect");
+        raw.push(0x00);
+        raw.extend_from_slice(&1.0f64.to_be_bytes());
+        raw.push(0x03);
+        let mut property = |key: &str, value: &[u8]| {
+            raw.extend_from_slice(&(key.len() as u16).to_be_bytes());
+            raw.extend_from_slice(key.as_bytes());
+            raw.extend_from_slice(value);
+        };
+        let string =
+            |s: &str| [&[0x02], &(s.len() as u16).to_be_bytes()[..], s.as_bytes()].concat();
+        let number = |n: f64| [&[0x00][..], &n.to_be_bytes()].concat();
+        property("app", &string("live"));
+        property("flashVer", &string("FMLE/3.0"));
+        property("tcUrl", &string("rtmp://localhost/live"));
+        property("fpad", &[0x01, 0x00]);
+        property("capabilities", &number(239.0));
+        property("audioCodecs", &number(3575.0));
+        property("videoCodecs", &number(252.0));
+        property("videoFunction", &number(1.0));
+        raw.extend_from_slice(&[0x00, 0x00, 0x09]);
+        let original = Bytes::from(raw);
+
+        for (message_type, encoding) in [
+            (MSG_COMMAND_AMF0, AmfEncoding::Amf0),
+            (MSG_COMMAND_AMF3, AmfEncoding::Amf3),
+        ] {
+            let payload = match encoding {
+                AmfEncoding::Amf0 => original.clone(),
+                AmfEncoding::Amf3 => [&[0x00][..], &original].concat().into(),
+            };
+            let chunk = RtmpChunk {
+                csid: CSID_COMMAND,
+                timestamp: 0,
+                message_type,
+                stream_id: 0,
+                payload: payload.clone(),
+            };
+
+            let cmd = match RtmpMessage::from_chunk(&chunk).unwrap() {
+                RtmpMessage::Command(cmd) | RtmpMessage::CommandAmf3(cmd) => cmd,
+                other => panic!("expected command, got {:?}", other),
+            };
+            assert_eq!(cmd.encode(encoding), payload);
+        }
+    }
+
+    #[test]
+    fn test_amf_encoding_from_object_encoding() {
+        assert_eq!(AmfEncoding::from_object_encoding(0.0), AmfEncoding::Amf0);
+        assert_eq!(AmfEncoding::from_object_encoding(3.0), AmfEncoding::Amf3);
+        assert_eq!(AmfEncoding::default(), AmfEncoding::Amf0);
+    }
+
+    #[test]
+    fn test_command_object_property_order() {
+        let mut obj = AmfObject::new();
+        for key in ["objectEncoding", "tcUrl", "flashVer", "app", "custom"] {
+            obj.insert(key.to_string(), AmfValue::String(key.into()));
+        }
+        let cmd = Command {
+            name: CMD_CONNECT.to_string(),
+            transaction_id: 1.0,
+            command_object: AmfValue::Object(obj),
+            arguments: vec![],
+            stream_id: 0,
+        };
+
+        let (_, payload) = RtmpMessage::Command(cmd.clone()).encode();
+        for _ in 0..16 {
+            assert_eq!(RtmpMessage::Command(cmd.clone()).encode().1, payload);
+        }
+
+        // "connect" string + transaction ID, then the object with "app" first
+        let object = &payload[3 + 7 + 9..];
+        assert_eq!(object[0], 0x03);
+        assert_eq!(&object[1..6], b"\x00\x03app");
+
+        let position = |key: &str| {
+            payload
+                .windows(key.len())
+                .position(|w| w == key.as_bytes())
+                .unwrap()
+        };
+        assert!(position("app") < position("flashVer"));
+        assert!(position("flashVer") < position("tcUrl"));
+        assert!(position("tcUrl") < position("objectEncoding"));
+        assert!(position("objectEncoding") < position("custom"));
+    }
+
     #[test]
     fn test_set_chunk_size_message() {
         let chunk = RtmpChunk {
@@ -1159,7 +1321,7 @@ mod tests {
         let mut encoder = Amf0Encoder::new();
         encoder.encode(&AmfValue::String("@setDataFrame".into()));
         encoder.encode(&AmfValue::String("onMetaData".into()));
-        let mut metadata = HashMap::new();
+        let mut metadata = AmfObject::new();
         metadata.insert("width".to_string(), AmfValue::Number(1920.0));
         encoder.encode(&AmfValue::Object(metadata));
 
@@ -1202,7 +1364,7 @@ mod tests {
 
     #[test]
     fn test_command_result() {
-        let mut props = HashMap::new();
+        let mut props = AmfObject::new();
         props.insert(
             "fmsVer".to_string(),
             AmfValue::String("FMS/3,5,7,7009".into()),
@@ -1245,7 +1407,7 @@ mod tests {
 
     #[test]
     fn test_connect_params_all_fields() {
-        let mut obj = HashMap::new();
+        let mut obj = AmfObject::new();
         obj.insert("app".to_string(), AmfValue::String("live".into()));
         obj.insert(
             "flashVer".to_string(),
@@ -1283,12 +1445,20 @@ mod tests {
         assert_eq!(params.page_url, Some("http://twitch.tv".into()));
         assert_eq!(params.object_encoding, 0.0);
         assert!(params.extra.contains_key("custom"));
+        assert_eq!(
+            params
+                .command_object
+                .as_object()
+                .and_then(|o| o.get("tcUrl"))
+                .and_then(|v| v.as_str()),
+            Some("rtmp://example.com/live")
+        );
     }
 
     #[test]
     fn test_connect_params_case_insensitive() {
         // Test lowercase variants
-        let mut obj = HashMap::new();
+        let mut obj = AmfObject::new();
         obj.insert("flashver".to_string(), AmfValue::String("test".into()));
         obj.insert("tcurl".to_string(), AmfValue::String("url".into()));
         obj.insert("pageurl".to_string(), AmfValue::String("page".into()));
@@ -1415,13 +1585,13 @@ mod tests {
 
     #[test]
     fn test_connect_params_ertmp_fields() {
-        let mut obj = HashMap::new();
+        let mut obj = AmfObject::new();
         obj.insert("app".to_string(), AmfValue::String("live".into()));
 
         // E-RTMP fields
         obj.insert("capsEx".to_string(), AmfValue::Number(3.0)); // RECONNECT | MULTITRACK
 
-        let mut video_map = HashMap::new();
+        let mut video_map = AmfObject::new();
         video_map.insert("avc1".to_string(), AmfValue::Number(7.0)); // Full capability
         video_map.insert("hvc1".to_string(), AmfValue::Number(4.0)); // Forward only
         obj.insert(
@@ -1429,7 +1599,7 @@ mod tests {
             AmfValue::Object(video_map),
         );
 
-        let mut audio_map = HashMap::new();
+        let mut audio_map = AmfObject::new();
         audio_map.insert("mp4a".to_string(), AmfValue::Number(7.0));
         audio_map.insert("Opus".to_string(), AmfValue::Number(4.0));
         obj.insert(
@@ -1455,7 +1625,7 @@ mod tests {
 
     #[test]
     fn test_connect_params_fourcc_list() {
-        let mut obj = HashMap::new();
+        let mut obj = AmfObject::new();
         obj.insert("app".to_string(), AmfValue::String("live".into()));
 
         // fourCcList as alternative to info maps
@@ -1480,7 +1650,7 @@ mod tests {
 
     #[test]
     fn test_connect_params_no_ertmp() {
-        let mut obj = HashMap::new();
+        let mut obj = AmfObject::new();
         obj.insert("app".to_string(), AmfValue::String("live".into()));
 
         let params = ConnectParams::from_amf(&AmfValue::Object(obj));
@@ -1494,7 +1664,7 @@ mod tests {
 
     #[test]
     fn test_connect_params_caps_ex_flags() {
-        let mut obj = HashMap::new();
+        let mut obj = AmfObject::new();
         obj.insert("app".to_string(), AmfValue::String("live".into()));
         obj.insert("capsEx".to_string(), AmfValue::Number(15.0)); // All flags
 
@@ -1509,11 +1679,11 @@ mod tests {
 
     #[test]
     fn test_connect_params_to_enhanced_capabilities() {
-        let mut obj = HashMap::new();
+        let mut obj = AmfObject::new();
         obj.insert("app".to_string(), AmfValue::String("live".into()));
         obj.insert("capsEx".to_string(), AmfValue::Number(6.0)); // MULTITRACK | MODEX
 
-        let mut video_map = HashMap::new();
+        let mut video_map = AmfObject::new();
         video_map.insert("avc1".to_string(), AmfValue::Number(7.0));
         video_map.insert("av01".to_string(), AmfValue::Number(4.0));
         obj.insert(
@@ -1521,7 +1691,7 @@ mod tests {
             AmfValue::Object(video_map),
         );
 
-        let mut audio_map = HashMap::new();
+        let mut audio_map = AmfObject::new();
         audio_map.insert("Opus".to_string(), AmfValue::Number(7.0));
         obj.insert(
             "audioFourCcInfoMap".to_string(),
@@ -1546,7 +1716,7 @@ mod tests {
 
     #[test]
     fn test_connect_params_fourcc_list_to_capabilities() {
-        let mut obj = HashMap::new();
+        let mut obj = AmfObject::new();
         obj.insert("app".to_string(), AmfValue::String("live".into()));
 
         // Only fourCcList (no info maps) -> full capability assumed
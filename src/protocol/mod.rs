@@ -5,15 +5,23 @@
 //! - Chunk stream multiplexing and demultiplexing
 //! - Message framing and parsing
 //! - Enhanced RTMP capability negotiation
+//! - PROXY protocol headers from load balancers
+//! - RTMP URL parsing
 
 pub mod chunk;
 pub mod constants;
 pub mod enhanced;
 pub mod handshake;
 pub mod message;
+pub mod proxy;
 pub mod quirks;
+pub mod url;
 
-pub use chunk::{ChunkDecoder, ChunkEncoder};
+pub use chunk::{ChunkDecoder, ChunkEncoder, ChunkStreamInfo};
 pub use enhanced::{CapsEx, EnhancedCapabilities, EnhancedRtmpMode, FourCcCapability};
-pub use handshake::{Handshake, HandshakeRole};
+pub use handshake::{
+    Handshake, HandshakeDriver, HandshakeProgress, HandshakeRole, HandshakeVersionPolicy,
+};
 pub use message::{ConnectParams, ConnectResponseBuilder, RtmpMessage};
+pub use proxy::{parse_proxy_header, ProxyHeader};
+pub use url::{parse_rtmp_url, ParsedUrl};
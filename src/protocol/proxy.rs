@@ -0,0 +1,265 @@
+//! PROXY protocol header parsing
+//!
+//! TCP load balancers (HAProxy, AWS NLB, ...) can prefix each connection
+//! with a PROXY protocol header naming the real client, since the server
+//! otherwise only sees the balancer's address. The header comes before
+//! the RTMP handshake, in one of two formats:
+//!
+//! ```text
+//! v1: PROXY TCP4 203.0.113.7 192.0.2.1 51234 1935\r\n
+//! v2: 12-byte signature, version/command, family, length, addresses
+//! ```
+//!
+//! Reference: <https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt>
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::error::ProtocolError;
+
+/// Start of a v1 header
+const V1_PREFIX: &[u8] = b"PROXY ";
+
+/// Longest v1 header, including the CRLF
+const V1_MAX_LEN: usize = 107;
+
+/// Signature starting a v2 header
+const V2_SIGNATURE: [u8; 12] = [
+    0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A,
+];
+
+/// Fixed part of a v2 header: signature, version/command, family, length
+const V2_HEADER_LEN: usize = 16;
+
+/// Addresses carried by a PROXY header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyHeader {
+    /// The real client (None for health checks and unknown protocols)
+    pub source: Option<SocketAddr>,
+    /// The address the client connected to
+    pub destination: Option<SocketAddr>,
+}
+
+/// Parse a PROXY header (v1 or v2) at the start of `buf`
+///
+/// Returns the header and its length in bytes, or `None` if more data is
+/// needed. Anything that is not a well-formed header is an error.
+pub fn parse_proxy_header(buf: &[u8]) -> Result<Option<(ProxyHeader, usize)>, ProtocolError> {
+    let starts_with = |prefix: &[u8]| {
+        let n = buf.len().min(prefix.len());
+        buf[..n] == prefix[..n]
+    };
+
+    if starts_with(&V2_SIGNATURE) {
+        parse_v2(buf)
+    } else if starts_with(V1_PREFIX) {
+        parse_v1(buf)
+    } else {
+        Err(invalid("missing PROXY signature"))
+    }
+}
+
+fn parse_v1(buf: &[u8]) -> Result<Option<(ProxyHeader, usize)>, ProtocolError> {
+    let searched = &buf[..buf.len().min(V1_MAX_LEN)];
+    let Some(end) = searched.windows(2).position(|w| w == b"\r\n") else {
+        if buf.len() >= V1_MAX_LEN {
+            return Err(invalid("v1 header too long"));
+        }
+        return Ok(None);
+    };
+
+    let line =
+        std::str::from_utf8(&buf[V1_PREFIX.len()..end]).map_err(|_| invalid("v1 not ASCII"))?;
+    let fields: Vec<&str> = line.split(' ').collect();
+
+    let header = match fields.as_slice() {
+        ["UNKNOWN", ..] => ProxyHeader {
+            source: None,
+            destination: None,
+        },
+        [family @ ("TCP4" | "TCP6"), src, dst, src_port, dst_port] => {
+            let addr = |ip: &str, port: &str| -> Result<SocketAddr, ProtocolError> {
+                let ip: IpAddr = ip.parse().map_err(|_| invalid("v1 bad address"))?;
+                if ip.is_ipv4() != (*family == "TCP4") {
+                    return Err(invalid("v1 address does not match family"));
+                }
+                // Ports are decimal without sign or leading zeros
+                if port.starts_with(['+', '0']) && port != "0" {
+                    return Err(invalid("v1 bad port"));
+                }
+                let port: u16 = port.parse().map_err(|_| invalid("v1 bad port"))?;
+                Ok(SocketAddr::new(ip, port))
+            };
+            ProxyHeader {
+                source: Some(addr(src, src_port)?),
+                destination: Some(addr(dst, dst_port)?),
+            }
+        }
+        _ => return Err(invalid("v1 bad fields")),
+    };
+
+    Ok(Some((header, end + 2)))
+}
+
+fn parse_v2(buf: &[u8]) -> Result<Option<(ProxyHeader, usize)>, ProtocolError> {
+    if buf.len() < V2_HEADER_LEN {
+        return Ok(None);
+    }
+
+    let version = buf[12] >> 4;
+    let command = buf[12] & 0x0F;
+    let family = buf[13];
+    let len = u16::from_be_bytes([buf[14], buf[15]]) as usize;
+
+    if version != 2 {
+        return Err(invalid("v2 bad version"));
+    }
+    if command > 1 {
+        return Err(invalid("v2 bad command"));
+    }
+    let Some(body) = buf.get(V2_HEADER_LEN..V2_HEADER_LEN + len) else {
+        return Ok(None);
+    };
+
+    let unknown = ProxyHeader {
+        source: None,
+        destination: None,
+    };
+
+    // LOCAL: the balancer's own connection (health checks)
+    if command == 0 {
+        return Ok(Some((unknown, V2_HEADER_LEN + len)));
+    }
+
+    let header = match family {
+        // TCP or UDP over IPv4
+        0x11 | 0x12 => {
+            let body = body
+                .get(..12)
+                .ok_or_else(|| invalid("v2 short IPv4 block"))?;
+            let ip = |at: usize| {
+                IpAddr::V4(Ipv4Addr::new(
+                    body[at],
+                    body[at + 1],
+                    body[at + 2],
+                    body[at + 3],
+                ))
+            };
+            ProxyHeader {
+                source: Some(SocketAddr::new(ip(0), port(body, 8))),
+                destination: Some(SocketAddr::new(ip(4), port(body, 10))),
+            }
+        }
+        // TCP or UDP over IPv6
+        0x21 | 0x22 => {
+            let body = body
+                .get(..36)
+                .ok_or_else(|| invalid("v2 short IPv6 block"))?;
+            let ip = |at: usize| {
+                let octets: [u8; 16] = body[at..at + 16].try_into().unwrap();
+                IpAddr::V6(Ipv6Addr::from(octets))
+            };
+            ProxyHeader {
+                source: Some(SocketAddr::new(ip(0), port(body, 32))),
+                destination: Some(SocketAddr::new(ip(16), port(body, 34))),
+            }
+        }
+        // Unspecified or unix sockets: no usable address
+        _ => unknown,
+    };
+
+    Ok(Some((header, V2_HEADER_LEN + len)))
+}
+
+fn port(body: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([body[at], body[at + 1]])
+}
+
+fn invalid(reason: &str) -> ProtocolError {
+    ProtocolError::InvalidProxyHeader(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_v1() {
+        let mut buf = b"PROXY TCP4 203.0.113.7 192.0.2.1 51234 1935\r\n".to_vec();
+        let len = buf.len();
+        buf.extend_from_slice(&[0x03, 0, 0, 0]);
+
+        let (header, consumed) = parse_proxy_header(&buf).unwrap().unwrap();
+        assert_eq!(consumed, len);
+        assert_eq!(header.source, Some("203.0.113.7:51234".parse().unwrap()));
+        assert_eq!(header.destination, Some("192.0.2.1:1935".parse().unwrap()));
+
+        let (header, _) = parse_proxy_header(b"PROXY TCP6 2001:db8::1 2001:db8::2 4000 1935\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(header.source, Some("[2001:db8::1]:4000".parse().unwrap()));
+
+        let (header, _) = parse_proxy_header(b"PROXY UNKNOWN\r\n").unwrap().unwrap();
+        assert_eq!(header.source, None);
+
+        // Partial headers wait for more data
+        assert!(parse_proxy_header(b"PRO").unwrap().is_none());
+        assert!(parse_proxy_header(b"PROXY TCP4 1.2.3.4").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_v1_malformed() {
+        for bad in [
+            &b"PROXY TCP4 1.2.3.4 5.6.7.8 1111\r\n"[..],
+            b"PROXY TCP4 2001:db8::1 5.6.7.8 1111 2222\r\n",
+            b"PROXY TCP4 1.2.3.4 5.6.7.8 99999 2222\r\n",
+            b"PROXY TCP4 1.2.3.4 5.6.7.8 0111 2222\r\n",
+            b"PROXY UDP4 1.2.3.4 5.6.7.8 1111 2222\r\n",
+            b"\x03\x00\x00\x00\x00",
+        ] {
+            assert!(parse_proxy_header(bad).is_err(), "{:?}", bad);
+        }
+
+        let endless = [b"PROXY TCP4 ".as_slice(), &[b'1'; 120]].concat();
+        assert!(parse_proxy_header(&endless).is_err());
+    }
+
+    fn v2(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.push(0x20 | command);
+        buf.push(family);
+        buf.extend_from_slice(&(addresses.len() as u16).to_be_bytes());
+        buf.extend_from_slice(addresses);
+        buf
+    }
+
+    #[test]
+    fn test_parse_v2() {
+        let addresses = [203, 0, 113, 7, 192, 0, 2, 1, 0xC8, 0x22, 0x07, 0x8F];
+        let mut buf = v2(1, 0x11, &addresses);
+        buf.extend_from_slice(&[0x03, 0, 0, 0]);
+
+        let (header, consumed) = parse_proxy_header(&buf).unwrap().unwrap();
+        assert_eq!(consumed, 28);
+        assert_eq!(header.source, Some("203.0.113.7:51234".parse().unwrap()));
+        assert_eq!(header.destination, Some("192.0.2.1:1935".parse().unwrap()));
+
+        // Health checks carry no client
+        let (header, consumed) = parse_proxy_header(&v2(0, 0x00, &[])).unwrap().unwrap();
+        assert_eq!((header.source, consumed), (None, 16));
+
+        // Partial headers wait for more data
+        assert!(parse_proxy_header(&buf[..10]).unwrap().is_none());
+        assert!(parse_proxy_header(&buf[..20]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_v2_malformed() {
+        let mut bad_version = v2(1, 0x11, &[0; 12]);
+        bad_version[12] = 0x11;
+        assert!(parse_proxy_header(&bad_version).is_err());
+
+        assert!(parse_proxy_header(&v2(2, 0x11, &[0; 12])).is_err());
+        assert!(parse_proxy_header(&v2(1, 0x11, &[0; 8])).is_err());
+        assert!(parse_proxy_header(&v2(1, 0x21, &[0; 12])).is_err());
+    }
+}
@@ -25,10 +25,18 @@
 //! - Sends multiple audio/video sequence headers
 //! - May have timestamp discontinuities
 
+use std::collections::BTreeMap;
+
+use crate::amf::Amf0Decoder;
 use crate::protocol::message::Command;
 
 /// Configuration for handling encoder quirks
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
 pub struct QuirksConfig {
     /// Accept commands before handshake completes
     pub allow_early_commands: bool,
@@ -39,6 +47,14 @@ pub struct QuirksConfig {
     /// Accept malformed AMF (missing end markers)
     pub lenient_amf: bool,
 
+    /// Log malformed AMF recovered from in lenient mode (rate-limited
+    /// `tracing::warn`). Recoveries are counted in the session stats either way.
+    pub warn_on_amf_recovery: bool,
+
+    /// Decode the rest of a command or data message as AMF3 after a
+    /// top-level AVM+ marker, as FMS does (see [`Amf0Decoder::sticky_avmplus`])
+    pub sticky_avmplus: bool,
+
     /// Accept timestamp regression
     pub allow_timestamp_regression: bool,
 
@@ -50,6 +66,10 @@ pub struct QuirksConfig {
 
     /// Accept oversized chunks (larger than negotiated)
     pub allow_oversized_chunks: bool,
+
+    /// Clamp a frame's timestamp delta from the previous frame of the same
+    /// type to at most this many milliseconds (None = no clamping)
+    pub max_timestamp_delta: Option<u32>,
 }
 
 impl Default for QuirksConfig {
@@ -59,10 +79,13 @@ impl Default for QuirksConfig {
             allow_early_commands: true,
             allow_fc_before_connect: true,
             lenient_amf: true,
+            warn_on_amf_recovery: false,
+            sticky_avmplus: false,
             allow_timestamp_regression: true,
             allow_duplicate_metadata: true,
             allow_empty_app: true,
             allow_oversized_chunks: true,
+            max_timestamp_delta: None,
         }
     }
 }
@@ -74,12 +97,22 @@ impl QuirksConfig {
             allow_early_commands: false,
             allow_fc_before_connect: false,
             lenient_amf: false,
+            warn_on_amf_recovery: false,
+            sticky_avmplus: false,
             allow_timestamp_regression: false,
             allow_duplicate_metadata: false,
             allow_empty_app: false,
             allow_oversized_chunks: false,
+            max_timestamp_delta: None,
         }
     }
+
+    /// Create the AMF0 decoder for a command or data message
+    pub fn amf0_decoder(&self) -> Amf0Decoder {
+        Amf0Decoder::with_lenient(self.lenient_amf)
+            .sticky_avmplus(self.sticky_avmplus)
+            .warn_on_recovery(self.warn_on_amf_recovery)
+    }
 }
 
 /// Detected encoder type
@@ -126,8 +159,16 @@ impl EncoderType {
 /// 3. FCPublish
 /// 4. createStream -> _result
 /// 5. publish -> onStatus
+///
+/// Message streams recorded with [`on_stream_created`](Self::on_stream_created)
+/// have their own publish or play state, so one connection can publish one
+/// stream while playing another.
 pub struct CommandSequence {
     state: CommandSequenceState,
+    /// Created message streams and what they are doing
+    streams: BTreeMap<u32, CommandSequenceState>,
+    /// Stream the last stream command applied to
+    current: Option<u32>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -143,11 +184,22 @@ impl CommandSequence {
     pub fn new() -> Self {
         Self {
             state: CommandSequenceState::Initial,
+            streams: BTreeMap::new(),
+            current: None,
         }
     }
 
     /// Check if a command is valid in the current state
+    ///
+    /// Stream commands are checked against the state of the message
+    /// stream they target once any stream IDs are known, or the state
+    /// [`on_command`](Self::on_command) keeps otherwise.
     pub fn is_valid_command(&self, cmd: &Command) -> bool {
+        let target = if self.streams.is_empty() {
+            Some(self.state)
+        } else {
+            self.streams.get(&target_stream(cmd)).copied()
+        };
         match cmd.name.as_str() {
             "connect" => self.state == CommandSequenceState::Initial,
             "releaseStream" | "FCPublish" => {
@@ -158,39 +210,106 @@ impl CommandSequence {
                 self.state == CommandSequenceState::Connected
                     || self.state == CommandSequenceState::Initial // OBS quirk
             }
-            "publish" => self.state == CommandSequenceState::StreamCreated,
-            "play" => self.state == CommandSequenceState::StreamCreated,
-            "FCUnpublish" | "deleteStream" | "closeStream" => {
-                self.state == CommandSequenceState::Publishing
-                    || self.state == CommandSequenceState::Playing
-            }
+            "publish" | "play" => target == Some(CommandSequenceState::StreamCreated),
+            "FCUnpublish" | "deleteStream" | "closeStream" => matches!(
+                target,
+                Some(
+                    CommandSequenceState::StreamCreated
+                        | CommandSequenceState::Publishing
+                        | CommandSequenceState::Playing
+                )
+            ),
             _ => true, // Allow unknown commands
         }
     }
 
     /// Transition state based on command response
+    ///
+    /// Tracks a single stream without IDs; use
+    /// [`on_stream_created`](Self::on_stream_created) and
+    /// [`on_stream_command`](Self::on_stream_command) to track each stream.
     pub fn on_command(&mut self, cmd_name: &str) {
         match cmd_name {
             "connect" => self.state = CommandSequenceState::Connected,
             "createStream" => self.state = CommandSequenceState::StreamCreated,
             "publish" => self.state = CommandSequenceState::Publishing,
             "play" => self.state = CommandSequenceState::Playing,
-            "FCUnpublish" | "deleteStream" | "closeStream" => {
-                self.state = CommandSequenceState::Connected;
-            }
+            // closeStream keeps the stream ID for another publish/play;
+            // FCUnpublish is only a notification ahead of the teardown
+            "closeStream" => self.state = CommandSequenceState::StreamCreated,
+            "deleteStream" => self.state = CommandSequenceState::Connected,
             _ => {}
         }
     }
 
+    /// Record the stream ID the server returned for createStream
+    pub fn on_stream_created(&mut self, stream_id: u32) {
+        self.state = CommandSequenceState::Connected;
+        self.streams
+            .insert(stream_id, CommandSequenceState::StreamCreated);
+        self.current = Some(stream_id);
+    }
+
+    /// Transition the state of one message stream
+    pub fn on_stream_command(&mut self, cmd_name: &str, stream_id: u32) {
+        let Some(state) = self.streams.get_mut(&stream_id) else {
+            return;
+        };
+        match cmd_name {
+            "publish" => *state = CommandSequenceState::Publishing,
+            "play" => *state = CommandSequenceState::Playing,
+            // closeStream keeps the stream ID for another publish/play;
+            // FCUnpublish is only a notification ahead of the teardown
+            "closeStream" => *state = CommandSequenceState::StreamCreated,
+            "deleteStream" => {
+                self.streams.remove(&stream_id);
+                self.current = self.streams.keys().next_back().copied();
+                return;
+            }
+            _ => return,
+        }
+        self.current = Some(stream_id);
+    }
+
     /// Get current state
+    ///
+    /// The state of the stream last created or used, if any is left.
     pub fn state(&self) -> &'static str {
-        match self.state {
-            CommandSequenceState::Initial => "initial",
-            CommandSequenceState::Connected => "connected",
-            CommandSequenceState::StreamCreated => "stream_created",
-            CommandSequenceState::Publishing => "publishing",
-            CommandSequenceState::Playing => "playing",
-        }
+        let state = self
+            .current
+            .and_then(|id| self.streams.get(&id))
+            .unwrap_or(&self.state);
+        state_name(*state)
+    }
+
+    /// Get the state of one message stream (None if it was never created
+    /// or has been deleted)
+    pub fn stream_state(&self, stream_id: u32) -> Option<&'static str> {
+        self.streams.get(&stream_id).map(|state| state_name(*state))
+    }
+}
+
+fn state_name(state: CommandSequenceState) -> &'static str {
+    match state {
+        CommandSequenceState::Initial => "initial",
+        CommandSequenceState::Connected => "connected",
+        CommandSequenceState::StreamCreated => "stream_created",
+        CommandSequenceState::Publishing => "publishing",
+        CommandSequenceState::Playing => "playing",
+    }
+}
+
+/// Message stream a command targets
+///
+/// deleteStream names the stream in its first argument and is usually
+/// sent on stream 0; other stream commands are sent on their stream.
+fn target_stream(cmd: &Command) -> u32 {
+    match (
+        cmd.name.as_str(),
+        cmd.arguments.first().and_then(|v| v.as_number()),
+    ) {
+        ("deleteStream", Some(id)) => id as u32,
+        _ => cmd.stream_id,
     }
 }
 
@@ -207,6 +326,7 @@ impl Default for CommandSequence {
 pub struct TimestampNormalizer {
     last_timestamp: u32,
     offset: u32,
+    rebase_pending: bool,
 }
 
 impl TimestampNormalizer {
@@ -214,15 +334,23 @@ impl TimestampNormalizer {
         Self {
             last_timestamp: 0,
             offset: 0,
+            rebase_pending: false,
         }
     }
 
     /// Normalize a timestamp, handling regression
     pub fn normalize(&mut self, timestamp: u32) -> u32 {
-        // Check for significant regression (more than 1 second)
-        if timestamp < self.last_timestamp && self.last_timestamp - timestamp > 1000 {
-            // Timestamp regressed significantly, adjust offset
-            self.offset = self.last_timestamp + 1;
+        if self.rebase_pending {
+            // Continue right after the last timestamp
+            self.rebase_pending = false;
+            self.offset = self.last_timestamp.wrapping_add(1).wrapping_sub(timestamp);
+        } else {
+            // Check for significant regression (more than 1 second)
+            let shifted = timestamp.wrapping_add(self.offset);
+            if shifted < self.last_timestamp && self.last_timestamp - shifted > 1000 {
+                // Timestamp regressed significantly, adjust offset
+                self.offset = self.last_timestamp + 1;
+            }
         }
 
         let normalized = timestamp.wrapping_add(self.offset);
@@ -230,10 +358,19 @@ impl TimestampNormalizer {
         normalized
     }
 
+    /// Continue the next timestamp from the last one, however far back it is
+    ///
+    /// For a new source taking over the timeline, such as a publisher that
+    /// reconnects with its timestamps starting from zero again.
+    pub fn rebase(&mut self) {
+        self.rebase_pending = true;
+    }
+
     /// Reset normalizer state
     pub fn reset(&mut self) {
         self.last_timestamp = 0;
         self.offset = 0;
+        self.rebase_pending = false;
     }
 }
 
@@ -243,6 +380,44 @@ impl Default for TimestampNormalizer {
     }
 }
 
+/// Clamp oversized forward timestamp jumps
+///
+/// Some encoders emit a single bogus delta (e.g. 0x00FFFFFF) between two
+/// frames. The jump is cut down to `max_delta` and later timestamps are
+/// shifted by the same amount, so the stream stays continuous. Backward
+/// steps are left alone; see [`TimestampNormalizer`] for regressions.
+#[derive(Debug)]
+pub struct TimestampDeltaClamp {
+    max_delta: u32,
+    last_timestamp: Option<u32>,
+    offset: u32,
+}
+
+impl TimestampDeltaClamp {
+    pub fn new(max_delta: u32) -> Self {
+        Self {
+            max_delta,
+            last_timestamp: None,
+            offset: 0,
+        }
+    }
+
+    /// Clamp a timestamp, returning it and whether the delta was cut down
+    pub fn clamp(&mut self, timestamp: u32) -> (u32, bool) {
+        let mut clamped = false;
+        if let Some(last) = self.last_timestamp {
+            // Deltas past i32::MAX are backward steps across the wrap
+            let delta = timestamp.wrapping_sub(last);
+            if delta > self.max_delta && delta <= i32::MAX as u32 {
+                self.offset = self.offset.wrapping_add(delta - self.max_delta);
+                clamped = true;
+            }
+        }
+        self.last_timestamp = Some(timestamp);
+        (timestamp.wrapping_sub(self.offset), clamped)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -310,6 +485,32 @@ mod tests {
         assert!(!config.allow_oversized_chunks);
     }
 
+    #[test]
+    fn test_amf0_decoder_follows_quirks() {
+        use bytes::Bytes;
+
+        // Unknown marker
+        let mut decoder = QuirksConfig::default().amf0_decoder();
+        let mut buf = Bytes::from_static(&[0xFF]);
+        assert_eq!(decoder.decode(&mut buf).unwrap(), AmfValue::Undefined);
+        assert_eq!(decoder.recovered_errors(), 1);
+
+        let mut buf = Bytes::from_static(&[0xFF]);
+        assert!(QuirksConfig::strict()
+            .amf0_decoder()
+            .decode(&mut buf)
+            .is_err());
+
+        // AVM+ marker, then AMF3 integer 7 and null
+        let quirks = QuirksConfig {
+            sticky_avmplus: true,
+            ..Default::default()
+        };
+        let mut buf = Bytes::from_static(&[0x11, 0x04, 0x07, 0x01]);
+        let values = quirks.amf0_decoder().decode_all(&mut buf).unwrap();
+        assert_eq!(values, vec![AmfValue::Integer(7), AmfValue::Null]);
+    }
+
     #[test]
     fn test_encoder_type_detection() {
         // OBS detection
@@ -542,6 +743,75 @@ mod tests {
         assert_eq!(seq.state(), "connected");
     }
 
+    #[test]
+    fn test_command_sequence_republish_after_teardown() {
+        let mut seq = CommandSequence::new();
+        let command = |name: &str| Command {
+            name: name.to_string(),
+            transaction_id: 0.0,
+            command_object: AmfValue::Null,
+            arguments: vec![],
+            stream_id: 1,
+        };
+
+        seq.on_command("connect");
+        seq.on_command("createStream");
+        seq.on_command("publish");
+
+        // OBS tears down with FCUnpublish, closeStream, deleteStream
+        for name in ["FCUnpublish", "closeStream", "deleteStream"] {
+            assert!(seq.is_valid_command(&command(name)), "{} rejected", name);
+            seq.on_command(name);
+        }
+        assert_eq!(seq.state(), "connected");
+
+        // The same connection can set up another stream
+        assert!(seq.is_valid_command(&command("createStream")));
+        seq.on_command("createStream");
+        assert!(seq.is_valid_command(&command("publish")));
+
+        // closeStream alone leaves the stream ready to publish again
+        seq.on_command("publish");
+        seq.on_command("closeStream");
+        assert_eq!(seq.state(), "stream_created");
+        assert!(seq.is_valid_command(&command("publish")));
+    }
+
+    #[test]
+    fn test_command_sequence_publish_and_play_streams() {
+        let mut seq = CommandSequence::new();
+        let command = |name: &str, stream_id: u32| Command {
+            name: name.to_string(),
+            transaction_id: 0.0,
+            command_object: AmfValue::Null,
+            arguments: vec![],
+            stream_id,
+        };
+
+        seq.on_command("connect");
+        seq.on_stream_created(1);
+        seq.on_stream_command("publish", 1);
+        seq.on_stream_created(2);
+
+        // Stream 2 can play while stream 1 publishes
+        assert!(!seq.is_valid_command(&command("publish", 1)));
+        assert!(seq.is_valid_command(&command("play", 2)));
+        seq.on_stream_command("play", 2);
+        assert_eq!(seq.stream_state(1), Some("publishing"));
+        assert_eq!(seq.stream_state(2), Some("playing"));
+        assert!(!seq.is_valid_command(&command("play", 3)));
+
+        // deleteStream names its stream in the arguments
+        let delete = Command {
+            arguments: vec![AmfValue::Number(2.0)],
+            ..command("deleteStream", 0)
+        };
+        assert!(seq.is_valid_command(&delete));
+        seq.on_stream_command("deleteStream", 2);
+        assert_eq!(seq.stream_state(2), None);
+        assert_eq!(seq.state(), "publishing");
+    }
+
     #[test]
     fn test_command_sequence_unknown_command_always_valid() {
         let seq = CommandSequence::new();
@@ -558,6 +828,23 @@ mod tests {
         assert!(seq.is_valid_command(&unknown));
     }
 
+    #[test]
+    fn test_timestamp_delta_clamp() {
+        let mut clamp = TimestampDeltaClamp::new(1000);
+
+        assert_eq!(clamp.clamp(0), (0, false));
+        assert_eq!(clamp.clamp(40), (40, false));
+
+        // Pathological jump is cut to the maximum delta
+        assert_eq!(clamp.clamp(0x00FF_FFFF), (1040, true));
+
+        // Later frames keep their spacing
+        assert_eq!(clamp.clamp(0x00FF_FFFF + 40), (1080, false));
+
+        // Small backward steps pass through
+        assert_eq!(clamp.clamp(0x00FF_FFFF + 20), (1060, false));
+    }
+
     #[test]
     fn test_timestamp_normalizer_reset() {
         let mut normalizer = TimestampNormalizer::new();
@@ -587,6 +874,20 @@ mod tests {
         assert!(next > after_first);
     }
 
+    #[test]
+    fn test_timestamp_normalizer_rebase() {
+        let mut normalizer = TimestampNormalizer::new();
+        normalizer.normalize(0);
+        normalizer.normalize(40);
+
+        // A new source starting over continues after the last timestamp,
+        // even when it steps back less than a second
+        normalizer.rebase();
+        assert_eq!(normalizer.normalize(0), 41);
+        assert_eq!(normalizer.normalize(33), 74);
+        assert_eq!(normalizer.normalize(66), 107);
+    }
+
     #[test]
     fn test_timestamp_normalizer_default() {
         let normalizer = TimestampNormalizer::default();
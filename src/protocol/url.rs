@@ -0,0 +1,70 @@
+//! RTMP URL parsing
+//!
+//! Shared by the client, which connects to `rtmp://host[:port]/app/stream`,
+//! and the registry, which maps the same URLs to stream keys.
+
+/// Parse `rtmp://host[:port]/app[/stream]`
+pub fn parse_rtmp_url(url: &str) -> Option<ParsedUrl> {
+    let url = url.strip_prefix("rtmp://")?;
+
+    let (host_port, path) = url.split_once('/')?;
+    let (host, port) = if let Some((h, p)) = host_port.split_once(':') {
+        (h.to_string(), p.parse().ok()?)
+    } else {
+        (host_port.to_string(), 1935)
+    };
+
+    // The query belongs to the stream name, and may itself contain slashes
+    let (base, query) = match path.split_once('?') {
+        Some((base, query)) => (base, Some(query)),
+        None => (path, None),
+    };
+    let (app, stream_key) = match base.rsplit_once('/') {
+        Some((a, s)) => {
+            let stream = match query {
+                Some(query) => format!("{}?{}", s, query),
+                None => s.to_string(),
+            };
+            (a.to_string(), Some(stream))
+        }
+        None => (path.to_string(), None),
+    };
+
+    Some(ParsedUrl {
+        host,
+        port,
+        app,
+        stream_key,
+    })
+}
+
+/// Parsed RTMP URL components
+///
+/// The last path segment is the stream and everything before it the app,
+/// so multi-segment apps such as `live/_definst_` stay whole. A URL with a
+/// single path segment (a tcUrl) has no stream.
+#[derive(Debug, Clone)]
+pub struct ParsedUrl {
+    pub host: String,
+    pub port: u16,
+    pub app: String,
+    pub stream_key: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_multi_segment_app() {
+        let parsed = parse_rtmp_url("rtmp://localhost/live/_definst_/test").unwrap();
+        assert_eq!(parsed.app, "live/_definst_");
+        assert_eq!(parsed.stream_key, Some("test".into()));
+
+        let parsed = parse_rtmp_url("rtmp://localhost/live/test?token=a/b").unwrap();
+        assert_eq!(parsed.app, "live");
+        assert_eq!(parsed.stream_key, Some("test?token=a/b".into()));
+
+        assert!(parse_rtmp_url("http://localhost/live/test").is_none());
+    }
+}
@@ -3,30 +3,130 @@
 //! Configuration for the stream registry including broadcast capacity,
 //! grace periods, and buffer sizes.
 
+use std::collections::HashMap;
 use std::time::Duration;
 
+/// What a subscriber joining a running stream receives before live frames
+///
+/// Metadata and sequence headers are always sent first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum CatchupStrategy {
+    /// The whole buffered GOP: smooth start, but playback begins up to a
+    /// GOP behind live
+    #[default]
+    FullGop,
+    /// Only the keyframe that starts the buffered GOP: the picture appears
+    /// at once and playback resumes at the next live frame
+    KeyframeOnly,
+    /// No frames: lowest latency, the picture appears at the next keyframe
+    HeadersOnly,
+    /// The keyframe that starts the buffered GOP, stamped with the newest
+    /// buffered timestamp: the player starts at live right away, at the
+    /// cost of a brief decode gap until the next keyframe
+    LiveEdge,
+}
+
 /// Configuration for the stream registry
+///
+/// With the `serde` feature this can be loaded from a config file; missing
+/// fields keep their defaults and durations are strings such as `"30s"`.
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
 pub struct RegistryConfig {
-    /// Capacity of the broadcast channel per stream
+    /// Capacity of the broadcast channel per stream, in frames
     ///
-    /// At 30fps, 128 frames is about 4 seconds of video buffer.
-    /// If subscribers fall behind more than this, they'll receive a Lagged error.
+    /// This is how far a subscriber may fall behind the publisher before
+    /// it receives a Lagged error and skips ahead. At 30fps, 128 frames is
+    /// about 4 seconds of video, less once audio frames share the channel.
+    ///
+    /// Raising it lets bursty or briefly stalled subscribers catch up
+    /// without dropping frames, at the cost of keeping that many frames
+    /// alive per stream while anyone lags. Lowering it bounds memory but
+    /// drops frames sooner. Must be at least 1.
     pub broadcast_capacity: usize,
 
     /// Grace period to keep stream alive after publisher disconnects
     ///
     /// If the publisher reconnects within this period, subscribers continue
     /// without interruption. This handles brief network hiccups.
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::serde_util::duration")
+    )]
     pub publisher_grace_period: Duration,
 
+    /// Shift a reclaiming publisher's timestamps onto the stream's timeline
+    ///
+    /// A publisher that reconnects during the grace period usually starts
+    /// its timestamps from zero again, and subscribers' players would jump
+    /// back. When enabled, its frames continue from the last timestamp
+    /// broadcast instead, and the stream's timestamps go through a
+    /// [`TimestampNormalizer`](crate::protocol::quirks::TimestampNormalizer),
+    /// which also smooths over backward jumps of more than a second.
+    pub continue_timestamps_on_reclaim: bool,
+
+    /// Let a new publisher take over a live stream
+    ///
+    /// By default a second publish of a live stream is rejected. When
+    /// enabled, the new publisher replaces the old one, whose session is
+    /// closed with [`DisconnectReason::PublisherTakeover`](crate::DisconnectReason::PublisherTakeover).
+    pub publisher_takeover: bool,
+
     /// Timeout for idle streams with no publisher and no subscribers
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::serde_util::duration")
+    )]
     pub idle_stream_timeout: Duration,
 
     /// Maximum GOP buffer size in bytes per stream
     pub max_gop_size: usize,
 
+    /// Maximum bytes held by the GOP buffers of all streams together
+    ///
+    /// When a frame does not fit, streams without a publisher (grace
+    /// period, idle or pending) give up their buffered GOP first. The
+    /// stream that ran out drops its own GOP and buffers again from its
+    /// next keyframe. None (the default) leaves only `max_gop_size`.
+    pub total_gop_budget_bytes: Option<usize>,
+
+    /// Buffer the latest GOP for late joiners
+    ///
+    /// When disabled, late joiners still receive metadata and sequence
+    /// headers but start at the next keyframe.
+    pub gop_buffer_enabled: bool,
+
+    /// Per-app overrides of `gop_buffer_enabled`, keyed by app name
+    pub gop_buffer_overrides: HashMap<String, bool>,
+
+    /// How much of the buffered GOP late joiners receive
+    pub catchup_strategy: CatchupStrategy,
+
+    /// Media time of audio without any video before a stream counts as
+    /// audio-only
+    ///
+    /// Audio-only streams are ready for late joiners once they have their
+    /// audio header, instead of waiting for a video keyframe.
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::serde_util::duration")
+    )]
+    pub audio_only_grace: Duration,
+
     /// Interval for running cleanup tasks
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::serde_util::duration")
+    )]
     pub cleanup_interval: Duration,
 
     /// Number of lag events before disconnecting a slow subscriber
@@ -34,6 +134,19 @@ pub struct RegistryConfig {
 
     /// Lag threshold (frames) below which we continue normally
     pub lag_threshold_low: u64,
+
+    /// Number of independently locked shards the stream map is split into
+    ///
+    /// Publishers registering or leaving only block streams that hash to
+    /// the same shard.
+    pub shard_count: usize,
+
+    /// Match stream keys case-insensitively
+    ///
+    /// When enabled, keys are lowercased and stripped of leading and
+    /// trailing slashes before lookup, so `live/Test` and `live/test/`
+    /// refer to the same stream.
+    pub case_insensitive_keys: bool,
 }
 
 impl Default for RegistryConfig {
@@ -41,11 +154,20 @@ impl Default for RegistryConfig {
         Self {
             broadcast_capacity: 128, // ~4 seconds @ 30fps
             publisher_grace_period: Duration::from_secs(10),
+            continue_timestamps_on_reclaim: false,
+            publisher_takeover: false,
             idle_stream_timeout: Duration::from_secs(30),
             max_gop_size: 4 * 1024 * 1024, // 4MB
+            total_gop_budget_bytes: None,
+            gop_buffer_enabled: true,
+            gop_buffer_overrides: HashMap::new(),
+            catchup_strategy: CatchupStrategy::default(),
+            audio_only_grace: Duration::from_secs(1),
             cleanup_interval: Duration::from_secs(5),
             max_consecutive_lag_events: 10,
             lag_threshold_low: 30, // ~1 second @ 30fps
+            shard_count: 16,
+            case_insensitive_keys: false,
         }
     }
 }
@@ -56,9 +178,9 @@ impl RegistryConfig {
         Self::default()
     }
 
-    /// Set the broadcast channel capacity
+    /// Set the broadcast channel capacity in frames (at least 1)
     pub fn broadcast_capacity(mut self, capacity: usize) -> Self {
-        self.broadcast_capacity = capacity;
+        self.broadcast_capacity = capacity.max(1);
         self
     }
 
@@ -68,6 +190,18 @@ impl RegistryConfig {
         self
     }
 
+    /// Continue a reclaiming publisher's timestamps from the last broadcast
+    pub fn continue_timestamps_on_reclaim(mut self, enabled: bool) -> Self {
+        self.continue_timestamps_on_reclaim = enabled;
+        self
+    }
+
+    /// Let a new publisher take over a live stream from the current one
+    pub fn publisher_takeover(mut self, enabled: bool) -> Self {
+        self.publisher_takeover = enabled;
+        self
+    }
+
     /// Set the idle stream timeout
     pub fn idle_stream_timeout(mut self, duration: Duration) -> Self {
         self.idle_stream_timeout = duration;
@@ -79,4 +213,81 @@ impl RegistryConfig {
         self.max_gop_size = size;
         self
     }
+
+    /// Set the GOP memory budget shared by all streams
+    pub fn total_gop_budget_bytes(mut self, bytes: usize) -> Self {
+        self.total_gop_budget_bytes = Some(bytes);
+        self
+    }
+
+    /// Enable or disable GOP buffering for all apps without an override
+    pub fn gop_buffer_enabled(mut self, enabled: bool) -> Self {
+        self.gop_buffer_enabled = enabled;
+        self
+    }
+
+    /// Enable or disable GOP buffering for a single app
+    pub fn gop_buffer_override(mut self, app: impl Into<String>, enabled: bool) -> Self {
+        self.gop_buffer_overrides.insert(app.into(), enabled);
+        self
+    }
+
+    /// Set how much of the buffered GOP late joiners receive
+    pub fn catchup_strategy(mut self, strategy: CatchupStrategy) -> Self {
+        self.catchup_strategy = strategy;
+        self
+    }
+
+    /// Set how long audio must arrive without video for a stream to count
+    /// as audio-only
+    pub fn audio_only_grace(mut self, grace: Duration) -> Self {
+        self.audio_only_grace = grace;
+        self
+    }
+
+    /// Check whether streams of `app` buffer their latest GOP
+    pub fn gop_buffer_enabled_for(&self, app: &str) -> bool {
+        self.gop_buffer_overrides
+            .get(app)
+            .copied()
+            .unwrap_or(self.gop_buffer_enabled)
+    }
+
+    /// Set the number of stream map shards (at least 1)
+    pub fn shard_count(mut self, count: usize) -> Self {
+        self.shard_count = count.max(1);
+        self
+    }
+
+    /// Enable or disable case-insensitive stream key matching
+    pub fn case_insensitive_keys(mut self, enabled: bool) -> Self {
+        self.case_insensitive_keys = enabled;
+        self
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_registry_config() {
+        let config: RegistryConfig = serde_json::from_str(
+            r#"{
+                "broadcast_capacity": 256,
+                "publisher_grace_period": "2s",
+                "cleanup_interval": 1,
+                "catchup_strategy": "keyframe_only",
+                "case_insensitive_keys": true
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.broadcast_capacity, 256);
+        assert_eq!(config.publisher_grace_period, Duration::from_secs(2));
+        assert_eq!(config.cleanup_interval, Duration::from_secs(1));
+        assert_eq!(config.catchup_strategy, CatchupStrategy::KeyframeOnly);
+        assert!(config.case_insensitive_keys);
+        assert_eq!(config.idle_stream_timeout, Duration::from_secs(30));
+    }
 }
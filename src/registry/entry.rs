@@ -2,16 +2,23 @@
 //!
 //! This module defines the per-stream state stored in the registry.
 
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Instant;
 
-use tokio::sync::broadcast;
+use bytes::Bytes;
+use tokio::sync::{broadcast, watch};
 
-use crate::media::flv::FlvTag;
-use crate::media::gop::GopBuffer;
+use crate::media::flv::{script_data_name, FlvTag};
+use crate::media::gop::{GopBuffer, StreamKind};
+use crate::protocol::constants::CMD_ON_CUE_POINT;
+use crate::protocol::quirks::TimestampNormalizer;
 
-use super::config::RegistryConfig;
-use super::frame::BroadcastFrame;
+use super::config::{CatchupStrategy, RegistryConfig};
+use super::frame::{BroadcastFrame, FrameType};
+use super::sink::{FrameSink, SinkId};
 
 /// State of a stream entry
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -22,12 +29,70 @@ pub enum StreamState {
     GracePeriod,
     /// No publisher, waiting for cleanup
     Idle,
+    /// Created ahead of its publisher; subscribers may join and wait
+    Pending,
+}
+
+/// GOP buffer memory shared by all streams of a registry
+#[derive(Debug, Default)]
+pub(super) struct GopBudget {
+    /// Bytes the GOP buffers may hold together (None = unlimited)
+    limit: Option<usize>,
+    /// Bytes the GOP buffers hold now
+    used: AtomicUsize,
+}
+
+impl GopBudget {
+    /// Create a budget of `limit` bytes
+    pub(super) fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get the bytes currently held
+    pub(super) fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Check whether a limit is set
+    fn is_limited(&self) -> bool {
+        self.limit.is_some()
+    }
+
+    /// Take `bytes` from the budget; false if that would exceed the limit
+    fn try_reserve(&self, bytes: usize) -> bool {
+        let Some(limit) = self.limit else {
+            self.used.fetch_add(bytes, Ordering::Relaxed);
+            return true;
+        };
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(bytes).filter(|&total| total <= limit)
+            })
+            .is_ok()
+    }
+
+    /// Give `bytes` back to the budget
+    fn release(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
 }
 
 /// Entry for a single stream in the registry
 pub struct StreamEntry {
     /// GOP buffer for late-joiner support
-    pub gop_buffer: GopBuffer,
+    ///
+    /// Guarded separately from the entry so media frames can be appended
+    /// while holding only a read lock on the entry.
+    pub gop_buffer: Mutex<GopBuffer>,
+
+    /// Whether media frames are appended to the GOP buffer
+    pub gop_buffer_enabled: bool,
+
+    /// How much of the GOP buffer late joiners receive
+    pub catchup_strategy: CatchupStrategy,
 
     /// Cached video sequence header for fast subscriber catchup
     pub video_header: Option<BroadcastFrame>,
@@ -38,15 +103,49 @@ pub struct StreamEntry {
     /// Cached metadata
     pub metadata: Option<BroadcastFrame>,
 
+    /// Most recent cue point, replayed to late joiners
+    pub cue_point: Option<BroadcastFrame>,
+
     /// Current publisher's session ID (None if no publisher)
     pub publisher_id: Option<u64>,
 
     /// Broadcast sender for fan-out to subscribers
     pub(super) tx: broadcast::Sender<BroadcastFrame>,
 
+    /// Set when the stream is evicted, closing its sessions
+    pub(super) evicted: watch::Sender<bool>,
+
+    /// Session ID of the last publisher replaced by a takeover
+    pub(super) replaced_publisher: watch::Sender<Option<u64>>,
+
+    /// Counts the publishers that stopped cleanly
+    pub(super) unpublished: watch::Sender<u64>,
+
+    /// Memory budget the GOP buffer draws from
+    pub(super) budget: Arc<GopBudget>,
+
     /// Number of active subscribers
     pub subscriber_count: AtomicU32,
 
+    /// Frames dropped across all subscribers, past and present
+    pub dropped_frames: AtomicU64,
+
+    /// Subscribers that joined with their session, by session ID
+    pub(super) subscribers: Mutex<HashMap<u64, SubscriberInfo>>,
+
+    /// In-process sinks called with every frame sent
+    pub(super) sinks: Mutex<Vec<(SinkId, Box<dyn FrameSink>)>>,
+
+    /// ID handed to the next sink
+    pub(super) next_sink_id: AtomicU64,
+
+    /// Latest timestamp broadcast
+    pub(super) last_timestamp: AtomicU32,
+
+    /// Keeps a reclaiming publisher's timestamps on the stream's timeline
+    /// (only with [`RegistryConfig::continue_timestamps_on_reclaim`])
+    timeline: Option<Mutex<TimestampNormalizer>>,
+
     /// When the publisher disconnected (for grace period tracking)
     pub publisher_disconnected_at: Option<Instant>,
 
@@ -58,18 +157,38 @@ pub struct StreamEntry {
 }
 
 impl StreamEntry {
-    /// Create a new stream entry
-    pub(super) fn new(config: &RegistryConfig) -> Self {
-        let (tx, _) = broadcast::channel(config.broadcast_capacity);
+    /// Create a new stream entry for a stream of `app`
+    pub(super) fn new(config: &RegistryConfig, app: &str, budget: Arc<GopBudget>) -> Self {
+        // A zero capacity would make the channel constructor panic
+        let (tx, _) = broadcast::channel(config.broadcast_capacity.max(1));
+
+        let mut gop = GopBuffer::with_max_size(config.max_gop_size);
+        let grace_ms = config.audio_only_grace.as_millis().min(u32::MAX as u128) as u32;
+        gop.set_audio_only_grace(grace_ms);
 
         Self {
-            gop_buffer: GopBuffer::with_max_size(config.max_gop_size),
+            gop_buffer: Mutex::new(gop),
+            gop_buffer_enabled: config.gop_buffer_enabled_for(app),
+            catchup_strategy: config.catchup_strategy,
             video_header: None,
             audio_header: None,
             metadata: None,
+            cue_point: None,
             publisher_id: None,
             tx,
+            evicted: watch::Sender::new(false),
+            replaced_publisher: watch::Sender::new(None),
+            unpublished: watch::Sender::new(0),
+            budget,
             subscriber_count: AtomicU32::new(0),
+            dropped_frames: AtomicU64::new(0),
+            subscribers: Mutex::new(HashMap::new()),
+            sinks: Mutex::new(Vec::new()),
+            next_sink_id: AtomicU64::new(0),
+            last_timestamp: AtomicU32::new(0),
+            timeline: config
+                .continue_timestamps_on_reclaim
+                .then(|| Mutex::new(TimestampNormalizer::new())),
             publisher_disconnected_at: None,
             created_at: Instant::now(),
             state: StreamState::Idle,
@@ -86,12 +205,80 @@ impl StreamEntry {
         self.publisher_id.is_some()
     }
 
+    /// Lock the GOP buffer
+    pub fn gop(&self) -> MutexGuard<'_, GopBuffer> {
+        // The buffer holds no invariants a panicking holder could break
+        self.gop_buffer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Lock the registered subscribers
+    pub(super) fn subscribers(&self) -> MutexGuard<'_, HashMap<u64, SubscriberInfo>> {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Snapshot the stream's statistics
+    pub(super) fn stats(&self) -> StreamStats {
+        let gop = self.gop();
+        StreamStats {
+            subscriber_count: self.subscriber_count(),
+            has_publisher: self.has_publisher(),
+            state: self.state,
+            gop_frame_count: gop.frame_count(),
+            gop_size_bytes: gop.size(),
+            kind: gop.stream_kind(),
+            ready: self.is_ready(&gop),
+            dropped_frames: self.dropped_frames.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Check whether late joiners can start cleanly
+    fn is_ready(&self, gop: &GopBuffer) -> bool {
+        gop.is_ready_with_headers(self.video_header.is_some(), self.audio_header.is_some())
+    }
+
+    /// Build the end-of-sequence frames for the stream's codecs
+    ///
+    /// Sent when the publisher stops cleanly, so decoders can flush; codecs
+    /// without an end of sequence get none.
+    pub(super) fn end_of_sequence(&self) -> Vec<BroadcastFrame> {
+        let timestamp = self.last_timestamp.load(Ordering::Relaxed);
+        let video = self
+            .video_header
+            .as_ref()
+            .and_then(|header| video_sequence_end(&header.data))
+            .map(|data| BroadcastFrame::video(timestamp, data, false, false));
+        let audio = self
+            .audio_header
+            .as_ref()
+            .and_then(|header| audio_sequence_end(&header.data))
+            .map(|data| BroadcastFrame::audio(timestamp, data, false));
+        video.into_iter().chain(audio).collect()
+    }
+
     /// Get catchup frames for a new subscriber
     ///
-    /// Returns sequence headers followed by GOP buffer contents.
+    /// Returns sequence headers followed by as much of the GOP buffer as
+    /// the [`CatchupStrategy`] allows. Streams not yet ready for late
+    /// joiners (see [`GopBuffer::is_ready`]) give the headers only.
     pub fn get_catchup_frames(&self) -> Vec<BroadcastFrame> {
+        self.catchup_frames(&self.gop(), self.catchup_strategy)
+    }
+
+    /// Build catchup frames from an already locked GOP buffer
+    fn catchup_frames(&self, gop: &GopBuffer, strategy: CatchupStrategy) -> Vec<BroadcastFrame> {
         let mut frames = Vec::new();
 
+        // Until the stream is ready, buffered frames could not be decoded
+        let strategy = if self.is_ready(gop) {
+            strategy
+        } else {
+            CatchupStrategy::HeadersOnly
+        };
+
         // Add metadata first
         if let Some(ref meta) = self.metadata {
             frames.push(meta.clone());
@@ -104,31 +291,138 @@ impl StreamEntry {
         if let Some(ref audio) = self.audio_header {
             frames.push(audio.clone());
         }
+        if let Some(ref cue_point) = self.cue_point {
+            frames.push(cue_point.clone());
+        }
 
         // Add GOP buffer contents
-        for tag in self.gop_buffer.get_catchup_data() {
-            frames.push(BroadcastFrame::from_flv_tag(&tag));
+        match strategy {
+            CatchupStrategy::FullGop => {
+                for tag in gop.get_catchup_data() {
+                    frames.push(BroadcastFrame::from_flv_tag(&tag));
+                }
+            }
+            CatchupStrategy::KeyframeOnly => {
+                if let Some(tag) = gop.keyframe() {
+                    frames.push(BroadcastFrame::from_flv_tag(tag));
+                }
+            }
+            CatchupStrategy::HeadersOnly => {}
+            CatchupStrategy::LiveEdge => {
+                if let (Some(tag), Some((_, newest))) = (gop.keyframe(), gop.timestamp_range()) {
+                    let mut frame = BroadcastFrame::from_flv_tag(tag);
+                    frame.timestamp = newest;
+                    frames.push(frame);
+                }
+            }
         }
 
         frames
     }
 
     /// Subscribe to this stream's broadcast channel
-    pub(super) fn subscribe(&self) -> broadcast::Receiver<BroadcastFrame> {
-        self.tx.subscribe()
+    ///
+    /// Returns the receiver together with the catchup frames. The GOP
+    /// buffer stays locked across both so a concurrent [`Self::publish`]
+    /// lands either in the catchup or on the receiver, never both.
+    /// `strategy` overrides the stream's configured catchup strategy.
+    pub(super) fn subscribe(
+        &self,
+        strategy: Option<CatchupStrategy>,
+    ) -> (broadcast::Receiver<BroadcastFrame>, Vec<BroadcastFrame>) {
+        let gop = self.gop();
+        let rx = self.tx.subscribe();
+        let catchup = self.catchup_frames(&gop, strategy.unwrap_or(self.catchup_strategy));
+        (rx, catchup)
+    }
+
+    /// Lock the attached sinks
+    fn sinks(&self) -> MutexGuard<'_, Vec<(SinkId, Box<dyn FrameSink>)>> {
+        self.sinks
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
     }
 
-    /// Send a frame to all subscribers
+    /// Attach a sink, feeding it the catchup frames first
+    ///
+    /// The GOP buffer stays locked like in [`Self::subscribe`], so the sink
+    /// sees each frame exactly once.
+    pub(super) fn add_sink(&self, mut sink: Box<dyn FrameSink>) -> SinkId {
+        let gop = self.gop();
+        for frame in self.catchup_frames(&gop, self.catchup_strategy) {
+            sink.on_frame(&frame);
+        }
+        let id = SinkId(self.next_sink_id.fetch_add(1, Ordering::Relaxed));
+        self.sinks().push((id, sink));
+        id
+    }
+
+    /// Detach a sink
+    pub(super) fn remove_sink(&self, id: SinkId) -> Option<Box<dyn FrameSink>> {
+        let mut sinks = self.sinks();
+        let index = sinks.iter().position(|(sink_id, _)| *sink_id == id)?;
+        Some(sinks.remove(index).1)
+    }
+
+    /// Send a frame to all sinks and subscribers
     ///
     /// Returns the number of receivers that received the message, or 0 if there are no receivers.
     pub(super) fn send(&self, frame: BroadcastFrame) -> usize {
+        for (_, sink) in self.sinks().iter_mut() {
+            sink.on_frame(&frame);
+        }
         self.tx.send(frame).unwrap_or(0)
     }
 
-    /// Update cached headers and GOP buffer based on frame type
-    pub(super) fn update_caches(&mut self, frame: &BroadcastFrame) {
-        use super::frame::FrameType;
+    /// Start a new publisher's timeline
+    ///
+    /// A publisher reclaiming the stream continues from the latest timestamp
+    /// broadcast if the timeline is kept; any other starts from scratch.
+    pub(super) fn start_timeline(&self, reclaimed: bool) {
+        let continues = match &self.timeline {
+            Some(timeline) => {
+                let mut timeline = timeline
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                if reclaimed {
+                    timeline.rebase();
+                } else {
+                    timeline.reset();
+                }
+                reclaimed
+            }
+            None => false,
+        };
+        if !continues {
+            self.last_timestamp.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Move a frame onto the stream's timeline
+    ///
+    /// After a rebase the frame continues from the latest timestamp
+    /// broadcast, and later frames keep their spacing.
+    pub(super) fn continue_timestamp(&self, frame: &mut BroadcastFrame) {
+        if let Some(timeline) = &self.timeline {
+            let mut timeline = timeline
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            frame.timestamp = timeline.normalize(frame.timestamp);
+        }
+        self.last_timestamp
+            .fetch_max(frame.timestamp, Ordering::Relaxed);
+    }
 
+    /// Check whether a frame replaces one of the cached headers
+    ///
+    /// Only these frames need exclusive access to the entry; everything
+    /// else goes through [`Self::publish`] under a shared lock.
+    pub(super) fn updates_headers(frame: &BroadcastFrame) -> bool {
+        frame.is_header || frame.frame_type == FrameType::Metadata || is_cue_point(frame)
+    }
+
+    /// Update the cached sequence headers and metadata
+    pub(super) fn update_headers(&mut self, frame: &BroadcastFrame) {
         match frame.frame_type {
             FrameType::Video if frame.is_header => {
                 self.video_header = Some(frame.clone());
@@ -139,15 +433,167 @@ impl StreamEntry {
             FrameType::Metadata => {
                 self.metadata = Some(frame.clone());
             }
+            FrameType::Data if is_cue_point(frame) => {
+                self.cue_point = Some(frame.clone());
+            }
+            _ => {}
+        }
+    }
+
+    /// Append a frame to the GOP buffer and send it to subscribers
+    ///
+    /// Returns false if the GOP budget had no room for the frame.
+    pub(super) fn publish(&self, frame: BroadcastFrame) -> bool {
+        self.publish_locked(&mut self.gop(), frame)
+    }
+
+    /// Continue a frame's timestamp and publish it as one step
+    ///
+    /// Media frames are broadcast under a shared lock on the entry, so the
+    /// GOP lock is what orders them: holding it from the timestamp through
+    /// the send keeps overlapping broadcasts, e.g. from an old and a new
+    /// publisher during a takeover, from being timed in one order and
+    /// delivered in another.
+    pub(super) fn continue_and_publish(&self, mut frame: BroadcastFrame) -> bool {
+        let mut gop = self.gop();
+        self.continue_timestamp(&mut frame);
+        self.publish_locked(&mut gop, frame)
+    }
+
+    /// Publish a frame with the GOP buffer already locked
+    fn publish_locked(&self, gop: &mut GopBuffer, frame: BroadcastFrame) -> bool {
+        let mut fits = true;
+
+        // Update GOP buffer for video frames (non-headers); audio only
+        // tells audio-only streams apart
+        match frame.frame_type {
+            FrameType::Video if !frame.is_header => {
+                gop.note_video();
+                if self.gop_buffer_enabled {
+                    fits = self.buffer_tag(gop, FlvTag::video(frame.timestamp, frame.data.clone()));
+                }
+            }
+            FrameType::Audio if !frame.is_header => gop.note_audio(frame.timestamp),
             _ => {}
         }
 
-        // Update GOP buffer for video frames (non-headers)
-        if frame.frame_type == FrameType::Video && !frame.is_header {
-            let tag = FlvTag::video(frame.timestamp, frame.data.clone());
-            self.gop_buffer.push(tag);
+        self.send(frame);
+        fits
+    }
+
+    /// Append a tag to the GOP buffer, drawing on the budget
+    fn buffer_tag(&self, gop: &mut GopBuffer, tag: FlvTag) -> bool {
+        if tag.is_keyframe() {
+            // The old GOP is replaced, so its memory is free again
+            self.budget.release(gop.size());
+            gop.clear_frames();
+        } else if self.budget.is_limited() && gop.frame_count() == 0 {
+            // Frames without their keyframe are no use to late joiners
+            return true;
         }
+
+        let before = gop.size();
+        let size = tag.size();
+        if !self.budget.try_reserve(size) {
+            // A GOP missing frames is no use either
+            self.release_locked_gop(gop);
+            return false;
+        }
+
+        // The buffer may drop old frames to stay within max_gop_size
+        gop.push(tag);
+        self.budget.release(before + size - gop.size());
+        true
     }
+
+    /// Drop the buffered GOP, giving its memory back to the budget
+    ///
+    /// Returns the number of bytes released.
+    pub(super) fn release_gop(&self) -> usize {
+        self.release_locked_gop(&mut self.gop())
+    }
+
+    /// [`Self::release_gop`] with the buffer already locked
+    fn release_locked_gop(&self, gop: &mut GopBuffer) -> usize {
+        let size = gop.size();
+        gop.clear_frames();
+        self.budget.release(size);
+        size
+    }
+}
+
+impl Drop for StreamEntry {
+    fn drop(&mut self) {
+        let gop = self
+            .gop_buffer
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.budget.release(gop.size());
+    }
+}
+
+/// Video end-of-sequence tag body matching a sequence header
+///
+/// Legacy AVC and HEVC end with an AVC packet type of 2, enhanced RTMP
+/// codecs with a SequenceEnd packet. None for codecs without one.
+fn video_sequence_end(header: &[u8]) -> Option<Bytes> {
+    match header {
+        // Enhanced SequenceStart: keep the FourCC, switch to SequenceEnd
+        [first, fourcc @ ..] if first & 0x80 != 0 && first & 0x0F == 0 && fourcc.len() >= 4 => {
+            let mut end = vec![0x80 | 0x10 | 0x02];
+            end.extend_from_slice(&fourcc[..4]);
+            Some(Bytes::from(end))
+        }
+        [first, ..] if first & 0x80 == 0 && matches!(first & 0x0F, 7 | 12) => {
+            Some(Bytes::from(vec![0x10 | (first & 0x0F), 0x02, 0, 0, 0]))
+        }
+        _ => None,
+    }
+}
+
+/// Audio end-of-sequence tag body matching a sequence header
+///
+/// Legacy FLV audio has no end of sequence, so AAC ends with the enhanced
+/// RTMP SequenceEnd packet for `mp4a`, as do enhanced codecs with their
+/// own FourCC. None for codecs without a sequence header.
+fn audio_sequence_end(header: &[u8]) -> Option<Bytes> {
+    let fourcc = match header {
+        // Enhanced SequenceStart
+        [first, fourcc @ ..] if first >> 4 == 9 && first & 0x0F == 0 && fourcc.len() >= 4 => {
+            &fourcc[..4]
+        }
+        // Legacy AAC
+        [first, 0, ..] if first >> 4 == 10 => b"mp4a",
+        _ => return None,
+    };
+    let mut end = vec![0x90 | 0x02];
+    end.extend_from_slice(fourcc);
+    Some(Bytes::from(end))
+}
+
+/// A subscriber of a stream, as listed by
+/// [`StreamRegistry::list_subscribers`](super::StreamRegistry::list_subscribers)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriberInfo {
+    /// Session ID of the subscriber
+    pub session_id: u64,
+    /// Remote address of the subscriber
+    pub peer_addr: SocketAddr,
+    /// When the subscriber joined
+    pub joined_at: Instant,
+    /// Frames this subscriber dropped
+    pub dropped_frames: u64,
+    /// Buffer length the player asked for with Set Buffer Length, in
+    /// milliseconds
+    pub buffer_ms: Option<u32>,
+}
+
+/// Check for an `onCuePoint` data frame
+///
+/// Cue points mark positions (chapters, ad breaks) that stay relevant, so
+/// the latest one is kept for late joiners; other timed data is not.
+fn is_cue_point(frame: &BroadcastFrame) -> bool {
+    frame.frame_type == FrameType::Data && script_data_name(&frame.data) == Some(CMD_ON_CUE_POINT)
 }
 
 /// Statistics for a stream
@@ -163,4 +609,13 @@ pub struct StreamStats {
     pub gop_frame_count: usize,
     /// Size of GOP buffer in bytes
     pub gop_size_bytes: usize,
+    /// Which media the stream carries
+    pub kind: StreamKind,
+    /// Whether late joiners can start cleanly: a video stream has its
+    /// header and a buffered GOP, an audio-only stream its audio header
+    pub ready: bool,
+    /// Frames dropped across all subscribers (see
+    /// [`SessionStats::dropped_frames`](crate::stats::SessionStats::dropped_frames)
+    /// for a single subscriber)
+    pub dropped_frames: u64,
 }
@@ -15,6 +15,8 @@ pub enum RegistryError {
     PublisherMismatch,
     /// Stream is not active (e.g., in grace period without publisher)
     StreamNotActive(StreamKey),
+    /// The registry already holds its maximum number of live streams
+    StreamLimitReached(StreamKey),
 }
 
 impl std::fmt::Display for RegistryError {
@@ -26,6 +28,9 @@ impl std::fmt::Display for RegistryError {
             }
             RegistryError::PublisherMismatch => write!(f, "Publisher ID mismatch"),
             RegistryError::StreamNotActive(key) => write!(f, "Stream not active: {}", key),
+            RegistryError::StreamLimitReached(key) => {
+                write!(f, "Stream limit reached, cannot publish: {}", key)
+            }
         }
     }
 }
@@ -5,7 +5,12 @@
 
 use bytes::Bytes;
 
-use crate::media::flv::{FlvTag, FlvTagType};
+use crate::media::enhanced_audio::EnhancedAudioData;
+use crate::media::enhanced_video::EnhancedVideoData;
+use crate::media::flv::{FlvTag, FlvTagType, VideoCodec};
+use crate::media::modex;
+use crate::protocol::constants::CMD_ON_METADATA;
+use crate::protocol::url::parse_rtmp_url;
 
 /// Unique identifier for a stream (app + stream name)
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -24,6 +29,32 @@ impl StreamKey {
             name: name.into(),
         }
     }
+
+    /// Create a key from an RTMP URL such as `rtmp://host/live/test`
+    ///
+    /// The path is split the way the client connects: the last segment is
+    /// the stream name and everything before it the app, so multi-segment
+    /// apps such as `live/_definst_` stay whole. A query string stays on the
+    /// name. Returns None unless the URL has both an app and a name, e.g.
+    /// for a bare tcUrl.
+    pub fn from_rtmp_url(url: &str) -> Option<Self> {
+        let parsed = parse_rtmp_url(url)?;
+        let name = parsed.stream_key.filter(|name| !name.is_empty())?;
+        if parsed.app.is_empty() {
+            return None;
+        }
+        Some(Self::new(parsed.app, name))
+    }
+
+    /// Get the normalized form of this key
+    ///
+    /// Lowercases both parts and trims leading and trailing slashes.
+    pub fn normalized(&self) -> Self {
+        Self {
+            app: self.app.trim_matches('/').to_lowercase(),
+            name: self.name.trim_matches('/').to_lowercase(),
+        }
+    }
 }
 
 impl std::fmt::Display for StreamKey {
@@ -41,6 +72,10 @@ pub enum FrameType {
     Audio,
     /// Metadata (onMetaData)
     Metadata,
+    /// Timed script data such as `onCuePoint` or `onTextData`
+    ///
+    /// Delivered to players at its timestamp, interleaved with media.
+    Data,
 }
 
 /// A frame to be broadcast to subscribers
@@ -58,6 +93,11 @@ pub struct BroadcastFrame {
     pub is_keyframe: bool,
     /// Whether this is a sequence header
     pub is_header: bool,
+    /// Sub-millisecond part of the timestamp in nanoseconds (E-RTMP only)
+    ///
+    /// Set from a ModEx TimestampOffsetNano signal when the publisher
+    /// negotiated the capability; always below one millisecond.
+    pub timestamp_nano_offset: u32,
 }
 
 impl BroadcastFrame {
@@ -69,6 +109,7 @@ impl BroadcastFrame {
             data,
             is_keyframe,
             is_header,
+            timestamp_nano_offset: 0,
         }
     }
 
@@ -80,6 +121,7 @@ impl BroadcastFrame {
             data,
             is_keyframe: false,
             is_header,
+            timestamp_nano_offset: 0,
         }
     }
 
@@ -91,22 +133,364 @@ impl BroadcastFrame {
             data,
             is_keyframe: false,
             is_header: false,
+            timestamp_nano_offset: 0,
+        }
+    }
+
+    /// Create a timed script data frame (AMF0 name and values)
+    pub fn data(timestamp: u32, data: Bytes) -> Self {
+        Self {
+            frame_type: FrameType::Data,
+            timestamp,
+            data,
+            is_keyframe: false,
+            is_header: false,
+            timestamp_nano_offset: 0,
+        }
+    }
+
+    /// Set the nanosecond timestamp offset
+    pub fn with_timestamp_nano_offset(mut self, nanos: u32) -> Self {
+        self.timestamp_nano_offset = nanos;
+        self
+    }
+
+    /// Get the timestamp in nanoseconds
+    ///
+    /// Combines the 32-bit millisecond timestamp with the nanosecond offset.
+    pub fn timestamp_nanos(&self) -> u64 {
+        self.timestamp as u64 * 1_000_000 + self.timestamp_nano_offset as u64
+    }
+
+    /// Set `is_header` and `is_keyframe` from the payload
+    ///
+    /// Recognizes legacy AVC/HEVC and AAC sequence headers as well as
+    /// enhanced RTMP SequenceStart packets. Frames other than audio and
+    /// video are returned unchanged.
+    pub fn classify(mut self) -> Self {
+        match self.frame_type {
+            FrameType::Video => {
+                (self.is_keyframe, self.is_header) = video_flags(&self.data);
+            }
+            FrameType::Audio => {
+                self.is_keyframe = false;
+                self.is_header = is_audio_header(&self.data);
+            }
+            _ => {}
         }
+        self
     }
 
     /// Convert from FLV tag
+    ///
+    /// Header and keyframe flags come from [`Self::classify`]; the
+    /// nanosecond offset is recovered from the ModEx signals of enhanced
+    /// payloads.
     pub fn from_flv_tag(tag: &FlvTag) -> Self {
+        let first = tag.data.first().copied().unwrap_or(0);
         match tag.tag_type {
             FlvTagType::Video => {
-                let is_keyframe = tag.is_keyframe();
-                let is_header = tag.is_avc_sequence_header();
-                Self::video(tag.timestamp, tag.data.clone(), is_keyframe, is_header)
+                let nanos = if EnhancedVideoData::is_enhanced(first) {
+                    modex::timestamp_offset_nano(&tag.data).unwrap_or(0)
+                } else {
+                    0
+                };
+                Self::video(tag.timestamp, tag.data.clone(), false, false)
+                    .classify()
+                    .with_timestamp_nano_offset(nanos)
             }
             FlvTagType::Audio => {
-                let is_header = tag.is_aac_sequence_header();
-                Self::audio(tag.timestamp, tag.data.clone(), is_header)
+                let nanos = if EnhancedAudioData::is_enhanced(first) {
+                    modex::timestamp_offset_nano(&tag.data).unwrap_or(0)
+                } else {
+                    0
+                };
+                Self::audio(tag.timestamp, tag.data.clone(), false)
+                    .classify()
+                    .with_timestamp_nano_offset(nanos)
             }
-            FlvTagType::Script => Self::metadata(tag.data.clone()),
+            // Only onMetaData is the stream's metadata; cue points and
+            // other script data stay timed data
+            FlvTagType::Script if tag.script_name() == Some(CMD_ON_METADATA) => Self {
+                timestamp: tag.timestamp,
+                ..Self::metadata(tag.data.clone())
+            },
+            FlvTagType::Script => Self::data(tag.timestamp, tag.data.clone()),
         }
     }
 }
+
+/// Read the keyframe and sequence header flags of a video payload
+pub(crate) fn video_flags(data: &[u8]) -> (bool, bool) {
+    let Some(&first) = data.first() else {
+        return (false, false);
+    };
+    if EnhancedVideoData::is_enhanced(first) {
+        // ExVideoTagHeader: bits 4-6 are the frame type, bits 0-3 the
+        // packet type (0 = SequenceStart)
+        let frame_type = (first >> 4) & 0x07;
+        let is_keyframe = frame_type == 1 || frame_type == 4;
+        (is_keyframe, first & 0x0F == 0)
+    } else {
+        // Legacy: bits 4-7 are the frame type, bits 0-3 the codec ID,
+        // followed by the AVC packet type (0 = sequence header)
+        let is_keyframe = first >> 4 == 1 || first >> 4 == 4;
+        let is_header = matches!(
+            VideoCodec::from_byte(first),
+            Some(VideoCodec::Avc | VideoCodec::Hevc)
+        ) && data.get(1) == Some(&0);
+        (is_keyframe, is_header)
+    }
+}
+
+/// Check whether an audio payload is a sequence header
+pub(crate) fn is_audio_header(data: &[u8]) -> bool {
+    match data {
+        // Enhanced: packet type 0 = SequenceStart
+        [first, ..] if EnhancedAudioData::is_enhanced(*first) => first & 0x0F == 0,
+        // Legacy AAC: SoundFormat 10 and AACPacketType 0
+        [first, 0, ..] => first >> 4 == 10,
+        _ => false,
+    }
+}
+
+impl From<&FlvTag> for BroadcastFrame {
+    fn from(tag: &FlvTag) -> Self {
+        Self::from_flv_tag(tag)
+    }
+}
+
+impl From<&BroadcastFrame> for FlvTag {
+    fn from(frame: &BroadcastFrame) -> Self {
+        match frame.frame_type {
+            FrameType::Video => FlvTag::video(frame.timestamp, frame.data.clone()),
+            FrameType::Audio => FlvTag::audio(frame.timestamp, frame.data.clone()),
+            FrameType::Metadata | FrameType::Data => {
+                FlvTag::script(frame.timestamp, frame.data.clone())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_frames_eq(a: &BroadcastFrame, b: &BroadcastFrame) {
+        assert_eq!(a.frame_type, b.frame_type);
+        assert_eq!(a.timestamp, b.timestamp);
+        assert_eq!(a.data, b.data);
+        assert_eq!(a.is_keyframe, b.is_keyframe);
+        assert_eq!(a.is_header, b.is_header);
+        assert_eq!(a.timestamp_nano_offset, b.timestamp_nano_offset);
+    }
+
+    #[test]
+    fn test_stream_key_from_rtmp_url() {
+        let cases = [
+            ("rtmp://h/live/test", Some(("live", "test"))),
+            ("rtmp://h:1936/live/test", Some(("live", "test"))),
+            (
+                "rtmp://h/live/_definst_/test",
+                Some(("live/_definst_", "test")),
+            ),
+            (
+                "rtmp://h/live/test?token=abc",
+                Some(("live", "test?token=abc")),
+            ),
+            ("rtmp://h/live", None),
+            ("rtmp://h/live/", None),
+            ("rtmp://h//test", None),
+            ("http://h/live/test", None),
+        ];
+        for (url, expected) in cases {
+            let expected = expected.map(|(app, name)| StreamKey::new(app, name));
+            assert_eq!(StreamKey::from_rtmp_url(url), expected, "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_stream_key_display_round_trips() {
+        for key in [
+            StreamKey::new("live", "test"),
+            StreamKey::new("live/_definst_", "test"),
+        ] {
+            let url = format!("rtmp://localhost/{}", key);
+            assert_eq!(StreamKey::from_rtmp_url(&url), Some(key));
+        }
+    }
+
+    #[test]
+    fn test_video_roundtrip() {
+        let frames = [
+            // AVC sequence header
+            BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x00, 0x00]), true, true),
+            // AVC keyframe
+            BroadcastFrame::video(40, Bytes::from_static(&[0x17, 0x01, 0x00]), true, false),
+            // AVC inter frame
+            BroadcastFrame::video(80, Bytes::from_static(&[0x27, 0x01, 0x00]), false, false),
+        ];
+
+        for frame in &frames {
+            let tag = FlvTag::from(frame);
+            assert_eq!(tag.tag_type, FlvTagType::Video);
+            assert_eq!(tag.timestamp, frame.timestamp);
+            assert_eq!(tag.data, frame.data);
+
+            assert_frames_eq(&BroadcastFrame::from_flv_tag(&tag), frame);
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        let cases: [(FrameType, &[u8], bool, bool); 9] = [
+            // AVC sequence header, keyframe, inter frame
+            (FrameType::Video, &[0x17, 0x00, 0, 0, 0], true, true),
+            (FrameType::Video, &[0x17, 0x01, 0, 0, 0], true, false),
+            (FrameType::Video, &[0x27, 0x01, 0, 0, 0], false, false),
+            // Enhanced HEVC SequenceStart and CodedFrames keyframe
+            (
+                FrameType::Video,
+                &[0x90, b'h', b'v', b'c', b'1'],
+                true,
+                true,
+            ),
+            (
+                FrameType::Video,
+                &[0x91, b'h', b'v', b'c', b'1'],
+                true,
+                false,
+            ),
+            // AAC sequence header and raw frame
+            (FrameType::Audio, &[0xAF, 0x00, 0x12, 0x10], false, true),
+            (FrameType::Audio, &[0xAF, 0x01, 0x21], false, false),
+            // Enhanced Opus SequenceStart
+            (
+                FrameType::Audio,
+                &[0x90, b'O', b'p', b'u', b's'],
+                false,
+                true,
+            ),
+            // MP3 has no sequence header
+            (FrameType::Audio, &[0x2F, 0x00], false, false),
+        ];
+        for (frame_type, data, is_keyframe, is_header) in cases {
+            // Deliberately mislabeled
+            let frame = BroadcastFrame {
+                frame_type,
+                timestamp: 0,
+                data: Bytes::from_static(data),
+                is_keyframe: !is_keyframe,
+                is_header: !is_header,
+                timestamp_nano_offset: 0,
+            }
+            .classify();
+            assert_eq!(frame.is_keyframe, is_keyframe, "{:02x?}", data);
+            assert_eq!(frame.is_header, is_header, "{:02x?}", data);
+        }
+
+        let metadata = BroadcastFrame::metadata(Bytes::from_static(&[0x17, 0x00])).classify();
+        assert!(!metadata.is_header);
+    }
+
+    #[test]
+    fn test_audio_roundtrip() {
+        let frames = [
+            // AAC sequence header
+            BroadcastFrame::audio(0, Bytes::from_static(&[0xAF, 0x00, 0x12]), true),
+            // AAC raw
+            BroadcastFrame::audio(23, Bytes::from_static(&[0xAF, 0x01, 0x21]), false),
+        ];
+
+        for frame in &frames {
+            let tag = FlvTag::from(frame);
+            assert_eq!(tag.tag_type, FlvTagType::Audio);
+            assert_frames_eq(&BroadcastFrame::from(&tag), frame);
+        }
+    }
+
+    #[test]
+    fn test_metadata_roundtrip() {
+        let frame = BroadcastFrame {
+            timestamp: 1000,
+            ..BroadcastFrame::metadata(Bytes::from_static(b"\x02\x00\x0aonMetaData"))
+        };
+
+        let tag = FlvTag::from(&frame);
+        assert_eq!(tag.tag_type, FlvTagType::Script);
+        assert_eq!(tag.timestamp, 1000);
+
+        assert_frames_eq(&BroadcastFrame::from_flv_tag(&tag), &frame);
+
+        // Cue points must not come back as the stream's metadata
+        let cue_point = BroadcastFrame::data(2000, Bytes::from_static(b"\x02\x00\x0aonCuePoint"));
+        let tag = FlvTag::from(&cue_point);
+        assert_eq!(tag.tag_type, FlvTagType::Script);
+        assert_frames_eq(&BroadcastFrame::from_flv_tag(&tag), &cue_point);
+    }
+
+    #[test]
+    fn test_flv_tag_roundtrip() {
+        let tags = [
+            FlvTag::video(33, Bytes::from_static(&[0x17, 0x01, 0x00])),
+            FlvTag::audio(46, Bytes::from_static(&[0xAF, 0x01, 0x21])),
+            FlvTag::script(0, Bytes::from_static(b"\x02\x00\x0aonMetaData")),
+            FlvTag::script(66, Bytes::from_static(b"\x02\x00\x0aonTextData")),
+        ];
+
+        for tag in &tags {
+            let back = FlvTag::from(&BroadcastFrame::from(tag));
+            assert_eq!(back.tag_type, tag.tag_type);
+            assert_eq!(back.timestamp, tag.timestamp);
+            assert_eq!(back.data, tag.data);
+        }
+
+        assert_eq!(
+            BroadcastFrame::from(&tags[2]).frame_type,
+            FrameType::Metadata
+        );
+        assert_eq!(BroadcastFrame::from(&tags[3]).frame_type, FrameType::Data);
+    }
+
+    #[test]
+    fn test_timestamp_nanos() {
+        let frame = BroadcastFrame::video(1500, Bytes::new(), false, false);
+        assert_eq!(frame.timestamp_nanos(), 1_500_000_000);
+
+        let frame = frame.with_timestamp_nano_offset(250_000);
+        assert_eq!(frame.timestamp, 1500);
+        assert_eq!(frame.timestamp_nanos(), 1_500_250_000);
+
+        // Past the 32-bit millisecond range the nanosecond value keeps growing
+        let frame =
+            BroadcastFrame::audio(u32::MAX, Bytes::new(), false).with_timestamp_nano_offset(1);
+        assert_eq!(frame.timestamp_nanos(), u32::MAX as u64 * 1_000_000 + 1);
+    }
+
+    #[test]
+    fn test_enhanced_roundtrip_keeps_nano_offset() {
+        // Enhanced HEVC keyframe: ModEx(TimestampOffsetNano = 500000) then CodedFramesX
+        let data = Bytes::from_static(&[
+            0x97, 0x02, 0x07, 0xA1, 0x20, 0x03, b'h', b'v', b'c', b'1', 0x26, 0x01,
+        ]);
+        let frame =
+            BroadcastFrame::video(40, data, true, false).with_timestamp_nano_offset(500_000);
+
+        let tag = FlvTag::from(&frame);
+        let back = BroadcastFrame::from_flv_tag(&tag);
+        assert_eq!(back.timestamp_nano_offset, 500_000);
+        assert_eq!(back.timestamp_nanos(), 40_500_000);
+    }
+
+    #[test]
+    fn test_stream_key_normalized() {
+        let key = StreamKey::new("/Live/", "My_Stream/");
+        let normalized = key.normalized();
+
+        assert_eq!(normalized, StreamKey::new("live", "my_stream"));
+        assert_eq!(normalized, StreamKey::new("live", "MY_STREAM").normalized());
+
+        // The original key keeps its display form
+        assert_eq!(key.to_string(), "/Live//My_Stream/");
+    }
+}
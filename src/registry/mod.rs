@@ -8,12 +8,12 @@
 //! ```text
 //!                          Arc<StreamRegistry>
 //!                     ┌─────────────────────────┐
-//!                     │ streams: HashMap<Key,   │
+//!                     │ shards: [HashMap<Key,   │
 //!                     │   StreamEntry {         │
 //!                     │     gop_buffer,         │
 //!                     │     tx: broadcast::Tx,  │
 //!                     │   }                     │
-//!                     │ >                       │
+//!                     │ >]                      │
 //!                     └───────────┬─────────────┘
 //!                                 │
 //!         ┌───────────────────────┼───────────────────────┐
@@ -35,10 +35,12 @@ pub mod config;
 pub mod entry;
 pub mod error;
 pub mod frame;
+pub mod sink;
 pub mod store;
 
-pub use config::RegistryConfig;
-pub use entry::{StreamEntry, StreamState, StreamStats};
+pub use config::{CatchupStrategy, RegistryConfig};
+pub use entry::{StreamEntry, StreamState, StreamStats, SubscriberInfo};
 pub use error::RegistryError;
 pub use frame::{BroadcastFrame, FrameType, StreamKey};
+pub use sink::{FrameSink, SinkId};
 pub use store::StreamRegistry;
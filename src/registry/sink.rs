@@ -0,0 +1,31 @@
+//! In-process frame sinks
+//!
+//! A [`FrameSink`] attached with
+//! [`StreamRegistry::add_sink`](super::StreamRegistry::add_sink) is called
+//! for every frame broadcast on a stream, next to the channel subscribers.
+//! Unlike a subscriber it cannot lag: it runs on the publisher's path, so
+//! one ingest can feed recorders, HTTP-FLV muxers and the like without
+//! subscribing once per output.
+
+use super::frame::BroadcastFrame;
+
+/// Receiver of every frame broadcast on a stream
+///
+/// `on_frame` is called synchronously while the stream's GOP buffer is
+/// locked, so it must not block; hand work that may wait (disk or network
+/// writes) to a task through a channel.
+pub trait FrameSink: Send {
+    /// Called with each frame, starting with the stream's catchup frames
+    fn on_frame(&mut self, frame: &BroadcastFrame);
+}
+
+impl<F: FnMut(&BroadcastFrame) + Send> FrameSink for F {
+    fn on_frame(&mut self, frame: &BroadcastFrame) {
+        self(frame)
+    }
+}
+
+/// Handle to a sink attached to a stream, for
+/// [`StreamRegistry::remove_sink`](super::StreamRegistry::remove_sink)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SinkId(pub(super) u64);
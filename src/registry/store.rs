@@ -3,25 +3,55 @@
 //! The central registry that manages all active streams and routes media
 //! from publishers to subscribers.
 
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::net::SocketAddr;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use tokio::sync::{broadcast, RwLock};
+use bytes::Bytes;
+use tokio::sync::{broadcast, watch, Mutex, RwLock};
 
-use super::config::RegistryConfig;
-use super::entry::{StreamEntry, StreamState, StreamStats};
+use crate::amf::AmfObject;
+use crate::media::flv::{FlvTag, FlvTagType};
+use crate::media::metadata::encode_on_metadata;
+
+use super::config::{CatchupStrategy, RegistryConfig};
+use super::entry::{GopBudget, StreamEntry, StreamState, StreamStats, SubscriberInfo};
 use super::error::RegistryError;
 use super::frame::{BroadcastFrame, StreamKey};
+use super::sink::{FrameSink, SinkId};
+
+/// Map of stream key to stream entry, one per shard
+type Shard = RwLock<HashMap<StreamKey, Arc<RwLock<StreamEntry>>>>;
 
 /// Central registry for all active streams
 ///
 /// Thread-safe via `RwLock`. Read-heavy workloads (subscriber count checks,
-/// broadcasting) benefit from the concurrent read access.
+/// broadcasting) benefit from the concurrent read access. The stream map is
+/// split into shards by key hash so publisher churn and cleanup only take
+/// the write lock of one shard at a time.
 pub struct StreamRegistry {
-    /// Map of stream key to stream entry
-    streams: RwLock<HashMap<StreamKey, Arc<RwLock<StreamEntry>>>>,
+    /// Stream map shards, selected by hash of the stream key
+    shards: Box<[Shard]>,
+
+    /// Hasher used to pick a key's shard
+    hasher: RandomState,
+
+    /// GOP memory shared by all streams
+    gop_budget: Arc<GopBudget>,
+
+    /// Maximum number of live streams (0 = unlimited)
+    max_streams: usize,
+
+    /// Serializes publisher registration while `max_streams` is enforced
+    publish_gate: Mutex<()>,
+
+    /// Current cleanup interval, watched by the cleanup task
+    cleanup_interval: watch::Sender<Duration>,
 
     /// Configuration
     config: RegistryConfig,
@@ -35,17 +65,51 @@ impl StreamRegistry {
 
     /// Create a new stream registry with custom configuration
     pub fn with_config(config: RegistryConfig) -> Self {
+        let shards = (0..config.shard_count.max(1))
+            .map(|_| RwLock::new(HashMap::new()))
+            .collect();
+
         Self {
-            streams: RwLock::new(HashMap::new()),
+            shards,
+            hasher: RandomState::new(),
+            gop_budget: Arc::new(GopBudget::new(config.total_gop_budget_bytes)),
+            max_streams: 0,
+            publish_gate: Mutex::new(()),
+            cleanup_interval: watch::Sender::new(config.cleanup_interval),
             config,
         }
     }
 
+    /// Get the form `key` is stored under
+    ///
+    /// With [`RegistryConfig::case_insensitive_keys`] enabled keys are
+    /// normalized; callers keep the original key for logging.
+    fn storage_key<'a>(&self, key: &'a StreamKey) -> Cow<'a, StreamKey> {
+        if self.config.case_insensitive_keys {
+            Cow::Owned(key.normalized())
+        } else {
+            Cow::Borrowed(key)
+        }
+    }
+
+    /// Get the shard holding `key`
+    fn shard(&self, key: &StreamKey) -> &Shard {
+        let index = self.hasher.hash_one(key) as usize % self.shards.len();
+        &self.shards[index]
+    }
+
     /// Get the registry configuration
     pub fn config(&self) -> &RegistryConfig {
         &self.config
     }
 
+    /// Cap the number of live streams (0 = unlimited)
+    ///
+    /// Set from [`ServerConfig::max_streams`](crate::ServerConfig::max_streams).
+    pub(crate) fn set_max_streams(&mut self, max: usize) {
+        self.max_streams = max;
+    }
+
     /// Register a publisher for a stream
     ///
     /// If the stream doesn't exist, it will be created.
@@ -56,22 +120,57 @@ impl StreamRegistry {
         key: &StreamKey,
         session_id: u64,
     ) -> Result<(), RegistryError> {
-        let mut streams = self.streams.write().await;
+        let stored = self.storage_key(key);
+
+        // Counting and registering under the gate keeps concurrent
+        // publishers from both taking the last slot
+        let _gate = if self.max_streams > 0 {
+            let gate = self.publish_gate.lock().await;
+            if self.live_stream_count_except(&stored).await >= self.max_streams {
+                return Err(RegistryError::StreamLimitReached(key.clone()));
+            }
+            Some(gate)
+        } else {
+            None
+        };
 
-        if let Some(entry_arc) = streams.get(key) {
+        let mut streams = self.shard(&stored).write().await;
+
+        if let Some(entry_arc) = streams.get(&*stored) {
             let mut entry = entry_arc.write().await;
 
             // Check if stream is available for publishing
             match entry.state {
-                StreamState::Active if entry.publisher_id.is_some() => {
+                StreamState::Active
+                    if entry.publisher_id.is_some() && !self.config.publisher_takeover =>
+                {
                     return Err(RegistryError::StreamAlreadyPublishing(key.clone()));
                 }
-                StreamState::GracePeriod | StreamState::Idle | StreamState::Active => {
+                StreamState::GracePeriod
+                | StreamState::Idle
+                | StreamState::Pending
+                | StreamState::Active => {
+                    // Subscribers that stayed through the grace period keep
+                    // the old timeline; anyone else starts on the new one
+                    entry.start_timeline(entry.state == StreamState::GracePeriod);
+
                     // Reclaim or take over the stream
+                    let replaced = entry.publisher_id.filter(|&id| id != session_id);
                     entry.publisher_id = Some(session_id);
                     entry.publisher_disconnected_at = None;
                     entry.state = StreamState::Active;
 
+                    // The old publisher's session closes once it sees this
+                    if let Some(old) = replaced {
+                        entry.replaced_publisher.send_replace(Some(old));
+                        tracing::info!(
+                            stream = %key,
+                            session_id = session_id,
+                            replaced = old,
+                            "Publisher taken over"
+                        );
+                    }
+
                     tracing::info!(
                         stream = %key,
                         session_id = session_id,
@@ -82,11 +181,11 @@ impl StreamRegistry {
             }
         } else {
             // Create new stream entry
-            let mut entry = StreamEntry::new(&self.config);
+            let mut entry = StreamEntry::new(&self.config, &key.app, self.gop_budget.clone());
             entry.publisher_id = Some(session_id);
             entry.state = StreamState::Active;
 
-            streams.insert(key.clone(), Arc::new(RwLock::new(entry)));
+            streams.insert(stored.into_owned(), Arc::new(RwLock::new(entry)));
 
             tracing::info!(
                 stream = %key,
@@ -98,30 +197,43 @@ impl StreamRegistry {
         Ok(())
     }
 
+    /// Create a stream ahead of its publisher
+    ///
+    /// The stream accepts subscribers, who receive nothing until a
+    /// publisher claims it with [`Self::register_publisher`]. Useful for
+    /// failover, where viewers should wait for a replacement publisher
+    /// rather than be turned away. A pending stream without subscribers
+    /// is removed after `idle_stream_timeout`. Does nothing if the stream
+    /// already exists.
+    pub async fn create_pending(&self, key: &StreamKey) {
+        let stored = self.storage_key(key);
+        let mut streams = self.shard(&stored).write().await;
+
+        if streams.contains_key(&*stored) {
+            return;
+        }
+
+        let mut entry = StreamEntry::new(&self.config, &key.app, self.gop_budget.clone());
+        entry.state = StreamState::Pending;
+        streams.insert(stored.into_owned(), Arc::new(RwLock::new(entry)));
+
+        tracing::info!(stream = %key, "Pending stream created");
+    }
+
     /// Unregister a publisher from a stream
     ///
     /// The stream enters grace period if there are active subscribers,
     /// allowing the publisher to reconnect.
     pub async fn unregister_publisher(&self, key: &StreamKey, session_id: u64) {
-        let streams = self.streams.read().await;
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
 
-        if let Some(entry_arc) = streams.get(key) {
+        if let Some(entry_arc) = streams.get(&*stored) {
             let mut entry = entry_arc.write().await;
-
-            // Verify this is the actual publisher
-            if entry.publisher_id != Some(session_id) {
-                tracing::warn!(
-                    stream = %key,
-                    expected = ?entry.publisher_id,
-                    actual = session_id,
-                    "Publisher unregister mismatch"
-                );
+            if !release_publisher(&mut entry, key, session_id) {
                 return;
             }
 
-            entry.publisher_id = None;
-            entry.publisher_disconnected_at = Some(Instant::now());
-
             // If there are subscribers, enter grace period; otherwise go idle
             if entry.subscriber_count() > 0 {
                 entry.state = StreamState::GracePeriod;
@@ -143,33 +255,124 @@ impl StreamRegistry {
         }
     }
 
+    /// Unregister a publisher that stopped cleanly
+    ///
+    /// Unlike [`unregister_publisher`](Self::unregister_publisher) there is
+    /// no grace period: the stream goes idle at once. Subscribers get the
+    /// end-of-sequence frames of the stream's codecs, then the
+    /// [`unpublish_signal`](Self::unpublish_signal) fires, so players can
+    /// stop instead of freezing on the last picture.
+    pub async fn unpublish(&self, key: &StreamKey, session_id: u64) {
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
+
+        if let Some(entry_arc) = streams.get(&*stored) {
+            let mut entry = entry_arc.write().await;
+            if !release_publisher(&mut entry, key, session_id) {
+                return;
+            }
+
+            entry.state = StreamState::Idle;
+            for frame in entry.end_of_sequence() {
+                entry.send(frame);
+            }
+            entry.unpublished.send_modify(|count| *count += 1);
+            let notified = entry.subscriber_count();
+            tracing::info!(
+                stream = %key,
+                session_id = session_id,
+                subscribers = notified,
+                "Publisher stopped, end of stream sent"
+            );
+        }
+    }
+
     /// Subscribe to a stream
     ///
     /// Returns a broadcast receiver and catchup frames for the subscriber.
     /// The catchup frames contain sequence headers and recent GOP data.
+    /// The subscriber is counted but not listed by
+    /// [`list_subscribers`](Self::list_subscribers); see
+    /// [`subscribe_session`](Self::subscribe_session).
     pub async fn subscribe(
         &self,
         key: &StreamKey,
     ) -> Result<(broadcast::Receiver<BroadcastFrame>, Vec<BroadcastFrame>), RegistryError> {
-        let streams = self.streams.read().await;
+        self.subscribe_inner(key, None, None).await
+    }
+
+    /// Subscribe to a stream on behalf of a session
+    ///
+    /// Like [`subscribe`](Self::subscribe), and lists the session among the
+    /// stream's subscribers until
+    /// [`unsubscribe_session`](Self::unsubscribe_session).
+    pub async fn subscribe_session(
+        &self,
+        key: &StreamKey,
+        session_id: u64,
+        peer_addr: SocketAddr,
+    ) -> Result<(broadcast::Receiver<BroadcastFrame>, Vec<BroadcastFrame>), RegistryError> {
+        let info = SubscriberInfo {
+            session_id,
+            peer_addr,
+            joined_at: Instant::now(),
+            dropped_frames: 0,
+            buffer_ms: None,
+        };
+        self.subscribe_inner(key, Some(info), None).await
+    }
+
+    /// Subscribe to a stream on behalf of a session with its own catchup
+    ///
+    /// Like [`subscribe_session`](Self::subscribe_session), but the late
+    /// joiner's catchup follows `strategy` instead of
+    /// [`RegistryConfig::catchup_strategy`].
+    pub async fn subscribe_session_with(
+        &self,
+        key: &StreamKey,
+        session_id: u64,
+        peer_addr: SocketAddr,
+        strategy: CatchupStrategy,
+    ) -> Result<(broadcast::Receiver<BroadcastFrame>, Vec<BroadcastFrame>), RegistryError> {
+        let info = SubscriberInfo {
+            session_id,
+            peer_addr,
+            joined_at: Instant::now(),
+            dropped_frames: 0,
+            buffer_ms: None,
+        };
+        self.subscribe_inner(key, Some(info), Some(strategy)).await
+    }
+
+    async fn subscribe_inner(
+        &self,
+        key: &StreamKey,
+        info: Option<SubscriberInfo>,
+        strategy: Option<CatchupStrategy>,
+    ) -> Result<(broadcast::Receiver<BroadcastFrame>, Vec<BroadcastFrame>), RegistryError> {
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
 
         let entry_arc = streams
-            .get(key)
+            .get(&*stored)
             .ok_or_else(|| RegistryError::StreamNotFound(key.clone()))?;
 
         let entry = entry_arc.read().await;
 
-        // Allow subscription even during grace period (publisher might reconnect)
+        // Allow subscription even during grace period (publisher might
+        // reconnect) and on pending streams (publisher yet to arrive)
         if entry.state == StreamState::Idle && entry.publisher_id.is_none() {
             return Err(RegistryError::StreamNotActive(key.clone()));
         }
 
         // Get receiver and catchup frames
-        let rx = entry.subscribe();
-        let catchup = entry.get_catchup_frames();
+        let (rx, catchup) = entry.subscribe(strategy);
 
         // Increment subscriber count
         entry.subscriber_count.fetch_add(1, Ordering::Relaxed);
+        if let Some(info) = info {
+            entry.subscribers().insert(info.session_id, info);
+        }
 
         tracing::info!(
             stream = %key,
@@ -181,12 +384,55 @@ impl StreamRegistry {
         Ok((rx, catchup))
     }
 
+    /// Attach an in-process sink to a stream
+    ///
+    /// The sink is fed the stream's catchup frames, then every frame
+    /// broadcast until it is removed or the stream goes away. It does not
+    /// count as a subscriber.
+    pub async fn add_sink(
+        &self,
+        key: &StreamKey,
+        sink: Box<dyn FrameSink>,
+    ) -> Result<SinkId, RegistryError> {
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
+        let entry_arc = streams
+            .get(&*stored)
+            .ok_or_else(|| RegistryError::StreamNotFound(key.clone()))?;
+
+        let id = entry_arc.read().await.add_sink(sink);
+        tracing::debug!(stream = %key, sink = id.0, "Sink added");
+        Ok(id)
+    }
+
+    /// Detach a sink from a stream, returning it
+    pub async fn remove_sink(&self, key: &StreamKey, id: SinkId) -> Option<Box<dyn FrameSink>> {
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
+        let entry = streams.get(&*stored)?.read().await;
+        entry.remove_sink(id)
+    }
+
     /// Unsubscribe from a stream
     pub async fn unsubscribe(&self, key: &StreamKey) {
-        let streams = self.streams.read().await;
+        self.unsubscribe_inner(key, None).await;
+    }
 
-        if let Some(entry_arc) = streams.get(key) {
+    /// Unsubscribe a session subscribed with
+    /// [`subscribe_session`](Self::subscribe_session)
+    pub async fn unsubscribe_session(&self, key: &StreamKey, session_id: u64) {
+        self.unsubscribe_inner(key, Some(session_id)).await;
+    }
+
+    async fn unsubscribe_inner(&self, key: &StreamKey, session_id: Option<u64>) {
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
+
+        if let Some(entry_arc) = streams.get(&*stored) {
             let entry = entry_arc.read().await;
+            if let Some(session_id) = session_id {
+                entry.subscribers().remove(&session_id);
+            }
             let prev = entry.subscriber_count.fetch_sub(1, Ordering::Relaxed);
 
             tracing::debug!(
@@ -197,31 +443,257 @@ impl StreamRegistry {
         }
     }
 
+    /// Forcibly remove a stream
+    ///
+    /// Its publisher and all subscribers are disconnected and their
+    /// handlers see [`DisconnectReason::Evicted`](crate::DisconnectReason::Evicted).
+    /// Returns false if the stream does not exist.
+    pub async fn evict_stream(&self, key: &StreamKey) -> bool {
+        let stored = self.storage_key(key);
+        let Some(entry_arc) = self.shard(&stored).write().await.remove(&*stored) else {
+            return false;
+        };
+
+        // Signal before the entry (and with it the broadcast channel) is
+        // dropped, so subscribers see the eviction rather than a stream end
+        let entry = entry_arc.read().await;
+        entry.evicted.send_replace(true);
+
+        tracing::info!(
+            stream = %key,
+            publisher = ?entry.publisher_id,
+            subscribers = entry.subscriber_count(),
+            "Stream evicted"
+        );
+        true
+    }
+
+    /// Get a signal that resolves when the stream is evicted
+    pub(crate) async fn eviction_signal(&self, key: &StreamKey) -> Option<watch::Receiver<bool>> {
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
+        let entry = streams.get(&*stored)?.read().await;
+        Some(entry.evicted.subscribe())
+    }
+
+    /// Get a signal that changes whenever the stream's publisher stops
+    /// cleanly
+    ///
+    /// It changes after the end-of-sequence frames are broadcast, so a
+    /// subscriber that drains its frames first sees them before the signal.
+    pub(crate) async fn unpublish_signal(&self, key: &StreamKey) -> Option<watch::Receiver<u64>> {
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
+        let entry = streams.get(&*stored)?.read().await;
+        Some(entry.unpublished.subscribe())
+    }
+
+    /// Get a signal carrying the last publisher replaced by a takeover
+    pub(crate) async fn takeover_signal(
+        &self,
+        key: &StreamKey,
+    ) -> Option<watch::Receiver<Option<u64>>> {
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
+        let entry = streams.get(&*stored)?.read().await;
+        Some(entry.replaced_publisher.subscribe())
+    }
+
+    /// Count frames a subscriber of a stream dropped
+    pub async fn record_dropped_frames(&self, key: &StreamKey, count: u64) {
+        self.record_dropped_frames_inner(key, None, count).await;
+    }
+
+    /// Count frames a session's subscription dropped, for the stream and
+    /// the session's [`SubscriberInfo`]
+    pub async fn record_session_dropped_frames(
+        &self,
+        key: &StreamKey,
+        session_id: u64,
+        count: u64,
+    ) {
+        self.record_dropped_frames_inner(key, Some(session_id), count)
+            .await;
+    }
+
+    /// Record the buffer length a subscribed session asked for
+    ///
+    /// Players announce it with the Set Buffer Length user control event;
+    /// it shows up in [`list_subscribers`](Self::list_subscribers).
+    pub async fn set_subscriber_buffer_length(
+        &self,
+        key: &StreamKey,
+        session_id: u64,
+        buffer_ms: u32,
+    ) {
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
+
+        if let Some(entry_arc) = streams.get(&*stored) {
+            let entry = entry_arc.read().await;
+            let mut subscribers = entry.subscribers();
+            if let Some(info) = subscribers.get_mut(&session_id) {
+                info.buffer_ms = Some(buffer_ms);
+            }
+        }
+    }
+
+    async fn record_dropped_frames_inner(
+        &self,
+        key: &StreamKey,
+        session_id: Option<u64>,
+        count: u64,
+    ) {
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
+
+        if let Some(entry_arc) = streams.get(&*stored) {
+            let entry = entry_arc.read().await;
+            entry.dropped_frames.fetch_add(count, Ordering::Relaxed);
+            if let Some(session_id) = session_id {
+                if let Some(info) = entry.subscribers().get_mut(&session_id) {
+                    info.dropped_frames += count;
+                }
+            }
+        }
+    }
+
+    /// List the sessions subscribed to a stream, earliest first
+    ///
+    /// Only subscribers that joined with
+    /// [`subscribe_session`](Self::subscribe_session) are listed, which
+    /// includes every player of the server. Empty if the stream does not
+    /// exist.
+    pub async fn list_subscribers(&self, key: &StreamKey) -> Vec<SubscriberInfo> {
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
+
+        let Some(entry_arc) = streams.get(&*stored) else {
+            return Vec::new();
+        };
+        let entry = entry_arc.read().await;
+        let mut subscribers: Vec<_> = entry.subscribers().values().cloned().collect();
+        subscribers.sort_by_key(|info| (info.joined_at, info.session_id));
+        subscribers
+    }
+
     /// Broadcast a frame to all subscribers of a stream
     ///
-    /// Also updates the GOP buffer and sequence headers as needed.
+    /// Also updates the GOP buffer and sequence headers as needed. The
+    /// frame's header and keyframe flags are taken from its payload (see
+    /// [`BroadcastFrame::classify`]), so a mislabeled frame cannot replace
+    /// a cached header.
     pub async fn broadcast(&self, key: &StreamKey, frame: BroadcastFrame) {
-        let streams = self.streams.read().await;
+        let mut frame = frame.classify();
+        let stored = self.storage_key(key);
+        let fits = {
+            let streams = self.shard(&stored).read().await;
+            let Some(entry_arc) = streams.get(&*stored) else {
+                return;
+            };
+
+            // Header and metadata frames replace cached state and need the
+            // write lock; media frames only touch the GOP buffer and the
+            // broadcast sender, which a read lock is enough for
+            if StreamEntry::updates_headers(&frame) {
+                let mut entry = entry_arc.write().await;
+                entry.continue_timestamp(&mut frame);
+                entry.update_headers(&frame);
+                entry.publish(frame)
+            } else {
+                let entry = entry_arc.read().await;
+                entry.continue_and_publish(frame)
+            }
+        };
 
-        if let Some(entry_arc) = streams.get(key) {
-            let mut entry = entry_arc.write().await;
+        // Streams nobody publishes to make room for the next keyframe of
+        // this one
+        if !fits {
+            let released = self.release_idle_gops().await;
+            tracing::debug!(
+                stream = %key,
+                released_bytes = released,
+                used_bytes = self.gop_budget.used(),
+                "GOP budget exhausted"
+            );
+        }
+    }
+
+    /// Drop the buffered GOPs of streams without a publisher
+    ///
+    /// Returns the number of bytes released.
+    async fn release_idle_gops(&self) -> usize {
+        let mut released = 0;
+        for shard in self.shards.iter() {
+            for entry_arc in shard.read().await.values() {
+                // Skip entries busy elsewhere rather than wait for them
+                if let Ok(entry) = entry_arc.try_read() {
+                    if entry.publisher_id.is_none() {
+                        released += entry.release_gop();
+                    }
+                }
+            }
+        }
+        released
+    }
+
+    /// Get the bytes held by the GOP buffers of all streams
+    ///
+    /// Bounded by [`RegistryConfig::total_gop_budget_bytes`] when set.
+    pub fn gop_memory_usage(&self) -> usize {
+        self.gop_budget.used()
+    }
 
-            // Update cached headers and GOP buffer
-            entry.update_caches(&frame);
+    /// Set the cached metadata for a stream and forward it to subscribers
+    ///
+    /// `data` is an encoded `onMetaData` data message body (see
+    /// [`crate::media::metadata`]). Late joiners receive it before the
+    /// sequence headers. Useful when relaying a stream that carries no
+    /// metadata of its own.
+    pub async fn set_metadata(&self, key: &StreamKey, data: Bytes) {
+        self.broadcast(key, BroadcastFrame::metadata(data)).await;
+    }
 
-            // Broadcast to subscribers
-            // Note: send() returns Ok(n) where n is number of receivers, or Err if no receivers
-            let _ = entry.send(frame);
+    /// Broadcast a timed data message such as `onCuePoint` or `onTextData`
+    ///
+    /// `tag` is a script tag whose body is the AMF0 name and values.
+    /// Players receive it interleaved with the media at its timestamp.
+    /// The latest `onCuePoint` is also kept for late joiners; other data
+    /// only reaches current subscribers.
+    pub async fn broadcast_data(&self, key: &StreamKey, tag: FlvTag) {
+        if tag.tag_type != FlvTagType::Script {
+            tracing::warn!(stream = %key, tag_type = ?tag.tag_type, "Ignoring non-script data tag");
+            return;
         }
+        self.broadcast(key, BroadcastFrame::data(tag.timestamp, tag.data))
+            .await;
+    }
+
+    /// Remove the cached metadata of a stream
+    ///
+    /// Late joiners no longer receive metadata. Current subscribers are
+    /// sent an empty `onMetaData` so players drop what they had.
+    pub async fn clear_metadata(&self, key: &StreamKey) {
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
+        let Some(entry_arc) = streams.get(&*stored) else {
+            return;
+        };
+
+        let mut entry = entry_arc.write().await;
+        entry.metadata = None;
+        let empty = encode_on_metadata(&AmfObject::new());
+        entry.send(BroadcastFrame::metadata(empty));
     }
 
     /// Get sequence headers for a stream (video and audio decoder config)
     ///
     /// Used when resuming playback after pause to reinitialize decoders.
     pub async fn get_sequence_headers(&self, key: &StreamKey) -> Vec<BroadcastFrame> {
-        let streams = self.streams.read().await;
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
 
-        if let Some(entry_arc) = streams.get(key) {
+        if let Some(entry_arc) = streams.get(&*stored) {
             let entry = entry_arc.read().await;
             let mut frames = Vec::with_capacity(2);
 
@@ -240,9 +712,10 @@ impl StreamRegistry {
 
     /// Check if a stream exists and has an active publisher
     pub async fn has_active_stream(&self, key: &StreamKey) -> bool {
-        let streams = self.streams.read().await;
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
 
-        if let Some(entry_arc) = streams.get(key) {
+        if let Some(entry_arc) = streams.get(&*stored) {
             let entry = entry_arc.read().await;
             entry.state == StreamState::Active && entry.publisher_id.is_some()
         } else {
@@ -252,9 +725,10 @@ impl StreamRegistry {
 
     /// Check if a stream exists (active or in grace period)
     pub async fn stream_exists(&self, key: &StreamKey) -> bool {
-        let streams = self.streams.read().await;
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
 
-        if let Some(entry_arc) = streams.get(key) {
+        if let Some(entry_arc) = streams.get(&*stored) {
             let entry = entry_arc.read().await;
             matches!(entry.state, StreamState::Active | StreamState::GracePeriod)
         } else {
@@ -264,17 +738,11 @@ impl StreamRegistry {
 
     /// Get stream statistics
     pub async fn get_stream_stats(&self, key: &StreamKey) -> Option<StreamStats> {
-        let streams = self.streams.read().await;
+        let stored = self.storage_key(key);
+        let streams = self.shard(&stored).read().await;
 
-        if let Some(entry_arc) = streams.get(key) {
-            let entry = entry_arc.read().await;
-            Some(StreamStats {
-                subscriber_count: entry.subscriber_count(),
-                has_publisher: entry.publisher_id.is_some(),
-                state: entry.state,
-                gop_frame_count: entry.gop_buffer.frame_count(),
-                gop_size_bytes: entry.gop_buffer.size(),
-            })
+        if let Some(entry_arc) = streams.get(&*stored) {
+            Some(entry_arc.read().await.stats())
         } else {
             None
         }
@@ -282,7 +750,62 @@ impl StreamRegistry {
 
     /// Get total number of streams
     pub async fn stream_count(&self) -> usize {
-        self.streams.read().await.len()
+        let mut count = 0;
+        for shard in self.shards.iter() {
+            count += shard.read().await.len();
+        }
+        count
+    }
+
+    /// Get total number of streams without awaiting
+    ///
+    /// For sync contexts such as a blocking metrics exporter. Best effort:
+    /// shards locked for writing at the time of the call are skipped, so
+    /// under contention the count may be low.
+    pub fn blocking_stream_count(&self) -> usize {
+        self.shards
+            .iter()
+            .filter_map(|shard| shard.try_read().ok())
+            .map(|streams| streams.len())
+            .sum()
+    }
+
+    /// List streams and their statistics without awaiting
+    ///
+    /// Like [`blocking_stream_count`](Self::blocking_stream_count), this
+    /// is a possibly stale snapshot: streams whose shard or entry is
+    /// locked for writing at the time of the call are left out.
+    pub fn blocking_list_streams(&self) -> Vec<(StreamKey, StreamStats)> {
+        let mut list = Vec::new();
+        for shard in self.shards.iter() {
+            let Ok(streams) = shard.try_read() else {
+                continue;
+            };
+            for (key, entry_arc) in streams.iter() {
+                if let Ok(entry) = entry_arc.try_read() {
+                    list.push((key.clone(), entry.stats()));
+                }
+            }
+        }
+        list
+    }
+
+    /// Count streams with a publisher or in their grace period, other
+    /// than `skip`
+    async fn live_stream_count_except(&self, skip: &StreamKey) -> usize {
+        let mut count = 0;
+        for shard in self.shards.iter() {
+            for (key, entry_arc) in shard.read().await.iter() {
+                if key == skip {
+                    continue;
+                }
+                let entry = entry_arc.read().await;
+                if matches!(entry.state, StreamState::Active | StreamState::GracePeriod) {
+                    count += 1;
+                }
+            }
+        }
+        count
     }
 
     /// Run cleanup task once
@@ -290,8 +813,21 @@ impl StreamRegistry {
     /// Removes streams that have:
     /// - Been in grace period longer than `publisher_grace_period`
     /// - Been idle longer than `idle_stream_timeout`
-    pub async fn cleanup(&self) {
-        let mut streams = self.streams.write().await;
+    ///
+    /// Returns the keys of the removed streams.
+    pub async fn cleanup(&self) -> Vec<StreamKey> {
+        // Shards are swept one at a time so broadcasts to streams in other
+        // shards are never blocked
+        let mut removed = Vec::new();
+        for shard in self.shards.iter() {
+            removed.extend(self.cleanup_shard(shard).await);
+        }
+        removed
+    }
+
+    /// Remove expired streams from a single shard
+    async fn cleanup_shard(&self, shard: &Shard) -> Vec<StreamKey> {
+        let mut streams = shard.write().await;
         let now = Instant::now();
 
         let keys_to_remove: Vec<StreamKey> = streams
@@ -317,6 +853,11 @@ impl StreamRegistry {
                                     > self.config.idle_stream_timeout
                             }
                         }
+                        StreamState::Pending => {
+                            entry.subscriber_count() == 0
+                                && now.duration_since(entry.created_at)
+                                    > self.config.idle_stream_timeout
+                        }
                         StreamState::Active => false,
                     };
 
@@ -331,29 +872,85 @@ impl StreamRegistry {
             })
             .collect();
 
-        for key in keys_to_remove {
-            streams.remove(&key);
+        for key in &keys_to_remove {
+            streams.remove(key);
             tracing::info!(stream = %key, "Stream removed by cleanup");
         }
+        keys_to_remove
+    }
+
+    /// Change how often the background cleanup task runs
+    ///
+    /// Takes effect immediately in a running task: the next sweep happens
+    /// `interval` from now. A zero interval is treated as one millisecond.
+    pub fn set_cleanup_interval(&self, interval: Duration) {
+        self.cleanup_interval.send_replace(interval);
+    }
+
+    /// Get the current cleanup interval
+    pub fn cleanup_interval(&self) -> Duration {
+        *self.cleanup_interval.borrow()
     }
 
     /// Spawn background cleanup task
     ///
-    /// Returns a handle that can be used to abort the task.
+    /// Runs every [`cleanup_interval`](Self::cleanup_interval), which
+    /// [`set_cleanup_interval`](Self::set_cleanup_interval) can change
+    /// while it runs. Returns a handle that can be used to abort the task.
     pub fn spawn_cleanup_task(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
         let registry = Arc::clone(self);
-        let interval = registry.config.cleanup_interval;
+        let mut interval_rx = registry.cleanup_interval.subscribe();
 
         tokio::spawn(async move {
-            let mut ticker = tokio::time::interval(interval);
+            let mut ticker = cleanup_ticker(*interval_rx.borrow_and_update(), false);
             loop {
-                ticker.tick().await;
-                registry.cleanup().await;
+                tokio::select! {
+                    _ = ticker.tick() => {
+                        registry.cleanup().await;
+                    }
+                    Ok(()) = interval_rx.changed() => {
+                        let interval = *interval_rx.borrow_and_update();
+                        tracing::debug!(interval = ?interval, "Cleanup interval changed");
+                        ticker = cleanup_ticker(interval, true);
+                    }
+                }
             }
         })
     }
 }
 
+/// Ticker for the cleanup task, first firing now or after one interval
+fn cleanup_ticker(interval: Duration, delay_first: bool) -> tokio::time::Interval {
+    // tokio panics on a zero period
+    let interval = interval.max(Duration::from_millis(1));
+    let start = if delay_first {
+        tokio::time::Instant::now() + interval
+    } else {
+        tokio::time::Instant::now()
+    };
+    tokio::time::interval_at(start, interval)
+}
+
+/// Detach `session_id` from the stream it publishes
+///
+/// Returns false, leaving the entry untouched, if another session is the
+/// publisher.
+fn release_publisher(entry: &mut StreamEntry, key: &StreamKey, session_id: u64) -> bool {
+    if entry.publisher_id != Some(session_id) {
+        tracing::warn!(
+            stream = %key,
+            expected = ?entry.publisher_id,
+            actual = session_id,
+            "Publisher unregister mismatch"
+        );
+        return false;
+    }
+
+    entry.publisher_id = None;
+    entry.publisher_disconnected_at = Some(Instant::now());
+    true
+}
+
 impl Default for StreamRegistry {
     fn default() -> Self {
         Self::new()
@@ -362,9 +959,9 @@ impl Default for StreamRegistry {
 
 #[cfg(test)]
 mod tests {
-    use bytes::Bytes;
-
     use super::*;
+    use crate::media::gop::StreamKind;
+    use crate::registry::FrameType;
 
     #[tokio::test]
     async fn test_register_publisher() {
@@ -411,6 +1008,29 @@ mod tests {
         assert_eq!(stats.subscriber_count, 0);
     }
 
+    #[tokio::test]
+    async fn test_evict_stream() {
+        let registry = StreamRegistry::new();
+        let key = StreamKey::new("live", "test_stream");
+
+        assert!(!registry.evict_stream(&key).await);
+
+        registry.register_publisher(&key, 1).await.unwrap();
+        let (mut rx, _) = registry.subscribe(&key).await.unwrap();
+        let mut eviction = registry.eviction_signal(&key).await.unwrap();
+
+        assert!(registry.evict_stream(&key).await);
+        assert!(!registry.stream_exists(&key).await);
+        assert!(*eviction.borrow_and_update());
+        assert!(matches!(
+            rx.recv().await,
+            Err(broadcast::error::RecvError::Closed)
+        ));
+
+        // The key is free for a new publisher
+        registry.register_publisher(&key, 2).await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_grace_period() {
         let config =
@@ -437,6 +1057,67 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_cleanup_returns_removed_keys() {
+        let config = RegistryConfig::default()
+            .publisher_grace_period(std::time::Duration::ZERO)
+            .idle_stream_timeout(std::time::Duration::ZERO);
+        let registry = StreamRegistry::with_config(config);
+        let ended = StreamKey::new("live", "ended");
+        let abandoned = StreamKey::new("live", "abandoned");
+        let live = StreamKey::new("live", "live");
+
+        registry.register_publisher(&ended, 1).await.unwrap();
+        registry.unregister_publisher(&ended, 1).await;
+        registry.register_publisher(&abandoned, 2).await.unwrap();
+        let (_rx, _) = registry.subscribe(&abandoned).await.unwrap();
+        registry.unregister_publisher(&abandoned, 2).await;
+        registry.register_publisher(&live, 3).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+        let mut removed = registry.cleanup().await;
+        removed.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(removed, vec![abandoned.clone(), ended.clone()]);
+        assert!(!registry.stream_exists(&ended).await);
+        assert!(!registry.stream_exists(&abandoned).await);
+        assert!(registry.stream_exists(&live).await);
+
+        // Nothing left to remove
+        assert!(registry.cleanup().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_set_cleanup_interval_retunes_task() {
+        let config = RegistryConfig {
+            cleanup_interval: std::time::Duration::from_secs(3600),
+            ..RegistryConfig::default()
+        }
+        .publisher_grace_period(std::time::Duration::ZERO)
+        .idle_stream_timeout(std::time::Duration::ZERO);
+        let registry = Arc::new(StreamRegistry::with_config(config));
+        let key = StreamKey::new("live", "test_stream");
+        let task = registry.spawn_cleanup_task();
+
+        // Let the immediate first sweep pass before the stream ends
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        registry.register_publisher(&key, 1).await.unwrap();
+        registry.unregister_publisher(&key, 1).await;
+
+        registry.set_cleanup_interval(std::time::Duration::from_millis(10));
+        assert_eq!(
+            registry.cleanup_interval(),
+            std::time::Duration::from_millis(10)
+        );
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while registry.stream_exists(&key).await {
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("stream never cleaned up");
+        task.abort();
+    }
+
     #[tokio::test]
     async fn test_publisher_reconnect() {
         let registry = StreamRegistry::new();
@@ -461,29 +1142,1017 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_catchup_frames() {
-        let registry = StreamRegistry::new();
+    async fn test_publisher_takeover() {
+        let registry =
+            StreamRegistry::with_config(RegistryConfig::default().publisher_takeover(true));
         let key = StreamKey::new("live", "test_stream");
 
         registry.register_publisher(&key, 1).await.unwrap();
+        let mut takeover = registry.takeover_signal(&key).await.unwrap();
+        assert_eq!(*takeover.borrow_and_update(), None);
 
-        // Broadcast sequence headers
-        let video_header = BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x00]), true, true);
-        let audio_header = BroadcastFrame::audio(0, Bytes::from_static(&[0xAF, 0x00]), true);
-        registry.broadcast(&key, video_header).await;
-        registry.broadcast(&key, audio_header).await;
+        // A second publisher replaces the first instead of being refused
+        registry.register_publisher(&key, 2).await.unwrap();
+        assert!(takeover.has_changed().unwrap());
+        assert_eq!(*takeover.borrow_and_update(), Some(1));
 
-        // Broadcast a keyframe
-        let keyframe = BroadcastFrame::video(33, Bytes::from_static(&[0x17, 0x01]), true, false);
-        registry.broadcast(&key, keyframe).await;
+        // The replaced session leaving must not unregister its successor
+        registry.unregister_publisher(&key, 1).await;
+        let stats = registry.get_stream_stats(&key).await.unwrap();
+        assert!(stats.has_publisher);
+        assert_eq!(stats.state, StreamState::Active);
+    }
 
-        // Late joiner subscribes
-        let (_rx, catchup) = registry.subscribe(&key).await.unwrap();
+    #[tokio::test]
+    async fn test_reclaim_continues_timestamps() {
+        let registry = StreamRegistry::with_config(
+            RegistryConfig::default().continue_timestamps_on_reclaim(true),
+        );
+        let key = StreamKey::new("live", "test_stream");
+        let header = || BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x00]), true, true);
+        let frame = |timestamp| {
+            BroadcastFrame::video(timestamp, Bytes::from_static(&[0x27, 0x01]), false, false)
+        };
 
-        // Should receive headers + keyframe
-        assert_eq!(catchup.len(), 3);
-        assert!(catchup[0].is_header); // video header
-        assert!(catchup[1].is_header); // audio header
+        registry.register_publisher(&key, 1).await.unwrap();
+        let (mut rx, _) = registry.subscribe(&key).await.unwrap();
+        registry.broadcast(&key, header()).await;
+        for timestamp in [0, 33, 66] {
+            registry.broadcast(&key, frame(timestamp)).await;
+        }
+
+        // The replacement publisher starts again from zero
+        registry.unregister_publisher(&key, 1).await;
+        registry.register_publisher(&key, 2).await.unwrap();
+        registry.broadcast(&key, header()).await;
+        for timestamp in [0, 33, 66] {
+            registry.broadcast(&key, frame(timestamp)).await;
+        }
+
+        let mut timestamps = Vec::new();
+        while let Ok(frame) = rx.try_recv() {
+            timestamps.push(frame.timestamp);
+        }
+        assert_eq!(timestamps, vec![0, 0, 33, 66, 67, 67, 100, 133]);
+
+        // Late joiners get the header on the continued timeline too
+        let (_rx, catchup) = registry.subscribe(&key).await.unwrap();
+        assert_eq!(catchup[0].timestamp, 67);
+
+        // A publisher claiming the stream after it went idle starts fresh
+        registry.unsubscribe(&key).await;
+        registry.unsubscribe(&key).await;
+        registry.unregister_publisher(&key, 2).await;
+        registry.register_publisher(&key, 3).await.unwrap();
+        let (mut rx, _) = registry.subscribe(&key).await.unwrap();
+        registry.broadcast(&key, frame(10)).await;
+        assert_eq!(rx.recv().await.unwrap().timestamp, 10);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_overlapping_broadcasts_stay_ordered() {
+        let registry = Arc::new(StreamRegistry::with_config(
+            RegistryConfig::default()
+                .continue_timestamps_on_reclaim(true)
+                .broadcast_capacity(1024),
+        ));
+        let key = StreamKey::new("live", "test_stream");
+
+        // The old publisher leaves the timeline at 5000
+        registry.register_publisher(&key, 1).await.unwrap();
+        let (mut rx, _) = registry.subscribe(&key).await.unwrap();
+        registry
+            .broadcast(
+                &key,
+                BroadcastFrame::video(5000, Bytes::from_static(&[0x17, 0x01, 0, 0]), true, false),
+            )
+            .await;
+        assert_eq!(rx.recv().await.unwrap().timestamp, 5000);
+        registry.unregister_publisher(&key, 1).await;
+        registry.register_publisher(&key, 2).await.unwrap();
+
+        // Several senders race to deliver the first frames after the takeover
+        let mut tasks = Vec::new();
+        for task in 0..8u8 {
+            let registry = registry.clone();
+            let key = key.clone();
+            tasks.push(tokio::spawn(async move {
+                for seq in 0..50u8 {
+                    let data = Bytes::from(vec![0x27, 0x01, task, seq]);
+                    let frame = BroadcastFrame::video(seq as u32 * 10, data, false, false);
+                    registry.broadcast(&key, frame).await;
+                }
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let mut received = Vec::new();
+        while let Ok(frame) = rx.try_recv() {
+            received.push(frame);
+        }
+        assert_eq!(received.len(), 400);
+
+        // Every frame is on the continued timeline, with the same offset
+        for frame in &received {
+            let source = frame.data[3] as u32 * 10;
+            assert_eq!(frame.timestamp - source, 5001, "{:?}", frame);
+        }
+
+        // Each sender's frames arrive in the order it sent them, and in the
+        // same order they entered the GOP buffer
+        let mut next = [0u8; 8];
+        for frame in &received {
+            let (task, seq) = (frame.data[2] as usize, frame.data[3]);
+            assert_eq!(seq, next[task]);
+            next[task] += 1;
+        }
+        let buffered: Vec<Bytes> = {
+            let streams = registry.shard(&key).read().await;
+            let entry = streams.get(&key).unwrap().read().await;
+            let catchup = entry.gop().get_catchup_data();
+            catchup
+                .into_iter()
+                .filter(|tag| tag.data[0] == 0x27)
+                .map(|tag| tag.data)
+                .collect()
+        };
+        let delivered: Vec<Bytes> = received.iter().map(|f| f.data.clone()).collect();
+        assert_eq!(buffered, delivered);
+    }
+
+    #[tokio::test]
+    async fn test_max_streams() {
+        let config =
+            RegistryConfig::default().publisher_grace_period(std::time::Duration::from_secs(60));
+        let mut registry = StreamRegistry::with_config(config);
+        registry.set_max_streams(2);
+        let first = StreamKey::new("live", "first");
+        let second = StreamKey::new("live", "second");
+        let third = StreamKey::new("live", "third");
+
+        registry.register_publisher(&first, 1).await.unwrap();
+        registry.register_publisher(&second, 2).await.unwrap();
+        assert!(matches!(
+            registry.register_publisher(&third, 3).await,
+            Err(RegistryError::StreamLimitReached(_))
+        ));
+
+        // A stream in its grace period still holds its slot, and its
+        // publisher may reclaim it
+        let _viewer = registry.subscribe(&second).await.unwrap();
+        registry.unregister_publisher(&second, 2).await;
+        assert!(matches!(
+            registry.register_publisher(&third, 3).await,
+            Err(RegistryError::StreamLimitReached(_))
+        ));
+        registry.register_publisher(&second, 4).await.unwrap();
+
+        // Streams waiting for a publisher don't count
+        registry.evict_stream(&first).await;
+        registry
+            .create_pending(&StreamKey::new("live", "fourth"))
+            .await;
+        registry.register_publisher(&third, 5).await.unwrap();
+        assert_eq!(registry.stream_count().await, 3);
+    }
+
+    #[test]
+    fn test_blocking_queries() {
+        let registry = Arc::new(StreamRegistry::new());
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            registry
+                .register_publisher(&StreamKey::new("live", "a"), 1)
+                .await
+                .unwrap();
+            registry.create_pending(&StreamKey::new("live", "b")).await;
+        });
+
+        // Queried from a plain thread with no runtime
+        let from_thread = registry.clone();
+        let (count, mut list) = std::thread::spawn(move || {
+            (
+                from_thread.blocking_stream_count(),
+                from_thread.blocking_list_streams(),
+            )
+        })
+        .join()
+        .unwrap();
+        list.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+
+        assert_eq!(count, 2);
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].0, StreamKey::new("live", "a"));
+        assert!(list[0].1.has_publisher);
+        assert_eq!(list[1].1.state, StreamState::Pending);
+
+        // Shards locked for writing are skipped rather than waited on
+        let _locked: Vec<_> = registry
+            .shards
+            .iter()
+            .map(|shard| shard.try_write().unwrap())
+            .collect();
+        assert_eq!(registry.blocking_stream_count(), 0);
+        assert!(registry.blocking_list_streams().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unpublish_sends_end_of_stream() {
+        let registry = StreamRegistry::new();
+        let key = StreamKey::new("live", "test");
+
+        registry.register_publisher(&key, 1).await.unwrap();
+        let header = Bytes::from_static(&[0x17, 0x00, 0, 0, 0, 0x01]);
+        registry
+            .broadcast(&key, BroadcastFrame::video(0, header, false, true))
+            .await;
+        let keyframe = Bytes::from_static(&[0x17, 0x01, 0, 0, 0, 0x65]);
+        registry
+            .broadcast(&key, BroadcastFrame::video(40, keyframe, true, false))
+            .await;
+
+        let audio_header = Bytes::from_static(&[0xAF, 0x00, 0x12, 0x10]);
+        registry
+            .broadcast(&key, BroadcastFrame::audio(40, audio_header, true))
+            .await;
+
+        let (mut rx, _) = registry.subscribe(&key).await.unwrap();
+        let mut unpublished = registry.unpublish_signal(&key).await.unwrap();
+        registry.unpublish(&key, 1).await;
+
+        // No grace period: the stream goes idle and subscribers get the AVC
+        // and AAC ends of sequence, then the signal
+        let video = rx.recv().await.unwrap();
+        assert_eq!(video.frame_type, FrameType::Video);
+        assert_eq!(video.timestamp, 40);
+        assert_eq!(&video.data[..], &[0x17, 0x02, 0, 0, 0]);
+        let audio = rx.recv().await.unwrap();
+        assert_eq!(audio.frame_type, FrameType::Audio);
+        assert_eq!(&audio.data[..], &[0x92, b'm', b'p', b'4', b'a']);
+        assert!(unpublished.has_changed().unwrap());
+        unpublished.borrow_and_update();
+        let stats = registry.get_stream_stats(&key).await.unwrap();
+        assert_eq!(stats.state, StreamState::Idle);
+
+        // A publisher taking the stream over is relayed as before
+        registry.register_publisher(&key, 2).await.unwrap();
+        let keyframe = Bytes::from_static(&[0x17, 0x01, 0, 0, 0, 0x66]);
+        registry
+            .broadcast(&key, BroadcastFrame::video(0, keyframe, true, false))
+            .await;
+        assert_eq!(rx.recv().await.unwrap().frame_type, FrameType::Video);
+    }
+
+    #[tokio::test]
+    async fn test_unpublish_end_of_stream_per_codec() {
+        let cases: [(FrameType, &[u8], &[u8]); 4] = [
+            // Enhanced HEVC SequenceStart keeps its FourCC
+            (
+                FrameType::Video,
+                &[0x90, b'h', b'v', b'c', b'1', 0x01],
+                &[0x92, b'h', b'v', b'c', b'1'],
+            ),
+            // Legacy HEVC (codec ID 12)
+            (
+                FrameType::Video,
+                &[0x1C, 0x00, 0, 0, 0],
+                &[0x1C, 0x02, 0, 0, 0],
+            ),
+            // Enhanced Opus SequenceStart
+            (
+                FrameType::Audio,
+                &[0x90, b'O', b'p', b'u', b's', 0x01],
+                &[0x92, b'O', b'p', b'u', b's'],
+            ),
+            // VP6 has no end of sequence
+            (FrameType::Video, &[0x14, 0x00], &[]),
+        ];
+        for (frame_type, header, end) in cases {
+            let registry = StreamRegistry::new();
+            let key = StreamKey::new("live", "test");
+            registry.register_publisher(&key, 1).await.unwrap();
+            let header = Bytes::copy_from_slice(header);
+            let frame = match frame_type {
+                FrameType::Video => BroadcastFrame::video(0, header, false, true),
+                _ => BroadcastFrame::audio(0, header, true),
+            };
+            registry.broadcast(&key, frame).await;
+
+            let (mut rx, _) = registry.subscribe(&key).await.unwrap();
+            registry.unpublish(&key, 1).await;
+            match rx.try_recv() {
+                Ok(frame) => {
+                    assert_eq!(frame.frame_type, frame_type);
+                    assert_eq!(&frame.data[..], end);
+                }
+                Err(_) => assert!(end.is_empty()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_before_publish() {
+        let registry = StreamRegistry::new();
+        let key = StreamKey::new("live", "failover");
+
+        registry.create_pending(&key).await;
+        let (mut rx, catchup) = registry.subscribe(&key).await.unwrap();
+        assert!(catchup.is_empty());
+        assert!(rx.try_recv().is_err());
+
+        let stats = registry.get_stream_stats(&key).await.unwrap();
+        assert_eq!(stats.state, StreamState::Pending);
+        assert!(!stats.has_publisher);
+
+        // The publisher claims the pending stream without a conflict
+        registry.register_publisher(&key, 1).await.unwrap();
+        assert!(registry.has_active_stream(&key).await);
+
+        let keyframe = BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x01]), true, false);
+        registry.broadcast(&key, keyframe).await;
+
+        let frame = rx.recv().await.unwrap();
+        assert!(frame.is_keyframe);
+        assert_eq!(frame.timestamp, 0);
+    }
+
+    #[tokio::test]
+    async fn test_catchup_frames() {
+        let registry = StreamRegistry::new();
+        let key = StreamKey::new("live", "test_stream");
+
+        registry.register_publisher(&key, 1).await.unwrap();
+
+        // Broadcast sequence headers
+        let video_header = BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x00]), true, true);
+        let audio_header = BroadcastFrame::audio(0, Bytes::from_static(&[0xAF, 0x00]), true);
+        registry.broadcast(&key, video_header).await;
+        registry.broadcast(&key, audio_header).await;
+
+        // Broadcast a keyframe
+        let keyframe = BroadcastFrame::video(33, Bytes::from_static(&[0x17, 0x01]), true, false);
+        registry.broadcast(&key, keyframe).await;
+
+        // Late joiner subscribes
+        let (_rx, catchup) = registry.subscribe(&key).await.unwrap();
+
+        // Should receive headers + keyframe
+        assert_eq!(catchup.len(), 3);
+        assert!(catchup[0].is_header); // video header
+        assert!(catchup[1].is_header); // audio header
         assert!(catchup[2].is_keyframe); // keyframe
     }
+
+    #[tokio::test]
+    async fn test_mislabeled_frame_keeps_cached_header() {
+        let registry = StreamRegistry::new();
+        let key = StreamKey::new("live", "test");
+        registry.register_publisher(&key, 1).await.unwrap();
+
+        let header = Bytes::from_static(&[0x17, 0x00, 0, 0, 0, 0x01]);
+        registry
+            .broadcast(&key, BroadcastFrame::video(0, header.clone(), false, false))
+            .await;
+        // A keyframe claiming to be a header
+        let keyframe = Bytes::from_static(&[0x17, 0x01, 0, 0, 0, 0x65]);
+        registry
+            .broadcast(
+                &key,
+                BroadcastFrame::video(33, keyframe.clone(), true, true),
+            )
+            .await;
+
+        let (_rx, catchup) = registry.subscribe(&key).await.unwrap();
+        assert_eq!(catchup.len(), 2);
+        assert!(catchup[0].is_header);
+        assert_eq!(catchup[0].data, header);
+        assert!(catchup[1].is_keyframe && !catchup[1].is_header);
+        assert_eq!(catchup[1].data, keyframe);
+    }
+
+    #[tokio::test]
+    async fn test_sink_sees_catchup_and_live_frames() {
+        let registry = StreamRegistry::new();
+        let key = StreamKey::new("live", "test");
+
+        assert!(matches!(
+            registry
+                .add_sink(&key, Box::new(|_: &BroadcastFrame| {}))
+                .await,
+            Err(RegistryError::StreamNotFound(_))
+        ));
+
+        registry.register_publisher(&key, 1).await.unwrap();
+        let video_header = BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x00]), true, true);
+        registry.broadcast(&key, video_header).await;
+        let keyframe = BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x01]), true, false);
+        registry.broadcast(&key, keyframe).await;
+
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let record = seen.clone();
+        let id = registry
+            .add_sink(
+                &key,
+                Box::new(move |frame: &BroadcastFrame| {
+                    record.lock().unwrap().push(frame.timestamp)
+                }),
+            )
+            .await
+            .unwrap();
+        // The sink is not a channel subscriber
+        assert_eq!(
+            registry
+                .get_stream_stats(&key)
+                .await
+                .unwrap()
+                .subscriber_count,
+            0
+        );
+
+        for ts in [33, 66] {
+            let inter = BroadcastFrame::video(ts, Bytes::from_static(&[0x27, 0x01]), false, false);
+            registry.broadcast(&key, inter).await;
+        }
+        let audio = BroadcastFrame::audio(70, Bytes::from_static(&[0xAF, 0x01]), false);
+        registry.broadcast(&key, audio).await;
+        registry.unpublish(&key, 1).await;
+
+        // Header and keyframe from catchup, then every live frame and the
+        // end of stream
+        assert_eq!(*seen.lock().unwrap(), vec![0, 0, 33, 66, 70, 70]);
+
+        assert!(registry.remove_sink(&key, id).await.is_some());
+        assert!(registry.remove_sink(&key, id).await.is_none());
+        registry.register_publisher(&key, 2).await.unwrap();
+        let frame = BroadcastFrame::video(100, Bytes::from_static(&[0x27, 0x01]), false, false);
+        registry.broadcast(&key, frame).await;
+        assert_eq!(seen.lock().unwrap().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_catchup_strategies() {
+        let cases = [
+            (CatchupStrategy::FullGop, 5),
+            (CatchupStrategy::KeyframeOnly, 4),
+            (CatchupStrategy::HeadersOnly, 3),
+            (CatchupStrategy::LiveEdge, 4),
+        ];
+        for (strategy, expected) in cases {
+            let registry =
+                StreamRegistry::with_config(RegistryConfig::default().catchup_strategy(strategy));
+            let key = StreamKey::new("live", "test");
+
+            registry.register_publisher(&key, 1).await.unwrap();
+            registry
+                .set_metadata(&key, Bytes::from_static(b"onMetaData"))
+                .await;
+            let video_header =
+                BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x00]), true, true);
+            let audio_header = BroadcastFrame::audio(0, Bytes::from_static(&[0xAF, 0x00]), true);
+            registry.broadcast(&key, video_header).await;
+            registry.broadcast(&key, audio_header).await;
+            let keyframe =
+                BroadcastFrame::video(33, Bytes::from_static(&[0x17, 0x01]), true, false);
+            registry.broadcast(&key, keyframe).await;
+            let inter = BroadcastFrame::video(66, Bytes::from_static(&[0x27, 0x01]), false, false);
+            registry.broadcast(&key, inter).await;
+
+            let (_rx, catchup) = registry.subscribe(&key).await.unwrap();
+            assert_eq!(catchup.len(), expected, "{:?}", strategy);
+            assert_eq!(catchup[0].frame_type, FrameType::Metadata);
+            assert!(catchup[1].is_header);
+            assert!(catchup[2].is_header);
+            if strategy == CatchupStrategy::LiveEdge {
+                // The keyframe is stamped at the newest buffered frame
+                assert!(catchup[3].is_keyframe);
+                assert_eq!(catchup[3].timestamp, 66);
+            } else if strategy != CatchupStrategy::HeadersOnly {
+                assert!(catchup[3].is_keyframe);
+                assert_eq!(catchup[3].timestamp, 33);
+            }
+            if strategy == CatchupStrategy::FullGop {
+                assert_eq!(catchup[4].timestamp, 66);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_session_with_strategy() {
+        let registry = StreamRegistry::new();
+        let key = StreamKey::new("live", "test");
+        let addr: SocketAddr = "127.0.0.1:1935".parse().unwrap();
+
+        registry.register_publisher(&key, 1).await.unwrap();
+        let video_header = BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x00]), true, true);
+        registry.broadcast(&key, video_header).await;
+        let keyframe = BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x01]), true, false);
+        registry.broadcast(&key, keyframe).await;
+        for ts in (33..=990).step_by(33) {
+            let inter = BroadcastFrame::video(ts, Bytes::from_static(&[0x27, 0x01]), false, false);
+            registry.broadcast(&key, inter).await;
+        }
+
+        // The registry default still sends the whole GOP
+        let (_rx, full) = registry.subscribe_session(&key, 2, addr).await.unwrap();
+        assert_eq!(full.len(), 32);
+        assert_eq!(full[1].timestamp, 0);
+
+        let (_rx, live) = registry
+            .subscribe_session_with(&key, 3, addr, CatchupStrategy::LiveEdge)
+            .await
+            .unwrap();
+        assert_eq!(live.len(), 2);
+        assert!(live[1].is_keyframe);
+        assert_eq!(live[1].timestamp, 990);
+        assert_eq!(registry.list_subscribers(&key).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_audio_only_stream_ready_for_late_joiner() {
+        let registry = StreamRegistry::new();
+        let key = StreamKey::new("radio", "station");
+
+        registry.register_publisher(&key, 1).await.unwrap();
+        let audio_header = BroadcastFrame::audio(0, Bytes::from_static(&[0xAF, 0x00]), true);
+        registry.broadcast(&key, audio_header).await;
+        registry
+            .broadcast(
+                &key,
+                BroadcastFrame::audio(0, Bytes::from_static(&[0xAF, 0x01]), false),
+            )
+            .await;
+
+        // Video might still be on its way
+        let stats = registry.get_stream_stats(&key).await.unwrap();
+        assert_eq!(stats.kind, StreamKind::Unknown);
+        assert!(!stats.ready);
+
+        for ts in (23..=1058).step_by(23) {
+            let frame = BroadcastFrame::audio(ts, Bytes::from_static(&[0xAF, 0x01]), false);
+            registry.broadcast(&key, frame).await;
+        }
+        let stats = registry.get_stream_stats(&key).await.unwrap();
+        assert_eq!(stats.kind, StreamKind::AudioOnly);
+        assert!(stats.ready);
+
+        let (mut rx, catchup) = registry.subscribe(&key).await.unwrap();
+        assert_eq!(catchup.len(), 1);
+        assert_eq!(catchup[0].frame_type, FrameType::Audio);
+        assert!(catchup[0].is_header);
+
+        let frame = BroadcastFrame::audio(1081, Bytes::from_static(&[0xAF, 0x01]), false);
+        registry.broadcast(&key, frame).await;
+        assert_eq!(rx.recv().await.unwrap().timestamp, 1081);
+    }
+
+    #[tokio::test]
+    async fn test_catchup_waits_for_ready_stream() {
+        let registry = StreamRegistry::new();
+        let key = StreamKey::new("live", "test");
+
+        registry.register_publisher(&key, 1).await.unwrap();
+        let video_header = BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x00]), true, true);
+        registry.broadcast(&key, video_header).await;
+        for ts in [0, 33] {
+            let frame = BroadcastFrame::video(ts, Bytes::from_static(&[0x27, 0x01]), false, false);
+            registry.broadcast(&key, frame).await;
+        }
+
+        // P-frames without their keyframe would not decode
+        assert!(!registry.get_stream_stats(&key).await.unwrap().ready);
+        let (_rx, catchup) = registry.subscribe(&key).await.unwrap();
+        assert_eq!(catchup.len(), 1);
+        assert!(catchup[0].is_header);
+
+        let keyframe = BroadcastFrame::video(66, Bytes::from_static(&[0x17, 0x01]), true, false);
+        registry.broadcast(&key, keyframe).await;
+        let (_rx, catchup) = registry.subscribe(&key).await.unwrap();
+        assert_eq!(catchup.len(), 2);
+        assert!(catchup[1].is_keyframe);
+    }
+
+    #[tokio::test]
+    async fn test_catchup_without_gop_buffer() {
+        let config = RegistryConfig::default().gop_buffer_override("lowlatency", false);
+        let registry = StreamRegistry::with_config(config);
+        let buffered = StreamKey::new("live", "test");
+        let unbuffered = StreamKey::new("lowlatency", "test");
+
+        for key in [&buffered, &unbuffered] {
+            registry.register_publisher(key, 1).await.unwrap();
+            registry
+                .set_metadata(key, Bytes::from_static(b"onMetaData"))
+                .await;
+            let video_header =
+                BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x00]), true, true);
+            let audio_header = BroadcastFrame::audio(0, Bytes::from_static(&[0xAF, 0x00]), true);
+            registry.broadcast(key, video_header).await;
+            registry.broadcast(key, audio_header).await;
+            let keyframe =
+                BroadcastFrame::video(33, Bytes::from_static(&[0x17, 0x01]), true, false);
+            registry.broadcast(key, keyframe).await;
+            let inter = BroadcastFrame::video(66, Bytes::from_static(&[0x27, 0x01]), false, false);
+            registry.broadcast(key, inter).await;
+        }
+
+        let (_rx, catchup) = registry.subscribe(&buffered).await.unwrap();
+        assert_eq!(catchup.len(), 5);
+
+        // Metadata and sequence headers only
+        let (_rx, catchup) = registry.subscribe(&unbuffered).await.unwrap();
+        assert_eq!(catchup.len(), 3);
+        assert_eq!(catchup[0].frame_type, FrameType::Metadata);
+        assert!(catchup[1].is_header);
+        assert!(catchup[2].is_header);
+
+        let stats = registry.get_stream_stats(&unbuffered).await.unwrap();
+        assert_eq!(stats.gop_frame_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_capacity_bounds_lag() {
+        let config = RegistryConfig::default().broadcast_capacity(4);
+        let registry = StreamRegistry::with_config(config);
+        let key = StreamKey::new("live", "test");
+        registry.register_publisher(&key, 1).await.unwrap();
+
+        let (mut within, _) = registry.subscribe(&key).await.unwrap();
+        let (mut beyond, _) = registry.subscribe(&key).await.unwrap();
+
+        let audio = |ts| BroadcastFrame::audio(ts, Bytes::from_static(&[0xAF, 0x01]), false);
+        for ts in 0..4 {
+            registry.broadcast(&key, audio(ts)).await;
+        }
+
+        // Four frames behind still fits
+        for ts in 0..4 {
+            assert_eq!(within.recv().await.unwrap().timestamp, ts);
+        }
+
+        // Two more frames push the idle subscriber past capacity
+        for ts in 4..6 {
+            registry.broadcast(&key, audio(ts)).await;
+        }
+        assert!(matches!(
+            beyond.recv().await,
+            Err(broadcast::error::RecvError::Lagged(2))
+        ));
+        assert_eq!(beyond.recv().await.unwrap().timestamp, 2);
+        assert_eq!(within.recv().await.unwrap().timestamp, 4);
+    }
+
+    #[tokio::test]
+    async fn test_nano_offset_reaches_subscribers() {
+        let registry = StreamRegistry::new();
+        let key = StreamKey::new("live", "test_stream");
+
+        registry.register_publisher(&key, 1).await.unwrap();
+        let video_header = BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x00]), true, true);
+        registry.broadcast(&key, video_header).await;
+        let (mut rx, _) = registry.subscribe(&key).await.unwrap();
+
+        let keyframe = BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x01]), true, false);
+        registry.broadcast(&key, keyframe).await;
+
+        // Enhanced HEVC inter frame: ModEx(TimestampOffsetNano = 500000)
+        let data = Bytes::from_static(&[
+            0xA7, 0x02, 0x07, 0xA1, 0x20, 0x03, b'h', b'v', b'c', b'1', 0x02, 0x01,
+        ]);
+        let frame =
+            BroadcastFrame::video(33, data, false, false).with_timestamp_nano_offset(500_000);
+        registry.broadcast(&key, frame).await;
+
+        rx.recv().await.unwrap();
+        let received = rx.recv().await.unwrap();
+        assert_eq!(received.timestamp, 33);
+        assert_eq!(received.timestamp_nanos(), 33_500_000);
+
+        // Late joiners get the same value from the GOP buffer
+        let (_rx, catchup) = registry.subscribe(&key).await.unwrap();
+        assert_eq!(catchup.len(), 3);
+        assert_eq!(catchup[2].timestamp_nanos(), 33_500_000);
+    }
+
+    #[tokio::test]
+    async fn test_late_joiner_receives_metadata_first() {
+        let registry = StreamRegistry::new();
+        let key = StreamKey::new("live", "test_stream");
+
+        registry.register_publisher(&key, 1).await.unwrap();
+
+        let video_header = BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x00]), true, true);
+        let audio_header = BroadcastFrame::audio(0, Bytes::from_static(&[0xAF, 0x00]), true);
+        registry.broadcast(&key, video_header).await;
+        registry.broadcast(&key, audio_header).await;
+
+        // Metadata injected after the headers still leads the catchup
+        let meta = Bytes::from_static(&[0x02, 0x00, 0x0A]);
+        registry.set_metadata(&key, meta.clone()).await;
+
+        let (_rx, catchup) = registry.subscribe(&key).await.unwrap();
+
+        assert_eq!(catchup.len(), 3);
+        assert_eq!(catchup[0].frame_type, FrameType::Metadata);
+        assert_eq!(catchup[0].data, meta);
+        assert_eq!(catchup[1].frame_type, FrameType::Video);
+        assert_eq!(catchup[2].frame_type, FrameType::Audio);
+    }
+
+    #[tokio::test]
+    async fn test_list_subscribers() {
+        let registry = StreamRegistry::new();
+        let key = StreamKey::new("live", "test_stream");
+        registry.register_publisher(&key, 1).await.unwrap();
+        assert!(registry.list_subscribers(&key).await.is_empty());
+
+        let first: SocketAddr = "192.0.2.1:50000".parse().unwrap();
+        let second: SocketAddr = "192.0.2.2:50001".parse().unwrap();
+        let _rx2 = registry.subscribe_session(&key, 2, first).await.unwrap();
+        let _rx3 = registry.subscribe_session(&key, 3, second).await.unwrap();
+        // Anonymous subscribers such as recorders are counted, not listed
+        let _anonymous = registry.subscribe(&key).await.unwrap();
+        registry.record_session_dropped_frames(&key, 3, 5).await;
+
+        let subscribers = registry.list_subscribers(&key).await;
+        assert_eq!(subscribers.len(), 2);
+        assert_eq!(subscribers[0].session_id, 2);
+        assert_eq!(subscribers[0].peer_addr, first);
+        assert_eq!(subscribers[0].dropped_frames, 0);
+        assert_eq!(subscribers[1].session_id, 3);
+        assert_eq!(subscribers[1].peer_addr, second);
+        assert_eq!(subscribers[1].dropped_frames, 5);
+        assert!(subscribers[0].joined_at <= subscribers[1].joined_at);
+
+        let stats = registry.get_stream_stats(&key).await.unwrap();
+        assert_eq!(stats.subscriber_count, 3);
+        assert_eq!(stats.dropped_frames, 5);
+
+        registry.unsubscribe_session(&key, 2).await;
+        let subscribers = registry.list_subscribers(&key).await;
+        assert_eq!(subscribers.len(), 1);
+        assert_eq!(subscribers[0].session_id, 3);
+        assert_eq!(
+            registry
+                .get_stream_stats(&key)
+                .await
+                .unwrap()
+                .subscriber_count,
+            2
+        );
+
+        let unknown = StreamKey::new("live", "unknown");
+        assert!(registry.list_subscribers(&unknown).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_clear_metadata() {
+        let registry = StreamRegistry::new();
+        let key = StreamKey::new("live", "test_stream");
+
+        registry.register_publisher(&key, 1).await.unwrap();
+        registry
+            .set_metadata(&key, Bytes::from_static(&[0x02, 0x00, 0x0A]))
+            .await;
+        registry
+            .broadcast(
+                &key,
+                BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x00]), true, true),
+            )
+            .await;
+
+        let (mut rx, _) = registry.subscribe(&key).await.unwrap();
+        registry.clear_metadata(&key).await;
+
+        // Existing subscribers are told the data frame is gone
+        let frame = rx.recv().await.unwrap();
+        assert_eq!(frame.frame_type, FrameType::Metadata);
+        let values = crate::amf::amf0::decode_all(&frame.data).unwrap();
+        assert_eq!(values[0].as_str(), Some("onMetaData"));
+        assert_eq!(values[1].as_object().map(|o| o.len()), Some(0));
+
+        // Late joiners no longer receive metadata
+        let (_rx, catchup) = registry.subscribe(&key).await.unwrap();
+        assert_eq!(catchup.len(), 1);
+        assert_eq!(catchup[0].frame_type, FrameType::Video);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_data() {
+        use crate::amf::{amf0, AmfValue};
+
+        let registry = StreamRegistry::new();
+        let key = StreamKey::new("live", "test_stream");
+        let script = |name: &str| {
+            amf0::encode_all(&[
+                AmfValue::String(name.into()),
+                AmfValue::String("payload".into()),
+            ])
+        };
+
+        registry.register_publisher(&key, 1).await.unwrap();
+        let (mut rx, _) = registry.subscribe(&key).await.unwrap();
+
+        let cue = FlvTag::script(1500, script("onCuePoint"));
+        assert_eq!(cue.script_name(), Some("onCuePoint"));
+        registry.broadcast_data(&key, cue).await;
+        registry
+            .broadcast_data(&key, FlvTag::script(1600, script("onTextData")))
+            .await;
+        registry
+            .broadcast_data(&key, FlvTag::video(1700, Bytes::from_static(&[0x27])))
+            .await;
+
+        // Current subscribers receive both at their timestamps
+        let frame = rx.recv().await.unwrap();
+        assert_eq!(frame.frame_type, FrameType::Data);
+        assert_eq!(frame.timestamp, 1500);
+        assert_eq!(frame.data, script("onCuePoint"));
+        let frame = rx.recv().await.unwrap();
+        assert_eq!(frame.frame_type, FrameType::Data);
+        assert_eq!(frame.timestamp, 1600);
+        assert!(rx.try_recv().is_err());
+
+        // Late joiners get the latest cue point only
+        let (_rx, catchup) = registry.subscribe(&key).await.unwrap();
+        assert_eq!(catchup.len(), 1);
+        assert_eq!(catchup[0].timestamp, 1500);
+        assert_eq!(catchup[0].data, script("onCuePoint"));
+    }
+
+    #[tokio::test]
+    async fn test_media_broadcast_takes_shared_lock() {
+        let registry = StreamRegistry::new();
+        let key = StreamKey::new("live", "test_stream");
+
+        registry.register_publisher(&key, 1).await.unwrap();
+        let (mut rx, _) = registry.subscribe(&key).await.unwrap();
+
+        // Hold a read lock on the entry, as a concurrent stats query would
+        let streams = registry.shard(&key).read().await;
+        let _entry = streams.get(&key).unwrap().read().await;
+
+        let keyframe = BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x01]), true, false);
+        let inter = BroadcastFrame::video(33, Bytes::from_static(&[0x27, 0x01]), false, false);
+        let audio = BroadcastFrame::audio(33, Bytes::from_static(&[0xAF, 0x01]), false);
+
+        for frame in [keyframe, inter, audio] {
+            tokio::time::timeout(
+                std::time::Duration::from_secs(1),
+                registry.broadcast(&key, frame),
+            )
+            .await
+            .expect("media broadcast should not wait for exclusive access");
+        }
+
+        assert!(rx.recv().await.unwrap().is_keyframe);
+        assert_eq!(rx.recv().await.unwrap().timestamp, 33);
+        assert_eq!(rx.recv().await.unwrap().frame_type, FrameType::Audio);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_churn() {
+        let config = RegistryConfig::default()
+            .publisher_grace_period(std::time::Duration::ZERO)
+            .idle_stream_timeout(std::time::Duration::ZERO);
+        let registry = Arc::new(StreamRegistry::with_config(config));
+
+        let mut tasks = Vec::new();
+        for i in 0..4000u64 {
+            let registry = Arc::clone(&registry);
+            tasks.push(tokio::spawn(async move {
+                let key = StreamKey::new("live", format!("stream_{}", i % 500));
+
+                match i % 4 {
+                    0 => {
+                        if registry.register_publisher(&key, i).await.is_ok() {
+                            let frame = BroadcastFrame::video(
+                                0,
+                                Bytes::from_static(&[0x17, 0x01]),
+                                true,
+                                false,
+                            );
+                            registry.broadcast(&key, frame).await;
+                            registry.unregister_publisher(&key, i).await;
+                        }
+                    }
+                    1 => {
+                        if registry.subscribe(&key).await.is_ok() {
+                            registry.unsubscribe(&key).await;
+                        }
+                    }
+                    2 => {
+                        registry.get_stream_stats(&key).await;
+                    }
+                    _ => {
+                        registry.cleanup().await;
+                    }
+                }
+            }));
+        }
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        // Every publisher left and every subscriber unsubscribed
+        registry.cleanup().await;
+        assert_eq!(registry.stream_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_case_insensitive_keys() {
+        let config = RegistryConfig::default().case_insensitive_keys(true);
+        let registry = StreamRegistry::with_config(config);
+
+        let publish_key = StreamKey::new("Live", "Test");
+        let play_key = StreamKey::new("live/", "test");
+
+        registry.register_publisher(&publish_key, 1).await.unwrap();
+        let (mut rx, _) = registry.subscribe(&play_key).await.unwrap();
+
+        let frame = BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x01]), true, false);
+        registry.broadcast(&publish_key, frame).await;
+        assert!(rx.recv().await.unwrap().is_keyframe);
+
+        // A second publisher differing only in case is rejected
+        let result = registry
+            .register_publisher(&StreamKey::new("LIVE", "TEST"), 2)
+            .await;
+        assert!(matches!(
+            result,
+            Err(RegistryError::StreamAlreadyPublishing(_))
+        ));
+        assert_eq!(registry.stream_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_keys_case_sensitive_by_default() {
+        let registry = StreamRegistry::new();
+
+        registry
+            .register_publisher(&StreamKey::new("live", "Test"), 1)
+            .await
+            .unwrap();
+
+        let result = registry.subscribe(&StreamKey::new("live", "test")).await;
+        assert!(matches!(result, Err(RegistryError::StreamNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_total_gop_budget() {
+        let budget = 64 * 1024;
+        let registry =
+            StreamRegistry::with_config(RegistryConfig::default().total_gop_budget_bytes(budget));
+        let frame = |keyframe: bool| {
+            let mut data = vec![0x27; 4096];
+            if keyframe {
+                data[0] = 0x17;
+            }
+            BroadcastFrame::video(0, Bytes::from(data), keyframe, false)
+        };
+
+        // A stream whose publisher left keeps its GOP while there is room
+        let idle = StreamKey::new("live", "idle");
+        registry.register_publisher(&idle, 100).await.unwrap();
+        registry.broadcast(&idle, frame(true)).await;
+        registry.unregister_publisher(&idle, 100).await;
+        assert_eq!(registry.gop_memory_usage(), 4096);
+
+        let keys: Vec<_> = (0..50)
+            .map(|i| StreamKey::new("live", format!("stream{}", i)))
+            .collect();
+        for (session_id, key) in keys.iter().enumerate() {
+            registry
+                .register_publisher(key, session_id as u64)
+                .await
+                .unwrap();
+        }
+        for round in 0..4 {
+            for key in &keys {
+                registry.broadcast(key, frame(round == 0)).await;
+                assert!(registry.gop_memory_usage() <= budget);
+            }
+        }
+
+        // The idle stream gave up its GOP first
+        let stats = registry.get_stream_stats(&idle).await.unwrap();
+        assert_eq!(stats.gop_size_bytes, 0);
+
+        let mut buffered = 0;
+        for key in &keys {
+            buffered += registry.get_stream_stats(key).await.unwrap().gop_size_bytes;
+        }
+        assert!(buffered > 0);
+        assert_eq!(buffered, registry.gop_memory_usage());
+
+        // Removed streams give their memory back
+        for key in &keys {
+            assert!(registry.evict_stream(key).await);
+        }
+        assert_eq!(registry.gop_memory_usage(), 0);
+    }
 }
@@ -1,24 +1,58 @@
 //! Server configuration
 
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::time::Duration;
 
 use crate::media::fourcc::{AudioFourCc, VideoFourCc};
 use crate::protocol::constants::*;
 use crate::protocol::enhanced::{CapsEx, EnhancedRtmpMode, FourCcCapability};
+use crate::protocol::handshake::HandshakeVersionPolicy;
+use crate::protocol::quirks::QuirksConfig;
+use crate::session::stream::StreamMode;
+
+use super::policy::{AppPolicy, TokenValidator};
+use super::record::RecordConfig;
 
 /// Server configuration options
+///
+/// With the `serde` feature this can be loaded from a config file. Missing
+/// fields keep their defaults; durations are strings such as `"10s"`.
+/// `record`, `token_validator` and the advertised E-RTMP codec lists can
+/// only be set in code.
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
 pub struct ServerConfig {
     /// Address to bind to
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::serde_util::socket_addr")
+    )]
     pub bind_addr: SocketAddr,
 
     /// Maximum concurrent connections (0 = unlimited)
     pub max_connections: usize,
 
+    /// Maximum concurrent live streams (0 = unlimited)
+    ///
+    /// Publishes beyond the limit are rejected. Streams in their
+    /// publisher grace period still count; a publisher reclaiming its own
+    /// stream is always let in.
+    pub max_streams: usize,
+
     /// Chunk size to negotiate with clients
     pub chunk_size: u32,
 
+    /// Largest RTMP message a client may declare, in bytes
+    ///
+    /// Sessions declaring a longer message are closed with a protocol
+    /// error before the payload is buffered.
+    pub max_message_size: u32,
+
     /// Window acknowledgement size
     pub window_ack_size: u32,
 
@@ -26,11 +60,26 @@ pub struct ServerConfig {
     pub peer_bandwidth: u32,
 
     /// Connection timeout (handshake must complete within this time)
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::serde_util::duration")
+    )]
     pub connection_timeout: Duration,
 
     /// Idle timeout (disconnect if no data received)
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::serde_util::duration")
+    )]
     pub idle_timeout: Duration,
 
+    /// How long graceful shutdown waits for drained sessions to close
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::serde_util::duration")
+    )]
+    pub shutdown_timeout: Duration,
+
     /// Enable TCP_NODELAY (disable Nagle's algorithm)
     pub tcp_nodelay: bool,
 
@@ -40,32 +89,135 @@ pub struct ServerConfig {
     /// TCP send buffer size (0 = OS default)
     pub tcp_send_buffer: usize,
 
+    /// Expect a PROXY protocol (v1 or v2) header before the handshake
+    ///
+    /// For servers behind a TCP load balancer: the session's `peer_addr`
+    /// becomes the client named in the header. Connections without a
+    /// valid header are closed.
+    pub expect_proxy_protocol: bool,
+
     /// Application-level read buffer size
     pub read_buffer_size: usize,
 
     /// Application-level write buffer size
     pub write_buffer_size: usize,
 
+    /// Idle session buffers kept for reuse by new connections (0 = no
+    /// pooling)
+    pub buffer_pool_size: usize,
+
+    /// How long subscriber media writes may wait to be batched before
+    /// they are flushed (zero = flush after every message)
+    ///
+    /// Keyframes and large frames are flushed at once regardless.
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::serde_util::duration")
+    )]
+    pub write_flush_deadline: Duration,
+
     /// Enable GOP buffering for late-joiner support
     pub gop_buffer_enabled: bool,
 
+    /// Per-app overrides of `gop_buffer_enabled`, keyed by app name
+    ///
+    /// Lets ultra-low-latency apps skip GOP buffering while others keep it.
+    pub gop_buffer_overrides: HashMap<String, bool>,
+
     /// Maximum GOP buffer size in bytes
     pub gop_buffer_max_size: usize,
 
     /// Stats update interval
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::serde_util::duration")
+    )]
     pub stats_interval: Duration,
 
+    /// Report published streams whose audio and video timestamps drift
+    /// further apart than this (None = no A/V sync checks)
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::serde_util::option_duration")
+    )]
+    pub av_desync_threshold: Option<Duration>,
+
+    /// What to do with AAC/AVC frames that arrive before their sequence
+    /// header (None = pass them through unchecked)
+    pub sequence_header_policy: Option<SequenceHeaderPolicy>,
+
     /// Enhanced RTMP mode (Auto, LegacyOnly, or EnhancedOnly)
     pub enhanced_rtmp: EnhancedRtmpMode,
 
     /// Enhanced RTMP server capabilities to advertise
     pub enhanced_capabilities: EnhancedServerCapabilities,
+
+    /// Record published streams to FLV (None = no recording)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub record: Option<RecordConfig>,
+
+    /// Publish and play access per app, checked before the handler
+    ///
+    /// See [`policy`](super::policy) for where tokens come from.
+    pub app_policies: HashMap<String, AppPolicy>,
+
+    /// Checks tokens for apps whose policy requires one (None = such
+    /// requests are always rejected)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub token_validator: Option<TokenValidator>,
+
+    /// Encoder compatibility settings
+    pub quirks: QuirksConfig,
+
+    /// C0 version bytes accepted from clients
+    pub handshake_version_policy: HandshakeVersionPolicy,
+
+    /// Emit debug spans around the handshake, each command and each media
+    /// message
+    pub protocol_tracing: bool,
+}
+
+/// Handling of media frames that arrive before their sequence header
+///
+/// Only legacy AAC audio and AVC video are checked; other codecs carry no
+/// sequence header or are self-describing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum SequenceHeaderPolicy {
+    /// Hold frames back and deliver them right after the header
+    ///
+    /// Video is held from a keyframe on. The header is stamped with the
+    /// first held frame's timestamp, so replayed timestamps never go
+    /// backwards. At most [`MAX_HELD_FRAMES`](Self::MAX_HELD_FRAMES) and
+    /// [`MAX_HELD_BYTES`](Self::MAX_HELD_BYTES) per track are kept; past
+    /// either, audio drops its oldest frames and video waits for the next
+    /// keyframe.
+    Buffer,
+    /// Drop frames until the header arrives
+    Drop,
+}
+
+impl SequenceHeaderPolicy {
+    /// Frames held per track under [`Buffer`](Self::Buffer)
+    pub const MAX_HELD_FRAMES: usize = 256;
+
+    /// Payload bytes held per track under [`Buffer`](Self::Buffer)
+    pub const MAX_HELD_BYTES: usize = 4 * 1024 * 1024;
 }
 
 /// Server-side Enhanced RTMP capabilities.
 ///
 /// Configure which E-RTMP features and codecs the server supports.
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
 pub struct EnhancedServerCapabilities {
     /// Support for NetConnection.Connect.ReconnectRequest
     pub reconnect: bool,
@@ -76,10 +228,15 @@ pub struct EnhancedServerCapabilities {
     /// Support for ModEx signal parsing (nanosecond timestamps, etc.)
     pub modex: bool,
 
+    /// Support for nanosecond timestamp offsets (carried in ModEx signals)
+    pub timestamp_nano_offset: bool,
+
     /// Video codecs supported with their capabilities
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub video_codecs: Vec<(VideoFourCc, FourCcCapability)>,
 
     /// Audio codecs supported with their capabilities
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub audio_codecs: Vec<(AudioFourCc, FourCcCapability)>,
 }
 
@@ -89,6 +246,7 @@ impl Default for EnhancedServerCapabilities {
             reconnect: false,
             multitrack: false,
             modex: true, // Parse ModEx but don't require it
+            timestamp_nano_offset: false,
             video_codecs: vec![
                 (VideoFourCc::Avc, FourCcCapability::forward()),
                 (VideoFourCc::Hevc, FourCcCapability::forward()),
@@ -110,6 +268,7 @@ impl EnhancedServerCapabilities {
             reconnect: false,
             multitrack: false,
             modex: true,
+            timestamp_nano_offset: false,
             video_codecs: vec![],
             audio_codecs: vec![],
         }
@@ -139,6 +298,12 @@ impl EnhancedServerCapabilities {
         self
     }
 
+    /// Enable nanosecond timestamp offset support.
+    pub fn with_timestamp_nano_offset(mut self) -> Self {
+        self.timestamp_nano_offset = true;
+        self
+    }
+
     /// Convert to CapsEx bitmask for protocol encoding.
     pub fn to_caps_ex(&self) -> CapsEx {
         let mut caps = CapsEx::empty();
@@ -151,6 +316,9 @@ impl EnhancedServerCapabilities {
         if self.modex {
             caps.insert(CapsEx::MODEX);
         }
+        if self.timestamp_nano_offset {
+            caps.insert(CapsEx::TIMESTAMP_NANO_OFFSET);
+        }
         caps
     }
 
@@ -161,8 +329,8 @@ impl EnhancedServerCapabilities {
         let mut caps = EnhancedCapabilities {
             enabled: true,
             caps_ex: self.to_caps_ex(),
-            video_codecs: std::collections::HashMap::new(),
-            audio_codecs: std::collections::HashMap::new(),
+            video_codecs: HashMap::new(),
+            audio_codecs: HashMap::new(),
             video_function: crate::protocol::enhanced::VideoFunctionFlags::empty(),
         };
 
@@ -183,21 +351,36 @@ impl Default for ServerConfig {
         Self {
             bind_addr: "0.0.0.0:1935".parse().unwrap(),
             max_connections: 0, // Unlimited
+            max_streams: 0,     // Unlimited
             chunk_size: RECOMMENDED_CHUNK_SIZE,
+            max_message_size: MAX_MESSAGE_SIZE,
             window_ack_size: DEFAULT_WINDOW_ACK_SIZE,
             peer_bandwidth: DEFAULT_PEER_BANDWIDTH,
             connection_timeout: Duration::from_secs(10),
             idle_timeout: Duration::from_secs(60),
+            shutdown_timeout: Duration::from_secs(5),
             tcp_nodelay: true, // Important for low latency
             tcp_recv_buffer: 0,
             tcp_send_buffer: 0,
+            expect_proxy_protocol: false,
             read_buffer_size: 64 * 1024, // 64KB
             write_buffer_size: 64 * 1024,
+            buffer_pool_size: 64,
+            write_flush_deadline: Duration::ZERO,
             gop_buffer_enabled: true,
+            gop_buffer_overrides: HashMap::new(),
             gop_buffer_max_size: 4 * 1024 * 1024, // 4MB
             stats_interval: Duration::from_secs(5),
+            av_desync_threshold: None,
+            sequence_header_policy: None,
             enhanced_rtmp: EnhancedRtmpMode::Auto,
             enhanced_capabilities: EnhancedServerCapabilities::default(),
+            record: None,
+            app_policies: HashMap::new(),
+            token_validator: None,
+            quirks: QuirksConfig::default(),
+            handshake_version_policy: HandshakeVersionPolicy::default(),
+            protocol_tracing: false,
         }
     }
 }
@@ -223,30 +406,119 @@ impl ServerConfig {
         self
     }
 
+    /// Set maximum live streams
+    pub fn max_streams(mut self, max: usize) -> Self {
+        self.max_streams = max;
+        self
+    }
+
     /// Set chunk size
     pub fn chunk_size(mut self, size: u32) -> Self {
         self.chunk_size = size.min(MAX_CHUNK_SIZE);
         self
     }
 
+    /// Set the largest message size accepted from clients
+    pub fn max_message_size(mut self, size: u32) -> Self {
+        self.max_message_size = size;
+        self
+    }
+
+    /// Set window acknowledgement size sent on connect
+    pub fn window_ack_size(mut self, size: u32) -> Self {
+        self.window_ack_size = size;
+        self
+    }
+
+    /// Set peer bandwidth sent on connect
+    pub fn peer_bandwidth(mut self, size: u32) -> Self {
+        self.peer_bandwidth = size;
+        self
+    }
+
+    /// Set how long subscriber media writes may wait to be batched
+    ///
+    /// Instead of flushing after every message, media sent to subscribers
+    /// is buffered (up to `write_buffer_size`) and flushed once `deadline`
+    /// has passed since the first unflushed write, or immediately when a
+    /// keyframe or large frame arrives. Trades a few milliseconds of
+    /// latency for fewer, larger TCP writes on busy servers. Zero (the
+    /// default) flushes after every message.
+    pub fn write_flush_deadline(mut self, deadline: Duration) -> Self {
+        self.write_flush_deadline = deadline;
+        self
+    }
+
+    /// Report A/V sync drift beyond `threshold`
+    ///
+    /// Publishers whose latest audio and video timestamps drift further
+    /// apart trigger [`RtmpHandler::on_av_desync`](crate::RtmpHandler::on_av_desync)
+    /// once per excursion.
+    pub fn av_desync_threshold(mut self, threshold: Duration) -> Self {
+        self.av_desync_threshold = Some(threshold);
+        self
+    }
+
+    /// Check that AAC/AVC frames follow their sequence header
+    ///
+    /// Frames that arrive first are held back or dropped per `policy`, and
+    /// [`RtmpHandler::on_frame_before_header`](crate::RtmpHandler::on_frame_before_header)
+    /// is told, so decoders are never fed frames they cannot decode.
+    pub fn sequence_header_policy(mut self, policy: SequenceHeaderPolicy) -> Self {
+        self.sequence_header_policy = Some(policy);
+        self
+    }
+
     /// Disable GOP buffering
     pub fn disable_gop_buffer(mut self) -> Self {
         self.gop_buffer_enabled = false;
         self
     }
 
+    /// Enable or disable GOP buffering for a single app
+    ///
+    /// Overrides `gop_buffer_enabled` for streams published to `app`.
+    pub fn gop_buffer_override(mut self, app: impl Into<String>, enabled: bool) -> Self {
+        self.gop_buffer_overrides.insert(app.into(), enabled);
+        self
+    }
+
+    /// Expect a PROXY protocol header on every connection
+    ///
+    /// Only enable behind a load balancer that sends one; clients could
+    /// otherwise claim any address.
+    pub fn expect_proxy_protocol(mut self, enabled: bool) -> Self {
+        self.expect_proxy_protocol = enabled;
+        self
+    }
+
     /// Set connection timeout
     pub fn connection_timeout(mut self, timeout: Duration) -> Self {
         self.connection_timeout = timeout;
         self
     }
 
+    /// Set how many idle session buffers are kept for reuse
+    ///
+    /// Each session holds a read and a write buffer; they return to the
+    /// pool on disconnect. 0 disables pooling.
+    pub fn buffer_pool_size(mut self, size: usize) -> Self {
+        self.buffer_pool_size = size;
+        self
+    }
+
     /// Set idle timeout
     pub fn idle_timeout(mut self, timeout: Duration) -> Self {
         self.idle_timeout = timeout;
         self
     }
 
+    /// Set how long graceful shutdown waits for sessions to close
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
     /// Set Enhanced RTMP mode.
     ///
     /// - `Auto`: Negotiate E-RTMP if client supports it (default)
@@ -262,11 +534,62 @@ impl ServerConfig {
         self.enhanced_capabilities = caps;
         self
     }
+
+    /// Record published streams to FLV files
+    ///
+    /// See [`RecordConfig`] for file layout and segmenting.
+    pub fn record(mut self, config: RecordConfig) -> Self {
+        self.record = Some(config);
+        self
+    }
+
+    /// Set the publish and play access of an app
+    pub fn app_policy(mut self, app: impl Into<String>, policy: AppPolicy) -> Self {
+        self.app_policies.insert(app.into(), policy);
+        self
+    }
+
+    /// Set the function that checks tokens required by app policies
+    ///
+    /// Called with the mode, app, stream name and token.
+    pub fn token_validator(
+        mut self,
+        f: impl Fn(StreamMode, &str, &str, &str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.token_validator = Some(TokenValidator::new(f));
+        self
+    }
+
+    /// Set encoder compatibility settings
+    pub fn quirks(mut self, quirks: QuirksConfig) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Set which C0 version bytes clients may send
+    ///
+    /// Defaults to [`HandshakeVersionPolicy::AtLeast3`].
+    pub fn handshake_version_policy(mut self, policy: HandshakeVersionPolicy) -> Self {
+        self.handshake_version_policy = policy;
+        self
+    }
+
+    /// Trace the protocol in detail
+    ///
+    /// Sessions open a `debug` span for the handshake, for each command
+    /// (`name`, `transaction_id`, `csid`, `stream_id`) and for each audio
+    /// or video message (`kind`, `csid`, `timestamp`, `stream_id`, `len`).
+    /// Off by default; sessions then create no spans at all.
+    pub fn protocol_tracing(mut self, enabled: bool) -> Self {
+        self.protocol_tracing = enabled;
+        self
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::server::policy::Access;
 
     #[test]
     fn test_default_config() {
@@ -275,11 +598,17 @@ mod tests {
         assert_eq!(config.bind_addr.port(), 1935);
         assert_eq!(config.max_connections, 0);
         assert_eq!(config.chunk_size, RECOMMENDED_CHUNK_SIZE);
+        assert_eq!(config.max_message_size, MAX_MESSAGE_SIZE);
+        assert_eq!(
+            config.handshake_version_policy,
+            HandshakeVersionPolicy::AtLeast3
+        );
         assert_eq!(config.window_ack_size, DEFAULT_WINDOW_ACK_SIZE);
         assert_eq!(config.peer_bandwidth, DEFAULT_PEER_BANDWIDTH);
         assert!(config.tcp_nodelay);
         assert!(config.gop_buffer_enabled);
         assert_eq!(config.enhanced_rtmp, EnhancedRtmpMode::Auto);
+        assert!(config.record.is_none());
     }
 
     #[test]
@@ -305,6 +634,14 @@ mod tests {
         assert_eq!(config.max_connections, 100);
     }
 
+    #[test]
+    fn test_builder_max_streams() {
+        let config = ServerConfig::default().max_streams(50);
+
+        assert_eq!(config.max_streams, 50);
+        assert_eq!(ServerConfig::default().max_streams, 0);
+    }
+
     #[test]
     fn test_builder_chunk_size() {
         let config = ServerConfig::default().chunk_size(8192);
@@ -312,6 +649,13 @@ mod tests {
         assert_eq!(config.chunk_size, 8192);
     }
 
+    #[test]
+    fn test_builder_max_message_size() {
+        let config = ServerConfig::default().max_message_size(1024 * 1024);
+
+        assert_eq!(config.max_message_size, 1024 * 1024);
+    }
+
     #[test]
     fn test_builder_chunk_size_capped() {
         // Chunk size should be capped at MAX_CHUNK_SIZE
@@ -320,6 +664,16 @@ mod tests {
         assert_eq!(config.chunk_size, MAX_CHUNK_SIZE);
     }
 
+    #[test]
+    fn test_builder_window_ack_and_peer_bandwidth() {
+        let config = ServerConfig::default()
+            .window_ack_size(5_000_000)
+            .peer_bandwidth(6_000_000);
+
+        assert_eq!(config.window_ack_size, 5_000_000);
+        assert_eq!(config.peer_bandwidth, 6_000_000);
+    }
+
     #[test]
     fn test_builder_disable_gop_buffer() {
         let config = ServerConfig::default().disable_gop_buffer();
@@ -327,6 +681,28 @@ mod tests {
         assert!(!config.gop_buffer_enabled);
     }
 
+    #[test]
+    fn test_builder_gop_buffer_override() {
+        let config = ServerConfig::default().gop_buffer_override("lowlatency", false);
+
+        assert!(config.gop_buffer_enabled);
+        assert_eq!(config.gop_buffer_overrides.get("lowlatency"), Some(&false));
+    }
+
+    #[test]
+    fn test_builder_app_policy() {
+        let policy = AppPolicy::new(Access::Open, Access::TokenRequired);
+        let config = ServerConfig::default()
+            .app_policy("live", policy)
+            .token_validator(|_, _, _, token| token == "secret");
+
+        assert_eq!(config.app_policies.get("live"), Some(&policy));
+        let validator = config.token_validator.unwrap();
+        assert!(validator.validate(StreamMode::Playing, "live", "cam", "secret"));
+        assert!(!validator.validate(StreamMode::Playing, "live", "cam", "other"));
+        assert!(ServerConfig::default().app_policies.is_empty());
+    }
+
     #[test]
     fn test_builder_connection_timeout() {
         let config = ServerConfig::default().connection_timeout(Duration::from_secs(30));
@@ -341,6 +717,13 @@ mod tests {
         assert_eq!(config.idle_timeout, Duration::from_secs(120));
     }
 
+    #[test]
+    fn test_builder_shutdown_timeout() {
+        let config = ServerConfig::default().shutdown_timeout(Duration::from_secs(1));
+
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(1));
+    }
+
     #[test]
     fn test_builder_chaining() {
         let addr: SocketAddr = "127.0.0.1:1935".parse().unwrap();
@@ -443,12 +826,15 @@ mod tests {
         assert!(!caps_ex.supports_reconnect());
         assert!(!caps_ex.supports_multitrack());
         assert!(caps_ex.supports_modex());
+        assert!(!caps_ex.supports_timestamp_nano_offset());
 
         let caps_full = EnhancedServerCapabilities::default()
             .with_reconnect()
-            .with_multitrack();
+            .with_multitrack()
+            .with_timestamp_nano_offset();
         let caps_ex = caps_full.to_caps_ex();
 
+        assert!(caps_ex.supports_timestamp_nano_offset());
         assert!(caps_ex.supports_reconnect());
         assert!(caps_ex.supports_multitrack());
         assert!(caps_ex.supports_modex());
@@ -466,4 +852,68 @@ mod tests {
         assert_eq!(config.enhanced_rtmp, EnhancedRtmpMode::Auto);
         assert_eq!(config.enhanced_capabilities.video_codecs.len(), 1);
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_server_config() {
+        let config: ServerConfig = serde_json::from_str(
+            r#"{
+                "bind_addr": "127.0.0.1:1936",
+                "max_connections": 500,
+                "connection_timeout": "5s",
+                "idle_timeout": "1.5m",
+                "write_flush_deadline": "20ms",
+                "sequence_header_policy": "drop",
+                "gop_buffer_overrides": { "lowlatency": false },
+                "app_policies": { "live": { "play": "token_required" } },
+                "enhanced_rtmp": "legacy_only",
+                "handshake_version_policy": "at_least_3",
+                "enhanced_capabilities": { "reconnect": true },
+                "quirks": { "allow_empty_app": false, "max_timestamp_delta": 5000 }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.bind_addr, "127.0.0.1:1936".parse().unwrap());
+        assert_eq!(config.max_connections, 500);
+        assert_eq!(config.connection_timeout, Duration::from_secs(5));
+        assert_eq!(config.idle_timeout, Duration::from_secs(90));
+        assert_eq!(config.write_flush_deadline, Duration::from_millis(20));
+        assert_eq!(
+            config.sequence_header_policy,
+            Some(SequenceHeaderPolicy::Drop)
+        );
+        assert_eq!(config.gop_buffer_overrides.get("lowlatency"), Some(&false));
+        assert_eq!(
+            config.app_policies.get("live"),
+            Some(&AppPolicy::new(Access::Open, Access::TokenRequired))
+        );
+        assert_eq!(config.enhanced_rtmp, EnhancedRtmpMode::LegacyOnly);
+        assert_eq!(
+            config.handshake_version_policy,
+            HandshakeVersionPolicy::AtLeast3
+        );
+        assert!(config.enhanced_capabilities.reconnect);
+        assert!(!config.quirks.allow_empty_app);
+        assert_eq!(config.quirks.max_timestamp_delta, Some(5000));
+
+        // Everything else keeps its default, including fields that cannot
+        // be deserialized
+        let defaults = ServerConfig::default();
+        assert_eq!(config.chunk_size, defaults.chunk_size);
+        assert_eq!(config.stats_interval, defaults.stats_interval);
+        assert_eq!(
+            config.enhanced_capabilities.video_codecs.len(),
+            defaults.enhanced_capabilities.video_codecs.len()
+        );
+        assert!(config.record.is_none());
+
+        // A bare port listens on all interfaces
+        let config: ServerConfig = serde_json::from_str(r#"{ "bind_addr": 1937 }"#).unwrap();
+        assert_eq!(config.bind_addr, "0.0.0.0:1937".parse().unwrap());
+
+        // Typos and malformed durations are errors, not silent defaults
+        assert!(serde_json::from_str::<ServerConfig>(r#"{ "idle_timout": "5s" }"#).is_err());
+        assert!(serde_json::from_str::<ServerConfig>(r#"{ "idle_timeout": "5 days" }"#).is_err());
+    }
 }
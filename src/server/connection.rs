@@ -8,24 +8,31 @@
 //! 5. Disconnect
 
 use std::collections::HashMap;
+use std::future::Future;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
 
-use bytes::{Bytes, BytesMut};
+use bytes::{Buf, Bytes, BytesMut};
 use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
 use tokio::net::TcpStream;
-use tokio::sync::broadcast;
-use tokio::time::timeout;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::time::{sleep_until, timeout, Instant};
+use tracing::Instrument;
 
-use crate::registry::{BroadcastFrame, FrameType, StreamKey, StreamRegistry};
+use crate::registry::frame::{is_audio_header, video_flags};
+use crate::registry::{BroadcastFrame, FrameType, RegistryError, StreamKey, StreamRegistry};
 
-use crate::amf::AmfValue;
+use crate::amf::{AmfObject, AmfValue};
 use crate::error::{Error, ProtocolError, Result};
 use crate::media::enhanced_audio::EnhancedAudioData;
 use crate::media::enhanced_video::EnhancedVideoData;
 use crate::media::flv::FlvTag;
 use crate::media::fourcc::{AudioFourCc, VideoFourCc};
-use crate::media::{AacData, H264Data};
+use crate::media::metadata::encode_on_metadata;
+use crate::media::modex;
+use crate::media::{AacData, AudioFrame, H264Data, VideoFrame};
 use crate::protocol::chunk::{ChunkDecoder, ChunkEncoder, RtmpChunk};
 use crate::protocol::constants::*;
 use crate::protocol::enhanced::EnhancedRtmpMode;
@@ -34,11 +41,24 @@ use crate::protocol::message::{
     Command, ConnectParams, ConnectResponseBuilder, DataMessage, PlayParams, PublishParams,
     RtmpMessage, UserControlEvent,
 };
-use crate::protocol::quirks::EncoderType;
-use crate::server::config::ServerConfig;
-use crate::server::handler::{AuthResult, MediaDeliveryMode, RtmpHandler};
-use crate::session::context::{SessionContext, StreamContext};
+use crate::protocol::proxy::parse_proxy_header;
+use crate::protocol::quirks::{CommandSequence, EncoderType, TimestampDeltaClamp};
+use crate::server::config::{SequenceHeaderPolicy, ServerConfig};
+use crate::server::handler::{
+    AuthResult, ByteDirection, DisconnectReason, MediaDeliveryMode, RtmpHandler,
+};
+use crate::server::pacer::Pacer;
+use crate::server::policy;
+use crate::server::pool::BufferPool;
+use crate::server::record::Recorder;
+use crate::session::context::{SessionContext, SessionControl, StreamContext};
 use crate::session::state::SessionState;
+use crate::session::stream::{HeldFrames, StreamMode};
+use crate::transport::Transport;
+
+/// How long dropped frames may wait before they are added to the stream's
+/// counts in the registry
+const DROP_REPORT_INTERVAL: Duration = Duration::from_secs(1);
 
 /// Detected codec for logging purposes.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -107,17 +127,104 @@ enum SubscriberState {
     SkippingToKeyframe,
 }
 
+/// A publish on one message stream
+struct Publishing {
+    /// Stream key in the registry
+    key: StreamKey,
+
+    /// Recorder for the stream (if recording is configured)
+    recorder: Option<Recorder>,
+
+    /// Eviction signal of the stream
+    eviction: Option<watch::Receiver<bool>>,
+
+    /// Takeover signal of the stream
+    takeover: Option<watch::Receiver<Option<u64>>>,
+
+    /// Clamps for oversized audio/video timestamp deltas (if configured)
+    audio_ts_clamp: Option<TimestampDeltaClamp>,
+    video_ts_clamp: Option<TimestampDeltaClamp>,
+}
+
+/// Playback of a registry stream on one message stream
+struct Playback {
+    /// Stream key in the registry
+    key: StreamKey,
+
+    /// Eviction signal of the stream
+    eviction: Option<watch::Receiver<bool>>,
+
+    /// Changes when the stream's publisher stops cleanly
+    unpublished: Option<watch::Receiver<u64>>,
+
+    /// Subscriber state for backpressure handling
+    subscriber_state: SubscriberState,
+
+    /// Count of consecutive lag events (for disconnection threshold)
+    consecutive_lag_count: u32,
+
+    /// Whether the player paused
+    is_paused: bool,
+
+    /// Frames dropped while paused (for logging)
+    frames_dropped_while_paused: u64,
+
+    /// Dropped frames not yet added to the stream's counts
+    unreported_drops: u64,
+
+    /// Skip audio until keyframe (set on unpause, cleared on keyframe)
+    /// This prevents the jarring experience of audio playing while video is frozen
+    skip_audio_until_keyframe: bool,
+
+    /// Bitrate the subscription is paced to, if any
+    pace_bitrate: Option<u64>,
+
+    /// Send-time scheduler (pacing only)
+    pacer: Option<Pacer>,
+
+    /// Frame waiting for its pacing send time; nothing more is received
+    /// for the playback until it is sent
+    paced: Option<(Instant, BroadcastFrame)>,
+}
+
+impl Playback {
+    fn new(key: StreamKey, pace_bitrate: Option<u64>) -> Self {
+        Self {
+            key,
+            eviction: None,
+            unpublished: None,
+            subscriber_state: SubscriberState::Normal,
+            consecutive_lag_count: 0,
+            is_paused: false,
+            frames_dropped_while_paused: 0,
+            unreported_drops: 0,
+            skip_audio_until_keyframe: false,
+            pace_bitrate,
+            pacer: pace_bitrate.map(Pacer::new),
+            paced: None,
+        }
+    }
+
+    /// Frames received but not yet sent (a frame held back for pacing)
+    fn held_frames(&self) -> u64 {
+        self.paced.is_some() as u64
+    }
+}
+
 /// Per-connection handler
-pub struct Connection<H: RtmpHandler> {
+///
+/// Runs over a `TcpStream` by default; any [`Transport`] works, such as
+/// [`DuplexTransport`](crate::transport::DuplexTransport) for in-process tests.
+pub struct Connection<H: RtmpHandler, S: Transport = TcpStream> {
     /// Session state
     state: SessionState,
 
     /// Session context for callbacks
     context: SessionContext,
 
-    /// TCP stream (buffered)
-    reader: BufReader<tokio::io::ReadHalf<TcpStream>>,
-    writer: BufWriter<tokio::io::WriteHalf<TcpStream>>,
+    /// Transport stream (buffered)
+    reader: BufReader<tokio::io::ReadHalf<S>>,
+    writer: BufWriter<tokio::io::WriteHalf<S>>,
 
     /// Read buffer
     read_buf: BytesMut,
@@ -141,11 +248,14 @@ pub struct Connection<H: RtmpHandler> {
     /// Pending FC commands (stream key -> transaction ID)
     pending_fc: HashMap<String, f64>,
 
-    /// Stream key we are publishing to (if any)
-    publishing_to: Option<StreamKey>,
+    /// Streams we publish, by message stream ID
+    publishing_to: HashMap<u32, Publishing>,
+
+    /// Streams we play, by message stream ID
+    subscribed_to: HashMap<u32, Playback>,
 
-    /// Stream key we are subscribed to (if any)
-    subscribed_to: Option<StreamKey>,
+    /// Command order, checked for out-of-sequence commands
+    commands: CommandSequence,
 
     last_audio_ts: Option<u32>,
 
@@ -157,187 +267,440 @@ pub struct Connection<H: RtmpHandler> {
     /// Detected audio codec
     detected_audio_codec: Option<DetectedCodec>,
 
-    /// Broadcast receiver for subscriber mode
-    frame_rx: Option<broadcast::Receiver<BroadcastFrame>>,
+    /// Broadcast receivers of the streams we play, by message stream ID
+    frame_rx: HashMap<u32, broadcast::Receiver<BroadcastFrame>>,
 
-    /// Subscriber state for backpressure handling
-    subscriber_state: SubscriberState,
+    /// When unreported dropped frames are added to the streams' counts
+    drop_report_at: Option<Instant>,
 
-    /// Count of consecutive lag events (for disconnection threshold)
-    consecutive_lag_count: u32,
+    /// Seek waiting for the frame receiver (stream ID, position in ms)
+    pending_seek: Option<(u32, u32)>,
 
-    /// RTMP stream ID we're using for playback (subscriber mode)
-    playback_stream_id: Option<u32>,
+    /// Reason for a clean exit from the main loop (errors carry their own)
+    disconnect_reason: Option<DisconnectReason>,
 
-    /// Whether the subscriber is paused
-    is_paused: bool,
+    /// When buffered media must be flushed (write coalescing only)
+    flush_deadline: Option<Instant>,
 
-    /// Frames dropped while paused (for logging)
-    frames_dropped_while_paused: u64,
+    /// Requests from the handler or server (taken by the main loop)
+    control_rx: Option<mpsc::UnboundedReceiver<SessionControl>>,
 
-    /// Skip audio until keyframe (set on unpause, cleared on keyframe)
-    /// This prevents the jarring experience of audio playing while video is frozen
-    skip_audio_until_keyframe: bool,
+    /// Pool the read and write buffers return to on drop
+    buffer_pool: Option<Arc<BufferPool>>,
 }
 
-impl<H: RtmpHandler> Connection<H> {
+impl<H: RtmpHandler, S: Transport> Connection<H, S> {
     /// Create a new connection handler
     pub fn new(
         session_id: u64,
-        socket: TcpStream,
+        socket: S,
+        peer_addr: SocketAddr,
+        config: ServerConfig,
+        handler: Arc<H>,
+        registry: Arc<StreamRegistry>,
+    ) -> Self {
+        Self::with_buffer_pool(
+            session_id, socket, peer_addr, config, handler, registry, None,
+        )
+    }
+
+    /// Create a connection whose buffers come from (and return to) `pool`
+    pub(crate) fn with_buffer_pool(
+        session_id: u64,
+        socket: S,
         peer_addr: SocketAddr,
         config: ServerConfig,
         handler: Arc<H>,
         registry: Arc<StreamRegistry>,
+        buffer_pool: Option<Arc<BufferPool>>,
     ) -> Self {
         let (read_half, write_half) = tokio::io::split(socket);
+        let (control_tx, control_rx) = mpsc::unbounded_channel();
+        let mut context = SessionContext::new(session_id, peer_addr);
+        context.control = Some(control_tx);
+        let mut chunk_decoder = ChunkDecoder::new();
+        chunk_decoder.set_max_message_size(config.max_message_size);
+        let take_buffer = |capacity| match buffer_pool {
+            Some(ref pool) => pool.get(capacity),
+            None => BytesMut::with_capacity(capacity),
+        };
+        let read_buf = take_buffer(config.read_buffer_size);
+        let write_buf = take_buffer(config.write_buffer_size);
 
         Self {
             state: SessionState::new(session_id, peer_addr),
-            context: SessionContext::new(session_id, peer_addr),
+            context,
             reader: BufReader::with_capacity(config.read_buffer_size, read_half),
             writer: BufWriter::with_capacity(config.write_buffer_size, write_half),
-            read_buf: BytesMut::with_capacity(config.read_buffer_size),
-            chunk_decoder: ChunkDecoder::new(),
+            read_buf,
+            chunk_decoder,
             chunk_encoder: ChunkEncoder::new(),
-            write_buf: BytesMut::with_capacity(config.write_buffer_size),
+            write_buf,
             config,
             handler,
             registry,
             pending_fc: HashMap::new(),
-            publishing_to: None,
-            subscribed_to: None,
+            publishing_to: HashMap::new(),
+            subscribed_to: HashMap::new(),
+            commands: CommandSequence::new(),
             last_audio_ts: None,
             last_video_ts: None,
             detected_video_codec: None,
             detected_audio_codec: None,
-            frame_rx: None,
-            subscriber_state: SubscriberState::Normal,
-            consecutive_lag_count: 0,
-            playback_stream_id: None,
-            is_paused: false,
-            frames_dropped_while_paused: 0,
-            skip_audio_until_keyframe: false,
+            frame_rx: HashMap::new(),
+            drop_report_at: None,
+            pending_seek: None,
+            disconnect_reason: None,
+            flush_deadline: None,
+            control_rx: Some(control_rx),
+            buffer_pool,
         }
     }
 
+    /// Get a sender for delivering control requests to this connection
+    pub(crate) fn control_sender(&self) -> Option<mpsc::UnboundedSender<SessionControl>> {
+        self.context.control.clone()
+    }
+
     /// Run the connection
     pub async fn run(&mut self) -> Result<()> {
+        // The real client address comes first when behind a load balancer
+        if self.config.expect_proxy_protocol {
+            timeout(self.config.connection_timeout, self.read_proxy_header())
+                .await
+                .map_err(|_| Error::Timeout)??;
+        }
+
         // Check if handler allows connection
         if !self.handler.on_connection(&self.context).await {
             return Err(Error::Rejected("Connection rejected by handler".into()));
         }
 
         // Perform handshake
-        self.do_handshake().await?;
+        let span =
+            self.protocol_span(|| tracing::debug_span!("handshake", session_id = self.state.id));
+        if let Err(e) = self.do_handshake().instrument(span).await {
+            let reason = match e {
+                Error::Timeout => DisconnectReason::Timeout,
+                _ => DisconnectReason::HandshakeFailed,
+            };
+            self.handler.on_disconnect(&self.context, &reason).await;
+            return Err(e);
+        }
         self.handler.on_handshake_complete(&self.context).await;
 
-        // Set our chunk size
-        tracing::debug!(
-            session_id = self.state.id,
-            chunk_size = self.config.chunk_size,
-            "Sending set chunk size"
-        );
-        self.send_set_chunk_size(self.config.chunk_size).await?;
-        self.chunk_encoder.set_chunk_size(self.config.chunk_size);
-
         tracing::debug!(session_id = self.state.id, "Entering main message loop");
 
         // Main message loop
         let idle_timeout = self.config.idle_timeout;
-        let result = loop {
-            // Handle subscriber mode: take frame_rx out to avoid borrow conflicts
-            let mut frame_rx = self.frame_rx.take();
-
-            // Use select! to handle both TCP input and broadcast frames
-            let loop_result = if let Some(ref mut rx) = frame_rx {
-                // Subscriber mode: listen for both TCP and broadcast frames
-                tokio::select! {
-                    biased;
-
-                    // Receive broadcast frames for subscribers (higher priority)
-                    frame_result = rx.recv() => {
-                        match frame_result {
-                            Ok(frame) => {
-                                // Put receiver back before processing
-                                self.frame_rx = frame_rx;
-                                // Reset lag count on successful receive
-                                self.consecutive_lag_count = 0;
-                                if let Err(e) = self.send_broadcast_frame(frame).await {
-                                    tracing::debug!(error = %e, "Failed to send frame");
-                                    Err(e)
-                                } else {
-                                    Ok(true)
+        let mut control_rx = self.control_rx.take();
+        let result = 'session: {
+            // Handle messages that arrived along with the handshake
+            if let Err(e) = self.process_buffered().await {
+                break 'session Err(e);
+            }
+            loop {
+                // Handle subscriber mode: take frame_rx out to avoid borrow conflicts
+                let mut frame_rx = std::mem::take(&mut self.frame_rx);
+                // Playbacks holding a paced frame receive nothing until it is sent
+                for (stream_id, playback) in &self.subscribed_to {
+                    if playback.paced.is_some() {
+                        if let Some(rx) = frame_rx.remove(stream_id) {
+                            self.frame_rx.insert(*stream_id, rx);
+                        }
+                    }
+                }
+                let paced_at = self.next_paced_at();
+                let flush_at = self.flush_deadline;
+                let report_at = self.drop_report_at;
+                let evictions = self.eviction_signals();
+                let unpublishes = self.unpublish_signals();
+                let takeovers: Vec<_> = self
+                    .publishing_to
+                    .values()
+                    .filter_map(|publishing| publishing.takeover.clone())
+                    .collect();
+
+                // Use select! to handle both TCP input and broadcast frames
+                // Pending flushes and drop reports keep the subscriber
+                // branch after the last playback ends, so their timers
+                // still fire
+                let subscriber_mode = !frame_rx.is_empty()
+                    || paced_at.is_some()
+                    || flush_at.is_some()
+                    || report_at.is_some();
+                let loop_result = if subscriber_mode {
+                    // Subscriber mode: listen for both TCP and broadcast frames
+                    tokio::select! {
+                        biased;
+
+                        // Flush coalesced media (checked first so a steady
+                        // stream of frames cannot starve it)
+                        _ = sleep_until(flush_at.unwrap_or_else(Instant::now)), if flush_at.is_some() => {
+                            self.restore_receivers(frame_rx);
+                            self.flush_deadline = None;
+                            self.writer.flush().await.map(|_| true).map_err(Error::from)
+                        }
+
+                        // Add batched dropped frames to the streams' counts
+                        _ = sleep_until(report_at.unwrap_or_else(Instant::now)), if report_at.is_some() => {
+                            self.restore_receivers(frame_rx);
+                            self.report_drops().await;
+                            Ok(true)
+                        }
+
+                        // Reconnect and shutdown requests
+                        Some(control) = recv_control(&mut control_rx) => {
+                            self.restore_receivers(frame_rx);
+                            self.handle_control(control).await
+                        }
+
+                        // Checked before frames so an evicted stream's closed
+                        // channel is not mistaken for its end
+                        _ = wait_for_eviction(evictions) => {
+                            self.restore_receivers(frame_rx);
+                            self.handle_control(SessionControl::Evict).await
+                        }
+
+                        _ = wait_for_takeover(takeovers, self.state.id) => {
+                            self.restore_receivers(frame_rx);
+                            self.handle_control(SessionControl::Takeover).await
+                        }
+
+                        // Send frames held back for pacing
+                        _ = sleep_until(paced_at.unwrap_or_else(Instant::now)), if paced_at.is_some() => {
+                            self.restore_receivers(frame_rx);
+                            self.send_paced_frames().await.map(|_| true)
+                        }
+
+                        // Receive broadcast frames for subscribers (higher priority)
+                        (stream_id, frame_result) = recv_frame(&mut frame_rx) => {
+                            // Put receivers back before processing
+                            self.restore_receivers(frame_rx);
+                            match frame_result {
+                                Ok(frame) => {
+                                    // Reset lag count on successful receive
+                                    if let Some(playback) = self.subscribed_to.get_mut(&stream_id) {
+                                        playback.consecutive_lag_count = 0;
+                                    }
+                                    if let Err(e) = self.send_broadcast_frame(stream_id, frame).await {
+                                        tracing::debug!(error = %e, "Failed to send frame");
+                                        Err(e)
+                                    } else {
+                                        Ok(true)
+                                    }
+                                }
+                                Err(broadcast::error::RecvError::Lagged(n)) => {
+                                    self.handle_lag(stream_id, n).await.map(|_| true)
+                                }
+                                Err(broadcast::error::RecvError::Closed) => {
+                                    // Publisher ended, notify subscriber
+                                    if let Err(e) = self.handle_stream_ended(stream_id).await {
+                                        tracing::debug!(error = %e, "Error handling stream end");
+                                    }
+                                    if self.subscribed_to.len() > 1 || !self.publishing_to.is_empty() {
+                                        // Other streams carry on without this one
+                                        self.stop_stream(stream_id).await;
+                                        Ok(true)
+                                    } else {
+                                        self.disconnect_reason = Some(DisconnectReason::StreamEnded);
+                                        Ok(false) // Signal to exit loop
+                                    }
                                 }
                             }
-                            Err(broadcast::error::RecvError::Lagged(n)) => {
-                                self.frame_rx = frame_rx;
-                                self.handle_lag(n).await.map(|_| true)
+                        }
+
+                        // After the frames, so the stream's last media and its
+                        // end of sequence go out before the notice
+                        stream_id = wait_for_unpublish(unpublishes) => {
+                            self.restore_receivers(frame_rx);
+                            self.handle_unpublished(stream_id).await.map(|_| true)
+                        }
+
+                        // Read from TCP. Only the read races the other
+                        // branches; it is cancel safe, and the data is processed
+                        // once the select has finished
+                        read = timeout(idle_timeout, self.reader.read_buf(&mut self.read_buf)) => {
+                            // Keep the receivers unless the command unsubscribed
+                            // (or already put a new one in place)
+                            self.restore_receivers(frame_rx);
+                            let mut result = match read {
+                                Ok(Ok(n)) => Ok(self.process_read(n).await),
+                                Ok(Err(e)) => Ok(Err(e.into())),
+                                Err(elapsed) => Err(elapsed),
+                            };
+                            // A seek discards queued frames, so it runs once the
+                            // receiver is back
+                            if let Some((stream_id, milliseconds)) = self.pending_seek.take() {
+                                if matches!(result, Ok(Ok(true))) {
+                                    let seeked = self.do_seek(stream_id, milliseconds).await;
+                                    result = Ok(seeked.map(|_| true));
+                                }
                             }
-                            Err(broadcast::error::RecvError::Closed) => {
-                                self.frame_rx = frame_rx;
-                                // Publisher ended, notify subscriber
-                                if let Err(e) = self.handle_stream_ended().await {
-                                    tracing::debug!(error = %e, "Error handling stream end");
+                            match result {
+                                Ok(Ok(continue_loop)) => Ok(continue_loop),
+                                Ok(Err(e)) => {
+                                    tracing::debug!(error = %e, "Processing error");
+                                    Err(e)
+                                }
+                                Err(_) => {
+                                    tracing::debug!("Idle timeout");
+                                    self.disconnect_reason = Some(DisconnectReason::Timeout);
+                                    Ok(false)
                                 }
-                                Ok(false) // Signal to exit loop
                             }
                         }
                     }
+                } else {
+                    // Publisher mode: listen for TCP and control requests
+                    tokio::select! {
+                        biased;
 
-                    // Read from TCP
-                    result = timeout(idle_timeout, self.read_and_process()) => {
-                        self.frame_rx = frame_rx;
-                        match result {
-                            Ok(Ok(continue_loop)) => Ok(continue_loop),
-                            Ok(Err(e)) => {
-                                tracing::debug!(error = %e, "Processing error");
-                                Err(e)
-                            }
-                            Err(_) => {
-                                tracing::debug!("Idle timeout");
-                                Ok(false)
+                        Some(control) = recv_control(&mut control_rx) => {
+                            self.handle_control(control).await
+                        }
+
+                        _ = wait_for_eviction(evictions) => {
+                            self.handle_control(SessionControl::Evict).await
+                        }
+
+                        _ = wait_for_takeover(takeovers, self.state.id) => {
+                            self.handle_control(SessionControl::Takeover).await
+                        }
+
+                        read = timeout(idle_timeout, self.reader.read_buf(&mut self.read_buf)) => {
+                            let result = match read {
+                                Ok(Ok(n)) => Ok(self.process_read(n).await),
+                                Ok(Err(e)) => Ok(Err(e.into())),
+                                Err(elapsed) => Err(elapsed),
+                            };
+                            match result {
+                                Ok(Ok(continue_loop)) => Ok(continue_loop),
+                                Ok(Err(e)) => {
+                                    tracing::debug!(error = %e, "Processing error");
+                                    Err(e)
+                                }
+                                Err(_) => {
+                                    tracing::debug!("Idle timeout");
+                                    self.disconnect_reason = Some(DisconnectReason::Timeout);
+                                    Ok(false)
+                                }
                             }
                         }
                     }
-                }
-            } else {
-                // Publisher mode: only listen for TCP
-                self.frame_rx = frame_rx;
-                match timeout(idle_timeout, self.read_and_process()).await {
-                    Ok(Ok(continue_loop)) => Ok(continue_loop),
-                    Ok(Err(e)) => {
-                        tracing::debug!(error = %e, "Processing error");
-                        Err(e)
-                    }
-                    Err(_) => {
-                        tracing::debug!("Idle timeout");
-                        Ok(false)
-                    }
-                }
-            };
+                };
 
-            match loop_result {
-                Ok(true) => continue,
-                Ok(false) => break Ok(()),
-                Err(e) => break Err(e),
+                match loop_result {
+                    Ok(true) => continue,
+                    Ok(false) => break Ok(()),
+                    Err(e) => break Err(e),
+                }
             }
         };
 
+        // Send any coalesced media still buffered
+        if self.flush_deadline.take().is_some() {
+            let _ = self.writer.flush().await;
+        }
+
         // Cleanup: unregister publisher or unsubscribe
         self.cleanup_on_disconnect().await;
 
         // Notify handler of disconnect
-        self.handler.on_disconnect(&self.context).await;
+        let reason = match &result {
+            Ok(()) => self
+                .disconnect_reason
+                .take()
+                .unwrap_or(DisconnectReason::PeerClosed),
+            Err(e) => DisconnectReason::from(e),
+        };
+        self.handler.on_disconnect(&self.context, &reason).await;
 
         result
     }
 
+    /// Handle a control request; always ends the session
+    async fn handle_control(&mut self, control: SessionControl) -> Result<bool> {
+        let (tc_url, reason) = match control {
+            SessionControl::Reconnect { tc_url } => (tc_url, DisconnectReason::ReconnectRequested),
+            SessionControl::Shutdown { tc_url } => (tc_url, DisconnectReason::ServerShutdown),
+            SessionControl::Evict => {
+                tracing::info!(session_id = self.state.id, "Session evicted");
+                self.disconnect_reason = Some(DisconnectReason::Evicted);
+                return Ok(false);
+            }
+            SessionControl::Takeover => {
+                tracing::info!(session_id = self.state.id, "Publisher taken over");
+                self.disconnect_reason = Some(DisconnectReason::PublisherTakeover);
+                return Ok(false);
+            }
+        };
+
+        // Legacy clients cannot be redirected; they are simply disconnected
+        if self.context.supports_reconnect() {
+            let mut status = Command::on_status(
+                0,
+                "status",
+                NC_CONNECT_RECONNECT_REQUEST,
+                "Server requested reconnect",
+            );
+            let info = status.arguments.first_mut().and_then(|a| a.as_object_mut());
+            if let (Some(url), Some(info)) = (tc_url, info) {
+                info.insert("tcUrl".to_string(), AmfValue::String(url));
+            }
+            self.send_command(CSID_COMMAND, 0, &status).await?;
+
+            tracing::info!(session_id = self.state.id, "Sent reconnect request");
+        }
+
+        self.disconnect_reason = Some(reason);
+        Ok(false)
+    }
+
+    /// Put back the frame receivers taken for the main loop's select
+    ///
+    /// Receivers of playbacks stopped meanwhile are dropped, and a play
+    /// started meanwhile on the same message stream keeps its new one.
+    fn restore_receivers(&mut self, receivers: HashMap<u32, broadcast::Receiver<BroadcastFrame>>) {
+        for (stream_id, rx) in receivers {
+            if self.subscribed_to.contains_key(&stream_id) {
+                self.frame_rx.entry(stream_id).or_insert(rx);
+            }
+        }
+    }
+
+    /// Unpublish signals of the playbacks, by message stream ID
+    ///
+    /// Playbacks holding a paced frame are left out, so the notice never
+    /// overtakes their media.
+    fn unpublish_signals(&self) -> Vec<(u32, watch::Receiver<u64>)> {
+        self.subscribed_to
+            .iter()
+            .filter(|(_, playback)| playback.paced.is_none())
+            .filter_map(|(&stream_id, playback)| Some((stream_id, playback.unpublished.clone()?)))
+            .collect()
+    }
+
+    /// Eviction signals of every stream published or played
+    fn eviction_signals(&self) -> Vec<watch::Receiver<bool>> {
+        let publishing = self.publishing_to.values().map(|p| &p.eviction);
+        let playing = self.subscribed_to.values().map(|p| &p.eviction);
+        publishing.chain(playing).flatten().cloned().collect()
+    }
+
     /// Cleanup when connection disconnects
     async fn cleanup_on_disconnect(&mut self) {
-        // Unregister as publisher if we were publishing
-        if let Some(ref key) = self.publishing_to {
-            self.registry.unregister_publisher(key, self.state.id).await;
+        for (_, publishing) in std::mem::take(&mut self.publishing_to) {
+            // Finish the recording first, while its sink is still attached
+            if let Some(recorder) = publishing.recorder {
+                recorder.finish().await;
+            }
+
+            // Unregister as publisher
+            let key = publishing.key;
+            self.registry
+                .unregister_publisher(&key, self.state.id)
+                .await;
             tracing::debug!(
                 session_id = self.state.id,
                 stream = %key,
@@ -345,22 +708,78 @@ impl<H: RtmpHandler> Connection<H> {
             );
         }
 
-        // Unsubscribe if we were subscribed
-        if let Some(ref key) = self.subscribed_to {
-            self.registry.unsubscribe(key).await;
+        // Unsubscribe, counting frames still queued in the channels as
+        // dropped so the final tally is complete
+        for (stream_id, rx) in std::mem::take(&mut self.frame_rx) {
+            self.drop_frames(stream_id, rx.len() as u64);
+        }
+        let held: Vec<_> = self
+            .subscribed_to
+            .iter()
+            .map(|(&stream_id, playback)| (stream_id, playback.held_frames()))
+            .collect();
+        for (stream_id, count) in held {
+            self.drop_frames(stream_id, count);
+        }
+        self.report_drops().await;
+        for (_, playback) in std::mem::take(&mut self.subscribed_to) {
+            self.registry
+                .unsubscribe_session(&playback.key, self.state.id)
+                .await;
             tracing::debug!(
                 session_id = self.state.id,
-                stream = %key,
+                stream = %playback.key,
+                frames_delivered = self.context.stats.frames_delivered,
+                dropped_frames = self.context.stats.dropped_frames,
                 "Unsubscribed on disconnect"
             );
         }
     }
 
-    /// Handle lag event from broadcast channel
-    async fn handle_lag(&mut self, skipped: u64) -> Result<()> {
-        let config = self.registry.config();
+    /// Count frames a playback dropped, for the session and the stream
+    ///
+    /// The session's count is updated right away. The stream's is updated
+    /// in batches, when frames are delivered again, when the subscription
+    /// ends, or after [`DROP_REPORT_INTERVAL`], so a paused or lagging
+    /// subscriber does not lock the stream's entry for every frame.
+    fn drop_frames(&mut self, stream_id: u32, count: u64) {
+        if count == 0 {
+            return;
+        }
+        self.context.stats.dropped_frames += count;
+        if let Some(playback) = self.subscribed_to.get_mut(&stream_id) {
+            playback.unreported_drops += count;
+            self.drop_report_at
+                .get_or_insert_with(|| Instant::now() + DROP_REPORT_INTERVAL);
+        }
+    }
 
-        self.consecutive_lag_count += 1;
+    /// Add the dropped frames counted since the last report to the streams
+    async fn report_drops(&mut self) {
+        self.drop_report_at = None;
+        let mut reports = Vec::new();
+        for playback in self.subscribed_to.values_mut() {
+            let count = std::mem::take(&mut playback.unreported_drops);
+            if count > 0 {
+                reports.push((playback.key.clone(), count));
+            }
+        }
+        for (key, count) in reports {
+            self.registry
+                .record_session_dropped_frames(&key, self.state.id, count)
+                .await;
+        }
+    }
+
+    /// Handle lag event from a playback's broadcast channel
+    async fn handle_lag(&mut self, stream_id: u32, skipped: u64) -> Result<()> {
+        self.drop_frames(stream_id, skipped);
+        let Some(playback) = self.subscribed_to.get_mut(&stream_id) else {
+            return Ok(());
+        };
+        playback.consecutive_lag_count += 1;
+
+        let config = self.registry.config();
 
         if skipped < config.lag_threshold_low {
             // Minor lag, continue normally
@@ -373,8 +792,8 @@ impl<H: RtmpHandler> Connection<H> {
         }
 
         // Significant lag - enter skip mode
-        if self.subscriber_state != SubscriberState::SkippingToKeyframe {
-            self.subscriber_state = SubscriberState::SkippingToKeyframe;
+        if playback.subscriber_state != SubscriberState::SkippingToKeyframe {
+            playback.subscriber_state = SubscriberState::SkippingToKeyframe;
             tracing::warn!(
                 session_id = self.state.id,
                 skipped = skipped,
@@ -383,10 +802,10 @@ impl<H: RtmpHandler> Connection<H> {
         }
 
         // Check if we should disconnect slow subscriber
-        if self.consecutive_lag_count >= config.max_consecutive_lag_events {
+        if playback.consecutive_lag_count >= config.max_consecutive_lag_events {
             tracing::warn!(
                 session_id = self.state.id,
-                consecutive_lags = self.consecutive_lag_count,
+                consecutive_lags = playback.consecutive_lag_count,
                 "Disconnecting slow subscriber"
             );
             return Err(Error::Rejected("Subscriber too slow".into()));
@@ -396,34 +815,62 @@ impl<H: RtmpHandler> Connection<H> {
     }
 
     /// Handle stream ended (publisher closed broadcast channel)
-    async fn handle_stream_ended(&mut self) -> Result<()> {
+    async fn handle_stream_ended(&mut self, stream_id: u32) -> Result<()> {
         // Reset pause state on stream end
-        if self.is_paused {
-            self.is_paused = false;
+        if let Some(playback) = self.subscribed_to.get_mut(&stream_id) {
+            playback.is_paused = false;
         }
 
-        if let Some(stream_id) = self.playback_stream_id {
-            // Send StreamEOF
-            self.send_user_control(UserControlEvent::StreamEof(stream_id))
-                .await?;
+        // Send StreamEOF
+        self.send_user_control(UserControlEvent::StreamEof(stream_id))
+            .await?;
 
-            // Send onStatus
-            let status = Command::on_status(stream_id, "status", NS_PLAY_STOP, "Stream ended");
-            self.send_command(CSID_COMMAND, stream_id, &status).await?;
+        // Send onStatus
+        let status = Command::on_status(stream_id, "status", NS_PLAY_STOP, "Stream ended");
+        self.send_command(CSID_COMMAND, stream_id, &status).await?;
 
-            tracing::info!(
-                session_id = self.state.id,
-                stream_id = stream_id,
-                "Stream ended, notified subscriber"
-            );
-        }
+        tracing::info!(
+            session_id = self.state.id,
+            stream_id = stream_id,
+            "Stream ended, notified subscriber"
+        );
 
         Ok(())
     }
 
+    /// Read the PROXY protocol header and take the client address from it
+    ///
+    /// Bytes after the header stay buffered for the handshake.
+    async fn read_proxy_header(&mut self) -> Result<()> {
+        loop {
+            if let Some((header, len)) = parse_proxy_header(&self.read_buf)? {
+                self.read_buf.advance(len);
+                if let Some(source) = header.source {
+                    tracing::debug!(
+                        session_id = self.state.id,
+                        proxy = %self.context.peer_addr,
+                        client = %source,
+                        "PROXY header received"
+                    );
+                    self.context.peer_addr = source;
+                    self.state.peer_addr = source;
+                }
+                return Ok(());
+            }
+
+            let start = self.read_buf.len();
+            let n = self.reader.read_buf(&mut self.read_buf).await?;
+            if n == 0 {
+                return Err(Error::ConnectionClosed);
+            }
+            self.tap(ByteDirection::In, &self.read_buf[start..]);
+        }
+    }
+
     /// Perform RTMP handshake
     async fn do_handshake(&mut self) -> Result<()> {
-        let mut handshake = Handshake::new(HandshakeRole::Server);
+        let mut handshake = Handshake::new(HandshakeRole::Server)
+            .with_version_policy(self.config.handshake_version_policy);
 
         // Move to waiting state
         handshake.generate_initial();
@@ -436,10 +883,12 @@ impl<H: RtmpHandler> Connection<H> {
                 // Read more data
                 let bytes_needed = handshake.bytes_needed();
                 if bytes_needed > 0 && self.read_buf.len() < bytes_needed {
+                    let start = self.read_buf.len();
                     let n = self.reader.read_buf(&mut self.read_buf).await?;
                     if n == 0 {
                         return Err(Error::ConnectionClosed);
                     }
+                    self.tap(ByteDirection::In, &self.read_buf[start..]);
                 }
 
                 // Process handshake
@@ -454,6 +903,7 @@ impl<H: RtmpHandler> Connection<H> {
 
                 // Send response if any (S0S1S2 for C0C1, nothing for C2)
                 if let Some(data) = response {
+                    self.tap(ByteDirection::Out, &data);
                     self.writer.write_all(&data).await?;
                     self.writer.flush().await?;
                 }
@@ -468,6 +918,8 @@ impl<H: RtmpHandler> Connection<H> {
         .await
         .map_err(|_| Error::Timeout)??;
 
+        self.context.protocol_params.handshake_timestamp = handshake.peer_timestamp();
+        self.context.protocol_params.clock_skew_ms = handshake.peer_clock_skew_ms();
         self.state.complete_handshake();
         tracing::debug!(
             session_id = self.state.id,
@@ -493,14 +945,42 @@ impl<H: RtmpHandler> Connection<H> {
         Ok(())
     }
 
-    /// Read data and process messages
-    async fn read_and_process(&mut self) -> Result<bool> {
+    /// Process data read from the socket
+    ///
+    /// Runs outside the `select!` in [`run`](Self::run), so handling a
+    /// message is never cancelled halfway by a timer or a broadcast frame.
+    async fn process_read(&mut self, n: usize) -> Result<bool> {
+        if n == 0 {
+            self.disconnect_reason = Some(DisconnectReason::PeerClosed);
+            return Ok(false); // Connection closed
+        }
+        let start = self.read_buf.len() - n;
+        self.tap(ByteDirection::In, &self.read_buf[start..]);
+
         tracing::trace!(
             session_id = self.state.id,
+            bytes_read = n,
             buf_len = self.read_buf.len(),
-            "read_and_process called"
+            "Read data from socket"
         );
 
+        let needs_ack = self.state.add_bytes_received(n as u64);
+        self.process_buffered().await?;
+
+        // Chunk stream diagnostics are published once per read
+        self.context
+            .set_chunk_streams(self.chunk_decoder.chunk_streams().collect());
+
+        // Send acknowledgement if needed
+        if needs_ack {
+            self.send_acknowledgement().await?;
+        }
+
+        Ok(true)
+    }
+
+    /// Decode and handle every complete message in the read buffer
+    async fn process_buffered(&mut self) -> Result<()> {
         // Keep trying to decode until we need more data
         // This is important for multi-chunk messages where multiple chunks
         // may be in the buffer but only the last one completes the message
@@ -537,50 +1017,22 @@ impl<H: RtmpHandler> Connection<H> {
             buf_len = self.read_buf.len(),
             "Waiting for more data"
         );
-
-        // Wait for more data from the socket
-        let n = self.reader.read_buf(&mut self.read_buf).await?;
-        if n == 0 {
-            return Ok(false); // Connection closed
-        }
-
-        tracing::trace!(
-            session_id = self.state.id,
-            bytes_read = n,
-            buf_len = self.read_buf.len(),
-            "Read data from socket"
-        );
-
-        let needs_ack = self.state.add_bytes_received(n as u64);
-
-        // Process any complete messages with the new data
-        while let Some(chunk) = self.chunk_decoder.decode(&mut self.read_buf)? {
-            tracing::trace!(
-                session_id = self.state.id,
-                csid = chunk.csid,
-                msg_type = chunk.message_type,
-                "Decoded chunk after read"
-            );
-            self.handle_chunk(chunk).await?;
-        }
-
-        // Send acknowledgement if needed
-        if needs_ack {
-            self.send_acknowledgement().await?;
-        }
-
-        Ok(true)
+        Ok(())
     }
 
     /// Handle a decoded chunk
     async fn handle_chunk(&mut self, chunk: RtmpChunk) -> Result<()> {
-        let message = RtmpMessage::from_chunk(&chunk)?;
+        let mut decoder = self.config.quirks.amf0_decoder();
+        let message = RtmpMessage::from_chunk_with(&chunk, &mut decoder);
+        self.context.stats.amf_recovered_errors += decoder.recovered_errors();
+        let message = message?;
 
         match message {
             RtmpMessage::SetChunkSize(size) => {
                 tracing::debug!(size = size, "Peer set chunk size");
                 self.chunk_decoder.set_chunk_size(size);
                 self.state.in_chunk_size = size;
+                self.context.protocol_params.in_chunk_size = size;
             }
 
             RtmpMessage::Abort { csid } => {
@@ -593,14 +1045,16 @@ impl<H: RtmpHandler> Connection<H> {
 
             RtmpMessage::WindowAckSize(size) => {
                 self.state.window_ack_size = size;
+                self.context.protocol_params.window_ack_size = Some(size);
             }
 
-            RtmpMessage::SetPeerBandwidth {
-                size,
-                limit_type: _,
-            } => {
+            RtmpMessage::SetPeerBandwidth { size, limit_type } => {
+                self.context.protocol_params.peer_bandwidth = Some(size);
+                self.context.protocol_params.peer_bandwidth_limit_type = Some(limit_type);
+
                 // Send window ack size back
-                self.send_window_ack_size(size).await?;
+                self.send_protocol_control(RtmpMessage::WindowAckSize(size))
+                    .await?;
             }
 
             RtmpMessage::UserControl(event) => {
@@ -608,19 +1062,51 @@ impl<H: RtmpHandler> Connection<H> {
             }
 
             RtmpMessage::Command(cmd) | RtmpMessage::CommandAmf3(cmd) => {
-                self.handle_command(cmd).await?;
+                let span = self.protocol_span(|| {
+                    tracing::debug_span!(
+                        "command",
+                        session_id = self.state.id,
+                        name = %cmd.name,
+                        transaction_id = cmd.transaction_id,
+                        csid = chunk.csid,
+                        stream_id = chunk.stream_id,
+                    )
+                });
+                self.handle_command(cmd).instrument(span).await?;
             }
 
             RtmpMessage::Data(data) | RtmpMessage::DataAmf3(data) => {
-                self.handle_data(data).await?;
+                self.handle_data(chunk.timestamp, data).await?;
             }
 
             RtmpMessage::Audio { timestamp, data } => {
-                self.handle_audio(timestamp, data).await?;
+                let span = self.media_span("audio", &chunk, timestamp);
+                async {
+                    let ready = self
+                        .check_sequence_header(chunk.stream_id, FrameType::Audio, timestamp, data)
+                        .await;
+                    for (timestamp, data) in ready {
+                        self.handle_audio(chunk.stream_id, timestamp, data).await?;
+                    }
+                    Ok::<_, Error>(())
+                }
+                .instrument(span)
+                .await?;
             }
 
             RtmpMessage::Video { timestamp, data } => {
-                self.handle_video(timestamp, data).await?;
+                let span = self.media_span("video", &chunk, timestamp);
+                async {
+                    let ready = self
+                        .check_sequence_header(chunk.stream_id, FrameType::Video, timestamp, data)
+                        .await;
+                    for (timestamp, data) in ready {
+                        self.handle_video(chunk.stream_id, timestamp, data).await?;
+                    }
+                    Ok::<_, Error>(())
+                }
+                .instrument(span)
+                .await?;
             }
 
             _ => {
@@ -631,17 +1117,53 @@ impl<H: RtmpHandler> Connection<H> {
         Ok(())
     }
 
-    /// Handle user control event
-    async fn handle_user_control(&mut self, event: UserControlEvent) -> Result<()> {
-        match event {
-            UserControlEvent::PingRequest(timestamp) => {
-                self.send_ping_response(timestamp).await?;
-            }
-            UserControlEvent::SetBufferLength {
-                stream_id: _,
-                buffer_ms: _,
+    /// Span for a protocol step, created only with protocol tracing on
+    fn protocol_span(&self, make: impl FnOnce() -> tracing::Span) -> tracing::Span {
+        if self.config.protocol_tracing {
+            make()
+        } else {
+            tracing::Span::none()
+        }
+    }
+
+    /// Span for an audio or video message
+    fn media_span(&self, kind: &'static str, chunk: &RtmpChunk, timestamp: u32) -> tracing::Span {
+        self.protocol_span(|| {
+            tracing::debug_span!(
+                "media",
+                session_id = self.state.id,
+                kind = kind,
+                csid = chunk.csid,
+                timestamp = timestamp,
+                stream_id = chunk.stream_id,
+                len = chunk.payload.len(),
+            )
+        })
+    }
+
+    /// Handle user control event
+    async fn handle_user_control(&mut self, event: UserControlEvent) -> Result<()> {
+        match event {
+            UserControlEvent::PingRequest(timestamp) => {
+                self.send_ping_response(timestamp).await?;
+            }
+            UserControlEvent::SetBufferLength {
+                stream_id,
+                buffer_ms,
             } => {
-                // Client's buffer length - we can use this for flow control
+                tracing::debug!(
+                    session_id = self.state.id,
+                    stream_id = stream_id,
+                    buffer_ms = buffer_ms,
+                    "Player buffer length"
+                );
+                self.state.set_buffer_length(stream_id, buffer_ms);
+                // Players may announce it again once playing
+                if let Some(playback) = self.subscribed_to.get(&stream_id) {
+                    self.registry
+                        .set_subscriber_buffer_length(&playback.key, self.state.id, buffer_ms)
+                        .await;
+                }
             }
             _ => {}
         }
@@ -658,8 +1180,21 @@ impl<H: RtmpHandler> Connection<H> {
             args = ?cmd.arguments,
             "Received command"
         );
+        // Encoders are known to bend the order, so it is only logged
+        if !self.commands.is_valid_command(&cmd) {
+            tracing::debug!(
+                session_id = self.state.id,
+                command = cmd.name,
+                stream_id = cmd.stream_id,
+                state = self.commands.state(),
+                "Command out of sequence"
+            );
+        }
         match cmd.name.as_str() {
-            CMD_CONNECT => self.handle_connect(cmd).await?,
+            CMD_CONNECT => {
+                self.handle_connect(cmd).await?;
+                self.commands.on_command(CMD_CONNECT);
+            }
             CMD_CREATE_STREAM => self.handle_create_stream(cmd).await?,
             CMD_DELETE_STREAM => self.handle_delete_stream(cmd).await?,
             CMD_PUBLISH => self.handle_publish(cmd).await?,
@@ -667,18 +1202,62 @@ impl<H: RtmpHandler> Connection<H> {
             CMD_FC_PUBLISH => self.handle_fc_publish(cmd).await?,
             CMD_FC_UNPUBLISH => self.handle_fc_unpublish(cmd).await?,
             CMD_RELEASE_STREAM => self.handle_release_stream(cmd).await?,
+            CMD_FC_SUBSCRIBE => self.handle_fc_subscribe(cmd).await?,
+            CMD_GET_STREAM_LENGTH => self.handle_get_stream_length(cmd).await?,
             CMD_PAUSE => self.handle_pause(cmd).await?,
-            CMD_CLOSE | "closeStream" => self.handle_close_stream(cmd).await?,
-            _ => {
-                tracing::debug!(command = cmd.name, "Unknown command");
-            }
+            CMD_SEEK => self.handle_seek(cmd).await?,
+            CMD_CLOSE | CMD_CLOSE_STREAM => self.handle_close_stream(cmd).await?,
+            _ => self.handle_custom_command(cmd).await?,
+        }
+        Ok(())
+    }
+
+    /// Pass a command the server does not model to the handler
+    ///
+    /// Sends a `_result` carrying the handler's reply, if any.
+    async fn handle_custom_command(&mut self, cmd: Command) -> Result<()> {
+        if !self.answer_with_handler(&cmd).await? {
+            tracing::debug!(command = cmd.name, "Unknown command");
         }
         Ok(())
     }
 
+    /// Offer a command to [`RtmpHandler::on_command`]
+    ///
+    /// Sends a `_result` carrying the handler's reply and returns whether
+    /// there was one.
+    async fn answer_with_handler(&mut self, cmd: &Command) -> Result<bool> {
+        let reply = self
+            .handler
+            .on_command(
+                &self.context,
+                &cmd.name,
+                cmd.transaction_id,
+                &cmd.command_object,
+                &cmd.arguments,
+            )
+            .await;
+
+        let Some(values) = reply else {
+            return Ok(false);
+        };
+
+        let result = Command {
+            name: CMD_RESULT.to_string(),
+            transaction_id: cmd.transaction_id,
+            command_object: AmfValue::Null,
+            arguments: values,
+            stream_id: cmd.stream_id,
+        };
+        self.send_command(CSID_COMMAND, cmd.stream_id, &result)
+            .await?;
+        Ok(true)
+    }
+
     /// Handle connect command
     async fn handle_connect(&mut self, cmd: Command) -> Result<()> {
-        let params = ConnectParams::from_amf(&cmd.command_object);
+        let mut params = ConnectParams::from_amf(&cmd.command_object);
+        params.arguments = cmd.arguments;
         let encoder_type = params
             .flash_ver
             .as_deref()
@@ -702,12 +1281,19 @@ impl<H: RtmpHandler> Connection<H> {
                     self.context.with_enhanced_capabilities(caps.clone());
                 }
 
-                // Send window ack size
-                self.send_window_ack_size(self.config.window_ack_size)
-                    .await?;
-
-                // Send peer bandwidth
-                self.send_peer_bandwidth(self.config.peer_bandwidth).await?;
+                // Send window ack size, peer bandwidth and chunk size
+                let chunk_size = self
+                    .handler
+                    .chunk_size_for(&self.context)
+                    .map_or(self.config.chunk_size, |size| size.clamp(1, MAX_CHUNK_SIZE));
+                for msg in connect_control_messages(&self.config, chunk_size) {
+                    tracing::debug!(session_id = self.state.id, ?msg, "Sending protocol control");
+                    self.send_protocol_control(msg.clone()).await?;
+                    if let RtmpMessage::SetChunkSize(size) = msg {
+                        self.chunk_encoder.set_chunk_size(size);
+                        self.state.out_chunk_size = size;
+                    }
+                }
 
                 // Send stream begin
                 self.send_user_control(UserControlEvent::StreamBegin(0))
@@ -831,6 +1417,7 @@ impl<H: RtmpHandler> Connection<H> {
         );
 
         self.send_command(CSID_COMMAND, 0, &result).await?;
+        self.commands.on_stream_created(stream_id);
 
         tracing::debug!(stream_id = stream_id, "Stream created");
         Ok(())
@@ -844,21 +1431,69 @@ impl<H: RtmpHandler> Connection<H> {
             .and_then(|v| v.as_number())
             .unwrap_or(0.0) as u32;
 
-        if let Some(stream) = self.state.remove_stream(stream_id) {
-            if stream.is_publishing() {
-                let stream_ctx = StreamContext::new(
-                    self.context.clone(),
-                    stream_id,
-                    stream.stream_key.unwrap_or_default(),
-                    true,
+        self.stop_stream(stream_id).await;
+        self.state.remove_stream(stream_id);
+        self.commands
+            .on_stream_command(CMD_DELETE_STREAM, stream_id);
+
+        Ok(())
+    }
+
+    /// Tear down publishing or playback on a message stream
+    ///
+    /// Leaves the stream ID allocated, so the same connection can publish
+    /// or play again afterwards.
+    async fn stop_stream(&mut self, stream_id: u32) {
+        let Some(stream) = self.state.get_stream_mut(stream_id) else {
+            return;
+        };
+        let stream_ctx = StreamContext::new(
+            self.context.clone(),
+            stream_id,
+            stream.stream_key.clone().unwrap_or_default(),
+            stream.is_publishing(),
+        )
+        .with_sequence_headers(stream.video_config.clone(), stream.audio_config.clone());
+        stream.stop();
+        self.commands.on_stream_command(CMD_CLOSE_STREAM, stream_id);
+
+        if let Some(publishing) = self.publishing_to.remove(&stream_id) {
+            if let Some(recorder) = publishing.recorder {
+                recorder.finish().await;
+            }
+            // A clean stop, so subscribers are told rather than left
+            // waiting out the grace period
+            let key = publishing.key;
+            self.registry.unpublish(&key, self.state.id).await;
+            tracing::debug!(
+                session_id = self.state.id,
+                stream = %key,
+                "Unregistered publisher on stream close"
+            );
+
+            #[allow(deprecated)]
+            self.handler.on_publish_stop(&stream_ctx).await;
+            self.handler.on_unpublish(&stream_ctx).await;
+        } else if let Some(playback) = self.subscribed_to.get(&stream_id) {
+            let mut dropped = playback.held_frames();
+            if let Some(rx) = self.frame_rx.remove(&stream_id) {
+                dropped += rx.len() as u64;
+            }
+            self.drop_frames(stream_id, dropped);
+            self.report_drops().await;
+            if let Some(playback) = self.subscribed_to.remove(&stream_id) {
+                self.registry
+                    .unsubscribe_session(&playback.key, self.state.id)
+                    .await;
+                tracing::debug!(
+                    session_id = self.state.id,
+                    stream = %playback.key,
+                    "Unsubscribed on stream close"
                 );
-                #[allow(deprecated)]
-                self.handler.on_publish_stop(&stream_ctx).await;
-                self.handler.on_unpublish(&stream_ctx).await;
             }
-        }
 
-        Ok(())
+            self.handler.on_play_stop(&stream_ctx).await;
+        }
     }
 
     /// Handle FCPublish command (OBS/Twitch compatibility)
@@ -878,14 +1513,15 @@ impl<H: RtmpHandler> Connection<H> {
                 self.pending_fc
                     .insert(stream_key.clone(), cmd.transaction_id);
 
-                // Send onFCPublish response
-                let response = Command {
-                    name: CMD_ON_FC_PUBLISH.to_string(),
-                    transaction_id: 0.0,
-                    command_object: AmfValue::Null,
-                    arguments: vec![],
-                    stream_id: 0,
-                };
+                // Send onFCPublish response; some encoders wait for the
+                // status code before starting to send media
+                let mut response = Command::on_status(
+                    0,
+                    "status",
+                    NS_PUBLISH_START,
+                    &format!("FCPublish to stream {}", stream_key),
+                );
+                response.name = CMD_ON_FC_PUBLISH.to_string();
                 self.send_command(CSID_COMMAND, 0, &response).await?;
             }
             AuthResult::Reject(reason) => {
@@ -919,9 +1555,49 @@ impl<H: RtmpHandler> Connection<H> {
     }
 
     /// Handle releaseStream command
-    async fn handle_release_stream(&mut self, _cmd: Command) -> Result<()> {
-        // No response needed, this is just cleanup notification
-        Ok(())
+    async fn handle_release_stream(&mut self, cmd: Command) -> Result<()> {
+        // Nothing to release, but encoders like Wirecast wait for the reply
+        let result = Command::result(cmd.transaction_id, AmfValue::Null, AmfValue::Undefined);
+        self.send_command(CSID_COMMAND, 0, &result).await
+    }
+
+    /// Handle FCSubscribe command
+    async fn handle_fc_subscribe(&mut self, cmd: Command) -> Result<()> {
+        // Handlers answered this through on_command before it was built in
+        if self.answer_with_handler(&cmd).await? {
+            return Ok(());
+        }
+        let stream_name = cmd.arguments.first().and_then(|v| v.as_str()).unwrap_or("");
+
+        // Nothing to set up; CDN edges and some players wait for the reply
+        // before they play
+        let mut response = Command::on_status(
+            0,
+            "status",
+            NS_PLAY_START,
+            &format!("FCSubscribe to stream {}", stream_name),
+        );
+        response.name = CMD_ON_FC_SUBSCRIBE.to_string();
+        self.send_command(CSID_COMMAND, 0, &response).await
+    }
+
+    /// Handle getStreamLength command
+    async fn handle_get_stream_length(&mut self, cmd: Command) -> Result<()> {
+        // Handlers answered this through on_command before it was built in
+        if self.answer_with_handler(&cmd).await? {
+            return Ok(());
+        }
+        let stream_name = cmd.arguments.first().and_then(|v| v.as_str()).unwrap_or("");
+        let length = self
+            .handler
+            .on_get_stream_length(&self.context, stream_name)
+            .await;
+
+        let mut result =
+            Command::result(cmd.transaction_id, AmfValue::Null, AmfValue::Number(length));
+        result.stream_id = cmd.stream_id;
+        self.send_command(CSID_COMMAND, cmd.stream_id, &result)
+            .await
     }
 
     /// Handle publish command
@@ -940,19 +1616,29 @@ impl<H: RtmpHandler> Connection<H> {
             .unwrap_or("live")
             .to_string();
 
+        let policy = self.check_app_policy(StreamMode::Publishing, &stream_key);
+        let stream_key = policy.clone().unwrap_or(stream_key);
+
         let params = PublishParams {
             stream_key: stream_key.clone(),
             publish_type: publish_type.clone(),
             stream_id: cmd.stream_id,
         };
 
-        let result = self.handler.on_publish(&self.context, &params).await;
+        let result = match policy {
+            Ok(_) => self.handler.on_publish(&self.context, &params).await,
+            Err(reason) => AuthResult::Reject(reason),
+        };
 
         match result {
             AuthResult::Accept => {
                 // Create stream key for registry
-                let app = self.context.app.clone();
-                let registry_key = StreamKey::new(&app, &stream_key);
+                let registry_key =
+                    self.handler
+                        .stream_key_for(&self.context, &self.context.app, &stream_key);
+
+                // A second publish on the message stream replaces the first
+                self.stop_stream(cmd.stream_id).await;
 
                 // Register as publisher in the registry
                 if let Err(e) = self
@@ -966,36 +1652,76 @@ impl<H: RtmpHandler> Connection<H> {
                         error = %e,
                         "Failed to register publisher"
                     );
+                    // Tell the client why before the connection is closed
+                    let description = match e {
+                        RegistryError::StreamAlreadyPublishing(_) => {
+                            format!("{} is already being published", stream_key)
+                        }
+                        RegistryError::StreamLimitReached(_) => "Too many live streams".to_string(),
+                        _ => e.to_string(),
+                    };
                     let status = Command::on_status(
                         cmd.stream_id,
                         "error",
                         NS_PUBLISH_BAD_NAME,
-                        &format!("Stream already publishing: {}", e),
+                        &description,
                     );
                     self.send_command(CSID_COMMAND, cmd.stream_id, &status)
                         .await?;
-                    return Err(Error::Rejected(format!("Stream already publishing: {}", e)));
+                    return Err(Error::Rejected(description));
+                }
+
+                // Record the stream if configured
+                let mut recorder = None;
+                if let Some(record) = self.config.record.clone() {
+                    if record.matches(&registry_key) {
+                        match Recorder::start(self.registry.clone(), registry_key.clone(), record)
+                            .await
+                        {
+                            Ok(started) => recorder = Some(started),
+                            Err(e) => tracing::warn!(
+                                stream = %registry_key,
+                                error = %e,
+                                "Failed to start recording"
+                            ),
+                        }
+                    }
                 }
 
-                // Track that we're publishing to this stream
-                self.publishing_to = Some(registry_key);
+                // Track that we're publishing to this stream; timestamps
+                // start over with the new publish
+                let max_ts_delta = self.config.quirks.max_timestamp_delta;
+                let publishing = Publishing {
+                    eviction: self.registry.eviction_signal(&registry_key).await,
+                    takeover: self.registry.takeover_signal(&registry_key).await,
+                    key: registry_key,
+                    recorder,
+                    audio_ts_clamp: max_ts_delta.map(TimestampDeltaClamp::new),
+                    video_ts_clamp: max_ts_delta.map(TimestampDeltaClamp::new),
+                };
+                self.publishing_to.insert(cmd.stream_id, publishing);
 
                 // Update stream state
                 if let Some(stream) = self.state.get_stream_mut(cmd.stream_id) {
                     stream.start_publish(stream_key.clone(), publish_type);
                 }
+                self.commands.on_stream_command(CMD_PUBLISH, cmd.stream_id);
 
                 // Send StreamBegin
                 self.send_user_control(UserControlEvent::StreamBegin(cmd.stream_id))
                     .await?;
 
-                // Send onStatus
-                let status = Command::on_status(
+                // Send onStatus on the publishing stream; encoders such as
+                // ffmpeg hold off on media until it arrives
+                let mut status = Command::on_status(
                     cmd.stream_id,
                     "status",
                     NS_PUBLISH_START,
                     &format!("{} is now published", stream_key),
                 );
+                if let Some(AmfValue::Object(info)) = status.arguments.first_mut() {
+                    info.insert("details".to_string(), AmfValue::String(stream_key.clone()));
+                }
                 self.send_command(CSID_COMMAND, cmd.stream_id, &status)
                     .await?;
 
@@ -1047,24 +1773,57 @@ impl<H: RtmpHandler> Connection<H> {
             .and_then(|v| v.as_bool())
             .unwrap_or(true);
 
+        let policy = self.check_app_policy(StreamMode::Playing, &stream_name);
+        let stream_name = policy.clone().unwrap_or(stream_name);
+
+        let buffer_ms = self
+            .state
+            .get_stream(cmd.stream_id)
+            .and_then(|stream| stream.buffer_length_ms);
         let params = PlayParams {
             stream_name: stream_name.clone(),
             start,
             duration,
             reset,
             stream_id: cmd.stream_id,
+            buffer_ms,
         };
 
-        let result = self.handler.on_play(&self.context, &params).await;
+        let result = match policy {
+            Ok(_) => self.handler.on_play(&self.context, &params).await,
+            Err(reason) => AuthResult::Reject(reason),
+        };
 
         match result {
             AuthResult::Accept => {
                 // Create stream key for registry
-                let app = self.context.app.clone();
-                let registry_key = StreamKey::new(&app, &stream_name);
+                let registry_key =
+                    self.handler
+                        .stream_key_for(&self.context, &self.context.app, &stream_name);
+
+                // A second play on the message stream replaces the first
+                self.stop_stream(cmd.stream_id).await;
 
                 // Subscribe to the stream in registry
-                let (rx, catchup_frames) = match self.registry.subscribe(&registry_key).await {
+                let strategy = self.handler.catchup_strategy_for(&self.context, &params);
+                let subscribed = match strategy {
+                    Some(strategy) => {
+                        self.registry
+                            .subscribe_session_with(
+                                &registry_key,
+                                self.state.id,
+                                self.context.peer_addr,
+                                strategy,
+                            )
+                            .await
+                    }
+                    None => {
+                        self.registry
+                            .subscribe_session(&registry_key, self.state.id, self.context.peer_addr)
+                            .await
+                    }
+                };
+                let (rx, catchup_frames) = match subscribed {
                     Ok(result) => result,
                     Err(e) => {
                         tracing::debug!(
@@ -1085,14 +1844,24 @@ impl<H: RtmpHandler> Connection<H> {
                     }
                 };
 
+                if let Some(buffer_ms) = params.buffer_ms {
+                    self.registry
+                        .set_subscriber_buffer_length(&registry_key, self.state.id, buffer_ms)
+                        .await;
+                }
+
                 // Store subscription info
-                self.subscribed_to = Some(registry_key.clone());
-                self.frame_rx = Some(rx);
-                self.playback_stream_id = Some(cmd.stream_id);
+                let pace_bitrate = self.handler.pace_bitrate_for(&self.context, &params);
+                let mut playback = Playback::new(registry_key.clone(), pace_bitrate);
+                playback.eviction = self.registry.eviction_signal(&registry_key).await;
+                playback.unpublished = self.registry.unpublish_signal(&registry_key).await;
+                self.subscribed_to.insert(cmd.stream_id, playback);
+                self.frame_rx.insert(cmd.stream_id, rx);
 
                 if let Some(stream) = self.state.get_stream_mut(cmd.stream_id) {
                     stream.start_play(stream_name.clone());
                 }
+                self.commands.on_stream_command(CMD_PLAY, cmd.stream_id);
 
                 // Send StreamBegin
                 self.send_user_control(UserControlEvent::StreamBegin(cmd.stream_id))
@@ -1129,7 +1898,7 @@ impl<H: RtmpHandler> Connection<H> {
                 );
 
                 for frame in catchup_frames {
-                    self.send_broadcast_frame(frame).await?;
+                    self.send_broadcast_frame(cmd.stream_id, frame).await?;
                 }
 
                 tracing::info!(
@@ -1152,43 +1921,89 @@ impl<H: RtmpHandler> Connection<H> {
         Ok(())
     }
 
+    /// Check a publish or play against the app's access policy
+    ///
+    /// Returns the stream name without its token, or why it was rejected.
+    fn check_app_policy(
+        &self,
+        mode: StreamMode,
+        stream_name: &str,
+    ) -> std::result::Result<String, String> {
+        let app = &self.context.app;
+        let tc_url = self
+            .context
+            .connect_params
+            .as_ref()
+            .and_then(|params| params.tc_url.as_deref());
+        let result = policy::check(
+            self.config.app_policies.get(app),
+            self.config.token_validator.as_ref(),
+            mode,
+            app,
+            stream_name,
+            tc_url,
+        );
+        if let Err(reason) = &result {
+            tracing::info!(
+                session_id = self.state.id,
+                app = %app,
+                ?mode,
+                reason = %reason,
+                "Rejected by app policy"
+            );
+        }
+        result
+    }
+
     /// Handle closeStream command
     async fn handle_close_stream(&mut self, cmd: Command) -> Result<()> {
-        if let Some(stream) = self.state.get_stream_mut(cmd.stream_id) {
-            stream.stop();
-        }
+        self.stop_stream(cmd.stream_id).await;
         Ok(())
     }
 
     /// Handle pause command from subscriber
     async fn handle_pause(&mut self, cmd: Command) -> Result<()> {
-        let stream_id = match self.playback_stream_id {
+        let stream_id = match self.find_playback_stream(cmd.stream_id) {
             Some(id) => id,
             None => return Ok(()), // Not in play mode
         };
 
-        // The pause flag is the first argument (true = pause, false = unpause)
+        // The pause flag is the first argument (true = pause, false = unpause),
+        // followed by the stream position in milliseconds
         let pause_flag = cmd
             .arguments
             .first()
             .and_then(|v| v.as_bool())
             .unwrap_or(true);
+        let position_ms = cmd
+            .arguments
+            .get(1)
+            .and_then(|v| v.as_number())
+            .unwrap_or(0.0);
 
         if pause_flag {
-            self.do_pause(stream_id).await
+            self.do_pause(stream_id, position_ms).await
         } else {
-            self.do_unpause(stream_id).await
+            self.do_unpause(stream_id, position_ms).await
         }
     }
 
     /// Pause playback for subscriber
-    async fn do_pause(&mut self, stream_id: u32) -> Result<()> {
-        if self.is_paused {
+    async fn do_pause(&mut self, stream_id: u32, position_ms: f64) -> Result<()> {
+        let Some(playback) = self.subscribed_to.get_mut(&stream_id) else {
+            return Ok(());
+        };
+        if playback.is_paused {
             return Ok(()); // Already paused
         }
 
-        self.is_paused = true;
-        self.frames_dropped_while_paused = 0;
+        playback.is_paused = true;
+        // A frame held back for pacing is dropped like the ones that follow
+        let held = playback.held_frames();
+        playback.paced = None;
+        playback.frames_dropped_while_paused = held;
+        let stream_key = playback.key.name.clone();
+        self.drop_frames(stream_id, held);
 
         // Send onStatus(NetStream.Pause.Notify)
         let status = Command::on_status(stream_id, "status", NS_PAUSE_NOTIFY, "Playback paused");
@@ -1199,30 +2014,31 @@ impl<H: RtmpHandler> Connection<H> {
             .await?;
 
         // Notify handler
-        let stream_key = self
-            .subscribed_to
-            .as_ref()
-            .map(|k| k.name.clone())
-            .unwrap_or_default();
         let stream_ctx = StreamContext::new(self.context.clone(), stream_id, stream_key, false);
-        self.handler.on_pause(&stream_ctx).await;
+        self.handler.on_pause_changed(&stream_ctx, true).await;
 
-        tracing::info!(session_id = self.state.id, "Subscriber paused");
+        tracing::info!(session_id = self.state.id, position_ms, "Subscriber paused");
         Ok(())
     }
 
     /// Unpause playback for subscriber
-    async fn do_unpause(&mut self, stream_id: u32) -> Result<()> {
-        if !self.is_paused {
+    async fn do_unpause(&mut self, stream_id: u32, position_ms: f64) -> Result<()> {
+        let Some(playback) = self.subscribed_to.get_mut(&stream_id) else {
+            return Ok(());
+        };
+        if !playback.is_paused {
             return Ok(()); // Not paused
         }
 
-        self.is_paused = false;
+        playback.is_paused = false;
 
         // Force keyframe sync for clean video resumption
         // Also skip audio to avoid hearing audio while video is frozen
-        self.subscriber_state = SubscriberState::SkippingToKeyframe;
-        self.skip_audio_until_keyframe = true;
+        playback.subscriber_state = SubscriberState::SkippingToKeyframe;
+        playback.skip_audio_until_keyframe = true;
+        let stream_key = playback.key.name.clone();
+        let frames_dropped = std::mem::take(&mut playback.frames_dropped_while_paused);
+        self.report_drops().await;
 
         // Send onStatus(NetStream.Unpause.Notify)
         let status = Command::on_status(stream_id, "status", NS_UNPAUSE_NOTIFY, "Playback resumed");
@@ -1234,49 +2050,139 @@ impl<H: RtmpHandler> Connection<H> {
 
         // Resend sequence headers to reinitialize decoder
         // This is critical for clean playback resumption
-        if let Some(ref key) = self.subscribed_to {
-            let headers = self.registry.get_sequence_headers(key).await;
-            tracing::debug!(
-                session_id = self.state.id,
-                header_count = headers.len(),
-                "Resending sequence headers after unpause"
-            );
-            for frame in headers {
-                match frame.frame_type {
-                    FrameType::Video => {
-                        self.send_video(stream_id, frame.timestamp, frame.data)
-                            .await?;
-                    }
-                    FrameType::Audio => {
-                        self.send_audio(stream_id, frame.timestamp, frame.data)
-                            .await?;
-                    }
-                    _ => {}
-                }
-            }
-            self.writer.flush().await?;
-        }
+        self.resend_sequence_headers(stream_id, None).await?;
 
         // Notify handler
-        let stream_key = self
-            .subscribed_to
-            .as_ref()
-            .map(|k| k.name.clone())
-            .unwrap_or_default();
         let stream_ctx = StreamContext::new(self.context.clone(), stream_id, stream_key, false);
-        self.handler.on_unpause(&stream_ctx).await;
+        self.handler.on_pause_changed(&stream_ctx, false).await;
 
         tracing::info!(
             session_id = self.state.id,
-            frames_dropped = self.frames_dropped_while_paused,
+            position_ms,
+            frames_dropped,
             "Subscriber unpaused (waiting for keyframe)"
         );
-        self.frames_dropped_while_paused = 0;
+        Ok(())
+    }
+
+    /// Handle seek command from subscriber
+    ///
+    /// The seek itself runs in the main loop once the frame receiver is
+    /// available again (see `do_seek`).
+    async fn handle_seek(&mut self, cmd: Command) -> Result<()> {
+        let stream_id = match self.find_playback_stream(cmd.stream_id) {
+            Some(id) => id,
+            None => return Ok(()), // Not in play mode
+        };
+
+        // The only argument is the new position in milliseconds
+        let milliseconds = cmd
+            .arguments
+            .first()
+            .and_then(|v| v.as_number())
+            .unwrap_or(0.0);
+
+        self.pending_seek = Some((stream_id, milliseconds.max(0.0) as u32));
+        Ok(())
+    }
+
+    /// Seek playback for subscriber
+    async fn do_seek(&mut self, stream_id: u32, milliseconds: u32) -> Result<()> {
+        // Frames sent after this point are for the new position, once the
+        // handler has moved its source
+        let fresh_rx = self.frame_rx.get(&stream_id).map(|rx| rx.resubscribe());
+
+        let Some(playback) = self.subscribed_to.get(&stream_id) else {
+            return Ok(());
+        };
+        let stream_key = playback.key.name.clone();
+        let stream_ctx = StreamContext::new(self.context.clone(), stream_id, stream_key, false);
+        if !self.handler.on_seek(&stream_ctx, milliseconds).await {
+            let status = Command::on_status(
+                stream_id,
+                "error",
+                NS_SEEK_FAILED,
+                &format!("Seek to {} ms failed", milliseconds),
+            );
+            return self.send_command(CSID_COMMAND, stream_id, &status).await;
+        }
+
+        // Discard frames queued for the old position
+        let mut discarded = 0;
+        if let (Some(rx), Some(fresh_rx)) = (self.frame_rx.get_mut(&stream_id), fresh_rx) {
+            discarded = rx.len();
+            *rx = fresh_rx;
+        }
+
+        // Restart at a keyframe on a new pacing timeline
+        if let Some(playback) = self.subscribed_to.get_mut(&stream_id) {
+            playback.subscriber_state = SubscriberState::SkippingToKeyframe;
+            playback.skip_audio_until_keyframe = true;
+            playback.pacer = playback.pace_bitrate.map(Pacer::new);
+            discarded += playback.held_frames() as usize;
+            playback.paced = None;
+        }
+        self.drop_frames(stream_id, discarded as u64);
+        self.report_drops().await;
+
+        let status = Command::on_status(
+            stream_id,
+            "status",
+            NS_SEEK_NOTIFY,
+            &format!("Seeking to {} ms", milliseconds),
+        );
+        self.send_command(CSID_COMMAND, stream_id, &status).await?;
+        self.send_user_control(UserControlEvent::StreamBegin(stream_id))
+            .await?;
+        self.resend_sequence_headers(stream_id, Some(milliseconds))
+            .await?;
+
+        tracing::info!(
+            session_id = self.state.id,
+            milliseconds,
+            frames_discarded = discarded,
+            "Subscriber seeking (waiting for keyframe)"
+        );
+        Ok(())
+    }
+
+    /// Resend the stream's sequence headers to the subscriber
+    ///
+    /// Headers keep their original timestamp unless `timestamp` is given.
+    async fn resend_sequence_headers(
+        &mut self,
+        stream_id: u32,
+        timestamp: Option<u32>,
+    ) -> Result<()> {
+        let Some(playback) = self.subscribed_to.get(&stream_id) else {
+            return Ok(());
+        };
+        let headers = self.registry.get_sequence_headers(&playback.key).await;
+        tracing::debug!(
+            session_id = self.state.id,
+            header_count = headers.len(),
+            "Resending sequence headers"
+        );
+        for frame in headers {
+            let frame_timestamp = timestamp.unwrap_or(frame.timestamp);
+            match frame.frame_type {
+                FrameType::Video => {
+                    self.send_video(stream_id, frame_timestamp, frame.data)
+                        .await?;
+                }
+                FrameType::Audio => {
+                    self.send_audio(stream_id, frame_timestamp, frame.data)
+                        .await?;
+                }
+                _ => {}
+            }
+        }
+        self.writer.flush().await?;
         Ok(())
     }
 
     /// Handle data message
-    async fn handle_data(&mut self, data: DataMessage) -> Result<()> {
+    async fn handle_data(&mut self, timestamp: u32, data: DataMessage) -> Result<()> {
         match data.name.as_str() {
             CMD_SET_DATA_FRAME => {
                 // @setDataFrame usually has "onMetaData" as first value
@@ -1290,6 +2196,18 @@ impl<H: RtmpHandler> Connection<H> {
             CMD_ON_METADATA => {
                 self.handle_metadata(data.stream_id, &data.values).await?;
             }
+            CMD_CLEAR_DATA_FRAME => {
+                self.handle_clear_metadata(data.stream_id).await;
+            }
+            CMD_ON_CUE_POINT | CMD_ON_TEXT_DATA => {
+                // Forward to players at its place in the media
+                if let Some(key) = self.publishing_key(data.stream_id) {
+                    let (_, payload) = RtmpMessage::Data(data).encode();
+                    self.registry
+                        .broadcast_data(key, FlvTag::script(timestamp, payload))
+                        .await;
+                }
+            }
             _ => {
                 tracing::trace!(name = data.name, "Unknown data message");
             }
@@ -1297,39 +2215,94 @@ impl<H: RtmpHandler> Connection<H> {
         Ok(())
     }
 
+    /// Handle @clearDataFrame: forget the publisher's metadata
+    async fn handle_clear_metadata(&mut self, stream_id: u32) {
+        if let Some(stream) = self.state.get_stream_mut(stream_id) {
+            stream.has_metadata = false;
+            stream.gop_buffer.clear_metadata();
+        }
+
+        if let Some(key) = self.publishing_key(stream_id) {
+            self.registry.clear_metadata(key).await;
+        }
+    }
+
     /// Handle metadata
     async fn handle_metadata(&mut self, stream_id: u32, values: &[AmfValue]) -> Result<()> {
         // Extract metadata object
-        let metadata: HashMap<String, AmfValue> = values
+        let mut metadata: AmfObject = values
             .first()
             .and_then(|v| v.as_object().cloned())
             .unwrap_or_default();
 
-        if let Some(stream) = self.state.get_stream_mut(stream_id) {
-            stream.on_metadata();
-        }
-
-        if let Some(stream) = self.state.get_stream(stream_id) {
-            if stream.is_publishing() {
-                let stream_ctx = StreamContext::new(
+        let stream_ctx = match self.state.get_stream(stream_id) {
+            Some(stream) if stream.is_publishing() => Some(
+                StreamContext::new(
                     self.context.clone(),
                     stream_id,
                     stream.stream_key.clone().unwrap_or_default(),
                     true,
+                )
+                .with_sequence_headers(stream.video_config.clone(), stream.audio_config.clone()),
+            ),
+            _ => None,
+        };
+
+        if let Some(ref stream_ctx) = stream_ctx {
+            if !self
+                .handler
+                .on_metadata_mut(stream_ctx, &mut metadata)
+                .await
+            {
+                tracing::debug!(
+                    session_id = self.context.session_id,
+                    stream_key = %stream_ctx.stream_key,
+                    "Metadata dropped by handler"
                 );
-                self.handler.on_metadata(&stream_ctx, &metadata).await;
+                return Ok(());
             }
         }
 
+        let encoded = encode_on_metadata(&metadata);
+
+        if let Some(stream) = self.state.get_stream_mut(stream_id) {
+            stream.on_metadata();
+            stream.gop_buffer.set_metadata(encoded.clone());
+        }
+
+        if let Some(ref stream_ctx) = stream_ctx {
+            self.handler.on_metadata(stream_ctx, &metadata).await;
+        }
+
+        // Cache for late joiners and forward to subscribers
+        if let Some(key) = self.publishing_key(stream_id) {
+            self.registry.set_metadata(key, encoded).await;
+        }
+
         Ok(())
     }
 
     /// Handle audio message
-    async fn handle_audio(&mut self, timestamp: u32, data: Bytes) -> Result<()> {
-        if data.is_empty() {
+    async fn handle_audio(
+        &mut self,
+        msg_stream_id: u32,
+        timestamp: u32,
+        data: Bytes,
+    ) -> Result<()> {
+        if audio_payload_too_short(&data) {
+            tracing::debug!(len = data.len(), "Skipping truncated audio message");
             return Ok(());
         }
 
+        // Find publishing stream
+        let stream_id = self.find_publishing_stream(msg_stream_id)?;
+
+        let clamp = self.publishing_to.get_mut(&stream_id);
+        let timestamp = match clamp.and_then(|p| p.audio_ts_clamp.as_mut()) {
+            Some(clamp) => clamp_timestamp(clamp, timestamp, "audio"),
+            None => timestamp,
+        };
+
         if let Some(prev_audio_ts) = self.last_audio_ts {
             // Use wrapping_sub to handle timestamp wraparound (RTMP timestamps are 32-bit)
             let timestamp_delta = timestamp.wrapping_sub(prev_audio_ts);
@@ -1370,31 +2343,38 @@ impl<H: RtmpHandler> Connection<H> {
             }
         }
 
-        // Find publishing stream
-        let stream_id = self.find_publishing_stream()?;
         let stream = self
             .state
             .get_stream_mut(stream_id)
             .ok_or(ProtocolError::StreamNotFound(stream_id))?;
 
-        let is_header = if is_enhanced {
-            // Enhanced audio: packet type 0 = SequenceStart
-            (data[0] & 0x0F) == 0
-        } else {
-            // Legacy AAC: SoundFormat=10 (0xA_) and AACPacketType=0
-            data.len() >= 2 && (data[0] >> 4) == 10 && data[1] == 0
-        };
+        let is_header = is_audio_header(&data);
         stream.on_audio(timestamp, is_header, data.len());
+        let av_desync = self
+            .config
+            .av_desync_threshold
+            .and_then(|threshold| stream.check_av_sync(threshold.as_millis() as i64));
 
         // Store sequence header
         if is_header {
             let tag = FlvTag::audio(timestamp, data.clone());
             stream.gop_buffer.set_audio_header(tag);
+
+            if !is_enhanced {
+                if let Ok(AacData::SequenceHeader(config)) = AacData::parse(data.slice(1..)) {
+                    stream.audio_config = Some(config);
+                }
+            }
         }
 
         // Create stream context for callbacks
         let stream_key = stream.stream_key.clone().unwrap_or_default();
-        let stream_ctx = StreamContext::new(self.context.clone(), stream_id, stream_key, true);
+        let stream_ctx = StreamContext::new(self.context.clone(), stream_id, stream_key, true)
+            .with_sequence_headers(stream.video_config.clone(), stream.audio_config.clone());
+
+        if let Some(drift_ms) = av_desync {
+            self.report_av_desync(&stream_ctx, drift_ms).await;
+        }
 
         // Deliver based on mode
         let mode = self.handler.media_delivery_mode();
@@ -1414,20 +2394,31 @@ impl<H: RtmpHandler> Connection<H> {
                     self.handler
                         .on_enhanced_audio_frame(&stream_ctx, &enhanced_data, timestamp)
                         .await;
+                    if let Ok(frame) = AudioFrame::from_enhanced(enhanced_data) {
+                        self.handler
+                            .on_parsed_audio_frame(&stream_ctx, &frame, timestamp)
+                            .await;
+                    }
                 }
-            } else if data.len() >= 2 && (data[0] >> 4) == 10 {
-                // Legacy AAC
-                if let Ok(aac_data) = AacData::parse(data.slice(1..)) {
+            } else if let Ok(frame) = AudioFrame::parse(data.clone()) {
+                // Legacy AAC / G.711 / MP3
+                if let AudioFrame::Aac(aac_data) = &frame {
                     self.handler
-                        .on_audio_frame(&stream_ctx, &aac_data, timestamp)
+                        .on_audio_frame(&stream_ctx, aac_data, timestamp)
                         .await;
                 }
+                self.handler
+                    .on_parsed_audio_frame(&stream_ctx, &frame, timestamp)
+                    .await;
             }
         }
 
         // Broadcast to subscribers via registry
-        if let Some(ref key) = self.publishing_to {
-            let frame = BroadcastFrame::audio(timestamp, data, is_header);
+        if let Some(publishing) = self.publishing_to.get(&stream_id) {
+            let key = &publishing.key;
+            let nanos = self.timestamp_nano_offset(is_enhanced, &data);
+            let frame =
+                BroadcastFrame::audio(timestamp, data, is_header).with_timestamp_nano_offset(nanos);
             self.registry.broadcast(key, frame).await;
         }
 
@@ -1435,11 +2426,26 @@ impl<H: RtmpHandler> Connection<H> {
     }
 
     /// Handle video message
-    async fn handle_video(&mut self, timestamp: u32, data: Bytes) -> Result<()> {
-        if data.is_empty() {
+    async fn handle_video(
+        &mut self,
+        msg_stream_id: u32,
+        timestamp: u32,
+        data: Bytes,
+    ) -> Result<()> {
+        if video_payload_too_short(&data) {
+            tracing::debug!(len = data.len(), "Skipping truncated video message");
             return Ok(());
         }
 
+        // Find publishing stream
+        let stream_id = self.find_publishing_stream(msg_stream_id)?;
+
+        let clamp = self.publishing_to.get_mut(&stream_id);
+        let timestamp = match clamp.and_then(|p| p.video_ts_clamp.as_mut()) {
+            Some(clamp) => clamp_timestamp(clamp, timestamp, "video"),
+            None => timestamp,
+        };
+
         if let Some(prev_video_ts) = self.last_video_ts {
             // Use wrapping_sub to handle timestamp wraparound (RTMP timestamps are 32-bit)
             let timestamp_delta = timestamp.wrapping_sub(prev_video_ts);
@@ -1480,27 +2486,17 @@ impl<H: RtmpHandler> Connection<H> {
             }
         }
 
-        // Find publishing stream
-        let stream_id = self.find_publishing_stream()?;
         let stream = self
             .state
             .get_stream_mut(stream_id)
             .ok_or(ProtocolError::StreamNotFound(stream_id))?;
 
-        let (is_keyframe, is_header) = if is_enhanced {
-            // ExVideoTagHeader format: bit 7 set, bits 4-6 are frame type, bits 0-3 are packet type
-            let frame_type = (data[0] >> 4) & 0x07;
-            let packet_type = data[0] & 0x0F;
-            let is_keyframe = frame_type == 1 || frame_type == 4; // Keyframe or GeneratedKeyframe
-            let is_header = packet_type == 0; // SequenceStart
-            (is_keyframe, is_header)
-        } else {
-            // Legacy FLV format: bits 4-7 are frame type, bits 0-3 are codec ID
-            let is_keyframe = (data[0] >> 4) == 1;
-            let is_header = data.len() >= 2 && (data[0] & 0x0F) == 7 && data[1] == 0;
-            (is_keyframe, is_header)
-        };
+        let (is_keyframe, is_header) = video_flags(&data);
         stream.on_video(timestamp, is_keyframe, is_header, data.len());
+        let av_desync = self
+            .config
+            .av_desync_threshold
+            .and_then(|threshold| stream.check_av_sync(threshold.as_millis() as i64));
 
         // Create FLV tag
         let tag = FlvTag::video(timestamp, data.clone());
@@ -1508,6 +2504,12 @@ impl<H: RtmpHandler> Connection<H> {
         // Store sequence header
         if is_header {
             stream.gop_buffer.set_video_header(tag.clone());
+
+            if !is_enhanced && (data[0] & 0x0F) == 7 {
+                if let Ok(H264Data::SequenceHeader(config)) = H264Data::parse(data.slice(1..)) {
+                    stream.video_config = Some(config);
+                }
+            }
         } else {
             // Add to GOP buffer
             stream.gop_buffer.push(tag.clone());
@@ -1515,7 +2517,12 @@ impl<H: RtmpHandler> Connection<H> {
 
         // Create stream context for callbacks
         let stream_key = stream.stream_key.clone().unwrap_or_default();
-        let stream_ctx = StreamContext::new(self.context.clone(), stream_id, stream_key, true);
+        let stream_ctx = StreamContext::new(self.context.clone(), stream_id, stream_key, true)
+            .with_sequence_headers(stream.video_config.clone(), stream.audio_config.clone());
+
+        if let Some(drift_ms) = av_desync {
+            self.report_av_desync(&stream_ctx, drift_ms).await;
+        }
 
         // Notify keyframe
         if is_keyframe && !is_header {
@@ -1539,6 +2546,11 @@ impl<H: RtmpHandler> Connection<H> {
                     self.handler
                         .on_enhanced_video_frame(&stream_ctx, &enhanced_data, timestamp)
                         .await;
+                    if let Ok(frame) = VideoFrame::from_enhanced(enhanced_data) {
+                        self.handler
+                            .on_parsed_video_frame(&stream_ctx, &frame, timestamp)
+                            .await;
+                    }
                 }
             } else if data.len() >= 2 && (data[0] & 0x0F) == 7 {
                 // Legacy AVC/H.264
@@ -1546,21 +2558,139 @@ impl<H: RtmpHandler> Connection<H> {
                     self.handler
                         .on_video_frame(&stream_ctx, &h264_data, timestamp)
                         .await;
+                    self.handler
+                        .on_parsed_video_frame(&stream_ctx, &VideoFrame::Avc(h264_data), timestamp)
+                        .await;
                 }
             }
         }
 
         // Broadcast to subscribers via registry
-        if let Some(ref key) = self.publishing_to {
-            let frame = BroadcastFrame::video(timestamp, data, is_keyframe, is_header);
+        if let Some(publishing) = self.publishing_to.get(&stream_id) {
+            let key = &publishing.key;
+            let nanos = self.timestamp_nano_offset(is_enhanced, &data);
+            let frame = BroadcastFrame::video(timestamp, data, is_keyframe, is_header)
+                .with_timestamp_nano_offset(nanos);
             self.registry.broadcast(key, frame).await;
         }
 
         Ok(())
     }
 
-    /// Find the publishing stream (assumes single publish per connection)
-    fn find_publishing_stream(&self) -> Result<u32> {
+    /// Log A/V sync drift beyond the threshold and tell the handler
+    async fn report_av_desync(&self, stream_ctx: &StreamContext, drift_ms: i64) {
+        tracing::warn!(
+            session_id = self.state.id,
+            stream_key = %stream_ctx.stream_key,
+            drift_ms = drift_ms,
+            "A/V sync drift beyond threshold"
+        );
+        self.handler.on_av_desync(stream_ctx, drift_ms).await;
+    }
+
+    /// Hold back or drop AAC/AVC frames that precede their sequence header
+    ///
+    /// Returns the frames to process now, in order: usually just this one,
+    /// none while it waits for the header, or the header followed by the
+    /// frames held for it.
+    async fn check_sequence_header(
+        &mut self,
+        msg_stream_id: u32,
+        frame_type: FrameType,
+        timestamp: u32,
+        data: Bytes,
+    ) -> Vec<(u32, Bytes)> {
+        let Some(policy) = self.config.sequence_header_policy else {
+            return vec![(timestamp, data)];
+        };
+        let Some(is_header) = sequence_header_role(frame_type, &data) else {
+            return vec![(timestamp, data)];
+        };
+        // Frames without a publishing stream fail in the handlers as usual
+        let Ok(stream_id) = self.find_publishing_stream(msg_stream_id) else {
+            return vec![(timestamp, data)];
+        };
+        let Some(stream) = self.state.get_stream_mut(stream_id) else {
+            return vec![(timestamp, data)];
+        };
+
+        let (has_header, held) = match frame_type {
+            FrameType::Audio => (stream.has_audio_header, &mut stream.held_audio),
+            _ => (stream.has_video_header, &mut stream.held_video),
+        };
+        if is_header {
+            // Stamp the header with the first held frame's time and keep
+            // the replay monotonic, so timestamps never go backwards
+            let start = held
+                .frames
+                .front()
+                .map_or(timestamp, |&(first, _)| first.min(timestamp));
+            let mut ready = vec![(start, data)];
+            let mut last = start;
+            for (timestamp, data) in held.frames.drain(..) {
+                last = last.max(timestamp);
+                ready.push((last, data));
+            }
+            held.bytes = 0;
+            return ready;
+        }
+        if has_header {
+            return vec![(timestamp, data)];
+        }
+
+        if policy == SequenceHeaderPolicy::Buffer {
+            hold_frame(held, frame_type, timestamp, data);
+        }
+        if held.reported {
+            return Vec::new();
+        }
+        held.reported = true;
+
+        let stream_key = stream.stream_key.clone().unwrap_or_default();
+        let stream_ctx = StreamContext::new(self.context.clone(), stream_id, stream_key, true);
+        tracing::warn!(
+            session_id = self.state.id,
+            stream_key = %stream_ctx.stream_key,
+            frame_type = ?frame_type,
+            timestamp = timestamp,
+            policy = ?policy,
+            "Media frame before its sequence header"
+        );
+        self.handler
+            .on_frame_before_header(&stream_ctx, frame_type, timestamp)
+            .await;
+        Vec::new()
+    }
+
+    /// Nanosecond timestamp offset of an enhanced media payload
+    ///
+    /// Zero unless the publisher negotiated the capability.
+    fn timestamp_nano_offset(&self, is_enhanced: bool, data: &Bytes) -> u32 {
+        let negotiated = self
+            .context
+            .enhanced_capabilities
+            .as_ref()
+            .map(|c| c.supports_timestamp_nano_offset())
+            .unwrap_or(false);
+
+        if is_enhanced && negotiated {
+            modex::timestamp_offset_nano(data).unwrap_or(0)
+        } else {
+            0
+        }
+    }
+
+    /// Find the publishing stream media on `msg_stream_id` belongs to
+    ///
+    /// The connection may be playing on other streams at the same time.
+    /// Media on a stream that is not publishing (some encoders use 0) goes
+    /// to the connection's publishing stream.
+    fn find_publishing_stream(&self, msg_stream_id: u32) -> Result<u32> {
+        if let Some(stream) = self.state.get_stream(msg_stream_id) {
+            if stream.is_publishing() {
+                return Ok(msg_stream_id);
+            }
+        }
         for (id, stream) in &self.state.streams {
             if stream.is_publishing() {
                 return Ok(*id);
@@ -1569,6 +2699,26 @@ impl<H: RtmpHandler> Connection<H> {
         Err(ProtocolError::StreamNotFound(0).into())
     }
 
+    /// Registry key of the publish data on `msg_stream_id` belongs to
+    fn publishing_key(&self, msg_stream_id: u32) -> Option<&StreamKey> {
+        let stream_id = self.find_publishing_stream(msg_stream_id).ok()?;
+        Some(&self.publishing_to.get(&stream_id)?.key)
+    }
+
+    /// Find the playback a stream command on `msg_stream_id` applies to
+    ///
+    /// Commands on a stream that is not playing (some players use 0) go to
+    /// the connection's playback if it has just one.
+    fn find_playback_stream(&self, msg_stream_id: u32) -> Option<u32> {
+        if self.subscribed_to.contains_key(&msg_stream_id) {
+            return Some(msg_stream_id);
+        }
+        match self.subscribed_to.len() {
+            1 => self.subscribed_to.keys().next().copied(),
+            _ => None,
+        }
+    }
+
     // === Message sending helpers ===
 
     async fn send_command(&mut self, csid: u32, stream_id: u32, cmd: &Command) -> Result<()> {
@@ -1584,7 +2734,7 @@ impl<H: RtmpHandler> Connection<H> {
 
         self.write_buf.clear();
         self.chunk_encoder.encode(&chunk, &mut self.write_buf);
-        self.writer.write_all(&self.write_buf).await?;
+        self.write_encoded().await?;
         self.writer.flush().await?;
 
         Ok(())
@@ -1613,7 +2763,7 @@ impl<H: RtmpHandler> Connection<H> {
     }
 
     async fn send_connect_error(&mut self, transaction_id: f64, reason: &str) -> Result<()> {
-        let mut info = HashMap::new();
+        let mut info = AmfObject::new();
         info.insert("level".to_string(), AmfValue::String("error".into()));
         info.insert(
             "code".to_string(),
@@ -1627,7 +2777,7 @@ impl<H: RtmpHandler> Connection<H> {
     }
 
     async fn send_connect_redirect(&mut self, transaction_id: f64, url: &str) -> Result<()> {
-        let mut info = HashMap::new();
+        let mut info = AmfObject::new();
         info.insert("level".to_string(), AmfValue::String("error".into()));
         info.insert(
             "code".to_string(),
@@ -1644,8 +2794,9 @@ impl<H: RtmpHandler> Connection<H> {
         self.send_command(CSID_COMMAND, 0, &error).await
     }
 
-    async fn send_set_chunk_size(&mut self, size: u32) -> Result<()> {
-        let (msg_type, payload) = RtmpMessage::SetChunkSize(size).encode();
+    /// Send a protocol control message (chunk size, window ack, bandwidth)
+    async fn send_protocol_control(&mut self, msg: RtmpMessage) -> Result<()> {
+        let (msg_type, payload) = msg.encode();
 
         let chunk = RtmpChunk {
             csid: CSID_PROTOCOL_CONTROL,
@@ -1657,52 +2808,24 @@ impl<H: RtmpHandler> Connection<H> {
 
         self.write_buf.clear();
         self.chunk_encoder.encode(&chunk, &mut self.write_buf);
-        self.writer.write_all(&self.write_buf).await?;
+        self.write_encoded().await?;
         self.writer.flush().await?;
 
         Ok(())
     }
 
-    async fn send_window_ack_size(&mut self, size: u32) -> Result<()> {
-        let (msg_type, payload) = RtmpMessage::WindowAckSize(size).encode();
-
-        let chunk = RtmpChunk {
-            csid: CSID_PROTOCOL_CONTROL,
-            timestamp: 0,
-            message_type: msg_type,
-            stream_id: 0,
-            payload,
-        };
-
-        self.write_buf.clear();
-        self.chunk_encoder.encode(&chunk, &mut self.write_buf);
+    /// Write the chunks encoded into `write_buf` (without flushing)
+    async fn write_encoded(&mut self) -> Result<()> {
+        self.tap(ByteDirection::Out, &self.write_buf);
         self.writer.write_all(&self.write_buf).await?;
-        self.writer.flush().await?;
-
         Ok(())
     }
 
-    async fn send_peer_bandwidth(&mut self, size: u32) -> Result<()> {
-        let (msg_type, payload) = RtmpMessage::SetPeerBandwidth {
-            size,
-            limit_type: BANDWIDTH_LIMIT_DYNAMIC,
+    /// Pass raw socket bytes to the handler if it taps them
+    fn tap(&self, direction: ByteDirection, data: &[u8]) {
+        if H::TAPS_RAW {
+            self.handler.on_raw_bytes(&self.context, direction, data);
         }
-        .encode();
-
-        let chunk = RtmpChunk {
-            csid: CSID_PROTOCOL_CONTROL,
-            timestamp: 0,
-            message_type: msg_type,
-            stream_id: 0,
-            payload,
-        };
-
-        self.write_buf.clear();
-        self.chunk_encoder.encode(&chunk, &mut self.write_buf);
-        self.writer.write_all(&self.write_buf).await?;
-        self.writer.flush().await?;
-
-        Ok(())
     }
 
     async fn send_user_control(&mut self, event: UserControlEvent) -> Result<()> {
@@ -1718,7 +2841,7 @@ impl<H: RtmpHandler> Connection<H> {
 
         self.write_buf.clear();
         self.chunk_encoder.encode(&chunk, &mut self.write_buf);
-        self.writer.write_all(&self.write_buf).await?;
+        self.write_encoded().await?;
         self.writer.flush().await?;
 
         Ok(())
@@ -1739,7 +2862,7 @@ impl<H: RtmpHandler> Connection<H> {
 
         self.write_buf.clear();
         self.chunk_encoder.encode(&chunk, &mut self.write_buf);
-        self.writer.write_all(&self.write_buf).await?;
+        self.write_encoded().await?;
         self.writer.flush().await?;
 
         self.state.mark_ack_sent();
@@ -1771,7 +2894,7 @@ impl<H: RtmpHandler> Connection<H> {
 
         self.write_buf.clear();
         self.chunk_encoder.encode(&chunk, &mut self.write_buf);
-        self.writer.write_all(&self.write_buf).await?;
+        self.write_encoded().await?;
         // Don't flush after every frame - batch writes for efficiency
         Ok(())
     }
@@ -1794,7 +2917,7 @@ impl<H: RtmpHandler> Connection<H> {
 
         self.write_buf.clear();
         self.chunk_encoder.encode(&chunk, &mut self.write_buf);
-        self.writer.write_all(&self.write_buf).await?;
+        self.write_encoded().await?;
         // Don't flush after every frame - batch writes for efficiency
         Ok(())
     }
@@ -1803,45 +2926,102 @@ impl<H: RtmpHandler> Connection<H> {
     ///
     /// Handles backpressure by skipping non-keyframes when in skip mode.
     /// Also handles pause state by consuming frames without sending.
-    async fn send_broadcast_frame(&mut self, frame: BroadcastFrame) -> Result<()> {
-        let stream_id = self.playback_stream_id.unwrap_or(1);
+    async fn send_broadcast_frame(&mut self, stream_id: u32, frame: BroadcastFrame) -> Result<()> {
+        let Some(playback) = self.subscribed_to.get_mut(&stream_id) else {
+            return Ok(());
+        };
 
         // PAUSE: Consume frame but don't send
-        if self.is_paused {
-            self.frames_dropped_while_paused += 1;
+        if playback.is_paused {
+            playback.frames_dropped_while_paused += 1;
+            self.drop_frames(stream_id, 1);
             tracing::trace!(session_id = self.state.id, "Frame dropped (paused)");
             return Ok(());
         }
 
         // Backpressure handling: skip non-keyframes if we're lagging
-        if self.subscriber_state == SubscriberState::SkippingToKeyframe {
+        if playback.subscriber_state == SubscriberState::SkippingToKeyframe {
             match frame.frame_type {
                 FrameType::Video => {
                     if frame.is_keyframe || frame.is_header {
                         // Got a keyframe or header, resume normal operation
-                        self.subscriber_state = SubscriberState::Normal;
-                        self.skip_audio_until_keyframe = false;
+                        playback.subscriber_state = SubscriberState::Normal;
+                        playback.skip_audio_until_keyframe = false;
                         tracing::debug!(
                             session_id = self.state.id,
                             "Received keyframe, resuming normal playback"
                         );
                     } else {
                         // Skip non-keyframe video
+                        self.drop_frames(stream_id, 1);
                         return Ok(());
                     }
                 }
                 FrameType::Audio => {
                     // Skip audio after unpause to avoid audio playing while video frozen
                     // But keep audio during lag recovery (glitches worse than brief desync)
-                    if self.skip_audio_until_keyframe {
+                    if playback.skip_audio_until_keyframe {
+                        self.drop_frames(stream_id, 1);
                         return Ok(());
                     }
                 }
-                FrameType::Metadata => {
-                    // Always forward metadata
+                FrameType::Metadata | FrameType::Data => {
+                    // Always forward metadata and timed data
+                }
+            }
+        }
+
+        // Hold the frame back until the pacing clocks allow it
+        if let Some(pacer) = playback.pacer.as_mut() {
+            let send_at = pacer.schedule(frame.timestamp, frame.data.len());
+            if send_at > Instant::now() {
+                playback.paced = Some((send_at, frame));
+                return Ok(());
+            }
+        }
+
+        self.deliver_frame(stream_id, frame).await
+    }
+
+    /// Send the frames whose pacing send time has come
+    async fn send_paced_frames(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        for (&stream_id, playback) in &mut self.subscribed_to {
+            if playback.paced.as_ref().is_some_and(|(at, _)| *at <= now) {
+                if let Some((_, frame)) = playback.paced.take() {
+                    due.push((stream_id, frame));
                 }
             }
         }
+        for (stream_id, frame) in due {
+            self.deliver_frame(stream_id, frame).await?;
+        }
+        Ok(())
+    }
+
+    /// Earliest pacing send time of the frames held back
+    fn next_paced_at(&self) -> Option<Instant> {
+        self.subscribed_to
+            .values()
+            .filter_map(|playback| playback.paced.as_ref().map(|(at, _)| *at))
+            .min()
+    }
+
+    /// Write a frame that passed the playback's filters and pacing
+    async fn deliver_frame(&mut self, stream_id: u32, frame: BroadcastFrame) -> Result<()> {
+        // Keyframes and large frames go out immediately even when coalescing
+        let len = frame.data.len();
+        let urgent = frame.is_keyframe || len >= self.config.write_buffer_size / 2;
+
+        // Frames flow again, so earlier drops are complete
+        if self
+            .subscribed_to
+            .get(&stream_id)
+            .is_some_and(|playback| playback.unreported_drops > 0)
+        {
+            self.report_drops().await;
+        }
 
         // Send the frame based on type
         match frame.frame_type {
@@ -1857,12 +3037,60 @@ impl<H: RtmpHandler> Connection<H> {
                 // Send metadata as data message
                 self.send_metadata_frame(stream_id, frame.data).await?;
             }
+            FrameType::Data => {
+                self.send_data_frame(stream_id, frame.timestamp, frame.data)
+                    .await?;
+            }
         }
 
-        // Periodically flush to ensure data is sent
-        // The flush happens less frequently when sending many frames
-        self.writer.flush().await?;
+        self.context.stats.frames_delivered += 1;
+        self.context.stats.bytes_sent += len as u64;
+
+        self.flush_media(urgent).await
+    }
+
+    /// Tell the subscriber the publisher stopped
+    ///
+    /// Sends NetStream.Play.UnpublishNotify; the end-of-sequence frames
+    /// came before it with the rest of the media. The subscription stays,
+    /// so playback resumes if the stream is published again.
+    async fn handle_unpublished(&mut self, stream_id: u32) -> Result<()> {
+        let Some(playback) = self.subscribed_to.get_mut(&stream_id) else {
+            return Ok(());
+        };
+        if let Some(unpublished) = playback.unpublished.as_mut() {
+            unpublished.borrow_and_update();
+        }
+        let stream_key = playback.key.name.clone();
+
+        let status = Command::on_status(
+            stream_id,
+            "status",
+            NS_PLAY_UNPUBLISH_NOTIFY,
+            &format!("{} is now unpublished", stream_key),
+        );
+        self.send_command(CSID_COMMAND, stream_id, &status).await?;
+
+        tracing::info!(
+            session_id = self.state.id,
+            stream_id = stream_id,
+            "Publisher stopped, notified subscriber"
+        );
+        Ok(())
+    }
 
+    /// Flush media writes, or defer the flush when write coalescing is enabled
+    async fn flush_media(&mut self, urgent: bool) -> Result<()> {
+        match self.config.write_flush_deadline {
+            deadline if !deadline.is_zero() && !urgent => {
+                self.flush_deadline
+                    .get_or_insert_with(|| Instant::now() + deadline);
+            }
+            _ => {
+                self.writer.flush().await?;
+                self.flush_deadline = None;
+            }
+        }
         Ok(())
     }
 
@@ -1889,10 +3117,3689 @@ impl<H: RtmpHandler> Connection<H> {
 
         self.write_buf.clear();
         self.chunk_encoder.encode(&chunk, &mut self.write_buf);
-        self.writer.write_all(&self.write_buf).await?;
+        self.write_encoded().await?;
+
+        Ok(())
+    }
+
+    /// Send a timed data message (`onCuePoint`, `onTextData`) to subscriber
+    ///
+    /// Unlike metadata it keeps its timestamp, so players handle it at the
+    /// right point in the media.
+    async fn send_data_frame(&mut self, stream_id: u32, timestamp: u32, data: Bytes) -> Result<()> {
+        let chunk = RtmpChunk {
+            csid: CSID_COMMAND,
+            timestamp,
+            message_type: MSG_DATA_AMF0,
+            stream_id,
+            payload: data,
+        };
+
+        self.write_buf.clear();
+        self.chunk_encoder.encode(&chunk, &mut self.write_buf);
+        self.write_encoded().await?;
 
         Ok(())
     }
 }
 
-use bytes::Buf;
+impl<H: RtmpHandler, S: Transport> Drop for Connection<H, S> {
+    fn drop(&mut self) {
+        if let Some(pool) = self.buffer_pool.take() {
+            pool.put(
+                std::mem::take(&mut self.read_buf),
+                self.config.read_buffer_size,
+            );
+            pool.put(
+                std::mem::take(&mut self.write_buf),
+                self.config.write_buffer_size,
+            );
+        }
+    }
+}
+
+/// Protocol control messages sent in response to a successful `connect`.
+///
+/// Order matters: Window Acknowledgement Size, Set Peer Bandwidth, then Set
+/// Chunk Size, all before the `_result`. Some encoders key their behavior
+/// off this sequence.
+fn connect_control_messages(config: &ServerConfig, chunk_size: u32) -> [RtmpMessage; 3] {
+    [
+        RtmpMessage::WindowAckSize(config.window_ack_size),
+        RtmpMessage::SetPeerBandwidth {
+            size: config.peer_bandwidth,
+            limit_type: BANDWIDTH_LIMIT_DYNAMIC,
+        },
+        RtmpMessage::SetChunkSize(chunk_size),
+    ]
+}
+
+/// Whether an audio message is too short to carry a frame
+///
+/// Empty messages and a lone SoundFormat byte are keepalives from some
+/// encoders; enhanced audio needs its FourCC.
+fn audio_payload_too_short(data: &[u8]) -> bool {
+    match data.first() {
+        None => true,
+        Some(&b) if EnhancedAudioData::is_enhanced(b) => data.len() < 5,
+        Some(_) => data.len() < 2,
+    }
+}
+
+/// Whether a video message is too short to carry a frame
+///
+/// Legacy AVC needs the packet type and composition time after the header
+/// byte, except for command frames; enhanced video needs its FourCC.
+fn video_payload_too_short(data: &[u8]) -> bool {
+    match data.first() {
+        None => true,
+        Some(&b) if EnhancedVideoData::is_enhanced(b) => data.len() < 5,
+        Some(&b) if b & 0x0F == 7 && b >> 4 != 5 => data.len() < 5,
+        Some(_) => data.len() < 2,
+    }
+}
+
+/// Hold a frame that arrived before its sequence header
+///
+/// Video is held from a keyframe on, since the frames before one cannot be
+/// decoded, and a newer keyframe replaces what was held. Past the frame or
+/// byte limit, audio drops its oldest frames and video is dropped until
+/// the next keyframe.
+fn hold_frame(held: &mut HeldFrames, frame_type: FrameType, timestamp: u32, data: Bytes) {
+    if frame_type == FrameType::Video {
+        if data[0] >> 4 == 1 {
+            held.frames.clear();
+            held.bytes = 0;
+        } else if held.frames.is_empty() {
+            return;
+        }
+    }
+
+    held.bytes += data.len();
+    held.frames.push_back((timestamp, data));
+    while held.frames.len() > SequenceHeaderPolicy::MAX_HELD_FRAMES
+        || held.bytes > SequenceHeaderPolicy::MAX_HELD_BYTES
+    {
+        if frame_type == FrameType::Video {
+            held.frames.clear();
+            held.bytes = 0;
+            break;
+        }
+        if let Some((_, old)) = held.frames.pop_front() {
+            held.bytes -= old.len();
+        }
+    }
+}
+
+/// Whether a frame belongs to a track that needs a sequence header, and
+/// if so whether it is that header
+///
+/// Only legacy AAC and AVC are checked; AVC command frames and payloads
+/// too short to classify are left alone.
+fn sequence_header_role(frame_type: FrameType, data: &[u8]) -> Option<bool> {
+    if data.len() < 2 {
+        return None;
+    }
+    match frame_type {
+        FrameType::Audio if data[0] >> 4 == 10 => Some(data[1] == 0),
+        FrameType::Video
+            if !EnhancedVideoData::is_enhanced(data[0])
+                && data[0] & 0x0F == 7
+                && data[0] >> 4 != 5 =>
+        {
+            Some(data[1] == 0)
+        }
+        _ => None,
+    }
+}
+
+/// Apply a timestamp delta clamp, logging when it kicks in
+fn clamp_timestamp(clamp: &mut TimestampDeltaClamp, timestamp: u32, media: &str) -> u32 {
+    let (clamped, was_clamped) = clamp.clamp(timestamp);
+    if was_clamped {
+        tracing::warn!(
+            media = media,
+            timestamp = timestamp,
+            clamped = clamped,
+            "Clamped oversized timestamp delta"
+        );
+    }
+    clamped
+}
+
+/// Wait until one of the streams published or played is evicted
+async fn wait_for_eviction(signals: Vec<watch::Receiver<bool>>) {
+    async fn evicted(mut rx: watch::Receiver<bool>) {
+        // An error means the stream was removed without eviction
+        if rx.wait_for(|evicted| *evicted).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    first_of(signals.into_iter().map(evicted).collect()).await
+}
+
+/// Wait until the publisher of a played stream stops cleanly; returns the
+/// playback's message stream ID
+async fn wait_for_unpublish(signals: Vec<(u32, watch::Receiver<u64>)>) -> u32 {
+    async fn unpublished(stream_id: u32, mut rx: watch::Receiver<u64>) -> u32 {
+        // An error means the stream was removed
+        if rx.changed().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+        stream_id
+    }
+
+    let unpublishes = signals
+        .into_iter()
+        .map(|(stream_id, rx)| unpublished(stream_id, rx));
+    first_of(unpublishes.collect()).await
+}
+
+/// Wait until another publisher takes over a stream this session publishes
+async fn wait_for_takeover(signals: Vec<watch::Receiver<Option<u64>>>, session_id: u64) {
+    async fn replaced(mut rx: watch::Receiver<Option<u64>>, session_id: u64) {
+        // An error means the stream was removed
+        let replaced = rx.wait_for(|replaced| *replaced == Some(session_id));
+        if replaced.await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+
+    let takeovers = signals.into_iter().map(|rx| replaced(rx, session_id));
+    first_of(takeovers.collect()).await
+}
+
+/// Receive the next frame of any playback, with its message stream ID
+async fn recv_frame(
+    receivers: &mut HashMap<u32, broadcast::Receiver<BroadcastFrame>>,
+) -> (
+    u32,
+    std::result::Result<BroadcastFrame, broadcast::error::RecvError>,
+) {
+    async fn recv(
+        stream_id: u32,
+        rx: &mut broadcast::Receiver<BroadcastFrame>,
+    ) -> (
+        u32,
+        std::result::Result<BroadcastFrame, broadcast::error::RecvError>,
+    ) {
+        (stream_id, rx.recv().await)
+    }
+
+    // One playback is the common case, and is waited on without allocating
+    if receivers.len() == 1 {
+        if let Some((&stream_id, rx)) = receivers.iter_mut().next() {
+            return recv(stream_id, rx).await;
+        }
+    }
+    let frames = receivers
+        .iter_mut()
+        .map(|(&stream_id, rx)| recv(stream_id, rx))
+        .collect();
+    first_of(frames).await
+}
+
+/// Wait for whichever of `futures` finishes first, or forever if there are
+/// none
+async fn first_of<F: Future>(mut futures: Vec<F>) -> F::Output {
+    if futures.len() <= 1 {
+        return match futures.pop() {
+            Some(future) => future.await,
+            None => std::future::pending().await,
+        };
+    }
+
+    let mut futures: Vec<_> = futures.into_iter().map(Box::pin).collect();
+    std::future::poll_fn(|cx| {
+        for future in futures.iter_mut() {
+            if let Poll::Ready(output) = future.as_mut().poll(cx) {
+                return Poll::Ready(output);
+            }
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Receive the next control request, or never resolve without a channel
+async fn recv_control(
+    rx: &mut Option<mpsc::UnboundedReceiver<SessionControl>>,
+) -> Option<SessionControl> {
+    match rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    use crate::client::{ClientConfig, RtmpConnector};
+    use crate::registry::CatchupStrategy;
+    use crate::session::context::ProtocolParams;
+    use crate::stats::SessionStats;
+    use crate::test_util::{spawn_session, wait_until};
+    use crate::transport::DuplexTransport;
+
+    /// Handler that records disconnect reasons
+    #[derive(Clone, Default)]
+    struct RecordingHandler {
+        reasons: Arc<Mutex<Vec<DisconnectReason>>>,
+    }
+
+    impl RtmpHandler for RecordingHandler {
+        async fn on_disconnect(&self, _ctx: &SessionContext, reason: &DisconnectReason) {
+            self.reasons.lock().unwrap().push(reason.clone());
+        }
+    }
+
+    /// Complete the client side of the handshake
+    async fn client_handshake(client: &mut DuplexTransport) {
+        let mut handshake = Handshake::new(HandshakeRole::Client);
+        let c0c1 = handshake.generate_initial().unwrap();
+        client.write_all(&c0c1).await.unwrap();
+
+        let mut read_buf = BytesMut::new();
+        while !handshake.is_done() {
+            assert!(client.read_buf(&mut read_buf).await.unwrap() > 0);
+
+            let mut buf = Bytes::copy_from_slice(&read_buf);
+            if let Some(response) = handshake.process(&mut buf).unwrap() {
+                read_buf.advance(read_buf.len() - buf.len());
+                client.write_all(&response).await.unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_reason_peer_closed() {
+        let handler = RecordingHandler::default();
+        let (mut client, handle) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(handler.clone()),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        client_handshake(&mut client).await;
+        drop(client);
+
+        assert!(handle.await.unwrap().is_ok());
+        let reasons = handler.reasons.lock().unwrap();
+        assert_eq!(*reasons, vec![DisconnectReason::PeerClosed]);
+        assert!(!reasons[0].is_error());
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_reason_protocol_error() {
+        let handler = RecordingHandler::default();
+        let (mut client, handle) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(handler.clone()),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        client_handshake(&mut client).await;
+
+        // AMF0 command whose name is a number instead of a string
+        let mut encoder = ChunkEncoder::new();
+        let mut wire = BytesMut::new();
+        let chunk = RtmpChunk {
+            csid: CSID_COMMAND,
+            timestamp: 0,
+            message_type: MSG_COMMAND_AMF0,
+            stream_id: 0,
+            payload: Bytes::from_static(&[0x00, 0x3F, 0xF0, 0, 0, 0, 0, 0, 0]),
+        };
+        encoder.encode(&chunk, &mut wire);
+        client.write_all(&wire).await.unwrap();
+
+        assert!(handle.await.unwrap().is_err());
+        let reasons = handler.reasons.lock().unwrap();
+        assert_eq!(reasons.len(), 1);
+        assert!(matches!(reasons[0], DisconnectReason::ProtocolError(_)));
+        assert!(reasons[0].is_error());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_message_rejected() {
+        let handler = RecordingHandler::default();
+        let config = ServerConfig::default().max_message_size(64 * 1024);
+        let (mut client, handle) = spawn_session(
+            1,
+            config,
+            Arc::new(handler.clone()),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        client_handshake(&mut client).await;
+
+        // Type 0 header on csid 6 declaring a 16MB video message on
+        // stream 1; no payload follows
+        let header = [0x06, 0, 0, 0, 0xFF, 0xFF, 0xFF, MSG_VIDEO, 0x01, 0, 0, 0];
+        client.write_all(&header).await.unwrap();
+
+        let err = handle.await.unwrap().unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Protocol(ProtocolError::MessageTooLarge {
+                size: 0xFF_FFFF,
+                max: 65536,
+            })
+        ));
+        let reasons = handler.reasons.lock().unwrap();
+        assert!(matches!(reasons[..], [DisconnectReason::ProtocolError(_)]));
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_reason_handshake_failed() {
+        let handler = RecordingHandler::default();
+        let (mut client, handle) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(handler.clone()),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        // Invalid RTMP version byte followed by a full C1
+        let mut c0c1 = vec![0x01];
+        c0c1.extend_from_slice(&[0u8; HANDSHAKE_SIZE]);
+        client.write_all(&c0c1).await.unwrap();
+
+        assert!(handle.await.unwrap().is_err());
+        assert_eq!(
+            *handler.reasons.lock().unwrap(),
+            vec![DisconnectReason::HandshakeFailed]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_coalesced_writes_still_parse() {
+        let registry = Arc::new(StreamRegistry::new());
+        let key = StreamKey::new("live", "test");
+        registry.register_publisher(&key, 99).await.unwrap();
+
+        let config =
+            ServerConfig::default().write_flush_deadline(std::time::Duration::from_millis(20));
+        let (client_side, _) = spawn_session(
+            1,
+            config,
+            Arc::new(RecordingHandler::default()),
+            registry.clone(),
+        );
+
+        let mut client = RtmpConnector::connect_with_transport(
+            ClientConfig::new("rtmp://localhost/live"),
+            client_side,
+        )
+        .await
+        .unwrap();
+        client.play("test").await.unwrap();
+
+        // A keyframe followed by a burst of small audio frames that get
+        // coalesced into a single write
+        let keyframe = Bytes::from(vec![0x17; 6000]);
+        registry
+            .broadcast(
+                &key,
+                BroadcastFrame::video(0, keyframe.clone(), true, false),
+            )
+            .await;
+        for i in 0..20u32 {
+            let audio = Bytes::from(vec![0xAF, 0x01, i as u8, 0x55]);
+            registry
+                .broadcast(&key, BroadcastFrame::audio(i * 23, audio, false))
+                .await;
+        }
+
+        let mut audio_seen = 0u32;
+        let mut video_seen = false;
+        while audio_seen < 20 {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), client.read_message())
+                    .await
+                    .expect("coalesced frames were never flushed")
+                    .unwrap();
+            match msg {
+                RtmpMessage::Video { timestamp, data } => {
+                    assert_eq!(timestamp, 0);
+                    assert_eq!(data, keyframe);
+                    video_seen = true;
+                }
+                RtmpMessage::Audio { timestamp, data } => {
+                    assert_eq!(timestamp, audio_seen * 23);
+                    assert_eq!(data.as_ref(), &[0xAF, 0x01, audio_seen as u8, 0x55]);
+                    audio_seen += 1;
+                }
+                _ => {}
+            }
+        }
+        assert!(video_seen);
+    }
+
+    #[tokio::test]
+    async fn test_coalesced_writes_flushed_after_playback_ends() {
+        let registry = Arc::new(StreamRegistry::new());
+        let key = StreamKey::new("live", "test");
+        registry.register_publisher(&key, 99).await.unwrap();
+
+        let config =
+            ServerConfig::default().write_flush_deadline(std::time::Duration::from_millis(100));
+        let (client_side, _) = spawn_session(
+            1,
+            config,
+            Arc::new(RecordingHandler::default()),
+            registry.clone(),
+        );
+
+        let mut client = RtmpConnector::connect_with_transport(
+            ClientConfig::new("rtmp://localhost/live"),
+            client_side,
+        )
+        .await
+        .unwrap();
+        client.play("test").await.unwrap();
+
+        // The frame is still coalesced when the playback ends, and the
+        // client sends nothing more that would wake the session
+        let audio = Bytes::from_static(&[0xAF, 0x01, 0x42]);
+        registry
+            .broadcast(&key, BroadcastFrame::audio(23, audio.clone(), false))
+            .await;
+        client.delete_stream().await.unwrap();
+
+        loop {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), client.read_message())
+                    .await
+                    .expect("coalesced frame was never flushed")
+                    .unwrap();
+            if let RtmpMessage::Audio { timestamp, data } = msg {
+                assert_eq!(timestamp, 23);
+                assert_eq!(data, audio);
+                break;
+            }
+        }
+    }
+
+    /// Transport counting the writes that reach it
+    struct CountingTransport {
+        inner: crate::transport::DuplexTransport,
+        writes: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl tokio::io::AsyncRead for CountingTransport {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::pin::Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl tokio::io::AsyncWrite for CountingTransport {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            let result = std::pin::Pin::new(&mut self.inner).poll_write(cx, buf);
+            if result.is_ready() {
+                self.writes
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            }
+            result
+        }
+
+        fn poll_flush(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::pin::Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::pin::Pin::new(&mut self.inner).poll_shutdown(cx)
+        }
+    }
+
+    /// Play a burst of small frames; returns the writes it took and how
+    /// long the last frame took to arrive
+    async fn play_burst(deadline: std::time::Duration) -> (usize, std::time::Duration) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let registry = Arc::new(StreamRegistry::new());
+        let key = StreamKey::new("live", "test");
+        registry.register_publisher(&key, 99).await.unwrap();
+
+        let (server_side, client_side) = DuplexTransport::pair(1024 * 1024);
+        let writes = Arc::new(AtomicUsize::new(0));
+        let mut connection = Connection::new(
+            1,
+            CountingTransport {
+                inner: server_side,
+                writes: writes.clone(),
+            },
+            "127.0.0.1:1935".parse().unwrap(),
+            ServerConfig::default().write_flush_deadline(deadline),
+            Arc::new(RecordingHandler::default()),
+            registry.clone(),
+        );
+        tokio::spawn(async move { connection.run().await });
+        let mut client = RtmpConnector::connect_with_transport(
+            ClientConfig::new("rtmp://localhost/live"),
+            client_side,
+        )
+        .await
+        .unwrap();
+        client.play("test").await.unwrap();
+
+        let before = writes.load(Ordering::Relaxed);
+        let sent_at = std::time::Instant::now();
+        for i in 0..20u32 {
+            let audio = Bytes::from(vec![0xAF, 0x01, i as u8]);
+            registry
+                .broadcast(&key, BroadcastFrame::audio(i * 23, audio, false))
+                .await;
+        }
+
+        let mut received = 0;
+        while received < 20 {
+            if let (MSG_AUDIO, _, _) = next_media(&mut client).await {
+                received += 1;
+            }
+        }
+        (writes.load(Ordering::Relaxed) - before, sent_at.elapsed())
+    }
+
+    #[tokio::test]
+    async fn test_write_flush_deadline_batches_writes() {
+        let deadline = std::time::Duration::from_millis(50);
+        let (immediate_writes, _) = play_burst(std::time::Duration::ZERO).await;
+        let (batched_writes, latency) = play_burst(deadline).await;
+
+        // Every frame is written on its own without a deadline, along with
+        // any control messages that happen to go out; with one the burst
+        // goes out in a few writes
+        assert!(immediate_writes >= 20, "{} writes", immediate_writes);
+        assert!(batched_writes <= 2, "{} writes", batched_writes);
+
+        // Nothing waits much past the deadline
+        assert!(latency < deadline * 10, "{:?}", latency);
+    }
+
+    /// Handler that keeps the session stats seen at disconnect
+    #[derive(Clone, Default)]
+    struct StatsHandler {
+        stats: Arc<Mutex<Option<SessionStats>>>,
+    }
+
+    impl RtmpHandler for StatsHandler {
+        async fn on_disconnect(&self, ctx: &SessionContext, _reason: &DisconnectReason) {
+            *self.stats.lock().unwrap() = Some(ctx.stats.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_stats_tally_delivered_and_dropped() {
+        use crate::registry::RegistryConfig;
+
+        let registry = Arc::new(StreamRegistry::with_config(
+            RegistryConfig::default().broadcast_capacity(4),
+        ));
+        let key = StreamKey::new("live", "test");
+        registry.register_publisher(&key, 99).await.unwrap();
+
+        let handler = StatsHandler::default();
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(handler.clone()),
+            registry.clone(),
+        );
+
+        let mut client = RtmpConnector::connect_with_transport(
+            ClientConfig::new("rtmp://localhost/live"),
+            client_side,
+        )
+        .await
+        .unwrap();
+        client.play("test").await.unwrap();
+
+        // Overrun the 4-slot channel: the oldest 6 frames are lost to lag
+        for i in 0..10u32 {
+            let audio = Bytes::from(vec![0xAF, 0x01, i as u8]);
+            registry
+                .broadcast(&key, BroadcastFrame::audio(i * 23, audio, false))
+                .await;
+        }
+
+        let mut received = 0;
+        while received < 4 {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), client.read_message())
+                    .await
+                    .expect("frames were never delivered")
+                    .unwrap();
+            if matches!(msg, RtmpMessage::Audio { .. }) {
+                received += 1;
+            }
+        }
+        drop(client);
+
+        wait_until(std::time::Duration::from_secs(5), || async {
+            handler.stats.lock().unwrap().is_some()
+        })
+        .await;
+        let stats = handler.stats.lock().unwrap().clone().unwrap();
+
+        assert_eq!(stats.frames_delivered, 4);
+        assert_eq!(stats.dropped_frames, 6);
+        assert_eq!(stats.bytes_sent, 12);
+        let stream = registry.get_stream_stats(&key).await.unwrap();
+        assert_eq!(stream.subscriber_count, 0);
+        assert_eq!(stream.dropped_frames, 6);
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscriber_dropped_frames_grow() {
+        use crate::registry::RegistryConfig;
+
+        let registry = Arc::new(StreamRegistry::with_config(
+            RegistryConfig::default().broadcast_capacity(4),
+        ));
+        let key = StreamKey::new("live", "test");
+        registry.register_publisher(&key, 99).await.unwrap();
+
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(RecordingHandler::default()),
+            registry.clone(),
+        );
+
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut client = RtmpConnector::connect_with_transport(config, client_side)
+            .await
+            .unwrap();
+        client.play("test").await.unwrap();
+
+        // Each burst overruns the 4-slot channel by 6 frames
+        for (burst, expected) in [(0u32, 6), (1, 12)] {
+            for i in 0..10u32 {
+                let audio = Bytes::from(vec![0xAF, 0x01, i as u8]);
+                let timestamp = (burst * 10 + i) * 23;
+                registry
+                    .broadcast(&key, BroadcastFrame::audio(timestamp, audio, false))
+                    .await;
+            }
+
+            let mut received = 0;
+            while received < 4 {
+                let msg =
+                    tokio::time::timeout(std::time::Duration::from_secs(5), client.read_message())
+                        .await
+                        .expect("frames were never delivered")
+                        .unwrap();
+                if matches!(msg, RtmpMessage::Audio { .. }) {
+                    received += 1;
+                }
+            }
+
+            let stream = registry.get_stream_stats(&key).await.unwrap();
+            assert_eq!(stream.dropped_frames, expected);
+        }
+    }
+
+    /// Handler that paces every player to 160 kbps (20 KB/s)
+    struct PacedHandler;
+
+    impl RtmpHandler for PacedHandler {
+        fn pace_bitrate_for(&self, _ctx: &SessionContext, _params: &PlayParams) -> Option<u64> {
+            Some(160_000)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_paced_subscriber_respects_bitrate() {
+        let registry = Arc::new(StreamRegistry::new());
+        let key = StreamKey::new("live", "test");
+        registry.register_publisher(&key, 99).await.unwrap();
+
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(PacedHandler),
+            registry.clone(),
+        );
+
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut client = RtmpConnector::connect_with_transport(config, client_side)
+            .await
+            .unwrap();
+        client.play("test").await.unwrap();
+
+        // A burst of 1000-byte frames sharing one timestamp
+        for i in 0..5u8 {
+            let mut audio = vec![0xAF, 0x01];
+            audio.resize(1000, i);
+            registry
+                .broadcast(&key, BroadcastFrame::audio(0, Bytes::from(audio), false))
+                .await;
+        }
+
+        let mut arrivals = Vec::new();
+        while arrivals.len() < 5 {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), client.read_message())
+                    .await
+                    .expect("paced frames never arrived")
+                    .unwrap();
+            if matches!(msg, RtmpMessage::Audio { .. }) {
+                arrivals.push(Instant::now());
+            }
+        }
+
+        // The last 4000 bytes need 200ms at the target bitrate
+        let window = arrivals[4] - arrivals[0];
+        assert!(
+            window >= std::time::Duration::from_millis(180),
+            "frames arrived too fast: {:?}",
+            window
+        );
+    }
+
+    #[tokio::test]
+    async fn test_paced_subscriber_keeps_answering_commands() {
+        let registry = Arc::new(StreamRegistry::new());
+        let key = StreamKey::new("live", "test");
+        registry.register_publisher(&key, 99).await.unwrap();
+
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(PacedHandler),
+            registry.clone(),
+        );
+
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut client = RtmpConnector::connect_with_transport(config, client_side)
+            .await
+            .unwrap();
+        client.play("test").await.unwrap();
+
+        // A second's worth of frames at the target bitrate
+        for i in 0..20u8 {
+            let mut audio = vec![0xAF, 0x01];
+            audio.resize(1000, i);
+            registry
+                .broadcast(&key, BroadcastFrame::audio(0, Bytes::from(audio), false))
+                .await;
+        }
+        loop {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), client.read_message())
+                    .await
+                    .expect("paced frames never arrived")
+                    .unwrap();
+            if matches!(msg, RtmpMessage::Audio { .. }) {
+                break;
+            }
+        }
+
+        // Commands are answered while frames wait for their send time
+        let started = Instant::now();
+        client.create_stream().await.unwrap();
+        assert!(
+            started.elapsed() < std::time::Duration::from_millis(500),
+            "command waited for pacing: {:?}",
+            started.elapsed()
+        );
+    }
+
+    /// Handler that records unpublished stream keys
+    #[derive(Clone, Default)]
+    struct UnpublishHandler {
+        unpublished: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl RtmpHandler for UnpublishHandler {
+        async fn on_unpublish(&self, ctx: &StreamContext) {
+            self.unpublished
+                .lock()
+                .unwrap()
+                .push(ctx.stream_key.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_stream_allows_republish() {
+        let registry = Arc::new(StreamRegistry::new());
+        let handler = UnpublishHandler::default();
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(handler.clone()),
+            registry.clone(),
+        );
+
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut client = RtmpConnector::connect_with_transport(config, client_side)
+            .await
+            .unwrap();
+        client.publish("first").await.unwrap();
+        client.delete_stream().await.unwrap();
+        client.publish("second").await.unwrap();
+
+        assert_eq!(
+            *handler.unpublished.lock().unwrap(),
+            vec!["first".to_string()]
+        );
+
+        let first = registry
+            .get_stream_stats(&StreamKey::new("live", "first"))
+            .await
+            .unwrap();
+        assert!(!first.has_publisher);
+        let second = registry
+            .get_stream_stats(&StreamKey::new("live", "second"))
+            .await
+            .unwrap();
+        assert!(second.has_publisher);
+    }
+
+    /// Read messages until an audio or video message arrives
+    async fn next_media<S: crate::transport::Transport>(
+        client: &mut crate::client::RtmpConnector<S>,
+    ) -> (u8, u32, Bytes) {
+        loop {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), client.read_message())
+                    .await
+                    .expect("no media arrived")
+                    .unwrap();
+            match msg {
+                RtmpMessage::Audio { timestamp, data } => return (MSG_AUDIO, timestamp, data),
+                RtmpMessage::Video { timestamp, data } => return (MSG_VIDEO, timestamp, data),
+                _ => {}
+            }
+        }
+    }
+
+    /// Read messages until an onStatus with `code` arrives
+    async fn wait_for_status<S: crate::transport::Transport>(
+        client: &mut crate::client::RtmpConnector<S>,
+        code: &str,
+    ) {
+        loop {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), client.read_message())
+                    .await
+                    .expect("status never arrived")
+                    .unwrap();
+            if let RtmpMessage::Command(cmd) = msg {
+                let info = cmd.arguments.first().and_then(|v| v.as_object());
+                if info.and_then(|i| i.get("code")).and_then(|c| c.as_str()) == Some(code) {
+                    return;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_play_relays_published_frames() {
+        use crate::client::CommandBuilder;
+
+        let registry = Arc::new(StreamRegistry::new());
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut clients = Vec::new();
+        for session_id in 1..=2 {
+            let (client_side, _) = spawn_session(
+                session_id,
+                ServerConfig::default(),
+                Arc::new(RecordingHandler::default()),
+                registry.clone(),
+            );
+            let client = RtmpConnector::connect_with_transport(config.clone(), client_side)
+                .await
+                .unwrap();
+            clients.push(client);
+        }
+        let (publisher, player) = clients.split_at_mut(1);
+        let (publisher, player) = (&mut publisher[0], &mut player[0]);
+
+        let video_header = Bytes::from_static(&[0x17, 0x00, 0, 0, 0, 0x01, 0x64]);
+        let audio_header = Bytes::from_static(&[0xAF, 0x00, 0x12, 0x10]);
+        let keyframe = |n: u8| Bytes::from(vec![0x17, 0x01, 0, 0, 0, n]);
+        let inter = |n: u8| Bytes::from(vec![0x27, 0x01, 0, 0, 0, n]);
+        let audio = |n: u8| Bytes::from(vec![0xAF, 0x01, n]);
+
+        // Publish headers and a keyframe before anyone plays
+        publisher.publish("test").await.unwrap();
+        publisher
+            .send_video_data(video_header.clone(), 0)
+            .await
+            .unwrap();
+        publisher
+            .send_audio_data(audio_header.clone(), 0)
+            .await
+            .unwrap();
+        publisher.send_video_data(keyframe(1), 0).await.unwrap();
+        let key = StreamKey::new("live", "test");
+        wait_until(std::time::Duration::from_secs(5), || async {
+            registry
+                .get_stream_stats(&key)
+                .await
+                .unwrap()
+                .gop_frame_count
+                == 1
+        })
+        .await;
+
+        // A late joiner starts with the catchup, then gets live frames
+        player.play("test").await.unwrap();
+        publisher.send_audio_data(audio(2), 23).await.unwrap();
+        publisher.send_video_data(inter(3), 33).await.unwrap();
+        publisher.send_audio_data(audio(4), 46).await.unwrap();
+        publisher.send_video_data(inter(5), 66).await.unwrap();
+
+        let expected = vec![
+            (MSG_VIDEO, 0, video_header.clone()),
+            (MSG_AUDIO, 0, audio_header.clone()),
+            (MSG_VIDEO, 0, keyframe(1)),
+            (MSG_AUDIO, 23, audio(2)),
+            (MSG_VIDEO, 33, inter(3)),
+            (MSG_AUDIO, 46, audio(4)),
+            (MSG_VIDEO, 66, inter(5)),
+        ];
+        let mut received = Vec::new();
+        for _ in 0..expected.len() {
+            received.push(next_media(player).await);
+        }
+        assert_eq!(received, expected);
+
+        // Paused, nothing is delivered
+        let stream_id = player.stream_id();
+        let pause = |paused: bool| {
+            CommandBuilder::new(CMD_PAUSE)
+                .argument(AmfValue::Boolean(paused))
+                .argument(AmfValue::Number(66.0))
+                .stream_id(stream_id)
+                .build()
+        };
+        player.send_command(&pause(true)).await.unwrap();
+        wait_for_status(player, NS_PAUSE_NOTIFY).await;
+        publisher.send_video_data(inter(6), 100).await.unwrap();
+
+        // On resume the sequence headers come again, then playback picks
+        // up at the next keyframe
+        player.send_command(&pause(false)).await.unwrap();
+        wait_for_status(player, NS_UNPAUSE_NOTIFY).await;
+        publisher.send_audio_data(audio(7), 110).await.unwrap();
+        publisher.send_video_data(keyframe(8), 133).await.unwrap();
+        publisher.send_audio_data(audio(9), 143).await.unwrap();
+
+        let expected = vec![
+            (MSG_VIDEO, 0, video_header),
+            (MSG_AUDIO, 0, audio_header),
+            (MSG_VIDEO, 133, keyframe(8)),
+            (MSG_AUDIO, 143, audio(9)),
+        ];
+        let mut received = Vec::new();
+        for _ in 0..expected.len() {
+            received.push(next_media(player).await);
+        }
+        assert_eq!(received, expected);
+    }
+
+    #[tokio::test]
+    async fn test_clean_unpublish_notifies_players() {
+        let registry = Arc::new(StreamRegistry::new());
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut clients = Vec::new();
+        for session_id in 1..=2 {
+            let (client_side, _) = spawn_session(
+                session_id,
+                ServerConfig::default(),
+                Arc::new(RecordingHandler::default()),
+                registry.clone(),
+            );
+            let client = RtmpConnector::connect_with_transport(config.clone(), client_side)
+                .await
+                .unwrap();
+            clients.push(client);
+        }
+        let (publisher, player) = clients.split_at_mut(1);
+        let (publisher, player) = (&mut publisher[0], &mut player[0]);
+
+        let video_header = Bytes::from_static(&[0x17, 0x00, 0, 0, 0, 0x01, 0x64]);
+        let audio_header = Bytes::from_static(&[0xAF, 0x00, 0x12, 0x10]);
+        let keyframe = Bytes::from_static(&[0x17, 0x01, 0, 0, 0, 0x65]);
+        publisher.publish("test").await.unwrap();
+        publisher
+            .send_video_data(video_header.clone(), 0)
+            .await
+            .unwrap();
+        publisher
+            .send_audio_data(audio_header.clone(), 0)
+            .await
+            .unwrap();
+        publisher
+            .send_video_data(keyframe.clone(), 0)
+            .await
+            .unwrap();
+        let key = StreamKey::new("live", "test");
+        wait_until(std::time::Duration::from_secs(5), || async {
+            registry
+                .get_stream_stats(&key)
+                .await
+                .unwrap()
+                .gop_frame_count
+                == 1
+        })
+        .await;
+
+        player.play("test").await.unwrap();
+        assert_eq!(next_media(player).await, (MSG_VIDEO, 0, video_header));
+        assert_eq!(next_media(player).await, (MSG_AUDIO, 0, audio_header));
+        assert_eq!(next_media(player).await, (MSG_VIDEO, 0, keyframe));
+
+        // Stopping cleanly ends both sequences and notifies the player
+        // instead of leaving it on the last frame for the grace period
+        publisher.delete_stream().await.unwrap();
+        assert_eq!(
+            next_media(player).await,
+            (MSG_VIDEO, 0, Bytes::from_static(&[0x17, 0x02, 0, 0, 0]))
+        );
+        assert_eq!(
+            next_media(player).await,
+            (
+                MSG_AUDIO,
+                0,
+                Bytes::from_static(&[0x92, b'm', b'p', b'4', b'a'])
+            )
+        );
+        wait_for_status(player, NS_PLAY_UNPUBLISH_NOTIFY).await;
+
+        let stats = registry.get_stream_stats(&key).await.unwrap();
+        assert_eq!(stats.state, crate::registry::StreamState::Idle);
+        assert_eq!(stats.subscriber_count, 1);
+    }
+
+    #[derive(Clone, Default)]
+    struct PlayCountHandler {
+        plays: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl RtmpHandler for PlayCountHandler {
+        async fn on_play(&self, _ctx: &SessionContext, params: &PlayParams) -> AuthResult {
+            self.plays.lock().unwrap().push(params.stream_name.clone());
+            AuthResult::Accept
+        }
+    }
+
+    #[tokio::test]
+    async fn test_app_policy_open_publish_token_play() {
+        use crate::client::CommandBuilder;
+        use crate::server::policy::{Access, AppPolicy};
+
+        let registry = Arc::new(StreamRegistry::new());
+        let handler = PlayCountHandler::default();
+        let server_config = ServerConfig::default()
+            .app_policy("live", AppPolicy::new(Access::Open, Access::TokenRequired))
+            .token_validator(|mode, _app, stream, token| {
+                mode == StreamMode::Playing && stream == "cam" && token == "secret"
+            });
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut clients = Vec::new();
+        for session_id in 1..=2 {
+            let (client_side, _) = spawn_session(
+                session_id,
+                server_config.clone(),
+                Arc::new(handler.clone()),
+                registry.clone(),
+            );
+            let client = RtmpConnector::connect_with_transport(config.clone(), client_side)
+                .await
+                .unwrap();
+            clients.push(client);
+        }
+        let (publisher, player) = clients.split_at_mut(1);
+        let (publisher, player) = (&mut publisher[0], &mut player[0]);
+
+        // Publishing needs no token
+        publisher.publish("cam").await.unwrap();
+        let header = Bytes::from_static(&[0x17, 0x00, 0, 0, 0, 0x01, 0x64, 0x00, 0x1F, 0xFF]);
+        let keyframe = Bytes::from_static(&[0x17, 0x01, 0, 0, 0, 0x65]);
+        publisher.send_video_data(header.clone(), 0).await.unwrap();
+        publisher
+            .send_video_data(keyframe.clone(), 0)
+            .await
+            .unwrap();
+
+        // Playing without a valid token is rejected before the handler
+        let stream_id = player.create_stream().await.unwrap();
+        for name in ["cam", "cam?token=wrong"] {
+            let play = CommandBuilder::play(name).stream_id(stream_id).build();
+            player.send_command(&play).await.unwrap();
+            wait_for_status(player, NS_PLAY_STREAM_NOT_FOUND).await;
+        }
+        assert!(handler.plays.lock().unwrap().is_empty());
+
+        // With the token the player joins the publisher's stream
+        player.play("cam?token=secret").await.unwrap();
+        assert_eq!(*handler.plays.lock().unwrap(), vec!["cam".to_string()]);
+        assert_eq!(next_media(player).await, (MSG_VIDEO, 0, header));
+        assert_eq!(next_media(player).await, (MSG_VIDEO, 0, keyframe));
+    }
+
+    #[derive(Clone, Default)]
+    struct PauseHandler {
+        paused: Arc<Mutex<Vec<bool>>>,
+    }
+
+    impl RtmpHandler for PauseHandler {
+        async fn on_pause_changed(&self, _ctx: &StreamContext, paused: bool) {
+            self.paused.lock().unwrap().push(paused);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pause_toggles_frame_flow() {
+        use crate::client::CommandBuilder;
+
+        let registry = Arc::new(StreamRegistry::new());
+        let handler = PauseHandler::default();
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut clients = Vec::new();
+        for session_id in 1..=2 {
+            let (client_side, _) = spawn_session(
+                session_id,
+                ServerConfig::default(),
+                Arc::new(handler.clone()),
+                registry.clone(),
+            );
+            let client = RtmpConnector::connect_with_transport(config.clone(), client_side)
+                .await
+                .unwrap();
+            clients.push(client);
+        }
+        let (publisher, player) = clients.split_at_mut(1);
+        let (publisher, player) = (&mut publisher[0], &mut player[0]);
+
+        let video_header = Bytes::from_static(&[0x17, 0x00, 0, 0, 0, 0x01, 0x64]);
+        let audio_header = Bytes::from_static(&[0xAF, 0x00, 0x12, 0x10]);
+        let keyframe = |n: u8| Bytes::from(vec![0x17, 0x01, 0, 0, 0, n]);
+        let inter = |n: u8| Bytes::from(vec![0x27, 0x01, 0, 0, 0, n]);
+        let audio = |n: u8| Bytes::from(vec![0xAF, 0x01, n]);
+
+        publisher.publish("test").await.unwrap();
+        publisher
+            .send_video_data(video_header.clone(), 0)
+            .await
+            .unwrap();
+        publisher
+            .send_audio_data(audio_header.clone(), 0)
+            .await
+            .unwrap();
+        publisher.send_video_data(keyframe(1), 0).await.unwrap();
+        player.play("test").await.unwrap();
+        for _ in 0..3 {
+            next_media(player).await;
+        }
+
+        let stream_id = player.stream_id();
+        let pause = |paused: bool, position: f64| {
+            CommandBuilder::new(CMD_PAUSE)
+                .argument(AmfValue::Boolean(paused))
+                .argument(AmfValue::Number(position))
+                .stream_id(stream_id)
+                .build()
+        };
+
+        // Frames published while paused are dropped, keyframes included
+        player.send_command(&pause(true, 0.0)).await.unwrap();
+        wait_for_status(player, NS_PAUSE_NOTIFY).await;
+        publisher.send_video_data(inter(2), 33).await.unwrap();
+        publisher.send_video_data(keyframe(3), 66).await.unwrap();
+        publisher.send_audio_data(audio(4), 70).await.unwrap();
+        let key = StreamKey::new("live", "test");
+        wait_until(std::time::Duration::from_secs(5), || async {
+            registry
+                .get_stream_stats(&key)
+                .await
+                .unwrap()
+                .dropped_frames
+                == 3
+        })
+        .await;
+
+        // Nothing reaches the player before the resume notification
+        player.send_command(&pause(false, 0.0)).await.unwrap();
+        loop {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), player.read_message())
+                    .await
+                    .expect("status never arrived")
+                    .unwrap();
+            match msg {
+                RtmpMessage::Audio { .. } | RtmpMessage::Video { .. } => {
+                    panic!("media delivered while paused")
+                }
+                RtmpMessage::Command(cmd) => {
+                    let info = cmd.arguments.first().and_then(|v| v.as_object());
+                    let code = info.and_then(|i| i.get("code")).and_then(|c| c.as_str());
+                    if code == Some(NS_UNPAUSE_NOTIFY) {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Resuming sends the headers, then waits for the next keyframe
+        publisher.send_video_data(inter(5), 100).await.unwrap();
+        publisher.send_video_data(keyframe(6), 133).await.unwrap();
+        let expected = vec![
+            (MSG_VIDEO, 0, video_header),
+            (MSG_AUDIO, 0, audio_header),
+            (MSG_VIDEO, 133, keyframe(6)),
+        ];
+        let mut received = Vec::new();
+        for _ in 0..expected.len() {
+            received.push(next_media(player).await);
+        }
+        assert_eq!(received, expected);
+        assert_eq!(*handler.paused.lock().unwrap(), vec![true, false]);
+    }
+
+    /// Handler written against the separate pause and resume hooks
+    #[derive(Clone, Default)]
+    struct LegacyPauseHandler {
+        calls: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl RtmpHandler for LegacyPauseHandler {
+        async fn on_pause(&self, _ctx: &StreamContext) {
+            self.calls.lock().unwrap().push("pause");
+        }
+
+        async fn on_unpause(&self, _ctx: &StreamContext) {
+            self.calls.lock().unwrap().push("unpause");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pause_changed_defaults_to_pause_hooks() {
+        let handler = LegacyPauseHandler::default();
+        let session = SessionContext::new(1, "127.0.0.1:1935".parse().unwrap());
+        let ctx = StreamContext::new(session, 1, "test".into(), false);
+
+        handler.on_pause_changed(&ctx, true).await;
+        handler.on_pause_changed(&ctx, false).await;
+        assert_eq!(*handler.calls.lock().unwrap(), vec!["pause", "unpause"]);
+    }
+
+    /// Serves a recording of `duration` ms and records seeks
+    #[derive(Clone, Default)]
+    struct VodHandler {
+        duration: u32,
+        seeks: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl RtmpHandler for VodHandler {
+        async fn on_seek(&self, _ctx: &StreamContext, milliseconds: u32) -> bool {
+            self.seeks.lock().unwrap().push(milliseconds);
+            milliseconds <= self.duration
+        }
+    }
+
+    #[tokio::test]
+    async fn test_seek_resends_headers_at_position() {
+        use crate::client::CommandBuilder;
+
+        let registry = Arc::new(StreamRegistry::new());
+        let handler = VodHandler {
+            duration: 10_000,
+            ..Default::default()
+        };
+        let config = ClientConfig::new("rtmp://localhost/vod");
+        let mut clients = Vec::new();
+        for session_id in 1..=2 {
+            let (client_side, _) = spawn_session(
+                session_id,
+                ServerConfig::default(),
+                Arc::new(handler.clone()),
+                registry.clone(),
+            );
+            let client = RtmpConnector::connect_with_transport(config.clone(), client_side)
+                .await
+                .unwrap();
+            clients.push(client);
+        }
+        // The first client stands in for the handler's file source
+        let (source, player) = clients.split_at_mut(1);
+        let (source, player) = (&mut source[0], &mut player[0]);
+
+        let video_header = Bytes::from_static(&[0x17, 0x00, 0, 0, 0, 0x01, 0x64]);
+        let audio_header = Bytes::from_static(&[0xAF, 0x00, 0x12, 0x10]);
+        let keyframe = |n: u8| Bytes::from(vec![0x17, 0x01, 0, 0, 0, n]);
+        let inter = |n: u8| Bytes::from(vec![0x27, 0x01, 0, 0, 0, n]);
+
+        source.publish("movie").await.unwrap();
+        source
+            .send_video_data(video_header.clone(), 0)
+            .await
+            .unwrap();
+        source
+            .send_audio_data(audio_header.clone(), 0)
+            .await
+            .unwrap();
+        source.send_video_data(keyframe(1), 0).await.unwrap();
+        player.play("movie").await.unwrap();
+        for _ in 0..3 {
+            next_media(player).await;
+        }
+
+        let stream_id = player.stream_id();
+        let seek = |milliseconds: f64| {
+            CommandBuilder::new(CMD_SEEK)
+                .argument(AmfValue::Number(milliseconds))
+                .stream_id(stream_id)
+                .build()
+        };
+
+        // Past the end of the recording the handler refuses
+        player.send_command(&seek(60_000.0)).await.unwrap();
+        wait_for_status(player, NS_SEEK_FAILED).await;
+
+        // Headers come again at the new position, then playback restarts
+        // at the source's next keyframe
+        player.send_command(&seek(5_000.0)).await.unwrap();
+        wait_for_status(player, NS_SEEK_NOTIFY).await;
+        source.send_video_data(inter(2), 4_980).await.unwrap();
+        source.send_video_data(keyframe(3), 5_000).await.unwrap();
+
+        let expected = vec![
+            (MSG_VIDEO, 5_000, video_header),
+            (MSG_AUDIO, 5_000, audio_header),
+            (MSG_VIDEO, 5_000, keyframe(3)),
+        ];
+        let mut received = Vec::new();
+        for _ in 0..expected.len() {
+            received.push(next_media(player).await);
+        }
+        assert_eq!(received, expected);
+        assert_eq!(*handler.seeks.lock().unwrap(), vec![60_000, 5_000]);
+    }
+
+    #[tokio::test]
+    async fn test_seek_counts_discarded_frames_as_dropped() {
+        let registry = Arc::new(StreamRegistry::new());
+        let key = StreamKey::new("vod", "movie");
+        registry.register_publisher(&key, 99).await.unwrap();
+
+        let (server_side, _client_side) = DuplexTransport::pair(64 * 1024);
+        let mut connection = Connection::new(
+            1,
+            server_side,
+            "127.0.0.1:1935".parse().unwrap(),
+            ServerConfig::default(),
+            Arc::new(VodHandler {
+                duration: 10_000,
+                ..Default::default()
+            }),
+            registry.clone(),
+        );
+        let (rx, _) = registry
+            .subscribe_session(&key, 1, "127.0.0.1:1935".parse().unwrap())
+            .await
+            .unwrap();
+        connection.frame_rx.insert(1, rx);
+        connection
+            .subscribed_to
+            .insert(1, Playback::new(key.clone(), None));
+
+        // Frames for the old position are still queued when the seek runs
+        for i in 0..3u32 {
+            let audio = Bytes::from(vec![0xAF, 0x01, i as u8]);
+            registry
+                .broadcast(&key, BroadcastFrame::audio(i * 23, audio, false))
+                .await;
+        }
+        connection.do_seek(1, 5_000).await.unwrap();
+
+        assert_eq!(connection.context.stats.dropped_frames, 3);
+        let stream = registry.get_stream_stats(&key).await.unwrap();
+        assert_eq!(stream.dropped_frames, 3);
+    }
+
+    #[tokio::test]
+    async fn test_evict_stream_disconnects_sessions() {
+        let registry = Arc::new(StreamRegistry::new());
+        let handler = RecordingHandler::default();
+        let config = ClientConfig::new("rtmp://localhost/live");
+
+        let mut sessions = Vec::new();
+        let mut clients = Vec::new();
+        for session_id in 1..=2 {
+            let (client_side, session) = spawn_session(
+                session_id,
+                ServerConfig::default(),
+                Arc::new(handler.clone()),
+                registry.clone(),
+            );
+            sessions.push(session);
+            let client = RtmpConnector::connect_with_transport(config.clone(), client_side)
+                .await
+                .unwrap();
+            clients.push(client);
+        }
+        clients[0].publish("test").await.unwrap();
+        clients[1].play("test").await.unwrap();
+
+        let key = StreamKey::new("live", "test");
+        assert!(registry.evict_stream(&key).await);
+
+        for session in sessions {
+            tokio::time::timeout(std::time::Duration::from_secs(5), session)
+                .await
+                .expect("session not closed by eviction")
+                .unwrap()
+                .unwrap();
+        }
+        assert_eq!(
+            *handler.reasons.lock().unwrap(),
+            vec![DisconnectReason::Evicted, DisconnectReason::Evicted]
+        );
+        assert!(!registry.stream_exists(&key).await);
+    }
+
+    #[tokio::test]
+    async fn test_publisher_takeover_closes_replaced_publisher() {
+        use crate::registry::RegistryConfig;
+
+        let registry = Arc::new(StreamRegistry::with_config(
+            RegistryConfig::default().publisher_takeover(true),
+        ));
+        let handler = RecordingHandler::default();
+        let config = ClientConfig::new("rtmp://localhost/live");
+
+        let mut sessions = Vec::new();
+        let mut clients = Vec::new();
+        for session_id in 1..=2 {
+            let (client_side, session) = spawn_session(
+                session_id,
+                ServerConfig::default(),
+                Arc::new(handler.clone()),
+                registry.clone(),
+            );
+            sessions.push(session);
+            let client = RtmpConnector::connect_with_transport(config.clone(), client_side)
+                .await
+                .unwrap();
+            clients.push(client);
+        }
+        clients[0].publish("test").await.unwrap();
+        clients[1].publish("test").await.unwrap();
+
+        let replaced = sessions.remove(0);
+        tokio::time::timeout(std::time::Duration::from_secs(5), replaced)
+            .await
+            .expect("replaced publisher not closed")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            *handler.reasons.lock().unwrap(),
+            vec![DisconnectReason::PublisherTakeover]
+        );
+
+        // Closing the replaced session must leave the new publisher in place
+        let key = StreamKey::new("live", "test");
+        assert!(registry.has_active_stream(&key).await);
+        assert!(!sessions[0].is_finished());
+    }
+
+    /// Handler that records raw socket bytes
+    #[derive(Clone, Default)]
+    struct TapHandler {
+        inbound: Arc<Mutex<Vec<u8>>>,
+        outbound: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl RtmpHandler for TapHandler {
+        const TAPS_RAW: bool = true;
+
+        fn on_raw_bytes(&self, _ctx: &SessionContext, direction: ByteDirection, data: &[u8]) {
+            let wire = match direction {
+                ByteDirection::In => &self.inbound,
+                ByteDirection::Out => &self.outbound,
+            };
+            wire.lock().unwrap().extend_from_slice(data);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_raw_bytes_tap_sees_handshake_and_commands() {
+        let handler = TapHandler::default();
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(handler.clone()),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let _client = RtmpConnector::connect_with_transport(config, client_side)
+            .await
+            .unwrap();
+
+        let contains =
+            |haystack: &[u8], needle: &[u8]| haystack.windows(needle.len()).any(|w| w == needle);
+
+        // C0C1 + C2, then the connect command
+        let inbound = handler.inbound.lock().unwrap().clone();
+        assert_eq!(inbound[0], RTMP_VERSION);
+        assert!(inbound.len() > 1 + 2 * HANDSHAKE_SIZE);
+        assert!(contains(&inbound[1 + 2 * HANDSHAKE_SIZE..], b"connect"));
+
+        // S0S1S2, then the connect response
+        let outbound = handler.outbound.lock().unwrap().clone();
+        assert_eq!(outbound[0], RTMP_VERSION);
+        assert!(outbound.len() > 1 + 2 * HANDSHAKE_SIZE);
+        assert!(contains(&outbound[1 + 2 * HANDSHAKE_SIZE..], b"_result"));
+    }
+
+    #[tokio::test]
+    async fn test_pathological_timestamp_delta_clamped() {
+        use crate::protocol::quirks::QuirksConfig;
+
+        let registry = Arc::new(StreamRegistry::new());
+        let quirks = QuirksConfig {
+            max_timestamp_delta: Some(1000),
+            ..Default::default()
+        };
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default().quirks(quirks),
+            Arc::new(RecordingHandler::default()),
+            registry.clone(),
+        );
+
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut client = RtmpConnector::connect_with_transport(config, client_side)
+            .await
+            .unwrap();
+        client.publish("test").await.unwrap();
+
+        let key = StreamKey::new("live", "test");
+        let (mut rx, _) = registry.subscribe(&key).await.unwrap();
+
+        let frame = Bytes::from_static(&[0x27, 0x01, 0, 0, 0, 0, 0, 0, 2, 0x41, 0x9A]);
+        for timestamp in [0, 40, 0x00F0_0000, 0x00F0_0000 + 40] {
+            client
+                .send_video_data(frame.clone(), timestamp)
+                .await
+                .unwrap();
+        }
+
+        let mut timestamps = Vec::new();
+        while timestamps.len() < 4 {
+            let frame = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv())
+                .await
+                .expect("frames never broadcast")
+                .unwrap();
+            timestamps.push(frame.timestamp);
+        }
+        assert_eq!(timestamps, vec![0, 40, 1040, 1080]);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_media_skipped() {
+        let registry = Arc::new(StreamRegistry::new());
+        let handler = Arc::new(RecordingHandler::default());
+        let (client_side, session) = spawn_session(
+            1,
+            ServerConfig::default(),
+            handler.clone(),
+            registry.clone(),
+        );
+
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut client = RtmpConnector::connect_with_transport(config, client_side)
+            .await
+            .unwrap();
+        client.publish("test").await.unwrap();
+
+        let key = StreamKey::new("live", "test");
+        let (mut rx, _) = registry.subscribe(&key).await.unwrap();
+
+        for data in [&[][..], &[0x17], &[0x17, 0x01]] {
+            client
+                .send_video_data(Bytes::copy_from_slice(data), 0)
+                .await
+                .unwrap();
+        }
+        for data in [&[][..], &[0xAF]] {
+            client
+                .send_audio_data(Bytes::copy_from_slice(data), 0)
+                .await
+                .unwrap();
+        }
+        let frame = Bytes::from_static(&[0x27, 0x01, 0, 0, 0, 0, 0, 0, 2, 0x41, 0x9A]);
+        client.send_video_data(frame.clone(), 40).await.unwrap();
+
+        let received = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv())
+            .await
+            .expect("frame never broadcast")
+            .unwrap();
+        assert_eq!(received.timestamp, 40);
+        assert_eq!(received.data, frame);
+        assert!(!session.is_finished());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_media_skipped_in_strict_mode() {
+        use crate::protocol::quirks::QuirksConfig;
+
+        // Strict AMF parsing says nothing about media payloads
+        let config = ServerConfig::default().quirks(QuirksConfig {
+            lenient_amf: false,
+            ..Default::default()
+        });
+        let registry = Arc::new(StreamRegistry::new());
+        let (client_side, session) = spawn_session(
+            1,
+            config,
+            Arc::new(RecordingHandler::default()),
+            registry.clone(),
+        );
+
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut client = RtmpConnector::connect_with_transport(config, client_side)
+            .await
+            .unwrap();
+        client.publish("test").await.unwrap();
+
+        let key = StreamKey::new("live", "test");
+        let (mut rx, _) = registry.subscribe(&key).await.unwrap();
+
+        client
+            .send_audio_data(Bytes::from_static(&[0xAF]), 0)
+            .await
+            .unwrap();
+        let frame = Bytes::from_static(&[0xAF, 0x01, 0x21]);
+        client.send_audio_data(frame.clone(), 23).await.unwrap();
+
+        let received = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv())
+            .await
+            .expect("frame never broadcast")
+            .unwrap();
+        assert_eq!(received.timestamp, 23);
+        assert_eq!(received.data, frame);
+        assert!(!session.is_finished());
+    }
+
+    #[tokio::test]
+    async fn test_amf_recoveries_counted_in_session_stats() {
+        let (server_side, _client_side) = DuplexTransport::pair(64 * 1024);
+        let mut connection = Connection::new(
+            1,
+            server_side,
+            "127.0.0.1:1935".parse().unwrap(),
+            ServerConfig::default(),
+            Arc::new(RecordingHandler::default()),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        // Data message "foo" followed by an unknown AMF0 marker
+        let chunk = RtmpChunk {
+            csid: CSID_COMMAND,
+            timestamp: 0,
+            message_type: MSG_DATA_AMF0,
+            stream_id: 1,
+            payload: Bytes::from_static(&[0x02, 0x00, 0x03, b'f', b'o', b'o', 0xFF]),
+        };
+        connection.handle_chunk(chunk.clone()).await.unwrap();
+        assert_eq!(connection.context.stats.amf_recovered_errors, 1);
+
+        connection.handle_chunk(chunk).await.unwrap();
+        assert_eq!(connection.context.stats.amf_recovered_errors, 2);
+    }
+
+    /// Handler that asks players to reconnect to another node
+    #[derive(Clone, Default)]
+    struct ReconnectHandler {
+        requested: Arc<Mutex<Vec<bool>>>,
+        reasons: Arc<Mutex<Vec<DisconnectReason>>>,
+    }
+
+    impl RtmpHandler for ReconnectHandler {
+        async fn on_play(&self, ctx: &SessionContext, _params: &PlayParams) -> AuthResult {
+            let sent = ctx.request_reconnect(Some("rtmp://edge2.example.com/live".into()));
+            self.requested.lock().unwrap().push(sent);
+            AuthResult::Accept
+        }
+
+        async fn on_disconnect(&self, _ctx: &SessionContext, reason: &DisconnectReason) {
+            self.reasons.lock().unwrap().push(reason.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_request_sent_to_capable_client() {
+        use crate::client::config::EnhancedClientCapabilities;
+        use crate::server::config::EnhancedServerCapabilities;
+
+        let registry = Arc::new(StreamRegistry::new());
+        let key = StreamKey::new("live", "test");
+        registry.register_publisher(&key, 99).await.unwrap();
+
+        let config = ServerConfig::default()
+            .enhanced_capabilities(EnhancedServerCapabilities::default().with_reconnect());
+        let handler = ReconnectHandler::default();
+        let (client_side, handle) = spawn_session(1, config, Arc::new(handler.clone()), registry);
+
+        let client_config = ClientConfig::new("rtmp://localhost/live")
+            .enhanced_capabilities(EnhancedClientCapabilities::default().with_reconnect());
+        let mut client = RtmpConnector::connect_with_transport(client_config, client_side)
+            .await
+            .unwrap();
+        client.play("test").await.unwrap();
+
+        let info = loop {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), client.read_message())
+                    .await
+                    .expect("reconnect request was never sent")
+                    .unwrap();
+            if let RtmpMessage::Command(cmd) = msg {
+                let info = cmd.arguments.first().and_then(|a| a.as_object()).cloned();
+                if let Some(info) = info {
+                    if info.get("code").and_then(|c| c.as_str())
+                        == Some(NC_CONNECT_RECONNECT_REQUEST)
+                    {
+                        assert_eq!(cmd.name, CMD_ON_STATUS);
+                        assert_eq!(cmd.stream_id, 0);
+                        break info;
+                    }
+                }
+            }
+        };
+
+        assert_eq!(
+            info.get("tcUrl").and_then(|u| u.as_str()),
+            Some("rtmp://edge2.example.com/live")
+        );
+        assert!(handle.await.unwrap().is_ok());
+        assert_eq!(*handler.requested.lock().unwrap(), vec![true]);
+        assert_eq!(
+            *handler.reasons.lock().unwrap(),
+            vec![DisconnectReason::ReconnectRequested]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_publish_gets_bad_name() {
+        let registry = Arc::new(StreamRegistry::new());
+        let handler = RecordingHandler::default();
+        let mut clients = Vec::new();
+        for session_id in 1..=2 {
+            let (client_side, _) = spawn_session(
+                session_id,
+                ServerConfig::default(),
+                Arc::new(handler.clone()),
+                registry.clone(),
+            );
+            let client = RtmpConnector::connect_with_transport(
+                ClientConfig::new("rtmp://localhost/live"),
+                client_side,
+            )
+            .await
+            .unwrap();
+            clients.push(client);
+        }
+        let (first, second) = clients.split_at_mut(1);
+        let (first, second) = (&mut first[0], &mut second[0]);
+        first.publish("test").await.unwrap();
+
+        match second.publish("test").await {
+            Err(Error::Rejected(code)) => assert_eq!(code, NS_PUBLISH_BAD_NAME),
+            other => panic!("expected BadName, got {:?}", other),
+        }
+
+        // Only the second session was closed, with the reason attached
+        wait_until(std::time::Duration::from_secs(5), || async {
+            !handler.reasons.lock().unwrap().is_empty()
+        })
+        .await;
+        assert_eq!(
+            *handler.reasons.lock().unwrap(),
+            vec![DisconnectReason::Rejected(
+                "test is already being published".into()
+            )]
+        );
+    }
+
+    /// Handler that records parsed video frames
+    #[derive(Clone, Default)]
+    struct FrameHandler {
+        frames: Arc<Mutex<Vec<VideoFrame>>>,
+    }
+
+    impl RtmpHandler for FrameHandler {
+        async fn on_parsed_video_frame(
+            &self,
+            _ctx: &StreamContext,
+            frame: &VideoFrame,
+            _timestamp: u32,
+        ) {
+            self.frames.lock().unwrap().push(frame.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hevc_keyframe_delivers_parsed_frame() {
+        use crate::media::HevcData;
+
+        let handler = FrameHandler::default();
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(handler.clone()),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        let mut client = RtmpConnector::connect_with_transport(
+            ClientConfig::new("rtmp://localhost/live"),
+            client_side,
+        )
+        .await
+        .unwrap();
+        client.publish("test").await.unwrap();
+
+        // Enhanced keyframe, CodedFramesX, "hvc1", one length-prefixed NALU
+        let keyframe = Bytes::from_static(&[
+            0x93, b'h', b'v', b'c', b'1', 0x00, 0x00, 0x00, 0x02, 0x26, 0x01,
+        ]);
+        client.send_video_data(keyframe, 0).await.unwrap();
+
+        wait_until(std::time::Duration::from_secs(5), || async {
+            !handler.frames.lock().unwrap().is_empty()
+        })
+        .await;
+        let frames = handler.frames.lock().unwrap();
+        assert_eq!(frames.len(), 1);
+        assert!(matches!(
+            frames[0],
+            VideoFrame::Hevc(HevcData::Frame { keyframe: true, .. })
+        ));
+    }
+
+    /// Handler that records the stream context of each keyframe
+    #[derive(Clone, Default)]
+    struct KeyframeHandler {
+        contexts: Arc<Mutex<Vec<StreamContext>>>,
+    }
+
+    impl RtmpHandler for KeyframeHandler {
+        async fn on_keyframe(&self, ctx: &StreamContext, _timestamp: u32) {
+            self.contexts.lock().unwrap().push(ctx.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_context_exposes_sequence_headers() {
+        let handler = KeyframeHandler::default();
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(handler.clone()),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut client = RtmpConnector::connect_with_transport(config, client_side)
+            .await
+            .unwrap();
+        client.publish("test").await.unwrap();
+
+        // AVC sequence header wrapping an AVCDecoderConfigurationRecord
+        let avcc: &[u8] = &[
+            0x01, 0x64, 0x00, 0x1F, 0xFF, 0xE1, 0x00, 0x04, 0x67, 0x64, 0x00, 0x1F, 0x01, 0x00,
+            0x03, 0x68, 0xEF, 0x38,
+        ];
+        let mut video_header = vec![0x17, 0x00, 0x00, 0x00, 0x00];
+        video_header.extend_from_slice(avcc);
+        client
+            .send_video_data(Bytes::from(video_header), 0)
+            .await
+            .unwrap();
+
+        // AAC sequence header: AAC-LC, 44.1kHz, stereo
+        let asc: &[u8] = &[0x12, 0x10];
+        let audio_header = Bytes::from(vec![0xAF, 0x00, asc[0], asc[1]]);
+        client.send_audio_data(audio_header, 0).await.unwrap();
+
+        let keyframe = Bytes::from_static(&[0x17, 0x01, 0, 0, 0, 0, 0, 0, 2, 0x65, 0x88]);
+        client.send_video_data(keyframe, 40).await.unwrap();
+
+        wait_until(std::time::Duration::from_secs(5), || async {
+            !handler.contexts.lock().unwrap().is_empty()
+        })
+        .await;
+        let contexts = handler.contexts.lock().unwrap();
+        assert_eq!(contexts.len(), 1);
+
+        let video = contexts[0].video_config().expect("AVC config not cached");
+        assert_eq!(video.profile, 100);
+        assert_eq!(video.raw.as_ref(), avcc);
+
+        let audio = contexts[0].audio_config().expect("AAC config not cached");
+        assert_eq!(audio.sampling_frequency, 44100);
+        assert_eq!(audio.raw.as_ref(), asc);
+    }
+
+    /// Handler that stamps metadata with the server name and strips
+    /// private fields
+    #[derive(Clone, Default)]
+    struct MetadataHandler {
+        seen: Arc<Mutex<Vec<AmfObject>>>,
+    }
+
+    impl RtmpHandler for MetadataHandler {
+        async fn on_metadata_mut(&self, _ctx: &StreamContext, metadata: &mut AmfObject) -> bool {
+            metadata.insert("server".to_string(), AmfValue::String("rtmp-rs".into()));
+            metadata.retain(|key, _| key != "privateToken");
+            true
+        }
+
+        async fn on_metadata(&self, _ctx: &StreamContext, metadata: &AmfObject) {
+            self.seen.lock().unwrap().push(metadata.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rewritten_metadata_reaches_late_joiner() {
+        let registry = Arc::new(StreamRegistry::new());
+        let handler = MetadataHandler::default();
+        let config = ClientConfig::new("rtmp://localhost/live");
+
+        let mut clients = Vec::new();
+        for session_id in 1..=2 {
+            let (client_side, _) = spawn_session(
+                session_id,
+                ServerConfig::default(),
+                Arc::new(handler.clone()),
+                registry.clone(),
+            );
+            let client = RtmpConnector::connect_with_transport(config.clone(), client_side)
+                .await
+                .unwrap();
+            clients.push(client);
+        }
+
+        clients[0].publish("test").await.unwrap();
+        let mut metadata = AmfObject::new();
+        metadata.insert("width".to_string(), AmfValue::Number(1280.0));
+        metadata.insert(
+            "privateToken".to_string(),
+            AmfValue::String("secret".into()),
+        );
+        clients[0].send_metadata(&metadata).await.unwrap();
+
+        wait_until(std::time::Duration::from_secs(5), || async {
+            !handler.seen.lock().unwrap().is_empty()
+        })
+        .await;
+        let seen = handler.seen.lock().unwrap()[0].clone();
+        assert_eq!(
+            seen.get("server"),
+            Some(&AmfValue::String("rtmp-rs".into()))
+        );
+        assert!(!seen.contains_key("privateToken"));
+
+        // Joins after the metadata was sent, so gets the cached copy
+        clients[1].play("test").await.unwrap();
+        let received = loop {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), clients[1].read_message())
+                    .await
+                    .expect("metadata never arrived")
+                    .unwrap();
+            if let RtmpMessage::Data(data) = msg {
+                if data.name == CMD_ON_METADATA {
+                    break data.values[0].as_object().unwrap().clone();
+                }
+            }
+        };
+        assert_eq!(
+            received.get("server"),
+            Some(&AmfValue::String("rtmp-rs".into()))
+        );
+        assert_eq!(received.get("width"), Some(&AmfValue::Number(1280.0)));
+        assert!(!received.contains_key("privateToken"));
+    }
+
+    /// Handler that sends players asking for `?live` to the live edge
+    struct LiveEdgeHandler;
+
+    impl RtmpHandler for LiveEdgeHandler {
+        fn catchup_strategy_for(
+            &self,
+            _ctx: &SessionContext,
+            params: &PlayParams,
+        ) -> Option<CatchupStrategy> {
+            params
+                .stream_name
+                .ends_with("?live")
+                .then_some(CatchupStrategy::LiveEdge)
+        }
+
+        fn stream_key_for(&self, _ctx: &SessionContext, app: &str, name: &str) -> StreamKey {
+            StreamKey::new(app, name.trim_end_matches("?live"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_live_edge_player_starts_at_newest_frame() {
+        let registry = Arc::new(StreamRegistry::new());
+        let config = ClientConfig::new("rtmp://localhost/live");
+
+        let mut clients = Vec::new();
+        for session_id in 1..=3 {
+            let (client_side, _) = spawn_session(
+                session_id,
+                ServerConfig::default(),
+                Arc::new(LiveEdgeHandler),
+                registry.clone(),
+            );
+            let client = RtmpConnector::connect_with_transport(config.clone(), client_side)
+                .await
+                .unwrap();
+            clients.push(client);
+        }
+
+        clients[0].publish("test").await.unwrap();
+        let frames = [
+            (0, Bytes::from_static(&[0x17, 0x00, 0, 0, 0, 0x01])),
+            (0, Bytes::from_static(&[0x17, 0x01, 0, 0, 0, 0x65])),
+            (33, Bytes::from_static(&[0x27, 0x01, 0, 0, 0, 0x41])),
+            (66, Bytes::from_static(&[0x27, 0x01, 0, 0, 0, 0x41])),
+            (99, Bytes::from_static(&[0x27, 0x01, 0, 0, 0, 0x41])),
+        ];
+        for (timestamp, data) in frames {
+            clients[0].send_video_data(data, timestamp).await.unwrap();
+        }
+
+        let key = StreamKey::new("live", "test");
+        wait_until(std::time::Duration::from_secs(5), || async {
+            registry
+                .get_stream_stats(&key)
+                .await
+                .map(|s| s.gop_frame_count)
+                == Some(4)
+        })
+        .await;
+
+        /// Timestamps of the first `count` video frames after the header
+        async fn catchup(client: &mut RtmpConnector<DuplexTransport>, count: usize) -> Vec<u32> {
+            let mut timestamps = Vec::new();
+            while timestamps.len() < count {
+                let msg =
+                    tokio::time::timeout(std::time::Duration::from_secs(5), client.read_message())
+                        .await
+                        .expect("catchup never arrived")
+                        .unwrap();
+                if let RtmpMessage::Video { timestamp, data } = msg {
+                    if data[1] != 0x00 {
+                        timestamps.push(timestamp);
+                    }
+                }
+            }
+            timestamps
+        }
+
+        clients[1].play("test").await.unwrap();
+        assert_eq!(catchup(&mut clients[1], 4).await, vec![0, 33, 66, 99]);
+
+        clients[2].play("test?live").await.unwrap();
+        assert_eq!(catchup(&mut clients[2], 1).await, vec![99]);
+    }
+
+    #[tokio::test]
+    async fn test_set_buffer_length_reaches_subscription() {
+        let registry = Arc::new(StreamRegistry::new());
+        let key = StreamKey::new("live", "test");
+
+        let mut clients = Vec::new();
+        for (session_id, buffer_length) in [(1, 1000), (2, 3000), (3, 0)] {
+            let mut config = ClientConfig::new("rtmp://localhost/live");
+            config.buffer_length = buffer_length;
+            let (client_side, _) = spawn_session(
+                session_id,
+                ServerConfig::default(),
+                Arc::new(RecordingHandler::default()),
+                registry.clone(),
+            );
+            let client = RtmpConnector::connect_with_transport(config, client_side)
+                .await
+                .unwrap();
+            clients.push(client);
+        }
+
+        clients[0].publish("test").await.unwrap();
+        let frames = [
+            (0, Bytes::from_static(&[0x17, 0x00, 0, 0, 0, 0x01])),
+            (0, Bytes::from_static(&[0x17, 0x01, 0, 0, 0, 0x65])),
+            (33, Bytes::from_static(&[0x27, 0x01, 0, 0, 0, 0x41])),
+            (66, Bytes::from_static(&[0x27, 0x01, 0, 0, 0, 0x41])),
+        ];
+        for (timestamp, data) in frames {
+            clients[0].send_video_data(data, timestamp).await.unwrap();
+        }
+        wait_until(std::time::Duration::from_secs(5), || async {
+            registry
+                .get_stream_stats(&key)
+                .await
+                .map(|s| s.gop_frame_count)
+                == Some(3)
+        })
+        .await;
+
+        /// Timestamps of the first `count` video frames after the header
+        async fn catchup(client: &mut RtmpConnector<DuplexTransport>, count: usize) -> Vec<u32> {
+            let mut timestamps = Vec::new();
+            while timestamps.len() < count {
+                let msg =
+                    tokio::time::timeout(std::time::Duration::from_secs(5), client.read_message())
+                        .await
+                        .expect("catchup never arrived")
+                        .unwrap();
+                if let RtmpMessage::Video { timestamp, data } = msg {
+                    if data[1] != 0x00 {
+                        timestamps.push(timestamp);
+                    }
+                }
+            }
+            timestamps
+        }
+
+        // The buffered player gets the whole GOP, the zero-buffer one
+        // jumps to live
+        clients[1].play("test").await.unwrap();
+        assert_eq!(catchup(&mut clients[1], 3).await, vec![0, 33, 66]);
+        clients[2].play("test").await.unwrap();
+        assert_eq!(catchup(&mut clients[2], 1).await, vec![66]);
+
+        let buffers: Vec<_> = registry
+            .list_subscribers(&key)
+            .await
+            .iter()
+            .map(|info| (info.session_id, info.buffer_ms))
+            .collect();
+        assert_eq!(buffers, vec![(2, Some(3000)), (3, Some(0))]);
+
+        // A player changing its buffer while playing
+        clients[1].set_buffer_length(500).await.unwrap();
+        wait_until(std::time::Duration::from_secs(5), || async {
+            registry.list_subscribers(&key).await[0].buffer_ms == Some(500)
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_removes_subscriber_info() {
+        let registry = Arc::new(StreamRegistry::new());
+        let key = StreamKey::new("live", "test");
+        registry.register_publisher(&key, 99).await.unwrap();
+
+        let (client_side, task) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(RecordingHandler::default()),
+            registry.clone(),
+        );
+        let mut client = RtmpConnector::connect_with_transport(
+            ClientConfig::new("rtmp://localhost/live"),
+            client_side,
+        )
+        .await
+        .unwrap();
+        client.play("test").await.unwrap();
+        wait_until(std::time::Duration::from_secs(5), || async {
+            registry.list_subscribers(&key).await.len() == 1
+        })
+        .await;
+
+        // Players usually leave by closing the connection, not closeStream
+        drop(client);
+        let _ = task.await;
+        assert!(registry.list_subscribers(&key).await.is_empty());
+    }
+
+    /// Handler that answers a custom RPC
+    struct RpcHandler;
+
+    impl RtmpHandler for RpcHandler {
+        async fn on_command(
+            &self,
+            _ctx: &SessionContext,
+            name: &str,
+            _transaction_id: f64,
+            _command_object: &AmfValue,
+            args: &[AmfValue],
+        ) -> Option<Vec<AmfValue>> {
+            match name {
+                "getStreamLength" => {
+                    assert_eq!(args, &[AmfValue::String("test".into())]);
+                    Some(vec![AmfValue::Number(42.0)])
+                }
+                _ => None,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_command_result() {
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(RpcHandler),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        let mut client = RtmpConnector::connect_with_transport(
+            ClientConfig::new("rtmp://localhost/live"),
+            client_side,
+        )
+        .await
+        .unwrap();
+
+        // Unanswered commands get no reply, so the next result is ours
+        for (name, transaction_id) in [("vendorPing", 5.0), ("getStreamLength", 6.0)] {
+            let cmd = Command {
+                name: name.to_string(),
+                transaction_id,
+                command_object: AmfValue::Null,
+                arguments: vec![AmfValue::String("test".into())],
+                stream_id: 0,
+            };
+            client.send_command(&cmd).await.unwrap();
+        }
+
+        let result = loop {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), client.read_message())
+                    .await
+                    .expect("no _result received")
+                    .unwrap();
+            match msg {
+                RtmpMessage::Command(cmd) if cmd.name == CMD_RESULT => break cmd,
+                _ => {}
+            }
+        };
+        assert_eq!(result.transaction_id, 6.0);
+        assert_eq!(result.command_object, AmfValue::Null);
+        assert_eq!(result.arguments, vec![AmfValue::Number(42.0)]);
+    }
+
+    /// Answers commands only after a pause
+    struct SlowRpcHandler;
+
+    impl RtmpHandler for SlowRpcHandler {
+        async fn on_command(
+            &self,
+            _ctx: &SessionContext,
+            _name: &str,
+            _transaction_id: f64,
+            _command_object: &AmfValue,
+            _args: &[AmfValue],
+        ) -> Option<Vec<AmfValue>> {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            Some(vec![AmfValue::Boolean(true)])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_frames_do_not_cancel_command_handling() {
+        let registry = Arc::new(StreamRegistry::new());
+        let key = StreamKey::new("live", "test");
+        registry.register_publisher(&key, 99).await.unwrap();
+
+        let config =
+            ServerConfig::default().write_flush_deadline(std::time::Duration::from_millis(5));
+        let (client_side, _) = spawn_session(1, config, Arc::new(SlowRpcHandler), registry.clone());
+
+        let mut client = RtmpConnector::connect_with_transport(
+            ClientConfig::new("rtmp://localhost/live"),
+            client_side,
+        )
+        .await
+        .unwrap();
+        client.play("test").await.unwrap();
+
+        // Frames and flush deadlines keep arriving while the handler runs
+        let feeder = {
+            let registry = registry.clone();
+            let key = key.clone();
+            tokio::spawn(async move {
+                for i in 0..100u32 {
+                    let audio = Bytes::from(vec![0xAF, 0x01, i as u8]);
+                    registry
+                        .broadcast(&key, BroadcastFrame::audio(i * 23, audio, false))
+                        .await;
+                    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                }
+            })
+        };
+
+        let cmd = Command {
+            name: "vendorPing".to_string(),
+            transaction_id: 7.0,
+            command_object: AmfValue::Null,
+            arguments: vec![],
+            stream_id: 0,
+        };
+        client.send_command(&cmd).await.unwrap();
+
+        let result = loop {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), client.read_message())
+                    .await
+                    .expect("command was dropped mid-handling")
+                    .unwrap();
+            match msg {
+                RtmpMessage::Command(cmd) if cmd.name == CMD_RESULT => break cmd,
+                _ => {}
+            }
+        };
+        assert_eq!(result.transaction_id, 7.0);
+        assert_eq!(result.arguments, vec![AmfValue::Boolean(true)]);
+        feeder.abort();
+    }
+
+    /// Send a command on a raw socket
+    async fn write_command(client: &mut DuplexTransport, cmd: Command) {
+        let stream_id = cmd.stream_id;
+        let (message_type, payload) = RtmpMessage::Command(cmd).encode();
+        let mut encoder = ChunkEncoder::new();
+        let mut wire = BytesMut::new();
+        let chunk = RtmpChunk {
+            csid: CSID_COMMAND,
+            timestamp: 0,
+            message_type,
+            stream_id,
+            payload,
+        };
+        encoder.encode(&chunk, &mut wire);
+        client.write_all(&wire).await.unwrap();
+    }
+
+    /// Handler that samples the chunk streams at every keyframe
+    #[derive(Clone, Default)]
+    struct ChunkStreamHandler {
+        snapshots: Arc<Mutex<Vec<Vec<crate::protocol::ChunkStreamInfo>>>>,
+    }
+
+    impl RtmpHandler for ChunkStreamHandler {
+        async fn on_keyframe(&self, ctx: &StreamContext, _timestamp: u32) {
+            let snapshot = ctx.session.chunk_stream_snapshot();
+            self.snapshots.lock().unwrap().push(snapshot);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chunk_stream_snapshot() {
+        use crate::protocol::chunk::ChunkStreamInfo;
+
+        let handler = ChunkStreamHandler::default();
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(handler.clone()),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut client = RtmpConnector::connect_with_transport(config, client_side)
+            .await
+            .unwrap();
+        client.publish("test").await.unwrap();
+        let keyframe = Bytes::from_static(&[0x17, 0x01, 0, 0, 0, 0x65]);
+        // The snapshot is taken per read, so the second keyframe's handler
+        // sees the first
+        for (i, timestamp) in [40, 80].into_iter().enumerate() {
+            client
+                .send_video_data(keyframe.clone(), timestamp)
+                .await
+                .unwrap();
+            wait_until(std::time::Duration::from_secs(5), || async {
+                handler.snapshots.lock().unwrap().len() > i
+            })
+            .await;
+        }
+        let snapshot = handler.snapshots.lock().unwrap()[1].clone();
+
+        let csids: Vec<u32> = snapshot.iter().map(|s| s.csid).collect();
+        let mut sorted = csids.clone();
+        sorted.sort_unstable();
+        assert_eq!(csids, sorted);
+
+        let command = snapshot.iter().find(|s| s.csid == CSID_COMMAND).unwrap();
+        assert_eq!(command.last_msg_type, MSG_COMMAND_AMF0);
+        assert!(command.bytes > 0);
+        assert_eq!(
+            snapshot.iter().find(|s| s.csid == CSID_VIDEO),
+            Some(&ChunkStreamInfo {
+                csid: CSID_VIDEO,
+                last_msg_type: MSG_VIDEO,
+                last_timestamp: 40,
+                bytes: keyframe.len() as u64,
+            })
+        );
+    }
+
+    /// Handler that records A/V desync reports
+    #[derive(Clone, Default)]
+    struct DesyncHandler {
+        drifts: Arc<Mutex<Vec<i64>>>,
+    }
+
+    impl RtmpHandler for DesyncHandler {
+        async fn on_av_desync(&self, _ctx: &StreamContext, drift_ms: i64) {
+            self.drifts.lock().unwrap().push(drift_ms);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_av_desync_reported() {
+        let handler = DesyncHandler::default();
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default().av_desync_threshold(std::time::Duration::from_millis(500)),
+            Arc::new(handler.clone()),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut client = RtmpConnector::connect_with_transport(config, client_side)
+            .await
+            .unwrap();
+        client.publish("test").await.unwrap();
+
+        let video = Bytes::from_static(&[0x27, 0x01, 0, 0, 0, 0x41]);
+        let audio = Bytes::from_static(&[0xAF, 0x01, 0x21]);
+        for (is_video, timestamp) in [
+            (false, 0),
+            (true, 0),
+            (false, 40),
+            (true, 1000), // video 960ms ahead
+            (true, 1033),
+            (false, 1020), // back in sync
+            (true, 1066),
+            (false, 1700), // audio 634ms ahead
+        ] {
+            if is_video {
+                client
+                    .send_video_data(video.clone(), timestamp)
+                    .await
+                    .unwrap();
+            } else {
+                client
+                    .send_audio_data(audio.clone(), timestamp)
+                    .await
+                    .unwrap();
+            }
+        }
+
+        wait_until(std::time::Duration::from_secs(5), || async {
+            handler.drifts.lock().unwrap().len() >= 2
+        })
+        .await;
+        assert_eq!(*handler.drifts.lock().unwrap(), vec![960, -634]);
+    }
+
+    /// Tracing layer recording each new span's name and fields
+    #[derive(Clone, Default)]
+    struct SpanCapture {
+        spans: Arc<Mutex<Vec<(String, String)>>>,
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for SpanCapture {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _id: &tracing::span::Id,
+            _ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            struct Fields(String);
+            impl tracing::field::Visit for Fields {
+                fn record_debug(
+                    &mut self,
+                    field: &tracing::field::Field,
+                    value: &dyn std::fmt::Debug,
+                ) {
+                    self.0.push_str(&format!("{}={:?} ", field.name(), value));
+                }
+            }
+
+            let mut fields = Fields(String::new());
+            attrs.record(&mut fields);
+            self.spans
+                .lock()
+                .unwrap()
+                .push((attrs.metadata().name().to_string(), fields.0));
+        }
+    }
+
+    /// Publish one video frame; returns the spans the session created
+    async fn publish_with_tracing(enabled: bool) -> Vec<(String, String)> {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let capture = SpanCapture::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(capture.clone()));
+
+        let handler = RecordingHandler::default();
+        let (client_side, session) = spawn_session(
+            1,
+            ServerConfig::default().protocol_tracing(enabled),
+            Arc::new(handler.clone()),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut client = RtmpConnector::connect_with_transport(config, client_side)
+            .await
+            .unwrap();
+        client.publish("test").await.unwrap();
+        client
+            .send_video_data(Bytes::from_static(&[0x17, 0x01, 0, 0, 0, 0x65]), 40)
+            .await
+            .unwrap();
+        drop(client);
+        let _ = session.await;
+
+        let spans = capture.spans.lock().unwrap().clone();
+        spans
+    }
+
+    #[tokio::test]
+    async fn test_protocol_tracing_spans() {
+        let spans = publish_with_tracing(true).await;
+        let find = |name: &str, field: &str| {
+            spans
+                .iter()
+                .any(|(n, fields)| n == name && fields.contains(field))
+        };
+
+        assert!(find("handshake", "session_id=1"));
+        assert!(find("command", "name=connect"));
+        assert!(find("command", "name=publish"));
+        assert!(find("media", "kind=\"video\""));
+        assert!(find("media", "timestamp=40"));
+        assert!(find("media", "csid="));
+
+        // Off by default: no spans at all
+        let spans = publish_with_tracing(false).await;
+        assert!(spans.is_empty(), "{:?}", spans);
+    }
+
+    /// Handler that records media tags and early-frame reports
+    #[derive(Clone, Default)]
+    struct HeaderCheckHandler {
+        tags: Arc<Mutex<Vec<(u32, Bytes)>>>,
+        early: Arc<Mutex<Vec<(FrameType, u32)>>>,
+    }
+
+    impl RtmpHandler for HeaderCheckHandler {
+        async fn on_media_tag(&self, _ctx: &StreamContext, tag: &FlvTag) -> bool {
+            self.tags
+                .lock()
+                .unwrap()
+                .push((tag.timestamp, tag.data.clone()));
+            true
+        }
+
+        async fn on_frame_before_header(
+            &self,
+            _ctx: &StreamContext,
+            frame_type: FrameType,
+            timestamp: u32,
+        ) {
+            self.early.lock().unwrap().push((frame_type, timestamp));
+        }
+    }
+
+    /// Publish a P-frame, a keyframe and a P-frame ahead of the AVC
+    /// sequence header and a P-frame after it; returns the tags the
+    /// handler saw
+    async fn publish_frames_before_header(
+        policy: SequenceHeaderPolicy,
+    ) -> (HeaderCheckHandler, Vec<u32>) {
+        let handler = HeaderCheckHandler::default();
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default().sequence_header_policy(policy),
+            Arc::new(handler.clone()),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        let config = ClientConfig::new("rtmp://localhost/live");
+        let mut client = RtmpConnector::connect_with_transport(config, client_side)
+            .await
+            .unwrap();
+        client.publish("test").await.unwrap();
+
+        let p_frame = Bytes::from_static(&[0x27, 0x01, 0, 0, 0, 0x41]);
+        let header = Bytes::from_static(&[0x17, 0x00, 0, 0, 0, 0x01, 0x64, 0x00, 0x1F, 0xFF]);
+        let keyframe = Bytes::from_static(&[0x17, 0x01, 0, 0, 0, 0x65]);
+        client.send_video_data(p_frame.clone(), 0).await.unwrap();
+        client.send_video_data(keyframe, 33).await.unwrap();
+        client.send_video_data(p_frame.clone(), 66).await.unwrap();
+        client.send_video_data(header, 100).await.unwrap();
+        client.send_video_data(p_frame, 133).await.unwrap();
+
+        wait_until(std::time::Duration::from_secs(5), || async {
+            handler
+                .tags
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|(ts, _)| *ts == 133)
+        })
+        .await;
+        let timestamps = handler
+            .tags
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(ts, _)| *ts)
+            .collect();
+        (handler, timestamps)
+    }
+
+    #[tokio::test]
+    async fn test_frames_before_header_buffered() {
+        let (handler, timestamps) =
+            publish_frames_before_header(SequenceHeaderPolicy::Buffer).await;
+
+        // The header goes first, at the held keyframe's time; the P-frame
+        // before the keyframe is dropped
+        assert_eq!(timestamps, vec![33, 33, 66, 133]);
+        let tags = handler.tags.lock().unwrap();
+        assert_eq!(tags[0].1[1], 0x00);
+        assert_eq!(tags[1].1[0], 0x17);
+        drop(tags);
+        assert_eq!(*handler.early.lock().unwrap(), vec![(FrameType::Video, 0)]);
+    }
+
+    #[tokio::test]
+    async fn test_frames_before_header_dropped() {
+        let (handler, timestamps) = publish_frames_before_header(SequenceHeaderPolicy::Drop).await;
+
+        assert_eq!(timestamps, vec![100, 133]);
+        assert_eq!(*handler.early.lock().unwrap(), vec![(FrameType::Video, 0)]);
+    }
+
+    #[test]
+    fn test_hold_frame_limits() {
+        let mut held = HeldFrames::default();
+        let frame = |first: u8| {
+            let mut data = vec![first, 0x01];
+            data.resize(1024 * 1024, 0);
+            Bytes::from(data)
+        };
+
+        // Audio past the byte budget drops its oldest frames
+        for ts in 0..6 {
+            hold_frame(&mut held, FrameType::Audio, ts, frame(0xAF));
+        }
+        assert_eq!(held.bytes, SequenceHeaderPolicy::MAX_HELD_BYTES);
+        assert_eq!(held.frames.front().map(|(ts, _)| *ts), Some(2));
+
+        // Video waits for the next keyframe instead
+        let mut held = HeldFrames::default();
+        hold_frame(&mut held, FrameType::Video, 0, frame(0x27));
+        assert!(held.frames.is_empty());
+        for ts in 0..5 {
+            let first = if ts == 0 { 0x17 } else { 0x27 };
+            hold_frame(&mut held, FrameType::Video, ts, frame(first));
+        }
+        assert!(held.frames.is_empty());
+        assert_eq!(held.bytes, 0);
+        hold_frame(&mut held, FrameType::Video, 5, frame(0x17));
+        assert_eq!(held.frames.len(), 1);
+    }
+
+    #[test]
+    fn test_sequence_header_role() {
+        assert_eq!(
+            sequence_header_role(FrameType::Audio, &[0xAF, 0x00, 0x12]),
+            Some(true)
+        );
+        assert_eq!(
+            sequence_header_role(FrameType::Audio, &[0xAF, 0x01, 0x21]),
+            Some(false)
+        );
+        // MP3 has no sequence header
+        assert_eq!(sequence_header_role(FrameType::Audio, &[0x2F, 0xFF]), None);
+        assert_eq!(
+            sequence_header_role(FrameType::Video, &[0x17, 0x00, 0, 0, 0]),
+            Some(true)
+        );
+        assert_eq!(
+            sequence_header_role(FrameType::Video, &[0x27, 0x01, 0, 0, 0]),
+            Some(false)
+        );
+        // AVC command frame
+        assert_eq!(sequence_header_role(FrameType::Video, &[0x57, 0x00]), None);
+    }
+
+    /// Handler that namespaces streams by the virtual host in tcUrl
+    struct TenantHandler;
+
+    impl RtmpHandler for TenantHandler {
+        fn stream_key_for(&self, ctx: &SessionContext, app: &str, name: &str) -> StreamKey {
+            let tenant = ctx
+                .connect_params
+                .as_ref()
+                .and_then(|p| p.tc_url.as_deref())
+                .and_then(|url| url.strip_prefix("rtmp://"))
+                .and_then(|rest| rest.split('/').next())
+                .unwrap_or_default();
+            StreamKey::new(format!("{}/{}", tenant, app), name)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_key_for_separates_tenants() {
+        let registry = Arc::new(StreamRegistry::new());
+        let handler = Arc::new(TenantHandler);
+        let mut clients = Vec::new();
+        for (session_id, host) in [
+            (1, "a.example.com"),
+            (2, "b.example.com"),
+            (3, "a.example.com"),
+        ] {
+            let (client_side, _) = spawn_session(
+                session_id,
+                ServerConfig::default(),
+                handler.clone(),
+                registry.clone(),
+            );
+            let config = ClientConfig::new(format!("rtmp://{}/live", host));
+            let client = RtmpConnector::connect_with_transport(config, client_side)
+                .await
+                .unwrap();
+            clients.push(client);
+        }
+
+        // Both tenants publish "test" without colliding
+        clients[0].publish("test").await.unwrap();
+        clients[1].publish("test").await.unwrap();
+        for tenant in ["a.example.com/live", "b.example.com/live"] {
+            let stats = registry
+                .get_stream_stats(&StreamKey::new(tenant, "test"))
+                .await
+                .unwrap();
+            assert!(stats.has_publisher);
+        }
+        assert!(registry
+            .get_stream_stats(&StreamKey::new("live", "test"))
+            .await
+            .is_none());
+
+        // A player of tenant a is routed to tenant a's publisher
+        clients[2].play("test").await.unwrap();
+        let frame = |n: u8| Bytes::from(vec![0x17, 0x01, 0, 0, 0, n]);
+        clients[1].send_video_data(frame(2), 0).await.unwrap();
+        clients[0].send_video_data(frame(1), 0).await.unwrap();
+        assert_eq!(next_media(&mut clients[2]).await, (MSG_VIDEO, 0, frame(1)));
+    }
+
+    /// Handler that records when `on_publish` has returned
+    #[derive(Clone, Default)]
+    struct PublishAcceptHandler {
+        accepted: Arc<Mutex<bool>>,
+    }
+
+    impl RtmpHandler for PublishAcceptHandler {
+        async fn on_publish(&self, _ctx: &SessionContext, _params: &PublishParams) -> AuthResult {
+            *self.accepted.lock().unwrap() = true;
+            AuthResult::Accept
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_start_status() {
+        use crate::client::CommandBuilder;
+
+        let handler = PublishAcceptHandler::default();
+        let (mut client, _handle) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(handler.clone()),
+            Arc::new(StreamRegistry::new()),
+        );
+        client_handshake(&mut client).await;
+
+        let mut obj = AmfObject::new();
+        obj.insert("app".to_string(), AmfValue::String("live".into()));
+        let connect = Command {
+            name: CMD_CONNECT.to_string(),
+            transaction_id: 1.0,
+            command_object: AmfValue::Object(obj),
+            arguments: Vec::new(),
+            stream_id: 0,
+        };
+        write_command(&mut client, connect).await;
+        let create = CommandBuilder::create_stream().transaction_id(2.0).build();
+        write_command(&mut client, create).await;
+
+        let mut decoder = ChunkDecoder::new();
+        let mut read_buf = BytesMut::new();
+        let mut publish_sent = false;
+        loop {
+            while let Some(chunk) = decoder.decode(&mut read_buf).unwrap() {
+                match RtmpMessage::from_chunk(&chunk) {
+                    Ok(RtmpMessage::SetChunkSize(size)) => decoder.set_chunk_size(size),
+                    Ok(RtmpMessage::Command(cmd)) if cmd.transaction_id == 2.0 => {
+                        let stream_id = cmd.arguments[0].as_number().unwrap() as u32;
+                        let publish = CommandBuilder::publish("test", "live")
+                            .stream_id(stream_id)
+                            .build();
+                        write_command(&mut client, publish).await;
+                        publish_sent = true;
+                    }
+                    Ok(RtmpMessage::Command(cmd)) if cmd.name == CMD_ON_STATUS => {
+                        let info = cmd.arguments[0].as_object().unwrap();
+                        let code = info.get("code").and_then(|v| v.as_str());
+                        if code != Some(NS_PUBLISH_START) {
+                            continue;
+                        }
+                        assert!(publish_sent);
+                        assert!(*handler.accepted.lock().unwrap());
+                        assert_eq!(chunk.csid, CSID_COMMAND);
+                        assert_eq!(chunk.stream_id, 1);
+                        assert_eq!(info.get("details").and_then(|v| v.as_str()), Some("test"));
+                        let description = info.get("description").and_then(|v| v.as_str());
+                        assert!(description.unwrap().contains("test"));
+                        return;
+                    }
+                    _ => {}
+                }
+            }
+            let read = tokio::time::timeout(
+                std::time::Duration::from_secs(5),
+                client.read_buf(&mut read_buf),
+            )
+            .await
+            .expect("no NetStream.Publish.Start received")
+            .unwrap();
+            assert!(read > 0);
+        }
+    }
+
+    /// Handler that checks a signature carried in the connect object
+    #[derive(Clone, Default)]
+    struct SigningHandler {
+        signature: Arc<Mutex<Option<String>>>,
+        arguments: Arc<Mutex<Vec<AmfValue>>>,
+    }
+
+    impl RtmpHandler for SigningHandler {
+        async fn on_connect(&self, _ctx: &SessionContext, params: &ConnectParams) -> AuthResult {
+            let signature = params
+                .command_object
+                .as_object()
+                .and_then(|o| o.get("x-signature"))
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            let accepted = signature.as_deref() == Some("abc123");
+            *self.signature.lock().unwrap() = signature;
+            *self.arguments.lock().unwrap() = params.arguments.clone();
+            if accepted {
+                AuthResult::Accept
+            } else {
+                AuthResult::Reject("bad signature".into())
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect_object_reaches_handler() {
+        let handler = SigningHandler::default();
+        let (mut client, _handle) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(handler.clone()),
+            Arc::new(StreamRegistry::new()),
+        );
+        client_handshake(&mut client).await;
+
+        let mut obj = AmfObject::new();
+        obj.insert("app".to_string(), AmfValue::String("live".into()));
+        obj.insert(
+            "tcUrl".to_string(),
+            AmfValue::String("rtmp://localhost/live".into()),
+        );
+        obj.insert("x-signature".to_string(), AmfValue::String("abc123".into()));
+        let connect = Command {
+            name: CMD_CONNECT.to_string(),
+            transaction_id: 1.0,
+            command_object: AmfValue::Object(obj),
+            arguments: vec![AmfValue::String("user-token".into())],
+            stream_id: 0,
+        };
+        write_command(&mut client, connect).await;
+
+        wait_until(std::time::Duration::from_secs(5), || async {
+            handler.signature.lock().unwrap().is_some()
+        })
+        .await;
+        assert_eq!(handler.signature.lock().unwrap().as_deref(), Some("abc123"));
+        assert_eq!(
+            *handler.arguments.lock().unwrap(),
+            vec![AmfValue::String("user-token".into())]
+        );
+    }
+
+    /// Handler that picks small chunks for an audio-only app
+    struct ChunkSizeHandler;
+
+    impl RtmpHandler for ChunkSizeHandler {
+        fn chunk_size_for(&self, ctx: &SessionContext) -> Option<u32> {
+            (ctx.app == "radio").then_some(256)
+        }
+    }
+
+    /// Connect to `app` on a raw socket and return the announced chunk size
+    async fn announced_chunk_size(app: &str) -> u32 {
+        let (mut client, _handle) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(ChunkSizeHandler),
+            Arc::new(StreamRegistry::new()),
+        );
+        client_handshake(&mut client).await;
+
+        let mut obj = AmfObject::new();
+        obj.insert("app".to_string(), AmfValue::String(app.into()));
+        let connect = Command {
+            name: CMD_CONNECT.to_string(),
+            transaction_id: 1.0,
+            command_object: AmfValue::Object(obj),
+            arguments: Vec::new(),
+            stream_id: 0,
+        };
+        write_command(&mut client, connect).await;
+
+        let mut decoder = ChunkDecoder::new();
+        let mut read_buf = BytesMut::new();
+        loop {
+            while let Some(chunk) = decoder.decode(&mut read_buf).unwrap() {
+                if let Ok(RtmpMessage::SetChunkSize(size)) = RtmpMessage::from_chunk(&chunk) {
+                    return size;
+                }
+            }
+            let read = tokio::time::timeout(
+                std::time::Duration::from_secs(5),
+                client.read_buf(&mut read_buf),
+            )
+            .await
+            .expect("no Set Chunk Size received")
+            .unwrap();
+            assert!(read > 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handler_chooses_chunk_size() {
+        assert_eq!(announced_chunk_size("radio").await, 256);
+        assert_eq!(
+            announced_chunk_size("live").await,
+            ServerConfig::default().chunk_size
+        );
+    }
+
+    #[tokio::test]
+    async fn test_release_stream_and_fc_publish_responses() {
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(RecordingHandler::default()),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        let mut client = RtmpConnector::connect_with_transport(
+            ClientConfig::new("rtmp://localhost/live"),
+            client_side,
+        )
+        .await
+        .unwrap();
+
+        for (name, transaction_id) in [(CMD_RELEASE_STREAM, 2.0), (CMD_FC_PUBLISH, 3.0)] {
+            let cmd = Command {
+                name: name.to_string(),
+                transaction_id,
+                command_object: AmfValue::Null,
+                arguments: vec![AmfValue::String("test".into())],
+                stream_id: 0,
+            };
+            client.send_command(&cmd).await.unwrap();
+        }
+
+        let mut responses = Vec::new();
+        while responses.len() < 2 {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), client.read_message())
+                    .await
+                    .expect("missing releaseStream/FCPublish response")
+                    .unwrap();
+            if let RtmpMessage::Command(cmd) = msg {
+                responses.push(cmd);
+            }
+        }
+
+        assert_eq!(responses[0].name, CMD_RESULT);
+        assert_eq!(responses[0].transaction_id, 2.0);
+        assert_eq!(responses[0].command_object, AmfValue::Null);
+
+        assert_eq!(responses[1].name, CMD_ON_FC_PUBLISH);
+        let code = responses[1]
+            .arguments
+            .first()
+            .and_then(|info| info.get("code"))
+            .and_then(|code| code.as_str());
+        assert_eq!(code, Some(NS_PUBLISH_START));
+    }
+
+    /// Handler reporting the length of recorded streams
+    struct StreamLengthHandler;
+
+    impl RtmpHandler for StreamLengthHandler {
+        async fn on_get_stream_length(&self, _ctx: &SessionContext, stream_name: &str) -> f64 {
+            match stream_name {
+                "movie" => 5400.5,
+                _ => 0.0,
+            }
+        }
+    }
+
+    /// Connect a client to a server-side connection over an in-memory pipe
+    async fn connect_duplex<H: RtmpHandler>(handler: H) -> RtmpConnector<DuplexTransport> {
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(handler),
+            Arc::new(StreamRegistry::new()),
+        );
+        RtmpConnector::connect_with_transport(
+            ClientConfig::new("rtmp://localhost/vod"),
+            client_side,
+        )
+        .await
+        .unwrap()
+    }
+
+    /// Read commands until one named `name` arrives
+    async fn next_command<S: crate::transport::Transport>(
+        client: &mut crate::client::RtmpConnector<S>,
+        name: &str,
+    ) -> Command {
+        loop {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), client.read_message())
+                    .await
+                    .expect("command never arrived")
+                    .unwrap();
+            match msg {
+                RtmpMessage::Command(cmd) if cmd.name == name => return cmd,
+                _ => {}
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fc_subscribe_and_get_stream_length() {
+        let mut client = connect_duplex(RecordingHandler::default()).await;
+
+        let command = |name: &str, transaction_id: f64| Command {
+            name: name.to_string(),
+            transaction_id,
+            command_object: AmfValue::Null,
+            arguments: vec![AmfValue::String("test".into())],
+            stream_id: 0,
+        };
+
+        client
+            .send_command(&command(CMD_FC_SUBSCRIBE, 2.0))
+            .await
+            .unwrap();
+        let response = next_command(&mut client, CMD_ON_FC_SUBSCRIBE).await;
+        let info = response.arguments.first().unwrap();
+        assert_eq!(
+            info.get("code").and_then(|c| c.as_str()),
+            Some(NS_PLAY_START)
+        );
+        assert_eq!(
+            info.get("description").and_then(|d| d.as_str()),
+            Some("FCSubscribe to stream test")
+        );
+
+        // Live streams have no length
+        client
+            .send_command(&command(CMD_GET_STREAM_LENGTH, 3.0))
+            .await
+            .unwrap();
+        let result = next_command(&mut client, CMD_RESULT).await;
+        assert_eq!(result.transaction_id, 3.0);
+        assert_eq!(result.command_object, AmfValue::Null);
+        assert_eq!(result.arguments, vec![AmfValue::Number(0.0)]);
+    }
+
+    #[tokio::test]
+    async fn test_get_stream_length_from_handler() {
+        let mut client = connect_duplex(StreamLengthHandler).await;
+
+        for (name, transaction_id, length) in [("movie", 4.0, 5400.5), ("live", 5.0, 0.0)] {
+            let cmd = Command {
+                name: CMD_GET_STREAM_LENGTH.to_string(),
+                transaction_id,
+                command_object: AmfValue::Null,
+                arguments: vec![AmfValue::String(name.into())],
+                stream_id: 0,
+            };
+            client.send_command(&cmd).await.unwrap();
+            let result = next_command(&mut client, CMD_RESULT).await;
+            assert_eq!(result.transaction_id, transaction_id);
+            assert_eq!(result.arguments, vec![AmfValue::Number(length)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_play_receives_cue_points_between_frames() {
+        let registry = Arc::new(StreamRegistry::new());
+        let mut clients = Vec::new();
+        for session_id in 1..=2 {
+            let (client_side, _) = spawn_session(
+                session_id,
+                ServerConfig::default(),
+                Arc::new(RecordingHandler::default()),
+                registry.clone(),
+            );
+            let client = RtmpConnector::connect_with_transport(
+                ClientConfig::new("rtmp://localhost/live"),
+                client_side,
+            )
+            .await
+            .unwrap();
+            clients.push(client);
+        }
+        let (publisher, player) = clients.split_at_mut(1);
+        let (publisher, player) = (&mut publisher[0], &mut player[0]);
+
+        publisher.publish("test").await.unwrap();
+        player.play("test").await.unwrap();
+
+        let key = StreamKey::new("live", "test");
+        let cue_point = crate::amf::amf0::encode_all(&[
+            AmfValue::String(CMD_ON_CUE_POINT.into()),
+            AmfValue::String("ad-break".into()),
+        ]);
+        publisher
+            .send_video_data(Bytes::from_static(&[0x17, 0x01, 0, 0, 0, 1]), 0)
+            .await
+            .unwrap();
+        wait_until(std::time::Duration::from_secs(5), || async {
+            registry
+                .get_stream_stats(&key)
+                .await
+                .unwrap()
+                .gop_frame_count
+                != 0
+        })
+        .await;
+        registry
+            .broadcast_data(&key, FlvTag::script(20, cue_point))
+            .await;
+        publisher
+            .send_video_data(Bytes::from_static(&[0x27, 0x01, 0, 0, 0, 2]), 40)
+            .await
+            .unwrap();
+
+        // The cue point arrives as a data message between the two frames
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            let msg =
+                tokio::time::timeout(std::time::Duration::from_secs(5), player.read_message())
+                    .await
+                    .expect("frames never arrived")
+                    .unwrap();
+            match msg {
+                RtmpMessage::Video { timestamp, .. } => received.push(format!("video@{timestamp}")),
+                RtmpMessage::Data(data) => {
+                    assert_eq!(data.values, vec![AmfValue::String("ad-break".into())]);
+                    received.push(data.name);
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(received, ["video@0", CMD_ON_CUE_POINT, "video@40"]);
+    }
+
+    #[tokio::test]
+    async fn test_publish_and_play_on_one_connection() {
+        use crate::client::CommandBuilder;
+
+        let registry = Arc::new(StreamRegistry::new());
+        let mut clients = Vec::new();
+        for session_id in 1..=3 {
+            let (client_side, _) = spawn_session(
+                session_id,
+                ServerConfig::default(),
+                Arc::new(RecordingHandler::default()),
+                registry.clone(),
+            );
+            let client = RtmpConnector::connect_with_transport(
+                ClientConfig::new("rtmp://localhost/live"),
+                client_side,
+            )
+            .await
+            .unwrap();
+            clients.push(client);
+        }
+        let [publisher_b, both, player_a] = &mut clients[..] else {
+            unreachable!()
+        };
+
+        // One connection plays B on its first stream and publishes A on
+        // its second
+        publisher_b.publish("b").await.unwrap();
+        both.play("b").await.unwrap();
+        let play_stream = both.stream_id();
+        let publish_stream = both.create_stream().await.unwrap();
+        assert_ne!(play_stream, publish_stream);
+        both.publish("a").await.unwrap();
+        player_a.play("a").await.unwrap();
+
+        let key_a = StreamKey::new("live", "a");
+        let key_b = StreamKey::new("live", "b");
+        let stats_a = registry.get_stream_stats(&key_a).await.unwrap();
+        let stats_b = registry.get_stream_stats(&key_b).await.unwrap();
+        assert!(stats_a.has_publisher && stats_b.has_publisher);
+        assert_eq!((stats_a.subscriber_count, stats_b.subscriber_count), (1, 1));
+
+        let frame = |n: u8| Bytes::from(vec![0x17, 0x01, 0, 0, 0, n]);
+        both.send_video_data(frame(1), 10).await.unwrap();
+        publisher_b.send_video_data(frame(2), 20).await.unwrap();
+
+        // Each stream reaches only its own player
+        assert_eq!(next_media(player_a).await, (MSG_VIDEO, 10, frame(1)));
+        assert_eq!(next_media(both).await, (MSG_VIDEO, 20, frame(2)));
+
+        // Stopping playback leaves the publish running
+        let delete = CommandBuilder::delete_stream(play_stream)
+            .transaction_id(0.0)
+            .build();
+        both.send_command(&delete).await.unwrap();
+        both.send_video_data(frame(3), 30).await.unwrap();
+        assert_eq!(next_media(player_a).await, (MSG_VIDEO, 30, frame(3)));
+        wait_until(std::time::Duration::from_secs(5), || async {
+            registry
+                .get_stream_stats(&key_b)
+                .await
+                .unwrap()
+                .subscriber_count
+                == 0
+        })
+        .await;
+        assert!(registry.has_active_stream(&key_a).await);
+    }
+
+    #[tokio::test]
+    async fn test_play_two_streams_and_close_one() {
+        let registry = Arc::new(StreamRegistry::new());
+        let key_a = StreamKey::new("live", "a");
+        let key_b = StreamKey::new("live", "b");
+        registry.register_publisher(&key_a, 98).await.unwrap();
+        registry.register_publisher(&key_b, 99).await.unwrap();
+
+        let (client_side, _session) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(RecordingHandler::default()),
+            registry.clone(),
+        );
+        let mut client = RtmpConnector::connect_with_transport(
+            ClientConfig::new("rtmp://localhost/live"),
+            client_side,
+        )
+        .await
+        .unwrap();
+        client.play("a").await.unwrap();
+        let stream_a = client.stream_id();
+        client.create_stream().await.unwrap();
+        client.play("b").await.unwrap();
+
+        // Playing again on a message stream replaces what it played
+        client.play("b").await.unwrap();
+        let subscribers = |key: StreamKey| {
+            let registry = registry.clone();
+            async move {
+                let stats = registry.get_stream_stats(&key).await.unwrap();
+                stats.subscriber_count
+            }
+        };
+        assert_eq!(subscribers(key_a.clone()).await, 1);
+        assert_eq!(subscribers(key_b.clone()).await, 1);
+
+        let frame = |n: u8| Bytes::from(vec![0x17, 0x01, 0, 0, 0, n]);
+        registry
+            .broadcast(&key_a, BroadcastFrame::video(10, frame(1), true, false))
+            .await;
+        assert_eq!(next_media(&mut client).await, (MSG_VIDEO, 10, frame(1)));
+        registry
+            .broadcast(&key_b, BroadcastFrame::video(20, frame(2), true, false))
+            .await;
+        assert_eq!(next_media(&mut client).await, (MSG_VIDEO, 20, frame(2)));
+
+        // Closing one playback leaves the other running
+        let close = Command {
+            name: "closeStream".to_string(),
+            transaction_id: 0.0,
+            command_object: AmfValue::Null,
+            arguments: vec![],
+            stream_id: stream_a,
+        };
+        client.send_command(&close).await.unwrap();
+        wait_until(Duration::from_secs(5), || async {
+            subscribers(key_a.clone()).await == 0
+        })
+        .await;
+        registry
+            .broadcast(&key_a, BroadcastFrame::video(30, frame(3), true, false))
+            .await;
+        registry
+            .broadcast(&key_b, BroadcastFrame::video(40, frame(4), true, false))
+            .await;
+        assert_eq!(next_media(&mut client).await, (MSG_VIDEO, 40, frame(4)));
+        assert_eq!(subscribers(key_b.clone()).await, 1);
+    }
+
+    /// Handler that records the peer address seen at connect
+    #[derive(Clone, Default)]
+    struct PeerHandler {
+        peers: Arc<Mutex<Vec<SocketAddr>>>,
+    }
+
+    impl RtmpHandler for PeerHandler {
+        async fn on_connect(&self, ctx: &SessionContext, _params: &ConnectParams) -> AuthResult {
+            self.peers.lock().unwrap().push(ctx.peer_addr);
+            AuthResult::Accept
+        }
+    }
+
+    /// Run a server connection expecting PROXY headers, sending `header` first
+    async fn connect_behind_proxy(
+        header: &[u8],
+    ) -> (Result<Vec<SocketAddr>>, tokio::task::JoinHandle<Result<()>>) {
+        let handler = PeerHandler::default();
+        let (mut client_side, server) = spawn_session(
+            1,
+            ServerConfig::default().expect_proxy_protocol(true),
+            Arc::new(handler.clone()),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        client_side.write_all(header).await.unwrap();
+        let connected = RtmpConnector::connect_with_transport(
+            ClientConfig::new("rtmp://localhost/live"),
+            client_side,
+        )
+        .await;
+        let peers = connected.map(|_client| handler.peers.lock().unwrap().clone());
+        (peers, server)
+    }
+
+    #[tokio::test]
+    async fn test_proxy_v1_header_sets_peer_addr() {
+        let (peers, _server) =
+            connect_behind_proxy(b"PROXY TCP4 203.0.113.7 192.0.2.1 51234 1935\r\n").await;
+        assert_eq!(peers.unwrap(), ["203.0.113.7:51234".parse().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_v2_header_sets_peer_addr() {
+        let mut header = vec![
+            0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A, 0x21, 0x21,
+            0x00, 0x24,
+        ];
+        header.extend_from_slice(
+            &"2001:db8::7"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets(),
+        );
+        header.extend_from_slice(
+            &"2001:db8::1"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets(),
+        );
+        header.extend_from_slice(&[0x0F, 0xA0, 0x07, 0x8F]);
+
+        let (peers, _server) = connect_behind_proxy(&header).await;
+        assert_eq!(peers.unwrap(), ["[2001:db8::7]:4000".parse().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_proxy_header_rejected() {
+        let (peers, server) = connect_behind_proxy(b"PROXY TCP4 nonsense\r\n").await;
+        assert!(peers.is_err());
+        assert!(matches!(
+            server.await.unwrap(),
+            Err(Error::Protocol(ProtocolError::InvalidProxyHeader(_)))
+        ));
+    }
+
+    /// Handler that records the peer's protocol parameters
+    #[derive(Clone, Default)]
+    struct ParamsHandler {
+        params: Arc<Mutex<Vec<ProtocolParams>>>,
+    }
+
+    impl RtmpHandler for ParamsHandler {
+        async fn on_connect(&self, ctx: &SessionContext, _params: &ConnectParams) -> AuthResult {
+            self.params.lock().unwrap().push(ctx.protocol_params);
+            AuthResult::Accept
+        }
+
+        async fn on_publish(&self, ctx: &SessionContext, _params: &PublishParams) -> AuthResult {
+            self.params.lock().unwrap().push(ctx.protocol_params);
+            AuthResult::Accept
+        }
+    }
+
+    #[tokio::test]
+    async fn test_protocol_params_follow_set_chunk_size() {
+        let handler = ParamsHandler::default();
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(handler.clone()),
+            Arc::new(StreamRegistry::new()),
+        );
+
+        // The connector announces its chunk size right after connect
+        let mut client = RtmpConnector::connect_with_transport(
+            ClientConfig::new("rtmp://localhost/live"),
+            client_side,
+        )
+        .await
+        .unwrap();
+        client.publish("test").await.unwrap();
+
+        let params = handler.params.lock().unwrap();
+        assert_eq!(params.len(), 2);
+        assert_eq!(params[0].in_chunk_size, DEFAULT_CHUNK_SIZE);
+        assert_eq!(params[0].object_encoding, None);
+        assert_eq!(params[1].in_chunk_size, RECOMMENDED_CHUNK_SIZE);
+        assert_eq!(params[1].object_encoding, Some(0.0));
+
+        // Both ends use wall-clock time in the handshake
+        assert!(params[0].handshake_timestamp.is_some());
+        assert!(params[0].clock_skew_ms.unwrap().abs() < 5_000);
+    }
+
+    #[tokio::test]
+    async fn test_client_media_reaches_published_stream() {
+        let registry = Arc::new(StreamRegistry::new());
+        let (client_side, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(RecordingHandler::default()),
+            registry.clone(),
+        );
+
+        let mut client = RtmpConnector::connect_with_transport(
+            ClientConfig::new("rtmp://localhost/live"),
+            client_side,
+        )
+        .await
+        .unwrap();
+        client.publish("test").await.unwrap();
+
+        let key = StreamKey::new("live", "test");
+        let (mut rx, _) = registry.subscribe(&key).await.unwrap();
+
+        // Raw AAC frame on the stream the publish command targeted
+        client
+            .send_audio_data(Bytes::from_static(&[0xAF, 0x01, 0x21, 0x10]), 0)
+            .await
+            .unwrap();
+
+        let frame = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv())
+            .await
+            .expect("audio never broadcast")
+            .unwrap();
+        assert_eq!(frame.frame_type, FrameType::Audio);
+    }
+
+    #[test]
+    fn test_connect_control_messages() {
+        let config = ServerConfig::default()
+            .window_ack_size(5_000_000)
+            .peer_bandwidth(6_000_000)
+            .chunk_size(8192);
+
+        let mut encoder = ChunkEncoder::new();
+        let mut decoder = ChunkDecoder::new();
+        let mut wire = BytesMut::new();
+
+        for msg in connect_control_messages(&config, config.chunk_size) {
+            let (message_type, payload) = msg.encode();
+            let chunk = RtmpChunk {
+                csid: CSID_PROTOCOL_CONTROL,
+                timestamp: 0,
+                message_type,
+                stream_id: 0,
+                payload,
+            };
+            encoder.encode(&chunk, &mut wire);
+        }
+
+        let mut decoded = Vec::new();
+        while let Some(chunk) = decoder.decode(&mut wire).unwrap() {
+            decoded.push(RtmpMessage::from_chunk(&chunk).unwrap());
+        }
+
+        assert_eq!(decoded.len(), 3);
+        assert!(matches!(decoded[0], RtmpMessage::WindowAckSize(5_000_000)));
+        assert!(matches!(
+            decoded[1],
+            RtmpMessage::SetPeerBandwidth {
+                size: 6_000_000,
+                limit_type: BANDWIDTH_LIMIT_DYNAMIC
+            }
+        ));
+        assert!(matches!(decoded[2], RtmpMessage::SetChunkSize(8192)));
+    }
+
+    #[tokio::test]
+    async fn test_connect_sends_control_messages_before_result() {
+        let config = ServerConfig::default()
+            .window_ack_size(5_000_000)
+            .peer_bandwidth(6_000_000)
+            .chunk_size(8192);
+        let (mut client, _handle) = spawn_session(
+            1,
+            config,
+            Arc::new(RecordingHandler::default()),
+            Arc::new(StreamRegistry::new()),
+        );
+        client_handshake(&mut client).await;
+
+        let mut obj = AmfObject::new();
+        obj.insert("app".to_string(), AmfValue::String("live".into()));
+        let connect = Command {
+            name: CMD_CONNECT.to_string(),
+            transaction_id: 1.0,
+            command_object: AmfValue::Object(obj),
+            arguments: Vec::new(),
+            stream_id: 0,
+        };
+        write_command(&mut client, connect).await;
+
+        // Protocol control messages seen before the connect _result
+        let mut decoder = ChunkDecoder::new();
+        let mut read_buf = BytesMut::new();
+        let mut control = Vec::new();
+        'read: loop {
+            while let Some(chunk) = decoder.decode(&mut read_buf).unwrap() {
+                match RtmpMessage::from_chunk(&chunk).unwrap() {
+                    RtmpMessage::Command(cmd) if cmd.name == CMD_RESULT => break 'read,
+                    RtmpMessage::SetChunkSize(size) => {
+                        decoder.set_chunk_size(size);
+                        control.push(RtmpMessage::SetChunkSize(size));
+                    }
+                    msg
+                    @ (RtmpMessage::WindowAckSize(_) | RtmpMessage::SetPeerBandwidth { .. }) => {
+                        control.push(msg)
+                    }
+                    _ => {}
+                }
+            }
+            let read = tokio::time::timeout(
+                std::time::Duration::from_secs(5),
+                client.read_buf(&mut read_buf),
+            )
+            .await
+            .expect("no connect _result received")
+            .unwrap();
+            assert!(read > 0);
+        }
+
+        assert_eq!(control.len(), 3);
+        assert!(matches!(control[0], RtmpMessage::WindowAckSize(5_000_000)));
+        assert!(matches!(
+            control[1],
+            RtmpMessage::SetPeerBandwidth {
+                size: 6_000_000,
+                limit_type: BANDWIDTH_LIMIT_DYNAMIC
+            }
+        ));
+        assert!(matches!(control[2], RtmpMessage::SetChunkSize(8192)));
+    }
+}
@@ -3,11 +3,13 @@
 //! The main extension point for RTMP applications. Implement this trait
 //! to handle connection events, authentication, and media data.
 
-use std::collections::HashMap;
-
-use crate::amf::AmfValue;
-use crate::media::{AacData, EnhancedAudioData, EnhancedVideoData, FlvTag, H264Data};
+use crate::amf::{AmfObject, AmfValue};
+use crate::error::{ClientError, Error};
+use crate::media::{
+    AacData, AudioFrame, EnhancedAudioData, EnhancedVideoData, FlvTag, H264Data, VideoFrame,
+};
 use crate::protocol::message::{ConnectParams, PlayParams, PublishParams};
+use crate::registry::{CatchupStrategy, FrameType, StreamKey};
 use crate::session::{SessionContext, StreamContext};
 
 /// Result of authentication/authorization checks
@@ -35,6 +37,79 @@ impl AuthResult {
     }
 }
 
+/// Why a session ended, passed to [`RtmpHandler::on_disconnect`]
+///
+/// More reasons may be added, so matches need a wildcard arm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DisconnectReason {
+    /// Handshake or idle timeout elapsed
+    Timeout,
+
+    /// The RTMP handshake failed
+    HandshakeFailed,
+
+    /// Malformed chunk, AMF or media data
+    ProtocolError(String),
+
+    /// The peer closed the connection
+    PeerClosed,
+
+    /// Another publisher took over the stream
+    PublisherTakeover,
+
+    /// The server is shutting down
+    ServerShutdown,
+
+    /// The client was asked to reconnect (see `SessionContext::request_reconnect`)
+    ReconnectRequested,
+
+    /// A request was rejected (by the handler, or a slow subscriber was dropped)
+    Rejected(String),
+
+    /// The stream being played ended
+    StreamEnded,
+
+    /// The application evicted the session or its stream (see
+    /// `StreamRegistry::evict_stream` and `SessionRegistry::disconnect_session`)
+    Evicted,
+}
+
+impl DisconnectReason {
+    /// Check if the session ended because of corrupt or invalid data
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            DisconnectReason::HandshakeFailed | DisconnectReason::ProtocolError(_)
+        )
+    }
+}
+
+impl From<&Error> for DisconnectReason {
+    fn from(err: &Error) -> Self {
+        match err {
+            Error::Io(_) | Error::ConnectionClosed => DisconnectReason::PeerClosed,
+            Error::Timeout | Error::Client(ClientError::CommandTimeout { .. }) => {
+                DisconnectReason::Timeout
+            }
+            Error::Handshake(_) => DisconnectReason::HandshakeFailed,
+            Error::Rejected(reason) => DisconnectReason::Rejected(reason.clone()),
+            Error::Protocol(_) | Error::Amf(_) | Error::Media(_) | Error::Config(_) => {
+                DisconnectReason::ProtocolError(err.to_string())
+            }
+        }
+    }
+}
+
+/// Direction of bytes passed to [`RtmpHandler::on_raw_bytes`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteDirection {
+    /// Received from the peer
+    In,
+    /// Sent to the peer
+    Out,
+}
+
 /// Media delivery mode configuration
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum MediaDeliveryMode {
@@ -84,6 +159,12 @@ pub enum MediaDeliveryMode {
 /// }
 /// ```
 pub trait RtmpHandler: Send + Sync + 'static {
+    /// Whether [`on_raw_bytes`](Self::on_raw_bytes) is called
+    ///
+    /// Set to true when implementing it; sessions skip the tap entirely
+    /// otherwise.
+    const TAPS_RAW: bool = false;
+
     /// Called when a new TCP connection is established
     ///
     /// Return false to immediately close the connection.
@@ -114,6 +195,27 @@ pub trait RtmpHandler: Send + Sync + 'static {
         async { AuthResult::Accept }
     }
 
+    /// Choose the outbound chunk size for a session
+    ///
+    /// Consulted after `connect` is accepted, before Set Chunk Size is
+    /// sent. Small chunks keep tiny audio frames from queueing behind large
+    /// video chunks, at the cost of more header overhead. None (the default)
+    /// uses [`ServerConfig::chunk_size`](crate::ServerConfig::chunk_size).
+    fn chunk_size_for(&self, _ctx: &SessionContext) -> Option<u32> {
+        None
+    }
+
+    /// Map a published or played stream to its registry key
+    ///
+    /// Publish and play both go through this, so a publisher and its
+    /// players meet as long as it is deterministic. Override it to
+    /// namespace keys, e.g. by a tenant derived from the app or from
+    /// credentials checked in `on_connect`. The default keys streams by
+    /// app and stream name.
+    fn stream_key_for(&self, _ctx: &SessionContext, app: &str, name: &str) -> StreamKey {
+        StreamKey::new(app, name)
+    }
+
     /// Called on FCPublish command (OBS/Twitch compatibility)
     ///
     /// This is called before 'publish' and can be used for early stream key validation.
@@ -147,11 +249,92 @@ pub trait RtmpHandler: Send + Sync + 'static {
         async { AuthResult::Accept }
     }
 
+    /// Pick the catchup a player receives when joining a running stream
+    ///
+    /// Called after `on_play` accepts. Return
+    /// [`CatchupStrategy::LiveEdge`] to jump low-latency players straight
+    /// to live, or None to keep the registry's
+    /// [`catchup_strategy`](crate::registry::RegistryConfig::catchup_strategy).
+    /// The default jumps players that announced a zero buffer length
+    /// ([`PlayParams::buffer_ms`]) to live and keeps the registry's choice
+    /// for everyone else.
+    ///
+    /// This default is a behaviour change: zero-buffer players used to get
+    /// the registry's catchup like any other. Override this hook to return
+    /// None to keep that.
+    fn catchup_strategy_for(
+        &self,
+        _ctx: &SessionContext,
+        params: &PlayParams,
+    ) -> Option<CatchupStrategy> {
+        (params.buffer_ms == Some(0)).then_some(CatchupStrategy::LiveEdge)
+    }
+
+    /// Pick a bitrate to pace a player to, in bits per second
+    ///
+    /// Called after `on_play` accepts. A paced subscription sends no faster
+    /// than the bitrate and no earlier than its frame timestamps allow, so
+    /// the GOP catch-up plays out as live rather than arriving at once.
+    /// Meant for testing players under constrained bandwidth: a player that
+    /// falls behind the publisher lags and skips frames like a slow client.
+    /// The default of None sends as fast as the socket allows.
+    fn pace_bitrate_for(&self, _ctx: &SessionContext, _params: &PlayParams) -> Option<u64> {
+        None
+    }
+
+    /// Called on `getStreamLength`
+    ///
+    /// Returns the stream's duration in seconds, sent back in a `_result`.
+    /// The default of 0 tells the player the stream is live; return the
+    /// length of recorded (VOD) streams.
+    fn on_get_stream_length(
+        &self,
+        _ctx: &SessionContext,
+        _stream_name: &str,
+    ) -> impl std::future::Future<Output = f64> + Send {
+        async { 0.0 }
+    }
+
+    /// Called for commands the server does not handle itself
+    ///
+    /// Covers platform and vendor RPCs such as `checkBandwidth`. Returning
+    /// `Some(values)` answers with a `_result` whose command object is null
+    /// and whose arguments are `values`; `None` sends nothing.
+    ///
+    /// `getStreamLength` and `FCSubscribe` are offered here first too, and
+    /// only get the server's own reply (see
+    /// [`on_get_stream_length`](Self::on_get_stream_length)) when this
+    /// returns `None`.
+    fn on_command(
+        &self,
+        _ctx: &SessionContext,
+        _name: &str,
+        _transaction_id: f64,
+        _command_object: &AmfValue,
+        _args: &[AmfValue],
+    ) -> impl std::future::Future<Output = Option<Vec<AmfValue>>> + Send {
+        async { None }
+    }
+
+    /// Called when stream metadata is received, before it is cached or
+    /// sent to subscribers
+    ///
+    /// Fields may be added (e.g. `server`), changed or removed; subscribers
+    /// and late joiners get the result. Return false to drop the metadata.
+    /// [`Self::on_metadata`] is called afterwards with the final version.
+    fn on_metadata_mut(
+        &self,
+        _ctx: &StreamContext,
+        _metadata: &mut AmfObject,
+    ) -> impl std::future::Future<Output = bool> + Send {
+        async { true }
+    }
+
     /// Called when stream metadata is received (@setDataFrame/onMetaData)
     fn on_metadata(
         &self,
         _ctx: &StreamContext,
-        _metadata: &HashMap<String, AmfValue>,
+        _metadata: &AmfObject,
     ) -> impl std::future::Future<Output = ()> + Send {
         async {}
     }
@@ -168,6 +351,9 @@ pub trait RtmpHandler: Send + Sync + 'static {
     }
 
     /// Called for each video frame (when MediaDeliveryMode includes ParsedFrames)
+    ///
+    /// Only fires for legacy AVC. See [`Self::on_parsed_video_frame`] for
+    /// a callback that covers every codec.
     fn on_video_frame(
         &self,
         _ctx: &StreamContext,
@@ -178,6 +364,9 @@ pub trait RtmpHandler: Send + Sync + 'static {
     }
 
     /// Called for each audio frame (when MediaDeliveryMode includes ParsedFrames)
+    ///
+    /// Only fires for legacy AAC. See [`Self::on_parsed_audio_frame`] for
+    /// a callback that covers every codec, including G.711 and MP3.
     fn on_audio_frame(
         &self,
         _ctx: &StreamContext,
@@ -187,6 +376,63 @@ pub trait RtmpHandler: Send + Sync + 'static {
         async {}
     }
 
+    /// Called for each video frame of any codec (when MediaDeliveryMode
+    /// includes ParsedFrames)
+    ///
+    /// Fires for legacy and enhanced payloads alike, after
+    /// [`Self::on_video_frame`] or [`Self::on_enhanced_video_frame`].
+    fn on_parsed_video_frame(
+        &self,
+        _ctx: &StreamContext,
+        _frame: &VideoFrame,
+        _timestamp: u32,
+    ) -> impl std::future::Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// Called for each audio frame of any codec (when MediaDeliveryMode
+    /// includes ParsedFrames)
+    ///
+    /// Fires for legacy and enhanced payloads alike, after
+    /// [`Self::on_audio_frame`] or [`Self::on_enhanced_audio_frame`].
+    fn on_parsed_audio_frame(
+        &self,
+        _ctx: &StreamContext,
+        _frame: &AudioFrame,
+        _timestamp: u32,
+    ) -> impl std::future::Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// Called when a publisher's audio and video drift out of sync
+    ///
+    /// `drift_ms` is the latest video timestamp minus the latest audio
+    /// timestamp. Fires once each time the drift exceeds
+    /// [`ServerConfig::av_desync_threshold`](crate::ServerConfig::av_desync_threshold);
+    /// never fires unless a threshold is configured.
+    fn on_av_desync(
+        &self,
+        _ctx: &StreamContext,
+        _drift_ms: i64,
+    ) -> impl std::future::Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// Called when an AAC or AVC frame arrives before its sequence header
+    ///
+    /// `frame_type` is [`FrameType::Audio`] or [`FrameType::Video`]. Fires
+    /// once per track, for the first such frame; never fires unless
+    /// [`ServerConfig::sequence_header_policy`](crate::ServerConfig::sequence_header_policy)
+    /// is set.
+    fn on_frame_before_header(
+        &self,
+        _ctx: &StreamContext,
+        _frame_type: FrameType,
+        _timestamp: u32,
+    ) -> impl std::future::Future<Output = ()> + Send {
+        async {}
+    }
+
     /// Called when a keyframe is received
     fn on_keyframe(
         &self,
@@ -225,11 +471,57 @@ pub trait RtmpHandler: Send + Sync + 'static {
         async {}
     }
 
+    /// Called when a subscriber pauses (`paused` true) or resumes playback
+    ///
+    /// The default calls [`on_pause`](Self::on_pause) or
+    /// [`on_unpause`](Self::on_unpause); override this to handle both in
+    /// one place.
+    fn on_pause_changed(
+        &self,
+        ctx: &StreamContext,
+        paused: bool,
+    ) -> impl std::future::Future<Output = ()> + Send {
+        async move {
+            if paused {
+                self.on_pause(ctx).await
+            } else {
+                self.on_unpause(ctx).await
+            }
+        }
+    }
+
+    /// Called when a subscriber seeks to `milliseconds`
+    ///
+    /// Reposition the source feeding the stream and return true; frames
+    /// queued for the old position are then discarded and the sequence
+    /// headers resent. Return false (the default, as live streams cannot
+    /// seek) to answer with NetStream.Seek.Failed.
+    fn on_seek(
+        &self,
+        _ctx: &StreamContext,
+        _milliseconds: u32,
+    ) -> impl std::future::Future<Output = bool> + Send {
+        async { false }
+    }
+
     /// Called when the connection closes
-    fn on_disconnect(&self, _ctx: &SessionContext) -> impl std::future::Future<Output = ()> + Send {
+    ///
+    /// `reason` distinguishes normal endings from timeouts and corrupt data.
+    fn on_disconnect(
+        &self,
+        _ctx: &SessionContext,
+        _reason: &DisconnectReason,
+    ) -> impl std::future::Future<Output = ()> + Send {
         async {}
     }
 
+    /// Called with the exact bytes read from or written to the socket
+    ///
+    /// Covers the handshake and the chunk stream. Runs inline on the
+    /// session task, so it should be quick (e.g. append to a buffer or
+    /// file). Only called when [`TAPS_RAW`](Self::TAPS_RAW) is true.
+    fn on_raw_bytes(&self, _ctx: &SessionContext, _direction: ByteDirection, _data: &[u8]) {}
+
     /// Get the media delivery mode for this handler
     fn media_delivery_mode(&self) -> MediaDeliveryMode {
         MediaDeliveryMode::Both
@@ -311,7 +603,7 @@ impl RtmpHandler for LoggingHandler {
         AuthResult::Accept
     }
 
-    async fn on_metadata(&self, ctx: &StreamContext, metadata: &HashMap<String, AmfValue>) {
+    async fn on_metadata(&self, ctx: &StreamContext, metadata: &AmfObject) {
         tracing::debug!(
             session_id = ctx.session.session_id,
             stream_key = %ctx.stream_key,
@@ -320,8 +612,8 @@ impl RtmpHandler for LoggingHandler {
         );
     }
 
-    async fn on_disconnect(&self, ctx: &SessionContext) {
-        tracing::info!(session_id = ctx.session_id, "Connection closed");
+    async fn on_disconnect(&self, ctx: &SessionContext, reason: &DisconnectReason) {
+        tracing::info!(session_id = ctx.session_id, reason = ?reason, "Connection closed");
     }
 }
 
@@ -346,6 +638,17 @@ where
     H1: RtmpHandler,
     H2: RtmpHandler,
 {
+    const TAPS_RAW: bool = H1::TAPS_RAW || H2::TAPS_RAW;
+
+    fn on_raw_bytes(&self, ctx: &SessionContext, direction: ByteDirection, data: &[u8]) {
+        if H1::TAPS_RAW {
+            self.first.on_raw_bytes(ctx, direction, data);
+        }
+        if H2::TAPS_RAW {
+            self.second.on_raw_bytes(ctx, direction, data);
+        }
+    }
+
     async fn on_connection(&self, ctx: &SessionContext) -> bool {
         self.first.on_connection(ctx).await && self.second.on_connection(ctx).await
     }
@@ -359,6 +662,33 @@ where
         }
     }
 
+    fn chunk_size_for(&self, ctx: &SessionContext) -> Option<u32> {
+        self.first
+            .chunk_size_for(ctx)
+            .or_else(|| self.second.chunk_size_for(ctx))
+    }
+
+    fn stream_key_for(&self, ctx: &SessionContext, app: &str, name: &str) -> StreamKey {
+        let key = self.first.stream_key_for(ctx, app, name);
+        self.second.stream_key_for(ctx, &key.app, &key.name)
+    }
+
+    fn catchup_strategy_for(
+        &self,
+        ctx: &SessionContext,
+        params: &PlayParams,
+    ) -> Option<CatchupStrategy> {
+        self.first
+            .catchup_strategy_for(ctx, params)
+            .or_else(|| self.second.catchup_strategy_for(ctx, params))
+    }
+
+    fn pace_bitrate_for(&self, ctx: &SessionContext, params: &PlayParams) -> Option<u64> {
+        self.first
+            .pace_bitrate_for(ctx, params)
+            .or_else(|| self.second.pace_bitrate_for(ctx, params))
+    }
+
     async fn on_publish(&self, ctx: &SessionContext, params: &PublishParams) -> AuthResult {
         let result = self.first.on_publish(ctx, params).await;
         if result.is_accept() {
@@ -368,8 +698,90 @@ where
         }
     }
 
-    async fn on_disconnect(&self, ctx: &SessionContext) {
-        self.first.on_disconnect(ctx).await;
-        self.second.on_disconnect(ctx).await;
+    async fn on_metadata_mut(&self, ctx: &StreamContext, metadata: &mut AmfObject) -> bool {
+        self.first.on_metadata_mut(ctx, metadata).await
+            && self.second.on_metadata_mut(ctx, metadata).await
+    }
+
+    async fn on_parsed_video_frame(&self, ctx: &StreamContext, frame: &VideoFrame, timestamp: u32) {
+        self.first
+            .on_parsed_video_frame(ctx, frame, timestamp)
+            .await;
+        self.second
+            .on_parsed_video_frame(ctx, frame, timestamp)
+            .await;
+    }
+
+    async fn on_parsed_audio_frame(&self, ctx: &StreamContext, frame: &AudioFrame, timestamp: u32) {
+        self.first
+            .on_parsed_audio_frame(ctx, frame, timestamp)
+            .await;
+        self.second
+            .on_parsed_audio_frame(ctx, frame, timestamp)
+            .await;
+    }
+
+    async fn on_av_desync(&self, ctx: &StreamContext, drift_ms: i64) {
+        self.first.on_av_desync(ctx, drift_ms).await;
+        self.second.on_av_desync(ctx, drift_ms).await;
+    }
+
+    async fn on_frame_before_header(
+        &self,
+        ctx: &StreamContext,
+        frame_type: FrameType,
+        timestamp: u32,
+    ) {
+        self.first
+            .on_frame_before_header(ctx, frame_type, timestamp)
+            .await;
+        self.second
+            .on_frame_before_header(ctx, frame_type, timestamp)
+            .await;
+    }
+
+    async fn on_get_stream_length(&self, ctx: &SessionContext, stream_name: &str) -> f64 {
+        let length = self.first.on_get_stream_length(ctx, stream_name).await;
+        if length != 0.0 {
+            length
+        } else {
+            self.second.on_get_stream_length(ctx, stream_name).await
+        }
+    }
+
+    async fn on_command(
+        &self,
+        ctx: &SessionContext,
+        name: &str,
+        transaction_id: f64,
+        command_object: &AmfValue,
+        args: &[AmfValue],
+    ) -> Option<Vec<AmfValue>> {
+        match self
+            .first
+            .on_command(ctx, name, transaction_id, command_object, args)
+            .await
+        {
+            Some(result) => Some(result),
+            None => {
+                self.second
+                    .on_command(ctx, name, transaction_id, command_object, args)
+                    .await
+            }
+        }
+    }
+
+    async fn on_pause_changed(&self, ctx: &StreamContext, paused: bool) {
+        self.first.on_pause_changed(ctx, paused).await;
+        self.second.on_pause_changed(ctx, paused).await;
+    }
+
+    async fn on_seek(&self, ctx: &StreamContext, milliseconds: u32) -> bool {
+        self.first.on_seek(ctx, milliseconds).await || self.second.on_seek(ctx, milliseconds).await
+    }
+
+    async fn on_disconnect(&self, ctx: &SessionContext, reason: &DisconnectReason) {
+        self.first.on_disconnect(ctx, reason).await;
+        self.second.on_disconnect(ctx, reason).await;
     }
 }
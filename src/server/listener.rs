@@ -14,6 +14,9 @@ use crate::registry::{RegistryConfig, StreamRegistry};
 use crate::server::config::ServerConfig;
 use crate::server::connection::Connection;
 use crate::server::handler::RtmpHandler;
+use crate::server::pool::{BufferPool, BufferPoolStats};
+use crate::session::context::SessionControl;
+use crate::session::registry::SessionRegistry;
 
 /// RTMP server
 pub struct RtmpServer<H: RtmpHandler> {
@@ -22,6 +25,8 @@ pub struct RtmpServer<H: RtmpHandler> {
     registry: Arc<StreamRegistry>,
     next_session_id: AtomicU64,
     connection_semaphore: Option<Arc<Semaphore>>,
+    sessions: Arc<SessionRegistry>,
+    buffer_pool: Option<Arc<BufferPool>>,
 }
 
 impl<H: RtmpHandler> RtmpServer<H> {
@@ -31,23 +36,42 @@ impl<H: RtmpHandler> RtmpServer<H> {
     }
 
     /// Create a new server with custom registry configuration
+    ///
+    /// GOP buffering is disabled where either config disables it, and
+    /// the server's per-app overrides take precedence over the registry's.
     pub fn with_registry_config(
         config: ServerConfig,
         handler: H,
-        registry_config: RegistryConfig,
+        mut registry_config: RegistryConfig,
     ) -> Self {
+        registry_config.gop_buffer_enabled &= config.gop_buffer_enabled;
+        registry_config
+            .gop_buffer_overrides
+            .extend(config.gop_buffer_overrides.clone());
+
+        let mut registry = StreamRegistry::with_config(registry_config);
+        registry.set_max_streams(config.max_streams);
+
         let connection_semaphore = if config.max_connections > 0 {
             Some(Arc::new(Semaphore::new(config.max_connections)))
         } else {
             None
         };
 
+        let buffer_pool = if config.buffer_pool_size > 0 {
+            Some(Arc::new(BufferPool::new(config.buffer_pool_size)))
+        } else {
+            None
+        };
+
         Self {
             config,
             handler: Arc::new(handler),
-            registry: Arc::new(StreamRegistry::with_config(registry_config)),
+            registry: Arc::new(registry),
             next_session_id: AtomicU64::new(1),
             connection_semaphore,
+            sessions: Arc::new(SessionRegistry::new()),
+            buffer_pool,
         }
     }
 
@@ -56,6 +80,53 @@ impl<H: RtmpHandler> RtmpServer<H> {
         &self.registry
     }
 
+    /// Get the registry of running sessions
+    ///
+    /// Use it to list sessions or to disconnect one
+    /// ([`SessionRegistry::disconnect_session`]).
+    pub fn sessions(&self) -> &Arc<SessionRegistry> {
+        &self.sessions
+    }
+
+    /// Get the number of running sessions
+    pub fn session_count(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Get the session buffer pool counters
+    ///
+    /// All zero when pooling is disabled.
+    pub fn buffer_pool_stats(&self) -> BufferPoolStats {
+        self.buffer_pool
+            .as_ref()
+            .map(|pool| pool.stats())
+            .unwrap_or_default()
+    }
+
+    /// Close every running session
+    ///
+    /// Clients that negotiated the E-RTMP reconnect capability are first
+    /// sent a `NetConnection.Connect.ReconnectRequest`, pointing them at
+    /// `tc_url` if given, so they can migrate to another node. Legacy
+    /// clients are simply disconnected. Returns the number of sessions
+    /// notified; sessions close asynchronously.
+    pub fn drain(&self, tc_url: Option<String>) -> usize {
+        self.sessions.send_all(SessionControl::Shutdown { tc_url })
+    }
+
+    /// Wait for all sessions to close, up to `shutdown_timeout`
+    async fn wait_for_sessions(&self) {
+        if tokio::time::timeout(self.config.shutdown_timeout, self.sessions.wait_empty())
+            .await
+            .is_err()
+        {
+            tracing::warn!(
+                remaining = self.session_count(),
+                "Shutdown timeout elapsed with sessions still open"
+            );
+        }
+    }
+
     /// Run the server
     ///
     /// This method blocks until the server is shut down.
@@ -79,6 +150,10 @@ impl<H: RtmpHandler> RtmpServer<H> {
     }
 
     /// Run the server with graceful shutdown
+    ///
+    /// When `shutdown` resolves the server stops accepting connections,
+    /// drains the running sessions (see [`drain`](Self::drain)) and waits
+    /// up to `shutdown_timeout` for them to close.
     pub async fn run_until<F>(&self, shutdown: F) -> Result<()>
     where
         F: std::future::Future<Output = ()>,
@@ -97,6 +172,12 @@ impl<H: RtmpHandler> RtmpServer<H> {
             result = self.accept_loop(&listener) => result,
         };
 
+        let drained = self.drain(None);
+        if drained > 0 {
+            tracing::info!(sessions = drained, "Draining sessions");
+            self.wait_for_sessions().await;
+        }
+
         // Stop cleanup task on shutdown
         cleanup_handle.abort();
 
@@ -149,12 +230,28 @@ impl<H: RtmpHandler> RtmpServer<H> {
         let config = self.config.clone();
         let handler = Arc::clone(&self.handler);
         let registry = Arc::clone(&self.registry);
+        let sessions = Arc::clone(&self.sessions);
+
+        let mut connection = Connection::with_buffer_pool(
+            session_id,
+            socket,
+            peer_addr,
+            config,
+            handler,
+            registry,
+            self.buffer_pool.clone(),
+        );
+        if let Some(tx) = connection.control_sender() {
+            sessions.insert(session_id, tx);
+        }
 
         tokio::spawn(async move {
-            let mut connection =
-                Connection::new(session_id, socket, peer_addr, config, handler, registry);
+            let result = connection.run().await;
+            // Return pooled buffers before the session is deregistered
+            drop(connection);
+            sessions.remove(session_id);
 
-            if let Err(e) = connection.run().await {
+            if let Err(e) = result {
                 tracing::debug!(
                     session_id = session_id,
                     error = %e,
@@ -188,3 +285,65 @@ impl<H: RtmpHandler> RtmpServer<H> {
         self.config.bind_addr
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct AcceptAll;
+
+    impl RtmpHandler for AcceptAll {}
+
+    #[tokio::test]
+    async fn test_short_lived_connections_reuse_buffers() {
+        const BATCH: usize = 50;
+        const BATCHES: usize = 40;
+
+        let server = RtmpServer::new(
+            ServerConfig::default().buffer_pool_size(2 * BATCH),
+            AcceptAll,
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        for _ in 0..BATCHES {
+            let mut clients = Vec::with_capacity(BATCH);
+            for _ in 0..BATCH {
+                let client = TcpStream::connect(addr).await.unwrap();
+                let (socket, peer_addr) = listener.accept().await.unwrap();
+                server.handle_connection(socket, peer_addr).await;
+                clients.push(client);
+            }
+            drop(clients);
+
+            tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                while server.session_count() > 0 {
+                    tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                }
+            })
+            .await
+            .expect("sessions never closed");
+        }
+
+        // Two buffers per session; only the first batch allocates and
+        // every later session is served from the pool
+        let stats = server.buffer_pool_stats();
+        let total = (2 * BATCH * BATCHES) as u64;
+        assert_eq!(stats.allocated, (2 * BATCH) as u64);
+        assert_eq!(stats.reused, total - (2 * BATCH) as u64);
+        assert_eq!(stats.idle, 2 * BATCH);
+    }
+
+    #[tokio::test]
+    async fn test_buffer_pool_disabled() {
+        let server = RtmpServer::new(ServerConfig::default().buffer_pool_size(0), AcceptAll);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, peer_addr) = listener.accept().await.unwrap();
+        server.handle_connection(socket, peer_addr).await;
+
+        assert_eq!(server.buffer_pool_stats(), BufferPoolStats::default());
+    }
+}
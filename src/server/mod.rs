@@ -4,12 +4,22 @@
 //! - TCP listener for accepting connections
 //! - Per-connection handler
 //! - Handler trait for application callbacks
+//! - Optional FLV recording of published streams
+//! - Per-app publish and play access policies
+//! - Pooled session buffers
 
 pub mod config;
 pub mod connection;
 pub mod handler;
 pub mod listener;
+mod pacer;
+pub mod policy;
+mod pool;
+pub mod record;
 
-pub use config::ServerConfig;
-pub use handler::{AuthResult, RtmpHandler};
+pub use config::{SequenceHeaderPolicy, ServerConfig};
+pub use handler::{AuthResult, ByteDirection, DisconnectReason, RtmpHandler};
 pub use listener::RtmpServer;
+pub use policy::{Access, AppPolicy, TokenValidator};
+pub use pool::BufferPoolStats;
+pub use record::RecordConfig;
@@ -0,0 +1,81 @@
+//! Subscriber pacing
+//!
+//! Spreads media sent to a subscriber over time instead of writing it as
+//! fast as the socket allows. Two clocks bound each frame's send time:
+//! - Media timestamps: a frame is not sent before its timestamp has
+//!   elapsed since the first frame (send-as-live)
+//! - Bitrate: the bytes already sent must fit the target bitrate
+
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Send-time scheduler for one subscription
+#[derive(Debug)]
+pub(crate) struct Pacer {
+    /// Target bitrate in bits per second
+    bitrate: u64,
+    /// When the pacer was created (play or seek); both clocks count from
+    /// here, so frames arriving later than their timestamp go out at once
+    started_at: Instant,
+    /// Timestamp of the first frame
+    first_timestamp: Option<u32>,
+    /// Bytes scheduled so far
+    bytes_scheduled: u64,
+}
+
+impl Pacer {
+    /// Create a pacer for `bitrate` bits per second
+    pub(crate) fn new(bitrate: u64) -> Self {
+        Self {
+            bitrate: bitrate.max(1),
+            started_at: Instant::now(),
+            first_timestamp: None,
+            bytes_scheduled: 0,
+        }
+    }
+
+    /// Earliest time a frame may be sent; counts the frame as sent
+    pub(crate) fn schedule(&mut self, timestamp: u32, size: usize) -> Instant {
+        let first = *self.first_timestamp.get_or_insert(timestamp);
+        // Frames behind the first one (interleaving, publisher restarts)
+        // are only held back by the bitrate
+        let delta = timestamp.wrapping_sub(first) as i32;
+        let media_offset = Duration::from_millis(delta.max(0) as u64);
+
+        let bits = self.bytes_scheduled * 8;
+        let bitrate_offset = Duration::from_micros(bits * 1_000_000 / self.bitrate);
+
+        self.bytes_scheduled += size as u64;
+        self.started_at + media_offset.max(bitrate_offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitrate_spreads_burst() {
+        // 8 kbps = 1000 bytes/sec
+        let mut pacer = Pacer::new(8_000);
+        let start = pacer.started_at;
+
+        assert_eq!(pacer.schedule(0, 500), start);
+        assert_eq!(pacer.schedule(0, 500), start + Duration::from_millis(500));
+        assert_eq!(pacer.schedule(0, 500), start + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_timestamps_pace_small_frames() {
+        let mut pacer = Pacer::new(10_000_000);
+        let start = pacer.started_at;
+
+        assert_eq!(pacer.schedule(1000, 100), start);
+        assert_eq!(pacer.schedule(1040, 100), start + Duration::from_millis(40));
+        assert_eq!(pacer.schedule(2000, 100), start + Duration::from_secs(1));
+
+        // An earlier timestamp is not held back by the media clock
+        assert_eq!(pacer.schedule(990, 100), start + Duration::from_micros(240));
+    }
+}
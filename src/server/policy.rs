@@ -0,0 +1,229 @@
+//! Per-app access policies
+//!
+//! Simple deployments can require a token to publish or play in an app
+//! without writing [`RtmpHandler`](super::RtmpHandler) checks. Policies in
+//! [`ServerConfig::app_policies`](super::ServerConfig::app_policies) are
+//! checked before `on_publish` and `on_play`; a missing or invalid token
+//! rejects the request without calling the handler.
+//!
+//! The token is the `token` query parameter of the stream name
+//! (`name?token=abc`), or else of the tcUrl sent with `connect`. In apps
+//! with a policy the parameter is removed from the stream name before the
+//! handler and registry see it, so a player with a token meets a publisher
+//! without one.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::session::stream::StreamMode;
+
+/// Who may publish or play
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Access {
+    /// Anyone; the handler still decides
+    #[default]
+    Open,
+    /// Only requests carrying a token the validator accepts
+    TokenRequired,
+}
+
+/// Publish and play access of one app
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct AppPolicy {
+    /// Access to publish
+    pub publish: Access,
+    /// Access to play
+    pub play: Access,
+}
+
+impl AppPolicy {
+    /// Create a policy
+    pub fn new(publish: Access, play: Access) -> Self {
+        Self { publish, play }
+    }
+
+    /// Access for publishing or playing
+    pub fn access(&self, mode: StreamMode) -> Access {
+        match mode {
+            StreamMode::Publishing => self.publish,
+            StreamMode::Playing => self.play,
+            StreamMode::Idle => Access::Open,
+        }
+    }
+}
+
+type ValidateFn = dyn Fn(StreamMode, &str, &str, &str) -> bool + Send + Sync;
+
+/// Checks tokens for apps whose policy requires one
+///
+/// Called with the mode, app, stream name (without the token) and token.
+#[derive(Clone)]
+pub struct TokenValidator(Arc<ValidateFn>);
+
+impl TokenValidator {
+    /// Create a validator from a function
+    pub fn new(f: impl Fn(StreamMode, &str, &str, &str) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// Check a token
+    pub fn validate(&self, mode: StreamMode, app: &str, stream: &str, token: &str) -> bool {
+        (self.0)(mode, app, stream, token)
+    }
+}
+
+impl fmt::Debug for TokenValidator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenValidator").finish_non_exhaustive()
+    }
+}
+
+/// Check a publish or play against the app's policy
+///
+/// Returns the stream name to use, or the reason for rejecting the request.
+pub(crate) fn check(
+    policy: Option<&AppPolicy>,
+    validator: Option<&TokenValidator>,
+    mode: StreamMode,
+    app: &str,
+    stream_name: &str,
+    tc_url: Option<&str>,
+) -> Result<String, String> {
+    let Some(policy) = policy else {
+        return Ok(stream_name.to_string());
+    };
+
+    let (name, token) = take_token(stream_name);
+    if policy.access(mode) == Access::Open {
+        return Ok(name);
+    }
+
+    let token = token.or_else(|| tc_url.and_then(|url| take_token(url).1));
+    let valid = match (&token, validator) {
+        (Some(token), Some(validator)) => validator.validate(mode, app, &name, token),
+        (Some(_), None) => {
+            tracing::warn!(app, "App policy requires a token but no validator is set");
+            false
+        }
+        (None, _) => false,
+    };
+
+    match (token, valid) {
+        (_, true) => Ok(name),
+        (None, _) => Err("Token required".to_string()),
+        (Some(_), false) => Err("Invalid token".to_string()),
+    }
+}
+
+/// Split the `token` query parameter off a stream name or URL
+fn take_token(name: &str) -> (String, Option<String>) {
+    let Some((base, query)) = name.split_once('?') else {
+        return (name.to_string(), None);
+    };
+
+    let mut token = None;
+    let rest: Vec<&str> = query
+        .split('&')
+        .filter(|param| match param.strip_prefix("token=") {
+            Some(value) => {
+                token.get_or_insert_with(|| value.to_string());
+                false
+            }
+            None => true,
+        })
+        .collect();
+
+    if rest.is_empty() {
+        (base.to_string(), token)
+    } else {
+        (format!("{}?{}", base, rest.join("&")), token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validator() -> TokenValidator {
+        TokenValidator::new(|mode, app, stream, token| {
+            mode == StreamMode::Playing && app == "live" && stream == "cam" && token == "secret"
+        })
+    }
+
+    #[test]
+    fn test_take_token() {
+        assert_eq!(take_token("cam"), ("cam".into(), None));
+        assert_eq!(
+            take_token("cam?token=secret"),
+            ("cam".into(), Some("secret".into()))
+        );
+        assert_eq!(
+            take_token("cam?a=1&token=secret&b=2"),
+            ("cam?a=1&b=2".into(), Some("secret".into()))
+        );
+        assert_eq!(take_token("cam?a=1"), ("cam?a=1".into(), None));
+    }
+
+    #[test]
+    fn test_check_open_publish_token_play() {
+        let policy = AppPolicy::new(Access::Open, Access::TokenRequired);
+        let validator = validator();
+        let check =
+            |mode, name, tc_url| check(Some(&policy), Some(&validator), mode, "live", name, tc_url);
+
+        assert_eq!(check(StreamMode::Publishing, "cam", None), Ok("cam".into()));
+        assert_eq!(
+            check(StreamMode::Playing, "cam", None),
+            Err("Token required".into())
+        );
+        assert_eq!(
+            check(StreamMode::Playing, "cam?token=wrong", None),
+            Err("Invalid token".into())
+        );
+        assert_eq!(
+            check(StreamMode::Playing, "cam?token=secret", None),
+            Ok("cam".into())
+        );
+        assert_eq!(
+            check(
+                StreamMode::Playing,
+                "cam",
+                Some("rtmp://host/live?token=secret")
+            ),
+            Ok("cam".into())
+        );
+    }
+
+    #[test]
+    fn test_check_without_policy_or_validator() {
+        // Apps without a policy are left to the handler, names untouched
+        assert_eq!(
+            check(None, None, StreamMode::Playing, "live", "cam?token=x", None),
+            Ok("cam?token=x".into())
+        );
+
+        // A required token cannot be checked without a validator
+        let policy = AppPolicy::new(Access::TokenRequired, Access::Open);
+        assert_eq!(
+            check(
+                Some(&policy),
+                None,
+                StreamMode::Publishing,
+                "live",
+                "cam?token=x",
+                None
+            ),
+            Err("Invalid token".into())
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Session buffer pool
+//!
+//! Sessions take their read and write buffers from a freelist shared by the
+//! server and hand them back on disconnect, so servers with many short-lived
+//! connections reuse a few allocations instead of thrashing the allocator.
+//! Buffers are cleared on return; a session never sees another's bytes.
+//! Buffers that grew well past their configured size are freed instead of
+//! kept, so one session's large message doesn't pin memory in the pool.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use bytes::BytesMut;
+
+/// Freelist of session buffers
+#[derive(Debug)]
+pub(crate) struct BufferPool {
+    /// Idle buffers
+    idle: Mutex<Vec<BytesMut>>,
+    /// Most idle buffers kept; extras are freed
+    max_idle: usize,
+    /// Buffers allocated because none was idle
+    allocated: AtomicU64,
+    /// Buffers handed out from the freelist
+    reused: AtomicU64,
+}
+
+/// Buffer pool counters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufferPoolStats {
+    /// Buffers allocated because none was idle
+    pub allocated: u64,
+    /// Buffers handed out from the freelist
+    pub reused: u64,
+    /// Buffers currently idle in the pool
+    pub idle: usize,
+}
+
+impl BufferPool {
+    /// Create a pool keeping at most `max_idle` idle buffers
+    pub(crate) fn new(max_idle: usize) -> Self {
+        Self {
+            idle: Mutex::new(Vec::new()),
+            max_idle,
+            allocated: AtomicU64::new(0),
+            reused: AtomicU64::new(0),
+        }
+    }
+
+    /// Take an empty buffer with at least `capacity` bytes of room
+    pub(crate) fn get(&self, capacity: usize) -> BytesMut {
+        let pooled = self
+            .idle
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .pop();
+        match pooled {
+            Some(mut buf) => {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                buf.reserve(capacity);
+                buf
+            }
+            None => {
+                self.allocated.fetch_add(1, Ordering::Relaxed);
+                BytesMut::with_capacity(capacity)
+            }
+        }
+    }
+
+    /// Return a buffer configured for `size` bytes to the pool
+    ///
+    /// Buffers grown past twice that size are freed.
+    pub(crate) fn put(&self, mut buf: BytesMut, size: usize) {
+        if buf.capacity() > size.saturating_mul(2) {
+            return;
+        }
+        buf.clear();
+        let mut idle = self
+            .idle
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if idle.len() < self.max_idle {
+            idle.push(buf);
+        }
+    }
+
+    /// Current counters
+    pub(crate) fn stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            allocated: self.allocated.load(Ordering::Relaxed),
+            reused: self.reused.load(Ordering::Relaxed),
+            idle: self
+                .idle
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_returned_buffers_are_reused_empty() {
+        let pool = BufferPool::new(4);
+
+        let mut buf = pool.get(1024);
+        buf.extend_from_slice(b"secret");
+        let ptr = buf.as_ptr();
+        pool.put(buf, 1024);
+
+        let buf = pool.get(1024);
+        assert_eq!(buf.as_ptr(), ptr);
+        assert!(buf.is_empty());
+        assert!(buf.capacity() >= 1024);
+        assert_eq!(
+            pool.stats(),
+            BufferPoolStats {
+                allocated: 1,
+                reused: 1,
+                idle: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_idle_buffers_capped() {
+        let pool = BufferPool::new(2);
+        let bufs: Vec<_> = (0..5).map(|_| pool.get(64)).collect();
+        for buf in bufs {
+            pool.put(buf, 64);
+        }
+        assert_eq!(pool.stats().idle, 2);
+        assert_eq!(pool.stats().allocated, 5);
+    }
+
+    #[test]
+    fn test_oversized_buffers_freed() {
+        let pool = BufferPool::new(4);
+
+        let mut buf = pool.get(64);
+        buf.reserve(1024);
+        pool.put(buf, 64);
+        assert_eq!(pool.stats().idle, 0);
+
+        // Growth within twice the size is kept
+        let mut buf = pool.get(64);
+        buf.reserve(100);
+        pool.put(buf, 64);
+        assert_eq!(pool.stats().idle, 1);
+    }
+}
@@ -0,0 +1,602 @@
+//! Server-side FLV recording
+//!
+//! When [`ServerConfig::record`](super::ServerConfig::record) is set, every
+//! accepted publish whose key passes the filter gets a registry
+//! [sink](crate::registry::FrameSink) that hands frames to a blocking
+//! writer task, which writes the stream to FLV files under
+//! `dir/<app>/<name>-<unix ms>.flv`. The recorder does not count as a
+//! subscriber of the stream.
+//!
+//! With a segment duration, files are cut on keyframes by a
+//! [`SegmentingFlvWriter`]. Each file begins with the metadata and sequence
+//! headers, and its timestamps start at zero.
+//!
+//! Frames are written in the order they are published. A
+//! [reorder depth](RecordConfig::reorder_depth) holds back a few frames so
+//! they are written in timestamp order even if the publisher interleaves
+//! audio and video slightly out of order.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use std::sync::mpsc::{self, TrySendError};
+
+use tokio::task::JoinHandle;
+
+use crate::media::flv::FlvTag;
+use crate::media::segment::{Segment, SegmentingFlvWriter};
+use crate::registry::{
+    BroadcastFrame, FrameType, RegistryError, SinkId, StreamKey, StreamRegistry,
+};
+
+/// Predicate selecting which streams are recorded
+pub type RecordFilter = Arc<dyn Fn(&StreamKey) -> bool + Send + Sync>;
+
+/// Callback invoked with every completed recording file
+pub type SegmentCallback = Arc<dyn Fn(&StreamKey, &Segment) + Send + Sync>;
+
+/// Recording configuration
+#[derive(Clone)]
+pub struct RecordConfig {
+    /// Directory recordings are written to
+    pub dir: PathBuf,
+
+    /// Streams to record
+    pub filter: RecordFilter,
+
+    /// Split recordings into files of about this length (None = one file
+    /// per publish)
+    pub segment: Option<Duration>,
+
+    /// Called when a recording file is complete
+    pub on_segment: Option<SegmentCallback>,
+
+    /// Frames held back to write them in timestamp order (0 = write them
+    /// as published)
+    pub reorder_depth: usize,
+}
+
+impl RecordConfig {
+    /// Record every stream into `dir`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            filter: Arc::new(|_| true),
+            segment: None,
+            on_segment: None,
+            reorder_depth: 0,
+        }
+    }
+
+    /// Only record streams for which `filter` returns true
+    pub fn filter(mut self, filter: impl Fn(&StreamKey) -> bool + Send + Sync + 'static) -> Self {
+        self.filter = Arc::new(filter);
+        self
+    }
+
+    /// Split recordings into segments, cut on keyframes
+    pub fn segment(mut self, duration: Duration) -> Self {
+        self.segment = Some(duration);
+        self
+    }
+
+    /// Call `f` with every completed file, e.g. to hand segments to a packager
+    pub fn on_segment(mut self, f: impl Fn(&StreamKey, &Segment) + Send + Sync + 'static) -> Self {
+        self.on_segment = Some(Arc::new(f));
+        self
+    }
+
+    /// Write frames sorted by timestamp within a window of `depth`
+    ///
+    /// FLV timestamps are decode times and must not go backwards, which
+    /// some publishers break when interleaving audio and video. Frames
+    /// arriving later than the window are written as soon as possible,
+    /// out of order.
+    pub fn reorder_depth(mut self, depth: usize) -> Self {
+        self.reorder_depth = depth;
+        self
+    }
+
+    /// Check whether a stream should be recorded
+    pub fn matches(&self, key: &StreamKey) -> bool {
+        (self.filter)(key)
+    }
+}
+
+impl fmt::Debug for RecordConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordConfig")
+            .field("dir", &self.dir)
+            .field("segment", &self.segment)
+            .field("reorder_depth", &self.reorder_depth)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Handle to a running stream recorder
+pub(crate) struct Recorder {
+    registry: Arc<StreamRegistry>,
+    key: StreamKey,
+    sink: SinkId,
+    task: JoinHandle<()>,
+}
+
+impl Recorder {
+    /// Attach a sink to `key` and start recording it
+    pub(crate) async fn start(
+        registry: Arc<StreamRegistry>,
+        key: StreamKey,
+        config: RecordConfig,
+    ) -> Result<Self, RegistryError> {
+        // Bounded like a subscriber's channel, so a stalled disk costs
+        // frames rather than memory
+        let capacity = registry.config().broadcast_capacity;
+        let (tx, rx) = mpsc::sync_channel(capacity);
+
+        let stream = key.clone();
+        let mut lagging = false;
+        let sink = move |frame: &BroadcastFrame| match tx.try_send(frame.clone()) {
+            Ok(()) => lagging = false,
+            Err(TrySendError::Full(_)) => {
+                if !lagging {
+                    tracing::warn!(stream = %stream, "Recorder lagged, frames lost");
+                    lagging = true;
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => {}
+        };
+        let sink = registry.add_sink(&key, Box::new(sink)).await?;
+
+        // File creation and writes block, so they stay off the runtime
+        let stream = key.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            let mut writer = RecordWriter::new(&config, &stream);
+            if let Err(e) = record(&mut writer, rx) {
+                tracing::warn!(stream = %stream, error = %e, "Recording failed");
+            }
+            writer.close();
+        });
+
+        Ok(Self {
+            registry,
+            key,
+            sink,
+            task,
+        })
+    }
+
+    /// Write out frames already broadcast, then close the recording
+    pub(crate) async fn finish(self) {
+        // Dropping the sink closes the channel once its frames are written
+        drop(self.registry.remove_sink(&self.key, self.sink).await);
+        let _ = self.task.await;
+    }
+}
+
+/// Write frames until the sink is dropped
+fn record(writer: &mut RecordWriter, rx: mpsc::Receiver<BroadcastFrame>) -> io::Result<()> {
+    for frame in rx {
+        writer.write(frame)?;
+    }
+    Ok(())
+}
+
+/// Writes frames of one stream, rotating files between segments
+struct RecordWriter {
+    key: StreamKey,
+    segments: SegmentingFlvWriter,
+    reorder: ReorderBuffer,
+}
+
+impl RecordWriter {
+    fn new(config: &RecordConfig, key: &StreamKey) -> Self {
+        let dir = config.dir.join(sanitize(&key.app));
+        let name = sanitize(&key.name);
+        let paths = move |_| next_path(&dir, &name);
+
+        // Without a segment duration the first file never rolls over
+        let target = config.segment.unwrap_or(Duration::MAX);
+        let mut segments = SegmentingFlvWriter::new(target, paths);
+
+        let stream = key.clone();
+        let on_segment = config.on_segment.clone();
+        segments = segments.on_segment(move |segment| {
+            tracing::info!(
+                stream = %stream,
+                path = %segment.path.display(),
+                duration_ms = segment.duration().as_millis() as u64,
+                "Recording closed"
+            );
+            if let Some(on_segment) = &on_segment {
+                on_segment(&stream, segment);
+            }
+        });
+
+        Self {
+            key: key.clone(),
+            segments,
+            reorder: ReorderBuffer::new(config.reorder_depth),
+        }
+    }
+
+    fn write(&mut self, frame: BroadcastFrame) -> io::Result<()> {
+        let is_timed = matches!(
+            frame.frame_type,
+            FrameType::Video | FrameType::Audio | FrameType::Data
+        );
+        if is_timed && !frame.is_header {
+            return match self.reorder.push(frame) {
+                Some(frame) => self.write_tag(&frame),
+                None => Ok(()),
+            };
+        }
+
+        // Metadata and headers apply to what follows
+        self.flush_reordered()?;
+        self.write_tag(&frame)
+    }
+
+    fn write_tag(&mut self, frame: &BroadcastFrame) -> io::Result<()> {
+        let tag = FlvTag::from(frame);
+        match frame.frame_type {
+            // Cue points belong where they were sent, not in every preamble
+            FrameType::Data => self.segments.write_data_tag(&tag),
+            _ => self.segments.write_tag(&tag),
+        }
+    }
+
+    fn flush_reordered(&mut self) -> io::Result<()> {
+        while let Some(frame) = self.reorder.pop() {
+            self.write_tag(&frame)?;
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        if let Err(e) = self.flush_reordered() {
+            tracing::warn!(stream = %self.key, error = %e, "Failed to write held back frames");
+        }
+        if let Err(e) = self.segments.finish() {
+            tracing::warn!(stream = %self.key, error = %e, "Failed to flush recording");
+        }
+    }
+}
+
+/// Window of frames sorted by timestamp
+struct ReorderBuffer {
+    depth: usize,
+    /// Held frames, earliest first
+    frames: VecDeque<BroadcastFrame>,
+}
+
+impl ReorderBuffer {
+    fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            frames: VecDeque::with_capacity(depth + 1),
+        }
+    }
+
+    /// Add a frame, returning the earliest one once more than `depth` are held
+    fn push(&mut self, frame: BroadcastFrame) -> Option<BroadcastFrame> {
+        if self.depth == 0 {
+            return Some(frame);
+        }
+
+        // After frames with the same timestamp, keeping arrival order
+        let index = self
+            .frames
+            .partition_point(|held| held.timestamp <= frame.timestamp);
+        self.frames.insert(index, frame);
+
+        if self.frames.len() > self.depth {
+            self.pop()
+        } else {
+            None
+        }
+    }
+
+    /// Take the earliest held frame
+    fn pop(&mut self) -> Option<BroadcastFrame> {
+        self.frames.pop_front()
+    }
+}
+
+/// Path for the next file in `dir`, never reusing an existing name
+fn next_path(dir: &Path, name: &str) -> PathBuf {
+    let mut millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    loop {
+        let path = dir.join(format!("{}-{}.flv", name, millis));
+        if !path.exists() {
+            return path;
+        }
+        millis += 1;
+    }
+}
+
+/// Make a client-supplied name safe to use as a path component
+fn sanitize(name: &str) -> String {
+    let clean: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect();
+    if clean.is_empty() {
+        "_".to_string()
+    } else {
+        clean
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::flv::{FlvReader, FlvTagType};
+    use crate::test_util::temp_dir;
+    use bytes::Bytes;
+    use std::fs::{self, File};
+
+    fn read_recordings(dir: &Path) -> Vec<Vec<FlvTag>> {
+        let mut paths: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        paths.sort();
+        paths
+            .iter()
+            .map(|p| {
+                FlvReader::new(File::open(p).unwrap())
+                    .unwrap()
+                    .collect::<io::Result<_>>()
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    async fn publish_synthetic(registry: &StreamRegistry, key: &StreamKey, seconds: u32) {
+        registry
+            .broadcast(
+                key,
+                BroadcastFrame::metadata(Bytes::from_static(b"\x02\x00\x00")),
+            )
+            .await;
+        registry
+            .broadcast(
+                key,
+                BroadcastFrame::video(0, Bytes::from_static(&[0x17, 0x00, 0, 0, 0]), true, true),
+            )
+            .await;
+        registry
+            .broadcast(
+                key,
+                BroadcastFrame::audio(0, Bytes::from_static(&[0xAF, 0x00, 0x12, 0x10]), true),
+            )
+            .await;
+
+        // One keyframe per second, 4 inter frames and 5 audio frames between
+        for ts in (0..seconds * 1000).step_by(200) {
+            let keyframe = ts % 1000 == 0;
+            let video = if keyframe { 0x17 } else { 0x27 };
+            registry
+                .broadcast(
+                    key,
+                    BroadcastFrame::video(
+                        ts,
+                        Bytes::copy_from_slice(&[video, 0x01, 0, 0, 0]),
+                        keyframe,
+                        false,
+                    ),
+                )
+                .await;
+            registry
+                .broadcast(
+                    key,
+                    BroadcastFrame::audio(ts + 10, Bytes::from_static(&[0xAF, 0x01, 0x21]), false),
+                )
+                .await;
+        }
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("live"), "live");
+        assert_eq!(sanitize("../etc/passwd"), "___etc_passwd");
+        assert_eq!(sanitize(""), "_");
+    }
+
+    #[tokio::test]
+    async fn test_record_stream() {
+        let dir = temp_dir("record");
+        let registry = Arc::new(StreamRegistry::new());
+        let key = StreamKey::new("live", "test");
+        registry.register_publisher(&key, 1).await.unwrap();
+
+        let recorder = Recorder::start(registry.clone(), key.clone(), RecordConfig::new(&dir))
+            .await
+            .unwrap();
+        publish_synthetic(&registry, &key, 2).await;
+
+        // The recorder is not a subscriber
+        let stats = registry.get_stream_stats(&key).await.unwrap();
+        assert_eq!(stats.subscriber_count, 0);
+        recorder.finish().await;
+
+        let files = read_recordings(&dir.join("live"));
+        assert_eq!(files.len(), 1);
+        let tags = &files[0];
+
+        assert_eq!(tags[0].tag_type, FlvTagType::Script);
+        assert!(tags[1].is_avc_sequence_header());
+        assert!(tags[2].is_aac_sequence_header());
+        // 10 video and 10 audio frames
+        assert_eq!(tags.len(), 3 + 20);
+        assert!(tags[3].is_keyframe());
+        assert_eq!(tags.last().unwrap().timestamp, 1810);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_record_segments_on_keyframes() {
+        let dir = temp_dir("segments");
+        let registry = Arc::new(StreamRegistry::new());
+        let key = StreamKey::new("live", "seg");
+        registry.register_publisher(&key, 1).await.unwrap();
+
+        let spans = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = spans.clone();
+        let config = RecordConfig::new(&dir)
+            .segment(Duration::from_millis(1500))
+            .on_segment(move |key, segment| {
+                assert_eq!(key.name, "seg");
+                seen.lock().unwrap().push((segment.start, segment.end));
+            });
+        let recorder = Recorder::start(registry.clone(), key.clone(), config)
+            .await
+            .unwrap();
+        publish_synthetic(&registry, &key, 5).await;
+        recorder.finish().await;
+
+        // Keyframes at 0..4s; cuts at 2s and 4s
+        assert_eq!(
+            *spans.lock().unwrap(),
+            vec![(0, 2000), (2000, 4000), (4000, 4810)]
+        );
+        let files = read_recordings(&dir.join("live"));
+        assert_eq!(files.len(), 3);
+        for tags in &files {
+            assert_eq!(tags[0].tag_type, FlvTagType::Script);
+            assert!(tags[1].is_avc_sequence_header());
+            assert!(tags[2].is_aac_sequence_header());
+            assert!(tags[3].is_keyframe());
+            assert_eq!(tags[3].timestamp, 0);
+        }
+        let frames: usize = files.iter().map(|tags| tags.len() - 3).sum();
+        assert_eq!(frames, 50);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_record_cue_points() {
+        let dir = temp_dir("cue");
+        let registry = Arc::new(StreamRegistry::new());
+        let key = StreamKey::new("live", "cue");
+        registry.register_publisher(&key, 1).await.unwrap();
+
+        let config = RecordConfig::new(&dir).segment(Duration::from_millis(1500));
+        let recorder = Recorder::start(registry.clone(), key.clone(), config)
+            .await
+            .unwrap();
+        publish_synthetic(&registry, &key, 2).await;
+        let cue = Bytes::from_static(b"\x02\x00\x0aonCuePoint");
+        registry
+            .broadcast(&key, BroadcastFrame::data(1500, cue.clone()))
+            .await;
+        for ts in [2000, 2200] {
+            let video = Bytes::from_static(&[0x17, 0x01, 0, 0, 0]);
+            registry
+                .broadcast(&key, BroadcastFrame::video(ts, video, ts == 2000, false))
+                .await;
+        }
+        recorder.finish().await;
+
+        let files = read_recordings(&dir.join("live"));
+        assert_eq!(files.len(), 2);
+        let cues: Vec<_> = files[0].iter().filter(|tag| tag.data == cue).collect();
+        assert_eq!(cues.len(), 1);
+        assert_eq!(cues[0].tag_type, FlvTagType::Script);
+        assert_eq!(cues[0].timestamp, 1500);
+
+        // Only the stream's metadata starts the next segment
+        assert!(files[1].iter().all(|tag| tag.data != cue));
+        assert_eq!(files[1][0].data, Bytes::from_static(b"\x02\x00\x00"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_record_reorders_by_timestamp() {
+        // Audio published a little behind the video it belongs with
+        let frames = [
+            (FrameType::Video, 0),
+            (FrameType::Video, 40),
+            (FrameType::Audio, 21),
+            (FrameType::Video, 80),
+            (FrameType::Audio, 42),
+            (FrameType::Audio, 63),
+            (FrameType::Video, 120),
+        ];
+        let frame = |frame_type: FrameType, timestamp: u32| match frame_type {
+            FrameType::Video => {
+                let first = if timestamp == 0 { 0x17 } else { 0x27 };
+                let data = [first, 0x01, 0, 0, 0, 0, 0, 0, 1, 0x41];
+                BroadcastFrame::video(
+                    timestamp,
+                    Bytes::copy_from_slice(&data),
+                    timestamp == 0,
+                    false,
+                )
+            }
+            _ => BroadcastFrame::audio(timestamp, Bytes::from_static(&[0xAF, 0x01, 0x21]), false),
+        };
+
+        for depth in [0, 2] {
+            let dir = temp_dir(&format!("reorder-{}", depth));
+            let registry = Arc::new(StreamRegistry::new());
+            let key = StreamKey::new("live", "interleaved");
+            registry.register_publisher(&key, 1).await.unwrap();
+
+            let config = RecordConfig::new(&dir).reorder_depth(depth);
+            let recorder = Recorder::start(registry.clone(), key.clone(), config)
+                .await
+                .unwrap();
+            registry
+                .broadcast(
+                    &key,
+                    BroadcastFrame::video(
+                        0,
+                        Bytes::from_static(&[0x17, 0x00, 0, 0, 0]),
+                        true,
+                        true,
+                    ),
+                )
+                .await;
+            for (frame_type, timestamp) in frames {
+                registry.broadcast(&key, frame(frame_type, timestamp)).await;
+            }
+            recorder.finish().await;
+
+            let files = read_recordings(&dir.join("live"));
+            let tags = &files[0];
+            assert!(tags[0].is_avc_sequence_header());
+            let written: Vec<_> = tags[1..].iter().map(|tag| tag.timestamp).collect();
+
+            let mut expected: Vec<_> = frames.iter().map(|&(_, timestamp)| timestamp).collect();
+            if depth > 0 {
+                expected.sort_unstable();
+            }
+            assert_eq!(written, expected, "depth {}", depth);
+            let _ = fs::remove_dir_all(&dir);
+        }
+    }
+
+    #[test]
+    fn test_record_config() {
+        let config = RecordConfig::new("/tmp/rec")
+            .filter(|key| key.app == "live")
+            .segment(Duration::from_secs(10));
+
+        assert!(config.matches(&StreamKey::new("live", "a")));
+        assert!(!config.matches(&StreamKey::new("vod", "a")));
+        assert_eq!(config.segment, Some(Duration::from_secs(10)));
+        assert_eq!(config.reorder_depth, 0);
+        assert!(format!("{:?}", config).contains("/tmp/rec"));
+    }
+}
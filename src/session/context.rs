@@ -4,8 +4,14 @@
 //! and methods to interact with the connection.
 
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
+use tokio::sync::mpsc;
+
+use crate::media::aac::AudioSpecificConfig;
+use crate::media::h264::AvcConfig;
+use crate::protocol::chunk::ChunkStreamInfo;
+use crate::protocol::constants::DEFAULT_CHUNK_SIZE;
 use crate::protocol::enhanced::EnhancedCapabilities;
 use crate::protocol::message::ConnectParams;
 use crate::protocol::quirks::EncoderType;
@@ -37,6 +43,79 @@ pub struct SessionContext {
 
     /// Current session statistics
     pub stats: SessionStats,
+
+    /// Protocol parameters negotiated by the peer so far
+    pub protocol_params: ProtocolParams,
+
+    /// Control channel to the running connection (if any)
+    pub(crate) control: Option<mpsc::UnboundedSender<SessionControl>>,
+
+    /// Inbound chunk streams, kept current by the connection
+    pub(crate) chunk_streams: Arc<Mutex<Vec<ChunkStreamInfo>>>,
+}
+
+/// Protocol parameters announced by the peer
+///
+/// Updated as the peer's control messages and connect command arrive.
+/// Useful for diagnosing encoders that send unusual values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProtocolParams {
+    /// Chunk size the peer sends with (Set Chunk Size)
+    pub in_chunk_size: u32,
+
+    /// Window acknowledgement size the peer announced
+    pub window_ack_size: Option<u32>,
+
+    /// Bandwidth the peer set for us (Set Peer Bandwidth)
+    pub peer_bandwidth: Option<u32>,
+
+    /// Limit type of the peer bandwidth (0 = hard, 1 = soft, 2 = dynamic)
+    pub peer_bandwidth_limit_type: Option<u8>,
+
+    /// objectEncoding from the connect command (0 = AMF0, 3 = AMF3)
+    pub object_encoding: Option<f64>,
+
+    /// Timestamp field of the peer's C1 handshake packet
+    pub handshake_timestamp: Option<u32>,
+
+    /// Our C1 receive time minus `handshake_timestamp`, in milliseconds
+    ///
+    /// A rough clock skew estimate for peers that send wall-clock time in
+    /// C1 (see [`Handshake::peer_clock_skew_ms`]). Telemetry only.
+    ///
+    /// [`Handshake::peer_clock_skew_ms`]: crate::protocol::handshake::Handshake::peer_clock_skew_ms
+    pub clock_skew_ms: Option<i32>,
+}
+
+impl Default for ProtocolParams {
+    fn default() -> Self {
+        Self {
+            in_chunk_size: DEFAULT_CHUNK_SIZE,
+            window_ack_size: None,
+            peer_bandwidth: None,
+            peer_bandwidth_limit_type: None,
+            object_encoding: None,
+            handshake_timestamp: None,
+            clock_skew_ms: None,
+        }
+    }
+}
+
+/// Requests delivered to a running connection from outside its task
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SessionControl {
+    /// Ask the client to reconnect, then close the session
+    Reconnect { tc_url: Option<String> },
+
+    /// Close the session for server shutdown, asking the client to
+    /// reconnect first if it negotiated the reconnect capability
+    Shutdown { tc_url: Option<String> },
+
+    /// Close the session at the application's request
+    Evict,
+
+    /// Close a publisher whose stream another publisher took over
+    Takeover,
 }
 
 impl SessionContext {
@@ -50,6 +129,9 @@ impl SessionContext {
             connect_params: None,
             enhanced_capabilities: None,
             stats: SessionStats::default(),
+            protocol_params: ProtocolParams::default(),
+            control: None,
+            chunk_streams: Arc::default(),
         }
     }
 
@@ -57,6 +139,7 @@ impl SessionContext {
     pub fn with_connect(&mut self, params: ConnectParams, encoder_type: EncoderType) {
         self.app = params.app.clone();
         self.encoder_type = encoder_type;
+        self.protocol_params.object_encoding = Some(params.object_encoding);
         self.connect_params = Some(Arc::new(params));
     }
 
@@ -75,6 +158,53 @@ impl SessionContext {
             .unwrap_or(false)
     }
 
+    /// Check if the client negotiated the E-RTMP reconnect capability
+    pub fn supports_reconnect(&self) -> bool {
+        self.enhanced_capabilities
+            .as_ref()
+            .map(|c| c.supports_reconnect())
+            .unwrap_or(false)
+    }
+
+    /// Ask the client to reconnect, optionally to a different tcUrl
+    ///
+    /// The connection sends an `onStatus` with code
+    /// `NetConnection.Connect.ReconnectRequest` and then closes gracefully.
+    /// Returns `false` without doing anything if the client did not
+    /// negotiate the reconnect capability or the session has already ended.
+    pub fn request_reconnect(&self, tc_url: Option<String>) -> bool {
+        if !self.supports_reconnect() {
+            return false;
+        }
+
+        self.control
+            .as_ref()
+            .map(|tx| tx.send(SessionControl::Reconnect { tc_url }).is_ok())
+            .unwrap_or(false)
+    }
+
+    /// Inbound chunk streams the peer has used, ordered by chunk stream ID
+    ///
+    /// Shows which chunk streams carry which message types, for debugging
+    /// encoders that multiplex oddly. Sampled after the messages of each
+    /// read from the socket have been handled, so handlers called for a
+    /// message see the state from before the read that delivered it.
+    pub fn chunk_stream_snapshot(&self) -> Vec<ChunkStreamInfo> {
+        self.chunk_streams
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Replace the chunk stream snapshot
+    pub(crate) fn set_chunk_streams(&self, mut streams: Vec<ChunkStreamInfo>) {
+        streams.sort_unstable_by_key(|s| s.csid);
+        *self
+            .chunk_streams
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = streams;
+    }
+
     /// Get the TC URL if available
     pub fn tc_url(&self) -> Option<&str> {
         self.connect_params
@@ -111,6 +241,12 @@ pub struct StreamContext {
 
     /// Whether this is a publishing or playing stream
     pub is_publishing: bool,
+
+    /// Cached AVC sequence header of a published stream
+    video_config: Option<AvcConfig>,
+
+    /// Cached AAC sequence header of a published stream
+    audio_config: Option<AudioSpecificConfig>,
 }
 
 impl StreamContext {
@@ -126,8 +262,37 @@ impl StreamContext {
             stream_id,
             stream_key,
             is_publishing,
+            video_config: None,
+            audio_config: None,
         }
     }
+
+    /// Attach the sequence headers seen so far on this stream
+    pub(crate) fn with_sequence_headers(
+        mut self,
+        video_config: Option<AvcConfig>,
+        audio_config: Option<AudioSpecificConfig>,
+    ) -> Self {
+        self.video_config = video_config;
+        self.audio_config = audio_config;
+        self
+    }
+
+    /// The AVCDecoderConfigurationRecord last received on this stream
+    ///
+    /// `None` until an H.264 sequence header arrives. The raw record is in
+    /// [`AvcConfig::raw`], ready for an external muxer's `avcC` box.
+    pub fn video_config(&self) -> Option<AvcConfig> {
+        self.video_config.clone()
+    }
+
+    /// The AudioSpecificConfig last received on this stream
+    ///
+    /// `None` until an AAC sequence header arrives. The raw bytes are in
+    /// [`AudioSpecificConfig::raw`].
+    pub fn audio_config(&self) -> Option<AudioSpecificConfig> {
+        self.audio_config.clone()
+    }
 }
 
 #[cfg(test)]
@@ -149,9 +314,12 @@ mod tests {
         assert_eq!(ctx.app, "");
         assert_eq!(ctx.encoder_type, EncoderType::Unknown);
         assert!(ctx.connect_params.is_none());
+        assert_eq!(ctx.protocol_params, ProtocolParams::default());
+        assert_eq!(ctx.protocol_params.in_chunk_size, 128);
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_session_context_with_connect() {
         let addr = make_test_addr();
         let mut ctx = SessionContext::new(1, addr);
@@ -167,9 +335,11 @@ mod tests {
         assert_eq!(ctx.app, "live");
         assert_eq!(ctx.encoder_type, EncoderType::Obs);
         assert!(ctx.connect_params.is_some());
+        assert_eq!(ctx.protocol_params.object_encoding, Some(0.0));
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_session_context_tc_url() {
         let addr = make_test_addr();
         let mut ctx = SessionContext::new(1, addr);
@@ -186,6 +356,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_session_context_page_url() {
         let addr = make_test_addr();
         let mut ctx = SessionContext::new(1, addr);
@@ -202,6 +373,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_session_context_flash_ver() {
         let addr = make_test_addr();
         let mut ctx = SessionContext::new(1, addr);
@@ -231,6 +403,38 @@ mod tests {
         assert!(ctx.flash_ver().is_none());
     }
 
+    #[test]
+    fn test_session_context_request_reconnect() {
+        use crate::protocol::enhanced::{CapsEx, EnhancedCapabilities};
+
+        let mut ctx = SessionContext::new(1, make_test_addr());
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        ctx.control = Some(tx);
+
+        // Legacy session: nothing is sent
+        assert!(!ctx.supports_reconnect());
+        assert!(!ctx.request_reconnect(None));
+        assert!(rx.try_recv().is_err());
+
+        let mut caps = EnhancedCapabilities::new();
+        caps.enabled = true;
+        caps.caps_ex = CapsEx::from_bits(CapsEx::RECONNECT);
+        ctx.with_enhanced_capabilities(caps);
+
+        assert!(ctx.supports_reconnect());
+        assert!(ctx.request_reconnect(Some("rtmp://edge2/live".into())));
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            SessionControl::Reconnect {
+                tc_url: Some("rtmp://edge2/live".into())
+            }
+        );
+
+        // Session already gone
+        drop(rx);
+        assert!(!ctx.request_reconnect(None));
+    }
+
     #[test]
     fn test_stream_context_new() {
         let addr = make_test_addr();
@@ -258,6 +462,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_session_context_clone() {
         let addr = make_test_addr();
         let mut ctx = SessionContext::new(1, addr);
@@ -4,11 +4,14 @@
 //! - Session lifecycle (handshake, connect, publish/play, disconnect)
 //! - Per-stream state (message stream ID, publish/play mode)
 //! - Context passed to handlers
+//! - Registry of running sessions
 
 pub mod context;
+pub mod registry;
 pub mod state;
 pub mod stream;
 
-pub use context::{SessionContext, StreamContext};
+pub use context::{ProtocolParams, SessionContext, StreamContext};
+pub use registry::SessionRegistry;
 pub use state::SessionState;
 pub use stream::StreamState;
@@ -0,0 +1,114 @@
+//! Registry of running sessions
+//!
+//! Maps session IDs to the control channels of their connection tasks, so
+//! sessions can be closed from outside their task: by the server when it
+//! shuts down, or by an application (e.g. an admin API kicking a viewer).
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use tokio::sync::{mpsc, Notify};
+
+use super::context::SessionControl;
+
+/// Control channels of the running sessions
+#[derive(Debug, Default)]
+pub struct SessionRegistry {
+    senders: Mutex<HashMap<u64, mpsc::UnboundedSender<SessionControl>>>,
+    closed: Notify,
+}
+
+impl SessionRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<u64, mpsc::UnboundedSender<SessionControl>>> {
+        self.senders.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Track a running session
+    pub(crate) fn insert(&self, session_id: u64, tx: mpsc::UnboundedSender<SessionControl>) {
+        self.lock().insert(session_id, tx);
+    }
+
+    /// Stop tracking a session once its task has finished
+    pub(crate) fn remove(&self, session_id: u64) {
+        self.lock().remove(&session_id);
+        self.closed.notify_waiters();
+    }
+
+    /// Get the number of running sessions
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Check if no sessions are running
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Get the IDs of the running sessions
+    pub fn session_ids(&self) -> Vec<u64> {
+        self.lock().keys().copied().collect()
+    }
+
+    /// Forcibly close a session
+    ///
+    /// The session stops publishing or playing and its handler sees
+    /// [`DisconnectReason::Evicted`](crate::DisconnectReason::Evicted).
+    /// Returns false if no such session is running. The session closes
+    /// asynchronously.
+    pub fn disconnect_session(&self, session_id: u64) -> bool {
+        self.lock()
+            .get(&session_id)
+            .is_some_and(|tx| tx.send(SessionControl::Evict).is_ok())
+    }
+
+    /// Send a control request to every session; returns how many were sent
+    pub(crate) fn send_all(&self, control: SessionControl) -> usize {
+        let sessions = self.lock();
+        for tx in sessions.values() {
+            let _ = tx.send(control.clone());
+        }
+        sessions.len()
+    }
+
+    /// Wait until no sessions are running
+    pub(crate) async fn wait_empty(&self) {
+        loop {
+            let closed = self.closed.notified();
+            if self.is_empty() {
+                break;
+            }
+            closed.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disconnect_session() {
+        let sessions = SessionRegistry::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        sessions.insert(7, tx);
+
+        assert_eq!(sessions.session_ids(), vec![7]);
+        assert!(sessions.disconnect_session(7));
+        assert_eq!(rx.try_recv().unwrap(), SessionControl::Evict);
+
+        // Unknown session
+        assert!(!sessions.disconnect_session(8));
+
+        // Session task gone but not yet removed
+        drop(rx);
+        assert!(!sessions.disconnect_session(7));
+
+        sessions.remove(7);
+        assert!(sessions.is_empty());
+    }
+}
@@ -10,6 +10,9 @@ use super::stream::StreamState;
 use crate::protocol::message::ConnectParams;
 use crate::protocol::quirks::EncoderType;
 
+/// Streams a player may announce a buffer length for before creating them
+const MAX_PENDING_BUFFER_LENGTHS: usize = 8;
+
 /// Session lifecycle state
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SessionPhase {
@@ -57,6 +60,9 @@ pub struct SessionState {
     /// Next message stream ID to allocate
     next_stream_id: u32,
 
+    /// Buffer lengths announced for streams not yet created
+    pending_buffer_lengths: HashMap<u32, u32>,
+
     /// Negotiated chunk size (incoming)
     pub in_chunk_size: u32,
 
@@ -89,6 +95,7 @@ impl SessionState {
             encoder_type: EncoderType::Unknown,
             streams: HashMap::new(),
             next_stream_id: 1, // Stream 0 is reserved for NetConnection
+            pending_buffer_lengths: HashMap::new(),
             in_chunk_size: 128,
             out_chunk_size: 128,
             window_ack_size: 2_500_000,
@@ -124,10 +131,30 @@ impl SessionState {
     pub fn allocate_stream_id(&mut self) -> u32 {
         let id = self.next_stream_id;
         self.next_stream_id += 1;
-        self.streams.insert(id, StreamState::new(id));
+        let mut stream = StreamState::new(id);
+        stream.buffer_length_ms = self.pending_buffer_lengths.remove(&id);
+        self.streams.insert(id, stream);
         id
     }
 
+    /// Record the buffer length a player set for a stream
+    ///
+    /// Players may announce it before createStream returns the stream; the
+    /// value is then kept until the stream is allocated.
+    pub fn set_buffer_length(&mut self, stream_id: u32, buffer_ms: u32) {
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            stream.buffer_length_ms = Some(buffer_ms);
+            return;
+        }
+        let upcoming = stream_id >= self.next_stream_id;
+        let pending = &mut self.pending_buffer_lengths;
+        if upcoming
+            && (pending.len() < MAX_PENDING_BUFFER_LENGTHS || pending.contains_key(&stream_id))
+        {
+            pending.insert(stream_id, buffer_ms);
+        }
+    }
+
     /// Get a stream by ID
     pub fn get_stream(&self, stream_id: u32) -> Option<&StreamState> {
         self.streams.get(&stream_id)
@@ -183,6 +210,26 @@ mod tests {
     use super::*;
     use std::net::{IpAddr, Ipv4Addr};
 
+    #[test]
+    fn test_buffer_length_before_stream() {
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 1935);
+        let mut state = SessionState::new(1, addr);
+
+        // Announced for the stream createStream is about to allocate
+        state.set_buffer_length(1, 0);
+        let id = state.allocate_stream_id();
+        assert_eq!(state.get_stream(id).unwrap().buffer_length_ms, Some(0));
+
+        state.set_buffer_length(id, 3000);
+        assert_eq!(state.get_stream(id).unwrap().buffer_length_ms, Some(3000));
+
+        // Streams already deleted are not brought back
+        state.remove_stream(id);
+        state.set_buffer_length(id, 100);
+        let id = state.allocate_stream_id();
+        assert_eq!(state.get_stream(id).unwrap().buffer_length_ms, None);
+    }
+
     #[test]
     fn test_session_lifecycle() {
         let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 1935);
@@ -3,9 +3,15 @@
 //! Each RTMP message stream (identified by stream ID) has its own state,
 //! including publish/play mode, stream key, and media state.
 
+use std::collections::VecDeque;
 use std::time::Instant;
 
+use bytes::Bytes;
+
+use crate::media::aac::AudioSpecificConfig;
 use crate::media::gop::GopBuffer;
+use crate::media::h264::AvcConfig;
+use crate::stats::metrics::av_sync_drift_ms;
 
 /// Stream mode (publishing or playing)
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -45,6 +51,12 @@ pub struct StreamState {
     /// Whether we've received metadata
     pub has_metadata: bool,
 
+    /// Parsed AVC sequence header (H.264 only)
+    pub video_config: Option<AvcConfig>,
+
+    /// Parsed AAC sequence header
+    pub audio_config: Option<AudioSpecificConfig>,
+
     /// Last video timestamp
     pub last_video_ts: u32,
 
@@ -63,8 +75,34 @@ pub struct StreamState {
     /// Total bytes received on this stream
     pub bytes_received: u64,
 
+    /// Whether A/V drift is currently beyond the configured threshold
+    pub av_desynced: bool,
+
+    /// Buffer length the player asked for with Set Buffer Length, in
+    /// milliseconds
+    pub buffer_length_ms: Option<u32>,
+
     /// GOP buffer for late-joiner support
     pub gop_buffer: GopBuffer,
+
+    /// Audio frames waiting for the AAC sequence header
+    pub held_audio: HeldFrames,
+
+    /// Video frames waiting for the AVC sequence header
+    pub held_video: HeldFrames,
+}
+
+/// Frames of one track that arrived before its sequence header
+#[derive(Debug, Default)]
+pub struct HeldFrames {
+    /// Held frames as (timestamp, payload), oldest first
+    pub frames: VecDeque<(u32, Bytes)>,
+
+    /// Payload bytes held
+    pub bytes: usize,
+
+    /// Whether the early frames have been reported to the handler
+    pub reported: bool,
 }
 
 impl StreamState {
@@ -79,13 +117,19 @@ impl StreamState {
             has_video_header: false,
             has_audio_header: false,
             has_metadata: false,
+            video_config: None,
+            audio_config: None,
             last_video_ts: 0,
             last_audio_ts: 0,
             video_frames: 0,
             audio_frames: 0,
             keyframes: 0,
             bytes_received: 0,
+            av_desynced: false,
+            buffer_length_ms: None,
             gop_buffer: GopBuffer::new(),
+            held_audio: HeldFrames::default(),
+            held_video: HeldFrames::default(),
         }
     }
 
@@ -155,6 +199,28 @@ impl StreamState {
         }
     }
 
+    /// Latest video timestamp minus latest audio timestamp, in milliseconds
+    ///
+    /// None until both audio and video have arrived.
+    pub fn av_sync_drift_ms(&self) -> Option<i64> {
+        if self.video_frames == 0 || self.audio_frames == 0 {
+            return None;
+        }
+        Some(av_sync_drift_ms(self.last_video_ts, self.last_audio_ts))
+    }
+
+    /// Check A/V drift against `threshold_ms`
+    ///
+    /// Returns the drift when it first exceeds the threshold; stays quiet
+    /// until the drift has come back within it.
+    pub fn check_av_sync(&mut self, threshold_ms: i64) -> Option<i64> {
+        let drift = self.av_sync_drift_ms()?;
+        let desynced = drift.abs() > threshold_ms;
+        let crossed = desynced && !self.av_desynced;
+        self.av_desynced = desynced;
+        crossed.then_some(drift)
+    }
+
     /// Mark metadata received
     pub fn on_metadata(&mut self) {
         self.has_metadata = true;
@@ -163,11 +229,7 @@ impl StreamState {
     /// Get bitrate estimate (bits per second)
     pub fn bitrate(&self) -> Option<u64> {
         let duration = self.duration()?.as_secs();
-        if duration > 0 {
-            Some((self.bytes_received * 8) / duration)
-        } else {
-            None
-        }
+        (self.bytes_received * 8).checked_div(duration)
     }
 }
 
@@ -200,4 +262,24 @@ mod tests {
         assert!(stream.is_ready());
         assert!(stream.has_video_header);
     }
+
+    #[test]
+    fn test_check_av_sync() {
+        let mut stream = StreamState::new(1);
+        stream.on_video(1000, true, false, 100);
+        assert_eq!(stream.av_sync_drift_ms(), None);
+        assert_eq!(stream.check_av_sync(500), None);
+
+        stream.on_audio(0, false, 10);
+        assert_eq!(stream.check_av_sync(500), Some(1000));
+        // Reported once per excursion
+        stream.on_video(1033, false, false, 100);
+        assert_eq!(stream.check_av_sync(500), None);
+
+        stream.on_audio(1020, false, 10);
+        assert_eq!(stream.check_av_sync(500), None);
+        stream.on_audio(1700, false, 10);
+        assert_eq!(stream.av_sync_drift_ms(), Some(-667));
+        assert_eq!(stream.check_av_sync(500), Some(-667));
+    }
 }
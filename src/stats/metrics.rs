@@ -17,10 +17,15 @@ pub struct SessionStats {
     pub audio_frames: u64,
     /// Number of keyframes received
     pub keyframes: u64,
-    /// Dropped frames count
+    /// Media frames delivered to a subscriber
+    pub frames_delivered: u64,
+    /// Frames not delivered to a subscriber (broadcast lag, skipping to a
+    /// keyframe, paused playback)
     pub dropped_frames: u64,
     /// Current bitrate estimate (bits/sec)
     pub bitrate: u64,
+    /// Malformed AMF values recovered from in lenient mode
+    pub amf_recovered_errors: u64,
 }
 
 impl SessionStats {
@@ -32,8 +37,8 @@ impl SessionStats {
     /// Calculate bitrate from bytes and duration
     pub fn calculate_bitrate(&mut self) {
         let secs = self.duration.as_secs();
-        if secs > 0 {
-            self.bitrate = (self.bytes_received * 8) / secs;
+        if let Some(bitrate) = (self.bytes_received * 8).checked_div(secs) {
+            self.bitrate = bitrate;
         }
     }
 }
@@ -102,11 +107,14 @@ impl StreamStats {
     /// Calculate bitrate in bits per second
     pub fn bitrate(&self) -> u64 {
         let secs = self.duration().as_secs();
-        if secs > 0 {
-            (self.bytes_received * 8) / secs
-        } else {
-            0
-        }
+        (self.bytes_received * 8).checked_div(secs).unwrap_or(0)
+    }
+
+    /// Latest video timestamp minus latest audio timestamp, in milliseconds
+    ///
+    /// Positive when video runs ahead of audio.
+    pub fn av_sync_drift_ms(&self) -> i64 {
+        av_sync_drift_ms(self.last_video_ts, self.last_audio_ts)
     }
 
     /// Calculate video framerate
@@ -120,6 +128,13 @@ impl StreamStats {
     }
 }
 
+/// Signed distance between a video and an audio timestamp
+///
+/// RTMP timestamps wrap at 32 bits, so the difference is taken modulo 2^32.
+pub(crate) fn av_sync_drift_ms(video_ts: u32, audio_ts: u32) -> i64 {
+    video_ts.wrapping_sub(audio_ts) as i32 as i64
+}
+
 /// Server-wide statistics
 #[derive(Debug, Clone, Default)]
 pub struct ServerStats {
@@ -208,6 +223,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::double_comparisons)]
     fn test_stream_stats_bitrate_zero_duration() {
         let stats = StreamStats::new("test".to_string());
 
@@ -255,6 +271,7 @@ mod tests {
         stats.audio_sample_rate = Some(44100);
         stats.audio_channels = Some(2);
 
+        assert_eq!(stats.av_sync_drift_ms(), -50);
         assert_eq!(stats.video_codec, Some("H.264".to_string()));
         assert_eq!(stats.width, Some(1920));
         assert_eq!(stats.height, Some(1080));
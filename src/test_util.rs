@@ -0,0 +1,62 @@
+//! Shared fixtures for tests that drive full sessions in-process
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::error::Result;
+use crate::registry::StreamRegistry;
+use crate::server::connection::Connection;
+use crate::server::{RtmpHandler, ServerConfig};
+use crate::transport::DuplexTransport;
+
+/// Start a server-side session on one end of an in-memory pipe
+///
+/// Returns the client end of the pipe and the session's task.
+pub(crate) fn spawn_session<H: RtmpHandler>(
+    session_id: u64,
+    config: ServerConfig,
+    handler: Arc<H>,
+    registry: Arc<StreamRegistry>,
+) -> (DuplexTransport, JoinHandle<Result<()>>) {
+    let (server_side, client_side) = DuplexTransport::pair(64 * 1024);
+    let mut connection = Connection::new(
+        session_id,
+        server_side,
+        "127.0.0.1:1935".parse().unwrap(),
+        config,
+        handler,
+        registry,
+    );
+    let task = tokio::spawn(async move { connection.run().await });
+    (client_side, task)
+}
+
+/// Poll `cond` every 10ms until it holds, panicking after `timeout`
+pub(crate) async fn wait_until<F, Fut>(timeout: Duration, mut cond: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let deadline = Instant::now() + timeout;
+    while !cond().await {
+        assert!(
+            Instant::now() < deadline,
+            "condition not met within {:?}",
+            timeout
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Empty directory under the system temp dir, unique to `name` and this
+/// test process
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rtmp-rs-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
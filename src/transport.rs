@@ -0,0 +1,169 @@
+//! Byte transports for RTMP sessions
+//!
+//! Server connections and client connectors run over anything that
+//! implements [`Transport`], which in practice is a `TcpStream`. For
+//! in-process use (mainly end-to-end tests) [`DuplexTransport`] provides a
+//! connected pair of in-memory pipes, so a full session can be driven
+//! without binding a port:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use rtmp_rs::server::connection::Connection;
+//! use rtmp_rs::transport::DuplexTransport;
+//! use rtmp_rs::{ClientConfig, RtmpConnector, RtmpHandler, ServerConfig, StreamRegistry};
+//!
+//! struct MyHandler;
+//! impl RtmpHandler for MyHandler {}
+//!
+//! # async fn example() -> rtmp_rs::Result<()> {
+//! let (server_side, client_side) = DuplexTransport::pair(64 * 1024);
+//! let mut connection = Connection::new(
+//!     1,
+//!     server_side,
+//!     "127.0.0.1:1935".parse().unwrap(),
+//!     ServerConfig::default(),
+//!     Arc::new(MyHandler),
+//!     Arc::new(StreamRegistry::new()),
+//! );
+//! tokio::spawn(async move { connection.run().await });
+//!
+//! let config = ClientConfig::new("rtmp://localhost/live");
+//! let mut client = RtmpConnector::connect_with_transport(config, client_side).await?;
+//! client.play("stream").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf};
+
+/// A bidirectional byte stream an RTMP session can run over
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + 'static> Transport for T {}
+
+/// In-memory transport backed by `tokio::io::duplex`
+///
+/// Bytes written to one end of a pair are read from the other. Dropping
+/// either end closes the pipe, which the peer sees as EOF.
+#[derive(Debug)]
+pub struct DuplexTransport {
+    inner: DuplexStream,
+}
+
+impl DuplexTransport {
+    /// Create a connected pair of transports
+    ///
+    /// `max_buf_size` is the number of bytes each direction buffers before
+    /// writes wait for the peer to read.
+    pub fn pair(max_buf_size: usize) -> (Self, Self) {
+        let (a, b) = tokio::io::duplex(max_buf_size);
+        (Self { inner: a }, Self { inner: b })
+    }
+}
+
+impl AsyncRead for DuplexTransport {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for DuplexTransport {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::client::{ClientConfig, RtmpConnector};
+    use crate::protocol::message::RtmpMessage;
+    use crate::registry::StreamRegistry;
+    use crate::server::{RtmpHandler, ServerConfig};
+    use crate::test_util::spawn_session;
+
+    struct AcceptAll;
+
+    impl RtmpHandler for AcceptAll {}
+
+    #[tokio::test]
+    async fn test_duplex_pair_carries_bytes() {
+        let (mut a, mut b) = DuplexTransport::pair(16);
+        a.write_all(b"rtmp").await.unwrap();
+        drop(a);
+
+        let mut received = Vec::new();
+        b.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"rtmp");
+    }
+
+    #[tokio::test]
+    async fn test_publish_and_play_over_duplex() {
+        let registry = Arc::new(StreamRegistry::new());
+        let config = ClientConfig::new("rtmp://localhost/live");
+
+        let (transport, _) = spawn_session(
+            1,
+            ServerConfig::default(),
+            Arc::new(AcceptAll),
+            registry.clone(),
+        );
+        let mut publisher = RtmpConnector::connect_with_transport(config.clone(), transport)
+            .await
+            .unwrap();
+        publisher.publish("test").await.unwrap();
+
+        let (transport, _) =
+            spawn_session(2, ServerConfig::default(), Arc::new(AcceptAll), registry);
+        let mut player = RtmpConnector::connect_with_transport(config, transport)
+            .await
+            .unwrap();
+        player.play("test").await.unwrap();
+
+        // AVC keyframe: NALU packet, zero composition time, one IDR NAL unit
+        let keyframe = Bytes::from_static(&[0x17, 0x01, 0, 0, 0, 0, 0, 0, 2, 0x65, 0x88]);
+        publisher
+            .send_video_data(keyframe.clone(), 40)
+            .await
+            .unwrap();
+
+        loop {
+            let msg = tokio::time::timeout(Duration::from_secs(5), player.read_message())
+                .await
+                .expect("player never received the keyframe")
+                .unwrap();
+            if let RtmpMessage::Video { timestamp, data } = msg {
+                assert_eq!(timestamp, 40);
+                assert_eq!(data, keyframe);
+                break;
+            }
+        }
+    }
+}